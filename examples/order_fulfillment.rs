@@ -0,0 +1,153 @@
+/*!
+An order-fulfillment workflow, showing a composite state for order review, an orthogonal state
+with parallel payment and shipping regions, time-based triggers for timeouts, and export of the
+whole model as a PlantUML state diagram.
+
+Run with `cargo run --example order_fulfillment`, the PlantUML source is written to stdout.
+
+Execution of the workflow, history on interruption, and persistence of running instances are
+not yet shown as the interpreter for this model is still to come.
+*/
+
+use std::time::Duration;
+use uml_state_machine::format::plant_uml::WritePlantUml;
+use uml_state_machine::prelude::*;
+
+fn labeled_state(region: &Region, label: &str) -> State {
+    let mut state = State::within(region.id().clone());
+    state.set_label(label);
+    state
+}
+
+fn transition(region: &Region, source: &ID, target: &ID, trigger: Option<Trigger>) {
+    let mut transition = Transition::within(source.clone(), target.clone(), region.id().clone());
+    if let Some(trigger) = trigger {
+        transition.add_trigger(trigger);
+    }
+    region.add_transition(transition);
+}
+
+fn review_state(region: &Region) -> State {
+    let mut review = labeled_state(region, "Review");
+    let _ = review.new_region();
+    let inner = review.default_region().unwrap();
+    let initial = inner.new_initial_state();
+    let fraud_check = labeled_state(inner, "FraudCheck");
+    let fraud_check_id = fraud_check.id().clone();
+    inner.add_state(fraud_check);
+    let stock_check = labeled_state(inner, "StockCheck");
+    let stock_check_id = stock_check.id().clone();
+    inner.add_state(stock_check);
+    let done = inner.new_final_state();
+    transition(inner, &initial, &fraud_check_id, None);
+    transition(
+        inner,
+        &fraud_check_id,
+        &stock_check_id,
+        Some(Trigger::signal("cleared")),
+    );
+    transition(
+        inner,
+        &stock_check_id,
+        &done,
+        Some(Trigger::signal("in_stock")),
+    );
+    review
+}
+
+fn fulfillment_state(region: &Region) -> State {
+    let mut fulfillment = labeled_state(region, "Fulfillment");
+    let _ = fulfillment.new_region();
+    let _ = fulfillment.new_region();
+
+    let payment = fulfillment.region(0).unwrap();
+    let initial = payment.new_initial_state();
+    let authorizing = labeled_state(payment, "Authorizing");
+    let authorizing_id = authorizing.id().clone();
+    payment.add_state(authorizing);
+    let captured = payment.new_final_state();
+    transition(payment, &initial, &authorizing_id, None);
+    transition(
+        payment,
+        &authorizing_id,
+        &captured,
+        Some(Trigger::signal("captured")),
+    );
+    transition(
+        payment,
+        &authorizing_id,
+        &authorizing_id,
+        Some(Trigger::after(Duration::from_secs(30))),
+    );
+
+    let shipping = fulfillment.region(1).unwrap();
+    let initial = shipping.new_initial_state();
+    let picking = labeled_state(shipping, "Picking");
+    let picking_id = picking.id().clone();
+    shipping.add_state(picking);
+    let in_transit = labeled_state(shipping, "InTransit");
+    let in_transit_id = in_transit.id().clone();
+    shipping.add_state(in_transit);
+    let delivered = shipping.new_final_state();
+    transition(shipping, &initial, &picking_id, None);
+    transition(
+        shipping,
+        &picking_id,
+        &in_transit_id,
+        Some(Trigger::signal("shipped")),
+    );
+    transition(
+        shipping,
+        &in_transit_id,
+        &delivered,
+        Some(Trigger::signal("delivered")),
+    );
+
+    fulfillment
+}
+
+fn order_workflow() -> StateMachine {
+    let machine = StateMachine::labeled("Order Fulfillment");
+    let region = machine.default_region().unwrap();
+
+    let initial = region.new_initial_state();
+    let review = review_state(region);
+    let review_id = review.id().clone();
+    region.add_state(review);
+    let fulfillment = fulfillment_state(region);
+    let fulfillment_id = fulfillment.id().clone();
+    region.add_state(fulfillment);
+    let cancelled = labeled_state(region, "Cancelled");
+    let cancelled_id = cancelled.id().clone();
+    region.add_state(cancelled);
+    let complete = region.new_final_state();
+
+    transition(region, &initial, &review_id, None);
+    transition(region, &review_id, &fulfillment_id, None);
+    transition(
+        region,
+        &review_id,
+        &cancelled_id,
+        Some(Trigger::after(Duration::from_secs(7 * 24 * 60 * 60))),
+    );
+    transition(
+        region,
+        &review_id,
+        &cancelled_id,
+        Some(Trigger::signal("cancel")),
+    );
+    transition(region, &fulfillment_id, &complete, None);
+    transition(region, &cancelled_id, &complete, None);
+
+    machine
+}
+
+fn main() {
+    let machine = order_workflow();
+    machine
+        .validate()
+        .expect("the order workflow should be valid");
+
+    let writer = WritePlantUml::default();
+    println!("{}", writer.stringify(&machine).unwrap());
+}
@@ -7,36 +7,51 @@ More detailed description, with
 
 */
 
-use std::cell::RefCell;
 use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::rc::Rc;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum FieldValue {
     Bool(bool),
     Byte(u8),
     Integer(i64),
     Float(f64),
     String(String),
-    Array(Rc<RefCell<Array>>),
-    Object(Rc<RefCell<Object>>),
+    Array(Arc<RwLock<Array>>),
+    Object(Arc<RwLock<Object>>),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+///
+/// The kind of value held by a field, without the value itself, as used to describe the payload
+/// of a signal.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FieldKind {
+    Bool,
+    Byte,
+    Integer,
+    Float,
+    String,
+    Array,
+    Object,
+}
+
+#[derive(Debug, Default)]
 pub struct Array {
-    inner: RefCell<Vec<FieldValue>>,
+    inner: RwLock<Vec<FieldValue>>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Debug, Default)]
 pub struct Object {
-    inner: RefCell<HashMap<FieldName, FieldValue>>,
+    inner: RwLock<HashMap<FieldName, FieldValue>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -69,18 +84,24 @@ pub trait Compound<K> {
 // Implementations
 // ------------------------------------------------------------------------------------------------
 
-impl Default for Array {
-    fn default() -> Self {
+impl Clone for Array {
+    fn clone(&self) -> Self {
         Self {
-            inner: Default::default(),
+            inner: RwLock::new(self.inner.read().unwrap().clone()),
         }
     }
 }
 
+impl PartialEq for Array {
+    fn eq(&self, other: &Self) -> bool {
+        *self.inner.read().unwrap() == *other.inner.read().unwrap()
+    }
+}
+
 impl From<Vec<FieldValue>> for Array {
     fn from(value: Vec<FieldValue>) -> Self {
         Self {
-            inner: RefCell::new(value),
+            inner: RwLock::new(value),
         }
     }
 }
@@ -88,53 +109,59 @@ impl From<Vec<FieldValue>> for Array {
 impl From<FieldValue> for Array {
     fn from(value: FieldValue) -> Self {
         Self {
-            inner: RefCell::new(vec![value]),
+            inner: RwLock::new(vec![value]),
         }
     }
 }
 
 impl Compound<usize> for Array {
     fn contains_key(&self, key: usize) -> bool {
-        key < self.inner.borrow().len()
+        key < self.inner.read().unwrap().len()
     }
 
     fn get(&self, key: usize) -> Option<FieldValue> {
-        self.inner.borrow().get(key).cloned()
+        self.inner.read().unwrap().get(key).cloned()
     }
 
     fn insert(&self, key: usize, value: FieldValue) {
-        self.inner.borrow_mut().insert(key, value)
+        self.inner.write().unwrap().insert(key, value)
     }
 
     fn remove(&self, key: usize) -> Option<FieldValue> {
-        if key < self.inner.borrow().len() {
+        if key < self.inner.read().unwrap().len() {
             None
         } else {
-            Some(self.inner.borrow_mut().remove(key))
+            Some(self.inner.write().unwrap().remove(key))
         }
     }
 
     fn len(&self) -> usize {
-        self.inner.borrow().len()
+        self.inner.read().unwrap().len()
     }
 }
 
 impl Array {
     pub fn push(&self, value: FieldValue) {
-        self.inner.borrow_mut().push(value)
+        self.inner.write().unwrap().push(value)
     }
 }
 
 // ------------------------------------------------------------------------------------------------
 
-impl Default for Object {
-    fn default() -> Self {
+impl Clone for Object {
+    fn clone(&self) -> Self {
         Self {
-            inner: Default::default(),
+            inner: RwLock::new(self.inner.read().unwrap().clone()),
         }
     }
 }
 
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        *self.inner.read().unwrap() == *other.inner.read().unwrap()
+    }
+}
+
 impl From<HashMap<FieldName, FieldValue>> for Object {
     fn from(value: HashMap<FieldName, FieldValue, RandomState>) -> Self {
         Self {
@@ -145,23 +172,29 @@ impl From<HashMap<FieldName, FieldValue>> for Object {
 
 impl Compound<FieldName> for Object {
     fn contains_key(&self, key: FieldName) -> bool {
-        self.inner.borrow().contains_key(&key)
+        self.inner.read().unwrap().contains_key(&key)
     }
 
     fn get(&self, key: FieldName) -> Option<FieldValue> {
-        self.inner.borrow().get(&key).cloned()
+        self.inner.read().unwrap().get(&key).cloned()
     }
 
     fn insert(&self, key: FieldName, value: FieldValue) {
-        let _ = self.inner.borrow_mut().insert(key, value);
+        let _ = self.inner.write().unwrap().insert(key, value);
     }
 
     fn remove(&self, key: FieldName) -> Option<FieldValue> {
-        self.inner.borrow_mut().remove(&key)
+        self.inner.write().unwrap().remove(&key)
     }
 
     fn len(&self) -> usize {
-        self.inner.borrow().len()
+        self.inner.read().unwrap().len()
+    }
+}
+
+impl Object {
+    pub fn names(&self) -> Vec<FieldName> {
+        self.inner.read().unwrap().keys().cloned().collect()
     }
 }
 
@@ -208,8 +241,7 @@ impl FromStr for FieldPath {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mapped: Result<Vec<FieldName>, _> =
-            s.split('/').map(|s| FieldName::from_str(s)).collect();
+        let mapped: Result<Vec<FieldName>, _> = s.split('/').map(FieldName::from_str).collect();
         match mapped {
             Ok(mapped) => Ok(Self(mapped)),
             Err(_) => Err(()),
@@ -262,6 +294,12 @@ impl From<f64> for FieldValue {
     }
 }
 
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::String(value.to_string())
+    }
+}
+
 impl From<String> for FieldValue {
     fn from(value: String) -> Self {
         FieldValue::String(value)
@@ -270,32 +308,208 @@ impl From<String> for FieldValue {
 
 impl From<Array> for FieldValue {
     fn from(value: Array) -> Self {
-        FieldValue::Array(Rc::new(RefCell::new(value)))
+        FieldValue::Array(Arc::new(RwLock::new(value)))
     }
 }
 
 impl From<Object> for FieldValue {
     fn from(value: Object) -> Self {
-        FieldValue::Object(Rc::new(RefCell::new(value)))
+        FieldValue::Object(Arc::new(RwLock::new(value)))
     }
 }
 
-impl FieldValue {
-    pub fn is_simple(&self) -> bool {
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            FieldValue::Bool(_)
-            | FieldValue::Byte(_)
-            | FieldValue::Integer(_)
-            | FieldValue::Float(_)
-            | FieldValue::String(_) => true,
+            FieldValue::Bool(value) => write!(f, "{}", value),
+            FieldValue::Byte(value) => write!(f, "{}", value),
+            FieldValue::Integer(value) => write!(f, "{}", value),
+            FieldValue::Float(value) => write!(f, "{:?}", value),
+            FieldValue::String(value) => write!(f, "{:?}", value),
+            FieldValue::Array(value) => write!(
+                f,
+                "[{}]",
+                value
+                    .read()
+                    .unwrap()
+                    .inner
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            FieldValue::Object(value) => {
+                let value = value.read().unwrap();
+                let members = value.inner.read().unwrap();
+                // members are written in name order, so that equal objects display equally.
+                let mut names: Vec<&FieldName> = members.keys().collect();
+                names.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+                write!(
+                    f,
+                    "{{{}}}",
+                    names
+                        .into_iter()
+                        .map(|k| format!("{}: {}", k, members[k]))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl PartialEq for FieldValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FieldValue::Bool(lhs), FieldValue::Bool(rhs)) => lhs == rhs,
+            (FieldValue::Byte(lhs), FieldValue::Byte(rhs)) => lhs == rhs,
+            (FieldValue::Integer(lhs), FieldValue::Integer(rhs)) => lhs == rhs,
+            (FieldValue::Float(lhs), FieldValue::Float(rhs)) => lhs == rhs,
+            (FieldValue::String(lhs), FieldValue::String(rhs)) => lhs == rhs,
+            (FieldValue::Array(lhs), FieldValue::Array(rhs)) => {
+                Arc::ptr_eq(lhs, rhs) || *lhs.read().unwrap() == *rhs.read().unwrap()
+            }
+            (FieldValue::Object(lhs), FieldValue::Object(rhs)) => {
+                Arc::ptr_eq(lhs, rhs) || *lhs.read().unwrap() == *rhs.read().unwrap()
+            }
             _ => false,
         }
     }
+}
+
+///
+/// Values that are equal hash equally; the members of an object are hashed in name order, and
+/// floats by their bits, with `-0.0` hashed as `0.0`.
+///
+impl Hash for FieldValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            FieldValue::Bool(v) => v.hash(state),
+            FieldValue::Byte(v) => v.hash(state),
+            FieldValue::Integer(v) => v.hash(state),
+            FieldValue::Float(v) => (if *v == 0.0 { 0.0 } else { *v }).to_bits().hash(state),
+            FieldValue::String(v) => v.hash(state),
+            FieldValue::Array(v) => {
+                let v = v.read().unwrap();
+                let values = v.inner.read().unwrap();
+                values.len().hash(state);
+                for value in values.iter() {
+                    value.hash(state);
+                }
+            }
+            FieldValue::Object(v) => {
+                let v = v.read().unwrap();
+                let members = v.inner.read().unwrap();
+                let mut names: Vec<&FieldName> = members.keys().collect();
+                names.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+                names.len().hash(state);
+                for name in names {
+                    name.hash(state);
+                    members[name].hash(state);
+                }
+            }
+        }
+    }
+}
+
+impl FieldValue {
+    ///
+    /// A copy of this value that shares no arrays or objects with it, unlike `clone`.
+    ///
+    pub fn deep_clone(&self) -> Self {
+        match self {
+            FieldValue::Array(v) => {
+                let values: Vec<FieldValue> = v
+                    .read()
+                    .unwrap()
+                    .inner
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(FieldValue::deep_clone)
+                    .collect();
+                Array::from(values).into()
+            }
+            FieldValue::Object(v) => {
+                let members: HashMap<FieldName, FieldValue> = v
+                    .read()
+                    .unwrap()
+                    .inner
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.deep_clone()))
+                    .collect();
+                Object::from(members).into()
+            }
+            _ => self.clone(),
+        }
+    }
+
+    pub fn is_simple(&self) -> bool {
+        matches!(
+            self,
+            FieldValue::Bool(_)
+                | FieldValue::Byte(_)
+                | FieldValue::Integer(_)
+                | FieldValue::Float(_)
+                | FieldValue::String(_)
+        )
+    }
 
     pub fn is_compound(&self) -> bool {
+        matches!(self, FieldValue::Array(_) | FieldValue::Object(_))
+    }
+
+    pub fn kind(&self) -> FieldKind {
         match self {
-            FieldValue::Array(_) | FieldValue::Object(_) => true,
-            _ => false,
+            FieldValue::Bool(_) => FieldKind::Bool,
+            FieldValue::Byte(_) => FieldKind::Byte,
+            FieldValue::Integer(_) => FieldKind::Integer,
+            FieldValue::Float(_) => FieldKind::Float,
+            FieldValue::String(_) => FieldKind::String,
+            FieldValue::Array(_) => FieldKind::Array,
+            FieldValue::Object(_) => FieldKind::Object,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Display for FieldKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                FieldKind::Bool => "bool",
+                FieldKind::Byte => "byte",
+                FieldKind::Integer => "integer",
+                FieldKind::Float => "float",
+                FieldKind::String => "string",
+                FieldKind::Array => "array",
+                FieldKind::Object => "object",
+            }
+        )
+    }
+}
+
+impl FromStr for FieldKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bool" => Ok(FieldKind::Bool),
+            "byte" => Ok(FieldKind::Byte),
+            "integer" => Ok(FieldKind::Integer),
+            "float" => Ok(FieldKind::Float),
+            "string" => Ok(FieldKind::String),
+            "array" => Ok(FieldKind::Array),
+            "object" => Ok(FieldKind::Object),
+            _ => Err(()),
         }
     }
 }
@@ -313,21 +527,27 @@ impl Default for Context {
 impl From<Object> for Context {
     fn from(value: Object) -> Self {
         Self {
-            root: FieldValue::Object(Rc::new(RefCell::new(value))),
+            root: FieldValue::Object(Arc::new(RwLock::new(value))),
         }
     }
 }
 
+impl Hash for Context {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root.hash(state)
+    }
+}
+
 impl Compound<FieldPath> for Context {
     fn contains_key(&self, key: FieldPath) -> bool {
         match self.find(&key) {
             None => false,
             Some((container, key)) => match container {
                 FieldValue::Array(array) => match usize::from_str(&key.to_string()) {
-                    Ok(key) => array.borrow().contains_key(key),
+                    Ok(key) => array.read().unwrap().contains_key(key),
                     Err(_) => false,
                 },
-                FieldValue::Object(object) => object.borrow().contains_key(key),
+                FieldValue::Object(object) => object.read().unwrap().contains_key(key),
                 _ => false,
             },
         }
@@ -338,10 +558,10 @@ impl Compound<FieldPath> for Context {
             None => None,
             Some((container, key)) => match container {
                 FieldValue::Array(array) => match usize::from_str(&key.to_string()) {
-                    Ok(key) => array.borrow().get(key),
+                    Ok(key) => array.read().unwrap().get(key),
                     Err(_) => None,
                 },
-                FieldValue::Object(object) => object.borrow().get(key),
+                FieldValue::Object(object) => object.read().unwrap().get(key),
                 _ => None,
             },
         }
@@ -353,10 +573,10 @@ impl Compound<FieldPath> for Context {
             Some((container, key)) => match container {
                 FieldValue::Array(array) => {
                     if let Ok(key) = usize::from_str(&key.to_string()) {
-                        array.borrow_mut().insert(key, value);
+                        array.write().unwrap().insert(key, value);
                     }
                 }
-                FieldValue::Object(object) => object.borrow_mut().insert(key, value),
+                FieldValue::Object(object) => object.write().unwrap().insert(key, value),
                 _ => (),
             },
         }
@@ -367,10 +587,10 @@ impl Compound<FieldPath> for Context {
             None => None,
             Some((container, key)) => match container {
                 FieldValue::Array(array) => match usize::from_str(&key.to_string()) {
-                    Ok(key) => array.borrow_mut().remove(key),
+                    Ok(key) => array.write().unwrap().remove(key),
                     Err(_) => None,
                 },
-                FieldValue::Object(object) => object.borrow_mut().remove(key),
+                FieldValue::Object(object) => object.write().unwrap().remove(key),
                 _ => None,
             },
         }
@@ -378,13 +598,40 @@ impl Compound<FieldPath> for Context {
 
     fn len(&self) -> usize {
         match &self.root {
-            FieldValue::Object(object) => object.borrow().len(),
+            FieldValue::Object(object) => object.read().unwrap().len(),
             _ => 0,
         }
     }
 }
 
 impl Context {
+    ///
+    /// The value holding every field of the context, always an object.
+    ///
+    pub fn root(&self) -> &FieldValue {
+        &self.root
+    }
+
+    ///
+    /// A copy of this context that shares no arrays or objects with it, unlike `clone`.
+    ///
+    pub fn deep_clone(&self) -> Self {
+        Self {
+            root: self.root.deep_clone(),
+        }
+    }
+
+    ///
+    /// A hash of this context that, unlike its `Hash` implementation, does not depend on the
+    /// platform or on the version of the standard library; it is the 64-bit FNV-1a hash of an
+    /// encoding of the context in which the members of objects are in name order.
+    ///
+    pub fn stable_hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        encode(&self.root, &mut bytes);
+        fnv1a(&bytes)
+    }
+
     fn find(&self, key: &FieldPath) -> Option<(FieldValue, FieldName)> {
         self.find_in(key, &self.root)
     }
@@ -399,10 +646,10 @@ impl Context {
             let name = key.first().unwrap();
             match container {
                 FieldValue::Array(array) => match usize::from_str(&name.to_string()) {
-                    Ok(key) => array.borrow().get(key),
+                    Ok(key) => array.read().unwrap().get(key),
                     Err(_) => None,
                 },
-                FieldValue::Object(object) => object.borrow().get(name.clone()),
+                FieldValue::Object(object) => object.read().unwrap().get(name.clone()),
                 _ => None,
             }
             .and_then(|v| {
@@ -424,6 +671,67 @@ impl Context {
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Append `value` to `bytes`, each value as a tag followed by its contents, with lengths and
+/// numbers little-endian; as for `Hash`, floats are encoded by their bits, with `-0.0` as `0.0`.
+///
+fn encode(value: &FieldValue, bytes: &mut Vec<u8>) {
+    fn encode_str(value: &str, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(value.as_bytes());
+    }
+
+    match value {
+        FieldValue::Bool(v) => bytes.extend_from_slice(&[0, *v as u8]),
+        FieldValue::Byte(v) => bytes.extend_from_slice(&[1, *v]),
+        FieldValue::Integer(v) => {
+            bytes.push(2);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        FieldValue::Float(v) => {
+            bytes.push(3);
+            let v = if *v == 0.0 { 0.0 } else { *v };
+            bytes.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        FieldValue::String(v) => {
+            bytes.push(4);
+            encode_str(v, bytes);
+        }
+        FieldValue::Array(v) => {
+            bytes.push(5);
+            let v = v.read().unwrap();
+            let values = v.inner.read().unwrap();
+            bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+            for value in values.iter() {
+                encode(value, bytes);
+            }
+        }
+        FieldValue::Object(v) => {
+            bytes.push(6);
+            let v = v.read().unwrap();
+            let members = v.inner.read().unwrap();
+            let mut names: Vec<&FieldName> = members.keys().collect();
+            names.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+            bytes.extend_from_slice(&(names.len() as u64).to_le_bytes());
+            for name in names {
+                encode_str(&name.0, bytes);
+                encode(&members[name], bytes);
+            }
+        }
+    }
+}
+
+///
+/// The 64-bit FNV-1a hash of `bytes`.
+///
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
 // ------------------------------------------------------------------------------------------------
 // Modules
 // ------------------------------------------------------------------------------------------------
@@ -10,6 +10,21 @@ use uml_state_machine::core::ID;
 let first_id = ID::random_with_prefix("thing").unwrap();
 let _next_id = first_id.append_random();
 ```
+
+IDs may also be created within a namespace, which is then available from any ID created within
+it. The namespace is separated from the rest of the ID by `/`, rather than the `::` that separates
+the segments of a path, so that an ID with a prefix is not mistaken for one with a namespace.
+
+```rust
+use uml_state_machine::core::ID;
+use std::str::FromStr;
+
+let namespace = ID::from_str("billing").unwrap();
+let state_id = ID::random_in_namespace(&namespace, "state").unwrap();
+assert!(state_id.to_string().starts_with("billing/state::"));
+assert_eq!(state_id.namespace(), Some(namespace));
+assert_eq!(ID::random_with_prefix("thing").unwrap().namespace(), None);
+```
 */
 
 use std::fmt::Display;
@@ -63,7 +78,8 @@ trait IDValueGenerator: Sync {
     fn next(&self) -> String;
     fn invalid_value(&self) -> String;
     fn is_valid_value(&self, s: &str) -> bool {
-        self.is_valid_prefix(s)
+        s.split(NAMESPACE_SEPARATOR)
+            .all(|segment| self.is_valid_prefix(segment))
     }
     fn is_valid_prefix(&self, s: &str) -> bool {
         !s.is_empty()
@@ -99,6 +115,8 @@ impl FromStr for ID {
 
 const TAG_SEPARATOR: &str = "::";
 
+const NAMESPACE_SEPARATOR: char = '/';
+
 impl ID {
     pub fn random() -> Self {
         Self(IDGENERATOR.next())
@@ -127,9 +145,43 @@ impl ID {
         Self(format!("{}{}{}", self.0, TAG_SEPARATOR, Self::random()))
     }
 
+    ///
+    /// Create a new random ID of the form `namespace/kind::value`.
+    ///
+    pub fn random_in_namespace(namespace: &ID, kind: &str) -> error::Result<Self> {
+        if kind.is_empty() {
+            Err(error::ErrorKind::EmptyString.into())
+        } else if IDGENERATOR.is_valid_prefix(kind) {
+            Ok(Self(format!(
+                "{}{}{}{}{}",
+                namespace,
+                NAMESPACE_SEPARATOR,
+                kind,
+                TAG_SEPARATOR,
+                Self::random()
+            )))
+        } else {
+            Err(error::ErrorKind::InvalidCharacter.into())
+        }
+    }
+
+    ///
+    /// Returns the namespace the ID was created in, with `random_in_namespace`, if any; the
+    /// segments of an ID path, such as the prefix of `random_with_prefix`, are not a namespace.
+    ///
+    pub fn namespace(&self) -> Option<ID> {
+        self.0
+            .rsplit_once(NAMESPACE_SEPARATOR)
+            .map(|(namespace, _)| Self(namespace.to_string()))
+    }
+
+    ///
+    /// The segments of an ID path, and of its namespace.
+    ///
     pub fn split(&self) -> Vec<ID> {
         self.0
-            .split(TAG_SEPARATOR)
+            .split(NAMESPACE_SEPARATOR)
+            .flat_map(|namespace| namespace.split(TAG_SEPARATOR))
             .filter_map(|s| {
                 if IDGENERATOR.is_valid_value(s) {
                     Some(ID::from_str(s).unwrap())
@@ -157,15 +209,10 @@ mod generator {
     use super::IDValueGenerator;
     use std::cell::RefCell;
 
-    #[derive(Debug)]
+    #[allow(dead_code)]
+    #[derive(Debug, Default)]
     pub(super) struct StringGenerator {}
 
-    impl Default for StringGenerator {
-        fn default() -> Self {
-            Self {}
-        }
-    }
-
     #[allow(unsafe_code)]
     unsafe impl Sync for StringGenerator {}
 
@@ -223,4 +270,33 @@ mod tests {
         let first_id = ID::random_with_prefix("thing").unwrap();
         let _next_id = first_id.append_random();
     }
+
+    #[test]
+    fn test_namespace() {
+        assert_eq!(ID::random().namespace(), None);
+
+        let namespace = ID::from_str("billing").unwrap();
+        let id = ID::random_in_namespace(&namespace, "state").unwrap();
+        assert_eq!(id.split().len(), 3);
+        assert_eq!(id.namespace(), Some(namespace.clone()));
+        assert_eq!(id.append_random().namespace(), Some(namespace.clone()));
+
+        assert!(ID::random_in_namespace(&namespace, "").is_err());
+        assert!(ID::random_in_namespace(&namespace, "a state").is_err());
+        assert!(ID::random_in_namespace(&namespace, "a/state").is_err());
+
+        // a prefix, or any other path segment, is not a namespace
+        let prefixed = ID::random_with_prefix("thing").unwrap();
+        assert_eq!(prefixed.split().len(), 2);
+        assert_eq!(prefixed.namespace(), None);
+        assert_eq!(prefixed.append_random().namespace(), None);
+        assert!(ID::random_with_prefix("a/thing").is_err());
+
+        // namespaces may nest, and are kept when parsed
+        let nested = ID::random_in_namespace(&id, "region").unwrap();
+        assert_eq!(nested.namespace(), Some(id.clone()));
+        let parsed = ID::from_str(&id.to_string()).unwrap();
+        assert_eq!(parsed.namespace(), Some(namespace));
+        assert!(ID::from_str("billing/").is_err());
+    }
 }
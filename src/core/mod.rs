@@ -1,4 +1,6 @@
 /*!
+Common types, identifiers and the instance data context, used by both the definition and
+execution modules.
 */
 
 // ------------------------------------------------------------------------------------------------
@@ -9,4 +11,4 @@ pub mod id;
 pub use id::ID;
 
 pub mod context;
-pub use context::{Array, Context, FieldName, FieldValue, Object};
+pub use context::{Array, Context, FieldKind, FieldName, FieldPath, FieldValue, Object};
@@ -0,0 +1,185 @@
+/*!
+Do-activities are the behaviors that begin once a state has been entered and continue until they
+finish, or until the state is exited.
+
+A do-activity is started with `Behavior::start`, which is given a `DoActivityHandle`; by default
+this performs the behavior to completion and then finishes the handle. A `ThreadedActivity`
+instead runs its body on a new thread, the body reports progress through the handle, and should
+return promptly once the handle is cancelled, as it is when the state is exited. The executor
+does not consider the state complete until its do-activity has finished.
+
+# Example
+
+```rust
+use uml_state_machine::definition::activities::{threaded, DoActivityHandle};
+use uml_state_machine::definition::types::{Behavior, Event, Trigger};
+use uml_state_machine::core::ID;
+use std::cell::RefCell;
+
+let activity = threaded("download", |handle: DoActivityHandle| {
+    for chunk in 1..=4 {
+        if handle.is_cancelled() {
+            return;
+        }
+        handle.set_progress(chunk as f64 / 4.0);
+    }
+});
+
+let handle = DoActivityHandle::default();
+let raised: RefCell<Vec<Event>> = Default::default();
+activity.start(&ID::random(), &Trigger::any(), &raised, handle.clone());
+while !handle.is_finished() {
+    std::thread::yield_now();
+}
+assert_eq!(handle.progress(), Some(1.0));
+```
+
+*/
+
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::core::ID;
+use crate::definition::types::{Behavior, EventSink, Labeled, Trigger};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Shared between a running do-activity and its owner; the activity reports progress and that
+/// it has finished, the owner signals cancellation.
+///
+#[derive(Clone, Debug, Default)]
+pub struct DoActivityHandle {
+    inner: Arc<ActivityState>,
+}
+
+///
+/// The signature of the body of a `ThreadedActivity`.
+///
+pub type ActivityFn = dyn Fn(DoActivityHandle) + Send + Sync;
+
+///
+/// A do-activity whose body runs on its own thread.
+///
+pub struct ThreadedActivity {
+    label: Option<String>,
+    body: Arc<ActivityFn>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Create a do-activity, labeled with `label`, that runs `body` on its own thread.
+///
+pub fn threaded<F>(label: &str, body: F) -> ThreadedActivity
+where
+    F: Fn(DoActivityHandle) + Send + Sync + 'static,
+{
+    ThreadedActivity {
+        label: Some(label.to_string()),
+        body: Arc::new(body),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl DoActivityHandle {
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn finish(&self) {
+        self.inner.finished.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.inner.finished.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Report the fraction of the activity completed, clamped to the range `0.0..=1.0`.
+    ///
+    pub fn set_progress(&self, fraction: f64) {
+        *self.inner.progress.lock().unwrap() = Some(fraction.clamp(0.0, 1.0))
+    }
+
+    pub fn progress(&self) -> Option<f64> {
+        *self.inner.progress.lock().unwrap()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for ThreadedActivity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadedActivity")
+            .field("label", &self.label)
+            .finish()
+    }
+}
+
+impl Labeled for ThreadedActivity {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Behavior for ThreadedActivity {
+    ///
+    /// Run the body to completion on the calling thread.
+    ///
+    fn perform(&self, _in_state: &ID, _on_trigger: &Trigger, _events: &dyn EventSink) {
+        (self.body)(DoActivityHandle::default())
+    }
+
+    fn start(
+        &self,
+        _in_state: &ID,
+        _on_trigger: &Trigger,
+        _events: &dyn EventSink,
+        handle: DoActivityHandle,
+    ) {
+        let body = self.body.clone();
+        let _ = std::thread::spawn(move || {
+            body(handle.clone());
+            handle.finish();
+        });
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        Some(Box::new(ThreadedActivity {
+            label: self.label.clone(),
+            body: self.body.clone(),
+        }))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+struct ActivityState {
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+    progress: Mutex<Option<f64>>,
+}
@@ -0,0 +1,687 @@
+/*!
+Static analysis of the behavior of a machine, beyond the structural rules checked by `validate`.
+
+[`reachable`] finds the vertices that may be reached from the machine's initial configuration,
+by any sequence of events, and so those that can never be reached. As guards and change events
+cannot be evaluated without an instance, every trigger is assumed to occur and every guard to
+hold at some point; the vertices reported as unreachable, and the transitions reported as never
+enabled, are therefore so whatever the machine's behaviors and context. The analysis does
+account for the order in which the executor takes the branches of choices and junctions, for
+initial and history pseudostates following only their first transition, and for completion
+transitions of composite states, which are enabled only where each of the state's regions may
+reach a final state.
+
+[`conflicts`] finds the pairs of transitions from the same state that may be enabled by the same
+event, which the executor cannot choose between unless it is deterministic or has a tie-breaker,
+and otherwise fails the step with `MoreThanOneTransition`. A pair conflicts where the triggers
+have the same event and payload predicates that are not disjoint, and one of the transitions is
+unguarded or both have the same guard; the conflict is certain where neither is guarded.
+
+# Example
+
+```rust
+use uml_state_machine::definition::analysis::reachable;
+use uml_state_machine::definition::builder::MachineBuilder;
+
+let (machine, names) = MachineBuilder::new()
+    .region(|region| {
+        region
+            .initial("idle")
+            .state("idle", |state| state.on("go", "done"))
+            .state("orphan", |state| state.on("go", "idle"))
+            .final_state("done")
+    })
+    .build_with_names()
+    .unwrap();
+
+let report = reachable(&machine);
+assert!(report.is_reachable(&names["done"]));
+assert!(report.can_complete());
+assert_eq!(report.unreachable_vertices(), &[names["orphan"].clone()]);
+assert_eq!(report.never_enabled_transitions().len(), 1);
+```
+
+```rust
+use uml_state_machine::definition::analysis::conflicts;
+use uml_state_machine::definition::builder::MachineBuilder;
+
+let (machine, names) = MachineBuilder::new()
+    .region(|region| {
+        region
+            .initial("idle")
+            .state("idle", |state| state.on("go", "left").on("go", "right"))
+            .final_state("left")
+            .final_state("right")
+    })
+    .build_with_names()
+    .unwrap();
+
+let conflicts = conflicts(&machine);
+assert_eq!(conflicts.len(), 1);
+assert_eq!(conflicts[0].source(), &names["idle"]);
+assert!(conflicts[0].is_certain());
+```
+
+*/
+
+use crate::core::ID;
+use crate::definition::guards::is_else;
+use crate::definition::types::{
+    ComparisonOperator, HasRegions, Identified, PseudoStateKind, Region, RegionContainerType,
+    StateMachine, Transition, Trigger, Vertex,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The results of [`reachable`]; vertices are listed in the order they appear in the machine,
+/// and transitions by the ID of the region containing them and their index within it, as for
+/// `StateMachine::remove_transition`.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReachabilityReport {
+    pub(crate) reachable: Vec<ID>,
+    pub(crate) unreachable: Vec<ID>,
+    pub(crate) final_states: Vec<ID>,
+    pub(crate) never_enabled: Vec<(ID, usize)>,
+    pub(crate) completes: bool,
+}
+
+///
+/// A pair of transitions from the same state that may be enabled by the same event, found by
+/// [`conflicts`].
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub(crate) source: ID,
+    pub(crate) trigger: Option<Trigger>,
+    pub(crate) transitions: [(ID, usize); 2],
+    pub(crate) certain: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Determine the vertices of `machine` that may be reached from its initial configuration; see
+/// the module documentation.
+///
+pub fn reachable(machine: &StateMachine) -> ReachabilityReport {
+    let model = Model::new(machine);
+    let mut completing: HashSet<ID> = Default::default();
+    loop {
+        let search = model.search(&completing);
+        // composite states may complete once each of their regions may reach a final state
+        let now_completing: HashSet<ID> = model
+            .regions_of
+            .iter()
+            .filter(|(state, regions)| {
+                search.reached.contains(*state)
+                    && regions
+                        .iter()
+                        .all(|region| model.may_finish(region, &search.reached))
+            })
+            .map(|(state, _)| state.clone())
+            .collect();
+        if now_completing == completing {
+            return model.report(machine, search);
+        }
+        completing = now_completing;
+    }
+}
+
+///
+/// Find the pairs of transitions from each state of `machine` that may be enabled by the same
+/// event; see the module documentation.
+///
+pub fn conflicts(machine: &StateMachine) -> Vec<Conflict> {
+    let model = Model::new(machine);
+    let mut conflicts: Vec<Conflict> = Default::default();
+    for vertex in model
+        .vertices
+        .iter()
+        .filter(|vertex| vertex.as_state().is_some())
+    {
+        let from = model
+            .transitions_from
+            .get(vertex.id())
+            .map_or(&[][..], Vec::as_slice);
+        for (position, first) in from.iter().enumerate() {
+            for second in &from[position + 1..] {
+                let (first_region, first_index, lhs) = &model.transitions[*first];
+                let (second_region, second_index, rhs) = &model.transitions[*second];
+                if let Some(trigger) = shared_trigger(lhs, rhs) {
+                    if guards_overlap(lhs, rhs) {
+                        conflicts.push(Conflict {
+                            source: vertex.id().clone(),
+                            trigger: trigger.cloned(),
+                            transitions: [
+                                (first_region.clone(), *first_index),
+                                (second_region.clone(), *second_index),
+                            ],
+                            certain: !lhs.has_guard() && !rhs.has_guard(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl ReachabilityReport {
+    ///
+    /// The vertices that may be reached from the machine's initial configuration.
+    ///
+    pub fn reachable_vertices(&self) -> &[ID] {
+        &self.reachable
+    }
+
+    pub fn is_reachable(&self, vertex: &ID) -> bool {
+        self.reachable.contains(vertex)
+    }
+
+    ///
+    /// The vertices that can never be reached, whatever events occur.
+    ///
+    pub fn unreachable_vertices(&self) -> &[ID] {
+        &self.unreachable
+    }
+
+    ///
+    /// The final states, at any depth, that may be reached.
+    ///
+    pub fn attainable_final_states(&self) -> &[ID] {
+        &self.final_states
+    }
+
+    ///
+    /// Returns `true` if each of the machine's regions may reach a final state, and so an
+    /// instance of the machine may complete.
+    ///
+    pub fn can_complete(&self) -> bool {
+        self.completes
+    }
+
+    ///
+    /// The transitions that can never fire, as their source is unreachable, an earlier branch
+    /// of the same choice or junction is always taken, they are not the first transition of an
+    /// initial or history pseudostate, or they are completion transitions of a composite state
+    /// that cannot complete.
+    ///
+    pub fn never_enabled_transitions(&self) -> &[(ID, usize)] {
+        &self.never_enabled
+    }
+
+    ///
+    /// Returns `true` if every vertex may be reached and every transition may fire.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.unreachable.is_empty() && self.never_enabled.is_empty()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Conflict {
+    ///
+    /// The state from which both transitions lead.
+    ///
+    pub fn source(&self) -> &ID {
+        &self.source
+    }
+
+    ///
+    /// The trigger of the first transition that both may be triggered by, or `None` where both
+    /// are completion transitions.
+    ///
+    pub fn trigger(&self) -> &Option<Trigger> {
+        &self.trigger
+    }
+
+    ///
+    /// The transitions, in document order, as the ID of the region containing each and its
+    /// index within it.
+    ///
+    pub fn transitions(&self) -> &[(ID, usize); 2] {
+        &self.transitions
+    }
+
+    ///
+    /// Returns `true` if neither transition is guarded, so that both are always enabled
+    /// together.
+    ///
+    pub fn is_certain(&self) -> bool {
+        self.certain
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The vertices and transitions of a machine, and how they contain each other.
+///
+struct Model {
+    vertices: Vec<Arc<Vertex>>,
+    vertex_region: HashMap<ID, ID>,
+    region_owner: HashMap<ID, Option<ID>>,
+    regions_of: HashMap<ID, Vec<ID>>,
+    region_vertices: HashMap<ID, Vec<ID>>,
+    initials: HashMap<ID, ID>,
+    transitions: Vec<(ID, usize, Arc<Transition>)>,
+    transitions_from: HashMap<ID, Vec<usize>>,
+}
+
+#[derive(Default)]
+struct Search {
+    reached: HashSet<ID>,
+    enabled: HashSet<usize>,
+    pending: VecDeque<ID>,
+}
+
+impl Model {
+    fn new(machine: &StateMachine) -> Self {
+        let mut model = Self {
+            vertices: Default::default(),
+            vertex_region: Default::default(),
+            region_owner: Default::default(),
+            regions_of: Default::default(),
+            region_vertices: Default::default(),
+            initials: Default::default(),
+            transitions: Default::default(),
+            transitions_from: Default::default(),
+        };
+        machine.for_each_region(&mut |region| model.add_region(region));
+        model
+    }
+
+    fn add_region(&mut self, region: &Region) {
+        let region_id = region.id().clone();
+        let owner = match region.container_type() {
+            RegionContainerType::State => Some(region.container.clone()),
+            RegionContainerType::StateMachine => None,
+        };
+        if let Some(owner) = &owner {
+            self.regions_of
+                .entry(owner.clone())
+                .or_default()
+                .push(region_id.clone());
+        }
+        let _ = self.region_owner.insert(region_id.clone(), owner);
+        let mut vertices: Vec<ID> = Default::default();
+        for vertex in region.vertices() {
+            let id = vertex.id().clone();
+            if vertex
+                .as_pseudo_state()
+                .is_some_and(|pseudo_state| pseudo_state.kind() == PseudoStateKind::Initial)
+            {
+                let _ = self.initials.entry(region_id.clone()).or_insert(id.clone());
+            }
+            let _ = self.vertex_region.insert(id.clone(), region_id.clone());
+            vertices.push(id);
+            self.vertices.push(vertex);
+        }
+        let _ = self.region_vertices.insert(region_id.clone(), vertices);
+        for (index, transition) in region.transitions().into_iter().enumerate() {
+            self.transitions_from
+                .entry(transition.source())
+                .or_default()
+                .push(self.transitions.len());
+            self.transitions
+                .push((region_id.clone(), index, transition));
+        }
+    }
+
+    fn search(&self, completing: &HashSet<ID>) -> Search {
+        let mut search = Search::default();
+        for (region, owner) in &self.region_owner {
+            if owner.is_none() {
+                self.enter_region(region, &mut search);
+            }
+        }
+        while let Some(vertex) = search.pending.pop_front() {
+            let transitions = self
+                .transitions_from
+                .get(&vertex)
+                .map_or(&[][..], Vec::as_slice);
+            for (position, index) in transitions.iter().enumerate() {
+                let transition = &self.transitions[*index].2;
+                if self.is_enabled(&vertex, transition, &transitions[..position], completing) {
+                    let _ = search.enabled.insert(*index);
+                    self.enter(&transition.target(), &mut search);
+                }
+            }
+        }
+        search
+    }
+
+    ///
+    /// Returns `true` if `transition`, from `vertex`, may fire given the transitions from the
+    /// same vertex that precede it, `preceding`.
+    ///
+    fn is_enabled(
+        &self,
+        vertex: &ID,
+        transition: &Transition,
+        preceding: &[usize],
+        completing: &HashSet<ID>,
+    ) -> bool {
+        match self.vertex(vertex) {
+            Some(Vertex::PseudoState(pseudo_state)) => match pseudo_state.kind() {
+                PseudoStateKind::Initial
+                | PseudoStateKind::ShallowHistory
+                | PseudoStateKind::DeepHistory => preceding.is_empty(),
+                PseudoStateKind::Choice | PseudoStateKind::Junction => {
+                    let is_otherwise = transition
+                        .guard()
+                        .as_ref()
+                        .is_some_and(|guard| is_else(guard.as_ref()));
+                    let always_taken = |index: &usize| self.transitions[*index].2.guard().is_none();
+                    if is_otherwise {
+                        // an else branch is only taken when no other branch may be
+                        !self.transitions_from[vertex].iter().any(always_taken)
+                    } else {
+                        !preceding.iter().any(always_taken)
+                    }
+                }
+                _ => true,
+            },
+            Some(Vertex::State(_)) if !transition.has_triggers() => {
+                !self.regions_of.contains_key(vertex) || completing.contains(vertex)
+            }
+            _ => true,
+        }
+    }
+
+    fn vertex(&self, id: &ID) -> Option<&Vertex> {
+        self.vertices
+            .iter()
+            .find(|vertex| vertex.id() == id)
+            .map(AsRef::as_ref)
+    }
+
+    fn reach(&self, vertex: &ID, search: &mut Search) -> bool {
+        if search.reached.insert(vertex.clone()) {
+            search.pending.push_back(vertex.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn enter_region(&self, region: &ID, search: &mut Search) {
+        if let Some(initial) = self.initials.get(region) {
+            self.enter(initial, search);
+        }
+    }
+
+    ///
+    /// Enter `vertex`, the states containing it, and by default the other regions of those
+    /// states.
+    ///
+    fn enter(&self, vertex: &ID, search: &mut Search) {
+        if !self.reach(vertex, search) {
+            return;
+        }
+        let mut current = vertex.clone();
+        while let Some(region) = self.vertex_region.get(&current).cloned() {
+            match &self.region_owner[&region] {
+                None => break,
+                Some(owner) => {
+                    for other in &self.regions_of[owner] {
+                        if *other != region {
+                            self.enter_region(other, search);
+                        }
+                    }
+                    let _ = self.reach(owner, search);
+                    current = owner.clone();
+                }
+            }
+        }
+        match self.vertex(vertex) {
+            Some(Vertex::State(state)) if !state.is_final() => {
+                if let Some(regions) = self.regions_of.get(vertex) {
+                    for region in regions {
+                        self.enter_region(region, search);
+                    }
+                }
+            }
+            Some(Vertex::ConnectionPointReference(reference)) => {
+                for entry in reference.entry() {
+                    self.enter(entry, search);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn may_finish(&self, region: &ID, reached: &HashSet<ID>) -> bool {
+        self.region_vertices[region].iter().any(|vertex| {
+            reached.contains(vertex)
+                && self
+                    .vertex(vertex)
+                    .and_then(Vertex::as_state)
+                    .is_some_and(|state| state.is_final())
+        })
+    }
+
+    fn report(&self, machine: &StateMachine, search: Search) -> ReachabilityReport {
+        let (reachable, unreachable): (Vec<ID>, Vec<ID>) = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.id().clone())
+            .partition(|id| search.reached.contains(id));
+        ReachabilityReport {
+            final_states: reachable
+                .iter()
+                .filter(|id| {
+                    self.vertex(id)
+                        .and_then(Vertex::as_state)
+                        .is_some_and(|state| state.is_final())
+                })
+                .cloned()
+                .collect(),
+            reachable,
+            unreachable,
+            never_enabled: self
+                .transitions
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !search.enabled.contains(index))
+                .map(|(_, (region, index, _))| (region.clone(), *index))
+                .collect(),
+            completes: machine
+                .regions()
+                .all(|region| self.may_finish(region.id(), &search.reached)),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The vertices of `machine` reached by a single search, where the states in `completing` are
+/// taken to be able to complete; the fixed point of `reachable` repeats this until `completing`
+/// no longer changes.
+///
+#[cfg(feature = "unstable")]
+pub(crate) fn reached_vertices(machine: &StateMachine, completing: &HashSet<ID>) -> HashSet<ID> {
+    Model::new(machine).search(completing).reached
+}
+
+///
+/// The trigger of `lhs` that an event triggering both transitions would fire, `Some(None)` where
+/// both are completion transitions, or `None` where no event triggers both.
+///
+fn shared_trigger<'a>(lhs: &'a Transition, rhs: &Transition) -> Option<Option<&'a Trigger>> {
+    if !lhs.has_triggers() && !rhs.has_triggers() {
+        return Some(None);
+    }
+    lhs.triggers()
+        .find(|left| {
+            rhs.triggers()
+                .any(|right| left.event() == right.event() && !predicates_disjoint(left, right))
+        })
+        .map(Some)
+}
+
+///
+/// Returns `true` if no payload may satisfy the predicates of both triggers, as they require
+/// different values of the same field.
+///
+fn predicates_disjoint(lhs: &Trigger, rhs: &Trigger) -> bool {
+    lhs.payload_predicates().any(|left| {
+        rhs.payload_predicates()
+            .filter(|right| right.path() == left.path())
+            .any(|right| match (left.operator(), right.operator()) {
+                (ComparisonOperator::Equal, ComparisonOperator::Equal) => {
+                    left.value() != right.value()
+                }
+                (ComparisonOperator::Equal, ComparisonOperator::NotEqual)
+                | (ComparisonOperator::NotEqual, ComparisonOperator::Equal) => {
+                    left.value() == right.value()
+                }
+                _ => false,
+            })
+    })
+}
+
+///
+/// Guards cannot be evaluated statically, so they may both hold where either transition is
+/// unguarded, or both have the same, labeled, guard.
+///
+fn guards_overlap(lhs: &Transition, rhs: &Transition) -> bool {
+    match (lhs.guard(), rhs.guard()) {
+        (Some(left), Some(right)) => left.label().is_some() && left.label() == right.label(),
+        _ => true,
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::builder::{MachineBuilder, TransitionBuilder};
+    use crate::definition::guards::else_guard;
+    use crate::definition::opaque::OpaqueConstraint;
+    use crate::definition::types::PayloadPredicate;
+
+    #[test]
+    fn test_reachable() {
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("working")
+                    .state("working", |state| {
+                        state
+                            .on("cancel", "decide")
+                            .transition(TransitionBuilder::to("done"))
+                            .region(|inner| {
+                                inner
+                                    .initial("a")
+                                    .state("a", |state| state.on("next", "a"))
+                                    .final_state("inner_done")
+                            })
+                    })
+                    .choice("decide")
+                    .transition("decide", TransitionBuilder::to("working"))
+                    .transition(
+                        "decide",
+                        TransitionBuilder::to("shadowed")
+                            .guard(OpaqueConstraint::new("never", None)),
+                    )
+                    .transition("decide", TransitionBuilder::to("done").guard(else_guard()))
+                    .simple_state("shadowed")
+                    .final_state("done")
+            })
+            .build_with_names()
+            .unwrap();
+
+        let report = reachable(&machine);
+        assert!(report.is_reachable(&names["working"]));
+        assert!(report.is_reachable(&names["a"]));
+        assert!(report.is_reachable(&names["decide"]));
+        assert_eq!(
+            report.unreachable_vertices(),
+            &[
+                names["shadowed"].clone(),
+                names["done"].clone(),
+                names["inner_done"].clone(),
+            ]
+        );
+        assert!(report.attainable_final_states().is_empty());
+        assert!(!report.can_complete());
+        // the completion of `working`, and the guarded and else branches of `decide`
+        assert_eq!(report.never_enabled_transitions().len(), 3);
+        assert!(!report.is_empty());
+    }
+
+    fn picked(choice: i64) -> Trigger {
+        let mut trigger = Trigger::signal("pick");
+        trigger.add_payload_predicate(PayloadPredicate::new(
+            "choice".parse().unwrap(),
+            ComparisonOperator::Equal,
+            choice.into(),
+        ));
+        trigger
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("idle")
+                    .state("idle", |state| {
+                        state
+                            .on("go", "a")
+                            .transition(
+                                TransitionBuilder::to("b")
+                                    .on("go")
+                                    .guard(OpaqueConstraint::new("ready", None)),
+                            )
+                            .transition(TransitionBuilder::to("a").trigger(picked(1)))
+                            .transition(TransitionBuilder::to("b").trigger(picked(2)))
+                            .transition(
+                                TransitionBuilder::to("a")
+                                    .on("stop")
+                                    .guard(OpaqueConstraint::new("x", None)),
+                            )
+                            .transition(
+                                TransitionBuilder::to("b")
+                                    .on("stop")
+                                    .guard(OpaqueConstraint::new("y", None)),
+                            )
+                    })
+                    .final_state("a")
+                    .final_state("b")
+            })
+            .build_with_names()
+            .unwrap();
+
+        let conflicts = conflicts(&machine);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.source(), &names["idle"]);
+        assert_eq!(conflict.trigger(), &Some(Trigger::signal("go")));
+        assert!(!conflict.is_certain());
+        let region = machine.default_region().unwrap().id().clone();
+        assert_eq!(conflict.transitions(), &[(region.clone(), 1), (region, 2)]);
+    }
+}
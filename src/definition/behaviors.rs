@@ -0,0 +1,307 @@
+/*!
+Composite behaviors, performing a number of [`Behavior`]s in order, and behaviors performed by
+closures.
+
+A state has a single entry, do-activity, and exit behavior, and a transition a single effect;
+where several actions are needed in one of these a [`Behaviors`] composite performs each of
+them, strictly in the order added, with the same state, trigger, event sink, and context. The
+composite is labeled with the labels of its behaviors, as `log_entry; start_timer`, unless it is
+given a label of its own.
+
+# Example
+
+```rust
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use uml_state_machine::core::ID;
+use uml_state_machine::definition::behaviors::{from_fn, Behaviors};
+use uml_state_machine::definition::types::{
+    Behavior, Event, Labeled, SignalEvent, Trigger,
+};
+
+let count = Arc::new(AtomicUsize::new(0));
+let counter = count.clone();
+let behavior = Behaviors::default()
+    .then(from_fn("count", move |_, _, _, _| {
+        let _ = counter.fetch_add(1, Ordering::SeqCst);
+    }))
+    .then(from_fn("notify", |_, _, events, _| {
+        events.raise(Event::Signal(SignalEvent::new("counted")))
+    }));
+
+let raised: RefCell<Vec<Event>> = Default::default();
+behavior.perform(&ID::random(), &Trigger::any(), &raised);
+
+assert_eq!(behavior.label(), &Some("count; notify".to_string()));
+assert_eq!(count.load(Ordering::SeqCst), 1);
+assert_eq!(raised.borrow().len(), 1);
+```
+
+*/
+
+use std::fmt::{Debug, Formatter};
+use std::slice::Iter;
+use std::sync::Arc;
+
+use crate::core::{Context, ID};
+use crate::definition::types::{Behavior, EventSink, Labeled, Trigger};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A behavior that performs each of its behaviors in order.
+///
+#[derive(Default)]
+pub struct Behaviors {
+    label: Option<String>,
+    /// `true` once the label has been set, or unset, so that adding behaviors keeps it.
+    labeled: bool,
+    behaviors: Vec<Box<dyn Behavior>>,
+}
+
+///
+/// The signature of a closure performed by a `FnBehavior`; the context is empty where the
+/// behavior is performed outside of an instance.
+///
+pub type BehaviorFn = dyn Fn(&ID, &Trigger, &dyn EventSink, &Context) + Send + Sync;
+
+///
+/// A behavior performed by a closure, see `from_fn`.
+///
+#[derive(Clone)]
+pub struct FnBehavior {
+    label: Option<String>,
+    perform: Arc<BehaviorFn>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Create a behavior, labeled `label`, performed by the closure `f`.
+///
+pub fn from_fn<F>(label: &str, f: F) -> FnBehavior
+where
+    F: Fn(&ID, &Trigger, &dyn EventSink, &Context) + Send + Sync + 'static,
+{
+    FnBehavior {
+        label: Some(label.to_string()),
+        perform: Arc::new(f),
+    }
+}
+
+///
+/// Create a behavior performing each of `behaviors` in order.
+///
+pub fn sequence(behaviors: impl IntoIterator<Item = Box<dyn Behavior>>) -> Behaviors {
+    let mut sequence = Behaviors::default();
+    for behavior in behaviors {
+        sequence.push(behavior);
+    }
+    sequence
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for Behaviors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Behaviors")
+            .field("label", &self.label)
+            .field("behaviors", &self.behaviors.len())
+            .finish()
+    }
+}
+
+impl Labeled for Behaviors {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string());
+        self.labeled = true;
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None;
+        self.labeled = true;
+    }
+}
+
+impl Behavior for Behaviors {
+    fn perform(&self, in_state: &ID, on_trigger: &Trigger, events: &dyn EventSink) {
+        for behavior in &self.behaviors {
+            behavior.perform(in_state, on_trigger, events)
+        }
+    }
+
+    fn perform_in(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        events: &dyn EventSink,
+        context: &Context,
+    ) {
+        for behavior in &self.behaviors {
+            behavior.perform_in(in_state, on_trigger, events, context)
+        }
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        let behaviors: Option<Vec<Box<dyn Behavior>>> = self
+            .behaviors
+            .iter()
+            .map(|behavior| behavior.duplicate())
+            .collect();
+        Some(Box::new(Behaviors {
+            label: self.label.clone(),
+            labeled: self.labeled,
+            behaviors: behaviors?,
+        }))
+    }
+}
+
+impl Behaviors {
+    ///
+    /// Add `behavior`, to be performed after those already added.
+    ///
+    pub fn then(mut self, behavior: impl Behavior + 'static) -> Self {
+        self.push(Box::new(behavior));
+        self
+    }
+
+    pub fn push(&mut self, behavior: Box<dyn Behavior>) {
+        self.behaviors.push(behavior);
+        if !self.labeled {
+            let labels: Vec<String> = self
+                .behaviors
+                .iter()
+                .filter_map(|behavior| behavior.label().clone())
+                .collect();
+            self.label = if labels.is_empty() {
+                None
+            } else {
+                Some(labels.join("; "))
+            };
+        }
+    }
+
+    pub fn behaviors(&self) -> Iter<'_, Box<dyn Behavior>> {
+        self.behaviors.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.behaviors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.behaviors.is_empty()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for FnBehavior {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnBehavior")
+            .field("label", &self.label)
+            .finish()
+    }
+}
+
+impl Labeled for FnBehavior {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Behavior for FnBehavior {
+    fn perform(&self, in_state: &ID, on_trigger: &Trigger, events: &dyn EventSink) {
+        (self.perform)(in_state, on_trigger, events, &Context::default())
+    }
+
+    fn perform_in(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        events: &dyn EventSink,
+        context: &Context,
+    ) {
+        (self.perform)(in_state, on_trigger, events, context)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::Compound;
+    use crate::core::FieldPath;
+    use crate::definition::opaque::OpaqueBehavior;
+    use crate::definition::types::Event;
+    use std::cell::RefCell;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_sequence() {
+        let performed = Arc::new(Mutex::new(vec![]));
+        let record = |name: &'static str| {
+            let performed = performed.clone();
+            from_fn(name, move |_, _, _, context| {
+                let total: FieldPath = "total".parse().unwrap();
+                performed
+                    .lock()
+                    .unwrap()
+                    .push((name, context.contains_key(total)))
+            })
+        };
+        let first: Box<dyn Behavior> = Box::new(record("first"));
+        let mut behavior = sequence(vec![first, Box::new(OpaqueBehavior::new("log()", None))])
+            .then(record("second"));
+        assert_eq!(behavior.len(), 3);
+        assert_eq!(behavior.label(), &Some("first; log(); second".to_string()));
+
+        let context = Context::default();
+        context.insert("total".parse::<FieldPath>().unwrap(), 1i64.into());
+        let raised: RefCell<Vec<Event>> = Default::default();
+        behavior.perform_in(&ID::random(), &Trigger::any(), &raised, &context);
+        behavior.perform(&ID::random(), &Trigger::any(), &raised);
+        assert_eq!(
+            *performed.lock().unwrap(),
+            vec![
+                ("first", true),
+                ("second", true),
+                ("first", false),
+                ("second", false)
+            ]
+        );
+
+        behavior.set_label("audit");
+        behavior.push(Box::new(record("third")));
+        assert_eq!(behavior.label(), &Some("audit".to_string()));
+        let copy = behavior.duplicate().unwrap();
+        assert_eq!(copy.label(), &Some("audit".to_string()));
+        assert!(Behaviors::default().label().is_none());
+    }
+}
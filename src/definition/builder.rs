@@ -1,508 +1,982 @@
 /*!
-One-line description.
-
-More detailed description, with
+A fluent builder for the model in `definition::types`.
+
+A `MachineBuilder` declares the regions of a machine, each with a `RegionBuilder`, which in turn
+declares the region's states, with a `StateBuilder`, its pseudostates, and transitions, with a
+`TransitionBuilder`. States may declare their own regions, so that nesting is written as it
+appears, and their own transitions, whose source is then the state. Vertices are given a name,
+unique within the machine, by which transitions refer to their targets, whether declared
+before or after; names are used as the labels of states and pseudostates unless they are given
+another. `MachineBuilder::build` creates, and validates, the machine, and
+`MachineBuilder::build_with_names` also returns the ID created for each name. The
+`state_machine!` macro creates a machine, with these builders, from a more concise description.
+
+The builders' types check some of the rules of the model as it is written: the closure that
+declares a region must return a builder on which `RegionBuilder::initial` has been called, which
+may only be called once, and a final state, declared with `RegionBuilder::final_state`, has no
+regions, behaviors, or transitions of its own. Rules that concern names, such as a transition
+from a final state declared with `RegionBuilder::transition`, are checked by `build`, as is an
+initial pseudostate declared with `RegionBuilder::pseudo_state` rather than `initial`.
+
+```rust,compile_fail
+use uml_state_machine::definition::builder::MachineBuilder;
+
+// a region must have an initial pseudostate.
+let machine = MachineBuilder::new().region(|region| region.simple_state("idle")).build();
+```
+
+```rust,compile_fail
+use uml_state_machine::definition::builder::MachineBuilder;
+
+// a region may not have two.
+let machine = MachineBuilder::new()
+    .region(|region| region.initial("idle").initial("idle").simple_state("idle"))
+    .build();
+```
+
+```rust,compile_fail
+use uml_state_machine::definition::builder::MachineBuilder;
+
+// a final state may not have transitions.
+let machine = MachineBuilder::new()
+    .region(|region| {
+        region
+            .initial("done")
+            .final_state_with("done", |state| state.on("again", "done"))
+    })
+    .build();
+```
+
+As the model is built from the outside in, a state's transitions belong to the region that
+contains the state, and those declared with `RegionBuilder::transition` to that region, in the
+order declared.
 
 # Example
 
+```rust
+use std::time::Duration;
+use uml_state_machine::definition::builder::{MachineBuilder, TransitionBuilder};
+use uml_state_machine::definition::guards::else_guard;
+use uml_state_machine::definition::opaque::OpaqueConstraint;
+use uml_state_machine::definition::types::*;
+
+let (machine, names) = MachineBuilder::labeled("door")
+    .region(|region| {
+        region
+            .initial("closed")
+            .state("closed", |state| state.on("open", "opened").on("lock", "locking"))
+            .state("opened", |state| {
+                state
+                    .on("close", "closed")
+                    .timeout(Duration::from_secs(60), "alarm")
+            })
+            .choice("locking")
+            .transition(
+                "locking",
+                TransitionBuilder::to("locked").guard(OpaqueConstraint::new("has_key", None)),
+            )
+            .transition("locking", TransitionBuilder::to("closed").guard(else_guard()))
+            .state("locked", |state| state.on("unlock", "closed"))
+            .state("alarm", |state| {
+                state.region(|inner| inner.initial("ringing").simple_state("ringing"))
+            })
+    })
+    .build_with_names()
+    .unwrap();
+
+assert_eq!(machine.label(), &Some("door".to_string()));
+let region = machine.default_region().unwrap();
+assert_eq!(region.vertices().len(), 6);
+assert_eq!(region.transitions_triggered_by(&names["closed"], &Trigger::signal("lock").event()).len(), 1);
+```
+
 */
 
-use crate::tag::StateID;
-use crate::{ActionFn, ConditionFn, State, StateKind, StateMachine, Transition};
+use crate::core::{FieldValue, ID};
+use crate::definition::behaviors::sequence;
+use crate::definition::cycles::debug_assert_no_cycles;
+use crate::definition::types::{
+    Behavior, Constraint, HasMetadata, HasRegions, Identified, Invoke, Labeled, PseudoState,
+    PseudoStateKind, Region, State, StateMachine, Transition, TransitionKind, Trigger, Validate,
+};
+use crate::error::{ErrorKind, Result};
 use std::collections::HashMap;
-use std::fmt::{Debug, Formatter};
-use std::hash::{Hash, Hasher};
-use std::rc::Rc;
-use std::str::FromStr;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+// ------------------------------------------------------------------------------------------------
+// Public Macros
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Create a machine, with a single region, from a concise description, returning the result of
+/// `MachineBuilder::build`.
+///
+/// The description is a list of statements, each ending in `;` unless it ends with a block:
+///
+/// * `label: "name";` labels the machine, and may only be the first statement.
+/// * `initial -> name;` declares the region's initial pseudostate, and its target.
+/// * `name;` declares a simple state, and `final name;` a final state.
+/// * `name { ... }` declares a composite state, whose region is described within the braces,
+///   and `name { ... } { ... }` an orthogonal state with one region for each block.
+/// * `choice name;`, `junction name;`, `history name;`, `deep_history name;`, and
+///   `terminate name;` declare pseudostates.
+/// * `source + Signal [guard] / effect => target;` declares a transition, where the trigger,
+///   guard, and effect are each optional. The guard is an expression whose value implements
+///   `Constraint`, and the effect an expression whose value implements `Behavior`.
+/// * `source + after(duration) => target;` declares a timeout.
+/// * `source + Signal [guard] / effect;`, without a target, declares an internal transition.
+///
+/// Names are identifiers, and every vertex is declared by a statement of its own. Each declared
+/// name becomes a constant of a module generated for the description, and each name a
+/// transition or initial pseudostate refers to is a path to such a constant, so that a name
+/// that is not declared, or is declared twice, does not compile.
+///
+/// ```rust,compile_fail
+/// use uml_state_machine::state_machine;
+///
+/// // `runing` is not declared.
+/// let machine = state_machine! {
+///     initial -> idle;
+///     idle;
+///     idle + Start => runing;
+///     running;
+/// };
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// use uml_state_machine::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+/// use uml_state_machine::definition::types::*;
+/// use uml_state_machine::state_machine;
+///
+/// let machine = state_machine! {
+///     label: "worker";
+///     initial -> idle;
+///     idle;
+///     idle + Start [OpaqueConstraint::new("is_ready", None)] / OpaqueBehavior::new("log()", None) => running;
+///     running {
+///         initial -> working;
+///         working;
+///         paused;
+///         working + Pause => paused;
+///         paused + Resume => working;
+///     }
+///     running + Stop => done;
+///     final done;
+/// }
+/// .unwrap();
+///
+/// assert_eq!(machine.label(), &Some("worker".to_string()));
+/// assert_eq!(machine.default_region().unwrap().vertices().len(), 4);
+/// ```
+///
+#[macro_export]
+macro_rules! state_machine {
+    (label: $label:literal; $($body:tt)*) => {{
+        #[allow(dead_code, non_upper_case_globals)]
+        mod __state_machine_names {
+            $crate::__state_machine_names!($($body)*);
+        }
+        $crate::definition::builder::MachineBuilder::labeled($label)
+            .region(|region| $crate::__state_machine_region!(region; $($body)*))
+            .build()
+    }};
+    ($($body:tt)*) => {{
+        #[allow(dead_code, non_upper_case_globals)]
+        mod __state_machine_names {
+            $crate::__state_machine_names!($($body)*);
+        }
+        $crate::definition::builder::MachineBuilder::new()
+            .region(|region| $crate::__state_machine_region!(region; $($body)*))
+            .build()
+    }};
+}
+
+///
+/// Declare a constant for each vertex declared by the description of a `state_machine!`, at
+/// any depth, by which `__state_machine_region` refers to it.
+///
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __state_machine_names {
+    () => {};
+    (@skip ; $($rest:tt)*) => {
+        $crate::__state_machine_names!($($rest)*);
+    };
+    (@skip $token:tt $($rest:tt)*) => {
+        $crate::__state_machine_names!(@skip $($rest)*);
+    };
+    (initial -> $target:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!($($rest)*);
+    };
+    (final $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    (choice $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    (junction $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    (history $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    (deep_history $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    (terminate $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    (@declare $name:ident $($rest:tt)*) => {
+        pub(super) const $name: &str = stringify!($name);
+        $crate::__state_machine_names!($($rest)*);
+    };
+    ($name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name $($rest)*);
+    };
+    ($name:ident { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::__state_machine_names!(@declare $name { $($inner)* } $($rest)*);
+    };
+    ({ $($inner:tt)* } $($rest:tt)*) => {
+        $crate::__state_machine_names!($($inner)*);
+        $crate::__state_machine_names!($($rest)*);
+    };
+    ($source:ident $($rest:tt)*) => {
+        $crate::__state_machine_names!(@skip $($rest)*);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __state_machine_region {
+    (@state $region:expr, $name:ident, [$($blocks:tt)*] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::__state_machine_region!(@state $region, $name, [$($blocks)* { $($inner)* }] $($rest)*)
+    };
+    (@state $region:expr, $name:ident, [$({ $($inner:tt)* })*] $($rest:tt)*) => {
+        $crate::__state_machine_region!(
+            $region.state(stringify!($name), |state| {
+                state $(.region(|region| $crate::__state_machine_region!(region; $($inner)*)))*
+            });
+            $($rest)*
+        )
+    };
+    ($region:expr;) => {
+        $region
+    };
+    ($region:expr; initial -> $target:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.initial(__state_machine_names::$target); $($rest)*)
+    };
+    ($region:expr; final $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.final_state(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; choice $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.choice(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; junction $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.junction(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; history $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.shallow_history(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; deep_history $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.deep_history(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; terminate $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.terminate(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; $name:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!($region.simple_state(stringify!($name)); $($rest)*)
+    };
+    ($region:expr; $name:ident { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::__state_machine_region!(@state $region, $name, [{ $($inner)* }] $($rest)*)
+    };
+    ($region:expr; $source:ident + after($after:expr) $([$guard:expr])? $(/ $effect:expr)? => $target:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!(
+            $region.transition(
+                __state_machine_names::$source,
+                $crate::definition::builder::TransitionBuilder::to(__state_machine_names::$target)
+                    .after($after)
+                    $(.guard($guard))?
+                    $(.effect($effect))?
+            );
+            $($rest)*
+        )
+    };
+    ($region:expr; $source:ident $(+ $signal:ident)? $([$guard:expr])? $(/ $effect:expr)? => $target:ident; $($rest:tt)*) => {
+        $crate::__state_machine_region!(
+            $region.transition(
+                __state_machine_names::$source,
+                $crate::definition::builder::TransitionBuilder::to(__state_machine_names::$target)
+                    $(.on(stringify!($signal)))?
+                    $(.guard($guard))?
+                    $(.effect($effect))?
+            );
+            $($rest)*
+        )
+    };
+    ($region:expr; $source:ident + $signal:ident $([$guard:expr])? $(/ $effect:expr)?; $($rest:tt)*) => {
+        $crate::__state_machine_region!(
+            $region.transition(
+                __state_machine_names::$source,
+                $crate::definition::builder::TransitionBuilder::internal()
+                    .on(stringify!($signal))
+                    $(.guard($guard))?
+                    $(.effect($effect))?
+            );
+            $($rest)*
+        )
+    };
+}
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
-pub struct StateMachineBuilder<E: Eq, D> {
+///
+/// Declares a machine, and its regions.
+///
+#[derive(Default)]
+pub struct MachineBuilder {
     label: Option<String>,
-    states: HashMap<StateID, StateBuilder<E, D>>,
-    initial: StateID,
+    namespace: Option<ID>,
+    metadata: HashMap<String, FieldValue>,
+    regions: Vec<RegionBuilder<WithInitial>>,
 }
 
-pub struct StateBuilder<E: Eq, D> {
-    id: StateID,
+///
+/// Declares a region, its vertices, and the transitions between them. `I` records whether the
+/// region's initial pseudostate has been declared.
+///
+pub struct RegionBuilder<I> {
     label: Option<String>,
-    kind: StateKind,
-    transitions: Vec<TransitionBuilder<E, D>>,
-    parent: Option<StateID>,
-    child_states: Vec<StateBuilder<E, D>>,
-    on_entry: Vec<ActionFn<D>>,
-    body: Vec<ActionFn<D>>,
-    on_exit: Vec<ActionFn<D>>,
+    metadata: HashMap<String, FieldValue>,
+    vertices: Vec<VertexBuilder>,
+    transitions: Vec<(String, TransitionBuilder)>,
+    initial: PhantomData<I>,
 }
 
-pub struct TransitionBuilder<E: Eq, D> {
+///
+/// A region builder without an initial pseudostate, which may not yet be built.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct WithoutInitial;
+
+///
+/// A region builder with an initial pseudostate, to which no other may be added.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct WithInitial;
+
+///
+/// Declares a state, its regions, behaviors, and the transitions from it. `K` is `Ordinary`, or
+/// `Final` for a final state, which may have none of these.
+///
+pub struct StateBuilder<K> {
+    name: String,
     label: Option<String>,
-    event: Option<E>,
-    target: Option<StateID>,
-    internal: bool,
-    conditions: Vec<ConditionFn<E, D>>,
-    actions: Vec<ActionFn<D>>,
+    metadata: HashMap<String, FieldValue>,
+    comments: Vec<String>,
+    regions: Vec<RegionBuilder<WithInitial>>,
+    entry: Option<Box<dyn Behavior>>,
+    do_activity: Option<Box<dyn Behavior>>,
+    exit: Option<Box<dyn Behavior>>,
+    deferrable_triggers: Vec<Trigger>,
+    invokes: Vec<Invoke>,
+    transitions: Vec<TransitionBuilder>,
+    kind: PhantomData<K>,
 }
 
-// ------------------------------------------------------------------------------------------------
-// Public Functions
-// ------------------------------------------------------------------------------------------------
+///
+/// A state builder for a state that is not final.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Ordinary;
+
+///
+/// A state builder for a final state.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Final;
+
+///
+/// Declares a transition, to a named target, or to its own source.
+///
+pub struct TransitionBuilder {
+    label: Option<String>,
+    metadata: HashMap<String, FieldValue>,
+    target: Option<String>,
+    kind: TransitionKind,
+    triggers: Vec<Trigger>,
+    guard: Option<Box<dyn Constraint>>,
+    effect: Option<Box<dyn Behavior>>,
+}
 
 // ------------------------------------------------------------------------------------------------
 // Implementations
 // ------------------------------------------------------------------------------------------------
 
-impl<E: Clone + Eq + Hash + Debug, D: Debug> Debug for StateMachineBuilder<E, D> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("StateMachineBuilder")
-            .field("label", &self.label)
-            .field("states", &self.states)
-            .field("initial", &self.initial)
-            .finish()
+impl MachineBuilder {
+    pub fn new() -> Self {
+        Default::default()
     }
-}
 
-impl<E: Clone + Eq + Hash, D> Default for StateMachineBuilder<E, D> {
-    fn default() -> Self {
+    pub fn labeled(label: &str) -> Self {
+        Self::new().label(label)
+    }
+
+    pub fn label(self, label: &str) -> Self {
         Self {
-            label: None,
-            states: Default::default(),
-            initial: StateID::invalid(),
+            label: Some(label.to_string()),
+            ..self
         }
     }
-}
 
-impl<E: Clone + Eq + Hash, D> From<&mut StateMachineBuilder<E, D>> for Rc<StateMachine<E, D>> {
-    fn from(builder: &mut StateMachineBuilder<E, D>) -> Self {
-        let mut chart: StateMachine<E, D> = StateMachine {
-            label: builder.label.clone(),
-            states: HashMap::with_capacity(builder.states.len()),
-            initial: builder.initial.clone(),
-            on_init: vec![],
-            on_done: vec![],
-        };
+    ///
+    /// Attach the tagged value `value`, named `key`, see `HasMetadata`.
+    ///
+    pub fn metadata(mut self, key: &str, value: FieldValue) -> Self {
+        let _ = self.metadata.insert(key.to_string(), value);
+        self
+    }
 
-        for state in builder.states.values() {
-            let _ = chart.states.insert(state.id.clone(), state.build());
+    ///
+    /// Create the machine, and all elements within it, in `namespace`, see
+    /// `StateMachine::in_namespace`.
+    ///
+    pub fn in_namespace(self, namespace: &ID) -> Self {
+        Self {
+            namespace: Some(namespace.clone()),
+            ..self
         }
-
-        chart.into()
     }
-}
 
-impl<E: Clone + Eq + Hash, D> StateMachineBuilder<E, D> {
-    fn new() -> Self {
-        Default::default()
-    }
-    pub fn unlabeled(&mut self) -> &mut Self {
-        self.label = None;
+    ///
+    /// Declare a region of the machine, the first being its default region.
+    ///
+    pub fn region(
+        mut self,
+        f: impl FnOnce(RegionBuilder<WithoutInitial>) -> RegionBuilder<WithInitial>,
+    ) -> Self {
+        self.regions.push(f(RegionBuilder::new()));
         self
     }
 
-    pub fn labeled(&mut self, label: &str) -> &mut Self {
-        self.label = Some(label.to_string());
-        self
+    ///
+    /// Create the machine, failing where a name is declared twice, a transition refers to a
+    /// name that is not declared, or the machine is not valid.
+    ///
+    pub fn build(self) -> Result<Arc<StateMachine>> {
+        self.build_with_names().map(|(machine, _)| machine)
     }
 
-    pub fn state(&mut self, state: &mut StateBuilder<E, D>) -> &mut Self {
-        let _ = self.states.insert(state.id.clone(), state.clone());
-        if let StateKind::Initial = state.kind {
-            self.initial = state.id.clone();
+    ///
+    /// Create the machine, as for `build`, returning with it the ID of each named vertex.
+    ///
+    pub fn build_with_names(self) -> Result<(Arc<StateMachine>, HashMap<String, ID>)> {
+        let mut machine = match &self.namespace {
+            None => StateMachine::default(),
+            Some(namespace) => StateMachine::in_namespace(namespace),
+        };
+        if let Some(label) = &self.label {
+            machine.set_label(label);
         }
-        self
+        for (key, value) in self.metadata {
+            machine.set_metadata(&key, value);
+        }
+        while machine.regions().len() < self.regions.len() {
+            let _ = machine.new_region();
+        }
+        let mut building = Building::default();
+        for (region, builder) in machine.regions.iter_mut().zip(self.regions) {
+            building.region(region, builder)?;
+        }
+        let mut transitions = building.transitions()?;
+        machine.for_each_region(&mut |region| {
+            for transition in transitions.remove(region.id()).unwrap_or_default() {
+                region.add_transition(transition);
+            }
+        });
+        machine.validate()?;
+        debug_assert_no_cycles(&machine);
+        Ok((Arc::new(machine), building.names))
     }
 }
 
 // ------------------------------------------------------------------------------------------------
 
-impl<E: Clone + Eq + Hash, D> Clone for StateBuilder<E, D> {
-    fn clone(&self) -> Self {
-        Self {
-            id: self.id.clone(),
-            label: self.label.clone(),
-            kind: self.kind.clone(),
-            transitions: self.transitions.clone(),
-            parent: self.parent.clone(),
-            child_states: self.child_states.clone(),
-            on_entry: self.on_entry.clone(),
-            body: self.body.clone(),
-            on_exit: self.on_exit.clone(),
-        }
-    }
-}
-
-impl<E: Clone + Eq + Hash, D> Default for StateBuilder<E, D> {
-    fn default() -> Self {
+impl RegionBuilder<WithoutInitial> {
+    fn new() -> Self {
         Self {
-            id: StateID::random_with_prefix("state").unwrap(),
             label: None,
-            kind: StateKind::Atomic,
+            metadata: Default::default(),
+            vertices: Default::default(),
             transitions: Default::default(),
-            parent: None,
-            child_states: Default::default(),
-            on_entry: vec![],
-            body: vec![],
-            on_exit: vec![],
+            initial: PhantomData,
         }
     }
-}
 
-impl<E: Clone + Eq + Hash + Debug, D: Debug> Debug for StateBuilder<E, D> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("StateBuilder")
-            .field("label", &self.label)
-            .field("kind", &self.kind)
-            .field("transitions", &self.transitions)
-            .field("parent", &self.parent)
-            .field("child_states", &self.child_states)
-            .field("on_entry", &"...".to_string())
-            .field("invoke", &"...".to_string())
-            .field("on_exit", &"...".to_string())
-            .finish()
+    ///
+    /// Declare the region's initial pseudostate, with a transition to `target`.
+    ///
+    pub fn initial(mut self, target: &str) -> RegionBuilder<WithInitial> {
+        self.vertices
+            .push(VertexBuilder::Initial(target.to_string()));
+        RegionBuilder {
+            label: self.label,
+            metadata: self.metadata,
+            vertices: self.vertices,
+            transitions: self.transitions,
+            initial: PhantomData,
+        }
     }
 }
 
-impl<E: Clone + Eq + Hash, D> StateBuilder<E, D> {
-    pub fn atomic() -> Self {
-        Self::make(StateKind::Atomic, None)
+impl<I> RegionBuilder<I> {
+    pub fn label(self, label: &str) -> Self {
+        Self {
+            label: Some(label.to_string()),
+            ..self
+        }
     }
 
-    pub fn atomic_with_id(id: &str) -> Self {
-        Self::make(StateKind::Atomic, Some(StateID::from_str(id).unwrap()))
+    pub fn metadata(mut self, key: &str, value: FieldValue) -> Self {
+        let _ = self.metadata.insert(key.to_string(), value);
+        self
     }
 
-    pub fn compound() -> Self {
-        Self::make(
-            StateKind::Composite {
-                child_states: Default::default(),
-                initial: StateID::invalid(),
-            },
-            None,
-        )
+    pub fn state(
+        mut self,
+        name: &str,
+        f: impl FnOnce(StateBuilder<Ordinary>) -> StateBuilder<Ordinary>,
+    ) -> Self {
+        self.vertices
+            .push(VertexBuilder::State(f(StateBuilder::new(name))));
+        self
     }
 
-    pub fn compound_with_id(id: &str) -> Self {
-        Self::make(
-            StateKind::Composite {
-                child_states: Default::default(),
-                initial: StateID::invalid(),
-            },
-            Some(StateID::from_str(id).unwrap()),
-        )
+    pub fn simple_state(self, name: &str) -> Self {
+        self.state(name, |state| state)
     }
 
-    pub fn parallel() -> Self {
-        Self::make(
-            StateKind::Orthogonal {
-                child_states: Default::default(),
-            },
-            None,
-        )
+    pub fn final_state(self, name: &str) -> Self {
+        self.final_state_with(name, |state| state)
     }
 
-    pub fn parallel_with_id(id: &str) -> Self {
-        Self::make(
-            StateKind::Orthogonal {
-                child_states: Default::default(),
-            },
-            Some(StateID::from_str(id).unwrap()),
-        )
+    pub fn final_state_with(
+        mut self,
+        name: &str,
+        f: impl FnOnce(StateBuilder<Final>) -> StateBuilder<Final>,
+    ) -> Self {
+        self.vertices
+            .push(VertexBuilder::FinalState(f(StateBuilder::new(name))));
+        self
     }
 
-    pub fn shallow_history() -> Self {
-        Self::make(
-            StateKind::History {
-                deep: false,
-                state: vec![],
-            },
-            None,
-        )
+    ///
+    /// Declare a pseudostate of `kind`, whose transitions are declared with `transition`. The
+    /// initial pseudostate is declared with `initial`, which the builder's type allows only once,
+    /// and `build` fails where `kind` is `PseudoStateKind::Initial`.
+    ///
+    pub fn pseudo_state(mut self, name: &str, kind: PseudoStateKind) -> Self {
+        self.vertices
+            .push(VertexBuilder::PseudoState(name.to_string(), kind));
+        self
     }
 
-    pub fn shallow_history_with_id(id: &str) -> Self {
-        Self::make(
-            StateKind::History {
-                deep: false,
-                state: vec![],
-            },
-            Some(StateID::from_str(id).unwrap()),
-        )
+    pub fn choice(self, name: &str) -> Self {
+        self.pseudo_state(name, PseudoStateKind::Choice)
     }
 
-    pub fn deep_history() -> Self {
-        Self::make(
-            StateKind::History {
-                deep: true,
-                state: vec![],
-            },
-            None,
-        )
+    pub fn junction(self, name: &str) -> Self {
+        self.pseudo_state(name, PseudoStateKind::Junction)
     }
 
-    pub fn deep_history_with_id(id: &str) -> Self {
-        Self::make(
-            StateKind::History {
-                deep: true,
-                state: vec![],
-            },
-            Some(StateID::from_str(id).unwrap()),
-        )
+    pub fn shallow_history(self, name: &str) -> Self {
+        self.pseudo_state(name, PseudoStateKind::ShallowHistory)
     }
 
-    pub fn initial() -> Self {
-        Self::make(StateKind::Initial, None)
+    pub fn deep_history(self, name: &str) -> Self {
+        self.pseudo_state(name, PseudoStateKind::DeepHistory)
     }
 
-    pub fn initial_with_id(id: &str) -> Self {
-        Self::make(StateKind::Initial, Some(StateID::from_str(id).unwrap()))
+    pub fn terminate(self, name: &str) -> Self {
+        self.pseudo_state(name, PseudoStateKind::Terminate)
     }
 
-    pub fn final_state() -> Self {
-        Self::make(StateKind::Final, None)
+    ///
+    /// Declare a transition from the vertex named `source`, as for the branches of a choice.
+    ///
+    pub fn transition(mut self, source: &str, transition: TransitionBuilder) -> Self {
+        self.transitions.push((source.to_string(), transition));
+        self
     }
+}
 
-    pub fn final_with_id(id: &str) -> Self {
-        Self::make(StateKind::Final, Some(StateID::from_str(id).unwrap()))
-    }
+// ------------------------------------------------------------------------------------------------
 
-    fn make(kind: StateKind, id: Option<StateID>) -> Self {
+impl<K> StateBuilder<K> {
+    fn new(name: &str) -> Self {
         Self {
-            id: match id {
-                None => StateID::random_with_prefix("state").unwrap(),
-                Some(id) => id,
-            },
+            name: name.to_string(),
             label: None,
-            kind,
+            metadata: Default::default(),
+            comments: Default::default(),
+            regions: Default::default(),
+            entry: None,
+            do_activity: None,
+            exit: None,
+            deferrable_triggers: Default::default(),
+            invokes: Default::default(),
             transitions: Default::default(),
-            parent: None,
-            child_states: Default::default(),
-            on_entry: Default::default(),
-            body: Default::default(),
-            on_exit: Default::default(),
+            kind: PhantomData,
         }
     }
 
-    pub fn unlabeled(&mut self) -> &mut Self {
-        self.label = None;
-        self
+    pub fn label(self, label: &str) -> Self {
+        Self {
+            label: Some(label.to_string()),
+            ..self
+        }
     }
 
-    pub fn labeled(&mut self, label: &str) -> &mut Self {
-        self.label = Some(label.to_string());
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comments.push(comment.to_string());
         self
     }
 
-    #[inline]
-    pub fn and(&mut self) -> &mut Self {
+    pub fn metadata(mut self, key: &str, value: FieldValue) -> Self {
+        let _ = self.metadata.insert(key.to_string(), value);
         self
     }
+}
 
-    #[inline]
-    pub fn then(&mut self) -> &mut Self {
+impl StateBuilder<Ordinary> {
+    ///
+    /// Declare a region of this state; a state with more than one region is orthogonal.
+    ///
+    pub fn region(
+        mut self,
+        f: impl FnOnce(RegionBuilder<WithoutInitial>) -> RegionBuilder<WithInitial>,
+    ) -> Self {
+        self.regions.push(f(RegionBuilder::new()));
         self
     }
 
-    #[inline]
-    pub fn on_entry(&mut self, action: ActionFn<D>) -> &mut Self {
-        self.on_entry.push(action);
-        self
+    ///
+    /// Add `entry` to the entry behavior of the state; where one has already been added both are
+    /// performed, in the order added, see `behaviors::Behaviors`.
+    ///
+    pub fn on_entry(self, entry: impl Behavior + 'static) -> Self {
+        Self {
+            entry: Some(and_then(self.entry, entry)),
+            ..self
+        }
     }
 
-    #[inline]
-    pub fn action(&mut self, action: ActionFn<D>) -> &mut Self {
-        self.body.push(action);
-        self
+    pub fn do_activity(self, do_activity: impl Behavior + 'static) -> Self {
+        Self {
+            do_activity: Some(Box::new(do_activity)),
+            ..self
+        }
+    }
+
+    ///
+    /// Add `exit` to the exit behavior of the state, as for `on_entry`.
+    ///
+    pub fn on_exit(self, exit: impl Behavior + 'static) -> Self {
+        Self {
+            exit: Some(and_then(self.exit, exit)),
+            ..self
+        }
     }
 
-    #[inline]
-    pub fn on_exit(&mut self, action: ActionFn<D>) -> &mut Self {
-        self.on_exit.push(action);
+    pub fn defer(mut self, trigger: Trigger) -> Self {
+        self.deferrable_triggers.push(trigger);
         self
     }
 
-    pub fn transition(&mut self, transition: &mut TransitionBuilder<E, D>) -> &mut Self {
-        if transition.target == Some(StateID::invalid()) {
-            transition.target = Some(self.id.clone());
-        }
-        self.transitions.push(transition.clone());
+    pub fn invoke(mut self, invoke: Invoke) -> Self {
+        self.invokes.push(invoke);
         self
     }
 
-    pub fn child(&mut self, state: &mut StateBuilder<E, D>) -> &mut Self {
-        self.child_states.push(state.clone());
+    pub fn transition(mut self, transition: TransitionBuilder) -> Self {
+        self.transitions.push(transition);
         self
     }
 
-    pub(self) fn build(&self) -> Rc<State<E, D>> {
-        let mut state: State<E, D> = State {
-            id: self.id.clone(),
-            label: self.label.clone(),
-            kind: self.kind.clone(),
-            transitions: Default::default(),
-            parent: None,
-            on_entry: self.on_entry.clone(),
-            on_run: self.body.clone(),
-            on_exit: self.on_exit.clone(),
-        };
-        for transition in &self.transitions {
-            state.transitions.push(transition.build());
-        }
-        state.into()
+    ///
+    /// Declare a transition to `target` triggered by the signal `signal`.
+    ///
+    pub fn on(self, signal: &str, target: &str) -> Self {
+        self.transition(TransitionBuilder::to(target).on(signal))
+    }
+
+    ///
+    /// Declare a timeout, see `Region::new_timeout`.
+    ///
+    pub fn timeout(self, after: Duration, target: &str) -> Self {
+        self.transition(TransitionBuilder::to(target).after(after))
     }
 }
 
 // ------------------------------------------------------------------------------------------------
 
-impl<E: Clone + Eq + Hash, D> Clone for TransitionBuilder<E, D> {
-    fn clone(&self) -> Self {
-        Self {
-            label: self.label.clone(),
-            event: self.event.clone(),
-            target: self.target.clone(),
-            internal: self.internal,
-            conditions: self.conditions.clone(),
-            actions: self.actions.clone(),
-        }
+impl TransitionBuilder {
+    ///
+    /// An external transition to `target`; a transition to its own source exits, and re-enters,
+    /// the source.
+    ///
+    pub fn to(target: &str) -> Self {
+        Self::with_target(Some(target.to_string()))
     }
-}
 
-impl<E: Clone + Eq + Hash + Debug, D: Debug> Debug for TransitionBuilder<E, D> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("TransitionBuilder")
-            .field("label", &self.label)
-            .field("event", &self.event)
-            .field("target", &self.target)
-            .field("internal", &self.internal)
-            .field("conditions", &"...".to_string())
-            .field("actions", &"...".to_string())
-            .finish()
+    ///
+    /// An internal transition, whose target is its source, which performs only its effect.
+    ///
+    pub fn internal() -> Self {
+        Self::with_target(None).kind(TransitionKind::Internal)
     }
-}
 
-impl<E: Clone + Eq + Hash, D> Default for TransitionBuilder<E, D> {
-    fn default() -> Self {
+    fn with_target(target: Option<String>) -> Self {
         Self {
             label: None,
-            event: None,
-            target: None,
-            conditions: vec![],
-            internal: false,
-            actions: vec![],
+            metadata: Default::default(),
+            target,
+            kind: TransitionKind::External,
+            triggers: Default::default(),
+            guard: None,
+            effect: None,
         }
     }
-}
 
-impl<E: Clone + Eq + Hash, D> PartialEq for TransitionBuilder<E, D> {
-    fn eq(&self, other: &Self) -> bool {
-        self.event == other.event && self.target == other.target && self.internal == other.internal
+    pub fn label(self, label: &str) -> Self {
+        Self {
+            label: Some(label.to_string()),
+            ..self
+        }
     }
-}
 
-impl<E: Clone + Eq + Hash, D> Eq for TransitionBuilder<E, D> {}
-
-impl<E: Clone + Eq + Hash, D> Hash for TransitionBuilder<E, D> {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.event.hash(state);
-        self.target.hash(state);
-        self.internal.hash(state);
+    pub fn metadata(mut self, key: &str, value: FieldValue) -> Self {
+        let _ = self.metadata.insert(key.to_string(), value);
+        self
     }
-}
 
-impl<E: Clone + Eq + Hash, D> TransitionBuilder<E, D> {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn kind(self, kind: TransitionKind) -> Self {
+        Self { kind, ..self }
     }
 
-    #[inline]
-    pub fn unlabeled(&mut self) -> &mut Self {
-        self.label = None;
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        self.triggers.push(trigger);
         self
     }
 
-    #[inline]
-    pub fn labeled(&mut self, label: &str) -> &mut Self {
-        self.label = Some(label.to_string());
-        self
+    pub fn on(self, signal: &str) -> Self {
+        self.trigger(Trigger::signal(signal))
     }
 
-    #[inline]
-    pub fn on_event(&mut self, event: E) -> &mut Self {
-        self.event = Some(event);
-        self
+    pub fn after(self, duration: Duration) -> Self {
+        self.trigger(Trigger::after(duration))
     }
 
-    #[inline]
-    pub fn to(&mut self, target_state: &str) -> &mut Self {
-        self.target = Some(StateID::from_str(target_state).unwrap());
-        self
+    pub fn guard(self, guard: impl Constraint + 'static) -> Self {
+        Self {
+            guard: Some(Box::new(guard)),
+            ..self
+        }
     }
 
-    #[inline]
-    pub fn back_to_self(&mut self) -> &mut Self {
-        self.target = Some(StateID::invalid());
-        self
+    ///
+    /// Add `effect` to the effect of the transition, as for `StateBuilder::on_entry`.
+    ///
+    pub fn effect(self, effect: impl Behavior + 'static) -> Self {
+        Self {
+            effect: Some(and_then(self.effect, effect)),
+            ..self
+        }
     }
+}
 
-    #[inline]
-    pub fn if_condition(&mut self, condition: ConditionFn<E, D>) -> &mut Self {
-        self.conditions.push(condition);
-        self
-    }
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
 
-    #[inline]
-    pub fn and(&mut self) -> &mut Self {
-        self
-    }
+enum VertexBuilder {
+    /// An initial pseudostate, and the name of its target.
+    Initial(String),
+    State(StateBuilder<Ordinary>),
+    FinalState(StateBuilder<Final>),
+    PseudoState(String, PseudoStateKind),
+}
 
-    #[inline]
-    pub fn then(&mut self) -> &mut Self {
-        self
+///
+/// The names declared so far, and the transitions to be added to each region once every name
+/// is known.
+///
+#[derive(Default)]
+struct Building {
+    names: HashMap<String, ID>,
+    pending: Vec<(ID, ID, TransitionBuilder)>,
+    pending_from: Vec<(ID, String, TransitionBuilder)>,
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Building {
+    fn name(&mut self, name: &str, id: &ID) -> Result<()> {
+        if self.names.insert(name.to_string(), id.clone()).is_some() {
+            Err(ErrorKind::DuplicateVertex(name.to_string()).into())
+        } else {
+            Ok(())
+        }
     }
 
-    #[inline]
-    pub fn do_action(&mut self, action: ActionFn<D>) -> &mut Self {
-        self.actions.push(action);
-        self
+    fn region(&mut self, region: &mut Region, builder: RegionBuilder<WithInitial>) -> Result<()> {
+        if let Some(label) = &builder.label {
+            region.set_label(label);
+        }
+        for (key, value) in builder.metadata {
+            region.set_metadata(&key, value);
+        }
+        for vertex in builder.vertices {
+            match vertex {
+                VertexBuilder::Initial(target) => {
+                    let initial = region.new_initial_state();
+                    self.pending.push((
+                        region.id().clone(),
+                        initial,
+                        TransitionBuilder::to(&target),
+                    ));
+                }
+                VertexBuilder::PseudoState(name, PseudoStateKind::Initial) => {
+                    return Err(ErrorKind::InitialPseudoState(name).into());
+                }
+                VertexBuilder::PseudoState(name, kind) => {
+                    let mut pseudo_state = PseudoState::within(region.id().clone(), kind);
+                    pseudo_state.set_label(&name);
+                    self.name(&name, pseudo_state.id())?;
+                    region.add_pseudo_state(pseudo_state);
+                }
+                VertexBuilder::State(builder) => {
+                    let state = self.state(region, builder, false)?;
+                    region.add_state(state);
+                }
+                VertexBuilder::FinalState(builder) => {
+                    let state = self.state(region, builder, true)?;
+                    region.add_state(state);
+                }
+            }
+        }
+        for (source, transition) in builder.transitions {
+            self.pending_from
+                .push((region.id().clone(), source, transition));
+        }
+        Ok(())
     }
 
-    #[inline]
-    pub fn externally(&mut self) -> &mut Self {
-        self.internal = false;
-        self
+    fn state<K>(
+        &mut self,
+        region: &Region,
+        builder: StateBuilder<K>,
+        final_state: bool,
+    ) -> Result<State> {
+        let mut state = State::within(region.id().clone());
+        state.set_label(builder.label.as_ref().unwrap_or(&builder.name));
+        self.name(&builder.name, state.id())?;
+        for comment in &builder.comments {
+            state.add_comment(comment);
+        }
+        for (key, value) in builder.metadata {
+            state.set_metadata(&key, value);
+        }
+        state.set_final(final_state);
+        for inner in builder.regions {
+            let mut inner_region = Region::within_state(state.id().clone());
+            self.region(&mut inner_region, inner)?;
+            state.add_region(inner_region);
+        }
+        if let Some(entry) = builder.entry {
+            state.set_entry(entry);
+        }
+        if let Some(do_activity) = builder.do_activity {
+            state.set_do_activity(do_activity);
+        }
+        if let Some(exit) = builder.exit {
+            state.set_exit(exit);
+        }
+        for trigger in builder.deferrable_triggers {
+            state.add_deferrable_trigger(trigger);
+        }
+        for invoke in builder.invokes {
+            state.add_invoke(invoke);
+        }
+        for transition in builder.transitions {
+            self.pending
+                .push((region.id().clone(), state.id().clone(), transition));
+        }
+        Ok(state)
     }
 
-    #[inline]
-    pub fn internally(&mut self) -> &mut Self {
-        self.internal = true;
-        self
+    ///
+    /// The transitions declared, with their names resolved, by the ID of their region.
+    ///
+    fn transitions(&mut self) -> Result<HashMap<ID, Vec<Transition>>> {
+        let mut pending = std::mem::take(&mut self.pending);
+        for (region, source, transition) in std::mem::take(&mut self.pending_from) {
+            pending.push((region, self.resolve(&source)?, transition));
+        }
+        let mut transitions: HashMap<ID, Vec<Transition>> = Default::default();
+        for (region, source, builder) in pending {
+            let target = match &builder.target {
+                None => source.clone(),
+                Some(target) => self.resolve(target)?,
+            };
+            let mut transition = Transition::within(source, target, region.clone());
+            if let Some(label) = &builder.label {
+                transition.set_label(label);
+            }
+            for (key, value) in builder.metadata {
+                transition.set_metadata(&key, value);
+            }
+            transition.set_kind(builder.kind);
+            for trigger in builder.triggers {
+                transition.add_trigger(trigger);
+            }
+            if let Some(guard) = builder.guard {
+                transition.set_guard(guard);
+            }
+            if let Some(effect) = builder.effect {
+                transition.set_effect(effect);
+            }
+            transitions.entry(region).or_default().push(transition);
+        }
+        Ok(transitions)
     }
 
-    pub(self) fn build(&self) -> Transition<E, D> {
-        let transition: Transition<E, D> = Transition {
-            label: self.label.clone(),
-            event: self.event.clone(),
-            target: self.target.clone(),
-            internal: self.internal,
-            guard: self.conditions.clone(),
-            actions: self.actions.clone(),
-        };
-        transition
+    fn resolve(&self, name: &str) -> Result<ID> {
+        self.names
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ErrorKind::UnknownVertex(name.to_string()).into())
     }
 }
 
-// ------------------------------------------------------------------------------------------------
-// Private Types
-// ------------------------------------------------------------------------------------------------
-
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
-// ------------------------------------------------------------------------------------------------
-// Modules
-// ------------------------------------------------------------------------------------------------
+fn and_then(
+    existing: Option<Box<dyn Behavior>>,
+    next: impl Behavior + 'static,
+) -> Box<dyn Behavior> {
+    match existing {
+        None => Box::new(next),
+        Some(existing) => Box::new(sequence(vec![existing]).then(next)),
+    }
+}
 
 // ------------------------------------------------------------------------------------------------
 // Unit Tests
@@ -511,50 +985,419 @@ impl<E: Clone + Eq + Hash, D> TransitionBuilder<E, D> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ErrorKind, StateMachineInstance};
+    use crate::definition::guards::else_guard;
+    use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+    use crate::definition::types::{Event, SignalEvent};
 
-    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-    enum Event {
-        This,
-        That,
+    fn signal(name: &str) -> Event {
+        Event::Signal(SignalEvent::new(name))
     }
 
     #[test]
-    fn test_simple() {
-        let simple: Rc<StateMachine<Event, HashMap<String, String>>> = StateMachineBuilder::new()
-            .labeled("simple")
-            .state(
-                StateBuilder::initial()
-                    .labeled("Start Here")
-                    .transition(TransitionBuilder::new().to("end")),
-            )
-            .state(StateBuilder::final_with_id("end").labeled("End Here"))
-            .into();
+    fn test_nested_machine() {
+        let namespace: ID = "orders".parse().unwrap();
+        let (machine, names) = MachineBuilder::labeled("order")
+            .in_namespace(&namespace)
+            .region(|region| {
+                region
+                    .label("main")
+                    .initial("open")
+                    .state("open", |state| {
+                        state
+                            .on_entry(OpaqueBehavior::new("reserve()", None))
+                            .defer(Trigger::signal("ship"))
+                            .transition(
+                                TransitionBuilder::internal()
+                                    .on("touch")
+                                    .effect(OpaqueBehavior::new("touched()", None)),
+                            )
+                            .on("pay", "paying")
+                    })
+                    .choice("paying")
+                    .transition(
+                        "paying",
+                        TransitionBuilder::to("fulfilment")
+                            .guard(OpaqueConstraint::new("paid", None)),
+                    )
+                    .transition("paying", TransitionBuilder::to("open").guard(else_guard()))
+                    .state("fulfilment", |state| {
+                        state
+                            .region(|packing| {
+                                packing
+                                    .initial("packing")
+                                    .state("packing", |state| state.on("packed", "packed"))
+                                    .final_state("packed")
+                            })
+                            .region(|billing| {
+                                billing
+                                    .initial("billing")
+                                    .state("billing", |state| state.on("billed", "billed"))
+                                    .final_state("billed")
+                            })
+                            .transition(TransitionBuilder::to("done"))
+                    })
+                    .final_state("done")
+            })
+            .region(|audit| audit.initial("watching").simple_state("watching"))
+            .build_with_names()
+            .unwrap();
+
+        assert_eq!(machine.regions().len(), 2);
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.label(), &Some("main".to_string()));
+        assert_eq!(region.vertices().len(), 5);
+        assert_eq!(region.transitions().len(), 6);
+        assert!(names["open"].to_string().starts_with("orders/"));
+
+        let open = region
+            .vertices()
+            .into_iter()
+            .find(|vertex| vertex.id() == &names["open"])
+            .unwrap();
+        let open = open.as_state().unwrap();
+        assert_eq!(open.label(), &Some("open".to_string()));
+        assert!(open.entry().is_some());
+        assert_eq!(open.deferrable_triggers().count(), 1);
+        let touched = region.transitions_triggered_by(&names["open"], &signal("touch"));
+        assert!(touched[0].is_internal());
+        assert_eq!(touched[0].target(), names["open"]);
+
+        let branches: Vec<ID> = region
+            .transitions()
+            .iter()
+            .filter(|transition| transition.source() == names["paying"])
+            .map(|transition| transition.target())
+            .collect();
+        assert_eq!(
+            branches,
+            vec![names["fulfilment"].clone(), names["open"].clone()]
+        );
+
+        let fulfilment = region
+            .vertices()
+            .into_iter()
+            .find(|vertex| vertex.id() == &names["fulfilment"])
+            .unwrap();
+        let fulfilment = fulfilment.as_state().unwrap();
+        assert!(fulfilment.is_orthogonal());
+        let packing = fulfilment.region(0).unwrap();
+        assert_eq!(packing.vertices().len(), 3);
+        assert_eq!(
+            packing.transitions_triggered_by(&names["packing"], &signal("packed"))[0].target(),
+            names["packed"]
+        );
+    }
 
-        let valid = simple.validate();
-        println!("{:#?}", valid);
-        assert!(valid.is_ok());
+    #[test]
+    fn test_invalid_machines() {
+        let duplicate = MachineBuilder::new()
+            .region(|region| region.initial("a").simple_state("a").simple_state("a"))
+            .build();
+        assert!(matches!(
+            duplicate,
+            Err(crate::error::Error(ErrorKind::DuplicateVertex(_), _))
+        ));
+
+        let unknown = MachineBuilder::new()
+            .region(|region| region.initial("a").state("a", |state| state.on("go", "b")))
+            .build();
+        assert!(matches!(
+            unknown,
+            Err(crate::error::Error(ErrorKind::UnknownVertex(_), _))
+        ));
+
+        let initial = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("a")
+                    .simple_state("a")
+                    .pseudo_state("start", PseudoStateKind::Initial)
+                    .transition("start", TransitionBuilder::to("a"))
+            })
+            .build();
+        assert!(matches!(
+            initial,
+            Err(crate::error::Error(ErrorKind::InitialPseudoState(_), _))
+        ));
+
+        let conflicting = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("a")
+                    .state("a", |state| {
+                        state
+                            .timeout(Duration::from_secs(1), "b")
+                            .timeout(Duration::from_secs(1), "a")
+                    })
+                    .simple_state("b")
+            })
+            .build();
+        assert!(conflicting.is_err());
+    }
+    #[test]
+    fn test_macro() {
+        let machine = crate::state_machine! {
+            initial -> idle;
+            idle;
+            idle + Start => checking;
+            idle + Touch / OpaqueBehavior::new("touched()", None);
+            choice checking;
+            checking [OpaqueConstraint::new("ready", None)] => running;
+            checking [else_guard()] => idle;
+            running {
+                initial -> a;
+                a;
+                a + Next => b;
+                b;
+            } {
+                initial -> c;
+                history h;
+                c;
+            }
+            running + after(Duration::from_secs(5)) => done;
+            terminate done;
+        }
+        .unwrap();
+
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.vertices().len(), 5);
+        let transitions = region.transitions();
+        assert_eq!(transitions.len(), 6);
+        assert!(transitions[2].is_internal());
+        assert_eq!(transitions[2].source(), transitions[2].target());
+        assert!(transitions[3].has_guard());
+        assert!(transitions[5].triggers().next().unwrap().event().is_time());
+
+        let running = region
+            .vertices()
+            .into_iter()
+            .find(|vertex| vertex.as_state().is_some_and(|state| state.is_orthogonal()))
+            .unwrap();
+        let running = running.as_state().unwrap();
+        assert_eq!(running.label(), &Some("running".to_string()));
+        assert_eq!(running.region(0).unwrap().transitions().len(), 2);
+        assert_eq!(running.region(1).unwrap().vertices().len(), 3);
+    }
 
-        let mut instance = StateMachineInstance::new(simple, HashMap::new());
+    #[test]
+    fn test_behaviors() {
+        use crate::definition::opaque::OpaqueBehavior;
+
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("open")
+                    .state("open", |state| {
+                        state
+                            .on_entry(OpaqueBehavior::new("log()", None))
+                            .on_entry(OpaqueBehavior::new("start_timer()", None))
+                            .on_exit(OpaqueBehavior::new("stop_timer()", None))
+                            .transition(
+                                TransitionBuilder::to("closed")
+                                    .on("resolve")
+                                    .effect(OpaqueBehavior::new("notify()", None))
+                                    .effect(OpaqueBehavior::new("archive()", None)),
+                            )
+                    })
+                    .final_state("closed")
+            })
+            .build_with_names()
+            .unwrap();
+        let region = machine.default_region().unwrap();
+        let vertices = region.vertices();
+        let open = vertices
+            .iter()
+            .find(|vertex| vertex.id() == &names["open"])
+            .unwrap()
+            .as_state()
+            .unwrap();
+        let label =
+            |behavior: &Option<Box<dyn Behavior>>| behavior.as_ref().unwrap().label().clone();
+        assert_eq!(
+            label(open.entry()),
+            Some("log(); start_timer()".to_string())
+        );
+        assert_eq!(label(open.exit()), Some("stop_timer()".to_string()));
+        assert_eq!(
+            label(region.transitions()[1].effect()),
+            Some("notify(); archive()".to_string())
+        );
+    }
 
-        let result = instance.post(&Event::This);
-        assert!(result.is_err());
-        match result.err().unwrap().0 {
-            ErrorKind::InstanceIsNotActive => println!("error-ed as expected"),
-            _ => panic!("expecting ErrorKind::InstanceIsNotActive"),
-        }
+    #[test]
+    fn test_transition_kinds() {
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("open")
+                    .state("open", |state| {
+                        state
+                            .transition(TransitionBuilder::to("open").on("retry"))
+                            .transition(TransitionBuilder::internal().on("touch"))
+                            .on("close", "closed")
+                    })
+                    .final_state("closed")
+            })
+            .build_with_names()
+            .unwrap();
+        let region = machine.default_region().unwrap();
+        let kinds: Vec<TransitionKind> = region
+            .transitions()
+            .iter()
+            .map(|transition| transition.kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TransitionKind::External,
+                TransitionKind::External,
+                TransitionKind::Internal,
+                TransitionKind::External
+            ]
+        );
+        assert_eq!(region.transitions()[1].target(), names["open"]);
+    }
 
-        let result = instance.execute();
-        println!("{:#?}", result);
-        println!("{:#?}", instance);
-        assert!(result.is_ok());
-        assert!(instance.is_done());
+    #[cfg(feature = "execution")]
+    #[test]
+    fn test_self_transition_reenters() {
+        use crate::definition::behaviors::from_fn;
+        use crate::execution::types::StateMachineInstance;
+        use std::sync::Mutex;
+
+        let performed = Arc::new(Mutex::new(vec![]));
+        let record = |name: &'static str| {
+            let performed = performed.clone();
+            from_fn(name, move |_, _, _, _| performed.lock().unwrap().push(name))
+        };
+        let machine = MachineBuilder::new()
+            .region(|region| {
+                region.initial("open").state("open", |state| {
+                    state
+                        .on_entry(record("entry"))
+                        .on_exit(record("exit"))
+                        .transition(TransitionBuilder::to("open").on("retry"))
+                        .transition(
+                            TransitionBuilder::internal()
+                                .on("touch")
+                                .effect(record("touched")),
+                        )
+                })
+            })
+            .build()
+            .unwrap();
+        let instance = StateMachineInstance::new(machine).unwrap();
+        instance.execute().unwrap();
+        instance.post(&signal("retry")).unwrap();
+        instance.post(&signal("touch")).unwrap();
+        assert_eq!(
+            *performed.lock().unwrap(),
+            vec!["entry", "exit", "entry", "touched"]
+        );
+    }
+
+    #[cfg(feature = "format-uml")]
+    #[test]
+    fn test_transition_kinds_xmi() {
+        use crate::format::uml::WriteXmi;
+        use crate::format::Stringify;
+
+        let machine = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("open")
+                    .state("open", |state| state.on("close", "closed"))
+                    .final_state("closed")
+            })
+            .build()
+            .unwrap();
+        let xmi = WriteXmi::default().stringify(&machine).unwrap();
+        assert!(xmi.contains(r#"kind="external""#));
+        assert!(!xmi.contains(r#"kind="internal""#));
+    }
+
+    #[test]
+    fn test_metadata() {
+        use crate::core::context::Compound;
+        use crate::core::{FieldName, Object};
+        use crate::definition::types::HasMetadata;
+        use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+        use std::cell::RefCell;
+
+        let hints = Object::default();
+        let color: FieldName = "color".parse().unwrap();
+        hints.insert(color.clone(), FieldValue::String("green".to_string()));
+        let (machine, names) = MachineBuilder::labeled("ticket")
+            .metadata("owner", FieldValue::String("support".to_string()))
+            .region(|region| {
+                region
+                    .metadata("lane", FieldValue::Integer(1))
+                    .initial("open")
+                    .state("open", |state| {
+                        state
+                            .metadata("sla_hours", FieldValue::Integer(4))
+                            .metadata("ui", hints.into())
+                            .transition(
+                                TransitionBuilder::to("closed")
+                                    .on("resolve")
+                                    .metadata("audited", FieldValue::Bool(true)),
+                            )
+                    })
+                    .final_state("closed")
+            })
+            .build_with_names()
+            .unwrap();
+        assert_eq!(
+            machine.metadata_value("owner"),
+            Some(&FieldValue::String("support".to_string()))
+        );
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.metadata_value("lane"), Some(&FieldValue::Integer(1)));
+        let vertices = region.vertices();
+        let open = vertices
+            .iter()
+            .find(|vertex| vertex.id() == &names["open"])
+            .unwrap()
+            .as_state()
+            .unwrap();
+        assert_eq!(open.metadata().len(), 2);
+        assert_eq!(
+            region.transitions()[1].metadata_value("audited"),
+            Some(&FieldValue::Bool(true))
+        );
+        assert!(region.transitions()[0].metadata().is_empty());
+
+        // the copy shares no objects with the original
+        let copy = machine.duplicate().unwrap();
+        let copy_vertices = copy.default_region().unwrap().vertices();
+        let copy_open = copy_vertices[1].as_state().unwrap();
+        if let Some(FieldValue::Object(ui)) = copy_open.metadata_value("ui") {
+            ui.read()
+                .unwrap()
+                .insert(color.clone(), FieldValue::String("red".to_string()));
+        }
+        match open.metadata_value("ui") {
+            Some(FieldValue::Object(ui)) => assert_eq!(
+                ui.read().unwrap().get(color),
+                Some(FieldValue::String("green".to_string()))
+            ),
+            other => panic!("expected an object, not {:?}", other),
+        }
 
-        let result = instance.execute();
-        assert!(result.is_err());
-        match result.err().unwrap().0 {
-            ErrorKind::InstanceIsDone => println!("error-ed as expected"),
-            _ => panic!("expecting ErrorKind::InstanceIsDone"),
+        #[derive(Default)]
+        struct Keys(RefCell<Vec<String>>);
+        impl StateMachineVisitor for Keys {
+            fn metadata(&self, _: &Resolver<'_>, metadata: &HashMap<String, FieldValue>) {
+                let mut keys: Vec<String> = metadata.keys().cloned().collect();
+                keys.sort();
+                self.0.borrow_mut().push(keys.join(","));
+            }
         }
+        let keys = Keys::default();
+        visit_state_machine(&machine, &keys).unwrap();
+        assert_eq!(
+            keys.0.into_inner(),
+            vec!["owner", "lane", "sla_hours,ui", "audited"]
+        );
     }
 }
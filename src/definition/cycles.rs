@@ -0,0 +1,236 @@
+/*!
+Detection of reference cycles between state machines.
+
+The model is built from `Arc` and `RwLock` values, so ownership must form a tree for memory to be
+reclaimed when a machine is dropped. References that cross that tree, the machines referenced by
+sub-machine states and the machine's vertex index, are held as `Weak` values. A machine may
+therefore reference itself, directly or through other machines, without leaking memory. Such a
+recursive sub-machine is still an error in the model, as it has no finite expansion, and
+[`sub_machine_cycles`] will report it.
+
+[`debug_assert_no_cycles`] is called by `MachineBuilder::build` and `registry::bind`, and may be
+called from tests; it does nothing when debug assertions are disabled.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use uml_state_machine::definition::cycles::{debug_assert_no_cycles, sub_machine_cycles};
+use uml_state_machine::definition::types::*;
+
+let machine = Arc::new(StateMachine::default());
+let region = machine.default_region().unwrap();
+let mut state = State::within(region.id().clone());
+state.set_sub_machine(machine.id().clone());
+region.add_state(state);
+machine.add_machine_reference(&machine);
+
+assert_eq!(sub_machine_cycles(&machine), vec![vec![machine.id().clone(), machine.id().clone()]]);
+
+// the self-reference is weak, so dropping the machine frees it.
+let weak = Arc::downgrade(&machine);
+drop(machine);
+assert!(weak.upgrade().is_none());
+```
+
+*/
+
+use std::sync::Arc;
+
+use crate::core::ID;
+use crate::definition::types::{HasRegions, Identified, Region, StateMachine};
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Return each cycle of sub-machine references reachable from `machine`. A cycle is the list of
+/// machine IDs along the path, starting and ending with the same ID. References that cannot be
+/// resolved are ignored.
+///
+pub fn sub_machine_cycles(machine: &StateMachine) -> Vec<Vec<ID>> {
+    let mut cycles = Default::default();
+    let mut path = vec![machine.id().clone()];
+    find_cycles(machine, machine, &mut path, &mut cycles);
+    cycles
+}
+
+///
+/// Panic if `machine` contains any cycle of sub-machine references, only when debug assertions
+/// are enabled.
+///
+pub fn debug_assert_no_cycles(machine: &StateMachine) {
+    if cfg!(debug_assertions) {
+        let cycles = sub_machine_cycles(machine);
+        assert!(
+            cycles.is_empty(),
+            "state machine {} has sub-machine reference cycles: {:?}",
+            machine.id(),
+            cycles
+                .iter()
+                .map(|cycle| cycle
+                    .iter()
+                    .map(ID::to_string)
+                    .collect::<Vec<String>>()
+                    .join(" -> "))
+                .collect::<Vec<String>>()
+        );
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn find_cycles(
+    root: &StateMachine,
+    machine: &StateMachine,
+    path: &mut Vec<ID>,
+    cycles: &mut Vec<Vec<ID>>,
+) {
+    let mut referenced: Vec<ID> = Default::default();
+    for region in machine.regions() {
+        sub_machine_references(region, &mut referenced);
+    }
+    for machine_id in referenced {
+        if path.contains(&machine_id) {
+            let mut cycle: Vec<ID> = path
+                .iter()
+                .skip_while(|id| **id != machine_id)
+                .cloned()
+                .collect();
+            cycle.push(machine_id);
+            cycles.push(cycle);
+        } else if let Some(sub_machine) = resolve(root, machine, &machine_id) {
+            path.push(machine_id);
+            find_cycles(root, &sub_machine, path, cycles);
+            let _ = path.pop();
+        }
+    }
+}
+
+fn resolve(root: &StateMachine, machine: &StateMachine, id: &ID) -> Option<Arc<StateMachine>> {
+    machine
+        .find_machine(id.clone())
+        .or_else(|| root.find_machine(id.clone()))
+}
+
+fn sub_machine_references(region: &Region, referenced: &mut Vec<ID>) {
+    for vertex in region.vertices() {
+        if let Some(state) = vertex.as_state() {
+            if let Some(machine_id) = state.sub_machine() {
+                if !referenced.contains(machine_id) {
+                    referenced.push(machine_id.clone());
+                }
+            }
+            for region in state.regions() {
+                sub_machine_references(region, referenced);
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::State;
+
+    fn reference(from: &Arc<StateMachine>, to: &Arc<StateMachine>) {
+        let region = from.default_region().unwrap();
+        let mut state = State::within(region.id().clone());
+        state.set_sub_machine(to.id().clone());
+        region.add_state(state);
+        from.add_machine_reference(to);
+    }
+
+    #[test]
+    fn test_mutual_references_are_freed() {
+        let first = Arc::new(StateMachine::default());
+        let second = Arc::new(StateMachine::default());
+        let third = Arc::new(StateMachine::default());
+        reference(&first, &second);
+        reference(&second, &third);
+        debug_assert_no_cycles(&first);
+
+        reference(&third, &second);
+        assert_eq!(
+            sub_machine_cycles(&first),
+            vec![vec![
+                second.id().clone(),
+                third.id().clone(),
+                second.id().clone()
+            ]]
+        );
+
+        first.index_references();
+        let vertex_id = first.default_region().unwrap().vertices()[0].id().clone();
+        let vertex = first.find_vertex(first.default_region().unwrap().id().clone(), vertex_id);
+        let vertex = Arc::downgrade(&vertex.unwrap());
+
+        let machines = [
+            Arc::downgrade(&first),
+            Arc::downgrade(&second),
+            Arc::downgrade(&third),
+        ];
+        drop(first);
+        drop(second);
+        drop(third);
+        assert!(machines.iter().all(|machine| machine.upgrade().is_none()));
+        assert!(vertex.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_managed_instances_are_freed() {
+        use crate::definition::builder::MachineBuilder;
+        use crate::definition::types::{Event, SignalEvent};
+        use crate::execution::manager::InstanceManager;
+        use crate::execution::types::{ExecutionObserver, StateMachineInstance};
+
+        struct Watching;
+
+        impl ExecutionObserver for Watching {}
+
+        let machine = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("idle")
+                    .state("idle", |state| state.on("go", "busy"))
+                    .simple_state("busy")
+            })
+            .build()
+            .unwrap();
+        let region = machine.default_region().unwrap();
+        machine.index_references();
+        let vertex = machine
+            .find_vertex(region.id().clone(), region.vertices()[1].id().clone())
+            .map(|vertex| Arc::downgrade(&vertex))
+            .unwrap();
+        let observer = Arc::new(Watching);
+
+        let mut manager = InstanceManager::default();
+        for name in &["first", "second"] {
+            let instance = StateMachineInstance::new(machine.clone()).unwrap();
+            instance.add_observer(observer.clone());
+            let _ = manager.add_named(name, instance).unwrap();
+        }
+        manager.execute_all().unwrap();
+        manager
+            .post_named("first", &Event::Signal(SignalEvent::new("go")))
+            .unwrap();
+        assert_eq!(Arc::strong_count(&machine), 3);
+        assert_eq!(Arc::strong_count(&observer), 3);
+
+        let weak = Arc::downgrade(&machine);
+        drop(machine);
+        assert_eq!(weak.strong_count(), 2);
+        drop(manager);
+        assert!(weak.upgrade().is_none());
+        assert!(vertex.upgrade().is_none());
+        assert_eq!(Arc::strong_count(&observer), 1);
+    }
+}
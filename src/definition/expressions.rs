@@ -0,0 +1,791 @@
+/*!
+A small expression language over the fields of a `Context`, so that guards and assignments may
+be given as text, as they are by formats such as SCXML, rather than as Rust code.
+
+An expression is made of literals, integers such as `100`, floats such as `2.5`, strings in
+single or double quotes, and `true` or `false`; field paths, such as `order/total`, which are
+read from the context; the arithmetic operators `+`, `-`, `*`, `/`, and `%`; the comparisons
+`==`, `!=`, `<`, `<=`, `>`, and `>=`; the boolean operators `!`, `&&`, and `||`; and
+parentheses. The operators have the usual precedence, and `&&` and `||` short-circuit. Paths
+starting with `_event`, such as `_event/total`, are read from the payload of the event that
+fired the trigger, see `Trigger::payload`.
+
+As `/` both separates the names in a path and divides, it divides only where it is not directly
+between two names; `order/total` is a path, `order / total` a division. Names are made of
+letters, digits, and `_`, fields whose names contain `-` cannot be referred to.
+
+Arithmetic on two integers is integer arithmetic, otherwise numbers are operated on as floats,
+and `+` also concatenates strings. Numbers of any type may be compared with each other, strings
+and booleans only with their own type. An expression fails to evaluate where a field is
+missing, or an operator is given values of the wrong type; an `ExprConstraint` is then `false`.
+
+# Example
+
+```rust
+use uml_state_machine::core::context::{Compound, FieldPath};
+use uml_state_machine::core::{Context, FieldValue, ID};
+use uml_state_machine::definition::expressions::{AssignBehavior, ExprConstraint};
+use uml_state_machine::definition::types::{Behavior, Constraint, Event, Trigger};
+use std::cell::RefCell;
+
+let context = Context::default();
+context.insert("total".parse::<FieldPath>().unwrap(), FieldValue::Integer(120));
+
+let guard = ExprConstraint::new("total > 100 && total <= 500").unwrap();
+assert!(guard.evaluate_in(&ID::random(), &Trigger::any(), &context));
+
+let discount = AssignBehavior::parse("total = total - total / 10").unwrap();
+let raised: RefCell<Vec<Event>> = Default::default();
+discount.perform_in(&ID::random(), &Trigger::any(), &raised, &context);
+assert_eq!(
+    context.get("total".parse::<FieldPath>().unwrap()),
+    Some(FieldValue::Integer(108))
+);
+```
+
+*/
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use crate::core::context::{Compound, FieldPath};
+use crate::core::{Context, FieldValue, ID};
+use crate::definition::impls::compare_field_values;
+use crate::definition::opaque::OpaqueConstraint;
+use crate::definition::types::{
+    Behavior, ComparisonOperator, Constraint, Event, EventSink, Labeled, SignalEvent, Trigger,
+};
+use crate::error::{ErrorKind, Result};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The name of the signal event raised by an `AssignBehavior` whose expression cannot be
+/// evaluated, as for the SCXML `error.execution` event.
+///
+pub const ERROR_EXECUTION: &str = "error.execution";
+
+///
+/// A parsed expression, and its source text.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+///
+/// A guard that holds where its expression evaluates to `true` against the context.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExprConstraint {
+    label: Option<String>,
+    expression: Expression,
+}
+
+///
+/// A behavior that assigns the value of its expression to a field of the context, as for the
+/// SCXML `<assign>` element. The field's parent must already exist in the context.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssignBehavior {
+    label: Option<String>,
+    location: FieldPath,
+    expression: Expression,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?.into_iter().peekable(),
+        };
+        let root = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(Self {
+                source: source.trim().to_string(),
+                root,
+            }),
+            Some(token) => Err(syntax_error(&format!("unexpected {}", token))),
+        }
+    }
+
+    pub fn source(&self) -> &String {
+        &self.source
+    }
+
+    ///
+    /// Evaluate this expression against `context`, without an event payload.
+    ///
+    pub fn evaluate(&self, context: &Context) -> Result<FieldValue> {
+        self.evaluate_with(context, None)
+    }
+
+    ///
+    /// Evaluate this expression against `context`, with `payload` providing the fields of paths
+    /// starting with `_event`.
+    ///
+    pub fn evaluate_with(
+        &self,
+        context: &Context,
+        payload: Option<&Context>,
+    ) -> Result<FieldValue> {
+        evaluate(&self.root, context, payload)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+macro_rules! make_labeled_impl {
+    ($type_name:ident) => {
+        impl Labeled for $type_name {
+            fn label(&self) -> &Option<String> {
+                &self.label
+            }
+
+            fn set_label(&mut self, label: &str) {
+                self.label = Some(label.to_string())
+            }
+
+            fn unset_label(&mut self) {
+                self.label = None
+            }
+        }
+    };
+}
+
+make_labeled_impl!(ExprConstraint);
+
+make_labeled_impl!(AssignBehavior);
+
+// ------------------------------------------------------------------------------------------------
+
+impl Constraint for ExprConstraint {
+    ///
+    /// Evaluate the expression against an empty context.
+    ///
+    fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool {
+        self.evaluate_in(in_state, on_trigger, &Context::default())
+    }
+
+    fn evaluate_in(&self, _in_state: &ID, on_trigger: &Trigger, context: &Context) -> bool {
+        matches!(
+            self.expression.evaluate_with(context, on_trigger.payload()),
+            Ok(FieldValue::Bool(true))
+        )
+    }
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ExprConstraint {
+    ///
+    /// Parse `expression` as a guard, labeled with its source text.
+    ///
+    pub fn new(expression: &str) -> Result<Self> {
+        let expression = Expression::parse(expression)?;
+        Ok(Self {
+            label: Some(expression.source.clone()),
+            expression,
+        })
+    }
+
+    ///
+    /// Parse the body of `opaque`, as read from a format, keeping its label so that writers
+    /// emit the body unchanged.
+    ///
+    pub fn from_opaque(opaque: &OpaqueConstraint) -> Result<Self> {
+        Ok(Self {
+            label: opaque.label().clone(),
+            expression: Expression::parse(opaque.body())?,
+        })
+    }
+
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Behavior for AssignBehavior {
+    ///
+    /// Without a context there is nothing to assign to, so this does nothing.
+    ///
+    fn perform(&self, _in_state: &ID, _on_trigger: &Trigger, _events: &dyn EventSink) {}
+
+    ///
+    /// Assign the value of the expression to the location, or where it cannot be evaluated
+    /// raise `ERROR_EXECUTION`, leaving the context unchanged.
+    ///
+    fn perform_in(
+        &self,
+        _in_state: &ID,
+        on_trigger: &Trigger,
+        events: &dyn EventSink,
+        context: &Context,
+    ) {
+        match self.expression.evaluate_with(context, on_trigger.payload()) {
+            Ok(value) => context.insert(self.location.clone(), value),
+            Err(_) => events.raise(Event::Signal(SignalEvent::new(ERROR_EXECUTION))),
+        }
+    }
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl AssignBehavior {
+    ///
+    /// Assign the value of `expression` to `location`, labeled `location = expression`.
+    ///
+    pub fn new(location: FieldPath, expression: &str) -> Result<Self> {
+        let expression = Expression::parse(expression)?;
+        Ok(Self {
+            label: Some(format!("{} = {}", location, expression)),
+            location,
+            expression,
+        })
+    }
+
+    ///
+    /// Parse an assignment of the form `location = expression`.
+    ///
+    pub fn parse(assignment: &str) -> Result<Self> {
+        let bytes = assignment.as_bytes();
+        let split = (0..bytes.len()).find(|index| {
+            bytes[*index] == b'='
+                && bytes.get(index + 1) != Some(&b'=')
+                && (*index == 0 || !b"=!<>".contains(&bytes[index - 1]))
+        });
+        match split {
+            None => Err(syntax_error("an assignment needs a location and '='")),
+            Some(index) => {
+                let location = assignment[..index].trim();
+                let location = FieldPath::from_str(location)
+                    .map_err(|_| syntax_error(&format!("'{}' is not a field path", location)))?;
+                Self::new(location, &assignment[index + 1..])
+            }
+        }
+    }
+
+    pub fn location(&self) -> &FieldPath {
+        &self.location
+    }
+
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Value(FieldValue),
+    Field(FieldPath),
+    Payload(FieldPath),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Box<Node>, ComparisonOperator, Box<Node>),
+    Arithmetic(Box<Node>, char, Box<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Value(FieldValue),
+    Path(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Value(value) => write!(f, "'{}'", value),
+            Token::Path(path) => write!(f, "'{}'", path),
+            Token::Operator(operator) => write!(f, "'{}'", operator),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Node> {
+        let mut lhs = self.and()?;
+        while self.next_if_operator(&["||"]).is_some() {
+            lhs = Node::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        let mut lhs = self.comparison()?;
+        while self.next_if_operator(&["&&"]).is_some() {
+            lhs = Node::And(Box::new(lhs), Box::new(self.comparison()?));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Node> {
+        let lhs = self.sum()?;
+        let operator = match self.next_if_operator(&["==", "!=", "<", "<=", ">", ">="]) {
+            None => return Ok(lhs),
+            Some("==") => ComparisonOperator::Equal,
+            Some("!=") => ComparisonOperator::NotEqual,
+            Some("<") => ComparisonOperator::Less,
+            Some("<=") => ComparisonOperator::LessOrEqual,
+            Some(">") => ComparisonOperator::Greater,
+            Some(_) => ComparisonOperator::GreaterOrEqual,
+        };
+        Ok(Node::Compare(
+            Box::new(lhs),
+            operator,
+            Box::new(self.sum()?),
+        ))
+    }
+
+    fn sum(&mut self) -> Result<Node> {
+        let mut lhs = self.product()?;
+        while let Some(operator) = self.next_if_operator(&["+", "-"]) {
+            lhs = arithmetic(lhs, operator, self.product()?);
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Node> {
+        let mut lhs = self.unary()?;
+        while let Some(operator) = self.next_if_operator(&["*", "/", "%"]) {
+            lhs = arithmetic(lhs, operator, self.unary()?);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        match self.next_if_operator(&["!", "-"]) {
+            Some("!") => Ok(Node::Not(Box::new(self.unary()?))),
+            Some(_) => Ok(Node::Negate(Box::new(self.unary()?))),
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node> {
+        match self.tokens.next() {
+            None => Err(syntax_error("unexpected end of expression")),
+            Some(Token::Value(value)) => Ok(Node::Value(value)),
+            Some(Token::Path(path)) => match path.as_str() {
+                "true" => Ok(Node::Value(FieldValue::Bool(true))),
+                "false" => Ok(Node::Value(FieldValue::Bool(false))),
+                _ => match path.strip_prefix("_event/") {
+                    Some(field) => Ok(Node::Payload(field_path(field)?)),
+                    None => Ok(Node::Field(field_path(&path)?)),
+                },
+            },
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(syntax_error("expected ')'")),
+                }
+            }
+            Some(token) => Err(syntax_error(&format!("unexpected {}", token))),
+        }
+    }
+
+    fn next_if_operator(&mut self, operators: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.peek() {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                let operator = *operator;
+                let _ = self.tokens.next();
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn syntax_error(reason: &str) -> crate::error::Error {
+    ErrorKind::ExpressionSyntax(reason.to_string()).into()
+}
+
+fn evaluation_error(reason: &str) -> crate::error::Error {
+    ErrorKind::ExpressionEvaluation(reason.to_string()).into()
+}
+
+fn field_path(path: &str) -> Result<FieldPath> {
+    FieldPath::from_str(path).map_err(|_| syntax_error(&format!("'{}' is not a field path", path)))
+}
+
+fn arithmetic(lhs: Node, operator: &str, rhs: Node) -> Node {
+    Node::Arithmetic(
+        Box::new(lhs),
+        operator.chars().next().unwrap_or('+'),
+        Box::new(rhs),
+    )
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = Default::default();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '\'' | '"' => Token::Value(FieldValue::String(string_literal(&mut chars, c)?)),
+            c if c.is_ascii_digit() => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.peek() {
+                    if c.is_ascii_digit() || *c == '.' {
+                        end = index + c.len_utf8();
+                        let _ = chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                number_literal(&source[start..end])?
+            }
+            c if is_name_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.peek().cloned() {
+                    let continues_path = c == '/'
+                        && source[index + 1..]
+                            .chars()
+                            .next()
+                            .map(is_name_char)
+                            .unwrap_or_default();
+                    if is_name_char(c) || continues_path {
+                        end = index + c.len_utf8();
+                        let _ = chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                Token::Path(source[start..end].to_string())
+            }
+            _ => {
+                let next = chars.peek().map(|(_, c)| *c);
+                let operator = match (c, next) {
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('!', _) => "!",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('+', _) => "+",
+                    ('-', _) => "-",
+                    ('*', _) => "*",
+                    ('/', _) => "/",
+                    ('%', _) => "%",
+                    _ => return Err(syntax_error(&format!("unexpected '{}'", c))),
+                };
+                if operator.len() == 2 {
+                    let _ = chars.next();
+                }
+                Token::Operator(operator)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn string_literal(chars: &mut Peekable<CharIndices<'_>>, quote: char) -> Result<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(syntax_error("unterminated string")),
+            Some((_, c)) if c == quote => return Ok(string),
+            Some((_, '\\')) => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, c)) => string.push(c),
+                None => return Err(syntax_error("unterminated string")),
+            },
+            Some((_, c)) => string.push(c),
+        }
+    }
+}
+
+fn number_literal(literal: &str) -> Result<Token> {
+    let invalid = || syntax_error(&format!("'{}' is not a number", literal));
+    if literal.contains('.') {
+        literal
+            .parse::<f64>()
+            .map(|v| Token::Value(FieldValue::Float(v)))
+            .map_err(|_| invalid())
+    } else {
+        literal
+            .parse::<i64>()
+            .map(|v| Token::Value(FieldValue::Integer(v)))
+            .map_err(|_| invalid())
+    }
+}
+
+fn evaluate(node: &Node, context: &Context, payload: Option<&Context>) -> Result<FieldValue> {
+    match node {
+        Node::Value(value) => Ok(value.clone()),
+        Node::Field(path) => context
+            .get(path.clone())
+            .ok_or_else(|| evaluation_error(&format!("there is no field '{}'", path))),
+        Node::Payload(path) => payload
+            .and_then(|payload| payload.get(path.clone()))
+            .ok_or_else(|| evaluation_error(&format!("the event has no field '{}'", path))),
+        Node::Not(inner) => Ok(FieldValue::Bool(!boolean(inner, context, payload)?)),
+        Node::Negate(inner) => match evaluate(inner, context, payload)? {
+            FieldValue::Byte(v) => Ok(FieldValue::Integer(-(v as i64))),
+            FieldValue::Integer(v) => v
+                .checked_neg()
+                .map(FieldValue::Integer)
+                .ok_or_else(|| evaluation_error("integer overflow")),
+            FieldValue::Float(v) => Ok(FieldValue::Float(-v)),
+            value => Err(evaluation_error(&format!("cannot negate '{}'", value))),
+        },
+        Node::And(lhs, rhs) => Ok(FieldValue::Bool(
+            boolean(lhs, context, payload)? && boolean(rhs, context, payload)?,
+        )),
+        Node::Or(lhs, rhs) => Ok(FieldValue::Bool(
+            boolean(lhs, context, payload)? || boolean(rhs, context, payload)?,
+        )),
+        Node::Compare(lhs, operator, rhs) => {
+            let lhs = evaluate(lhs, context, payload)?;
+            let rhs = evaluate(rhs, context, payload)?;
+            let ordering = compare_field_values(&lhs, &rhs);
+            let equal = ordering == Some(Ordering::Equal) || lhs == rhs;
+            let ordered = |accept: &[Ordering]| match ordering {
+                Some(ordering) => Ok(accept.contains(&ordering)),
+                None => Err(evaluation_error(&format!(
+                    "cannot compare '{}' with '{}'",
+                    lhs, rhs
+                ))),
+            };
+            Ok(FieldValue::Bool(match operator {
+                ComparisonOperator::Equal => equal,
+                ComparisonOperator::NotEqual => !equal,
+                ComparisonOperator::Less => ordered(&[Ordering::Less])?,
+                ComparisonOperator::LessOrEqual => ordered(&[Ordering::Less, Ordering::Equal])?,
+                ComparisonOperator::Greater => ordered(&[Ordering::Greater])?,
+                ComparisonOperator::GreaterOrEqual => {
+                    ordered(&[Ordering::Greater, Ordering::Equal])?
+                }
+            }))
+        }
+        Node::Arithmetic(lhs, operator, rhs) => calculate(
+            evaluate(lhs, context, payload)?,
+            *operator,
+            evaluate(rhs, context, payload)?,
+        ),
+    }
+}
+
+fn boolean(node: &Node, context: &Context, payload: Option<&Context>) -> Result<bool> {
+    match evaluate(node, context, payload)? {
+        FieldValue::Bool(v) => Ok(v),
+        value => Err(evaluation_error(&format!("'{}' is not a boolean", value))),
+    }
+}
+
+fn calculate(lhs: FieldValue, operator: char, rhs: FieldValue) -> Result<FieldValue> {
+    fn as_integer(value: &FieldValue) -> Option<i64> {
+        match value {
+            FieldValue::Byte(v) => Some(*v as i64),
+            FieldValue::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+    fn as_float(value: &FieldValue) -> Option<f64> {
+        match value {
+            FieldValue::Float(v) => Some(*v),
+            value => as_integer(value).map(|v| v as f64),
+        }
+    }
+    match (&lhs, &rhs) {
+        (FieldValue::String(lhs), FieldValue::String(rhs)) if operator == '+' => {
+            return Ok(FieldValue::String(format!("{}{}", lhs, rhs)))
+        }
+        _ => (),
+    }
+    if let (Some(lhs), Some(rhs)) = (as_integer(&lhs), as_integer(&rhs)) {
+        let result = match operator {
+            '+' => lhs.checked_add(rhs),
+            '-' => lhs.checked_sub(rhs),
+            '*' => lhs.checked_mul(rhs),
+            '/' if rhs == 0 => return Err(evaluation_error("division by zero")),
+            '/' => lhs.checked_div(rhs),
+            '%' if rhs == 0 => return Err(evaluation_error("division by zero")),
+            _ => lhs.checked_rem(rhs),
+        };
+        return result
+            .map(FieldValue::Integer)
+            .ok_or_else(|| evaluation_error("integer overflow"));
+    }
+    match (as_float(&lhs), as_float(&rhs)) {
+        (Some(lhs), Some(rhs)) => Ok(FieldValue::Float(match operator {
+            '+' => lhs + rhs,
+            '-' => lhs - rhs,
+            '*' => lhs * rhs,
+            '/' => lhs / rhs,
+            _ => lhs % rhs,
+        })),
+        _ => Err(evaluation_error(&format!(
+            "cannot apply '{}' to '{}' and '{}'",
+            operator, lhs, rhs
+        ))),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn context() -> Context {
+        let context = Context::default();
+        context.insert(
+            "order".parse().unwrap(),
+            crate::core::context::Object::default().into(),
+        );
+        context.insert("order/total".parse().unwrap(), FieldValue::Integer(120));
+        context.insert("order/rate".parse().unwrap(), FieldValue::Float(0.5));
+        context.insert("order/state".parse().unwrap(), "open".into());
+        context
+    }
+
+    fn value(expression: &str) -> Result<FieldValue> {
+        Expression::parse(expression)?.evaluate(&context())
+    }
+
+    #[test]
+    fn test_evaluation() {
+        assert_eq!(value("order/total > 100").unwrap(), FieldValue::Bool(true));
+        assert_eq!(value("order/total / 7").unwrap(), FieldValue::Integer(17));
+        assert_eq!(
+            value("order/total * order/rate").unwrap(),
+            FieldValue::Float(60.0)
+        );
+        assert_eq!(value("-(1 + 2) * 3 % 4").unwrap(), FieldValue::Integer(-1));
+        assert_eq!(
+            value("order/state == 'open' && !(order/total < 50 || false)").unwrap(),
+            FieldValue::Bool(true)
+        );
+        assert_eq!(
+            value("\"it's \" + order/state").unwrap(),
+            FieldValue::String("it's open".to_string())
+        );
+        assert_eq!(
+            value("order/total >= 120.0").unwrap(),
+            FieldValue::Bool(true)
+        );
+        assert_eq!(
+            value("false && order/missing").unwrap(),
+            FieldValue::Bool(false)
+        );
+
+        assert!(value("order/missing > 1").is_err());
+        assert!(value("order/state > 1").is_err());
+        assert!(value("order/total / 0").is_err());
+        assert!(value("order/total && true").is_err());
+
+        for invalid in &["", "1 +", "(1", "1 2", "a = 1", "'open"] {
+            assert!(Expression::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_guards_and_assignments() {
+        let context = context();
+        let payload = Context::default();
+        payload.insert("amount".parse().unwrap(), FieldValue::Integer(30));
+        let trigger = Trigger::signal("pay").with_payload(payload);
+        let state = ID::random();
+
+        let guard = ExprConstraint::new(" _event/amount < order/total ").unwrap();
+        assert_eq!(
+            guard.label(),
+            &Some("_event/amount < order/total".to_string())
+        );
+        assert!(guard.evaluate_in(&state, &trigger, &context));
+        assert!(!guard.evaluate_in(&state, &Trigger::signal("pay"), &context));
+        assert!(!guard.evaluate(&state, &trigger));
+
+        let opaque = OpaqueConstraint::new("order/total>=120", None);
+        let guard = ExprConstraint::from_opaque(&opaque).unwrap();
+        assert_eq!(guard.label(), opaque.label());
+        assert!(guard.evaluate_in(&state, &trigger, &context));
+
+        let raised: RefCell<Vec<Event>> = Default::default();
+        let pay = AssignBehavior::parse("order/total = order/total - _event/amount").unwrap();
+        assert_eq!(
+            pay.label(),
+            &Some("order/total = order/total - _event/amount".to_string())
+        );
+        pay.perform_in(&state, &trigger, &raised, &context);
+        pay.perform_in(&state, &trigger, &raised, &context);
+        assert_eq!(
+            context.get("order/total".parse().unwrap()),
+            Some(FieldValue::Integer(60))
+        );
+        assert!(raised.borrow().is_empty());
+
+        pay.perform_in(&state, &Trigger::signal("pay"), &raised, &context);
+        assert_eq!(
+            context.get("order/total".parse().unwrap()),
+            Some(FieldValue::Integer(60))
+        );
+        assert_eq!(
+            *raised.borrow(),
+            vec![Event::Signal(SignalEvent::new(ERROR_EXECUTION))]
+        );
+        assert!(AssignBehavior::parse("order/total == 1").is_err());
+        assert!(AssignBehavior::parse("order total = 1").is_err());
+    }
+}
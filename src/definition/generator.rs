@@ -0,0 +1,468 @@
+/*!
+Generates random, valid, machines, and sequences of events to drive them with, from a seed.
+
+A `MachineGenerator` creates machines with a single top-level region whose states may be
+composite, to a given depth, each region having an initial pseudostate, and possibly a final
+state and a shallow or deep history pseudostate. States have transitions triggered by the
+signals `a`, `b`, and so on, to any state or history pseudostate of the machine, and composite
+states with a final state may have a completion transition; transitions may be guarded. Entry,
+exit, and effect behaviors append their label, followed by `;`, to the string at the path
+`performed` of the instance's context, and guards hold where the number of behaviors performed
+so far is even, so that the context of an instance records which behaviors were performed, and
+in what order, and the transitions enabled depend on it. The machines generated are within the
+subset that `transform::flatten` supports; as the flat machine has a state for each
+combination of a leaf and the states its histories remember, larger settings, with history,
+make for flat machines that are slow to create and validate.
+
+The same seed, and settings, always generate the same machines and events, so that a failure
+found with a generated machine may be reproduced from its seed.
+
+# Example
+
+```rust
+use uml_state_machine::definition::generator::MachineGenerator;
+use uml_state_machine::definition::types::*;
+
+let mut generator = MachineGenerator::new(42).states(3).depth(1);
+let machine = generator.machine().unwrap();
+assert!(machine.default_region().unwrap().vertices().len() > 1);
+
+let events = generator.events(10);
+assert_eq!(events.len(), 10);
+assert_eq!(MachineGenerator::new(42).states(3).depth(1).events(10).len(), 10);
+```
+
+*/
+
+use crate::core::context::Compound;
+use crate::core::{Context, FieldPath, FieldValue};
+use crate::definition::behaviors::{from_fn, FnBehavior};
+use crate::definition::builder::{
+    MachineBuilder, Ordinary, RegionBuilder, StateBuilder, TransitionBuilder, WithInitial,
+    WithoutInitial,
+};
+use crate::definition::guards::{self, FnGuard};
+use crate::definition::types::{Event, PseudoStateKind, SignalEvent, StateMachine};
+use crate::error::Result;
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Values
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The path, within the context of an instance, of the behaviors performed so far.
+///
+pub const PERFORMED_PATH: &str = "performed";
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Generates random machines, and events, from a seed; see the module documentation.
+///
+#[derive(Clone, Debug)]
+pub struct MachineGenerator {
+    seed: u64,
+    random: u64,
+    states: usize,
+    depth: usize,
+    signals: usize,
+    history: bool,
+    guards: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl MachineGenerator {
+    ///
+    /// A generator of machines with up to 4 states in each region, nested to a depth of 2,
+    /// with transitions triggered by 3 signals, history pseudostates, and guards.
+    ///
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            random: seed,
+            states: 4,
+            depth: 2,
+            signals: 3,
+            history: true,
+            guards: true,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    ///
+    /// Generate at most `states` states, at least 1, in each region.
+    ///
+    pub fn states(self, states: usize) -> Self {
+        Self {
+            states: states.max(1),
+            ..self
+        }
+    }
+
+    ///
+    /// Nest composite states to at most `depth`, where 0 generates only simple states.
+    ///
+    pub fn depth(self, depth: usize) -> Self {
+        Self { depth, ..self }
+    }
+
+    ///
+    /// Trigger transitions by at most `signals` signals, at least 1.
+    ///
+    pub fn signals(self, signals: usize) -> Self {
+        Self {
+            signals: signals.clamp(1, 26),
+            ..self
+        }
+    }
+
+    pub fn history(self, history: bool) -> Self {
+        Self { history, ..self }
+    }
+
+    pub fn guards(self, guards: bool) -> Self {
+        Self { guards, ..self }
+    }
+
+    ///
+    /// Generate the next machine, labeled with the seed of the generator.
+    ///
+    pub fn machine(&mut self) -> Result<Arc<StateMachine>> {
+        let mut names = Names::default();
+        let mut plan = self.plan_region(0, &mut names);
+        self.plan_transitions(&mut plan, &names);
+        MachineBuilder::labeled(&format!("generated-{}", self.seed))
+            .region(|region| build_region(&plan, region))
+            .build()
+    }
+
+    ///
+    /// Generate `count` signal events, from the signals that trigger the machines' transitions.
+    ///
+    pub fn events(&mut self, count: usize) -> Vec<Event> {
+        (0..count)
+            .map(|_| {
+                let signal = self.signal();
+                Event::Signal(SignalEvent::new(&signal))
+            })
+            .collect()
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    fn plan_region(&mut self, depth: usize, names: &mut Names) -> RegionPlan {
+        let count = 1 + self.below(self.states);
+        let mut states = Vec::with_capacity(count);
+        for _ in 0..count {
+            let name = names.state();
+            let region = if depth < self.depth && self.chance(3) {
+                Some(self.plan_region(depth + 1, names))
+            } else {
+                None
+            };
+            states.push(StatePlan {
+                name,
+                region,
+                entry: self.chance(2),
+                exit: self.chance(2),
+                transitions: Default::default(),
+            });
+        }
+        let initial = states[self.below(states.len())].name.clone();
+        let final_state = if self.chance(3) {
+            Some(names.final_state())
+        } else {
+            None
+        };
+        let history = if depth > 0 && self.history && self.chance(2) {
+            let kind = if self.chance(2) {
+                PseudoStateKind::ShallowHistory
+            } else {
+                PseudoStateKind::DeepHistory
+            };
+            Some((names.history(), kind))
+        } else {
+            None
+        };
+        RegionPlan {
+            initial,
+            states,
+            final_state,
+            history,
+        }
+    }
+
+    fn plan_transitions(&mut self, region: &mut RegionPlan, names: &Names) {
+        for state in region.states.iter_mut() {
+            // each signal triggers at most one transition from a state, so that it is deterministic
+            let mut signals: Vec<String> = (0..self.signals).map(signal_name).collect();
+            for _ in 0..(self.below(3) + 1).min(self.signals) {
+                let signal = signals.remove(self.below(signals.len()));
+                let target = names.targets[self.below(names.targets.len())].clone();
+                state
+                    .transitions
+                    .push(self.plan_transition(Some(signal), target));
+            }
+            let name = state.name.clone();
+            if let Some(inner) = &mut state.region {
+                if inner.final_state.is_some() && self.chance(2) {
+                    // completions only target simple and composite states, so that they end
+                    let excluded = inner.names();
+                    let targets: Vec<&String> = names
+                        .states
+                        .iter()
+                        .filter(|target| *target != &name && !excluded.contains(target))
+                        .collect();
+                    if !targets.is_empty() {
+                        let target = targets[self.below(targets.len())].clone();
+                        state.transitions.push(self.plan_transition(None, target));
+                    }
+                }
+                self.plan_transitions(inner, names);
+            }
+        }
+    }
+
+    fn plan_transition(&mut self, signal: Option<String>, target: String) -> TransitionPlan {
+        TransitionPlan {
+            signal,
+            target,
+            effect: self.chance(3),
+            guarded: self.guards && self.chance(4),
+        }
+    }
+
+    fn signal(&mut self) -> String {
+        signal_name(self.below(self.signals))
+    }
+
+    ///
+    /// Returns `true` one time in `n`.
+    ///
+    fn chance(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    ///
+    /// A number in `0..n`, from a splitmix64 sequence.
+    ///
+    fn below(&mut self, n: usize) -> usize {
+        self.random = self.random.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.random;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z % n as u64) as usize
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct RegionPlan {
+    /// The name of the initial pseudostate's target.
+    initial: String,
+    states: Vec<StatePlan>,
+    final_state: Option<String>,
+    history: Option<(String, PseudoStateKind)>,
+}
+
+struct StatePlan {
+    name: String,
+    region: Option<RegionPlan>,
+    entry: bool,
+    exit: bool,
+    transitions: Vec<TransitionPlan>,
+}
+
+struct TransitionPlan {
+    /// The signal triggering the transition, `None` for a completion transition.
+    signal: Option<String>,
+    target: String,
+    effect: bool,
+    guarded: bool,
+}
+
+///
+/// The names given so far, and those of the vertices a transition may target.
+///
+#[derive(Default)]
+struct Names {
+    next: usize,
+    states: Vec<String>,
+    targets: Vec<String>,
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl RegionPlan {
+    ///
+    /// The names of the vertices within this region, and within its states.
+    ///
+    fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.final_state.iter().cloned().collect();
+        names.extend(self.history.iter().map(|(name, _)| name.clone()));
+        for state in &self.states {
+            names.push(state.name.clone());
+            if let Some(region) = &state.region {
+                names.extend(region.names());
+            }
+        }
+        names
+    }
+}
+
+impl Names {
+    fn state(&mut self) -> String {
+        let name = self.name("s");
+        self.states.push(name.clone());
+        name
+    }
+
+    fn final_state(&mut self) -> String {
+        self.name("f")
+    }
+
+    fn history(&mut self) -> String {
+        self.name("h")
+    }
+
+    fn name(&mut self, prefix: &str) -> String {
+        let name = format!("{}{}", prefix, self.next);
+        self.next += 1;
+        self.targets.push(name.clone());
+        name
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn build_region(
+    plan: &RegionPlan,
+    region: RegionBuilder<WithoutInitial>,
+) -> RegionBuilder<WithInitial> {
+    let mut region = region.initial(&plan.initial);
+    for state in &plan.states {
+        region = region.state(&state.name, |builder| build_state(state, builder));
+    }
+    if let Some(name) = &plan.final_state {
+        region = region.final_state(name);
+    }
+    if let Some((name, kind)) = &plan.history {
+        region = region.pseudo_state(name, kind.clone());
+    }
+    region
+}
+
+fn build_state(plan: &StatePlan, mut state: StateBuilder<Ordinary>) -> StateBuilder<Ordinary> {
+    if plan.entry {
+        state = state.on_entry(performs(&format!("enter_{}", plan.name)));
+    }
+    if plan.exit {
+        state = state.on_exit(performs(&format!("exit_{}", plan.name)));
+    }
+    for (index, transition) in plan.transitions.iter().enumerate() {
+        let mut builder = TransitionBuilder::to(&transition.target);
+        if let Some(signal) = &transition.signal {
+            builder = builder.on(signal);
+        }
+        if transition.effect {
+            builder = builder.effect(performs(&format!("{}_{}", plan.name, index)));
+        }
+        if transition.guarded {
+            builder = builder.guard(even());
+        }
+        state = state.transition(builder);
+    }
+    if let Some(region) = &plan.region {
+        state = state.region(|builder| build_region(region, builder));
+    }
+    state
+}
+
+fn signal_name(index: usize) -> String {
+    char::from(b'a' + index as u8).to_string()
+}
+
+fn performed_path() -> FieldPath {
+    PERFORMED_PATH.parse().unwrap()
+}
+
+fn performed(context: &Context) -> String {
+    match context.get(performed_path()) {
+        Some(FieldValue::String(performed)) => performed,
+        _ => String::new(),
+    }
+}
+
+fn performs(label: &str) -> FnBehavior {
+    let name = label.to_string();
+    from_fn(label, move |_, _, _, context| {
+        let performed = performed(context);
+        context.insert(performed_path(), format!("{}{};", performed, name).into())
+    })
+}
+
+fn even() -> FnGuard {
+    guards::from_fn("even", |_, _, context| {
+        performed(context).matches(';').count().is_multiple_of(2)
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::{HasRegions, Labeled, Vertex};
+
+    fn labels(machine: &StateMachine) -> Vec<Option<String>> {
+        let mut labels = vec![];
+        fn collect(vertices: Vec<Arc<Vertex>>, labels: &mut Vec<Option<String>>) {
+            for vertex in vertices {
+                if let Some(state) = vertex.as_state() {
+                    labels.push(state.label().clone());
+                    for region in state.regions() {
+                        collect(region.vertices(), labels);
+                    }
+                }
+            }
+        }
+        collect(machine.default_region().unwrap().vertices(), &mut labels);
+        labels
+    }
+
+    #[test]
+    fn test_generate_reproducibly() {
+        for seed in 0..50 {
+            let machine = MachineGenerator::new(seed).machine().unwrap();
+            let again = MachineGenerator::new(seed).machine().unwrap();
+            assert_eq!(labels(&machine), labels(&again));
+            assert_eq!(machine.label(), &Some(format!("generated-{}", seed)));
+        }
+        let flat = MachineGenerator::new(7)
+            .depth(0)
+            .history(false)
+            .machine()
+            .unwrap();
+        assert!(labels(&flat).iter().all(|label| label.is_some()));
+        assert_eq!(
+            MachineGenerator::new(3).events(20),
+            MachineGenerator::new(3).events(20)
+        );
+    }
+}
@@ -0,0 +1,671 @@
+/*!
+Combinators for building composite guards from individual [`Constraint`]s, constructors for guards
+from closures and context expressions, and the `else` guard for the branches of choice and
+junction pseudostates.
+
+The combinators [`and`], [`or`], [`not`], [`all_of`], and [`any_of`] label the guard they create
+from the labels of their sub-guards, such as `is_admin && !locked`, where every sub-guard is
+labeled; sub-guards that are themselves compound are parenthesized.
+
+The ordered combinators evaluate their sub-guards strictly in the order given and short-circuit;
+[`all_ordered`] stops at the first sub-guard that is `false` and [`any_ordered`] stops at the
+first that is `true`. Sub-guards after that point are never evaluated, which allows expensive
+or side-effecting checks to be placed last. Evaluating a guard with `Constraint::explain_in`
+returns, with its value, the sub-guards evaluated by that call, so that it is possible to
+determine why a guard did, or did not, allow a transition to fire; the executor records these
+in the `StepResult` of each step, see `StepResult::guards`.
+
+# Example
+
+```rust
+use uml_state_machine::core::{Context, ID};
+use uml_state_machine::definition::guards::all_ordered;
+use uml_state_machine::definition::types::{Constraint, Labeled, Trigger};
+
+struct Fixed(Option<String>, bool);
+
+impl Labeled for Fixed {
+    fn label(&self) -> &Option<String> { &self.0 }
+    fn set_label(&mut self, label: &str) { self.0 = Some(label.to_string()) }
+    fn unset_label(&mut self) { self.0 = None }
+}
+
+impl Constraint for Fixed {
+    fn evaluate(&self, _: &ID, _: &Trigger) -> bool { self.1 }
+}
+
+let guard = all_ordered(vec![
+    Box::new(Fixed(Some("is_ready".to_string()), true)),
+    Box::new(Fixed(Some("has_stock".to_string()), false)),
+    Box::new(Fixed(Some("is_paid".to_string()), true)),
+]);
+
+assert!(!guard.evaluate(&ID::random(), &Trigger::any()));
+assert_eq!(guard.label(), &Some("is_ready && has_stock && is_paid".to_string()));
+
+let outcome = guard.explain_in(&ID::random(), &Trigger::any(), &Context::default());
+assert!(!outcome.result);
+assert_eq!(outcome.failed_guard(), Some(1));
+assert_eq!(outcome.evaluated.len(), 2);
+```
+
+Guards may also be created from a closure over the current state, trigger, and the instance's
+context with [`from_fn`], or from an expression over the context with [`from_expression`].
+
+```rust
+use uml_state_machine::core::context::Compound;
+use uml_state_machine::core::{Context, FieldPath, ID};
+use uml_state_machine::definition::guards::{and, from_expression, from_fn, not};
+use uml_state_machine::definition::types::{Constraint, Labeled, Trigger};
+
+let guard = and(
+    from_fn("is_admin", |_, _, context| context.contains_key("admin".parse::<FieldPath>().unwrap())),
+    not(from_expression("locked").unwrap()),
+);
+
+assert_eq!(guard.label(), &Some("is_admin && !locked".to_string()));
+let context = Context::default();
+context.insert("admin".parse::<FieldPath>().unwrap(), true.into());
+context.insert("locked".parse::<FieldPath>().unwrap(), false.into());
+assert!(guard.evaluate_in(&ID::random(), &Trigger::any(), &context));
+```
+
+The [`else_guard`] is always `true`, an executor takes the branch it guards only where no other
+branch of the same choice or junction is enabled. It is identified by `Constraint::is_else`,
+not by its label, so relabeling it, or labeling another guard `else`, does not change which
+branch is taken. Formats that write a guard as `else` read it back with [`recognize_else`].
+
+*/
+
+use std::sync::Arc;
+
+use crate::core::{Context, ID};
+use crate::definition::expressions::ExprConstraint;
+use crate::definition::types::{Constraint, Labeled, Trigger};
+use crate::error::Result;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The result of evaluating a single sub-guard within a combinator.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct GuardEvaluation {
+    /// The index of the sub-guard within the combinator.
+    pub index: usize,
+    /// The sub-guard's label, if it has one.
+    pub label: Option<String>,
+    /// The value the sub-guard returned.
+    pub result: bool,
+}
+
+///
+/// The value of a guard, and the sub-guards evaluated, in order, to determine it, as returned by
+/// `Constraint::explain_in`; only the combinators evaluate sub-guards.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GuardOutcome {
+    /// The value the guard returned.
+    pub result: bool,
+    /// The sub-guards evaluated, in order, up to the one that short-circuited the guard.
+    pub evaluated: Vec<GuardEvaluation>,
+}
+
+///
+/// A guard that is `true` only if all sub-guards are, evaluated in order.
+///
+pub struct AllOrdered {
+    label: Option<String>,
+    constraints: Vec<Box<dyn Constraint>>,
+}
+
+///
+/// A guard that is `true` if any sub-guard is, evaluated in order.
+///
+pub struct AnyOrdered {
+    label: Option<String>,
+    constraints: Vec<Box<dyn Constraint>>,
+}
+
+///
+/// A guard that is `true` where its sub-guard is `false`.
+///
+pub struct Not {
+    label: Option<String>,
+    constraint: Box<dyn Constraint>,
+}
+
+///
+/// The signature of a closure evaluated by a `FnGuard`; the context is empty where the guard is
+/// evaluated outside of an instance.
+///
+pub type GuardFn = dyn Fn(&ID, &Trigger, &Context) -> bool + Send + Sync;
+
+///
+/// A guard evaluated by a closure, see `from_fn`.
+///
+#[derive(Clone)]
+pub struct FnGuard {
+    label: Option<String>,
+    evaluate: Arc<GuardFn>,
+}
+
+///
+/// The guard of the branch of a choice or junction taken where no other branch is enabled.
+///
+#[derive(Clone, Debug)]
+pub struct Else {
+    label: Option<String>,
+}
+
+///
+/// The label of an `else` guard, and the body of the opaque guard formats write it as.
+///
+pub const ELSE_LABEL: &str = "else";
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+pub fn else_guard() -> Else {
+    Else {
+        label: Some(ELSE_LABEL.to_string()),
+    }
+}
+
+///
+/// Returns `true` if `constraint` is an `else` guard, see `Constraint::is_else`.
+///
+pub fn is_else(constraint: &dyn Constraint) -> bool {
+    constraint.is_else()
+}
+
+///
+/// The guard read from a format, an `else` guard where `guard` is an opaque constraint whose
+/// body is `else`, otherwise `guard` itself.
+///
+pub fn recognize_else(guard: Box<dyn Constraint>) -> Box<dyn Constraint> {
+    match guard.as_opaque() {
+        Some(opaque) if opaque.body().trim() == ELSE_LABEL => Box::new(else_guard()),
+        _ => guard,
+    }
+}
+
+///
+/// Create a guard evaluating each of `constraints` in order, short-circuiting on the first that
+/// is `false`.
+///
+pub fn all_ordered(constraints: Vec<Box<dyn Constraint>>) -> AllOrdered {
+    AllOrdered {
+        label: compose_label(&constraints, " && "),
+        constraints,
+    }
+}
+
+///
+/// Create a guard evaluating each of `constraints` in order, short-circuiting on the first that
+/// is `true`.
+///
+pub fn any_ordered(constraints: Vec<Box<dyn Constraint>>) -> AnyOrdered {
+    AnyOrdered {
+        label: compose_label(&constraints, " || "),
+        constraints,
+    }
+}
+
+///
+/// Create a guard that is `true` where both `lhs` and `rhs` are, evaluated in that order.
+///
+pub fn and(lhs: impl Constraint + 'static, rhs: impl Constraint + 'static) -> AllOrdered {
+    all_ordered(vec![Box::new(lhs), Box::new(rhs)])
+}
+
+///
+/// Create a guard that is `true` where either `lhs` or `rhs` is, evaluated in that order.
+///
+pub fn or(lhs: impl Constraint + 'static, rhs: impl Constraint + 'static) -> AnyOrdered {
+    any_ordered(vec![Box::new(lhs), Box::new(rhs)])
+}
+
+///
+/// Create a guard that is `true` where `constraint` is `false`.
+///
+pub fn not(constraint: impl Constraint + 'static) -> Not {
+    Not {
+        label: constraint
+            .label()
+            .as_ref()
+            .map(|label| format!("!{}", operand(label, ""))),
+        constraint: Box::new(constraint),
+    }
+}
+
+///
+/// Create a guard that is `true` where all of `constraints` are, as for `all_ordered`.
+///
+pub fn all_of(constraints: impl IntoIterator<Item = Box<dyn Constraint>>) -> AllOrdered {
+    all_ordered(constraints.into_iter().collect())
+}
+
+///
+/// Create a guard that is `true` where any of `constraints` is, as for `any_ordered`.
+///
+pub fn any_of(constraints: impl IntoIterator<Item = Box<dyn Constraint>>) -> AnyOrdered {
+    any_ordered(constraints.into_iter().collect())
+}
+
+///
+/// Create a guard, labeled `label`, evaluated by the closure `f`.
+///
+pub fn from_fn<F>(label: &str, f: F) -> FnGuard
+where
+    F: Fn(&ID, &Trigger, &Context) -> bool + Send + Sync + 'static,
+{
+    FnGuard {
+        label: Some(label.to_string()),
+        evaluate: Arc::new(f),
+    }
+}
+
+///
+/// Parse `expression` as a guard over the instance's context, see `ExprConstraint`.
+///
+pub fn from_expression(expression: &str) -> Result<ExprConstraint> {
+    ExprConstraint::new(expression)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+macro_rules! make_ordered_impl {
+    ($type_name:ident, $short_circuit_on:expr) => {
+        impl Labeled for $type_name {
+            fn label(&self) -> &Option<String> {
+                &self.label
+            }
+
+            fn set_label(&mut self, label: &str) {
+                self.label = Some(label.to_string())
+            }
+
+            fn unset_label(&mut self) {
+                self.label = None
+            }
+        }
+
+        impl Constraint for $type_name {
+            fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool {
+                self.evaluate_each(|constraint| constraint.evaluate(in_state, on_trigger))
+                    .result
+            }
+
+            fn evaluate_in(&self, in_state: &ID, on_trigger: &Trigger, context: &Context) -> bool {
+                self.explain_in(in_state, on_trigger, context).result
+            }
+
+            fn explain_in(
+                &self,
+                in_state: &ID,
+                on_trigger: &Trigger,
+                context: &Context,
+            ) -> GuardOutcome {
+                self.evaluate_each(|constraint| {
+                    constraint.evaluate_in(in_state, on_trigger, context)
+                })
+            }
+
+            fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+                let constraints: Option<Vec<Box<dyn Constraint>>> = self
+                    .constraints
+                    .iter()
+                    .map(|constraint| constraint.duplicate())
+                    .collect();
+                Some(Box::new($type_name {
+                    label: self.label.clone(),
+                    constraints: constraints?,
+                }))
+            }
+        }
+
+        impl $type_name {
+            fn evaluate_each(&self, evaluate: impl Fn(&dyn Constraint) -> bool) -> GuardOutcome {
+                let mut evaluated = Vec::new();
+                for (index, constraint) in self.constraints.iter().enumerate() {
+                    let result = evaluate(constraint.as_ref());
+                    evaluated.push(GuardEvaluation {
+                        index,
+                        label: constraint.label().clone(),
+                        result,
+                    });
+                    if result == $short_circuit_on {
+                        break;
+                    }
+                }
+                GuardOutcome {
+                    result: evaluated
+                        .last()
+                        .map_or(!$short_circuit_on, |evaluation| evaluation.result),
+                    evaluated,
+                }
+            }
+        }
+
+        impl $type_name {
+            pub fn len(&self) -> usize {
+                self.constraints.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.constraints.is_empty()
+            }
+        }
+    };
+}
+
+macro_rules! make_labeled_impl {
+    ($type_name:ident) => {
+        impl Labeled for $type_name {
+            fn label(&self) -> &Option<String> {
+                &self.label
+            }
+
+            fn set_label(&mut self, label: &str) {
+                self.label = Some(label.to_string())
+            }
+
+            fn unset_label(&mut self) {
+                self.label = None
+            }
+        }
+    };
+}
+
+make_labeled_impl!(Not);
+
+impl Constraint for Not {
+    fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool {
+        !self.constraint.evaluate(in_state, on_trigger)
+    }
+
+    fn evaluate_in(&self, in_state: &ID, on_trigger: &Trigger, context: &Context) -> bool {
+        !self.constraint.evaluate_in(in_state, on_trigger, context)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(Not {
+            label: self.label.clone(),
+            constraint: self.constraint.duplicate()?,
+        }))
+    }
+}
+
+make_labeled_impl!(FnGuard);
+
+impl Constraint for FnGuard {
+    fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool {
+        (self.evaluate)(in_state, on_trigger, &Context::default())
+    }
+
+    fn evaluate_in(&self, in_state: &ID, on_trigger: &Trigger, context: &Context) -> bool {
+        (self.evaluate)(in_state, on_trigger, context)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl Labeled for Else {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Constraint for Else {
+    fn evaluate(&self, _in_state: &ID, _on_trigger: &Trigger) -> bool {
+        true
+    }
+
+    fn is_else(&self) -> bool {
+        true
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+make_ordered_impl!(AllOrdered, false);
+
+make_ordered_impl!(AnyOrdered, true);
+
+impl GuardOutcome {
+    ///
+    /// The index of the sub-guard that made an `all_ordered` guard `false`, if any.
+    ///
+    pub fn failed_guard(&self) -> Option<usize> {
+        self.short_circuited_on(false)
+    }
+
+    ///
+    /// The index of the sub-guard that made an `any_ordered` guard `true`, if any.
+    ///
+    pub fn satisfied_guard(&self) -> Option<usize> {
+        self.short_circuited_on(true)
+    }
+
+    fn short_circuited_on(&self, result: bool) -> Option<usize> {
+        self.evaluated
+            .last()
+            .filter(|evaluation| evaluation.result == result)
+            .map(|evaluation| evaluation.index)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn compose_label(constraints: &[Box<dyn Constraint>], operator: &str) -> Option<String> {
+    let labels: Option<Vec<String>> = constraints
+        .iter()
+        .map(|c| c.label().as_ref().map(|label| operand(label, operator)))
+        .collect();
+    match labels {
+        Some(labels) if !labels.is_empty() => Some(labels.join(operator)),
+        _ => None,
+    }
+}
+
+///
+/// The label of a sub-guard within a guard composed with `operator`, parenthesized where it is
+/// itself composed with a different operator or, for `not`, where it is more than a name.
+///
+fn operand(label: &str, operator: &str) -> String {
+    let compound = if operator.is_empty() {
+        label.contains(' ')
+    } else {
+        [" && ", " || "]
+            .iter()
+            .any(|other| *other != operator && label.contains(other))
+    };
+    if compound {
+        format!("({})", label)
+    } else {
+        label.to_string()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Counted {
+        label: Option<String>,
+        result: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Labeled for Counted {
+        fn label(&self) -> &Option<String> {
+            &self.label
+        }
+
+        fn set_label(&mut self, label: &str) {
+            self.label = Some(label.to_string())
+        }
+
+        fn unset_label(&mut self) {
+            self.label = None
+        }
+    }
+
+    impl Constraint for Counted {
+        fn evaluate(&self, _: &ID, _: &Trigger) -> bool {
+            let _ = self.calls.fetch_add(1, Ordering::SeqCst);
+            self.result
+        }
+    }
+
+    fn counted(label: &str, result: bool, calls: &Arc<AtomicUsize>) -> Box<dyn Constraint> {
+        Box::new(counted_guard(label, result, calls))
+    }
+
+    fn counted_guard(label: &str, result: bool, calls: &Arc<AtomicUsize>) -> Counted {
+        Counted {
+            label: Some(label.to_string()),
+            result,
+            calls: calls.clone(),
+        }
+    }
+
+    #[test]
+    fn test_any_ordered_short_circuits() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let guard = any_ordered(vec![
+            counted("a", false, &calls),
+            counted("b", true, &calls),
+            counted("c", true, &calls),
+        ]);
+        assert!(guard.evaluate(&ID::random(), &Trigger::any()));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let outcome = guard.explain_in(&ID::random(), &Trigger::any(), &Context::default());
+        assert_eq!(outcome.satisfied_guard(), Some(1));
+        assert_eq!(outcome.failed_guard(), None);
+        assert_eq!(guard.label(), &Some("a || b || c".to_string()));
+
+        let guard = any_ordered(vec![counted("a", false, &calls)]);
+        let outcome = guard.explain_in(&ID::random(), &Trigger::any(), &Context::default());
+        assert!(!outcome.result);
+        assert_eq!(outcome.satisfied_guard(), None);
+    }
+
+    #[test]
+    fn test_combinators() {
+        use crate::core::context::Compound;
+        use crate::core::FieldPath;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let guard = or(
+            and(
+                from_fn("is_admin", |_, _, context| {
+                    context.contains_key("admin".parse::<FieldPath>().unwrap())
+                }),
+                not(from_expression("locked").unwrap()),
+            ),
+            all_of(vec![
+                counted("a", false, &calls),
+                counted("b", true, &calls),
+            ]),
+        );
+        assert_eq!(
+            guard.label(),
+            &Some("(is_admin && !locked) || (a && b)".to_string())
+        );
+        let context = Context::default();
+        context.insert("locked".parse::<FieldPath>().unwrap(), false.into());
+        assert!(!guard.evaluate_in(&ID::random(), &Trigger::any(), &context));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        context.insert("admin".parse::<FieldPath>().unwrap(), true.into());
+        assert!(guard.evaluate_in(&ID::random(), &Trigger::any(), &context));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let guard = and(
+            any_of(vec![counted("a", true, &calls), counted("b", true, &calls)]),
+            not(not(counted_guard("c", true, &calls))),
+        );
+        assert_eq!(guard.label(), &Some("(a || b) && !!c".to_string()));
+        assert!(guard.evaluate(&ID::random(), &Trigger::any()));
+        let guard = not(guard);
+        assert_eq!(guard.label(), &Some("!((a || b) && !!c)".to_string()));
+        assert!(!guard.evaluate(&ID::random(), &Trigger::any()));
+        assert!(guard.duplicate().is_none());
+        let guard = not(from_fn("ready", |_, _, _| true));
+        assert_eq!(
+            guard.duplicate().unwrap().label(),
+            &Some("!ready".to_string())
+        );
+    }
+
+    #[test]
+    fn test_all_ordered_records_evaluation() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let guard = all_ordered(vec![counted("a", true, &calls), counted("b", true, &calls)]);
+        let outcome = guard.explain_in(&ID::random(), &Trigger::any(), &Context::default());
+        assert!(outcome.result);
+        assert_eq!(outcome.failed_guard(), None);
+        assert_eq!(
+            outcome.evaluated,
+            vec![
+                GuardEvaluation {
+                    index: 0,
+                    label: Some("a".to_string()),
+                    result: true
+                },
+                GuardEvaluation {
+                    index: 1,
+                    label: Some("b".to_string()),
+                    result: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_else_is_not_a_label() {
+        use crate::definition::opaque::OpaqueConstraint;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        assert!(is_else(&else_guard()));
+        assert!(!is_else(&counted_guard(ELSE_LABEL, true, &calls)));
+        assert!(!is_else(&from_fn(ELSE_LABEL, |_, _, _| true)));
+
+        let mut relabeled = else_guard();
+        relabeled.set_label("otherwise");
+        assert!(is_else(&relabeled));
+
+        let read = recognize_else(Box::new(OpaqueConstraint::new(ELSE_LABEL, None)));
+        assert!(read.is_else());
+        let read = recognize_else(Box::new(OpaqueConstraint::new("ready", None)));
+        assert!(!read.is_else());
+    }
+}
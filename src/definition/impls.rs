@@ -7,12 +7,18 @@ More detailed description, with
 
 */
 
-use crate::core::ID;
+use crate::core::context::{Compound, FieldPath};
+use crate::core::{Context, FieldKind, FieldValue, ID};
 use crate::definition::types::*;
-use crate::error::Result;
+use crate::error::{ErrorKind, Result};
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::slice::Iter;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // ------------------------------------------------------------------------------------------------
 // Macros
@@ -36,6 +42,24 @@ macro_rules! make_labeled_impl {
     };
 }
 
+macro_rules! make_has_metadata_impl {
+    ($type_name:ident) => {
+        impl HasMetadata for $type_name {
+            fn metadata(&self) -> &HashMap<String, FieldValue> {
+                &self.metadata
+            }
+
+            fn set_metadata(&mut self, key: &str, value: FieldValue) {
+                let _ = self.metadata.insert(key.to_string(), value);
+            }
+
+            fn unset_metadata(&mut self, key: &str) -> Option<FieldValue> {
+                self.metadata.remove(key)
+            }
+        }
+    };
+}
+
 macro_rules! make_identified_impl {
     ($type_name:ident) => {
         impl Identified for $type_name {
@@ -106,12 +130,13 @@ make_contained_impl!(ConnectionPointReference);
 impl ConnectionPointReference {
     pub fn within(container: ID) -> Self {
         Self {
-            id: ID::random(),
+            id: new_element_id(&container, "connection"),
             label: None,
             container,
             entry: vec![],
             exit: vec![],
             state: None,
+            redefined: None,
         }
     }
 
@@ -119,25 +144,38 @@ impl ConnectionPointReference {
         self.entry.iter()
     }
 
+    pub fn add_entry(&mut self, entry_point: ID) {
+        self.entry.push(entry_point)
+    }
+
     pub fn exit(&self) -> Iter<'_, ID> {
         self.exit.iter()
     }
 
+    pub fn add_exit(&mut self, exit_point: ID) {
+        self.exit.push(exit_point)
+    }
+
     pub fn state(&self) -> &Option<ID> {
         &self.state
     }
+
+    pub fn set_state(&mut self, state: ID) {
+        self.state = Some(state)
+    }
+
+    ///
+    /// The ID of the vertex, in the machine this machine extends, that this vertex redefines.
+    ///
+    pub fn redefined_vertex(&self) -> &Option<ID> {
+        &self.redefined
+    }
 }
 
 // ------------------------------------------------------------------------------------------------
 // Implementations - PseudoStateKind
 // ------------------------------------------------------------------------------------------------
 
-impl Default for PseudoStateKind {
-    fn default() -> Self {
-        PseudoStateKind::Initial
-    }
-}
-
 make_identified_impl!(PseudoState);
 
 make_labeled_impl!(PseudoState);
@@ -147,10 +185,11 @@ make_contained_impl!(PseudoState);
 impl PseudoState {
     pub fn within(container: ID, kind: PseudoStateKind) -> Self {
         Self {
-            id: ID::random(),
+            id: new_element_id(&container, "pseudo_state"),
             label: None,
             container,
             kind,
+            redefined: None,
         }
     }
 
@@ -158,6 +197,13 @@ impl PseudoState {
         self.kind.clone()
     }
 
+    ///
+    /// The ID of the vertex, in the machine this machine extends, that this vertex redefines.
+    ///
+    pub fn redefined_vertex(&self) -> &Option<ID> {
+        &self.redefined
+    }
+
     is_pseudo_state_kind!(is_initial, Initial);
 
     is_pseudo_state_kind!(is_deep_history, DeepHistory);
@@ -189,26 +235,32 @@ make_labeled_impl!(Region);
 
 make_contained_impl!(Region);
 
+make_has_metadata_impl!(Region);
+
 impl Region {
     pub fn within_state(container: ID) -> Self {
         Self {
-            id: ID::random(),
+            id: new_element_id(&container, "region"),
             container,
             container_type: RegionContainerType::State,
+            extended: None,
+            metadata: Default::default(),
             label: None,
-            vertices: Rc::new(RefCell::new(vec![])),
-            transitions: Rc::new(RefCell::new(vec![])),
+            vertices: Arc::new(RwLock::new(vec![])),
+            transitions: Arc::new(RwLock::new(vec![])),
         }
     }
 
     pub fn within_state_machine(container: ID) -> Self {
         Self {
-            id: ID::random(),
+            id: new_element_id(&container, "region"),
             container,
             container_type: RegionContainerType::StateMachine,
+            extended: None,
+            metadata: Default::default(),
             label: None,
-            vertices: Rc::new(RefCell::new(vec![])),
-            transitions: Rc::new(RefCell::new(vec![])),
+            vertices: Arc::new(RwLock::new(vec![])),
+            transitions: Arc::new(RwLock::new(vec![])),
         }
     }
 
@@ -296,16 +348,23 @@ impl Region {
         self.add_vertex(Vertex::ConnectionPointReference(cpr))
     }
 
+    ///
+    /// The ID of the region, in the machine this machine extends, that this region extends.
+    ///
+    pub fn extended_region(&self) -> &Option<ID> {
+        &self.extended
+    }
+
     pub fn container_type(&self) -> &RegionContainerType {
         &self.container_type
     }
 
-    pub fn vertices(&self) -> Vec<Rc<Vertex>> {
-        self.vertices.borrow().iter().cloned().collect()
+    pub fn vertices(&self) -> Vec<Arc<Vertex>> {
+        self.vertices.read().unwrap().iter().cloned().collect()
     }
 
-    pub fn transitions(&self) -> Vec<Rc<Transition>> {
-        self.transitions.borrow().iter().cloned().collect()
+    pub fn transitions(&self) -> Vec<Arc<Transition>> {
+        self.transitions.read().unwrap().iter().cloned().collect()
     }
 
     pub fn new_transition(&self, source: ID, target: ID) {
@@ -314,11 +373,43 @@ impl Region {
     }
 
     pub fn add_transition(&self, transition: Transition) {
-        self.transitions.borrow_mut().push(Rc::new(transition));
+        self.transitions.write().unwrap().push(Arc::new(transition));
+    }
+
+    ///
+    /// Declare that the state `source` times out, moving to `target` where it has been active for
+    /// `after` without leaving; this adds a transition triggered by the relative time event
+    /// `after`. A state may have more than one timeout, but no two with the same duration.
+    ///
+    pub fn new_timeout(&self, source: ID, after: Duration, target: ID) {
+        let mut transition: Transition = Transition::within(source, target, self.id.clone());
+        transition.add_trigger(Trigger::after(after));
+        self.add_transition(transition);
+    }
+
+    ///
+    /// Returns the transitions in this region from `source` that an occurrence of `event` would
+    /// trigger. Transitions that name the event explicitly take priority, those triggered by an
+    /// `AnyReceive` wildcard are only returned when no explicit transition exists. Guards are
+    /// not evaluated here; an executor falls back to the wildcards where no explicit transition
+    /// is enabled.
+    ///
+    pub fn transitions_triggered_by(&self, source: &ID, event: &Event) -> Vec<Arc<Transition>> {
+        let from_source: Vec<Arc<Transition>> = self
+            .transitions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|transition| &transition.source == source)
+            .cloned()
+            .collect();
+        triggered_by(from_source.clone(), from_source, event, |transition| {
+            Some(transition.clone())
+        })
     }
 
     fn add_vertex(&self, vertex: Vertex) {
-        self.vertices.borrow_mut().push(Rc::new(vertex));
+        self.vertices.write().unwrap().push(Arc::new(vertex));
     }
 
     fn new_pseudo_state(&self, kind: PseudoStateKind) -> ID {
@@ -341,11 +432,14 @@ make_contained_impl!(State);
 
 make_has_regions_impl!(State);
 
+make_has_metadata_impl!(State);
+
 impl State {
     pub fn within(container: ID) -> Self {
         Self {
-            id: ID::random(),
+            id: new_element_id(&container, "state"),
             label: None,
+            comments: vec![],
             container,
             regions: vec![],
             sub_machine: None,
@@ -356,7 +450,10 @@ impl State {
             entry: None,
             do_activity: None,
             exit: None,
+            invokes: vec![],
             final_state: false,
+            redefined: None,
+            metadata: Default::default(),
         }
     }
 
@@ -367,10 +464,29 @@ impl State {
         region_id
     }
 
+    ///
+    /// Documentation attached to this state, rendered as notes by writers that support them.
+    ///
+    pub fn comments(&self) -> Iter<'_, String> {
+        self.comments.iter()
+    }
+
+    pub fn add_comment(&mut self, comment: &str) {
+        self.comments.push(comment.to_string())
+    }
+
     pub fn sub_machine(&self) -> &Option<ID> {
         &self.sub_machine
     }
 
+    ///
+    /// Make this a sub-machine state referencing `machine`, which must be added to the
+    /// containing machine with `StateMachine::add_machine_reference` to be resolved.
+    ///
+    pub fn set_sub_machine(&mut self, machine: ID) {
+        self.sub_machine = Some(machine)
+    }
+
     pub fn connections(&self) -> Iter<'_, ID> {
         self.connections.iter()
     }
@@ -383,22 +499,53 @@ impl State {
         self.deferrable_triggers.iter()
     }
 
+    pub fn add_deferrable_trigger(&mut self, trigger: Trigger) {
+        self.deferrable_triggers.push(trigger)
+    }
+
+    ///
+    /// The external services started, in order, when this state is entered.
+    ///
+    pub fn invokes(&self) -> Iter<'_, Invoke> {
+        self.invokes.iter()
+    }
+
+    pub fn add_invoke(&mut self, invoke: Invoke) {
+        self.invokes.push(invoke)
+    }
+
     pub fn invariant(&self) -> &Option<Box<dyn Constraint>> {
         &self.invariant
     }
 
+    pub fn set_invariant(&mut self, invariant: Box<dyn Constraint>) {
+        self.invariant = Some(invariant)
+    }
+
     pub fn entry(&self) -> &Option<Box<dyn Behavior>> {
         &self.entry
     }
 
+    pub fn set_entry(&mut self, entry: Box<dyn Behavior>) {
+        self.entry = Some(entry)
+    }
+
     pub fn do_activity(&self) -> &Option<Box<dyn Behavior>> {
         &self.do_activity
     }
 
+    pub fn set_do_activity(&mut self, do_activity: Box<dyn Behavior>) {
+        self.do_activity = Some(do_activity)
+    }
+
     pub fn exit(&self) -> &Option<Box<dyn Behavior>> {
         &self.exit
     }
 
+    pub fn set_exit(&mut self, exit: Box<dyn Behavior>) {
+        self.exit = Some(exit)
+    }
+
     pub fn is_composite(&self) -> bool {
         self.regions.len() == 1
     }
@@ -418,6 +565,97 @@ impl State {
     pub fn is_final(&self) -> bool {
         self.final_state
     }
+
+    pub fn set_final(&mut self, final_state: bool) {
+        self.final_state = final_state
+    }
+
+    ///
+    /// The ID of the vertex, in the machine this machine extends, that this vertex redefines.
+    ///
+    pub fn redefined_vertex(&self) -> &Option<ID> {
+        &self.redefined
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - Invoke
+// ------------------------------------------------------------------------------------------------
+
+make_identified_impl!(Invoke);
+
+make_labeled_impl!(Invoke);
+
+impl Invoke {
+    ///
+    /// A service of the kind `kind`, with a new ID.
+    ///
+    pub fn new(kind: &str) -> Self {
+        Self::with_id(ID::random_with_prefix("invoke").unwrap(), kind)
+    }
+
+    pub fn with_id(id: ID, kind: &str) -> Self {
+        Self {
+            id,
+            label: None,
+            kind: kind.to_string(),
+            source: None,
+            params: vec![],
+        }
+    }
+
+    pub fn kind(&self) -> &String {
+        &self.kind
+    }
+
+    pub fn source(&self) -> &Option<String> {
+        &self.source
+    }
+
+    pub fn set_source(&mut self, source: &str) {
+        self.source = Some(source.to_string())
+    }
+
+    pub fn params(&self) -> Iter<'_, (String, FieldPath)> {
+        self.params.iter()
+    }
+
+    ///
+    /// Pass the value of the context field at `path`, when the service starts, as the parameter
+    /// `name`.
+    ///
+    pub fn add_param(&mut self, name: &str, path: FieldPath) {
+        self.params.push((name.to_string(), path))
+    }
+
+    ///
+    /// The signal event, `done.invoke.<id>`, posted when the service completes; `<id>` is the
+    /// invoke's label, as for the SCXML `id` attribute, or where it has none its ID.
+    ///
+    pub fn done_event(&self) -> Event {
+        Event::Signal(SignalEvent::new(&format!(
+            "done.invoke.{}",
+            self.event_id()
+        )))
+    }
+
+    ///
+    /// The signal event, `error.invoke.<id>`, posted when the service fails, or where there is
+    /// no handler for its kind; `<id>` is as for `done_event`.
+    ///
+    pub fn error_event(&self) -> Event {
+        Event::Signal(SignalEvent::new(&format!(
+            "error.invoke.{}",
+            self.event_id()
+        )))
+    }
+
+    fn event_id(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.id.to_string(),
+        }
+    }
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -426,18 +664,7 @@ impl State {
 
 impl Default for StateMachine {
     fn default() -> Self {
-        let machine_id = ID::random();
-        let mut new_machine = Self {
-            id: machine_id.clone(),
-            label: None,
-            regions: vec![],
-            sub_machine_states: vec![],
-            connection_points: vec![],
-            ref_machines: Default::default(),
-            ref_vertices: Default::default(),
-        };
-        let _ = new_machine.new_region();
-        new_machine
+        Self::with_id(ID::random())
     }
 }
 
@@ -447,20 +674,59 @@ make_labeled_impl!(StateMachine);
 
 make_has_regions_impl!(StateMachine);
 
+make_has_metadata_impl!(StateMachine);
+
 impl Validate for StateMachine {
     fn validate(&self) -> Result<()> {
-        assert!(!self.regions.is_empty());
-        Ok(())
+        self.validation_report().into_result()
     }
 }
 
 impl StateMachine {
+    ///
+    /// Check every rule of the model and of the UML specification, collecting all of the
+    /// errors and warnings found; `validate` fails with the first error of this report.
+    ///
+    pub fn validation_report(&self) -> ValidationReport {
+        assert!(!self.regions.is_empty());
+        crate::definition::wellformed::validate_machine(self)
+    }
+
     pub fn labeled(label: &str) -> Self {
         let mut machine: StateMachine = StateMachine::default();
         machine.set_label(label);
         machine
     }
 
+    ///
+    /// Create a machine whose own ID, and the IDs of all elements created within it, are prefixed
+    /// with `namespace`, for example `billing::state::3`.
+    ///
+    pub fn in_namespace(namespace: &ID) -> Self {
+        Self::with_id(ID::random_in_namespace(namespace, "machine").unwrap())
+    }
+
+    pub fn namespace(&self) -> Option<ID> {
+        self.id.namespace()
+    }
+
+    fn with_id(id: ID) -> Self {
+        let mut new_machine = Self {
+            id,
+            label: None,
+            regions: vec![],
+            sub_machine_states: vec![],
+            connection_points: vec![],
+            extended: None,
+            metadata: Default::default(),
+            ref_machines: Default::default(),
+            ref_vertices: Default::default(),
+            ref_transitions: Default::default(),
+        };
+        let _ = new_machine.new_region();
+        new_machine
+    }
+
     pub fn new_region(&mut self) -> ID {
         let region: Region = Region::within_state_machine(self.id().clone());
         let region_id = region.id().clone();
@@ -488,46 +754,247 @@ impl StateMachine {
 // ------------------------------------------------------------------------------------------------
 
 impl StateMachine {
-    pub fn find_machine(&self, machine: ID) -> Option<Rc<StateMachine>> {
-        self.ref_machines.borrow().get(&machine).cloned()
+    pub fn find_machine(&self, machine: ID) -> Option<Arc<StateMachine>> {
+        self.ref_machines
+            .read()
+            .unwrap()
+            .get(&machine)
+            .and_then(|machine| machine.upgrade())
     }
 
-    pub fn find_vertex(&self, container: ID, vertex: ID) -> Option<Rc<Vertex>> {
+    pub fn find_vertex(&self, container: ID, vertex: ID) -> Option<Arc<Vertex>> {
         self.ref_vertices
-            .borrow()
+            .read()
+            .unwrap()
             .get(&(container, vertex))
-            .cloned()
+            .and_then(|vertex| vertex.upgrade())
+    }
+
+    ///
+    /// The first state, in the order of `for_each_region`, labeled `label`.
+    ///
+    pub fn find_state_by_label(&self, label: &str) -> Option<Arc<Vertex>> {
+        self.find_vertices(|vertex| {
+            vertex
+                .as_state()
+                .is_some_and(|state| state.label().as_deref() == Some(label))
+        })
+        .into_iter()
+        .next()
+    }
+
+    ///
+    /// The vertex at `path`, the labels of a vertex in one of the machine's regions and of each
+    /// vertex within the one before it, separated by `/`, such as `"Root/Processing/Retry"`. A
+    /// vertex without a label is named by its ID.
+    ///
+    pub fn find_by_path(&self, path: &str) -> Option<Arc<Vertex>> {
+        let named = |vertex: &Vertex, name: &str| match vertex.label() {
+            Some(label) => label == name,
+            None => vertex.id().to_string() == name,
+        };
+        let mut found: Option<Arc<Vertex>> = None;
+        for name in path.split('/') {
+            let vertex = {
+                let regions = match &found {
+                    None => self.regions(),
+                    Some(vertex) => vertex.as_state()?.regions(),
+                };
+                regions
+                    .flat_map(|region| region.vertices())
+                    .find(|vertex| named(vertex, name))?
+            };
+            found = Some(vertex);
+        }
+        found
+    }
+
+    ///
+    /// Every vertex of the machine, at any depth and in the order of `for_each_region`, for
+    /// which `predicate` returns `true`.
+    ///
+    pub fn find_vertices(&self, predicate: impl Fn(&Vertex) -> bool) -> Vec<Arc<Vertex>> {
+        let mut found = vec![];
+        self.for_each_region(&mut |region| {
+            found.extend(
+                region
+                    .vertices
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|vertex| predicate(vertex))
+                    .cloned(),
+            )
+        });
+        found
+    }
+
+    ///
+    /// Every transition of the machine, at any depth and in the order of `for_each_region`, for
+    /// which `predicate` returns `true`.
+    ///
+    pub fn find_transitions(
+        &self,
+        predicate: impl Fn(&Transition) -> bool,
+    ) -> Vec<Arc<Transition>> {
+        let mut found = vec![];
+        self.for_each_region(&mut |region| {
+            found.extend(
+                region
+                    .transitions
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|transition| predicate(transition))
+                    .cloned(),
+            )
+        });
+        found
+    }
+
+    ///
+    /// The transitions from `source`, in the order of `for_each_region`, using the index built by
+    /// `index_references` where there is one.
+    ///
+    pub fn transitions_from(&self, source: &ID) -> Vec<Arc<Transition>> {
+        if let Some(index) = &*self.ref_transitions.read().unwrap() {
+            return upgrade_all(index.from.get(source));
+        }
+        self.find_transitions(|transition| &transition.source == source)
+    }
+
+    ///
+    /// The transitions to `target`, in the order of `for_each_region`, as for `transitions_from`.
+    ///
+    pub fn transitions_to(&self, target: &ID) -> Vec<Arc<Transition>> {
+        if let Some(index) = &*self.ref_transitions.read().unwrap() {
+            return upgrade_all(index.to.get(target));
+        }
+        self.find_transitions(|transition| &transition.target == target)
+    }
+
+    ///
+    /// The transitions from `source` that an occurrence of `event` would trigger, with the same
+    /// priority as `Region::transitions_triggered_by` but across all regions of the machine. Once
+    /// indexed only the transitions with a trigger for the event, or a wildcard, are considered.
+    ///
+    pub fn transitions_triggered_by(&self, source: &ID, event: &Event) -> Vec<Arc<Transition>> {
+        let indexed = self.ref_transitions.read().unwrap().as_ref().map(|index| {
+            (
+                upgrade_all(index.triggered.get(&(source.clone(), event.key()))),
+                upgrade_all(index.triggered.get(&(source.clone(), EventKey::AnyReceive))),
+            )
+        });
+        let (named, wildcard) = indexed.unwrap_or_else(|| {
+            let from_source = self.transitions_from(source);
+            (from_source.clone(), from_source)
+        });
+        triggered_by(named, wildcard, event, |transition| {
+            Some(transition.clone())
+        })
+    }
+
+    ///
+    /// Call `f` with every region of the machine, including those nested within states, parents
+    /// before their children.
+    ///
+    pub fn for_each_region(&self, f: &mut dyn FnMut(&Region)) {
+        fn visit(region: &Region, f: &mut dyn FnMut(&Region)) {
+            f(region);
+            for vertex in region.vertices() {
+                if let Some(state) = vertex.as_state() {
+                    for region in state.regions() {
+                        visit(region, f);
+                    }
+                }
+            }
+        }
+        for region in self.regions() {
+            visit(region, f);
+        }
     }
 
+    ///
+    /// Index the vertices and transitions of the machine, for `find_vertex`, `transitions_from`,
+    /// `transitions_to`, and `transitions_triggered_by`. The changes made by the methods of the
+    /// machine keep the index current, transitions added directly to a region after this are
+    /// not indexed until it is called again.
+    ///
     pub fn index_references(&self) {
         let regions = self.regions();
         for region in regions {
             self.add_reference_to_region(region);
         }
+        self.index_transitions();
     }
 
-    #[allow(dead_code)]
-    fn add_reference_to_machine(&self, machine: Rc<StateMachine>) {
+    ///
+    /// Allow sub-machine states in this machine to resolve `machine`. Only a weak reference is
+    /// held, the caller remains responsible for keeping `machine` alive.
+    ///
+    pub fn add_machine_reference(&self, machine: &Arc<StateMachine>) {
         let _ = self
             .ref_machines
-            .borrow_mut()
-            .insert(machine.id.clone(), machine.clone());
+            .write()
+            .unwrap()
+            .insert(machine.id.clone(), Arc::downgrade(machine));
         for region in &machine.regions {
             self.add_reference_to_region(region);
         }
     }
 
+    fn index_transitions(&self) {
+        let mut index = TransitionIndex::default();
+        self.for_each_region(&mut |region| {
+            for transition in region.transitions.read().unwrap().iter() {
+                let weak = Arc::downgrade(transition);
+                let source = transition.source.clone();
+                index
+                    .from
+                    .entry(source.clone())
+                    .or_default()
+                    .push(weak.clone());
+                index
+                    .to
+                    .entry(transition.target.clone())
+                    .or_default()
+                    .push(weak.clone());
+                let mut keys: Vec<EventKey> = vec![];
+                for trigger in transition.triggers() {
+                    let key = trigger.event().key();
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+                for key in keys {
+                    index
+                        .triggered
+                        .entry((source.clone(), key))
+                        .or_default()
+                        .push(weak.clone());
+                }
+            }
+        });
+        *self.ref_transitions.write().unwrap() = Some(index);
+    }
+
+    fn reindex_transitions(&self) {
+        if self.ref_transitions.read().unwrap().is_some() {
+            self.index_transitions();
+        }
+    }
+
     fn add_reference_to_region(&self, region: &Region) {
         for vertex in region.vertices() {
             self.add_reference_to_vertex(region.id(), vertex);
         }
     }
 
-    fn add_reference_to_vertex(&self, container: &ID, vertex: Rc<Vertex>) {
-        let _ = self
-            .ref_vertices
-            .borrow_mut()
-            .insert((container.clone(), vertex.id().clone()), vertex.clone());
+    fn add_reference_to_vertex(&self, container: &ID, vertex: Arc<Vertex>) {
+        let _ = self.ref_vertices.write().unwrap().insert(
+            (container.clone(), vertex.id().clone()),
+            Arc::downgrade(&vertex),
+        );
         if vertex.is_state() {
             let state = vertex.as_state().unwrap();
             for region in &state.regions {
@@ -537,6 +1004,438 @@ impl StateMachine {
     }
 }
 
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachine {
+    ///
+    /// Remove `vertex`, with all of the regions, vertices, and transitions within it, failing
+    /// where a transition elsewhere in the machine still refers to any of them.
+    ///
+    pub fn remove_vertex(&self, vertex: &ID) -> Result<Arc<Vertex>> {
+        let (container, removing) = self.locate_vertex(vertex)?;
+        let mut within_vertices = vec![vertex.clone()];
+        let mut within_regions = vec![];
+        vertices_within(&removing, &mut within_vertices, &mut within_regions);
+        let mut referenced = false;
+        self.for_each_region(&mut |region| {
+            if !within_regions.contains(region.id()) {
+                referenced |= region.transitions.read().unwrap().iter().any(|transition| {
+                    within_vertices.contains(&transition.source)
+                        || within_vertices.contains(&transition.target)
+                });
+            }
+        });
+        if referenced {
+            return Err(ErrorKind::ModelVertexReferenced(vertex.to_string()).into());
+        }
+        self.region_handle(&container)?
+            .vertices
+            .write()
+            .unwrap()
+            .retain(|existing| existing.id() != vertex);
+        let _ = self
+            .ref_vertices
+            .write()
+            .unwrap()
+            .remove(&(container, vertex.clone()));
+        self.reindex_transitions();
+        Ok(removing)
+    }
+
+    ///
+    /// Set the label of `vertex`, failing where it is shared, as with an executing instance.
+    ///
+    pub fn rename_vertex(&self, vertex: &ID, label: &str) -> Result<()> {
+        let (container, _) = self.locate_vertex(vertex)?;
+        let region = self.region_handle(&container)?;
+        let mut renaming = take_vertex(&region, vertex)?;
+        match &mut renaming {
+            Vertex::State(state) => state.set_label(label),
+            Vertex::PseudoState(pseudo_state) => pseudo_state.set_label(label),
+            Vertex::ConnectionPointReference(cpr) => cpr.set_label(label),
+        }
+        self.replace_vertex(&region, &container, renaming);
+        Ok(())
+    }
+
+    ///
+    /// Move `vertex`, with everything within it, to the region `to_region`. Transitions remain in
+    /// the regions that contain them. This fails where `vertex` is shared, as for
+    /// `rename_vertex`.
+    ///
+    pub fn move_vertex(&self, vertex: &ID, to_region: &ID) -> Result<()> {
+        let (container, moving) = self.locate_vertex(vertex)?;
+        let target = self.region_handle(to_region)?;
+        let mut within_regions = vec![];
+        vertices_within(&moving, &mut vec![], &mut within_regions);
+        if within_regions.contains(to_region) {
+            return Err(ErrorKind::ModelInvalidMove(vertex.to_string()).into());
+        }
+        drop(moving);
+        let mut moving = take_vertex(&self.region_handle(&container)?, vertex)?;
+        match &mut moving {
+            Vertex::State(state) => state.set_container(to_region.clone()),
+            Vertex::PseudoState(pseudo_state) => pseudo_state.set_container(to_region.clone()),
+            Vertex::ConnectionPointReference(cpr) => cpr.set_container(to_region.clone()),
+        }
+        let indexed = self
+            .ref_vertices
+            .write()
+            .unwrap()
+            .remove(&(container, vertex.clone()))
+            .is_some();
+        let moved = Arc::new(moving);
+        target.vertices.write().unwrap().push(moved.clone());
+        if indexed {
+            self.add_reference_to_vertex(to_region, moved);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Remove the transition at `index` in the region `region`, in the order of
+    /// `Region::transitions`.
+    ///
+    pub fn remove_transition(&self, region: &ID, index: usize) -> Result<Arc<Transition>> {
+        let region = self.region_handle(region)?;
+        let mut transitions = region.transitions.write().unwrap();
+        if index < transitions.len() {
+            let removed = transitions.remove(index);
+            drop(transitions);
+            self.reindex_transitions();
+            Ok(removed)
+        } else {
+            Err(ErrorKind::ModelUnknownElement(format!("{}[{}]", region.id, index)).into())
+        }
+    }
+
+    ///
+    /// Change the target of the transition at `index` in the region `region` to `target`, which
+    /// may be any vertex in the machine. This fails where the transition is shared, as for
+    /// `rename_vertex`.
+    ///
+    pub fn retarget_transition(&self, region: &ID, index: usize, target: &ID) -> Result<()> {
+        let _ = self.locate_vertex(target)?;
+        self.change_transition(region, index, |transition| {
+            transition.target = target.clone()
+        })
+    }
+
+    ///
+    /// Set the target of the initial transition of `region` to `target`, a vertex within it,
+    /// adding an initial pseudostate and its transition where the region has none.
+    ///
+    pub fn set_initial(&self, region: &ID, target: &ID) -> Result<()> {
+        let handle = self.region_handle(region)?;
+        let vertices = handle.vertices();
+        if !vertices.iter().any(|vertex| vertex.id() == target) {
+            return Err(ErrorKind::ModelUnknownElement(target.to_string()).into());
+        }
+        let initial = vertices
+            .iter()
+            .find(|vertex| {
+                vertex
+                    .as_pseudo_state()
+                    .is_some_and(|pseudo_state| pseudo_state.is_initial())
+            })
+            .map(|vertex| vertex.id().clone());
+        drop(vertices);
+        let initial = match initial {
+            None => handle.new_initial_state(),
+            Some(initial) => initial,
+        };
+        let index = handle
+            .transitions
+            .read()
+            .unwrap()
+            .iter()
+            .position(|transition| transition.source == initial);
+        match index {
+            None => {
+                handle.new_transition(initial, target.clone());
+                self.reindex_transitions();
+                Ok(())
+            }
+            Some(index) => self.retarget_transition(region, index, target),
+        }
+    }
+
+    fn change_transition(
+        &self,
+        region: &ID,
+        index: usize,
+        change: impl FnOnce(&mut Transition),
+    ) -> Result<()> {
+        let region = self.region_handle(region)?;
+        let mut transitions = region.transitions.write().unwrap();
+        if index >= transitions.len() {
+            return Err(ErrorKind::ModelUnknownElement(format!("{}[{}]", region.id, index)).into());
+        }
+        match Arc::try_unwrap(transitions.remove(index)) {
+            Ok(mut transition) => {
+                change(&mut transition);
+                transitions.insert(index, Arc::new(transition));
+                drop(transitions);
+                self.reindex_transitions();
+                Ok(())
+            }
+            Err(shared) => {
+                transitions.insert(index, shared);
+                Err(ErrorKind::ModelElementShared(format!("{}[{}]", region.id, index)).into())
+            }
+        }
+    }
+
+    ///
+    /// The vertex `vertex`, and the ID of the region that contains it.
+    ///
+    fn locate_vertex(&self, vertex: &ID) -> Result<(ID, Arc<Vertex>)> {
+        let mut found = None;
+        self.for_each_region(&mut |region| {
+            if found.is_none() {
+                found = region
+                    .vertices
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|existing| existing.id() == vertex)
+                    .map(|existing| (region.id().clone(), existing.clone()));
+            }
+        });
+        found.ok_or_else(|| ErrorKind::ModelUnknownElement(vertex.to_string()).into())
+    }
+
+    ///
+    /// A region sharing its vertices and transitions with the region `region` in the machine.
+    ///
+    fn region_handle(&self, region: &ID) -> Result<Region> {
+        let mut found = None;
+        self.for_each_region(&mut |existing| {
+            if existing.id() == region {
+                found = Some(Region {
+                    id: existing.id.clone(),
+                    label: existing.label.clone(),
+                    container: existing.container.clone(),
+                    container_type: existing.container_type,
+                    extended: existing.extended.clone(),
+                    metadata: existing.metadata.clone(),
+                    vertices: existing.vertices.clone(),
+                    transitions: existing.transitions.clone(),
+                });
+            }
+        });
+        found.ok_or_else(|| ErrorKind::ModelUnknownElement(region.to_string()).into())
+    }
+
+    fn replace_vertex(&self, region: &Region, container: &ID, vertex: Vertex) {
+        let key = (container.clone(), vertex.id().clone());
+        let vertex = Arc::new(vertex);
+        let mut vertices = region.vertices.write().unwrap();
+        if let Some(index) = vertices
+            .iter()
+            .position(|existing| existing.id() == vertex.id())
+        {
+            vertices[index] = vertex.clone();
+        } else {
+            vertices.push(vertex.clone());
+        }
+        drop(vertices);
+        if self.ref_vertices.read().unwrap().contains_key(&key) {
+            self.add_reference_to_vertex(container, vertex);
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachine {
+    ///
+    /// A deep copy of this machine, in the same namespace, where the machine and each of its
+    /// regions and vertices has a new ID, and every reference between them refers to the copy.
+    /// References to other machines, and the IDs of invokes, are unchanged. This fails where a
+    /// behavior, or constraint, cannot be copied, see `Behavior::duplicate`.
+    ///
+    pub fn duplicate(&self) -> Result<StateMachine> {
+        self.duplicate_as(self.new_copy(), false)
+    }
+
+    ///
+    /// A deep copy of this machine, as for `duplicate`, with the new IDs in `namespace`.
+    ///
+    pub fn duplicate_in_namespace(&self, namespace: &ID) -> Result<StateMachine> {
+        self.duplicate_as(StateMachine::in_namespace(namespace), false)
+    }
+
+    fn new_copy(&self) -> StateMachine {
+        match self.namespace() {
+            None => StateMachine::default(),
+            Some(namespace) => StateMachine::in_namespace(&namespace),
+        }
+    }
+
+    fn duplicate_as(&self, mut copy: StateMachine, extending: bool) -> Result<StateMachine> {
+        let mut ids: HashMap<ID, ID> = Default::default();
+        let _ = ids.insert(self.id.clone(), copy.id.clone());
+        for region in &self.regions {
+            new_ids_within(region, &copy.id, &mut ids);
+        }
+        for connection_point in &self.connection_points {
+            let _ = ids.insert(
+                connection_point.id.clone(),
+                new_element_id(&copy.id, "pseudo_state"),
+            );
+        }
+        let copied = Copied { ids, extending };
+
+        copy.label = self.label.clone();
+        copy.metadata = copy_metadata(&self.metadata);
+        copy.extended = if extending {
+            Some(self.id.clone())
+        } else {
+            self.extended.clone()
+        };
+        copy.regions = self
+            .regions
+            .iter()
+            .map(|region| copied.region(region))
+            .collect::<Result<Vec<Region>>>()?;
+        copy.sub_machine_states = copied.ids(&self.sub_machine_states);
+        copy.connection_points = self
+            .connection_points
+            .iter()
+            .map(|connection_point| copied.pseudo_state(connection_point))
+            .collect();
+        *copy.ref_machines.write().unwrap() = self.ref_machines.read().unwrap().clone();
+        if !self.ref_vertices.read().unwrap().is_empty() {
+            copy.index_references();
+            for machine in copy.ref_machines.read().unwrap().values() {
+                if let Some(machine) = machine.upgrade() {
+                    for region in &machine.regions {
+                        copy.add_reference_to_region(region);
+                    }
+                }
+            }
+        }
+        Ok(copy)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachine {
+    ///
+    /// A specialization of this machine, a copy as for `duplicate` that extends it, where each
+    /// region extends, and each vertex and transition redefines, the element it was copied from.
+    /// Regions, vertices, and transitions may be added to the copy, and those copied redefined
+    /// with `redefine_state` and `redefine_transition`; `validate_extension` checks that the
+    /// result is still a specialization of this machine.
+    ///
+    pub fn extend(&self) -> Result<StateMachine> {
+        self.duplicate_as(self.new_copy(), true)
+    }
+
+    ///
+    /// A specialization of this machine, as for `extend`, with the new IDs in `namespace`.
+    ///
+    pub fn extend_in_namespace(&self, namespace: &ID) -> Result<StateMachine> {
+        self.duplicate_as(StateMachine::in_namespace(namespace), true)
+    }
+
+    ///
+    /// The ID of the machine this machine extends, if any.
+    ///
+    pub fn extended_machine(&self) -> &Option<ID> {
+        &self.extended
+    }
+
+    ///
+    /// Change the state `state` in place, for example to add regions or replace its behaviors,
+    /// failing where it is shared, as for `rename_vertex`. The state keeps its ID, container, and
+    /// the state it redefines.
+    ///
+    pub fn redefine_state(&self, state: &ID, redefine: impl FnOnce(&mut State)) -> Result<()> {
+        let (container, existing) = self.locate_vertex(state)?;
+        if !existing.is_state() {
+            return Err(ErrorKind::ModelRedefinition(
+                state.to_string(),
+                "only states may be redefined in place".to_string(),
+            )
+            .into());
+        }
+        drop(existing);
+        let region = self.region_handle(&container)?;
+        let mut vertices = region.vertices.write().unwrap();
+        let index = vertices
+            .iter()
+            .position(|existing| existing.id() == state)
+            .unwrap();
+        let mut redefining = match Arc::try_unwrap(vertices.remove(index)) {
+            Ok(Vertex::State(redefining)) => redefining,
+            Ok(_) => unreachable!(),
+            Err(shared) => {
+                vertices.insert(index, shared);
+                return Err(ErrorKind::ModelElementShared(state.to_string()).into());
+            }
+        };
+        let (id, redefined) = (redefining.id.clone(), redefining.redefined.clone());
+        redefine(&mut redefining);
+        redefining.id = id;
+        redefining.container = container.clone();
+        redefining.redefined = redefined;
+        let redefining = Arc::new(Vertex::State(redefining));
+        vertices.insert(index, redefining.clone());
+        drop(vertices);
+        if self
+            .ref_vertices
+            .read()
+            .unwrap()
+            .contains_key(&(container.clone(), state.clone()))
+        {
+            self.add_reference_to_vertex(&container, redefining);
+        }
+        self.reindex_transitions();
+        Ok(())
+    }
+
+    ///
+    /// Change the transition at `index` in the region `region` in place, for example to replace
+    /// its target, guard, or effect, failing where it is shared, as for `rename_vertex`. The
+    /// transition keeps its container and the transition it redefines.
+    ///
+    pub fn redefine_transition(
+        &self,
+        region: &ID,
+        index: usize,
+        redefine: impl FnOnce(&mut Transition),
+    ) -> Result<()> {
+        self.change_transition(region, index, |transition| {
+            let (container, redefined) =
+                (transition.container.clone(), transition.redefined.clone());
+            redefine(transition);
+            transition.container = container;
+            transition.redefined = redefined;
+        })
+    }
+
+    ///
+    /// Check that this machine is a valid specialization of `base`, see
+    /// `is_redefinition_context_valid`, failing with the first redefinition that is not.
+    ///
+    pub fn validate_extension(&self, base: &StateMachine) -> Result<()> {
+        crate::definition::redefinition::validate_extension(self, base)
+    }
+
+    ///
+    /// Returns `true` if this machine extends `base`, each of its regions, vertices, and
+    /// transitions is extended, or redefined, exactly once within the matching context of this
+    /// machine, and each redefining transition keeps the source and triggers of the transition it
+    /// redefines; elements of `base` may be specialized, but not removed.
+    ///
+    pub fn is_redefinition_context_valid(&self, base: &StateMachine) -> bool {
+        self.validate_extension(base).is_ok()
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Implementations - Transition
 // ------------------------------------------------------------------------------------------------
@@ -545,6 +1444,8 @@ make_labeled_impl!(Transition);
 
 make_contained_impl!(Transition);
 
+make_has_metadata_impl!(Transition);
+
 impl Transition {
     pub fn within(source: ID, target: ID, container: ID) -> Self {
         Self {
@@ -556,6 +1457,8 @@ impl Transition {
             triggers: vec![],
             guard: None,
             effect: None,
+            redefined: None,
+            metadata: Default::default(),
         }
     }
 
@@ -575,6 +1478,37 @@ impl Transition {
         self.triggers.iter()
     }
 
+    pub fn add_trigger(&mut self, trigger: Trigger) {
+        self.triggers.push(trigger)
+    }
+
+    ///
+    /// Returns `true` if any of this transition's triggers match the event.
+    ///
+    pub fn is_triggered_by(&self, event: &Event) -> bool {
+        self.triggers.iter().any(|trigger| trigger.matches(event))
+    }
+
+    ///
+    /// Returns `true` if any of this transition's triggers name the event, ignoring any
+    /// `AnyReceive` wildcard triggers.
+    ///
+    pub fn is_explicitly_triggered_by(&self, event: &Event) -> bool {
+        self.triggers
+            .iter()
+            .any(|trigger| trigger.matches_explicitly(event))
+    }
+
+    ///
+    /// Returns `true` if any of this transition's triggers that match the event also accept
+    /// `payload`, see `Trigger::matches_payload`.
+    ///
+    pub fn is_triggered_with(&self, event: &Event, payload: &Context) -> bool {
+        self.triggers
+            .iter()
+            .any(|trigger| trigger.matches(event) && trigger.matches_payload(payload))
+    }
+
     pub fn has_guard(&self) -> bool {
         self.guard.is_some()
     }
@@ -583,6 +1517,10 @@ impl Transition {
         &self.guard
     }
 
+    pub fn set_guard(&mut self, guard: Box<dyn Constraint>) {
+        self.guard = Some(guard)
+    }
+
     pub fn has_effect(&self) -> bool {
         self.effect.is_some()
     }
@@ -591,29 +1529,36 @@ impl Transition {
         &self.effect
     }
 
+    pub fn set_effect(&mut self, effect: Box<dyn Behavior>) {
+        self.effect = Some(effect)
+    }
+
+    ///
+    /// The region, and index within it, of the transition in the machine this machine extends
+    /// that this transition redefines.
+    ///
+    pub fn redefined_transition(&self) -> &Option<(ID, usize)> {
+        &self.redefined
+    }
+
     pub fn kind(&self) -> TransitionKind {
         self.kind.clone()
     }
 
+    pub fn set_kind(&mut self, kind: TransitionKind) {
+        self.kind = kind
+    }
+
     pub fn is_internal(&self) -> bool {
-        match self.kind {
-            TransitionKind::Internal => true,
-            _ => false,
-        }
+        matches!(self.kind, TransitionKind::Internal)
     }
 
     pub fn is_local(&self) -> bool {
-        match self.kind {
-            TransitionKind::Local => true,
-            _ => false,
-        }
+        matches!(self.kind, TransitionKind::Local)
     }
 
     pub fn is_external(&self) -> bool {
-        match self.kind {
-            TransitionKind::External => true,
-            _ => false,
-        }
+        matches!(self.kind, TransitionKind::External)
     }
 }
 
@@ -621,56 +1566,622 @@ impl Transition {
 // Implementations - Trigger
 // ------------------------------------------------------------------------------------------------
 
-impl Default for Trigger {
-    fn default() -> Self {
-        Self { event: None }
+impl Validate for Trigger {
+    fn validate(&self) -> Result<()> {
+        match &self.event {
+            Event::Signal(event) if event.signal.is_empty() => {
+                Err(ErrorKind::TriggerEventName.into())
+            }
+            Event::Call(event) if event.operation.is_empty() => {
+                Err(ErrorKind::TriggerEventName.into())
+            }
+            _ => {
+                for predicate in &self.predicates {
+                    predicate.validate()?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
-impl Trigger {
-    pub fn with_event(event: Box<dyn Event>) -> Self {
-        Self { event: Some(event) }
-    }
-
-    pub fn event(&self) -> &Option<Box<dyn Event>> {
-        &self.event
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.event)?;
+        if !self.predicates.is_empty() {
+            write!(
+                f,
+                "[{}]",
+                self.predicates
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" && ")
+            )?;
+        }
+        Ok(())
     }
 }
 
-// ------------------------------------------------------------------------------------------------
-// Implementations - Vertex
-// ------------------------------------------------------------------------------------------------
-
-impl Vertex {
-    pub fn id(&self) -> &ID {
-        match self {
-            Vertex::State(state) => state.id(),
-            Vertex::PseudoState(pseudo_state) => pseudo_state.id(),
-            Vertex::ConnectionPointReference(cpr) => cpr.id(),
+impl Trigger {
+    pub fn with_event(event: Event) -> Self {
+        Self {
+            event,
+            predicates: vec![],
+            payload: None,
         }
     }
 
-    pub fn is_state(&self) -> bool {
-        match self {
-            Vertex::State(_) => true,
-            _ => false,
+    ///
+    /// This trigger as fired by an occurrence of its event carrying `payload`.
+    ///
+    pub fn with_payload(self, payload: Context) -> Self {
+        Self {
+            payload: Some(payload),
+            ..self
         }
     }
 
-    pub fn as_state(&self) -> Option<&State> {
-        match self {
-            Vertex::State(inner) => Some(inner),
+    pub fn signal(signal: &str) -> Self {
+        Self::with_event(Event::Signal(SignalEvent::new(signal)))
+    }
+
+    pub fn call(operation: &str) -> Self {
+        Self::with_event(Event::Call(CallEvent::new(operation)))
+    }
+
+    pub fn after(duration: Duration) -> Self {
+        Self::with_event(Event::Time(TimeEvent::Relative(duration)))
+    }
+
+    pub fn at(time: SystemTime) -> Self {
+        Self::with_event(Event::Time(TimeEvent::Absolute(time)))
+    }
+
+    pub fn when(change_expression: Arc<dyn Constraint>) -> Self {
+        Self::with_event(Event::Change(ChangeEvent::new(change_expression)))
+    }
+
+    pub fn any() -> Self {
+        Self::with_event(Event::AnyReceive)
+    }
+
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    ///
+    /// Returns `true` if an occurrence of `event` would cause this trigger to fire. An
+    /// `AnyReceive` trigger matches any signal or call event.
+    ///
+    pub fn matches(&self, event: &Event) -> bool {
+        self.matches_explicitly(event) || (self.is_any_receive() && event.is_message())
+    }
+
+    ///
+    /// Returns `true` if this trigger names `event` itself, rather than matching it as a
+    /// wildcard.
+    ///
+    pub fn matches_explicitly(&self, event: &Event) -> bool {
+        !self.is_any_receive() && &self.event == event
+    }
+
+    pub fn is_any_receive(&self) -> bool {
+        self.event.is_any_receive()
+    }
+
+    ///
+    /// The payload of the event occurrence that fired this trigger, where it had one; guards and
+    /// behaviors performed by the executor are given the trigger that fired.
+    ///
+    pub fn payload(&self) -> Option<&Context> {
+        self.payload.as_ref()
+    }
+
+    pub fn payload_predicates(&self) -> Iter<'_, PayloadPredicate> {
+        self.predicates.iter()
+    }
+
+    pub fn add_payload_predicate(&mut self, predicate: PayloadPredicate) {
+        self.predicates.push(predicate)
+    }
+
+    ///
+    /// Returns `true` if `payload` conforms to the payload schema of a signal event, see
+    /// `SignalEvent::accepts`, and all of this trigger's payload predicates hold for it; a trigger
+    /// with no schema or predicates accepts any payload.
+    ///
+    pub fn matches_payload(&self, payload: &Context) -> bool {
+        self.event
+            .as_signal()
+            .is_none_or(|signal| signal.accepts(payload))
+            && self
+                .predicates
+                .iter()
+                .all(|predicate| predicate.evaluate(payload))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - PayloadPredicate
+// ------------------------------------------------------------------------------------------------
+
+impl Display for ComparisonOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ComparisonOperator::Equal => "==",
+                ComparisonOperator::NotEqual => "!=",
+                ComparisonOperator::Less => "<",
+                ComparisonOperator::LessOrEqual => "<=",
+                ComparisonOperator::Greater => ">",
+                ComparisonOperator::GreaterOrEqual => ">=",
+            }
+        )
+    }
+}
+
+impl ComparisonOperator {
+    pub fn is_ordering(&self) -> bool {
+        !matches!(
+            self,
+            ComparisonOperator::Equal | ComparisonOperator::NotEqual
+        )
+    }
+}
+
+impl Display for PayloadPredicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.path, self.operator, self.value)
+    }
+}
+
+impl Validate for PayloadPredicate {
+    fn validate(&self) -> Result<()> {
+        let orderable = matches!(
+            self.value,
+            FieldValue::Byte(_)
+                | FieldValue::Integer(_)
+                | FieldValue::Float(_)
+                | FieldValue::String(_)
+        );
+        if self.operator.is_ordering() && !orderable {
+            Err(ErrorKind::TriggerPayloadPredicate.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl PayloadPredicate {
+    pub fn new(path: FieldPath, operator: ComparisonOperator, value: FieldValue) -> Self {
+        Self {
+            path,
+            operator,
+            value,
+        }
+    }
+
+    pub fn path(&self) -> &FieldPath {
+        &self.path
+    }
+
+    pub fn operator(&self) -> &ComparisonOperator {
+        &self.operator
+    }
+
+    pub fn value(&self) -> &FieldValue {
+        &self.value
+    }
+
+    ///
+    /// Evaluate the predicate against `payload`, a missing field never satisfies a predicate.
+    /// Numeric values of different types are compared as floating point numbers.
+    ///
+    pub fn evaluate(&self, payload: &Context) -> bool {
+        match payload.get(self.path.clone()) {
+            None => false,
+            Some(actual) => {
+                let ordering = compare_field_values(&actual, &self.value);
+                match self.operator {
+                    ComparisonOperator::Equal => {
+                        ordering == Some(Ordering::Equal) || actual == self.value
+                    }
+                    ComparisonOperator::NotEqual => {
+                        !(ordering == Some(Ordering::Equal) || actual == self.value)
+                    }
+                    ComparisonOperator::Less => ordering == Some(Ordering::Less),
+                    ComparisonOperator::LessOrEqual => {
+                        matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal))
+                    }
+                    ComparisonOperator::Greater => ordering == Some(Ordering::Greater),
+                    ComparisonOperator::GreaterOrEqual => {
+                        matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal))
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - Event
+// ------------------------------------------------------------------------------------------------
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Signal(event) => write!(f, "{}", event.signal),
+            Event::Call(event) => write!(f, "{}()", event.operation),
+            Event::Time(TimeEvent::Relative(duration)) => write!(f, "after({:?})", duration),
+            Event::Time(TimeEvent::Absolute(time)) => write!(
+                f,
+                "at({})",
+                time.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            ),
+            Event::Change(event) => match event.change_expression.label() {
+                None => write!(f, "when()"),
+                Some(label) => write!(f, "when({})", label),
+            },
+            Event::AnyReceive => write!(f, "*"),
+        }
+    }
+}
+
+impl Event {
+    pub fn is_signal(&self) -> bool {
+        matches!(self, Event::Signal(_))
+    }
+
+    pub fn as_signal(&self) -> Option<&SignalEvent> {
+        match self {
+            Event::Signal(inner) => Some(inner),
             _ => None,
         }
     }
 
-    pub fn is_pseudo_state(&self) -> bool {
+    pub fn is_call(&self) -> bool {
+        matches!(self, Event::Call(_))
+    }
+
+    pub fn as_call(&self) -> Option<&CallEvent> {
+        match self {
+            Event::Call(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn is_time(&self) -> bool {
+        matches!(self, Event::Time(_))
+    }
+
+    pub fn as_time(&self) -> Option<&TimeEvent> {
+        match self {
+            Event::Time(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn is_change(&self) -> bool {
+        matches!(self, Event::Change(_))
+    }
+
+    pub fn as_change(&self) -> Option<&ChangeEvent> {
+        match self {
+            Event::Change(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn is_any_receive(&self) -> bool {
+        matches!(self, Event::AnyReceive)
+    }
+
+    ///
+    /// Returns `true` for events that are delivered to an instance from outside, signals and
+    /// calls, as opposed to those generated by the passage of time or a change in context.
+    ///
+    pub fn is_message(&self) -> bool {
+        matches!(self, Event::Signal(_) | Event::Call(_))
+    }
+
+    pub(crate) fn key(&self) -> EventKey {
+        match self {
+            Event::Signal(inner) => EventKey::Signal(inner.signal.clone()),
+            Event::Call(inner) => EventKey::Call(inner.operation.clone()),
+            Event::Time(_) => EventKey::Time,
+            Event::Change(_) => EventKey::Change,
+            Event::AnyReceive => EventKey::AnyReceive,
+        }
+    }
+}
+
+impl PartialEq for SignalEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.signal == other.signal
+    }
+}
+
+impl Eq for SignalEvent {}
+
+impl Hash for SignalEvent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.signal.hash(state)
+    }
+}
+
+impl SignalEvent {
+    pub fn new(signal: &str) -> Self {
+        Self {
+            signal: signal.to_string(),
+            payload: Default::default(),
+        }
+    }
+
+    ///
+    /// Add the field at `path`, holding a value of `kind`, to the payload schema of this signal.
+    ///
+    pub fn with_field(mut self, path: FieldPath, kind: FieldKind) -> Self {
+        self.payload.push((path, kind));
+        self
+    }
+
+    pub fn signal(&self) -> &String {
+        &self.signal
+    }
+
+    pub fn has_payload_schema(&self) -> bool {
+        !self.payload.is_empty()
+    }
+
+    pub fn payload_schema(&self) -> Iter<'_, (FieldPath, FieldKind)> {
+        self.payload.iter()
+    }
+
+    ///
+    /// Returns `true` if `payload` has every field of this signal's payload schema, each holding
+    /// a value of the declared kind.
+    ///
+    pub fn accepts(&self, payload: &Context) -> bool {
+        self.payload.iter().all(|(path, kind)| {
+            payload
+                .get(path.clone())
+                .is_some_and(|value| value.kind() == *kind)
+        })
+    }
+}
+
+impl CallEvent {
+    pub fn new(operation: &str) -> Self {
+        Self {
+            operation: operation.to_string(),
+        }
+    }
+
+    pub fn operation(&self) -> &String {
+        &self.operation
+    }
+}
+
+impl TimeEvent {
+    pub fn is_relative(&self) -> bool {
+        matches!(self, TimeEvent::Relative(_))
+    }
+}
+
+impl Debug for ChangeEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChangeEvent")
+            .field("change_expression", self.change_expression.label())
+            .finish()
+    }
+}
+
+impl PartialEq for ChangeEvent {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.change_expression, &other.change_expression)
+    }
+}
+
+impl ChangeEvent {
+    pub fn new(change_expression: Arc<dyn Constraint>) -> Self {
+        Self { change_expression }
+    }
+
+    pub fn change_expression(&self) -> &Arc<dyn Constraint> {
+        &self.change_expression
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - EventSink
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Collects the events raised, for behaviors performed outside of an instance.
+///
+impl EventSink for RefCell<Vec<Event>> {
+    fn raise(&self, event: Event) {
+        self.borrow_mut().push(event)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - ValidationReport
+// ------------------------------------------------------------------------------------------------
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        match self.transition {
+            None => write!(f, "{}", self.element)?,
+            Some(index) => write!(f, "transition {} of region {}", index, self.element)?,
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(constraint) = &self.constraint {
+            write!(f, " [{}]", constraint)?;
+        }
+        Ok(())
+    }
+}
+
+impl Violation {
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    ///
+    /// The ID of the offending element; for a transition the ID of the region containing it,
+    /// see `transition`.
+    ///
+    pub fn element(&self) -> &ID {
+        &self.element
+    }
+
+    ///
+    /// For a transition, its index within the region `element`.
+    ///
+    pub fn transition(&self) -> Option<usize> {
+        self.transition
+    }
+
+    ///
+    /// The name of the UML constraint violated, for example `Region::initial_vertex`; rules of
+    /// this model, and warnings, have none.
+    ///
+    pub fn constraint(&self) -> &Option<String> {
+        &self.constraint
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for violation in &self.violations {
+            writeln!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl ValidationReport {
+    pub fn violations(&self) -> Iter<'_, Violation> {
+        self.violations.iter()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Violation> {
+        self.violations
+            .iter()
+            .filter(|violation| violation.is_error())
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Violation> {
+        self.violations
+            .iter()
+            .filter(|violation| !violation.is_error())
+    }
+
+    ///
+    /// Returns `true` if there are no errors, the machine may still have warnings.
+    ///
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.violations.len()
+    }
+
+    ///
+    /// The first error of the report, if any, as `validate` returns it.
+    ///
+    pub fn into_result(self) -> Result<()> {
+        match self
+            .violations
+            .into_iter()
+            .find(|violation| violation.is_error())
+            .and_then(|violation| violation.kind)
+        {
+            None => Ok(()),
+            Some(kind) => Err(kind.into()),
+        }
+    }
+
+    pub(crate) fn push(&mut self, violation: Violation) {
+        self.violations.push(violation)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - Vertex
+// ------------------------------------------------------------------------------------------------
+
+impl Vertex {
+    pub fn id(&self) -> &ID {
+        match self {
+            Vertex::State(state) => state.id(),
+            Vertex::PseudoState(pseudo_state) => pseudo_state.id(),
+            Vertex::ConnectionPointReference(cpr) => cpr.id(),
+        }
+    }
+
+    pub fn label(&self) -> &Option<String> {
+        match self {
+            Vertex::State(state) => state.label(),
+            Vertex::PseudoState(pseudo_state) => pseudo_state.label(),
+            Vertex::ConnectionPointReference(cpr) => cpr.label(),
+        }
+    }
+
+    pub fn redefined_vertex(&self) -> &Option<ID> {
+        match self {
+            Vertex::State(state) => state.redefined_vertex(),
+            Vertex::PseudoState(pseudo_state) => pseudo_state.redefined_vertex(),
+            Vertex::ConnectionPointReference(cpr) => cpr.redefined_vertex(),
+        }
+    }
+
+    pub fn is_state(&self) -> bool {
+        matches!(self, Vertex::State(_))
+    }
+
+    pub fn as_state(&self) -> Option<&State> {
         match self {
-            Vertex::PseudoState(_) => true,
-            _ => false,
+            Vertex::State(inner) => Some(inner),
+            _ => None,
         }
     }
 
+    pub fn is_pseudo_state(&self) -> bool {
+        matches!(self, Vertex::PseudoState(_))
+    }
+
     pub fn as_pseudo_state(&self) -> Option<&PseudoState> {
         match self {
             Vertex::PseudoState(inner) => Some(inner),
@@ -679,10 +2190,7 @@ impl Vertex {
     }
 
     pub fn is_connection_point_reference(&self) -> bool {
-        match self {
-            Vertex::ConnectionPointReference(_) => true,
-            _ => false,
-        }
+        matches!(self, Vertex::ConnectionPointReference(_))
     }
 
     pub fn as_connection_point_reference(&self) -> Option<&ConnectionPointReference> {
@@ -697,10 +2205,280 @@ impl Vertex {
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// The new IDs of the elements of a machine being duplicated, by their original IDs, and whether
+/// the copy extends the original, so that each element redefines the one it was copied from.
+///
+struct Copied {
+    ids: HashMap<ID, ID>,
+    extending: bool,
+}
+
+impl Copied {
+    fn id(&self, id: &ID) -> ID {
+        self.ids.get(id).unwrap_or(id).clone()
+    }
+
+    fn redefined(&self, original: &ID, redefined: &Option<ID>) -> Option<ID> {
+        if self.extending {
+            Some(original.clone())
+        } else {
+            redefined.clone()
+        }
+    }
+
+    fn ids(&self, ids: &[ID]) -> Vec<ID> {
+        ids.iter().map(|id| self.id(id)).collect()
+    }
+
+    fn region(&self, region: &Region) -> Result<Region> {
+        let copy = Region {
+            id: self.id(&region.id),
+            label: region.label.clone(),
+            container: self.id(&region.container),
+            container_type: region.container_type,
+            extended: self.redefined(&region.id, &region.extended),
+            metadata: copy_metadata(&region.metadata),
+            vertices: Default::default(),
+            transitions: Default::default(),
+        };
+        for vertex in region.vertices() {
+            let vertex = match vertex.as_ref() {
+                Vertex::State(state) => Vertex::State(self.state(state)?),
+                Vertex::PseudoState(pseudo_state) => {
+                    Vertex::PseudoState(self.pseudo_state(pseudo_state))
+                }
+                Vertex::ConnectionPointReference(cpr) => {
+                    Vertex::ConnectionPointReference(ConnectionPointReference {
+                        id: self.id(&cpr.id),
+                        label: cpr.label.clone(),
+                        container: self.id(&cpr.container),
+                        entry: self.ids(&cpr.entry),
+                        exit: self.ids(&cpr.exit),
+                        state: cpr.state.as_ref().map(|state| self.id(state)),
+                        redefined: self.redefined(&cpr.id, &cpr.redefined),
+                    })
+                }
+            };
+            copy.add_vertex(vertex);
+        }
+        for (index, transition) in region.transitions().iter().enumerate() {
+            let owner = format!("{} -> {}", transition.source, transition.target);
+            copy.add_transition(Transition {
+                label: transition.label.clone(),
+                container: self.id(&transition.container),
+                kind: transition.kind.clone(),
+                source: self.id(&transition.source),
+                target: self.id(&transition.target),
+                triggers: transition.triggers.clone(),
+                guard: duplicate_constraint(&transition.guard, &owner)?,
+                effect: duplicate_behavior(&transition.effect, &owner)?,
+                redefined: if self.extending {
+                    Some((region.id.clone(), index))
+                } else {
+                    transition.redefined.clone()
+                },
+                metadata: copy_metadata(&transition.metadata),
+            });
+        }
+        Ok(copy)
+    }
+
+    fn state(&self, state: &State) -> Result<State> {
+        let owner = state.id.to_string();
+        Ok(State {
+            id: self.id(&state.id),
+            label: state.label.clone(),
+            comments: state.comments.clone(),
+            container: self.id(&state.container),
+            regions: state
+                .regions
+                .iter()
+                .map(|region| self.region(region))
+                .collect::<Result<Vec<Region>>>()?,
+            sub_machine: state.sub_machine.clone(),
+            connections: self.ids(&state.connections),
+            connection_points: self.ids(&state.connection_points),
+            deferrable_triggers: state.deferrable_triggers.clone(),
+            invariant: duplicate_constraint(&state.invariant, &owner)?,
+            entry: duplicate_behavior(&state.entry, &owner)?,
+            do_activity: duplicate_behavior(&state.do_activity, &owner)?,
+            exit: duplicate_behavior(&state.exit, &owner)?,
+            invokes: state.invokes.clone(),
+            final_state: state.final_state,
+            redefined: self.redefined(&state.id, &state.redefined),
+            metadata: copy_metadata(&state.metadata),
+        })
+    }
+
+    fn pseudo_state(&self, pseudo_state: &PseudoState) -> PseudoState {
+        PseudoState {
+            id: self.id(&pseudo_state.id),
+            label: pseudo_state.label.clone(),
+            container: self.id(&pseudo_state.container),
+            kind: pseudo_state.kind.clone(),
+            redefined: self.redefined(&pseudo_state.id, &pseudo_state.redefined),
+        }
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+///
+/// The transitions of `named` that name `event`, for which `enabled` returns a value, or where
+/// there are none those of `wildcard` that only an `AnyReceive` wildcard triggers, for which it
+/// does; so that a wildcard takes the events that no explicit transition does. This is the
+/// priority of the `transitions_triggered_by` methods, which do not evaluate guards, and of the
+/// executor, for which `enabled` evaluates each transition's payload predicates and guard.
+///
+pub(crate) fn triggered_by<T>(
+    named: impl IntoIterator<Item = Arc<Transition>>,
+    wildcard: impl IntoIterator<Item = Arc<Transition>>,
+    event: &Event,
+    mut enabled: impl FnMut(&Arc<Transition>) -> Option<T>,
+) -> Vec<T> {
+    let explicit: Vec<T> = named
+        .into_iter()
+        .filter(|transition| transition.is_explicitly_triggered_by(event))
+        .filter_map(|transition| enabled(&transition))
+        .collect();
+    if explicit.is_empty() {
+        wildcard
+            .into_iter()
+            .filter(|transition| {
+                !transition.is_explicitly_triggered_by(event) && transition.is_triggered_by(event)
+            })
+            .filter_map(|transition| enabled(&transition))
+            .collect()
+    } else {
+        explicit
+    }
+}
+
+fn upgrade_all(transitions: Option<&Vec<Weak<Transition>>>) -> Vec<Arc<Transition>> {
+    transitions
+        .map(|transitions| transitions.iter().filter_map(Weak::upgrade).collect())
+        .unwrap_or_default()
+}
+
+///
+/// Remove `vertex` from `region`, where the region holds the only reference to it.
+///
+fn take_vertex(region: &Region, vertex: &ID) -> Result<Vertex> {
+    let mut vertices = region.vertices.write().unwrap();
+    let index = vertices
+        .iter()
+        .position(|existing| existing.id() == vertex)
+        .ok_or_else(|| ErrorKind::ModelUnknownElement(vertex.to_string()))?;
+    match Arc::try_unwrap(vertices.remove(index)) {
+        Ok(taken) => Ok(taken),
+        Err(shared) => {
+            vertices.insert(index, shared);
+            Err(ErrorKind::ModelElementShared(vertex.to_string()).into())
+        }
+    }
+}
+
+///
+/// Collect the IDs of the vertices, and regions, nested within `vertex`.
+///
+fn vertices_within(vertex: &Vertex, vertices: &mut Vec<ID>, regions: &mut Vec<ID>) {
+    if let Some(state) = vertex.as_state() {
+        for region in state.regions() {
+            regions.push(region.id().clone());
+            for inner in region.vertices() {
+                vertices.push(inner.id().clone());
+                vertices_within(&inner, vertices, regions);
+            }
+        }
+    }
+}
+
+///
+/// A copy of `metadata` that shares no arrays, or objects, with the original.
+///
+fn copy_metadata(metadata: &HashMap<String, FieldValue>) -> HashMap<String, FieldValue> {
+    metadata
+        .iter()
+        .map(|(key, value)| (key.clone(), value.deep_clone()))
+        .collect()
+}
+
+fn duplicate_behavior(
+    behavior: &Option<Box<dyn Behavior>>,
+    owner: &str,
+) -> Result<Option<Box<dyn Behavior>>> {
+    match behavior {
+        None => Ok(None),
+        Some(behavior) => match behavior.duplicate() {
+            None => Err(ErrorKind::ModelNotDuplicable(owner.to_string()).into()),
+            copy => Ok(copy),
+        },
+    }
+}
+
+fn duplicate_constraint(
+    constraint: &Option<Box<dyn Constraint>>,
+    owner: &str,
+) -> Result<Option<Box<dyn Constraint>>> {
+    match constraint {
+        None => Ok(None),
+        Some(constraint) => match constraint.duplicate() {
+            None => Err(ErrorKind::ModelNotDuplicable(owner.to_string()).into()),
+            copy => Ok(copy),
+        },
+    }
+}
+
+///
+/// Record a new ID, in the namespace of `machine`, for `region` and each vertex within it.
+///
+fn new_ids_within(region: &Region, machine: &ID, ids: &mut HashMap<ID, ID>) {
+    let _ = ids.insert(region.id.clone(), new_element_id(machine, "region"));
+    for vertex in region.vertices() {
+        let kind = match vertex.as_ref() {
+            Vertex::State(_) => "state",
+            Vertex::PseudoState(_) => "pseudo_state",
+            Vertex::ConnectionPointReference(_) => "connection",
+        };
+        let _ = ids.insert(vertex.id().clone(), new_element_id(machine, kind));
+        if let Some(state) = vertex.as_state() {
+            for region in state.regions() {
+                new_ids_within(region, machine, ids);
+            }
+        }
+    }
+}
+
+fn new_element_id(container: &ID, kind: &str) -> ID {
+    match container.namespace() {
+        None => ID::random(),
+        Some(namespace) => ID::random_in_namespace(&namespace, kind).unwrap(),
+    }
+}
+
+pub(crate) fn compare_field_values(lhs: &FieldValue, rhs: &FieldValue) -> Option<Ordering> {
+    fn as_number(value: &FieldValue) -> Option<f64> {
+        match value {
+            FieldValue::Byte(v) => Some(*v as f64),
+            FieldValue::Integer(v) => Some(*v as f64),
+            FieldValue::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+    match (lhs, rhs) {
+        (FieldValue::String(lhs), FieldValue::String(rhs)) => Some(lhs.cmp(rhs)),
+        (FieldValue::Bool(lhs), FieldValue::Bool(rhs)) => Some(lhs.cmp(rhs)),
+        (FieldValue::Integer(lhs), FieldValue::Integer(rhs)) => Some(lhs.cmp(rhs)),
+        _ => match (as_number(lhs), as_number(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs),
+            _ => None,
+        },
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Modules
 // ------------------------------------------------------------------------------------------------
@@ -713,6 +2491,7 @@ impl Vertex {
 mod tests {
     use crate::format::plant_uml::WritePlantUml;
     use crate::format::Stringify;
+    use std::str::FromStr;
 
     use super::*;
 
@@ -734,4 +2513,423 @@ mod tests {
         assert!(string.is_ok());
         println!("{}", string.unwrap());
     }
+
+    #[test]
+    fn test_namespace() {
+        let namespace = ID::from_str("billing").unwrap();
+        let machine = StateMachine::in_namespace(&namespace);
+        assert_eq!(machine.namespace(), Some(namespace.clone()));
+
+        let region: &Region = machine.default_region().unwrap();
+        assert_eq!(region.id().namespace(), Some(namespace.clone()));
+
+        let state_id = region.new_compound_state();
+        assert!(state_id.to_string().starts_with("billing/state::"));
+        machine.index_references();
+        let state = machine.find_vertex(region.id().clone(), state_id).unwrap();
+        let inner = state.as_state().unwrap().default_region().unwrap();
+        assert_eq!(inner.new_initial_state().namespace(), Some(namespace));
+
+        assert_eq!(StateMachine::default().namespace(), None);
+    }
+
+    #[test]
+    fn test_triggers() {
+        let machine: StateMachine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let initial_id = region.new_initial_state();
+        let state_id = region.new_simple_state();
+        let final_id = region.new_final_state();
+
+        region.new_transition(initial_id, state_id.clone());
+        let mut transition = Transition::within(state_id.clone(), final_id, region.id().clone());
+        transition.add_trigger(Trigger::signal("stop"));
+        transition.add_trigger(Trigger::after(Duration::from_secs(5)));
+        region.add_transition(transition);
+
+        assert!(machine.validate().is_ok());
+
+        let transition = region.transitions().last().unwrap().clone();
+        assert!(transition.is_triggered_by(&Event::Signal(SignalEvent::new("stop"))));
+        assert!(transition.is_triggered_by(Trigger::after(Duration::from_secs(5)).event()));
+        assert!(!transition.is_triggered_by(&Event::Signal(SignalEvent::new("go"))));
+        assert!(!transition.is_triggered_by(&Event::Call(CallEvent::new("stop"))));
+
+        let writer = WritePlantUml::default();
+        let string = writer.stringify(&machine).unwrap();
+        assert!(string.contains(" : stop, after(5s)"));
+
+        let mut trigger = Trigger::signal("cancelled");
+        trigger.add_payload_predicate(PayloadPredicate::new(
+            FieldPath::from_str("reason").unwrap(),
+            ComparisonOperator::Equal,
+            "fraud".into(),
+        ));
+        trigger.add_payload_predicate(PayloadPredicate::new(
+            FieldPath::from_str("total").unwrap(),
+            ComparisonOperator::Greater,
+            100i64.into(),
+        ));
+        assert_eq!(
+            trigger.to_string(),
+            "cancelled[reason == \"fraud\" && total > 100]"
+        );
+        let payload = Context::default();
+        assert!(!trigger.matches_payload(&payload));
+        payload.insert(FieldPath::from_str("reason").unwrap(), "fraud".into());
+        payload.insert(FieldPath::from_str("total").unwrap(), 250.5.into());
+        assert!(trigger.matches_payload(&payload));
+        payload.insert(FieldPath::from_str("total").unwrap(), 10i64.into());
+        assert!(!trigger.matches_payload(&payload));
+
+        let signal = SignalEvent::new("cancelled")
+            .with_field(FieldPath::from_str("reason").unwrap(), FieldKind::String);
+        assert_eq!(
+            Event::Signal(signal.clone()),
+            Event::Signal(SignalEvent::new("cancelled"))
+        );
+        let trigger = Trigger::with_event(Event::Signal(signal));
+        assert!(trigger.matches_payload(&payload));
+        payload.insert(FieldPath::from_str("reason").unwrap(), 1i64.into());
+        assert!(!trigger.matches_payload(&payload));
+        assert!(!trigger.matches_payload(&Context::default()));
+
+        let mut transition =
+            Transition::within(state_id.clone(), state_id.clone(), region.id().clone());
+        transition.add_trigger(Trigger::any());
+        region.add_transition(transition);
+
+        let stop = Event::Signal(SignalEvent::new("stop"));
+        let go = Event::Signal(SignalEvent::new("go"));
+        let triggered = region.transitions_triggered_by(&state_id, &stop);
+        assert_eq!(triggered.len(), 1);
+        assert!(!triggered[0].triggers().any(|t| t.is_any_receive()));
+        let triggered = region.transitions_triggered_by(&state_id, &go);
+        assert_eq!(triggered.len(), 1);
+        assert!(triggered[0].triggers().all(|t| t.is_any_receive()));
+        assert!(region
+            .transitions_triggered_by(&state_id, Trigger::after(Duration::from_secs(1)).event())
+            .is_empty());
+
+        let mut transition = Transition::within(ID::random(), ID::random(), region.id().clone());
+        transition.add_trigger(Trigger::signal(""));
+        region.add_transition(transition);
+
+        assert!(machine.validate().is_err());
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let machine: StateMachine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let initial_id = region.new_initial_state();
+        let waiting_id = region.new_simple_state();
+        let retry_id = region.new_simple_state();
+        let failed_id = region.new_final_state();
+
+        region.new_transition(initial_id, waiting_id.clone());
+        region.new_timeout(waiting_id.clone(), Duration::from_secs(5), retry_id.clone());
+        region.new_timeout(
+            waiting_id.clone(),
+            Duration::from_secs(30),
+            failed_id.clone(),
+        );
+        region.new_timeout(retry_id.clone(), Duration::from_secs(5), waiting_id.clone());
+        assert!(machine.validate().is_ok());
+
+        let after = Trigger::after(Duration::from_secs(5));
+        let triggered = region.transitions_triggered_by(&waiting_id, after.event());
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].target(), retry_id);
+
+        region.new_timeout(waiting_id, Duration::from_secs(5), failed_id);
+        match machine.validate() {
+            Err(crate::error::Error(ErrorKind::StateConflictingTimeouts(_), _)) => (),
+            other => panic!("expected conflicting timeouts, not {:?}", other),
+        }
+    }
+    #[test]
+    fn test_forks_and_joins() {
+        use crate::definition::builder::{MachineBuilder, RegionBuilder, TransitionBuilder};
+
+        fn machine(fork_to: &'static str, join_from: &'static str) -> Result<Arc<StateMachine>> {
+            MachineBuilder::new()
+                .region(|region: RegionBuilder<_>| {
+                    region
+                        .initial("idle")
+                        .state("idle", |state| state.on("split", "fork"))
+                        .pseudo_state("fork", PseudoStateKind::Fork)
+                        .transition("fork", TransitionBuilder::to("left"))
+                        .transition("fork", TransitionBuilder::to(fork_to))
+                        .state("both", |state| {
+                            state
+                                .region(|left| left.initial("left").simple_state("left"))
+                                .region(|right| right.initial("right").simple_state("right"))
+                        })
+                        .pseudo_state("join", PseudoStateKind::Join)
+                        .transition("left", TransitionBuilder::to("join"))
+                        .transition(join_from, TransitionBuilder::to("join"))
+                        .transition("join", TransitionBuilder::to("done"))
+                        .final_state("done")
+                })
+                .build()
+        }
+
+        assert!(machine("right", "right").is_ok());
+        for (fork_to, join_from) in &[("left", "right"), ("idle", "right"), ("right", "left")] {
+            match machine(fork_to, join_from) {
+                Err(crate::error::Error(ErrorKind::ForkJoinTransitions(_, _), _)) => (),
+                _ => panic!("expected ForkJoinTransitions"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutation() {
+        let machine: StateMachine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let idle_id = region.new_simple_state();
+        let busy_id = region.new_compound_state();
+        let done_id = region.new_final_state();
+        let (_, busy) = machine.locate_vertex(&busy_id).unwrap();
+        let inner_id = busy
+            .as_state()
+            .unwrap()
+            .default_region()
+            .unwrap()
+            .id()
+            .clone();
+        drop(busy);
+        let working_id = machine.region_handle(&inner_id).unwrap().new_simple_state();
+
+        machine.set_initial(region.id(), &idle_id).unwrap();
+        assert_eq!(region.vertices().len(), 4);
+        machine.set_initial(region.id(), &busy_id).unwrap();
+        assert_eq!(region.vertices().len(), 4);
+        assert_eq!(region.transitions()[0].target(), busy_id);
+
+        region.new_transition(idle_id.clone(), working_id.clone());
+        region.new_transition(busy_id.clone(), done_id.clone());
+        match machine.remove_vertex(&busy_id) {
+            Err(crate::error::Error(ErrorKind::ModelVertexReferenced(_), _)) => (),
+            other => panic!("expected a referenced vertex, not {:?}", other.map(|_| ())),
+        }
+
+        machine
+            .retarget_transition(region.id(), 1, &done_id)
+            .unwrap();
+        let shared = region.transitions();
+        assert!(machine
+            .retarget_transition(region.id(), 1, &idle_id)
+            .is_err());
+        drop(shared);
+        assert!(machine.remove_transition(region.id(), 3).is_err());
+        let _ = machine.remove_transition(region.id(), 2).unwrap();
+        machine.set_initial(region.id(), &idle_id).unwrap();
+
+        machine.rename_vertex(&working_id, "working").unwrap();
+        match machine.move_vertex(&busy_id, &inner_id) {
+            Err(crate::error::Error(ErrorKind::ModelInvalidMove(_), _)) => (),
+            other => panic!("expected an invalid move, not {:?}", other),
+        }
+        machine.move_vertex(&working_id, region.id()).unwrap();
+        let working = machine.locate_vertex(&working_id).unwrap();
+        assert_eq!(working.0, region.id().clone());
+        assert_eq!(
+            working.1.as_state().unwrap().label(),
+            &Some("working".to_string())
+        );
+        assert_eq!(working.1.as_state().unwrap().container(), region.id());
+
+        let removed = machine.remove_vertex(&busy_id).unwrap();
+        assert_eq!(removed.id(), &busy_id);
+        assert_eq!(region.vertices().len(), 4);
+        assert!(machine.validate().is_ok());
+    }
+    #[test]
+    fn test_queries() {
+        use crate::definition::builder::MachineBuilder;
+
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region.initial("Root").state("Root", |state| {
+                    state.region(|inner| {
+                        inner
+                            .initial("Processing")
+                            .state("Processing", |state| {
+                                state
+                                    .on("fail", "Failed")
+                                    .region(|inner| inner.initial("Retry").simple_state("Retry"))
+                            })
+                            .state("Failed", |state| {
+                                state.label("Retry").on("reset", "Processing")
+                            })
+                    })
+                })
+            })
+            .build_with_names()
+            .unwrap();
+
+        let found = machine.find_state_by_label("Processing").unwrap();
+        assert_eq!(found.id(), &names["Processing"]);
+        let found = machine.find_state_by_label("Retry").unwrap();
+        assert_eq!(found.id(), &names["Failed"]);
+        assert!(machine.find_state_by_label("Missing").is_none());
+
+        let found = machine.find_by_path("Root/Processing/Retry").unwrap();
+        assert_eq!(found.id(), &names["Retry"]);
+        let found = machine.find_by_path("Root/Retry").unwrap();
+        assert_eq!(found.id(), &names["Failed"]);
+        assert!(machine.find_by_path("Processing").is_none());
+        assert!(machine
+            .find_by_path("Root/Processing/Retry/Again")
+            .is_none());
+        let initial = machine.find_vertices(|vertex| {
+            vertex
+                .as_pseudo_state()
+                .is_some_and(|pseudo_state| pseudo_state.is_initial())
+        });
+        assert_eq!(initial.len(), 3);
+        let path = format!("Root/{}", initial[1].id());
+        assert_eq!(machine.find_by_path(&path).unwrap().id(), initial[1].id());
+
+        let triggered = machine.find_transitions(Transition::has_triggers);
+        assert_eq!(triggered.len(), 2);
+        assert_eq!(triggered[0].target(), names["Failed"]);
+        assert_eq!(triggered[1].target(), names["Processing"]);
+    }
+
+    #[test]
+    fn test_transition_index() {
+        use crate::definition::builder::{MachineBuilder, TransitionBuilder};
+
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("Idle")
+                    .state("Idle", |state| {
+                        state
+                            .on("start", "Running")
+                            .on("stop", "Done")
+                            .transition(TransitionBuilder::to("Done").trigger(Trigger::any()))
+                    })
+                    .state("Running", |state| state.on("stop", "Done"))
+                    .final_state("Done")
+            })
+            .build_with_names()
+            .unwrap();
+        let (idle, done) = (&names["Idle"], &names["Done"]);
+        let start = Event::Signal(SignalEvent::new("start"));
+        let other = Event::Signal(SignalEvent::new("other"));
+
+        let unindexed = (
+            machine.transitions_from(idle).len(),
+            machine.transitions_to(done).len(),
+            machine.transitions_triggered_by(idle, &start).len(),
+            machine.transitions_triggered_by(idle, &other).len(),
+        );
+        machine.index_references();
+        let indexed = (
+            machine.transitions_from(idle).len(),
+            machine.transitions_to(done).len(),
+            machine.transitions_triggered_by(idle, &start).len(),
+            machine.transitions_triggered_by(idle, &other).len(),
+        );
+        assert_eq!(unindexed, (3, 3, 1, 1));
+        assert_eq!(indexed, unindexed);
+        assert_eq!(
+            machine.transitions_triggered_by(idle, &start)[0].target(),
+            names["Running"]
+        );
+        assert!(machine.transitions_triggered_by(done, &start).is_empty());
+
+        let region = machine.default_region().unwrap().id().clone();
+        let index = machine
+            .default_region()
+            .unwrap()
+            .transitions()
+            .iter()
+            .position(|transition| transition.target() == names["Running"])
+            .unwrap();
+        machine.retarget_transition(&region, index, done).unwrap();
+        assert_eq!(machine.transitions_to(done).len(), 4);
+        let _ = machine.remove_transition(&region, index).unwrap();
+        assert_eq!(machine.transitions_from(idle).len(), 2);
+        let wildcard = machine.transitions_triggered_by(idle, &start);
+        assert_eq!(wildcard.len(), 1);
+        assert!(wildcard[0].triggers().any(Trigger::is_any_receive));
+    }
+
+    #[test]
+    fn test_duplicate() {
+        use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+
+        let template: StateMachine = StateMachine::labeled("template");
+        let region: &Region = template.default_region().unwrap();
+        let initial_id = region.new_initial_state();
+        let mut outer = State::within(region.id().clone());
+        let outer_id = outer.id().clone();
+        outer.set_entry(Box::new(OpaqueBehavior::new("greet()", None)));
+        let inner_region_id = outer.new_region();
+        let inner_id = outer.default_region().unwrap().new_simple_state();
+        region.add_state(outer);
+        region.new_transition(initial_id, inner_id.clone());
+        let mut leave = Transition::within(inner_id.clone(), outer_id.clone(), region.id().clone());
+        leave.add_trigger(Trigger::signal("leave"));
+        leave.set_guard(Box::new(OpaqueConstraint::new("ready", None)));
+        region.add_transition(leave);
+
+        let tenant: ID = "tenant_a".parse().unwrap();
+        let copy = template.duplicate_in_namespace(&tenant).unwrap();
+        assert_ne!(copy.id(), template.id());
+        assert_eq!(copy.namespace(), Some(tenant));
+        assert_eq!(copy.label(), &Some("template".to_string()));
+
+        let copy_region = copy.default_region().unwrap();
+        assert_ne!(copy_region.id(), region.id());
+        assert_eq!(copy_region.container(), copy.id());
+        let vertices = copy_region.vertices();
+        let copy_outer = vertices[1].as_state().unwrap();
+        assert_ne!(copy_outer.id(), &outer_id);
+        assert!(copy_outer.entry().is_some());
+        let copy_inner_region = copy_outer.default_region().unwrap();
+        assert_ne!(copy_inner_region.id(), &inner_region_id);
+        assert_eq!(copy_inner_region.container(), copy_outer.id());
+        let copy_inner_id = copy_inner_region.vertices()[0].id().clone();
+        assert_ne!(copy_inner_id, inner_id);
+
+        let transitions = copy_region.transitions();
+        assert_eq!(transitions[0].source(), vertices[0].id().clone());
+        assert_eq!(transitions[0].target(), copy_inner_id);
+        assert_eq!(transitions[1].target(), copy_outer.id().clone());
+        assert!(transitions[1].has_guard());
+        assert_eq!(transitions[1].container(), copy_region.id());
+        assert!(copy.validate().is_ok());
+
+        struct Uncopied(Option<String>);
+        impl Labeled for Uncopied {
+            fn label(&self) -> &Option<String> {
+                &self.0
+            }
+            fn set_label(&mut self, label: &str) {
+                self.0 = Some(label.to_string())
+            }
+            fn unset_label(&mut self) {
+                self.0 = None
+            }
+        }
+        impl Behavior for Uncopied {
+            fn perform(&self, _: &ID, _: &Trigger, _: &dyn EventSink) {}
+        }
+        let mut state = State::within(region.id().clone());
+        state.set_exit(Box::new(Uncopied(None)));
+        region.add_state(state);
+        match template.duplicate() {
+            Err(crate::error::Error(ErrorKind::ModelNotDuplicable(_), _)) => (),
+            other => panic!(
+                "expected a behavior that cannot be copied, not {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
 }
@@ -1,4 +1,6 @@
 /*!
+The descriptive model of a state machine; types, their implementations, and a visitor for
+traversing a complete model.
 */
 
 // ------------------------------------------------------------------------------------------------
@@ -11,3 +13,29 @@ pub mod types;
 pub mod impls;
 
 pub mod visitor;
+
+pub mod guards;
+
+pub mod behaviors;
+
+pub mod cycles;
+
+pub mod opaque;
+
+pub mod registry;
+
+pub mod activities;
+
+pub mod expressions;
+
+pub mod builder;
+
+pub mod analysis;
+
+pub mod transform;
+
+pub mod generator;
+
+mod wellformed;
+
+mod redefinition;
@@ -0,0 +1,413 @@
+/*!
+Opaque elements hold the textual body of an expression or behavior, in some language, that this
+crate cannot itself evaluate or perform. They are primarily created by parsers for formats such
+as SCXML, where guards are written in the format's data model language, or XState, where guards
+and actions are named, so that they survive a parse and stringify round trip.
+
+An opaque element's label is its body, this is what writers will emit.
+
+While an opaque behavior cannot be performed by the crate, or an opaque constraint evaluated,
+the names they refer to may be bound to Rust closures with `Bindings`. A parser given a set of
+bindings will produce a `BoundBehavior` wherever the behavior it reads refers to a bound name;
+this performs the bound closures in order while retaining the opaque body for writers. The
+opaque elements of a machine may also be bound after it has been read, from any format, with
+`registry::bind`, which binds constraints, as a `BoundConstraint`, as well as behaviors.
+
+# Example
+
+```rust
+use uml_state_machine::core::ID;
+use uml_state_machine::definition::opaque::OpaqueConstraint;
+use uml_state_machine::definition::types::{Constraint, Labeled, Trigger};
+
+let guard = OpaqueConstraint::new("_event.data.total > 100", Some("ecmascript"));
+
+assert_eq!(guard.label(), &Some("_event.data.total > 100".to_string()));
+assert_eq!(guard.language(), &Some("ecmascript".to_string()));
+assert!(!guard.evaluate(&ID::random(), &Trigger::any()));
+```
+
+Binding a name used in an opaque behavior to a closure.
+
+```rust
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use uml_state_machine::core::ID;
+use uml_state_machine::definition::opaque::{Bindings, OpaqueBehavior};
+use uml_state_machine::definition::types::{Behavior, Event, EventSink, SignalEvent, Trigger};
+
+let count = Arc::new(AtomicUsize::new(0));
+let counter = count.clone();
+let mut bindings = Bindings::default();
+bindings.bind("increment", move |_, _, events| {
+    let _ = counter.fetch_add(1, Ordering::SeqCst);
+    events.raise(Event::Signal(SignalEvent::new("incremented")));
+});
+
+let behavior = bindings.resolve(
+    OpaqueBehavior::new("<raise event=\"increment\"/>", Some("scxml")),
+    &["increment"],
+);
+let raised: RefCell<Vec<Event>> = Default::default();
+behavior.perform(&ID::random(), &Trigger::any(), &raised);
+
+assert_eq!(count.load(Ordering::SeqCst), 1);
+assert_eq!(raised.borrow().len(), 1);
+assert!(behavior.as_opaque().is_some());
+```
+
+*/
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::core::{Context, ID};
+use crate::definition::behaviors::BehaviorFn;
+use crate::definition::guards::GuardFn;
+use crate::definition::types::{Behavior, Constraint, EventSink, Labeled, Trigger};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// **UML**: `OpaqueExpression`, a constraint specified as a body in some language. As the body
+/// cannot be evaluated the constraint is always `false`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpaqueConstraint {
+    label: Option<String>,
+    body: String,
+    language: Option<String>,
+}
+
+///
+/// **UML**: `OpaqueBehavior`, a behavior specified as a body in some language. As the body
+/// cannot be performed the behavior does nothing.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpaqueBehavior {
+    label: Option<String>,
+    body: String,
+    language: Option<String>,
+}
+
+///
+/// A set of names, as referred to by opaque behaviors and constraints, bound to the closures that
+/// perform, or evaluate, them. A name may be bound to both a behavior and a constraint.
+///
+#[derive(Clone, Default)]
+pub struct Bindings {
+    behaviors: HashMap<String, Arc<BehaviorFn>>,
+    constraints: HashMap<String, Arc<GuardFn>>,
+}
+
+///
+/// An opaque behavior where some, or all, of the names it refers to are bound to closures.
+/// Performing this behavior performs each of the bound closures, in order.
+///
+pub struct BoundBehavior {
+    opaque: OpaqueBehavior,
+    performs: Vec<Arc<BehaviorFn>>,
+}
+
+///
+/// An opaque constraint whose name is bound to a closure, which evaluates it.
+///
+pub struct BoundConstraint {
+    opaque: OpaqueConstraint,
+    evaluate: Arc<GuardFn>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+macro_rules! make_opaque_impl {
+    ($type_name:ident) => {
+        impl Labeled for $type_name {
+            fn label(&self) -> &Option<String> {
+                &self.label
+            }
+
+            fn set_label(&mut self, label: &str) {
+                self.label = Some(label.to_string())
+            }
+
+            fn unset_label(&mut self) {
+                self.label = None
+            }
+        }
+
+        impl $type_name {
+            pub fn new(body: &str, language: Option<&str>) -> Self {
+                Self {
+                    label: Some(body.to_string()),
+                    body: body.to_string(),
+                    language: language.map(str::to_string),
+                }
+            }
+
+            pub fn body(&self) -> &String {
+                &self.body
+            }
+
+            pub fn language(&self) -> &Option<String> {
+                &self.language
+            }
+        }
+    };
+}
+
+make_opaque_impl!(OpaqueConstraint);
+
+impl Constraint for OpaqueConstraint {
+    fn evaluate(&self, _in_state: &ID, _on_trigger: &Trigger) -> bool {
+        false
+    }
+
+    fn as_opaque(&self) -> Option<&OpaqueConstraint> {
+        Some(self)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+make_opaque_impl!(OpaqueBehavior);
+
+impl Behavior for OpaqueBehavior {
+    fn perform(&self, _in_state: &ID, _on_trigger: &Trigger, _events: &dyn EventSink) {}
+
+    fn as_opaque(&self) -> Option<&OpaqueBehavior> {
+        Some(self)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for Bindings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut behaviors: Vec<&String> = self.behaviors.keys().collect();
+        behaviors.sort();
+        let mut constraints: Vec<&String> = self.constraints.keys().collect();
+        constraints.sort();
+        f.debug_struct("Bindings")
+            .field("behaviors", &behaviors)
+            .field("constraints", &constraints)
+            .finish()
+    }
+}
+
+impl Bindings {
+    ///
+    /// Bind `name` to the closure `f`, as a behavior, replacing any existing binding of the same
+    /// name as a behavior.
+    ///
+    pub fn bind<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&ID, &Trigger, &dyn EventSink) + Send + Sync + 'static,
+    {
+        self.bind_in(name, move |in_state, on_trigger, events, _| {
+            f(in_state, on_trigger, events)
+        })
+    }
+
+    ///
+    /// Bind `name` to the closure `f`, as for `bind`, where `f` is also given the context of the
+    /// instance performing it; the context is empty outside of an instance.
+    ///
+    pub fn bind_in<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&ID, &Trigger, &dyn EventSink, &Context) + Send + Sync + 'static,
+    {
+        let _ = self.behaviors.insert(name.to_string(), Arc::new(f));
+    }
+
+    ///
+    /// Bind `name` to the closure `f`, as a constraint, replacing any existing binding of the
+    /// same name as a constraint.
+    ///
+    pub fn bind_constraint<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&ID, &Trigger, &Context) -> bool + Send + Sync + 'static,
+    {
+        let _ = self.constraints.insert(name.to_string(), Arc::new(f));
+    }
+
+    ///
+    /// Remove the bindings of `name`, as a behavior and as a constraint.
+    ///
+    pub fn unbind(&mut self, name: &str) {
+        let _ = self.behaviors.remove(name);
+        let _ = self.constraints.remove(name);
+    }
+
+    ///
+    /// Returns `true` if `name` is bound as a behavior.
+    ///
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.behaviors.contains_key(name)
+    }
+
+    pub fn is_constraint_bound(&self, name: &str) -> bool {
+        self.constraints.contains_key(name)
+    }
+
+    ///
+    /// The names bound, as behaviors or constraints, sorted.
+    ///
+    pub fn names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self
+            .behaviors
+            .keys()
+            .chain(
+                self.constraints
+                    .keys()
+                    .filter(|name| !self.behaviors.contains_key(*name)),
+            )
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.behaviors.is_empty() && self.constraints.is_empty()
+    }
+
+    ///
+    /// Return a behavior for `opaque`, which refers to each of `names` in order. Where none of the
+    /// names are bound this is the opaque behavior itself, otherwise it is a `BoundBehavior`.
+    ///
+    pub fn resolve(&self, opaque: OpaqueBehavior, names: &[&str]) -> Box<dyn Behavior> {
+        let performs: Vec<Arc<BehaviorFn>> = names
+            .iter()
+            .filter_map(|name| self.behaviors.get(*name).cloned())
+            .collect();
+        if performs.is_empty() {
+            Box::new(opaque)
+        } else {
+            Box::new(BoundBehavior { opaque, performs })
+        }
+    }
+
+    ///
+    /// Return a constraint for `opaque`, which refers to `name`. Where the name is not bound this
+    /// is the opaque constraint itself, otherwise it is a `BoundConstraint`.
+    ///
+    pub fn resolve_constraint(&self, opaque: OpaqueConstraint, name: &str) -> Box<dyn Constraint> {
+        match self.constraints.get(name) {
+            None => Box::new(opaque),
+            Some(evaluate) => Box::new(BoundConstraint {
+                opaque,
+                evaluate: evaluate.clone(),
+            }),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for BoundBehavior {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoundBehavior")
+            .field("opaque", &self.opaque)
+            .field("performs", &self.performs.len())
+            .finish()
+    }
+}
+
+impl Labeled for BoundBehavior {
+    fn label(&self) -> &Option<String> {
+        self.opaque.label()
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.opaque.set_label(label)
+    }
+
+    fn unset_label(&mut self) {
+        self.opaque.unset_label()
+    }
+}
+
+impl Behavior for BoundBehavior {
+    fn perform(&self, in_state: &ID, on_trigger: &Trigger, events: &dyn EventSink) {
+        self.perform_in(in_state, on_trigger, events, &Context::default())
+    }
+
+    fn perform_in(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        events: &dyn EventSink,
+        context: &Context,
+    ) {
+        for perform in &self.performs {
+            perform(in_state, on_trigger, events, context)
+        }
+    }
+
+    fn as_opaque(&self) -> Option<&OpaqueBehavior> {
+        Some(&self.opaque)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        Some(Box::new(BoundBehavior {
+            opaque: self.opaque.clone(),
+            performs: self.performs.clone(),
+        }))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for BoundConstraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoundConstraint")
+            .field("opaque", &self.opaque)
+            .finish()
+    }
+}
+
+impl Labeled for BoundConstraint {
+    fn label(&self) -> &Option<String> {
+        self.opaque.label()
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.opaque.set_label(label)
+    }
+
+    fn unset_label(&mut self) {
+        self.opaque.unset_label()
+    }
+}
+
+impl Constraint for BoundConstraint {
+    fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool {
+        (self.evaluate)(in_state, on_trigger, &Context::default())
+    }
+
+    fn evaluate_in(&self, in_state: &ID, on_trigger: &Trigger, context: &Context) -> bool {
+        (self.evaluate)(in_state, on_trigger, context)
+    }
+
+    fn as_opaque(&self) -> Option<&OpaqueConstraint> {
+        Some(&self.opaque)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(BoundConstraint {
+            opaque: self.opaque.clone(),
+            evaluate: self.evaluate.clone(),
+        }))
+    }
+}
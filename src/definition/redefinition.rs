@@ -0,0 +1,417 @@
+/*!
+The rules for a machine that extends another, see `StateMachine::extend`, from the UML
+specification's **StateMachine extension**. An extension is a complete machine, holding its own
+copy of each element of the machine it extends; each copy records the element it extends, or
+redefines, and these records are checked against that machine.
+
+* Each region extends a region of the extended machine, and within the machine, or the state
+  redefining the one, that contains that region. A simple state may become composite by adding
+  regions.
+* Each vertex redefines a vertex of the same kind, within the region extending the one that
+  contains that vertex.
+* Each transition redefines a transition within the region extending the one that contains it,
+  from the vertex redefining its source and with the same triggers; its target, guard, and
+  effect may be replaced.
+* Every region, vertex, and transition of the extended machine is extended, or redefined, exactly
+  once; elements may be added, but not removed.
+*/
+
+use crate::core::ID;
+use crate::definition::types::{
+    Identified, Region, RegionContainerType, StateMachine, Transition, Vertex,
+};
+use crate::error::{Error, ErrorKind, Result};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+pub(crate) fn validate_extension(machine: &StateMachine, base: &StateMachine) -> Result<()> {
+    if machine.extended.as_ref() != Some(&base.id) {
+        return Err(redefinition(
+            machine.id().to_string(),
+            &format!("the machine does not extend {}", base.id),
+        ));
+    }
+    let index = Index::new(machine);
+    let base_index = Index::new(base);
+
+    let mut extended: HashMap<&ID, &ID> = Default::default();
+    for (id, entry) in &index.regions {
+        if let Some(base_region) = &entry.extended {
+            let base_entry = base_index.regions.iter().find(|(id, _)| id == base_region);
+            let valid = match base_entry {
+                None => false,
+                Some((_, base_entry)) => match (&entry.owner, &base_entry.owner) {
+                    (None, None) => true,
+                    (Some(owner), Some(base_owner)) => {
+                        index.redefined_vertex(owner) == Some(base_owner)
+                    }
+                    _ => false,
+                },
+            };
+            if !valid {
+                return Err(redefinition(
+                    id.to_string(),
+                    "a region must extend a region of the machine it extends, or of the state it \
+                     redefines",
+                ));
+            }
+            once(&mut extended, base_region, id)?;
+        }
+    }
+    all_once(
+        &extended,
+        base_index.regions.iter().map(|(id, _)| id),
+        "region",
+    )?;
+
+    let mut redefined: HashMap<&ID, &ID> = Default::default();
+    for (id, entry) in &index.vertices {
+        if let Some(base_vertex) = entry.vertex.redefined_vertex() {
+            let valid = base_index.vertex(base_vertex).is_some_and(|base_entry| {
+                index.extended_region(&entry.region) == Some(&base_entry.region)
+                    && same_kind(&entry.vertex, &base_entry.vertex)
+            });
+            if !valid {
+                return Err(redefinition(
+                    id.to_string(),
+                    "a vertex must redefine a vertex of the same kind, within the region \
+                     extending the one that contains it",
+                ));
+            }
+            once(&mut redefined, base_vertex, id)?;
+        }
+    }
+    all_once(
+        &redefined,
+        base_index.vertices.iter().map(|(id, _)| id),
+        "vertex",
+    )?;
+
+    let mut redefined: HashSet<&(ID, usize)> = Default::default();
+    for entry in &index.transitions {
+        let name = format!("{}[{}]", entry.region, entry.index);
+        if let Some(base_transition) = &entry.transition.redefined {
+            let base_entry = base_index.transitions.iter().find(|base_entry| {
+                (&base_entry.region, base_entry.index) == (&base_transition.0, base_transition.1)
+            });
+            let base_entry = match base_entry {
+                Some(base_entry)
+                    if index.extended_region(&entry.region) == Some(&base_entry.region) =>
+                {
+                    base_entry
+                }
+                _ => {
+                    return Err(redefinition(
+                        name,
+                        "a transition must redefine a transition within the region extending \
+                         the one that contains it",
+                    ))
+                }
+            };
+            if index.redefined_vertex(&entry.transition.source)
+                != Some(&base_entry.transition.source)
+            {
+                return Err(redefinition(
+                    name,
+                    "a redefining transition must keep the source of the transition it redefines",
+                ));
+            }
+            if entry.transition.triggers != base_entry.transition.triggers {
+                return Err(redefinition(
+                    name,
+                    "a redefining transition must keep the triggers of the transition it \
+                     redefines",
+                ));
+            }
+            if !redefined.insert(base_transition) {
+                return Err(redefinition(
+                    name,
+                    &format!(
+                        "transition {}[{}] is redefined more than once",
+                        base_transition.0, base_transition.1
+                    ),
+                ));
+            }
+        }
+    }
+    for base_entry in &base_index.transitions {
+        if !redefined.contains(&(base_entry.region.clone(), base_entry.index)) {
+            return Err(not_redefined(
+                format!("{}[{}]", base_entry.region, base_entry.index),
+                "transition",
+            ));
+        }
+    }
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct RegionEntry {
+    owner: Option<ID>,
+    extended: Option<ID>,
+}
+
+struct VertexEntry {
+    region: ID,
+    vertex: Arc<Vertex>,
+}
+
+struct TransitionEntry {
+    region: ID,
+    index: usize,
+    transition: Arc<Transition>,
+}
+
+///
+/// The regions, vertices, and transitions of a machine, at any depth, in the order visited.
+///
+struct Index {
+    regions: Vec<(ID, RegionEntry)>,
+    vertices: Vec<(ID, VertexEntry)>,
+    transitions: Vec<TransitionEntry>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Index {
+    fn new(machine: &StateMachine) -> Self {
+        let mut index = Self {
+            regions: Default::default(),
+            vertices: Default::default(),
+            transitions: Default::default(),
+        };
+        machine.for_each_region(&mut |region| index.add_region(region));
+        index
+    }
+
+    fn add_region(&mut self, region: &Region) {
+        self.regions.push((
+            region.id().clone(),
+            RegionEntry {
+                owner: match region.container_type {
+                    RegionContainerType::State => Some(region.container.clone()),
+                    RegionContainerType::StateMachine => None,
+                },
+                extended: region.extended.clone(),
+            },
+        ));
+        for vertex in region.vertices() {
+            self.vertices.push((
+                vertex.id().clone(),
+                VertexEntry {
+                    region: region.id().clone(),
+                    vertex,
+                },
+            ));
+        }
+        for (index, transition) in region.transitions().into_iter().enumerate() {
+            self.transitions.push(TransitionEntry {
+                region: region.id().clone(),
+                index,
+                transition,
+            });
+        }
+    }
+
+    fn vertex(&self, id: &ID) -> Option<&VertexEntry> {
+        self.vertices
+            .iter()
+            .find(|(vertex, _)| vertex == id)
+            .map(|(_, entry)| entry)
+    }
+
+    fn redefined_vertex(&self, id: &ID) -> Option<&ID> {
+        self.vertex(id)
+            .and_then(|entry| entry.vertex.redefined_vertex().as_ref())
+    }
+
+    fn extended_region(&self, id: &ID) -> Option<&ID> {
+        self.regions
+            .iter()
+            .find(|(region, _)| region == id)
+            .and_then(|(_, entry)| entry.extended.as_ref())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn same_kind(vertex: &Vertex, base: &Vertex) -> bool {
+    match (vertex, base) {
+        (Vertex::State(_), Vertex::State(_)) => true,
+        (Vertex::PseudoState(pseudo_state), Vertex::PseudoState(base)) => {
+            pseudo_state.kind == base.kind
+        }
+        (Vertex::ConnectionPointReference(_), Vertex::ConnectionPointReference(_)) => true,
+        _ => false,
+    }
+}
+
+fn once<'a>(redefined: &mut HashMap<&'a ID, &'a ID>, base: &'a ID, by: &'a ID) -> Result<()> {
+    match redefined.insert(base, by) {
+        None => Ok(()),
+        Some(_) => Err(redefinition(
+            by.to_string(),
+            &format!("{} is redefined more than once", base),
+        )),
+    }
+}
+
+fn all_once<'a>(
+    redefined: &HashMap<&ID, &ID>,
+    mut base: impl Iterator<Item = &'a ID>,
+    kind: &str,
+) -> Result<()> {
+    match base.find(|id| !redefined.contains_key(id)) {
+        None => Ok(()),
+        Some(id) => Err(not_redefined(id.to_string(), kind)),
+    }
+}
+
+fn not_redefined(element: String, kind: &str) -> Error {
+    redefinition(
+        element,
+        &format!(
+            "this {} of the extended machine is not redefined, inherited elements may not be \
+             removed",
+            kind
+        ),
+    )
+}
+
+fn redefinition(element: String, reason: &str) -> Error {
+    ErrorKind::ModelRedefinition(element, reason.to_string()).into()
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+    use crate::definition::types::{HasRegions, Labeled, State, Trigger, Validate};
+
+    fn lifecycle() -> (StateMachine, ID, ID) {
+        let machine = StateMachine::labeled("lifecycle");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let draft = region.new_simple_state();
+        let review = region.new_simple_state();
+        let published = region.new_final_state();
+        region.new_transition(initial, draft.clone());
+        let mut submit = Transition::within(draft.clone(), review.clone(), region.id().clone());
+        submit.add_trigger(Trigger::signal("submit"));
+        region.add_transition(submit);
+        let mut approve = Transition::within(review.clone(), published, region.id().clone());
+        approve.add_trigger(Trigger::signal("approve"));
+        region.add_transition(approve);
+        (machine, draft, review)
+    }
+
+    fn invalid(extension: &StateMachine, base: &StateMachine) -> String {
+        match extension.validate_extension(base) {
+            Err(Error(ErrorKind::ModelRedefinition(_, reason), _)) => reason,
+            result => panic!("expected ModelRedefinition, not {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn test_extend() {
+        let (base, draft, _) = lifecycle();
+        let variant = base.extend().unwrap();
+        assert_eq!(variant.extended_machine(), &Some(base.id().clone()));
+        assert!(variant.is_redefinition_context_valid(&base));
+
+        let region = variant.default_region().unwrap();
+        assert_eq!(
+            region.extended_region(),
+            &Some(base.default_region().unwrap().id().clone())
+        );
+        let vertices = region.vertices();
+        let variant_draft = vertices[1].id().clone();
+        assert_eq!(vertices[1].redefined_vertex(), &Some(draft));
+
+        // a legal review before publishing, and a note made on entering the draft.
+        let mut legal = State::within(region.id().clone());
+        legal.set_label("legal");
+        let legal_id = legal.id().clone();
+        region.add_state(legal);
+        let published = vertices[3].id().clone();
+        let mut approve = Transition::within(legal_id.clone(), published, region.id().clone());
+        approve.add_trigger(Trigger::signal("approve"));
+        region.add_transition(approve);
+        drop(vertices);
+        variant
+            .redefine_transition(region.id(), 2, |transition| {
+                transition.target = legal_id.clone();
+                transition.set_guard(Box::new(OpaqueConstraint::new("complete", None)));
+            })
+            .unwrap();
+        variant
+            .redefine_state(&variant_draft, |state| {
+                state.set_entry(Box::new(OpaqueBehavior::new("note()", None)));
+                let _ = state.new_region();
+            })
+            .unwrap();
+        assert!(variant.validate().is_ok());
+        assert!(variant.validate_extension(&base).is_ok());
+        assert_eq!(region.transitions()[2].target(), legal_id);
+        assert_eq!(
+            region.transitions()[2].redefined_transition(),
+            &Some((base.default_region().unwrap().id().clone(), 2))
+        );
+        let vertices = region.vertices();
+        let redefined = vertices[1].as_state().unwrap();
+        assert!(redefined.entry().is_some());
+        assert!(redefined.has_regions());
+        assert_eq!(
+            redefined.redefined_vertex(),
+            &Some(base.default_region().unwrap().vertices()[1].id().clone())
+        );
+
+        variant
+            .redefine_transition(region.id(), 1, |transition| {
+                transition.add_trigger(Trigger::signal("resubmit"))
+            })
+            .unwrap();
+        assert_eq!(
+            invalid(&variant, &base),
+            "a redefining transition must keep the triggers of the transition it redefines"
+        );
+
+        let variant = base.extend().unwrap();
+        let region = variant.default_region().unwrap();
+        let _ = variant.remove_transition(region.id(), 2).unwrap();
+        assert_eq!(
+            invalid(&variant, &base),
+            "this transition of the extended machine is not redefined, inherited elements may not \
+             be removed"
+        );
+
+        let variant = base.extend().unwrap();
+        let region = variant.default_region().unwrap();
+        let review = region.vertices()[2].id().clone();
+        variant
+            .redefine_transition(region.id(), 1, |transition| transition.source = review)
+            .unwrap();
+        assert_eq!(
+            invalid(&variant, &base),
+            "a redefining transition must keep the source of the transition it redefines"
+        );
+
+        let copy = base.duplicate().unwrap();
+        assert!(invalid(&base.extend().unwrap(), &copy).starts_with("the machine does not extend"));
+    }
+}
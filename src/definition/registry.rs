@@ -0,0 +1,518 @@
+/*!
+A pass binding the opaque elements of a machine to the closures of `opaque::Bindings`, such as
+for a machine parsed from a format where guards and actions are only names.
+
+The application binds a closure to each name, as a behavior or as a constraint, with
+[`Bindings`], and then calls [`bind`] with the machine. Each opaque entry, do-activity, and exit
+behavior, and transition effect, is bound, as a `BoundBehavior`, where it refers to a name bound
+as a behavior, and each opaque state invariant and guard, as a `BoundConstraint`, where it refers
+to a name bound as a constraint. The names an opaque behavior refers to are the items of its body
+separated by `,`, `;`, or line breaks, as xstate actions are read, and an opaque constraint
+refers to its whole body. Either may end with an empty argument list, `notify_user()` refers to
+`notify_user`. A bound element keeps its opaque body, so that writers emit it unchanged, and the
+names that are not bound are reported.
+
+This is the same as reading with `Bindings`, where a format supports it, but applies to any
+machine after it has been read, and to constraints as well as behaviors.
+
+In `BindingMode::Strict`, used with [`bind_with`] or `StateMachineInstance::with_bindings`, the
+bindings must match the machine as it may execute: binding fails, and the machine is unchanged,
+where a state that `analysis::reachable` finds reachable, or a transition from one that is not
+never enabled, refers to a name that is not bound, or where a name is bound that only the
+unreachable elements, those that `transform::flatten` leaves out, refer to.
+
+# Example
+
+```rust
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use uml_state_machine::definition::opaque::Bindings;
+use uml_state_machine::definition::registry::bind;
+use uml_state_machine::format::xstate::ReadXState;
+use uml_state_machine::format::Parse;
+
+let machine = ReadXState::default()
+    .parse(
+        r#"{
+            "id": "ticket",
+            "initial": "open",
+            "states": {
+                "open": { "on": { "close": { "target": "closed", "cond": "isResolved", "actions": ["notifyUser", "archive"] } } },
+                "closed": { "type": "final" }
+            }
+        }"#,
+    )
+    .unwrap();
+
+let notified = Arc::new(AtomicUsize::new(0));
+let counter = notified.clone();
+let mut bindings = Bindings::default();
+bindings.bind("notifyUser", move |_, _, _| {
+    let _ = counter.fetch_add(1, Ordering::SeqCst);
+});
+bindings.bind_constraint("isResolved", |_, _, _| true);
+
+let report = bind(&machine, &bindings).unwrap();
+assert_eq!(report.bound(), 2);
+assert_eq!(report.unresolved_names(), vec!["archive".to_string()]);
+```
+
+*/
+
+use crate::core::ID;
+use crate::definition::analysis::reachable;
+use crate::definition::cycles::debug_assert_no_cycles;
+use crate::definition::opaque::{Bindings, OpaqueBehavior, OpaqueConstraint};
+use crate::definition::types::{Behavior, Constraint, Identified, StateMachine};
+use crate::error::{ErrorKind, Result};
+use std::collections::BTreeSet;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A name referred to by an opaque element of a machine that is not bound.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unresolved {
+    /// The element referring to the name, a state ID, or the region ID and index of a
+    /// transition, as `region[index]`.
+    pub element: String,
+    /// The name that is not bound.
+    pub name: String,
+}
+
+///
+/// The result of `bind`, the number of elements bound and the names that could not be.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BindingReport {
+    bound: usize,
+    unresolved: Vec<Unresolved>,
+}
+
+///
+/// Whether binding checks that the bindings match the reachable elements of the machine.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BindingMode {
+    /// Bind whatever names are bound, reporting those that are not.
+    #[default]
+    Lenient,
+    /// Fail where a reachable element refers to a name that is not bound, or a name is bound
+    /// only for unreachable elements.
+    Strict,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Bind the opaque behaviors and constraints of `machine`, at any depth, to the closures of
+/// `bindings` for the names they refer to. Behaviors referring to some bound names are bound to
+/// those, as for `Bindings::resolve`. This fails where a state or transition with an opaque
+/// element is shared, as for `StateMachine::redefine_state`. In debug builds this panics where
+/// `machine` has sub-machine reference cycles, see `cycles::debug_assert_no_cycles`.
+///
+pub fn bind(machine: &StateMachine, bindings: &Bindings) -> Result<BindingReport> {
+    bind_with(machine, bindings, BindingMode::Lenient)
+}
+
+///
+/// Bind `machine` as for `bind`, in `mode`; in `BindingMode::Strict` this fails with
+/// `ErrorKind::BindingMismatch`, before anything is bound, where the bindings do not match the
+/// reachable elements of the machine.
+///
+pub fn bind_with(
+    machine: &StateMachine,
+    bindings: &Bindings,
+    mode: BindingMode,
+) -> Result<BindingReport> {
+    debug_assert_no_cycles(machine);
+    if mode == BindingMode::Strict {
+        check_strict(machine, bindings)?;
+    }
+    let mut states: Vec<ID> = Default::default();
+    let mut transitions: Vec<(ID, usize)> = Default::default();
+    machine.for_each_region(&mut |region| {
+        for vertex in region.vertices() {
+            if let Some(state) = vertex.as_state() {
+                if [&state.entry, &state.do_activity, &state.exit]
+                    .iter()
+                    .any(|behavior| opaque_behavior(behavior).is_some())
+                    || opaque_constraint(&state.invariant).is_some()
+                {
+                    states.push(state.id().clone());
+                }
+            }
+        }
+        for (index, transition) in region.transitions().iter().enumerate() {
+            if opaque_behavior(&transition.effect).is_some()
+                || opaque_constraint(&transition.guard).is_some()
+            {
+                transitions.push((region.id().clone(), index));
+            }
+        }
+    });
+
+    let mut binder = Binder {
+        bindings,
+        report: Default::default(),
+    };
+    for state in states {
+        let element = state.to_string();
+        machine.redefine_state(&state, |state| {
+            binder.behavior(&element, &mut state.entry);
+            binder.behavior(&element, &mut state.do_activity);
+            binder.behavior(&element, &mut state.exit);
+            binder.constraint(&element, &mut state.invariant);
+        })?;
+    }
+    for (region, index) in transitions {
+        let element = format!("{}[{}]", region, index);
+        machine.redefine_transition(&region, index, |transition| {
+            binder.behavior(&element, &mut transition.effect);
+            binder.constraint(&element, &mut transition.guard);
+        })?;
+    }
+    Ok(binder.report)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl BindingReport {
+    ///
+    /// The number of behaviors and constraints bound.
+    ///
+    pub fn bound(&self) -> usize {
+        self.bound
+    }
+
+    ///
+    /// Returns `true` if every name referred to was bound.
+    ///
+    pub fn is_complete(&self) -> bool {
+        self.unresolved.is_empty()
+    }
+
+    pub fn unresolved(&self) -> &Vec<Unresolved> {
+        &self.unresolved
+    }
+
+    ///
+    /// The distinct names that were not bound, sorted.
+    ///
+    pub fn unresolved_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .unresolved
+            .iter()
+            .map(|unresolved| unresolved.name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct Binder<'a> {
+    bindings: &'a Bindings,
+    report: BindingReport,
+}
+
+impl Binder<'_> {
+    fn behavior(&mut self, element: &str, behavior: &mut Option<Box<dyn Behavior>>) {
+        let opaque = match opaque_behavior(behavior) {
+            None => return,
+            Some(opaque) => opaque.clone(),
+        };
+        let body = opaque.body().clone();
+        let names = behavior_names(&body);
+        for name in &names {
+            if !self.bindings.is_bound(name) {
+                self.unresolved(element, name)
+            }
+        }
+        if names.iter().any(|name| self.bindings.is_bound(name)) {
+            *behavior = Some(self.bindings.resolve(opaque, &names));
+            self.report.bound += 1;
+        }
+    }
+
+    fn constraint(&mut self, element: &str, constraint: &mut Option<Box<dyn Constraint>>) {
+        let opaque = match opaque_constraint(constraint) {
+            None => return,
+            Some(opaque) => opaque.clone(),
+        };
+        let name = without_arguments(opaque.body().trim()).to_string();
+        if self.bindings.is_constraint_bound(&name) {
+            *constraint = Some(self.bindings.resolve_constraint(opaque, &name));
+            self.report.bound += 1;
+        } else {
+            self.unresolved(element, &name)
+        }
+    }
+
+    fn unresolved(&mut self, element: &str, name: &str) {
+        self.report.unresolved.push(Unresolved {
+            element: element.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Fail where a reachable element refers to a name that is not bound, as a behavior or a
+/// constraint as it is used, or a name is bound that only unreachable elements refer to.
+///
+fn check_strict(machine: &StateMachine, bindings: &Bindings) -> Result<()> {
+    let report = reachable(machine);
+    let mut unbound: BTreeSet<String> = Default::default();
+    let mut used: BTreeSet<String> = Default::default();
+    let mut pruned: BTreeSet<String> = Default::default();
+    let mut refer = |is_reachable: bool,
+                     behaviors: &[&Option<Box<dyn Behavior>>],
+                     constraints: &[&Option<Box<dyn Constraint>>]| {
+        let names = behaviors
+            .iter()
+            .filter_map(|behavior| opaque_behavior(behavior))
+            .flat_map(|opaque| behavior_names(opaque.body()))
+            .map(|name| (name.to_string(), bindings.is_bound(name)))
+            .chain(
+                constraints
+                    .iter()
+                    .filter_map(|constraint| opaque_constraint(constraint))
+                    .map(|opaque| without_arguments(opaque.body().trim()).to_string())
+                    .map(|name| {
+                        let is_bound = bindings.is_constraint_bound(&name);
+                        (name, is_bound)
+                    }),
+            );
+        for (name, is_bound) in names {
+            match (is_reachable, is_bound) {
+                (true, true) => {
+                    let _ = used.insert(name);
+                }
+                (true, false) => {
+                    let _ = unbound.insert(name);
+                }
+                (false, true) => {
+                    let _ = pruned.insert(name);
+                }
+                (false, false) => {}
+            }
+        }
+    };
+    machine.for_each_region(&mut |region| {
+        for vertex in region.vertices() {
+            if let Some(state) = vertex.as_state() {
+                refer(
+                    report.is_reachable(state.id()),
+                    &[&state.entry, &state.do_activity, &state.exit],
+                    &[&state.invariant],
+                );
+            }
+        }
+        for (index, transition) in region.transitions().iter().enumerate() {
+            let is_reachable = report.is_reachable(&transition.source())
+                && !report
+                    .never_enabled_transitions()
+                    .contains(&(region.id().clone(), index));
+            refer(is_reachable, &[&transition.effect], &[&transition.guard]);
+        }
+    });
+    let pruned: Vec<String> = pruned.difference(&used).cloned().collect();
+    if unbound.is_empty() && pruned.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorKind::BindingMismatch(unbound.into_iter().collect(), pruned).into())
+    }
+}
+
+fn opaque_behavior(behavior: &Option<Box<dyn Behavior>>) -> Option<&OpaqueBehavior> {
+    behavior.as_ref().and_then(|behavior| behavior.as_opaque())
+}
+
+fn opaque_constraint(constraint: &Option<Box<dyn Constraint>>) -> Option<&OpaqueConstraint> {
+    constraint
+        .as_ref()
+        .and_then(|constraint| constraint.as_opaque())
+}
+
+fn behavior_names(body: &str) -> Vec<&str> {
+    body.split([',', ';', '\n'])
+        .map(|name| without_arguments(name.trim()))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn without_arguments(name: &str) -> &str {
+    name.strip_suffix("()").unwrap_or(name)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::{Event, HasRegions, Region, State, Transition, Trigger};
+    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_bind() {
+        let machine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let initial_id = region.new_initial_state();
+        let mut state = State::within(region.id().clone());
+        state.set_entry(Box::new(OpaqueBehavior::new("log(); start_timer()", None)));
+        state.set_exit(Box::new(OpaqueBehavior::new("unknown", None)));
+        let _ = state.new_region();
+        let inner = state.default_region().unwrap();
+        let inner_initial = inner.new_initial_state();
+        let mut inner_state = State::within(inner.id().clone());
+        inner_state.set_invariant(Box::new(OpaqueConstraint::new("is_open", None)));
+        let inner_state_id = inner_state.id().clone();
+        inner.add_state(inner_state);
+        inner.new_transition(inner_initial, inner_state_id.clone());
+        let state_id = state.id().clone();
+        region.add_state(state);
+        let final_id = region.new_final_state();
+        region.new_transition(initial_id, state_id.clone());
+        let mut transition = Transition::within(state_id.clone(), final_id, region.id().clone());
+        transition.set_guard(Box::new(OpaqueConstraint::new("is_done()", None)));
+        transition.set_effect(Box::new(OpaqueBehavior::new("log", None)));
+        region.add_transition(transition);
+        machine.index_references();
+
+        let performed = Arc::new(Mutex::new(vec![]));
+        let mut bindings = Bindings::default();
+        for name in &["log", "start_timer"] {
+            let performed = performed.clone();
+            bindings.bind_in(name, move |_, _, _, _| {
+                performed.lock().unwrap().push(*name)
+            });
+        }
+        bindings.bind_constraint("is_open", |_, _, _| true);
+        assert_eq!(bindings.names(), vec!["is_open", "log", "start_timer"]);
+
+        let report = bind(&machine, &bindings).unwrap();
+        assert_eq!(report.bound(), 3);
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.unresolved_names(),
+            vec!["is_done".to_string(), "unknown".to_string()]
+        );
+        assert_eq!(report.unresolved()[0].element, state_id.to_string());
+
+        let raised: RefCell<Vec<Event>> = Default::default();
+        let vertex = machine.find_vertex(region.id().clone(), state_id).unwrap();
+        let state = vertex.as_state().unwrap();
+        let entry = state.entry().as_ref().unwrap();
+        entry.perform(&ID::random(), &Trigger::any(), &raised);
+        assert_eq!(*performed.lock().unwrap(), vec!["log", "start_timer"]);
+        assert_eq!(
+            entry.as_opaque().unwrap().body(),
+            &"log(); start_timer()".to_string()
+        );
+        assert!(state.exit().as_ref().unwrap().duplicate().is_some());
+        let inner = state.default_region().unwrap().vertices();
+        let invariant = inner[1].as_state().unwrap().invariant().as_ref().unwrap();
+        assert!(invariant.evaluate(&ID::random(), &Trigger::any()));
+        assert_eq!(invariant.label(), &Some("is_open".to_string()));
+        let guard = region.transitions()[1]
+            .guard()
+            .as_ref()
+            .unwrap()
+            .as_opaque()
+            .is_some();
+        assert!(guard);
+    }
+
+    #[test]
+    fn test_bind_strict() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut ordering = State::within(region.id().clone());
+        ordering.set_entry(Box::new(OpaqueBehavior::new("start", None)));
+        let ordering_id = ordering.id().clone();
+        region.add_state(ordering);
+        let shipped = region.new_final_state();
+        // auditing has no inbound transition, and only it refers to `audit` and `is_audited`.
+        let mut auditing = State::within(region.id().clone());
+        auditing.set_entry(Box::new(OpaqueBehavior::new("audit; start", None)));
+        let auditing_id = auditing.id().clone();
+        region.add_state(auditing);
+        region.new_transition(initial, ordering_id.clone());
+        let mut ship = Transition::within(ordering_id, shipped.clone(), region.id().clone());
+        ship.add_trigger(Trigger::signal("ship"));
+        ship.set_guard(Box::new(OpaqueConstraint::new("is_paid()", None)));
+        ship.set_effect(Box::new(OpaqueBehavior::new("ship", None)));
+        region.add_transition(ship);
+        let mut audited = Transition::within(auditing_id, shipped, region.id().clone());
+        audited.set_guard(Box::new(OpaqueConstraint::new("is_audited", None)));
+        region.add_transition(audited);
+
+        let bindings = |behaviors: &[&str], constraints: &[&str]| {
+            let mut bindings = Bindings::default();
+            for name in behaviors {
+                bindings.bind(name, |_, _, _| {});
+            }
+            for name in constraints {
+                bindings.bind_constraint(name, |_, _, _| true);
+            }
+            bindings
+        };
+        let mismatch =
+            |bindings: &Bindings| match bind_with(&machine, bindings, BindingMode::Strict)
+                .map_err(|e| e.0)
+            {
+                Err(ErrorKind::BindingMismatch(unbound, pruned)) => Some((unbound, pruned)),
+                Err(e) => panic!("unexpected error {:?}", e),
+                Ok(_) => None,
+            };
+
+        // `is_paid` is bound as a behavior, not as the constraint the guard needs.
+        assert_eq!(
+            mismatch(&bindings(&["start", "is_paid"], &[])),
+            Some((vec!["is_paid".to_string(), "ship".to_string()], vec![]))
+        );
+        assert_eq!(
+            mismatch(&bindings(
+                &["start", "ship", "audit"],
+                &["is_paid", "is_audited"]
+            )),
+            Some((vec![], vec!["audit".to_string(), "is_audited".to_string()]))
+        );
+        // a failed strict binding leaves the machine unchanged, and lenient binding binds the
+        // unreachable elements too.
+        let report = bind(&machine, &bindings(&["audit"], &[])).unwrap();
+        assert_eq!(report.bound(), 1);
+        assert_eq!(
+            report.unresolved_names(),
+            vec!["is_audited", "is_paid", "ship", "start"]
+        );
+
+        let report = bind_with(
+            &machine,
+            &bindings(&["start", "ship"], &["is_paid"]),
+            BindingMode::Strict,
+        )
+        .unwrap();
+        assert_eq!(report.bound(), 4);
+    }
+}
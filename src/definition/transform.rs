@@ -0,0 +1,1125 @@
+/*!
+Transformations from one machine to another, equivalent, machine.
+
+[`flatten`] creates a machine with a single region, and no composite states, from a machine
+whose states may contain regions. Each state of the flat machine is a state that has no regions
+of its own, its leaf, along with what the history of the regions that have history pseudostates
+remembers, so that a leaf may appear more than once where the history it has been reached with
+differs. The transitions of a flat state are those of its leaf and of each state containing it,
+the transitions of the innermost state taking priority, as they do when executing the
+hierarchical machine; the guard of a transition from a containing state is therefore only `true`
+where none of the transitions from within it triggered by the same event are enabled. Entry and
+exit behaviors are performed by the effects of the flat transitions, in the order the executor
+would perform them, and the branches of junctions are resolved into one transition for each path
+through them, as the executor does before firing a transition. Choices, and junctions reached
+by an initial or history transition, remain as pseudostates of the flat machine.
+
+Flattening copies the behaviors and constraints of the machine, see `Behavior::duplicate`, and
+the metadata of the machine and of each leaf, see `HasMetadata`. It
+fails with `TransformUnsupported` for those parts of a machine that have no flat equivalent:
+more than one region, orthogonal and sub-machine states, forks, joins, entry and exit points,
+and composite states with do-activities, invokes, or transitions triggered by time, change, or
+any-receive events. Where no branch of a junction is enabled the flat machine does not fire any
+transition, rather than failing as the executor does.
+
+# Example
+
+```rust
+use uml_state_machine::definition::builder::MachineBuilder;
+use uml_state_machine::definition::transform::flatten;
+use uml_state_machine::definition::types::*;
+
+let machine = MachineBuilder::new()
+    .region(|region| {
+        region
+            .initial("off")
+            .state("off", |state| state.on("power", "on"))
+            .state("on", |state| {
+                state
+                    .on("power", "off")
+                    .region(|inner| {
+                        inner
+                            .initial("idle")
+                            .state("idle", |state| state.on("go", "busy"))
+                            .state("busy", |state| state.on("done", "idle"))
+                    })
+            })
+    })
+    .build()
+    .unwrap();
+
+let flat = flatten(&machine).unwrap();
+let region = flat.default_region().unwrap();
+let labels: Vec<Option<String>> = region
+    .vertices()
+    .iter()
+    .filter_map(|vertex| vertex.as_state().map(|state| state.label().clone()))
+    .collect();
+assert_eq!(
+    labels,
+    vec![
+        Some("off".to_string()),
+        Some("on/idle".to_string()),
+        Some("on/busy".to_string()),
+    ]
+);
+// off -> on/idle, on/idle -> on/busy, on/idle -> off, on/busy -> on/idle, on/busy -> off
+assert_eq!(region.transitions().len(), 6);
+```
+
+*/
+
+use crate::core::ID;
+use crate::definition::behaviors;
+use crate::definition::guards::{all_ordered, is_else};
+use crate::definition::types::{
+    Behavior, Constraint, Contained, HasMetadata, HasRegions, Identified, Labeled, PseudoState,
+    PseudoStateKind, Region, RegionContainerType, State, StateMachine, Transition, TransitionKind,
+    Trigger, Validate, Vertex,
+};
+use crate::error::{ErrorKind, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Create a flat machine, equivalent to `machine`, in the same namespace; see the module
+/// documentation.
+///
+pub fn flatten(machine: &StateMachine) -> Result<StateMachine> {
+    let hierarchy = Hierarchy::new(machine)?;
+    let mut flat = match machine.namespace() {
+        None => StateMachine::default(),
+        Some(namespace) => StateMachine::in_namespace(&namespace),
+    };
+    if let Some(label) = machine.label() {
+        flat.set_label(label);
+    }
+    for (key, value) in machine.metadata() {
+        flat.set_metadata(key, value.deep_clone());
+    }
+    let region = flat.default_region().unwrap();
+    let mut flattening = Flattening {
+        hierarchy: &hierarchy,
+        region,
+        nodes: Default::default(),
+        pending: Default::default(),
+    };
+
+    let top = machine.default_region().unwrap().id();
+    let mut step = Step::default();
+    let target = match hierarchy.initials.get(top) {
+        None => return Err(ErrorKind::StateInitialState.into()),
+        Some(initial) => hierarchy.enter_vertex(initial, &mut step)?,
+    };
+    let initial = region.new_initial_state();
+    let target = flattening.node(target, step.history)?;
+    let mut transition = Transition::within(initial, target, region.id().clone());
+    if let Some(effect) = sequence(&step.effects)? {
+        transition.set_effect(effect);
+    }
+    region.add_transition(transition);
+
+    while let Some((node, id)) = flattening.pending.pop_front() {
+        flattening.transitions_from(&node, &id)?;
+    }
+    flat.validate()?;
+    Ok(flat)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The vertices and transitions of a hierarchical machine, and how they contain each other.
+///
+struct Hierarchy {
+    vertices: HashMap<ID, Arc<Vertex>>,
+    vertex_region: HashMap<ID, ID>,
+    region_owner: HashMap<ID, Option<ID>>,
+    regions_of: HashMap<ID, ID>,
+    initials: HashMap<ID, ID>,
+    histories: HashSet<ID>,
+    transitions: HashMap<ID, Vec<Arc<Transition>>>,
+}
+
+///
+/// A state of the flat machine, a leaf state, choice, junction, or terminate pseudostate, and
+/// the states remembered by the history of each region with a history pseudostate.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Node {
+    vertex: ID,
+    history: Vec<(ID, Vec<ID>)>,
+}
+
+///
+/// The behaviors performed by a flat transition, the states active, and the history, as it is
+/// fired.
+///
+#[derive(Default)]
+struct Step<'a> {
+    effects: Vec<&'a dyn Behavior>,
+    active: Vec<ID>,
+    history: Vec<(ID, Vec<ID>)>,
+}
+
+///
+/// A path through the branches of junctions, the guards that must hold to take it, and the
+/// vertex it reaches.
+///
+struct Path<'a> {
+    guards: Vec<Box<dyn Constraint>>,
+    branches: Vec<&'a Transition>,
+    target: ID,
+}
+
+struct Flattening<'a> {
+    hierarchy: &'a Hierarchy,
+    region: &'a Region,
+    nodes: HashMap<Node, ID>,
+    pending: VecDeque<(Node, ID)>,
+}
+
+///
+/// The negation of a constraint, the condition that a transition of an inner state is not
+/// enabled.
+///
+struct Not {
+    label: Option<String>,
+    constraint: Box<dyn Constraint>,
+}
+
+///
+/// The payload predicates of a trigger, as a constraint over the trigger an event arrives with.
+///
+struct Payload {
+    label: Option<String>,
+    trigger: Trigger,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Hierarchy {
+    fn new(machine: &StateMachine) -> Result<Self> {
+        if machine.regions().len() > 1 {
+            return Err(unsupported("machines with more than one region"));
+        }
+        let mut hierarchy = Self {
+            vertices: Default::default(),
+            vertex_region: Default::default(),
+            region_owner: Default::default(),
+            regions_of: Default::default(),
+            initials: Default::default(),
+            histories: Default::default(),
+            transitions: Default::default(),
+        };
+        let mut result = Ok(());
+        machine.for_each_region(&mut |region| {
+            if result.is_ok() {
+                result = hierarchy.add_region(region);
+            }
+        });
+        result.map(|_| hierarchy)
+    }
+
+    fn add_region(&mut self, region: &Region) -> Result<()> {
+        let owner = match region.container_type() {
+            RegionContainerType::State => Some(region.container()),
+            RegionContainerType::StateMachine => None,
+        };
+        let _ = self
+            .region_owner
+            .insert(region.id().clone(), owner.cloned());
+        for vertex in region.vertices() {
+            let id = vertex.id().clone();
+            match vertex.as_ref() {
+                Vertex::State(state) => {
+                    if state.regions().len() > 1 {
+                        return Err(unsupported("orthogonal states"));
+                    } else if state.sub_machine().is_some() {
+                        return Err(unsupported("sub-machine states"));
+                    }
+                    if let Some(inner) = state.default_region() {
+                        let _ = self.regions_of.insert(id.clone(), inner.id().clone());
+                        if state.do_activity().is_some() {
+                            return Err(unsupported("do-activities of composite states"));
+                        } else if state.invokes().next().is_some() {
+                            return Err(unsupported("invokes of composite states"));
+                        }
+                    }
+                }
+                Vertex::PseudoState(pseudo_state) => match pseudo_state.kind() {
+                    PseudoStateKind::Initial => {
+                        let _ = self.initials.insert(region.id().clone(), id.clone());
+                    }
+                    PseudoStateKind::ShallowHistory | PseudoStateKind::DeepHistory => {
+                        let _ = self.histories.insert(region.id().clone());
+                    }
+                    PseudoStateKind::Choice
+                    | PseudoStateKind::Junction
+                    | PseudoStateKind::Terminate => (),
+                    kind => return Err(unsupported(&format!("{:?} pseudostates", kind))),
+                },
+                Vertex::ConnectionPointReference(_) => {
+                    return Err(unsupported("connection point references"))
+                }
+            }
+            let _ = self.vertex_region.insert(id.clone(), region.id().clone());
+            let _ = self.vertices.insert(id, vertex);
+        }
+        for transition in region.transitions() {
+            self.transitions
+                .entry(transition.source())
+                .or_default()
+                .push(transition);
+        }
+        Ok(())
+    }
+
+    fn transitions_from(&self, vertex: &ID) -> &[Arc<Transition>] {
+        self.transitions.get(vertex).map_or(&[], Vec::as_slice)
+    }
+
+    fn state(&self, id: &ID) -> Option<&State> {
+        self.vertices.get(id).and_then(|vertex| vertex.as_state())
+    }
+
+    fn parent(&self, vertex: &ID) -> Option<&ID> {
+        self.vertex_region
+            .get(vertex)
+            .and_then(|region| self.region_owner[region].as_ref())
+    }
+
+    ///
+    /// The states that are, or contain, `vertex`, innermost first.
+    ///
+    fn chain(&self, vertex: &ID) -> Vec<ID> {
+        let mut chain: Vec<ID> = Default::default();
+        if self.state(vertex).is_some() {
+            chain.push(vertex.clone());
+        }
+        let mut current = vertex;
+        while let Some(parent) = self.parent(current) {
+            chain.push(parent.clone());
+            current = parent;
+        }
+        chain
+    }
+
+    fn is_within(&self, vertex: &ID, ancestor: &ID) -> bool {
+        let mut current = Some(vertex);
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            current = self.parent(id);
+        }
+        false
+    }
+
+    fn regions_containing(&self, vertex: &ID) -> Vec<&ID> {
+        let mut regions: Vec<&ID> = Default::default();
+        let mut current = vertex;
+        while let Some(region) = self.vertex_region.get(current) {
+            regions.push(region);
+            match &self.region_owner[region] {
+                None => break,
+                Some(owner) => current = owner,
+            }
+        }
+        regions
+    }
+
+    fn common_region(&self, lhs: &ID, rhs: &ID) -> Option<ID> {
+        let rhs_regions = self.regions_containing(rhs);
+        self.regions_containing(lhs)
+            .into_iter()
+            .find(|region| rhs_regions.contains(region))
+            .cloned()
+    }
+
+    fn ancestor_in(&self, vertex: &ID, region: &ID) -> ID {
+        let mut current = vertex.clone();
+        while let Some(container) = self.vertex_region.get(&current) {
+            if container == region {
+                break;
+            }
+            match self.parent(&current) {
+                None => break,
+                Some(parent) => current = parent.clone(),
+            }
+        }
+        current
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    ///
+    /// Fire `transition`, having taken the junction branches of `path`, from `node`; as for the
+    /// executor, exiting the states between the transition's source and the region containing
+    /// both it and its target, then performing the effects and entering the target.
+    ///
+    fn fire<'a>(
+        &'a self,
+        node: &Node,
+        transition: &'a Transition,
+        path: &Path<'a>,
+    ) -> Result<(ID, Step<'a>)> {
+        let mut step = Step {
+            effects: Default::default(),
+            active: self.chain(&node.vertex),
+            history: node.history.clone(),
+        };
+        let source = transition.source();
+        let target = &path.target;
+        let local = transition.is_local() && &source != target;
+        let (exiting, entering): (Vec<ID>, Option<ID>) = if local && self.is_within(target, &source)
+        {
+            let within = self.within(&step.active, &source, false);
+            (within, None)
+        } else if local && self.is_within(&source, target) {
+            let within = self.within(&step.active, target, false);
+            (within, Some(target.clone()))
+        } else {
+            let exiting = match self.common_region(&source, target) {
+                None => return Err(ErrorKind::TransitionTargetState.into()),
+                Some(common) => self.ancestor_in(&source, &common),
+            };
+            (self.within(&step.active, &exiting, true), None)
+        };
+        // history remembers the states active as the transition fired, not those left in turn
+        let active = step.active.clone();
+        for state in &exiting {
+            self.exit_state(state, &active, &mut step);
+        }
+        if let Some(effect) = transition.effect() {
+            step.effects.push(effect.as_ref());
+        }
+        for branch in &path.branches {
+            if let Some(effect) = branch.effect() {
+                step.effects.push(effect.as_ref());
+            }
+        }
+        let vertex = match entering {
+            // a local transition from within its target re-enters the target's region by default
+            Some(target) => self.enter_region(&self.regions_of[&target], &mut step)?,
+            None => self.enter_target(target, &mut step)?,
+        };
+        Ok((vertex, step))
+    }
+
+    ///
+    /// The states of `active` within `ancestor`, innermost first, including `ancestor` itself
+    /// only if `inclusive`.
+    ///
+    fn within(&self, active: &[ID], ancestor: &ID, inclusive: bool) -> Vec<ID> {
+        active
+            .iter()
+            .filter(|state| self.is_within(state, ancestor) && (inclusive || *state != ancestor))
+            .cloned()
+            .collect()
+    }
+
+    fn exit_state<'a>(&'a self, state: &ID, active: &[ID], step: &mut Step<'a>) {
+        let region = &self.vertex_region[state];
+        if self.histories.contains(region) {
+            let mut remembered = self.within(active, state, true);
+            remembered.reverse();
+            remember(&mut step.history, region, remembered);
+        }
+        if let Some(exit) = self.state(state).and_then(|state| state.exit().as_ref()) {
+            step.effects.push(exit.as_ref());
+        }
+        step.active.retain(|active| active != state);
+    }
+
+    fn activate<'a>(&'a self, state: &ID, step: &mut Step<'a>) {
+        if !step.active.contains(state) {
+            step.active.insert(0, state.clone());
+            if let Some(entry) = self.state(state).and_then(|state| state.entry().as_ref()) {
+                step.effects.push(entry.as_ref());
+            }
+        }
+    }
+
+    ///
+    /// Enter `target`, and the states containing it that are not already active; returning the
+    /// vertex of the flat state reached.
+    ///
+    fn enter_target<'a>(&'a self, target: &ID, step: &mut Step<'a>) -> Result<ID> {
+        let mut ancestors = self.chain(target);
+        if self.state(target).is_some() {
+            let _ = ancestors.remove(0);
+        }
+        for ancestor in ancestors.iter().rev() {
+            self.activate(ancestor, step);
+        }
+        self.enter_vertex(target, step)
+    }
+
+    fn enter_region<'a>(&'a self, region: &ID, step: &mut Step<'a>) -> Result<ID> {
+        match self.initials.get(region) {
+            None => Err(ErrorKind::StateInitialState.into()),
+            Some(initial) => self.enter_vertex(initial, step),
+        }
+    }
+
+    fn enter_vertex<'a>(&'a self, id: &ID, step: &mut Step<'a>) -> Result<ID> {
+        let vertex = match self.vertices.get(id) {
+            None => return Err(ErrorKind::TransitionTargetState.into()),
+            Some(vertex) => vertex,
+        };
+        match vertex.as_ref() {
+            Vertex::State(state) => {
+                self.activate(id, step);
+                match self.regions_of.get(id) {
+                    Some(region) if !state.is_final() => self.enter_region(region, step),
+                    _ => Ok(id.clone()),
+                }
+            }
+            Vertex::PseudoState(pseudo_state) => match pseudo_state.kind() {
+                PseudoStateKind::Initial => match self.transitions_from(id).first() {
+                    None => Err(ErrorKind::StateInitialState.into()),
+                    Some(transition) => self.follow(transition, step),
+                },
+                kind @ PseudoStateKind::ShallowHistory | kind @ PseudoStateKind::DeepHistory => {
+                    let region = &self.vertex_region[id];
+                    let remembered = step
+                        .history
+                        .iter()
+                        .find(|(remembering, _)| remembering == region)
+                        .map(|(_, remembered)| remembered.clone());
+                    match remembered {
+                        Some(remembered) if !remembered.is_empty() => self.restore_region(
+                            region,
+                            &remembered,
+                            kind == PseudoStateKind::DeepHistory,
+                            step,
+                        ),
+                        _ => match self.transitions_from(id).first() {
+                            None => self.enter_region(region, step),
+                            Some(transition) => self.follow(transition, step),
+                        },
+                    }
+                }
+                _ => Ok(id.clone()),
+            },
+            Vertex::ConnectionPointReference(_) => Err(unsupported("connection point references")),
+        }
+    }
+
+    fn follow<'a>(&'a self, transition: &'a Transition, step: &mut Step<'a>) -> Result<ID> {
+        if let Some(effect) = transition.effect() {
+            step.effects.push(effect.as_ref());
+        }
+        self.enter_target(&transition.target(), step)
+    }
+
+    fn restore_region<'a>(
+        &'a self,
+        region: &ID,
+        remembered: &[ID],
+        deep: bool,
+        step: &mut Step<'a>,
+    ) -> Result<ID> {
+        let state = remembered
+            .iter()
+            .find(|id| self.vertex_region.get(*id) == Some(region));
+        match state {
+            None => self.enter_region(region, step),
+            Some(state) if deep && self.regions_of.contains_key(state) => {
+                self.activate(state, step);
+                self.restore_region(&self.regions_of[state], remembered, deep, step)
+            }
+            Some(state) => self.enter_vertex(state, step),
+        }
+    }
+
+    ///
+    /// The paths from `transition`, through any junctions, to the vertices it may reach; where
+    /// the executor takes the first branch of a junction whose guard holds, otherwise its `else`
+    /// branch.
+    ///
+    fn paths<'a>(&'a self, transition: &'a Transition) -> Result<Vec<Path<'a>>> {
+        let mut paths: Vec<Path<'a>> = Default::default();
+        self.extend_paths(
+            Path {
+                guards: Default::default(),
+                branches: Default::default(),
+                target: transition.target(),
+            },
+            &mut paths,
+        )?;
+        Ok(paths)
+    }
+
+    fn extend_paths<'a>(&'a self, path: Path<'a>, paths: &mut Vec<Path<'a>>) -> Result<()> {
+        let is_junction = self
+            .vertices
+            .get(&path.target)
+            .and_then(|vertex| vertex.as_pseudo_state())
+            .is_some_and(PseudoState::is_junction);
+        if !is_junction {
+            paths.push(path);
+            return Ok(());
+        }
+        let branches = self.transitions_from(&path.target);
+        let guarded: Vec<&Arc<Transition>> = branches
+            .iter()
+            .filter(|branch| {
+                !branch
+                    .guard()
+                    .as_ref()
+                    .is_some_and(|guard| is_else(guard.as_ref()))
+            })
+            .collect();
+        let otherwise = branches.iter().find(|branch| {
+            branch
+                .guard()
+                .as_ref()
+                .is_some_and(|guard| is_else(guard.as_ref()))
+        });
+        let mut preceding: Vec<&Arc<Transition>> = Default::default();
+        for branch in guarded.iter().copied().chain(otherwise) {
+            let is_otherwise = otherwise.is_some_and(|otherwise| Arc::ptr_eq(otherwise, branch));
+            let mut guards = duplicate_all(&path.guards)?;
+            for earlier in &preceding {
+                match earlier.guard() {
+                    // an unguarded branch is always taken before those that follow it
+                    None => return Ok(()),
+                    Some(guard) => guards.push(not(duplicate_constraint(guard.as_ref())?)),
+                }
+            }
+            if !is_otherwise {
+                if let Some(guard) = branch.guard() {
+                    guards.push(duplicate_constraint(guard.as_ref())?);
+                }
+                preceding.push(branch);
+            }
+            let mut branches = path.branches.clone();
+            branches.push(branch.as_ref());
+            self.extend_paths(
+                Path {
+                    guards,
+                    branches,
+                    target: branch.target(),
+                },
+                paths,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl<'a> Flattening<'a> {
+    ///
+    /// The ID of the flat state for `vertex` with `history`, creating it where it is new.
+    ///
+    fn node(&mut self, vertex: ID, mut history: Vec<(ID, Vec<ID>)>) -> Result<ID> {
+        // regions within active states will be remembered again as they are exited
+        let active = self.hierarchy.chain(&vertex);
+        history.retain(|(region, _)| {
+            !self.hierarchy.region_owner[region]
+                .as_ref()
+                .is_some_and(|owner| active.contains(owner))
+        });
+        let node = Node { vertex, history };
+        if let Some(id) = self.nodes.get(&node) {
+            return Ok(id.clone());
+        }
+        let hierarchy = self.hierarchy;
+        let id = match hierarchy.vertices[&node.vertex].as_ref() {
+            Vertex::State(leaf) => {
+                let mut state = State::within(self.region.id().clone());
+                if let Some(label) = self.label(&node) {
+                    state.set_label(&label);
+                }
+                for comment in leaf.comments() {
+                    state.add_comment(comment);
+                }
+                for (key, value) in leaf.metadata() {
+                    state.set_metadata(key, value.deep_clone());
+                }
+                state.set_final(leaf.is_final() && hierarchy.parent(&node.vertex).is_none());
+                // leaves, unlike the states containing them, may have activities and invokes
+                if let Some(do_activity) = leaf.do_activity() {
+                    state.set_do_activity(duplicate_behavior(do_activity.as_ref())?);
+                }
+                for invoke in leaf.invokes() {
+                    state.add_invoke(invoke.clone());
+                }
+                for ancestor in hierarchy.chain(&node.vertex) {
+                    for trigger in hierarchy.state(&ancestor).unwrap().deferrable_triggers() {
+                        state.add_deferrable_trigger(trigger.clone());
+                    }
+                }
+                let id = state.id().clone();
+                self.region.add_state(state);
+                id
+            }
+            Vertex::PseudoState(original) => {
+                let mut pseudo_state =
+                    PseudoState::within(self.region.id().clone(), original.kind());
+                if let Some(label) = original.label() {
+                    pseudo_state.set_label(label);
+                }
+                let id = pseudo_state.id().clone();
+                self.region.add_pseudo_state(pseudo_state);
+                id
+            }
+            Vertex::ConnectionPointReference(_) => unreachable!(),
+        };
+        let _ = self.nodes.insert(node.clone(), id.clone());
+        self.pending.push_back((node, id.clone()));
+        Ok(id)
+    }
+
+    ///
+    /// The labels of the leaf and the states containing it, outermost first, followed by those
+    /// of the states remembered by history.
+    ///
+    fn label(&self, node: &Node) -> Option<String> {
+        let hierarchy = self.hierarchy;
+        let path = |vertex: &ID| -> Vec<String> {
+            hierarchy
+                .chain(vertex)
+                .iter()
+                .rev()
+                .filter_map(|state| hierarchy.state(state).unwrap().label().clone())
+                .collect()
+        };
+        let labels = path(&node.vertex);
+        if labels.is_empty() {
+            return None;
+        }
+        let remembered: Vec<String> = node
+            .history
+            .iter()
+            .filter_map(|(_, remembered)| remembered.last())
+            .map(|state| path(state).join("/"))
+            .collect();
+        if remembered.is_empty() {
+            Some(labels.join("/"))
+        } else {
+            Some(format!("{} [{}]", labels.join("/"), remembered.join(", ")))
+        }
+    }
+
+    ///
+    /// Add the flat transitions from `node`, whose flat state is `id`.
+    ///
+    fn transitions_from(&mut self, node: &Node, id: &ID) -> Result<()> {
+        let hierarchy = self.hierarchy;
+        let leaf = hierarchy.state(&node.vertex);
+        let levels: Vec<ID> = match leaf {
+            Some(_) => hierarchy.chain(&node.vertex),
+            None => vec![node.vertex.clone()],
+        };
+        for (depth, level) in levels.iter().enumerate() {
+            for transition in hierarchy.transitions_from(level) {
+                if !transition.has_triggers() {
+                    // completion transitions of a composite state are taken from its final states
+                    let completes = depth == 0
+                        || (depth == 1 && leaf.is_some_and(State::is_final))
+                        || leaf.is_none();
+                    if completes {
+                        self.add_transition(node, id, transition, None, Default::default())?;
+                    }
+                    continue;
+                }
+                if depth > 0 {
+                    check_composite_triggers(transition)?;
+                }
+                for trigger in transition.triggers() {
+                    if let Some(negated) = self.overridden(&levels[..depth], trigger)? {
+                        self.add_transition(node, id, transition, Some(trigger), negated)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// The negated conditions of the transitions from `inner` states that take priority over
+    /// one from the state containing them triggered by `trigger`; `None` where one always does.
+    ///
+    fn overridden(
+        &self,
+        inner: &[ID],
+        trigger: &Trigger,
+    ) -> Result<Option<Vec<Box<dyn Constraint>>>> {
+        let mut negated: Vec<Box<dyn Constraint>> = Default::default();
+        for state in inner {
+            for transition in self.hierarchy.transitions_from(state) {
+                for inner_trigger in transition.triggers() {
+                    if !inner_trigger.matches(trigger.event()) {
+                        continue;
+                    }
+                    let mut condition: Vec<Box<dyn Constraint>> = Default::default();
+                    if inner_trigger.payload_predicates().next().is_some() {
+                        condition.push(Box::new(Payload {
+                            label: Some(inner_trigger.to_string()),
+                            trigger: inner_trigger.clone(),
+                        }));
+                    }
+                    if let Some(guard) = transition.guard() {
+                        condition.push(duplicate_constraint(guard.as_ref())?);
+                    }
+                    if condition.is_empty() {
+                        return Ok(None);
+                    } else if inner_trigger.is_any_receive() {
+                        return Err(unsupported(
+                            "guarded any-receive transitions within composite states",
+                        ));
+                    }
+                    negated.push(not(conjunction(condition).unwrap()));
+                }
+            }
+        }
+        Ok(Some(negated))
+    }
+
+    fn add_transition(
+        &mut self,
+        node: &Node,
+        id: &ID,
+        transition: &'a Transition,
+        trigger: Option<&Trigger>,
+        negated: Vec<Box<dyn Constraint>>,
+    ) -> Result<()> {
+        let hierarchy = self.hierarchy;
+        if transition.is_internal() && transition.source() == transition.target() {
+            let mut flat = Transition::within(id.clone(), id.clone(), self.region.id().clone());
+            flat.set_kind(TransitionKind::Internal);
+            let mut guards = negated;
+            if let Some(guard) = transition.guard() {
+                guards.push(duplicate_constraint(guard.as_ref())?);
+            }
+            let effects = transition.effect().iter().map(|e| e.as_ref()).collect();
+            return self.add(flat, transition, trigger, guards, effects);
+        }
+        for path in hierarchy.paths(transition)? {
+            let (target, step) = hierarchy.fire(node, transition, &path)?;
+            let target = self.node(target, step.history.clone())?;
+            // the leaf is always exited, so a flat transition to its own source re-enters it
+            let mut flat = Transition::within(id.clone(), target, self.region.id().clone());
+            flat.set_kind(TransitionKind::External);
+            let mut guards = duplicate_all(&negated)?;
+            if let Some(guard) = transition.guard() {
+                guards.push(duplicate_constraint(guard.as_ref())?);
+            }
+            guards.extend(path.guards);
+            self.add(flat, transition, trigger, guards, step.effects)?;
+        }
+        Ok(())
+    }
+
+    fn add(
+        &self,
+        mut flat: Transition,
+        original: &Transition,
+        trigger: Option<&Trigger>,
+        guards: Vec<Box<dyn Constraint>>,
+        effects: Vec<&dyn Behavior>,
+    ) -> Result<()> {
+        if let Some(label) = original.label() {
+            flat.set_label(label);
+        }
+        if let Some(trigger) = trigger {
+            flat.add_trigger(trigger.clone());
+        }
+        if let Some(guard) = conjunction(guards) {
+            flat.set_guard(guard);
+        }
+        if let Some(effect) = sequence(&effects)? {
+            flat.set_effect(effect);
+        }
+        self.region.add_transition(flat);
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Labeled for Not {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Constraint for Not {
+    fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool {
+        !self.constraint.evaluate(in_state, on_trigger)
+    }
+
+    fn evaluate_in(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        context: &crate::core::Context,
+    ) -> bool {
+        !self.constraint.evaluate_in(in_state, on_trigger, context)
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(not(self.constraint.duplicate()?))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Labeled for Payload {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Constraint for Payload {
+    fn evaluate(&self, _in_state: &ID, on_trigger: &Trigger) -> bool {
+        self.trigger
+            .matches_payload(&on_trigger.payload().cloned().unwrap_or_default())
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(Payload {
+            label: self.label.clone(),
+            trigger: self.trigger.clone(),
+        }))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn unsupported(feature: &str) -> crate::error::Error {
+    ErrorKind::TransformUnsupported(feature.to_string()).into()
+}
+
+///
+/// Transitions from composite states are taken from each of the leaves within them, so that
+/// they may only be triggered by events that do not depend on when the state was entered.
+///
+fn check_composite_triggers(transition: &Transition) -> Result<()> {
+    for trigger in transition.triggers() {
+        let event = trigger.event();
+        if event.is_time() || event.is_change() {
+            return Err(unsupported("time and change events on composite states"));
+        } else if event.is_any_receive() {
+            return Err(unsupported("any-receive events on composite states"));
+        }
+    }
+    Ok(())
+}
+
+fn remember(history: &mut Vec<(ID, Vec<ID>)>, region: &ID, remembered: Vec<ID>) {
+    match history
+        .iter_mut()
+        .find(|(remembering, _)| remembering == region)
+    {
+        Some((_, existing)) => *existing = remembered,
+        None => {
+            history.push((region.clone(), remembered));
+            history.sort_by_key(|(region, _)| region.to_string());
+        }
+    }
+}
+
+fn not(constraint: Box<dyn Constraint>) -> Box<dyn Constraint> {
+    Box::new(Not {
+        label: constraint
+            .label()
+            .as_ref()
+            .map(|label| format!("!({})", label)),
+        constraint,
+    })
+}
+
+fn conjunction(mut constraints: Vec<Box<dyn Constraint>>) -> Option<Box<dyn Constraint>> {
+    match constraints.len() {
+        0 => None,
+        1 => constraints.pop(),
+        _ => Some(Box::new(all_ordered(constraints))),
+    }
+}
+
+fn sequence(behaviors: &[&dyn Behavior]) -> Result<Option<Box<dyn Behavior>>> {
+    let mut copies: Vec<Box<dyn Behavior>> = behaviors
+        .iter()
+        .map(|behavior| duplicate_behavior(*behavior))
+        .collect::<Result<Vec<Box<dyn Behavior>>>>()?;
+    Ok(match copies.len() {
+        0 => None,
+        1 => copies.pop(),
+        _ => Some(Box::new(behaviors::sequence(copies))),
+    })
+}
+
+fn duplicate_behavior(behavior: &dyn Behavior) -> Result<Box<dyn Behavior>> {
+    behavior
+        .duplicate()
+        .ok_or_else(|| not_duplicable(behavior.label()))
+}
+
+fn duplicate_constraint(constraint: &dyn Constraint) -> Result<Box<dyn Constraint>> {
+    constraint
+        .duplicate()
+        .ok_or_else(|| not_duplicable(constraint.label()))
+}
+
+fn duplicate_all(constraints: &[Box<dyn Constraint>]) -> Result<Vec<Box<dyn Constraint>>> {
+    constraints
+        .iter()
+        .map(|constraint| duplicate_constraint(constraint.as_ref()))
+        .collect()
+}
+
+fn not_duplicable(label: &Option<String>) -> crate::error::Error {
+    ErrorKind::ModelNotDuplicable(label.clone().unwrap_or_default()).into()
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::builder::MachineBuilder;
+    use crate::definition::opaque::OpaqueBehavior;
+
+    fn flat_state(region: &Region, label: &str) -> Arc<Vertex> {
+        region
+            .vertices()
+            .into_iter()
+            .find(|vertex| {
+                vertex
+                    .as_state()
+                    .is_some_and(|state| state.label().as_deref() == Some(label))
+            })
+            .unwrap()
+    }
+
+    fn effect_between(region: &Region, source: &str, target: &str) -> Option<String> {
+        let source = flat_state(region, source).id().clone();
+        let target = flat_state(region, target).id().clone();
+        let transition = region
+            .transitions()
+            .into_iter()
+            .find(|transition| transition.source() == source && transition.target() == target)
+            .unwrap();
+        transition
+            .effect()
+            .as_ref()
+            .and_then(|effect| effect.label().clone())
+    }
+
+    #[test]
+    fn test_flatten_history() {
+        let machine = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("off")
+                    .state("off", |state| state.on("power", "history"))
+                    .state("on", |state| {
+                        state
+                            .on_entry(OpaqueBehavior::new("enter_on", None))
+                            .on_exit(OpaqueBehavior::new("exit_on", None))
+                            .on("power", "off")
+                            .region(|inner| {
+                                inner
+                                    .initial("a")
+                                    .shallow_history("history")
+                                    .state("a", |state| {
+                                        state
+                                            .on_entry(OpaqueBehavior::new("enter_a", None))
+                                            .on("go", "b")
+                                    })
+                                    .state("b", |state| {
+                                        state
+                                            .on_exit(OpaqueBehavior::new("exit_b", None))
+                                            .on("go", "a")
+                                    })
+                            })
+                    })
+            })
+            .build()
+            .unwrap();
+
+        let flat = flatten(&machine).unwrap();
+        assert_eq!(flat.regions().len(), 1);
+        let region = flat.default_region().unwrap();
+        let states: Vec<Arc<Vertex>> = region
+            .vertices()
+            .into_iter()
+            .filter(|vertex| vertex.as_state().is_some())
+            .collect();
+        assert_eq!(states.len(), 5);
+        assert!(states
+            .iter()
+            .all(|state| state.as_state().unwrap().default_region().is_none()));
+        assert_eq!(region.transitions().len(), 8);
+
+        assert_eq!(
+            effect_between(region, "off", "on/a"),
+            Some("enter_on; enter_a".to_string())
+        );
+        assert_eq!(
+            effect_between(region, "on/b", "off [on/b]"),
+            Some("exit_b; exit_on".to_string())
+        );
+        assert_eq!(
+            effect_between(region, "off [on/b]", "on/b"),
+            Some("enter_on".to_string())
+        );
+        assert_eq!(
+            effect_between(region, "off [on/a]", "on/a"),
+            Some("enter_on; enter_a".to_string())
+        );
+        assert_eq!(effect_between(region, "on/a", "on/b"), None);
+    }
+
+    #[test]
+    fn test_flatten_unsupported() {
+        let machine = MachineBuilder::new()
+            .region(|region| {
+                region.initial("on").state("on", |state| {
+                    state
+                        .timeout(std::time::Duration::from_secs(1), "on")
+                        .region(|inner| inner.initial("a").simple_state("a"))
+                })
+            })
+            .build()
+            .unwrap();
+        match flatten(&machine) {
+            Err(crate::error::Error(ErrorKind::TransformUnsupported(_), _)) => (),
+            _ => panic!("expected TransformUnsupported"),
+        }
+    }
+}
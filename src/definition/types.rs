@@ -7,21 +7,22 @@ More detailed description, with
 
 */
 
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fmt::Debug;
-use std::rc::Rc;
 use std::slice::Iter;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime};
 
-use crate::core::ID;
-use crate::error::Result;
+use crate::core::context::FieldPath;
+use crate::core::{Context, FieldKind, FieldValue, ID};
+use crate::definition::activities::DoActivityHandle;
+use crate::definition::guards::GuardOutcome;
+use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+use crate::error::{ErrorKind, Result};
 
 // ------------------------------------------------------------------------------------------------
 // Public Traits
 // ------------------------------------------------------------------------------------------------
 
-pub trait Event: Debug {}
-
 pub trait HasRegions: Identified {
     fn has_regions(&self) -> bool;
 
@@ -44,6 +45,23 @@ pub trait Labeled {
     fn unset_label(&mut self);
 }
 
+///
+/// Tagged values attached to an element, such as its owner or hints for a user interface, which
+/// neither the model nor the executor interpret; these are kept by `StateMachine::duplicate` and
+/// by the native format.
+///
+pub trait HasMetadata {
+    fn metadata(&self) -> &HashMap<String, FieldValue>;
+
+    fn metadata_value(&self, key: &str) -> Option<&FieldValue> {
+        self.metadata().get(key)
+    }
+
+    fn set_metadata(&mut self, key: &str, value: FieldValue);
+
+    fn unset_metadata(&mut self, key: &str) -> Option<FieldValue>;
+}
+
 pub trait Identified {
     fn id(&self) -> &ID;
 }
@@ -73,17 +91,30 @@ pub struct StateMachine {
     pub(crate) sub_machine_states: Vec<ID>,
     /// **UML**: `{subsets ownedMember} +connectionPoint *`
     pub(crate) connection_points: Vec<PseudoState>,
-    pub(crate) ref_machines: RefCell<HashMap<ID, Rc<StateMachine>>>,
-    pub(crate) ref_vertices: RefCell<HashMap<(ID, ID), Rc<Vertex>>>,
+    /// **UML**: `{subsets redefinedElement} +extendedStateMachine *`, a machine may extend at
+    /// most one other here.
+    pub(crate) extended: Option<ID>,
+    /// Tagged values, see `HasMetadata`.
+    pub(crate) metadata: HashMap<String, FieldValue>,
+    /// Machines referenced by sub-machine states; these are not owned, so that two machines
+    /// referencing each other do not form an `Arc` cycle.
+    pub(crate) ref_machines: RwLock<HashMap<ID, Weak<StateMachine>>>,
+    /// An index over vertices owned by regions, it does not keep them alive.
+    pub(crate) ref_vertices: RwLock<HashMap<(ID, ID), Weak<Vertex>>>,
+    /// An index over transitions owned by regions, `None` until `index_references` is called.
+    pub(crate) ref_transitions: RwLock<Option<TransitionIndex>>,
 }
 
 // ------------------------------------------------------------------------------------------------
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RegionContainerType {
     State,
     StateMachine,
 }
 
+// vertices are always shared within an `Arc`, so the size of the largest variant is not copied
+#[allow(clippy::large_enum_variant)]
 pub enum Vertex {
     State(State),
     PseudoState(PseudoState),
@@ -96,16 +127,123 @@ pub struct Region {
     /// **UML**: `{subsets namespace} +stateMachine 0..1 : StateMachine`
     pub(crate) container: ID,
     pub(crate) container_type: RegionContainerType,
+    /// **UML**: `{subsets redefinedElement} +extendedRegion 0..1`
+    pub(crate) extended: Option<ID>,
+    /// Tagged values, see `HasMetadata`.
+    pub(crate) metadata: HashMap<String, FieldValue>,
     /// **UML**: `{subsets ownedMember} +subvertex *`
-    pub(crate) vertices: Rc<RefCell<Vec<Rc<Vertex>>>>,
+    pub(crate) vertices: Arc<RwLock<Vec<Arc<Vertex>>>>,
     /// **UML**: `{subsets ownedMember} +transition *`
-    pub(crate) transitions: Rc<RefCell<Vec<Rc<Transition>>>>,
+    pub(crate) transitions: Arc<RwLock<Vec<Arc<Transition>>>>,
 }
 
 // ------------------------------------------------------------------------------------------------
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Trigger {
-    pub(crate) event: Option<Box<dyn Event>>,
+    /// **UML**: `+event 1 : Event`
+    pub(crate) event: Event,
+    /// Predicates over the event's payload, all of which must hold for the trigger to fire.
+    pub(crate) predicates: Vec<PayloadPredicate>,
+    /// The payload of the event occurrence that fired the trigger, as given by the executor to
+    /// guards and behaviors; the triggers of a machine have no payload.
+    pub(crate) payload: Option<Context>,
+}
+
+///
+/// A comparison between a field of an event's payload, addressed by path, and a constant value.
+/// These are evaluated as part of trigger matching, and so before any guard on the transition.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PayloadPredicate {
+    pub(crate) path: FieldPath,
+    pub(crate) operator: ComparisonOperator,
+    pub(crate) value: FieldValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ComparisonOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+///
+/// The kinds of event that a trigger may reference, from §13.3.3.1 **Events**.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// **UML**: `SignalEvent`, the receipt of an asynchronous signal.
+    Signal(SignalEvent),
+    /// **UML**: `CallEvent`, the receipt of a request to invoke an operation.
+    Call(CallEvent),
+    /// **UML**: `TimeEvent`, a point in time has been reached.
+    Time(TimeEvent),
+    /// **UML**: `ChangeEvent`, a boolean expression has become true.
+    Change(ChangeEvent),
+    /// **UML**: `AnyReceiveEvent`, the receipt of any message not explicitly handled.
+    AnyReceive,
+}
+
+///
+/// Signal events are equal where they name the same signal, their payload schemas describe the
+/// signal rather than distinguishing occurrences of it.
+///
+#[derive(Clone, Debug)]
+pub struct SignalEvent {
+    /// **UML**: `+signal 1 : Signal`
+    pub(crate) signal: String,
+    /// **UML**: `Signal::ownedAttribute *`, the fields of the signal's payload and the kind of
+    /// value each holds; an empty schema accepts any payload.
+    pub(crate) payload: Vec<(FieldPath, FieldKind)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CallEvent {
+    /// **UML**: `+operation 1 : Operation`
+    pub(crate) operation: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeEvent {
+    /// **UML**: `isRelative = true`, measured from the time the trigger becomes active.
+    Relative(Duration),
+    /// **UML**: `isRelative = false`, an absolute time.
+    Absolute(SystemTime),
+}
+
+#[derive(Clone)]
+pub struct ChangeEvent {
+    /// **UML**: `+changeExpression 1 : ValueSpecification`
+    pub(crate) change_expression: Arc<dyn Constraint>,
+}
+
+///
+/// The instance to which a behavior sends an event, see `EventSink::send`.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SendTarget {
+    /// The instance with this ID, as for the SCXML `target` attribute.
+    Instance(ID),
+    /// The instance that started the sending instance, as for the SCXML `#_parent` target.
+    Parent,
+    /// The sending instance itself, the event is queued as an external event.
+    This,
+}
+
+///
+/// When an event sent to another instance is delivered.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Delivery {
+    /// Delivered once the step in which it was sent has completed.
+    #[default]
+    Immediate,
+    /// Held until the owner of the instances next delivers queued events.
+    Queued,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -113,6 +251,8 @@ pub struct Trigger {
 pub struct State {
     pub(crate) id: ID,
     pub(crate) label: Option<String>,
+    /// **UML**: `{subsets ownedElement} +ownedComment * : Comment`
+    pub(crate) comments: Vec<String>,
     /// **UML**: `{subsets namespace} +container 0..1 : Region`
     pub(crate) container: ID,
     /// **UML**: `{subsets ownedMember} +region *`
@@ -133,11 +273,36 @@ pub struct State {
     pub(crate) do_activity: Option<Box<dyn Behavior>>,
     /// **UML**: `{subsets ownedElement} +exit 0..1`
     pub(crate) exit: Option<Box<dyn Behavior>>,
+    /// The external services started when this state is entered, as for SCXML `<invoke>`.
+    pub(crate) invokes: Vec<Invoke>,
     pub(crate) final_state: bool,
+    /// **UML**: `{subsets redefinedElement} +redefinedVertex 0..1`
+    pub(crate) redefined: Option<ID>,
+    /// Tagged values, see `HasMetadata`.
+    pub(crate) metadata: HashMap<String, FieldValue>,
 }
 
+///
+/// An external service, started when its state is entered and cancelled if the state is exited
+/// before it completes, as for the SCXML `<invoke>` element and xstate services. The executor
+/// starts the service with the handler registered for its kind, see
+/// `execution::invoke::InvokeHandler`.
+///
 #[derive(Clone, Debug, PartialEq)]
+pub struct Invoke {
+    pub(crate) id: ID,
+    pub(crate) label: Option<String>,
+    /// The kind of service, as for the SCXML `type` attribute.
+    pub(crate) kind: String,
+    /// The service to start, as for the SCXML `src` attribute.
+    pub(crate) source: Option<String>,
+    /// The parameters of the service, each named and taken from the context when it starts.
+    pub(crate) params: Vec<(String, FieldPath)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum PseudoStateKind {
+    #[default]
     Initial,
     DeepHistory,
     ShallowHistory,
@@ -157,6 +322,8 @@ pub struct PseudoState {
     pub(crate) container: ID,
     /// default = Initial
     pub(crate) kind: PseudoStateKind,
+    /// **UML**: `{subsets redefinedElement} +redefinedVertex 0..1`
+    pub(crate) redefined: Option<ID>,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -172,6 +339,8 @@ pub struct ConnectionPointReference {
     pub(crate) exit: Vec<ID>,
     /// **UML**: `{subsets namespace} +state 0..1 : State`
     pub(crate) state: Option<ID>,
+    /// **UML**: `{subsets redefinedElement} +redefinedVertex 0..1`
+    pub(crate) redefined: Option<ID>,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -199,14 +368,198 @@ pub struct Transition {
     pub(crate) guard: Option<Box<dyn Constraint>>,
     /// **UML**: `{subsets ownedElement} +effect 0..1`
     pub(crate) effect: Option<Box<dyn Behavior>>,
+    /// **UML**: `{subsets redefinedElement} +redefinedTransition 0..1`, as the ID of the region
+    /// of the redefined transition and its index within it.
+    pub(crate) redefined: Option<(ID, usize)>,
+    /// Tagged values, see `HasMetadata`.
+    pub(crate) metadata: HashMap<String, FieldValue>,
+}
+
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Whether a violation makes a machine invalid, or only describes a likely mistake.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The machine is not well-formed, `validate` fails.
+    Error,
+    /// The machine is well-formed, but may not behave as intended.
+    Warning,
+}
+
+///
+/// A rule that an element of a machine does not follow, see `StateMachine::validation_report`.
+///
+#[derive(Debug)]
+pub struct Violation {
+    pub(crate) severity: Severity,
+    /// The ID of the element, or for a transition the ID of the region containing it.
+    pub(crate) element: ID,
+    /// For a transition, its index within the region `element`.
+    pub(crate) transition: Option<usize>,
+    /// The name of the UML constraint, for example `Region::initial_vertex`, if any.
+    pub(crate) constraint: Option<String>,
+    pub(crate) message: String,
+    /// The error `validate` returns for the violation, for errors.
+    pub(crate) kind: Option<ErrorKind>,
+}
+
+///
+/// All of the violations found in a machine, errors and warnings, in the order found.
+///
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub(crate) violations: Vec<Violation>,
 }
 
 // ------------------------------------------------------------------------------------------------
 
-pub trait Behavior: Labeled {
-    fn perform(&self, in_state: &ID, on_trigger: &Trigger);
+///
+/// A behavior of the model; as a machine may be shared between threads, so that each may run
+/// instances of it, behaviors must be `Send` and `Sync`.
+///
+pub trait Behavior: Labeled + Send + Sync {
+    ///
+    /// Perform this behavior, any events raised to `events` are internal events; the executor
+    /// dispatches them, in the order raised, before any further external events within the
+    /// same run-to-completion step.
+    ///
+    fn perform(&self, in_state: &ID, on_trigger: &Trigger, events: &dyn EventSink);
+
+    ///
+    /// Perform this behavior with access to the context of the instance performing it, as the
+    /// executor does; behaviors over the context override this, by default it is the same as
+    /// `perform`.
+    ///
+    fn perform_in(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        events: &dyn EventSink,
+        _context: &Context,
+    ) {
+        self.perform(in_state, on_trigger, events)
+    }
+
+    ///
+    /// Start this behavior as the do-activity of `in_state`, it must finish `handle` once it
+    /// has completed. By default it is performed to completion before returning.
+    ///
+    fn start(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        events: &dyn EventSink,
+        handle: DoActivityHandle,
+    ) {
+        self.perform(in_state, on_trigger, events);
+        handle.finish()
+    }
+
+    ///
+    /// The opaque body of this behavior, if it has one, so that writers can emit it unchanged.
+    ///
+    fn as_opaque(&self) -> Option<&OpaqueBehavior> {
+        None
+    }
+
+    ///
+    /// A copy of this behavior, as made by `StateMachine::duplicate`; by default behaviors
+    /// cannot be copied and this is `None`.
+    ///
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        None
+    }
 }
 
-pub trait Constraint: Labeled {
+///
+/// The destination of the internal events raised by a behavior, as for the SCXML `<raise>`
+/// element, and of the events it sends to other instances, as for the SCXML `<send>` element.
+///
+pub trait EventSink {
+    fn raise(&self, event: Event);
+
+    ///
+    /// Send `event` to the instance `target`; by default the event is discarded, as there is
+    /// nowhere to send it outside of an instance.
+    ///
+    fn send(&self, _target: SendTarget, _event: Event, _delivery: Delivery) {}
+}
+
+///
+/// A constraint of the model; as for behaviors, constraints must be `Send` and `Sync`.
+///
+pub trait Constraint: Labeled + Send + Sync {
     fn evaluate(&self, in_state: &ID, on_trigger: &Trigger) -> bool;
+
+    ///
+    /// Evaluate this constraint with access to the context of the instance evaluating it, as
+    /// the executor does; constraints over the context override this, by default it is the same
+    /// as `evaluate`.
+    ///
+    fn evaluate_in(&self, in_state: &ID, on_trigger: &Trigger, _context: &Context) -> bool {
+        self.evaluate(in_state, on_trigger)
+    }
+
+    ///
+    /// Evaluate this constraint as for `evaluate_in`, returning with its value the sub-guards
+    /// evaluated to determine it; by default there are none.
+    ///
+    fn explain_in(&self, in_state: &ID, on_trigger: &Trigger, context: &Context) -> GuardOutcome {
+        GuardOutcome {
+            result: self.evaluate_in(in_state, on_trigger, context),
+            evaluated: Vec::new(),
+        }
+    }
+
+    ///
+    /// The opaque body of this constraint, if it has one, as for `Behavior::as_opaque`.
+    ///
+    fn as_opaque(&self) -> Option<&OpaqueConstraint> {
+        None
+    }
+
+    ///
+    /// Returns `true` if this is the `else` guard of a choice or junction, taken only where no
+    /// other branch is enabled; only `guards::Else` is.
+    ///
+    fn is_else(&self) -> bool {
+        false
+    }
+
+    ///
+    /// A copy of this constraint, as for `Behavior::duplicate`.
+    ///
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        None
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The part of an event by which transitions are indexed; time and change events are not
+/// distinguished further, the transitions indexed under them are filtered by the event itself.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum EventKey {
+    Signal(String),
+    Call(String),
+    Time,
+    Change,
+    AnyReceive,
+}
+
+///
+/// The transitions of a machine by source, by target, and by the source and event of each of
+/// their triggers, in document order; as with `ref_vertices` it does not keep them alive.
+///
+#[derive(Default)]
+pub(crate) struct TransitionIndex {
+    pub(crate) from: HashMap<ID, Vec<Weak<Transition>>>,
+    pub(crate) to: HashMap<ID, Vec<Weak<Transition>>>,
+    pub(crate) triggered: HashMap<(ID, EventKey), Vec<Weak<Transition>>>,
 }
@@ -9,13 +9,14 @@ details of ownership and hierarchy traversal.
 */
 
 use std::borrow::Borrow;
-use std::rc::Rc;
+use std::collections::HashMap;
 use std::slice::Iter;
+use std::sync::Arc;
 
-use crate::core::ID;
+use crate::core::{FieldValue, ID};
 use crate::definition::types::{
-    Behavior, Constraint, HasRegions, Identified, Labeled, PseudoState, PseudoStateKind, Region,
-    State, StateMachine, TransitionKind, Trigger, Validate, Vertex,
+    Behavior, Constraint, HasMetadata, HasRegions, Identified, Labeled, PseudoState,
+    PseudoStateKind, Region, State, StateMachine, TransitionKind, Trigger, Validate, Vertex,
 };
 use crate::error::Error;
 
@@ -57,6 +58,7 @@ pub trait StateMachineVisitor {
         resolver: &Resolver<'_>,
         id: &ID,
         label: &Option<String>,
+        comments: Iter<'_, String>,
         region_count: usize,
         sub_machine: &Option<ID>,
         connections: Iter<'_, ID>,
@@ -77,6 +79,7 @@ pub trait StateMachineVisitor {
         resolver: &Resolver<'_>,
         id: &ID,
         label: &Option<String>,
+        comments: Iter<'_, String>,
         region_count: usize,
         sub_machine: &Option<ID>,
         connections: Iter<'_, ID>,
@@ -132,6 +135,14 @@ pub trait StateMachineVisitor {
         effect: &Option<Box<dyn Behavior>>,
     ) {
     }
+
+    ///
+    /// Called with the metadata of the machine, state, region, or transition just visited, see
+    /// `HasMetadata`, immediately after `enter_state_machine`, `enter_state`, `enter_region`, or
+    /// `transition`, and only where it has any.
+    ///
+    #[allow(unused_variables)]
+    fn metadata(&self, resolver: &Resolver<'_>, metadata: &HashMap<String, FieldValue>) {}
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -152,6 +163,7 @@ pub fn visit_state_machine(
         machine.sub_machine_states(),
         machine.connection_points(),
     );
+    visit_metadata(machine.metadata(), &resolver, visitor);
     let regions = machine.regions();
     let num_regions = regions.len();
     for (index, region) in regions.enumerate() {
@@ -172,11 +184,11 @@ pub fn visit_state_machine(
 // ------------------------------------------------------------------------------------------------
 
 impl<'a> Resolver<'a> {
-    pub fn find_machine(&self, machine: ID) -> Option<Rc<StateMachine>> {
+    pub fn find_machine(&self, machine: ID) -> Option<Arc<StateMachine>> {
         self.inner.find_machine(machine)
     }
 
-    pub fn find_vertex(&self, container: ID, vertex: ID) -> Option<Rc<Vertex>> {
+    pub fn find_vertex(&self, container: ID, vertex: ID) -> Option<Arc<Vertex>> {
         self.inner.find_vertex(container, vertex)
     }
 }
@@ -197,6 +209,7 @@ fn visit_state(
         resolver,
         state.id(),
         state.label(),
+        state.comments(),
         state.regions.len(),
         state.sub_machine(),
         state.connections(),
@@ -208,6 +221,7 @@ fn visit_state(
         state.exit(),
         state.is_final(),
     );
+    visit_metadata(state.metadata(), resolver, visitor);
     let regions = state.regions();
     let num_regions = regions.len();
     for (index, region) in regions.enumerate() {
@@ -217,6 +231,7 @@ fn visit_state(
         resolver,
         state.id(),
         state.label(),
+        state.comments(),
         state.regions.len(),
         state.sub_machine(),
         state.connections(),
@@ -231,6 +246,16 @@ fn visit_state(
     Ok(())
 }
 
+fn visit_metadata(
+    metadata: &HashMap<String, FieldValue>,
+    resolver: &Resolver<'_>,
+    visitor: &dyn StateMachineVisitor,
+) {
+    if !metadata.is_empty() {
+        visitor.metadata(resolver, metadata);
+    }
+}
+
 fn visit_region(
     region: &Region,
     resolver: &Resolver<'_>,
@@ -238,6 +263,7 @@ fn visit_region(
     last: bool,
 ) -> Result<(), Error> {
     visitor.enter_region(resolver, region.id(), region.label(), last);
+    visit_metadata(region.metadata(), resolver, visitor);
     for vertex in region.vertices() {
         match vertex.borrow() {
             Vertex::State(state) => {
@@ -274,6 +300,7 @@ fn visit_region(
             transition.guard(),
             transition.effect(),
         );
+        visit_metadata(transition.metadata(), resolver, visitor);
     }
     visitor.exit_region(resolver, region.id(), region.label(), last);
     Ok(())
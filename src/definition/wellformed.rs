@@ -0,0 +1,877 @@
+/*!
+The well-formedness rules of the UML specification, §14.5, and of this model, checked by
+`StateMachine::validation_report`; each violation names the offending element and, for the
+specification's rules, the constraint it violates. Warnings describe machines that are
+well-formed but likely to be mistaken: states that cannot be left, regions without an initial
+pseudostate, vertices that cannot be reached, transitions that can never fire, and transitions
+that may be enabled together, see `analysis`.
+
+The rules for transition kinds are not checked, as transitions in this model are `Internal` by
+default, unless their source and target differ, whatever their kind.
+*/
+
+use crate::core::ID;
+use crate::definition::analysis::{conflicts, reachable};
+use crate::definition::types::{
+    Event, HasRegions, Identified, Labeled, PseudoState, PseudoStateKind, Region,
+    RegionContainerType, Severity, StateMachine, Transition, Validate, ValidationReport, Vertex,
+    Violation,
+};
+use crate::error::{Error, ErrorKind};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+pub(crate) fn validate_machine(machine: &StateMachine) -> ValidationReport {
+    let model = Model::new(machine);
+    let mut report = ValidationReport::default();
+    for pseudo_state in machine.connection_points() {
+        if !is_connection_point(pseudo_state.kind()) {
+            ill_formed(
+                &mut report,
+                element(pseudo_state.id(), pseudo_state.label()),
+                "StateMachine::connection_points",
+                "the connection points of a machine must be entry or exit points",
+            );
+        }
+    }
+    for region in &model.regions {
+        model.check_region(region, &mut report);
+    }
+    for vertex in &model.vertices {
+        model.check_vertex(vertex, &mut report);
+    }
+    model.check_transitions(&mut report);
+    model.check_forks_and_joins(&mut report);
+    model.check_behavior(machine, &mut report);
+    report
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// An element named in a violation, as its ID, or for transitions the ID of its region and its
+/// index, and as it is described in errors.
+///
+struct Element {
+    id: ID,
+    transition: Option<usize>,
+    name: String,
+}
+
+struct RegionEntry {
+    id: ID,
+    label: Option<String>,
+    vertices: Vec<Arc<Vertex>>,
+}
+
+struct TransitionEntry {
+    region: ID,
+    index: usize,
+    transition: Arc<Transition>,
+}
+
+///
+/// The regions, vertices, and transitions of a machine, at any depth, and how they contain each
+/// other.
+///
+struct Model {
+    regions: Vec<RegionEntry>,
+    vertices: Vec<Arc<Vertex>>,
+    vertex_index: HashMap<ID, Arc<Vertex>>,
+    vertex_region: HashMap<ID, ID>,
+    region_owner: HashMap<ID, ID>,
+    transitions: Vec<TransitionEntry>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Model {
+    fn new(machine: &StateMachine) -> Self {
+        let mut model = Self {
+            regions: Default::default(),
+            vertices: Default::default(),
+            vertex_index: Default::default(),
+            vertex_region: Default::default(),
+            region_owner: Default::default(),
+            transitions: Default::default(),
+        };
+        machine.for_each_region(&mut |region| model.add_region(region));
+        model
+    }
+
+    fn add_region(&mut self, region: &Region) {
+        let owner = match region.container_type {
+            RegionContainerType::State => Some(region.container.clone()),
+            RegionContainerType::StateMachine => None,
+        };
+        if let Some(owner) = &owner {
+            let _ = self.region_owner.insert(region.id().clone(), owner.clone());
+        }
+        let vertices = region.vertices();
+        for vertex in &vertices {
+            let _ = self
+                .vertex_region
+                .insert(vertex.id().clone(), region.id().clone());
+            let _ = self
+                .vertex_index
+                .insert(vertex.id().clone(), vertex.clone());
+            self.vertices.push(vertex.clone());
+        }
+        for (index, transition) in region.transitions().into_iter().enumerate() {
+            self.transitions.push(TransitionEntry {
+                region: region.id().clone(),
+                index,
+                transition,
+            });
+        }
+        self.regions.push(RegionEntry {
+            id: region.id().clone(),
+            label: region.label().clone(),
+            vertices,
+        });
+    }
+
+    fn incoming(&self, vertex: &ID) -> Vec<&TransitionEntry> {
+        self.transitions
+            .iter()
+            .filter(|entry| &entry.transition.target() == vertex)
+            .collect()
+    }
+
+    fn outgoing(&self, vertex: &ID) -> Vec<&TransitionEntry> {
+        self.transitions
+            .iter()
+            .filter(|entry| &entry.transition.source() == vertex)
+            .collect()
+    }
+
+    fn pseudo_state(&self, id: &ID) -> Option<&PseudoState> {
+        self.vertex_index
+            .get(id)
+            .and_then(|vertex| vertex.as_pseudo_state())
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    fn check_region(&self, region: &RegionEntry, report: &mut ValidationReport) {
+        let count = |kind: PseudoStateKind| {
+            region
+                .vertices
+                .iter()
+                .filter_map(|vertex| vertex.as_pseudo_state())
+                .filter(|pseudo_state| pseudo_state.kind() == kind)
+                .count()
+        };
+        let rules = [
+            (
+                PseudoStateKind::Initial,
+                "Region::initial_vertex",
+                "a region may have at most one initial pseudostate",
+            ),
+            (
+                PseudoStateKind::DeepHistory,
+                "Region::deep_history_vertex",
+                "a region may have at most one deep history pseudostate",
+            ),
+            (
+                PseudoStateKind::ShallowHistory,
+                "Region::shallow_history_vertex",
+                "a region may have at most one shallow history pseudostate",
+            ),
+        ];
+        for (kind, constraint, description) in rules.iter() {
+            if count(kind.clone()) > 1 {
+                ill_formed(
+                    report,
+                    element(&region.id, &region.label),
+                    constraint,
+                    description,
+                );
+            }
+        }
+        if count(PseudoStateKind::Initial) == 0 {
+            warning(
+                report,
+                element(&region.id, &region.label),
+                "region has no initial pseudostate, it may only be entered by a transition to a vertex within it",
+            );
+        }
+    }
+
+    fn check_vertex(&self, vertex: &Vertex, report: &mut ValidationReport) {
+        let id = vertex.id();
+        let this = || element(id, vertex.label());
+        match vertex {
+            Vertex::State(state) => {
+                let composite = state.regions().len() > 0;
+                if state.is_final() {
+                    let rules = [
+                        (
+                            !self.outgoing(id).is_empty(),
+                            "FinalState::no_outgoing_transitions",
+                            "a final state may not have outgoing transitions",
+                        ),
+                        (
+                            composite,
+                            "FinalState::no_regions",
+                            "a final state may not have regions",
+                        ),
+                        (
+                            state.sub_machine().is_some(),
+                            "FinalState::cannot_reference_submachine",
+                            "a final state may not reference a sub-machine",
+                        ),
+                        (
+                            state.entry().is_some(),
+                            "FinalState::no_entry_behavior",
+                            "a final state may not have an entry behavior",
+                        ),
+                        (
+                            state.exit().is_some(),
+                            "FinalState::no_exit_behavior",
+                            "a final state may not have an exit behavior",
+                        ),
+                        (
+                            state.do_activity().is_some(),
+                            "FinalState::no_state_behavior",
+                            "a final state may not have a do-activity",
+                        ),
+                    ];
+                    for (_, constraint, description) in
+                        rules.iter().filter(|(violated, _, _)| *violated)
+                    {
+                        ill_formed(report, this(), constraint, description);
+                    }
+                } else if !composite && state.sub_machine().is_none() && !self.may_leave(id) {
+                    warning(
+                        report,
+                        this(),
+                        "state has no outgoing transitions and is not final",
+                    );
+                }
+                if state.sub_machine().is_some() && composite {
+                    ill_formed(
+                        report,
+                        this(),
+                        "State::submachine_or_regions",
+                        "a state may not have both a sub-machine and regions",
+                    );
+                }
+                if state.connections().next().is_some() && state.sub_machine().is_none() {
+                    ill_formed(
+                        report,
+                        this(),
+                        "State::submachine_states",
+                        "only sub-machine states may have connection point references",
+                    );
+                }
+                if state.connection_points().next().is_some() && !composite {
+                    ill_formed(
+                        report,
+                        this(),
+                        "State::composite_states",
+                        "only composite states may have entry or exit points",
+                    );
+                }
+                let entry_or_exit = state.connection_points().all(|point| {
+                    self.pseudo_state(point)
+                        .is_none_or(|pseudo_state| is_connection_point(pseudo_state.kind()))
+                });
+                if !entry_or_exit {
+                    ill_formed(
+                        report,
+                        this(),
+                        "State::entry_or_exit",
+                        "the connection points of a state must be entry or exit points",
+                    );
+                }
+                for trigger in state.deferrable_triggers() {
+                    if let Err(Error(kind, _)) = trigger.validate() {
+                        error(report, this(), None, kind);
+                    }
+                }
+            }
+            Vertex::PseudoState(pseudo_state) => self.check_pseudo_state(pseudo_state, report),
+            Vertex::ConnectionPointReference(reference) => {
+                let kind_is = |points: Vec<&ID>, kind: PseudoStateKind| {
+                    points.into_iter().all(|point| {
+                        self.pseudo_state(point)
+                            .is_none_or(|pseudo_state| pseudo_state.kind() == kind)
+                    })
+                };
+                if !kind_is(reference.entry().collect(), PseudoStateKind::EntryPoint) {
+                    ill_formed(
+                        report,
+                        this(),
+                        "ConnectionPointReference::entry_pseudostates",
+                        "the entry of a connection point reference must be entry points",
+                    );
+                }
+                if !kind_is(reference.exit().collect(), PseudoStateKind::ExitPoint) {
+                    ill_formed(
+                        report,
+                        this(),
+                        "ConnectionPointReference::exit_pseudostates",
+                        "the exit of a connection point reference must be exit points",
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_pseudo_state(&self, pseudo_state: &PseudoState, report: &mut ValidationReport) {
+        let id = pseudo_state.id();
+        let this = || element(id, pseudo_state.label());
+        let incoming = self.incoming(id);
+        let outgoing = self.outgoing(id);
+        match pseudo_state.kind() {
+            PseudoStateKind::Initial => {
+                if outgoing.len() > 1 {
+                    ill_formed(
+                        report,
+                        this(),
+                        "Pseudostate::initial_vertex",
+                        "an initial pseudostate may have at most one outgoing transition",
+                    );
+                }
+                if !incoming.is_empty() {
+                    ill_formed(
+                        report,
+                        this(),
+                        "PseudostateKind::initial",
+                        "an initial pseudostate may not have incoming transitions",
+                    );
+                }
+                for entry in outgoing {
+                    if entry.transition.has_triggers() || entry.transition.has_guard() {
+                        ill_formed(
+                            report,
+                            transition_element(entry),
+                            "Pseudostate::outgoing_from_initial",
+                            "the transition from an initial pseudostate may not have triggers or a guard",
+                        );
+                    }
+                }
+                return;
+            }
+            kind @ PseudoStateKind::ShallowHistory | kind @ PseudoStateKind::DeepHistory => {
+                if outgoing.len() > 1 {
+                    ill_formed(
+                        report,
+                        this(),
+                        "Pseudostate::history_vertices",
+                        "a history pseudostate may have at most one outgoing transition",
+                    );
+                }
+                if !self.region_owner.contains_key(&self.vertex_region[id]) {
+                    ill_formed(
+                        report,
+                        this(),
+                        if kind == PseudoStateKind::DeepHistory {
+                            "PseudostateKind::deepHistory"
+                        } else {
+                            "PseudostateKind::shallowHistory"
+                        },
+                        "a history pseudostate must be within a composite state",
+                    );
+                }
+            }
+            PseudoStateKind::Choice if incoming.is_empty() || outgoing.is_empty() => {
+                ill_formed(
+                    report,
+                    this(),
+                    "Pseudostate::choice_vertex",
+                    "a choice must have at least one incoming and one outgoing transition",
+                );
+            }
+            PseudoStateKind::Junction if incoming.is_empty() || outgoing.is_empty() => {
+                ill_formed(
+                    report,
+                    this(),
+                    "Pseudostate::junction_vertex",
+                    "a junction must have at least one incoming and one outgoing transition",
+                );
+            }
+            _ => (),
+        }
+        for entry in outgoing
+            .iter()
+            .filter(|entry| entry.transition.has_triggers())
+        {
+            ill_formed(
+                report,
+                transition_element(entry),
+                "Transition::outgoing_pseudostates",
+                "transitions from pseudostates, other than initial pseudostates, may not have triggers",
+            );
+        }
+    }
+
+    fn check_transitions(&self, report: &mut ValidationReport) {
+        let mut timeouts: Vec<(ID, &Event)> = Default::default();
+        for entry in &self.transitions {
+            let transition = &entry.transition;
+            if !self.vertex_index.contains_key(&transition.source()) {
+                ill_formed(
+                    report,
+                    transition_element(entry),
+                    "Transition::source",
+                    "the source of a transition must be a vertex of the machine",
+                );
+            }
+            if !self.vertex_index.contains_key(&transition.target()) {
+                ill_formed(
+                    report,
+                    transition_element(entry),
+                    "Transition::target",
+                    "the target of a transition must be a vertex of the machine",
+                );
+            }
+            for trigger in transition.triggers() {
+                if let Err(Error(kind, _)) = trigger.validate() {
+                    error(report, transition_element(entry), None, kind);
+                }
+            }
+            // unguarded transitions from one state triggered by the same time event would both fire.
+            if let (false, [trigger]) = (transition.has_guard(), transition.triggers.as_slice()) {
+                if let Event::Time(_) = trigger.event() {
+                    let timeout = (transition.source(), trigger.event());
+                    if timeouts.contains(&timeout) {
+                        error(
+                            report,
+                            transition_element(entry),
+                            None,
+                            ErrorKind::StateConflictingTimeouts(timeout.0.to_string()),
+                        );
+                    } else {
+                        timeouts.push(timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    ///
+    /// Check the rules for fork and join pseudostates: a fork has exactly one incoming
+    /// transition and a join exactly one outgoing transition, the other transitions, at least
+    /// two, have neither triggers nor guards, and connect to vertices in different regions of the
+    /// same orthogonal state.
+    ///
+    fn check_forks_and_joins(&self, report: &mut ValidationReport) {
+        for vertex in &self.vertices {
+            let (kind, fork) = match vertex.as_pseudo_state() {
+                Some(pseudo_state) if pseudo_state.is_fork() => ("fork", true),
+                Some(pseudo_state) if pseudo_state.is_join() => ("join", false),
+                _ => continue,
+            };
+            let id = vertex.id();
+            let mut malformed = |constraint: &str, reason: String| {
+                error(
+                    report,
+                    element(id, vertex.as_pseudo_state().unwrap().label()),
+                    Some(constraint),
+                    ErrorKind::ForkJoinTransitions(id.to_string(), reason),
+                )
+            };
+            let (single, segments) = if fork {
+                (self.incoming(id), self.outgoing(id))
+            } else {
+                (self.outgoing(id), self.incoming(id))
+            };
+            let vertex_constraint = if fork {
+                "Pseudostate::fork_vertex"
+            } else {
+                "Pseudostate::join_vertex"
+            };
+            if single.len() != 1 {
+                malformed(
+                    vertex_constraint,
+                    format!(
+                        "a {} must have exactly one {} transition",
+                        kind,
+                        if fork { "incoming" } else { "outgoing" }
+                    ),
+                );
+                continue;
+            } else if segments.len() < 2 {
+                malformed(
+                    vertex_constraint,
+                    format!(
+                        "a {} must have at least two {} transitions",
+                        kind,
+                        if fork { "outgoing" } else { "incoming" }
+                    ),
+                );
+                continue;
+            } else if segments
+                .iter()
+                .any(|entry| entry.transition.has_triggers() || entry.transition.has_guard())
+            {
+                malformed(
+                    if fork {
+                        "Transition::fork_segment_guards"
+                    } else {
+                        "Transition::join_segment_guards"
+                    },
+                    format!("the segments of a {} may not have triggers or guards", kind),
+                );
+            }
+            let ends: Vec<Vec<(ID, Option<ID>)>> = segments
+                .iter()
+                .map(|entry| {
+                    self.regions_containing(&if fork {
+                        entry.transition.target()
+                    } else {
+                        entry.transition.source()
+                    })
+                })
+                .collect();
+            // the innermost state containing every end, each end must be in a different region of it
+            let common = ends[0].iter().find_map(|(_, owner)| {
+                owner.as_ref().filter(|owner| {
+                    ends.iter()
+                        .all(|end| end.iter().any(|(_, other)| other.as_ref() == Some(*owner)))
+                })
+            });
+            let distinct = common.is_some_and(|common| {
+                let mut regions: Vec<&ID> = ends
+                    .iter()
+                    .filter_map(|end| {
+                        end.iter()
+                            .find(|(_, owner)| owner.as_ref() == Some(common))
+                            .map(|(region, _)| region)
+                    })
+                    .collect();
+                let count = regions.len();
+                regions.sort_by_key(|region| region.to_string());
+                regions.dedup();
+                regions.len() == count
+            });
+            if !distinct {
+                malformed(
+                    if fork {
+                        "Pseudostate::transitions_outgoing"
+                    } else {
+                        "Pseudostate::transitions_incoming"
+                    },
+                    format!(
+                        "the segments of a {} must connect to different regions of the same state",
+                        kind
+                    ),
+                );
+            }
+        }
+    }
+
+    ///
+    /// Warn of vertices that cannot be reached, transitions from reachable vertices that can
+    /// never fire, and transitions that may be enabled together.
+    ///
+    fn check_behavior(&self, machine: &StateMachine, report: &mut ValidationReport) {
+        let reachability = reachable(machine);
+        for id in reachability.unreachable_vertices() {
+            if let Some(vertex) = self.vertex_index.get(id) {
+                warning(
+                    report,
+                    element(id, vertex.label()),
+                    "vertex cannot be reached from the initial configuration",
+                );
+            }
+        }
+        for (region, index) in reachability.never_enabled_transitions() {
+            let entry = self
+                .transitions
+                .iter()
+                .find(|entry| &entry.region == region && entry.index == *index);
+            if let Some(entry) = entry {
+                if reachability.is_reachable(&entry.transition.source()) {
+                    warning(
+                        report,
+                        transition_element(entry),
+                        "transition can never fire",
+                    );
+                }
+            }
+        }
+        for conflict in conflicts(machine) {
+            let [first, (region, index)] = conflict.transitions();
+            let entry = self
+                .transitions
+                .iter()
+                .find(|entry| &entry.region == region && entry.index == *index);
+            if let Some(entry) = entry {
+                warning(
+                    report,
+                    transition_element(entry),
+                    &format!(
+                        "transition {} enabled together with transition {} of region {}, from the same state",
+                        if conflict.is_certain() { "is always" } else { "may be" },
+                        first.1,
+                        first.0
+                    ),
+                );
+            }
+        }
+    }
+
+    ///
+    /// Returns `true` if `state`, or a state containing it, has an outgoing transition.
+    ///
+    fn may_leave(&self, state: &ID) -> bool {
+        let mut current = Some(state.clone());
+        while let Some(id) = current {
+            if !self.outgoing(&id).is_empty() {
+                return true;
+            }
+            current = self
+                .vertex_region
+                .get(&id)
+                .and_then(|region| self.region_owner.get(region))
+                .cloned();
+        }
+        false
+    }
+
+    ///
+    /// The regions containing `vertex`, innermost first, each with the state that owns it.
+    ///
+    fn regions_containing(&self, vertex: &ID) -> Vec<(ID, Option<ID>)> {
+        let mut regions: Vec<(ID, Option<ID>)> = Default::default();
+        let mut current = vertex.clone();
+        while let Some(region) = self.vertex_region.get(&current) {
+            let owner = self.region_owner.get(region).cloned();
+            regions.push((region.clone(), owner.clone()));
+            match owner {
+                None => break,
+                Some(owner) => current = owner,
+            }
+        }
+        regions
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn is_connection_point(kind: PseudoStateKind) -> bool {
+    matches!(
+        kind,
+        PseudoStateKind::EntryPoint | PseudoStateKind::ExitPoint
+    )
+}
+
+fn element(id: &ID, label: &Option<String>) -> Element {
+    Element {
+        id: id.clone(),
+        transition: None,
+        name: match label {
+            None => id.to_string(),
+            Some(label) => format!("{} ({})", id, label),
+        },
+    }
+}
+
+fn transition_element(entry: &TransitionEntry) -> Element {
+    let name = format!("transition {} of region {}", entry.index, entry.region);
+    Element {
+        id: entry.region.clone(),
+        transition: Some(entry.index),
+        name: match entry.transition.label() {
+            None => name,
+            Some(label) => format!("{} ({})", name, label),
+        },
+    }
+}
+
+fn ill_formed(
+    report: &mut ValidationReport,
+    element: Element,
+    constraint: &str,
+    description: &str,
+) {
+    let kind = ErrorKind::ModelIllFormed(
+        element.name.clone(),
+        format!("{}, {}", constraint, description),
+    );
+    report.push(Violation {
+        severity: Severity::Error,
+        element: element.id,
+        transition: element.transition,
+        constraint: Some(constraint.to_string()),
+        message: description.to_string(),
+        kind: Some(kind),
+    });
+}
+
+fn error(
+    report: &mut ValidationReport,
+    element: Element,
+    constraint: Option<&str>,
+    kind: ErrorKind,
+) {
+    report.push(Violation {
+        severity: Severity::Error,
+        element: element.id,
+        transition: element.transition,
+        constraint: constraint.map(str::to_string),
+        message: kind.to_string(),
+        kind: Some(kind),
+    });
+}
+
+fn warning(report: &mut ValidationReport, element: Element, message: &str) {
+    report.push(Violation {
+        severity: Severity::Warning,
+        element: element.id,
+        transition: element.transition,
+        constraint: None,
+        message: message.to_string(),
+        kind: None,
+    });
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+    use crate::definition::types::{State, Trigger, Validate};
+
+    fn violated(machine: &StateMachine) -> String {
+        match machine.validate() {
+            Err(Error(ErrorKind::ModelIllFormed(_, constraint), _)) => {
+                constraint.split(',').next().unwrap().to_string()
+            }
+            result => panic!("expected ModelIllFormed, not {:?}", result.err()),
+        }
+    }
+
+    fn simple_machine() -> (StateMachine, ID, ID) {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        region.new_transition(initial.clone(), idle.clone());
+        (machine, initial, idle)
+    }
+
+    #[test]
+    fn test_well_formed() {
+        let (machine, _, _) = simple_machine();
+        assert!(machine.validate().is_ok());
+    }
+
+    #[test]
+    fn test_regions() {
+        let (machine, _, _) = simple_machine();
+        let _ = machine.default_region().unwrap().new_initial_state();
+        assert_eq!(violated(&machine), "Region::initial_vertex");
+
+        let (machine, _, _) = simple_machine();
+        let _ = machine
+            .default_region()
+            .unwrap()
+            .new_shallow_history_state();
+        assert_eq!(violated(&machine), "PseudostateKind::shallowHistory");
+    }
+
+    #[test]
+    fn test_pseudo_states() {
+        let (machine, initial, idle) = simple_machine();
+        let region = machine.default_region().unwrap();
+        let mut transition = Transition::within(initial, idle, region.id().clone());
+        transition.set_guard(Box::new(OpaqueConstraint::new("ready", None)));
+        region.add_transition(transition);
+        assert_eq!(violated(&machine), "Pseudostate::initial_vertex");
+
+        let (machine, initial, idle) = simple_machine();
+        let region = machine.default_region().unwrap();
+        let _ = region.transitions.write().unwrap().pop();
+        let mut transition = Transition::within(initial, idle, region.id().clone());
+        transition.add_trigger(Trigger::signal("start"));
+        region.add_transition(transition);
+        assert_eq!(violated(&machine), "Pseudostate::outgoing_from_initial");
+
+        let (machine, _, idle) = simple_machine();
+        let region = machine.default_region().unwrap();
+        let junction = region.new_junction();
+        region.new_transition(idle.clone(), junction.clone());
+        assert_eq!(violated(&machine), "Pseudostate::junction_vertex");
+        let mut transition = Transition::within(junction, idle, region.id().clone());
+        transition.add_trigger(Trigger::signal("back"));
+        region.add_transition(transition);
+        assert_eq!(violated(&machine), "Transition::outgoing_pseudostates");
+    }
+
+    #[test]
+    fn test_states() {
+        let (machine, _, idle) = simple_machine();
+        let region = machine.default_region().unwrap();
+        let mut done = State::within(region.id().clone());
+        done.set_final(true);
+        done.set_entry(Box::new(OpaqueBehavior::new("log", None)));
+        let done_id = done.id().clone();
+        region.add_state(done);
+        region.new_transition(idle, done_id);
+        assert_eq!(violated(&machine), "FinalState::no_entry_behavior");
+
+        let (machine, _, _) = simple_machine();
+        let region = machine.default_region().unwrap();
+        let mut nested = State::within(region.id().clone());
+        let _ = nested.new_region();
+        nested.set_sub_machine(machine.id().clone());
+        region.add_state(nested);
+        assert_eq!(violated(&machine), "State::submachine_or_regions");
+    }
+
+    #[test]
+    fn test_report() {
+        let (machine, _, idle) = simple_machine();
+        let report = machine.validation_report();
+        assert!(report.is_valid());
+        assert_eq!(report.warnings().count(), 1);
+        let warning = report.warnings().next().unwrap();
+        assert_eq!(warning.element(), &idle);
+        assert_eq!(
+            warning.message(),
+            "state has no outgoing transitions and is not final"
+        );
+
+        let region = machine.default_region().unwrap();
+        let _ = region.new_initial_state();
+        let choice = region.new_choice_state();
+        let lost = region.new_simple_state();
+        region.new_transition(idle.clone(), choice.clone());
+        let report = machine.validation_report();
+        assert!(!report.is_valid());
+        let constraints: Vec<&str> = report
+            .errors()
+            .filter_map(|violation| violation.constraint().as_deref())
+            .collect();
+        assert_eq!(
+            constraints,
+            vec!["Region::initial_vertex", "Pseudostate::choice_vertex"]
+        );
+        assert!(report
+            .errors()
+            .any(|violation| violation.element() == &choice));
+        assert!(report
+            .warnings()
+            .any(|violation| violation.element() == &lost
+                && violation.message()
+                    == "vertex cannot be reached from the initial configuration"));
+        assert!(report.into_result().is_err());
+    }
+}
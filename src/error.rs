@@ -3,6 +3,14 @@ Provides a common error implementation, error kind enumeration, and constrained
 */
 
 error_chain! {
+    links {
+        Id(crate::core::id::error::Error, crate::core::id::error::ErrorKind) #[doc = "An `ID` could not be created."];
+    }
+
+    foreign_links {
+        Io(::std::io::Error) #[doc = "An I/O error occurred writing a representation of a machine."];
+    }
+
     errors {
         #[doc = "`StateMachine::states` may not be empty."]
         ChartStatesEmpty {
@@ -52,12 +60,112 @@ error_chain! {
             display("`StateKind::Final` states may not have outbound transitions.")
         }
 
+        #[doc = "`PseudoStateKind::Choice` states must have at least one outbound transition."]
+        ChoiceStateTransitions {
+            description("`PseudoStateKind::Choice` states must have at least one outbound transition.")
+            display("`PseudoStateKind::Choice` states must have at least one outbound transition.")
+        }
+
         #[doc = "`Transition` must have at least one of `event`, `target`, or `conditions`."]
         TransitionTrigger {
             description("Transition must have at least one of `event`, `target`, or `conditions`.")
             display("Transition must have at least one of `event`, `target`, or `conditions`.")
         }
 
+        #[doc = "`Trigger` references a signal or call event with an empty name."]
+        TriggerEventName {
+            description("`Trigger` references a signal or call event with an empty name.")
+            display("`Trigger` references a signal or call event with an empty name.")
+        }
+
+        #[doc = "`PayloadPredicate` uses an ordering comparison with a value that cannot be ordered."]
+        TriggerPayloadPredicate {
+            description("`PayloadPredicate` uses an ordering comparison with a value that cannot be ordered.")
+            display("`PayloadPredicate` uses an ordering comparison with a value that cannot be ordered.")
+        }
+
+        #[doc = "A state has more than one unguarded transition triggered by the same time event."]
+        StateConflictingTimeouts(state: String) {
+            description("A state has more than one unguarded transition triggered by the same time event.")
+            display("A state has more than one unguarded transition triggered by the same time event: {}.", state)
+        }
+
+        #[doc = "A fork or join pseudostate does not connect one transition with transitions to, or from, each region of an orthogonal state, without triggers or guards."]
+        ForkJoinTransitions(vertex: String, reason: String) {
+            description("A fork or join pseudostate does not connect one transition with transitions to, or from, each region of an orthogonal state, without triggers or guards.")
+            display("The fork or join pseudostate {} is not well-formed: {}.", vertex, reason)
+        }
+
+        #[doc = "An element of the machine violates a well-formedness constraint of the UML specification, named with its description."]
+        ModelIllFormed(element: String, constraint: String) {
+            description("An element of the machine violates a well-formedness constraint of the UML specification.")
+            display("The element {} violates the UML constraint {}.", element, constraint)
+        }
+
+        #[doc = "No region, vertex, or transition with this ID, or index, exists in the machine."]
+        ModelUnknownElement(element: String) {
+            description("No region, vertex, or transition with this ID, or index, exists in the machine.")
+            display("No region, vertex, or transition with this ID, or index, exists in the machine: {}.", element)
+        }
+
+        #[doc = "A vertex cannot be removed while a transition outside of it refers to it, or to a vertex within it."]
+        ModelVertexReferenced(vertex: String) {
+            description("A vertex cannot be removed while a transition outside of it refers to it, or to a vertex within it.")
+            display("A vertex cannot be removed while a transition outside of it refers to it, or to a vertex within it: {}.", vertex)
+        }
+
+        #[doc = "A vertex cannot be moved into a region within itself."]
+        ModelInvalidMove(vertex: String) {
+            description("A vertex cannot be moved into a region within itself.")
+            display("A vertex cannot be moved into a region within itself: {}.", vertex)
+        }
+
+        #[doc = "An element cannot be changed while it is shared, for example with an executing instance."]
+        ModelElementShared(element: String) {
+            description("An element cannot be changed while it is shared, for example with an executing instance.")
+            display("An element cannot be changed while it is shared, for example with an executing instance: {}.", element)
+        }
+
+        #[doc = "A machine cannot be duplicated as a behavior, or constraint, of this element cannot be copied."]
+        ModelNotDuplicable(element: String) {
+            description("A machine cannot be duplicated as a behavior, or constraint, of this element cannot be copied.")
+            display("A machine cannot be duplicated as a behavior, or constraint, of this element cannot be copied: {}.", element)
+        }
+
+        #[doc = "An element of a machine does not validly redefine, or extend, an element of the machine it extends."]
+        ModelRedefinition(element: String, reason: String) {
+            description("An element of a machine does not validly redefine, or extend, an element of the machine it extends.")
+            display("The element {} does not validly redefine the machine it extends: {}.", element, reason)
+        }
+
+        #[doc = "The machine uses a feature that the transformation cannot represent in the machine it creates."]
+        TransformUnsupported(feature: String) {
+            description("The machine uses a feature that the transformation cannot represent in the machine it creates.")
+            display("The machine uses a feature that the transformation cannot represent in the machine it creates: {}.", feature)
+        }
+
+        #[doc = "In `BindingMode::Strict`, reachable elements refer to names that are not bound, or names are bound that only unreachable elements refer to."]
+        BindingMismatch(unbound: Vec<String>, pruned: Vec<String>) {
+            description("The bindings do not match the reachable elements of the machine.")
+            display(
+                "The bindings do not match the reachable elements of the machine, not bound: [{}], bound only for unreachable elements: [{}].",
+                unbound.join(", "),
+                pruned.join(", ")
+            )
+        }
+
+        #[doc = "The input could not be parsed in the expected format."]
+        FormatParse(message: String) {
+            description("The input could not be parsed in the expected format.")
+            display("The input could not be parsed in the expected format: {}.", message)
+        }
+
+        #[doc = "The input uses a feature of the format that cannot be represented in the model."]
+        FormatUnsupported(feature: String) {
+            description("The input uses a feature of the format that cannot be represented in the model.")
+            display("The input uses a feature of the format that cannot be represented in the model: {}.", feature)
+        }
+
         #[doc = "`Transition::target` is either missing or not a valid initial state."]
         TransitionTargetState {
             description("`Transition::target` is either missing or not a valid initial state.")
@@ -70,6 +178,18 @@ error_chain! {
             display("State has multiple live outbound transitions.")
         }
 
+        #[doc = "An expression could not be parsed."]
+        ExpressionSyntax(reason: String) {
+            description("An expression could not be parsed.")
+            display("An expression could not be parsed: {}.", reason)
+        }
+
+        #[doc = "An expression could not be evaluated against the context provided."]
+        ExpressionEvaluation(reason: String) {
+            description("An expression could not be evaluated against the context provided.")
+            display("An expression could not be evaluated against the context provided: {}.", reason)
+        }
+
         #[doc = "`StateMachineInstance` is already in a done state."]
         InstanceIsDone {
             description("`StateMachineInstance` is already in a done state.")
@@ -88,16 +208,98 @@ error_chain! {
             display("`StateMachineInstance::is_active` is false, `execute` must be called before `post`.")
         }
 
+        #[doc = "`StateMachineInstance` is in a step, this may only be done between steps."]
+        InstanceIsInStep {
+            description("`StateMachineInstance` is in a step, this may only be done between steps.")
+            display("`StateMachineInstance` is in a step, this may only be done between steps.")
+        }
+
         #[doc = "More than one transition is active for an active state."]
         MoreThanOneTransition {
             description("More than one transition is active for an active state.")
             display("More than one transition is active for an active state.")
         }
 
+        #[doc = "A step fired more transitions, without an external event, than the instance's limit; the pairs of source and target are the transitions that repeat."]
+        MicrostepLimit(limit: usize, cycle: Vec<(crate::core::ID, crate::core::ID)>) {
+            description("A step fired more transitions, without an external event, than the instance's limit.")
+            display(
+                "A step fired more than {} transitions without an external event, repeating: {}.",
+                limit,
+                cycle.iter().map(|(source, target)| format!("{} -> {}", source, target)).collect::<Vec<String>>().join(", ")
+            )
+        }
+
+        #[doc = "No outbound transition of a choice or junction is enabled, and there is no `else` branch."]
+        ChoiceNoBranch {
+            description("No outbound transition of a choice or junction is enabled, and there is no `else` branch.")
+            display("No outbound transition of a choice or junction is enabled, and there is no `else` branch.")
+        }
+
         #[doc = "An action executed for an active state panicked."]
-        ActionPanicked {
-            description("An action executed for an active state panicked.")
-            display("An action executed for an active state panicked.")
+        ActionPanicked(within: String) {
+            description("A behavior or constraint evaluated by the executor panicked.")
+            display("A behavior or constraint evaluated by the executor panicked: {}.", within)
+        }
+
+        #[doc = "The machine uses a feature that the executor does not support."]
+        ExecutionUnsupported(feature: String) {
+            description("The machine uses a feature that the executor does not support.")
+            display("The machine uses a feature that the executor does not support: {}.", feature)
+        }
+
+        #[doc = "A builder transition refers to a vertex by a name that was not declared."]
+        UnknownVertex(name: String) {
+            description("A builder transition refers to a vertex by a name that was not declared.")
+            display("A builder transition refers to a vertex by a name that was not declared: {}.", name)
+        }
+
+        #[doc = "A builder declares more than one vertex with the same name."]
+        DuplicateVertex(name: String) {
+            description("A builder declares more than one vertex with the same name.")
+            display("A builder declares more than one vertex with the same name: {}.", name)
+        }
+
+        #[doc = "A builder declares an initial pseudostate with `RegionBuilder::pseudo_state`, rather than `RegionBuilder::initial`."]
+        InitialPseudoState(name: String) {
+            description("A builder declares an initial pseudostate with `RegionBuilder::pseudo_state`, rather than `RegionBuilder::initial`.")
+            display("A builder declares an initial pseudostate with `RegionBuilder::pseudo_state`, rather than `RegionBuilder::initial`: {}.", name)
+        }
+
+        #[doc = "A snapshot could not be restored as an instance of the machine provided."]
+        InvalidSnapshot(reason: String) {
+            description("A snapshot could not be restored as an instance of the machine provided.")
+            display("A snapshot could not be restored as an instance of the machine provided: {}.", reason)
+        }
+
+        #[doc = "An instance could not be migrated to the machine provided."]
+        InvalidMigration(reason: String) {
+            description("An instance could not be migrated to the machine provided.")
+            display("An instance could not be migrated to the machine provided: {}.", reason)
+        }
+
+        #[doc = "No instance with this ID, or name, is managed by the instance manager."]
+        UnknownInstance(name: String) {
+            description("No instance with this ID, or name, is managed by the instance manager.")
+            display("No instance with this ID, or name, is managed by the instance manager: {}.", name)
+        }
+
+        #[doc = "An instance with this ID, or name, is already managed by the instance manager."]
+        DuplicateInstance(name: String) {
+            description("An instance with this ID, or name, is already managed by the instance manager.")
+            display("An instance with this ID, or name, is already managed by the instance manager: {}.", name)
+        }
+
+        #[doc = "The event queue of an instance is full, it may hold no more than this number of events."]
+        QueueFull(capacity: usize) {
+            description("The event queue of an instance is full.")
+            display("The event queue of an instance is full, it may hold no more than {} events.", capacity)
+        }
+
+        #[doc = "The event queue of an instance is full, and its `OverflowPolicy` is `Block`; the event posted is handed back, to be posted again once there is room."]
+        QueueBlocked(event: crate::definition::types::Event) {
+            description("The event queue of an instance is full, and the event posted is handed back.")
+            display("The event queue of an instance is full, and the event posted is handed back: {:?}.", event)
         }
 
         #[doc = "An event may not be posted while an action is running in a synchronous execution."]
@@ -0,0 +1,689 @@
+/*!
+An asynchronous adapter for `StateMachineInstance`, for hosts running on the
+[tokio](https://tokio.rs/) runtime; this module requires the `tokio` feature.
+
+An `AsyncStateMachineInstance` owns an instance behind an asynchronous mutex, so that it may be
+shared, as an `Arc`, by the tasks of a service; `post`, `tick`, and `execute` wait for any step
+in progress to complete, rather than failing, and each step still runs to completion before the
+next begins. Time events are driven by `AsyncStateMachineInstance::run`, which sleeps, with
+`tokio::time`, until the next timer of the instance is due and then ticks it, and which returns
+once the instance is done. The instance is given a `TokioClock`, so that its timers follow the
+runtime's clock, including where that clock is paused in tests. An instance may also be driven
+from a `Stream` of events, with `AsyncStateMachineInstance::drive`, yielding a `StepResult` per
+event.
+
+Behaviors that must await are implemented with `AsyncBehavior` and made into a `Behavior` with
+`spawned`; each time the behavior is performed its future is spawned as a task, and the events
+it returns are posted to the instance, as external events, once it completes. As a do-activity
+the state does not complete until the task has, and the task is aborted if the state is exited
+first. Guards that must await are implemented with `AsyncConstraint` and made into a
+`Constraint` with `awaited`, then given to the instance with
+`AsyncStateMachineInstance::with_guard`; they are evaluated before each step, against the
+context as it then is, and the executor uses the result throughout the step.
+
+Services invoked by the instance, see `execution::invoke`, may complete on other tasks or
+threads; their handlers should call `AsyncStateMachineInstance::wake` so that `run` ticks the
+instance and posts their outcome.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use std::time::Duration;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::async_rt::{spawned, AsyncStateMachineInstance};
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("download");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let mut fetching = State::within(region.id().clone());
+fetching.set_do_activity(Box::new(spawned("fetch", |_, _, _| async {
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    vec![Event::Signal(SignalEvent::new("fetched"))]
+})));
+let fetching_id = fetching.id().clone();
+region.add_state(fetching);
+let done = region.new_final_state();
+region.new_transition(initial, fetching_id.clone());
+let mut transition = Transition::within(fetching_id, done, region.id().clone());
+transition.add_trigger(Trigger::signal("fetched"));
+region.add_transition(transition);
+
+let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .unwrap();
+runtime.block_on(async {
+    let instance = Arc::new(AsyncStateMachineInstance::new(
+        StateMachineInstance::new(Arc::new(machine)).unwrap(),
+    ));
+    instance.execute().await.unwrap();
+    instance.run().await.unwrap();
+    assert!(instance.lock().await.is_done());
+});
+```
+
+*/
+
+use crate::core::{Context, ID};
+use crate::definition::activities::DoActivityHandle;
+use crate::definition::types::{Behavior, Constraint, Event, EventSink, Labeled, Trigger};
+use crate::error::Result;
+use crate::execution::timers::Clock;
+use crate::execution::types::{StateMachineInstance, StepResult};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as SyncMutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Mutex, MutexGuard, Notify};
+use tokio::task::AbortHandle;
+use tokio::time::Instant;
+
+// ------------------------------------------------------------------------------------------------
+// Public Traits
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A behavior that must await; the events it returns are posted to the instance that performed
+/// it, see `spawned`.
+///
+pub trait AsyncBehavior: Send + Sync {
+    fn perform(&self, in_state: ID, on_trigger: Trigger, context: Context)
+        -> BoxFuture<Vec<Event>>;
+}
+
+///
+/// A constraint that must await, see `awaited`.
+///
+pub trait AsyncConstraint: Send + Sync {
+    fn evaluate(&self, context: Context) -> BoxFuture<bool>;
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A future that may be spawned as a task.
+///
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+///
+/// An instance that may be shared by tasks, see the module documentation.
+///
+pub struct AsyncStateMachineInstance {
+    instance: Mutex<StateMachineInstance>,
+    clock: Arc<TokioClock>,
+    guards: Vec<TaskGuard>,
+    tasks: Arc<Tasks>,
+    /// The events returned by spawned behaviors, not yet posted.
+    returned: Mutex<UnboundedReceiver<Event>>,
+}
+
+///
+/// A clock that follows `tokio::time`, from the system time at which it was created.
+///
+#[derive(Debug)]
+pub struct TokioClock {
+    origin: SystemTime,
+    start: Instant,
+}
+
+///
+/// A behavior whose future is spawned as a task each time it is performed, see `spawned`.
+///
+#[derive(Clone)]
+pub struct TaskBehavior {
+    label: Option<String>,
+    behavior: Arc<dyn AsyncBehavior>,
+}
+
+///
+/// A guard evaluated, by an `AsyncStateMachineInstance`, before each step, see `awaited`; it
+/// does not hold until it has been evaluated.
+///
+#[derive(Clone)]
+pub struct TaskGuard {
+    label: Option<String>,
+    constraint: Arc<dyn AsyncConstraint>,
+    holds: Arc<AtomicBool>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Create a behavior, labeled `label`, that spawns the future of `behavior` as a task.
+///
+pub fn spawned(label: &str, behavior: impl AsyncBehavior + 'static) -> TaskBehavior {
+    TaskBehavior {
+        label: Some(label.to_string()),
+        behavior: Arc::new(behavior),
+    }
+}
+
+///
+/// Create a guard, labeled `label`, that holds where `constraint` last evaluated to `true`.
+///
+pub fn awaited(label: &str, constraint: impl AsyncConstraint + 'static) -> TaskGuard {
+    TaskGuard {
+        label: Some(label.to_string()),
+        constraint: Arc::new(constraint),
+        holds: Default::default(),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl<F, R> AsyncBehavior for F
+where
+    F: Fn(ID, Trigger, Context) -> R + Send + Sync,
+    R: Future<Output = Vec<Event>> + Send + 'static,
+{
+    fn perform(
+        &self,
+        in_state: ID,
+        on_trigger: Trigger,
+        context: Context,
+    ) -> BoxFuture<Vec<Event>> {
+        Box::pin(self(in_state, on_trigger, context))
+    }
+}
+
+impl<F, R> AsyncConstraint for F
+where
+    F: Fn(Context) -> R + Send + Sync,
+    R: Future<Output = bool> + Send + 'static,
+{
+    fn evaluate(&self, context: Context) -> BoxFuture<bool> {
+        Box::pin(self(context))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for AsyncStateMachineInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncStateMachineInstance")
+            .field("guards", &self.guards.len())
+            .finish()
+    }
+}
+
+impl AsyncStateMachineInstance {
+    ///
+    /// Own `instance`, which is given a `TokioClock` in place of its own clock.
+    ///
+    pub fn new(instance: StateMachineInstance) -> Self {
+        let clock = Arc::new(TokioClock::default());
+        let (returned, received) = unbounded_channel();
+        Self {
+            instance: Mutex::new(instance.with_clock(clock.clone())),
+            clock,
+            guards: Default::default(),
+            tasks: Arc::new(Tasks {
+                returned,
+                running: Default::default(),
+                wake: Default::default(),
+            }),
+            returned: Mutex::new(received),
+        }
+    }
+
+    ///
+    /// Evaluate `guard`, a guard of the instance's machine, before each step.
+    ///
+    pub fn with_guard(mut self, guard: TaskGuard) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    ///
+    /// Wait for any step in progress to complete, and lock the instance.
+    ///
+    pub async fn lock(&self) -> MutexGuard<'_, StateMachineInstance> {
+        self.instance.lock().await
+    }
+
+    ///
+    /// Start the instance, see `StateMachineInstance::execute`.
+    ///
+    pub async fn execute(&self) -> Result<()> {
+        self.dispatch(|instance| instance.execute()).await
+    }
+
+    ///
+    /// Post `event`, see `StateMachineInstance::post`.
+    ///
+    pub async fn post(&self, event: &Event) -> Result<()> {
+        self.dispatch(|instance| instance.post(event)).await
+    }
+
+    ///
+    /// Post `event`, and return what happened as a result, see `StateMachineInstance::step`.
+    ///
+    pub async fn step(&self, event: &Event) -> Result<StepResult> {
+        self.dispatch(|instance| instance.step(event)).await
+    }
+
+    ///
+    /// Step the instance with each of `events` in turn, as they are received, yielding the
+    /// outcome of each step; the equivalent of `StateMachineInstance::drive`.
+    ///
+    pub fn drive<'a, S>(&'a self, events: S) -> impl Stream<Item = Result<StepResult>> + 'a
+    where
+        S: Stream<Item = Event> + 'a,
+    {
+        stream::unfold(Box::pin(events), move |mut events| async move {
+            let event = events.next().await?;
+            Some((self.step(&event).await, events))
+        })
+    }
+
+    ///
+    /// Post `event`, with `payload`, see `StateMachineInstance::post_with`.
+    ///
+    pub async fn post_with(&self, event: &Event, payload: Context) -> Result<()> {
+        self.dispatch(|instance| instance.post_with(event, payload))
+            .await
+    }
+
+    ///
+    /// Update the context, see `StateMachineInstance::update_context`.
+    ///
+    pub async fn update_context<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Context),
+    {
+        self.dispatch(|instance| instance.update_context(f)).await
+    }
+
+    ///
+    /// Dispatch any elapsed timers, see `StateMachineInstance::tick`.
+    ///
+    pub async fn tick(&self) -> Result<usize> {
+        self.dispatch(|instance| instance.tick()).await
+    }
+
+    ///
+    /// Wake `run`, so that it ticks the instance, as where a service it invoked has completed.
+    ///
+    pub fn wake(&self) {
+        self.tasks.wake.notify_one()
+    }
+
+    ///
+    /// Drive the instance, starting it if it is new, until it is done; posting the events
+    /// returned by spawned behaviors as they complete, and ticking the instance each time a
+    /// timer is due, a do-activity finishes, or it is woken.
+    ///
+    pub async fn run(&self) -> Result<()> {
+        if self.instance.lock().await.is_new() {
+            self.execute().await?;
+        }
+        loop {
+            let (done, due) = {
+                let instance = self.instance.lock().await;
+                (instance.is_done(), instance.next_timer())
+            };
+            if done {
+                return Ok(());
+            }
+            let delay = due.map(|due| {
+                due.duration_since(self.clock.now())
+                    .unwrap_or(Duration::ZERO)
+            });
+            let returned = tokio::select! {
+                event = async { self.returned.lock().await.recv().await } => event,
+                _ = self.tasks.wake.notified() => None,
+                _ = tokio::time::sleep(delay.unwrap_or(Duration::ZERO)), if delay.is_some() => None,
+            };
+            match returned {
+                Some(event) => self.post(&event).await?,
+                None => {
+                    let _ = self.tick().await?;
+                }
+            }
+        }
+    }
+
+    async fn dispatch<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut StateMachineInstance) -> Result<R>,
+    {
+        let mut instance = self.instance.lock().await;
+        if !self.guards.is_empty() {
+            let context = instance.context().clone();
+            for guard in &self.guards {
+                let holds = guard.constraint.evaluate(context.clone()).await;
+                guard.holds.store(holds, Ordering::SeqCst);
+            }
+        }
+        let previous = DISPATCHING.with(|current| current.replace(Some(self.tasks.clone())));
+        let result = f(&mut instance);
+        DISPATCHING.with(|current| {
+            let _ = current.replace(previous);
+        });
+        self.tasks.abort_cancelled();
+        result
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self {
+            origin: SystemTime::now(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> SystemTime {
+        self.origin + self.start.elapsed()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for TaskBehavior {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskBehavior")
+            .field("label", &self.label)
+            .finish()
+    }
+}
+
+impl Labeled for TaskBehavior {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Behavior for TaskBehavior {
+    ///
+    /// Spawn the future on the current runtime, if there is one, without a context; the events
+    /// it returns are discarded.
+    ///
+    fn perform(&self, in_state: &ID, on_trigger: &Trigger, events: &dyn EventSink) {
+        self.perform_in(in_state, on_trigger, events, &Context::default())
+    }
+
+    fn perform_in(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        _events: &dyn EventSink,
+        context: &Context,
+    ) {
+        let _ = self.spawn(in_state, on_trigger, context, None);
+    }
+
+    fn start(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        _events: &dyn EventSink,
+        handle: DoActivityHandle,
+    ) {
+        if !self.spawn(
+            in_state,
+            on_trigger,
+            &Context::default(),
+            Some(handle.clone()),
+        ) {
+            handle.finish()
+        }
+    }
+
+    fn duplicate(&self) -> Option<Box<dyn Behavior>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl TaskBehavior {
+    ///
+    /// Spawn the future, returning `false` where there is no runtime to spawn it on.
+    ///
+    fn spawn(
+        &self,
+        in_state: &ID,
+        on_trigger: &Trigger,
+        context: &Context,
+        handle: Option<DoActivityHandle>,
+    ) -> bool {
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Err(_) => return false,
+            Ok(runtime) => runtime,
+        };
+        let tasks = DISPATCHING.with(|current| current.borrow().clone());
+        let future = self
+            .behavior
+            .perform(in_state.clone(), on_trigger.clone(), context.clone());
+        let returned_to = tasks.clone();
+        let finishing = handle.clone();
+        let task = runtime.spawn(async move {
+            let events = future.await;
+            if let Some(tasks) = returned_to {
+                for event in events {
+                    let _ = tasks.returned.send(event);
+                }
+                if let Some(handle) = finishing {
+                    handle.finish();
+                }
+                tasks.wake.notify_one();
+            } else if let Some(handle) = finishing {
+                handle.finish();
+            }
+        });
+        if let (Some(tasks), Some(handle)) = (tasks, handle) {
+            tasks
+                .running
+                .lock()
+                .unwrap()
+                .push((handle, task.abort_handle()));
+        }
+        true
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for TaskGuard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskGuard")
+            .field("label", &self.label)
+            .field("holds", &self.holds.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl Labeled for TaskGuard {
+    fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string())
+    }
+
+    fn unset_label(&mut self) {
+        self.label = None
+    }
+}
+
+impl Constraint for TaskGuard {
+    fn evaluate(&self, _in_state: &ID, _on_trigger: &Trigger) -> bool {
+        self.holds.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// A copy sharing the evaluations of this guard.
+    ///
+    fn duplicate(&self) -> Option<Box<dyn Constraint>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Shared between an `AsyncStateMachineInstance` and the tasks spawned while it dispatches.
+///
+struct Tasks {
+    returned: UnboundedSender<Event>,
+    /// The do-activities spawned as tasks, with the handles to abort them.
+    running: SyncMutex<Vec<(DoActivityHandle, AbortHandle)>>,
+    wake: Notify,
+}
+
+impl Tasks {
+    ///
+    /// Abort the tasks of do-activities whose states have been exited, and forget those that
+    /// have finished.
+    ///
+    fn abort_cancelled(&self) {
+        self.running.lock().unwrap().retain(|(handle, task)| {
+            if handle.is_cancelled() {
+                task.abort();
+            }
+            !(handle.is_cancelled() || handle.is_finished())
+        })
+    }
+}
+
+thread_local! {
+    /// The tasks of the instance dispatching an event on this thread, if any.
+    static DISPATCHING: RefCell<Option<Arc<Tasks>>> = const { RefCell::new(None) };
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::Compound;
+    use crate::core::FieldPath;
+    use crate::definition::types::{HasRegions, Identified, State, StateMachine, Transition};
+    use crate::execution::fixtures::signal;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_async_instance() {
+        let machine = StateMachine::labeled("door");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let closed_id = region.new_simple_state();
+        let mut opening = State::within(region.id().clone());
+        opening.set_do_activity(Box::new(spawned("motor", |_, _, _| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            vec![]
+        })));
+        let opening_id = opening.id().clone();
+        region.add_state(opening);
+        let open = region.new_simple_state();
+        let done = region.new_final_state();
+        region.new_transition(initial, closed_id.clone());
+        let allowed = awaited("allowed", |context: Context| async move {
+            tokio::task::yield_now().await;
+            context.contains_key("key".parse::<FieldPath>().unwrap())
+        });
+        let mut push =
+            Transition::within(closed_id.clone(), opening_id.clone(), region.id().clone());
+        push.add_trigger(Trigger::signal("push"));
+        push.set_guard(Box::new(allowed.clone()));
+        region.add_transition(push);
+        let mut stop =
+            Transition::within(opening_id.clone(), closed_id.clone(), region.id().clone());
+        stop.add_trigger(Trigger::signal("stop"));
+        region.add_transition(stop);
+        region.new_transition(opening_id.clone(), open.clone());
+        let mut leave = Transition::within(open.clone(), done, region.id().clone());
+        leave.add_trigger(Trigger::after(Duration::from_secs(5)));
+        region.add_transition(leave);
+
+        let instance = Arc::new(
+            AsyncStateMachineInstance::new(StateMachineInstance::new(Arc::new(machine)).unwrap())
+                .with_guard(allowed),
+        );
+        let running = {
+            let instance = instance.clone();
+            tokio::spawn(async move { instance.run().await })
+        };
+        tokio::task::yield_now().await;
+        assert!(instance.lock().await.is_in_state(&closed_id));
+
+        // the guard does not hold without the key, and the state is exited before the motor stops
+        instance.post(&signal("push")).await.unwrap();
+        assert!(instance.lock().await.is_in_state(&closed_id));
+        instance
+            .update_context(|context| {
+                context.insert("key".parse::<FieldPath>().unwrap(), true.into())
+            })
+            .await
+            .unwrap();
+        instance.post(&signal("push")).await.unwrap();
+        assert!(instance.lock().await.is_in_state(&opening_id));
+        instance.post(&signal("stop")).await.unwrap();
+        assert!(instance.lock().await.is_in_state(&closed_id));
+        assert!(instance.tasks.running.lock().unwrap().is_empty());
+
+        // the motor finishes, the state completes, and the timeout elapses
+        instance.post(&signal("push")).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert!(instance.lock().await.is_in_state(&open));
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        running.await.unwrap().unwrap();
+        assert!(instance.lock().await.is_done());
+    }
+
+    #[tokio::test]
+    async fn test_drive_stream() {
+        let machine = StateMachine::labeled("switch");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let off = region.new_simple_state();
+        let on = region.new_simple_state();
+        region.new_transition(initial, off.clone());
+        let mut toggle = Transition::within(off.clone(), on.clone(), region.id().clone());
+        toggle.add_trigger(Trigger::signal("toggle"));
+        region.add_transition(toggle);
+
+        let instance =
+            AsyncStateMachineInstance::new(StateMachineInstance::new(Arc::new(machine)).unwrap());
+        instance.execute().await.unwrap();
+        let results: Vec<Result<StepResult>> = instance
+            .drive(stream::iter(vec![signal("toggle"), signal("toggle")]))
+            .collect()
+            .await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].as_ref().unwrap().entered().collect::<Vec<&ID>>(),
+            vec![&on]
+        );
+        assert!(results[1].as_ref().unwrap().is_empty());
+        assert!(instance.lock().await.is_in_state(&on));
+    }
+}
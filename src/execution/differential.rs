@@ -0,0 +1,254 @@
+/*!
+Differential testing of a machine against its flattened equivalent, see `transform::flatten`,
+so that the two ways of executing a machine are kept from drifting apart.
+
+`compare_flattened` drives an instance of the machine, and an instance of the flat machine, with
+the same events, recording each with a `TraceRecorder`, and compares the traces entry by entry.
+As the states of the two machines differ, each entry is compared as an `Observation`: the event,
+the active configuration, as the labels of the active states from the outermost in, the hash of
+the context, and whether the instance was done. A flat state is labeled with the labels of its
+leaf and the states containing it, as `on/idle`, and the history it remembers, as
+`off [on/idle]`, which is not part of the configuration; the states of the machine should
+therefore be labeled, and labels not contain `/`. Entry, exit, and effect behaviors that record
+what they did in the context make the order in which they are performed part of the comparison.
+
+The machines created by `generator::MachineGenerator` are suitable for such comparisons, and
+the unit tests of this module compare a number of them.
+
+# Example
+
+```rust
+use uml_state_machine::definition::generator::MachineGenerator;
+use uml_state_machine::execution::differential::compare_flattened;
+
+let mut generator = MachineGenerator::new(1);
+let machine = generator.machine().unwrap();
+let events = generator.events(20);
+assert_eq!(compare_flattened(machine, &events).unwrap(), None);
+```
+
+*/
+
+use crate::core::ID;
+use crate::definition::transform::flatten;
+use crate::definition::types::{Event, Labeled, StateMachine};
+use crate::error::Result;
+use crate::execution::trace::{TraceEntry, TraceRecorder};
+use crate::execution::types::StateMachineInstance;
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A trace entry, of either instance, in terms that may be compared between the two.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    event: Option<Event>,
+    configuration: Vec<String>,
+    context_hash: u64,
+    done: bool,
+}
+
+///
+/// The first entry in which the instances of the machine and of the flat machine differ.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    index: usize,
+    interpreted: Option<Observation>,
+    flattened: Option<Observation>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Drive an instance of `machine`, and of its flattened equivalent, with `events`, until either
+/// is done, returning the first divergence between them, or `None` where they behaved the
+/// same. An error is returned where the machine cannot be flattened, either instance cannot be
+/// created, or an event fails for one instance and not the other.
+///
+pub fn compare_flattened(
+    machine: Arc<StateMachine>,
+    events: &[Event],
+) -> Result<Option<Divergence>> {
+    let flat = Arc::new(flatten(&machine)?);
+    let interpreted = Driven::new(machine)?;
+    let flattened = Driven::new(flat)?;
+
+    let mut index = 0;
+    let mut events = events.iter();
+    loop {
+        let lhs = interpreted.observations();
+        let rhs = flattened.observations();
+        for offset in 0..lhs.len().max(rhs.len()) {
+            let (lhs, rhs) = (lhs.get(offset), rhs.get(offset));
+            if lhs != rhs {
+                return Ok(Some(Divergence {
+                    index: index + offset,
+                    interpreted: lhs.cloned(),
+                    flattened: rhs.cloned(),
+                }));
+            }
+        }
+        index += lhs.len();
+        if interpreted.instance.is_done() || flattened.instance.is_done() {
+            return Ok(None);
+        }
+        match events.next() {
+            None => return Ok(None),
+            Some(event) => {
+                interpreted.instance.post(event)?;
+                flattened.instance.post(event)?;
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Observation {
+    ///
+    /// The event dispatched, `None` for the start of the instance.
+    ///
+    pub fn event(&self) -> Option<&Event> {
+        self.event.as_ref()
+    }
+
+    ///
+    /// The labels of the active states once the event had been processed, outermost first.
+    ///
+    pub fn configuration(&self) -> &[String] {
+        &self.configuration
+    }
+
+    pub fn context_hash(&self) -> u64 {
+        self.context_hash
+    }
+
+    ///
+    /// Returns `true` if the instance was done once the event had been processed.
+    ///
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Divergence {
+    ///
+    /// The index of the entry, within both traces.
+    ///
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    ///
+    /// The entry of the instance of the machine, `None` where it recorded fewer entries.
+    ///
+    pub fn interpreted(&self) -> Option<&Observation> {
+        self.interpreted.as_ref()
+    }
+
+    ///
+    /// The entry of the instance of the flat machine, `None` where it recorded fewer entries.
+    ///
+    pub fn flattened(&self) -> Option<&Observation> {
+        self.flattened.as_ref()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct Driven {
+    instance: StateMachineInstance,
+    recorder: Arc<TraceRecorder>,
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Driven {
+    fn new(machine: Arc<StateMachine>) -> Result<Self> {
+        let recorder = Arc::new(TraceRecorder::default());
+        let instance = StateMachineInstance::new(machine)?;
+        instance.add_observer(recorder.clone());
+        instance.execute()?;
+        Ok(Self { instance, recorder })
+    }
+
+    ///
+    /// The entries recorded since last called, the last of which is done where the instance is.
+    ///
+    fn observations(&self) -> Vec<Observation> {
+        let trace = self.recorder.take();
+        let last = trace.len().saturating_sub(1);
+        trace
+            .entries()
+            .enumerate()
+            .map(|(index, entry)| self.observe(entry, index == last && self.instance.is_done()))
+            .collect()
+    }
+
+    fn observe(&self, entry: &TraceEntry, done: bool) -> Observation {
+        Observation {
+            event: entry.event().cloned(),
+            configuration: entry
+                .after()
+                .iter()
+                .flat_map(|state| self.labels(state))
+                .collect(),
+            context_hash: entry.context_hash(),
+            done,
+        }
+    }
+
+    ///
+    /// The labels of `state`, those of a flat state being split into the labels of its leaf
+    /// and the states containing it.
+    ///
+    fn labels(&self, state: &ID) -> Vec<String> {
+        let label = self.instance.index.vertices[state]
+            .as_state()
+            .and_then(|state| state.label().clone())
+            .unwrap_or_default();
+        let label = match label.find(" [") {
+            None => label.as_str(),
+            Some(end) => &label[..end],
+        };
+        label.split('/').map(str::to_string).collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::generator::MachineGenerator;
+
+    #[test]
+    fn test_generated_machines_agree() {
+        for seed in 0..200 {
+            let mut generator = MachineGenerator::new(seed);
+            let machine = generator.machine().unwrap();
+            let events = generator.events(30);
+            assert_eq!(
+                compare_flattened(machine, &events).unwrap(),
+                None,
+                "seed {}",
+                seed
+            );
+        }
+    }
+}
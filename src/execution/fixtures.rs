@@ -0,0 +1,52 @@
+/*!
+Fixtures shared by the unit tests of the execution modules, for building small machines and the
+events that drive them.
+*/
+
+use crate::core::ID;
+use crate::definition::types::{
+    Behavior, Event, Identified, Labeled, Region, SignalEvent, State, Transition, Trigger,
+};
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+pub(crate) fn signal(name: &str) -> Event {
+    Event::Signal(SignalEvent::new(name))
+}
+
+///
+/// Add a state labeled `label` to `region`, with the entry behavior `entry`, returning its ID.
+///
+pub(crate) fn add_state(region: &Region, label: &str, entry: Option<Box<dyn Behavior>>) -> ID {
+    let mut state = State::within(region.id().clone());
+    state.set_label(label);
+    if let Some(entry) = entry {
+        state.set_entry(entry);
+    }
+    let id = state.id().clone();
+    region.add_state(state);
+    id
+}
+
+///
+/// Add a transition from `source` to `target` to `region`, triggered by the signal `signal`, or a
+/// completion transition where there is none.
+///
+pub(crate) fn add_transition(region: &Region, source: &ID, target: &ID, signal: Option<&str>) {
+    let mut transition = Transition::within(source.clone(), target.clone(), region.id().clone());
+    if let Some(signal) = signal {
+        transition.add_trigger(Trigger::signal(signal));
+    }
+    region.add_transition(transition);
+}
+
+///
+/// Add a transition from `source` to `target` to `region`, triggered by `trigger`.
+///
+pub(crate) fn add_triggered(region: &Region, source: &ID, target: &ID, trigger: Trigger) {
+    let mut transition = Transition::within(source.clone(), target.clone(), region.id().clone());
+    transition.add_trigger(trigger);
+    region.add_transition(transition);
+}
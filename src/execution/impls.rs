@@ -1,118 +1,1022 @@
 /*!
-One-line description.
-
-More detailed description, with
-
-# Example
-
+Implementations for the types in `execution::types`.
 */
 
-use crate::definition::iterators::Actions;
-use crate::definition::InternalEvent;
-use crate::{ErrorKind, Result, State, StateID, StateKind, StateMachine, Transition};
-use std::cell::RefCell;
-use std::collections::hash_map::RandomState;
-use std::collections::hash_set::Iter;
-use std::collections::HashSet;
+use crate::core::context::Compound;
+use crate::core::{Context, ID};
+use crate::definition::activities::DoActivityHandle;
+use crate::definition::guards::{is_else, GuardOutcome};
+use crate::definition::impls::triggered_by;
+use crate::definition::opaque::Bindings;
+use crate::definition::registry::{bind_with, BindingMode};
+use crate::definition::types::{
+    Behavior, Contained, Delivery, Event, EventKey, EventSink, HasRegions, Identified, Labeled,
+    PseudoState, PseudoStateKind, Region, SendTarget, SignalEvent, StateMachine, Transition,
+    Trigger, Validate, Vertex,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::execution::invoke::{Invocation, InvokeHandle, InvokeHandler, InvokeOutcome};
+use crate::execution::stats::{ExecutionStats, Throughput, ThroughputMetrics};
+use crate::execution::timers::{Clock, SendId, TimerService};
+use crate::execution::types::{
+    ActiveInvoke, ChangeWatch, ChartIndex, Drive, Enabled, ErrorPolicy, EvaluatedGuard,
+    ExecutionObserver, ExecutionState, FiredTransition, Hook, HookTable, InstanceSnapshot,
+    InternalQueue, Occurrence, OverflowPolicy, PerformedBehavior, SeededIds, SentEvent,
+    SnapshotStore, StateMachineInstance, StepResult, ERROR_SIGNAL,
+};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Formatter};
-use std::hash::Hash;
-use std::iter::FromIterator;
-use std::panic;
-use std::rc::Rc;
-
-// ------------------------------------------------------------------------------------------------
-// Public Types
-// ------------------------------------------------------------------------------------------------
-
-// ------------------------------------------------------------------------------------------------
-// Public Functions
-// ------------------------------------------------------------------------------------------------
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice::Iter;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 // ------------------------------------------------------------------------------------------------
-// Implementations
+// Implementations - StateMachineInstance
 // ------------------------------------------------------------------------------------------------
 
-impl<E: Clone + Eq + Hash + Debug, D: Debug> Debug for StateMachineInstance<E, D> {
+impl Debug for StateMachineInstance {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("StateMachineInstance")
             .field("id", &self.id)
-            .field("chart", &self.chart)
+            .field("chart", self.chart.id())
             .field("active", &self.active)
+            .field("queue", &self.queue)
+            .field("queue_capacity", &self.queue_capacity)
+            .field("internal", &self.internal)
+            .field("deferred", &self.deferred)
+            .field("timers", &self.timers)
+            .field("activities", &self.activities)
+            .field(
+                "invoke_handlers",
+                &self.invoke_handlers.keys().collect::<Vec<&String>>(),
+            )
+            .field("invocations", &self.invocations)
+            .field("history", &self.history)
+            .field("changes", &self.changes)
+            .field("done_behaviors", &self.done_behaviors.len())
+            .field("snapshot_store", &self.snapshot_store.is_some())
+            .field("tie_breaker", &self.tie_breaker.is_some())
+            .field("deterministic", &self.deterministic)
+            .field("microstep_limit", &self.microstep_limit)
+            .field("microsteps", &self.microsteps)
+            .field("error_policy", &self.error_policy)
+            .field("observers", &self.observers.borrow().len())
+            .field("hooks", &self.hooks.borrow().len())
+            .field("parent", &self.parent)
+            .field("outbox", &self.outbox)
+            .field("record", &self.record)
+            .field("panicked", &self.panicked)
             .field("context", &self.context)
             .field("state", &self.state)
+            .field("paused", &self.paused)
+            .field("stats", &self.stats)
+            .field("throughput", &self.throughput)
             .finish()
     }
 }
 
-impl<E: Clone + Eq + Hash, D> StateMachineInstance<E, D> {
-    pub fn new(chart: Rc<StateMachine<E, D>>, context: D) -> Self {
-        assert!(chart.validate().is_ok());
-        Self {
-            id: StateID::random_with_prefix("execution").unwrap(),
+impl StateMachineInstance {
+    ///
+    /// Create a new instance of `chart`, which must be valid, with an empty context.
+    ///
+    pub fn new(chart: Arc<StateMachine>) -> Result<Self> {
+        Self::with_context(chart, Context::default())
+    }
+
+    ///
+    /// Create a new instance of `chart`, which must be valid, with the provided context. To be
+    /// executed every choice pseudostate must also have at least one outbound transition.
+    ///
+    pub fn with_context(chart: Arc<StateMachine>, context: Context) -> Result<Self> {
+        chart.validate()?;
+        Self::from_valid(chart, context)
+    }
+
+    ///
+    /// Create a new instance of `chart`, as for `with_context`, once its opaque elements are bound
+    /// to `bindings` in `mode`, see `registry::bind_with`. In `BindingMode::Strict` this fails,
+    /// and no instance is created, where the bindings do not match the reachable elements of
+    /// `chart`.
+    ///
+    pub fn with_bindings(
+        chart: Arc<StateMachine>,
+        context: Context,
+        bindings: &Bindings,
+        mode: BindingMode,
+    ) -> Result<Self> {
+        chart.validate()?;
+        let _ = bind_with(&chart, bindings, mode)?;
+        Self::from_valid(chart, context)
+    }
+
+    fn from_valid(chart: Arc<StateMachine>, context: Context) -> Result<Self> {
+        let index = ChartIndex::from(chart.as_ref());
+        index.validate_choices()?;
+        Ok(Self {
+            id: ID::random_with_prefix("execution")?,
+            index: Arc::new(index),
             chart,
             active: Default::default(),
+            queue: Default::default(),
+            queue_capacity: None,
+            internal: Default::default(),
+            deferred: Default::default(),
+            timers: Default::default(),
+            tie_breaker: None,
+            deterministic: false,
+            microstep_limit: None,
+            microsteps: Default::default(),
+            error_policy: Default::default(),
+            observers: Default::default(),
+            hooks: Default::default(),
+            parent: None,
+            outbox: Default::default(),
+            record: Default::default(),
+            panicked: Default::default(),
+            activities: Default::default(),
+            invoke_handlers: Default::default(),
+            invocations: Default::default(),
+            history: Default::default(),
+            changes: Default::default(),
+            done_behaviors: Default::default(),
+            snapshot_store: None,
             context: RefCell::new(context),
             state: RefCell::new(ExecutionState::New),
+            paused: RefCell::new(false),
+            stats: Default::default(),
+            throughput: Default::default(),
+        })
+    }
+
+    ///
+    /// Use `clock` as the source of time for this instance's timers, in place of the system
+    /// clock. This should be called before the instance is started.
+    ///
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        let timers = TimerService::with_clock(clock);
+        timers.restore(self.timers.scheduled());
+        timers.restore_sends(self.timers.scheduled_sends(), self.timers.next_send());
+        Self { timers, ..self }
+    }
+
+    ///
+    /// Use `error_policy` to determine what happens when a step fails, in place of
+    /// `ErrorPolicy::FailFast`.
+    ///
+    pub fn with_error_policy(self, error_policy: ErrorPolicy) -> Self {
+        Self {
+            error_policy,
+            ..self
+        }
+    }
+
+    ///
+    /// Limit the number of events that may be waiting, queued or deferred, to `capacity`, at
+    /// least one, with `overflow_policy` determining what happens to events queued once it is
+    /// full. Events raised by behaviors, and deferred events recalled to the queue, are exempt,
+    /// see `execution::types`.
+    ///
+    pub fn with_queue_capacity(self, capacity: usize, overflow_policy: OverflowPolicy) -> Self {
+        Self {
+            queue_capacity: Some((capacity.max(1), overflow_policy)),
+            ..self
+        }
+    }
+
+    ///
+    /// Measure the instance's throughput, see `metrics`, over the most recent `window`, in place
+    /// of `DEFAULT_THROUGHPUT_WINDOW`.
+    ///
+    pub fn with_throughput_window(self, window: Duration) -> Self {
+        let _ = self.throughput.replace(Throughput::new(window));
+        self
+    }
+
+    ///
+    /// Perform `behavior` as the instance completes, after any behaviors already given; see the
+    /// module documentation for the order in which an instance finishes.
+    ///
+    pub fn with_done_behavior(self, behavior: impl Behavior + 'static) -> Self {
+        let mut done_behaviors = self.done_behaviors;
+        done_behaviors.push(Arc::new(behavior));
+        Self {
+            done_behaviors,
+            ..self
+        }
+    }
+
+    ///
+    /// Save the instance's snapshot to `store` as the instance completes, the last thing it does
+    /// before the step that completed it returns.
+    ///
+    pub fn with_snapshot_store(self, store: impl SnapshotStore + 'static) -> Self {
+        Self {
+            snapshot_store: Some(Arc::new(store)),
+            ..self
+        }
+    }
+
+    ///
+    /// Use `handler` to start the services of the kind `kind` invoked by states, see `invoke`.
+    /// This should be called before the instance is started.
+    ///
+    pub fn with_invoke_handler<H>(self, kind: &str, handler: H) -> Self
+    where
+        H: InvokeHandler + 'static,
+    {
+        let mut invoke_handlers = self.invoke_handlers;
+        let _ = invoke_handlers.insert(kind.to_string(), Arc::new(handler));
+        Self {
+            invoke_handlers,
+            ..self
+        }
+    }
+
+    ///
+    /// Make `parent` the target of events sent by this instance's behaviors to
+    /// `SendTarget::Parent`; without a parent such events are dropped.
+    ///
+    pub fn with_parent(self, parent: ID) -> Self {
+        Self {
+            parent: Some(parent),
+            ..self
+        }
+    }
+
+    ///
+    /// Create an instance of `chart` in the state recorded by `snapshot`, which must have been
+    /// taken from an instance of a machine with the same ID. The do-activity of each active state
+    /// that had not finished is started again, but no entry behaviors are performed.
+    ///
+    pub fn restore(chart: Arc<StateMachine>, snapshot: &InstanceSnapshot) -> Result<Self> {
+        if chart.id() != &snapshot.chart {
+            return Err(invalid_snapshot(&format!(
+                "it was taken from an instance of the machine '{}'",
+                snapshot.chart
+            )));
+        }
+        let mut instance = Self::with_context(chart, snapshot.context.deep_clone())?;
+        instance.id = snapshot.id.clone();
+        {
+            let index = &instance.index;
+            let mut active = instance.active.borrow_mut();
+            for state in &snapshot.active {
+                let region = index
+                    .vertex_region
+                    .get(state)
+                    .ok_or_else(|| invalid_snapshot(&format!("'{}' is not a state", state)))?;
+                if active.insert(region.clone(), state.clone()).is_some() {
+                    return Err(invalid_snapshot(&format!(
+                        "more than one state is active in the region '{}'",
+                        region
+                    )));
+                }
+            }
+            if let Some(region) = snapshot
+                .history
+                .keys()
+                .find(|region| !index.region_owner.contains_key(region))
+            {
+                return Err(invalid_snapshot(&format!("'{}' is not a region", region)));
+            }
+        }
+        let _ = instance.history.replace(snapshot.history.clone());
+        let _ = instance
+            .queue
+            .replace(snapshot.queue.iter().cloned().collect());
+        let _ = instance
+            .internal
+            .events
+            .replace(snapshot.internal.iter().cloned().collect());
+        let _ = instance.deferred.replace(snapshot.deferred.clone());
+        instance.timers.restore(snapshot.timers.clone());
+        instance
+            .timers
+            .restore_sends(snapshot.sends.clone(), snapshot.next_send);
+        let _ = instance.state.replace(snapshot.state.clone());
+        let _ = instance.paused.replace(snapshot.paused);
+        let trigger = Trigger::any();
+        let now = instance.timers.now();
+        for state in &snapshot.active {
+            instance.stats.borrow_mut().active_since(state, now);
+            instance.watch_changes(state);
+            if snapshot.activities.contains(state) {
+                instance.start_activity(state, &trigger);
+            }
+        }
+        instance.take_panic()?;
+        Ok(instance)
+    }
+
+    ///
+    /// Use `tie_breaker` to choose between transitions from the same state that are enabled by
+    /// the same event, in place of failing with `MoreThanOneTransition`.
+    ///
+    pub fn with_tie_breaker<F>(self, tie_breaker: F) -> Self
+    where
+        F: Fn(&ID, &[Arc<Transition>]) -> usize + Send + Sync + 'static,
+    {
+        Self {
+            tie_breaker: Some(Arc::new(tie_breaker)),
+            ..self
+        }
+    }
+
+    ///
+    /// Resolve conflicts between transitions by choosing the first in document order, where
+    /// there is no tie-breaker, in place of failing with `MoreThanOneTransition`.
+    ///
+    pub fn deterministic(self) -> Self {
+        Self {
+            deterministic: true,
+            ..self
+        }
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    ///
+    /// Fail a step with `MicrostepLimit` once more than `limit` transitions, including those
+    /// from pseudostates, have fired in response to completion and raised events since the
+    /// last external event was dispatched; this detects machines that cycle forever.
+    ///
+    pub fn with_microstep_limit(self, limit: usize) -> Self {
+        Self {
+            microstep_limit: Some(limit),
+            ..self
         }
     }
 
-    pub fn chart(&self) -> Rc<StateMachine<E, D>> {
+    ///
+    /// Use `id` as the ID of this instance, in place of a generated one, for example from
+    /// `SeededIds`. This should be called before the instance is started, or added to a manager.
+    ///
+    pub fn with_id(self, id: ID) -> Self {
+        Self { id, ..self }
+    }
+
+    pub fn id(&self) -> &ID {
+        &self.id
+    }
+
+    pub fn parent(&self) -> Option<&ID> {
+        self.parent.as_ref()
+    }
+
+    pub fn chart(&self) -> Arc<StateMachine> {
         self.chart.clone()
     }
 
-    pub fn execute(&mut self) -> Result<()> {
-        debug!("StateMachine::execute");
+    pub fn context(&self) -> Ref<'_, Context> {
+        self.context.borrow()
+    }
+
+    ///
+    /// Mutable access to the context, note that change events are not re-evaluated after changes
+    /// made this way until the next event is dispatched; `update_context` re-evaluates them
+    /// immediately.
+    ///
+    pub fn context_mut(&self) -> RefMut<'_, Context> {
+        self.context.borrow_mut()
+    }
+
+    ///
+    /// Change the context with `f`, and then, for an active instance, dispatch any change events
+    /// that occur as a result, and fire the guarded, trigger-less, transitions of completed states
+    /// whose guards now hold. As for `post`, if a step is in progress they are dispatched once
+    /// it completes, and if the instance is paused once it is resumed.
+    ///
+    pub fn update_context<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Context),
+    {
+        f(&mut self.context.borrow_mut());
+        if self.is_active() && !self.is_in_step() && !self.is_paused() {
+            self.run(|instance| instance.check_completions().map(|_| ()))
+        } else {
+            Ok(())
+        }
+    }
+
+    ///
+    /// Start the instance by entering each of the machine's regions through its initial
+    /// pseudostate, and then processing any resulting completion events, and any events posted by
+    /// behaviors in the meantime, to completion.
+    ///
+    pub fn execute(&self) -> Result<()> {
         if self.is_done() {
             Err(ErrorKind::InstanceIsDone.into())
-        } else if self.is_active() {
+        } else if !self.is_new() {
             Err(ErrorKind::InstanceIsActive.into())
-        } else if self.state.borrow().is_in_action() {
-            Err(ErrorKind::EventDuringAction.into())
         } else {
-            let initial_state_id = self.chart.initial_state_id();
-            let initial_state = self.chart.get_state(&initial_state_id).unwrap();
-            self.post_internal_event(&initial_state_id, None, &InternalEvent::Init);
-            self.active = HashSet::from_iter(self.enter_state(initial_state, false).drain(..));
-            self.check_done();
+            self.run(Self::start)
+        }
+    }
+
+    ///
+    /// Post `event` to the instance's queue, and if no step is in progress process the queue to
+    /// completion. Each event is dispatched in turn, the transitions it enables fire, and then
+    /// any completion events that result are processed, in microsteps, until no more
+    /// transitions are enabled before the next event is dispatched. An event posted while a step
+    /// is in progress, for example by an entry behavior, is queued and dispatched once the
+    /// current step completes. Events that enable no transition are discarded, unless an active
+    /// state defers them. Where the queue is full the event is handled according to the
+    /// instance's `OverflowPolicy`, see `with_queue_capacity`.
+    ///
+    pub fn post(&self, event: &Event) -> Result<()> {
+        self.post_occurrence(event, None)
+    }
+
+    ///
+    /// Post `event`, as for `post`, carrying `payload`. The payload is matched by the payload
+    /// predicates of triggers, see `Trigger::matches_payload`, and the guards and behaviors
+    /// performed as a result of the event are given it with the trigger that fired, see
+    /// `Trigger::payload`.
+    ///
+    pub fn post_with(&self, event: &Event, payload: Context) -> Result<()> {
+        self.post_occurrence(event, Some(payload))
+    }
+
+    fn schedule_send(
+        &self,
+        delay: Duration,
+        event: &Event,
+        payload: Option<Context>,
+    ) -> Result<SendId> {
+        if self.is_done() {
+            Err(ErrorKind::InstanceIsDone.into())
+        } else {
+            Ok(self.timers.schedule_send(delay, event, payload))
+        }
+    }
+
+    fn post_occurrence(&self, event: &Event, payload: Option<Context>) -> Result<()> {
+        if self.is_done() {
+            return Err(ErrorKind::InstanceIsDone.into());
+        } else if self.is_new() || self.is_in_error() {
+            return Err(ErrorKind::InstanceIsNotActive.into());
+        }
+        if self.enqueue(
+            Occurrence {
+                event: event.clone(),
+                target: None,
+                payload,
+            },
+            true,
+        )? {
+            self.throughput.borrow_mut().accepted(self.timers.now());
+        }
+        if self.is_in_step() || self.is_paused() {
             Ok(())
+        } else {
+            self.run(|_| Ok(()))
         }
     }
 
-    pub fn accepts(&self) -> HashSet<E, RandomState> {
-        self.active_states()
-            .map(|id| self.chart.get_state(id).unwrap())
-            .map(|state| state.accepts())
-            .flatten()
-            .collect()
+    ///
+    /// Recover an instance that is in the error state, so that it is again active, and then
+    /// process any queued events. The configuration is that left when the error occurred, so
+    /// this is most useful where an observer, or the owner of the instance, has determined that the
+    /// instance may safely continue.
+    ///
+    pub fn clear_error(&self) -> Result<()> {
+        if self.is_done() {
+            Err(ErrorKind::InstanceIsDone.into())
+        } else if !self.is_in_error() {
+            Ok(())
+        } else {
+            self.run(|_| Ok(()))
+        }
     }
 
-    pub fn post(&mut self, event: &E) -> Result<()> {
-        println!("StateMachine::post");
+    ///
+    /// Pause the instance, so that it does not advance until `resume` is called. While paused
+    /// events posted are queued but not dispatched, timers do not fire, and the outcomes of
+    /// do-activities and invoked services are not noticed. Where a step is in progress it stops
+    /// once the event being dispatched has been processed.
+    ///
+    pub fn pause(&self) -> Result<()> {
         if self.is_done() {
             Err(ErrorKind::InstanceIsDone.into())
-        } else if !self.is_active() {
+        } else if self.is_new() {
             Err(ErrorKind::InstanceIsNotActive.into())
-        } else if self.state.borrow().is_in_action() {
-            Err(ErrorKind::EventDuringAction.into())
         } else {
-            // TODO: remove this clone!
-            self.active = self
-                .active_states()
-                .map(|id| self.chart.get_state(id).unwrap())
-                .map(|st| self.handle_transition(&st, &Some(event)))
-                .flatten()
-                .collect::<HashSet<StateID>>();
-            self.check_done();
+            let _ = self.paused.replace(true);
+            Ok(())
+        }
+    }
+
+    ///
+    /// Resume a paused instance, and then process the events queued while it was paused, as for
+    /// `post`, and the changes to its context, as for `update_context`. Timers that fell due
+    /// while paused fire when `tick` is next called.
+    ///
+    pub fn resume(&self) -> Result<()> {
+        if !self.paused.replace(false) || self.is_in_step() || self.is_in_error() {
             Ok(())
+        } else {
+            self.run(|instance| instance.check_completions().map(|_| ()))
+        }
+    }
+
+    ///
+    /// Return the instance to new, with an empty context, as for `reset_with_context`.
+    ///
+    pub fn reset(&self) -> Result<()> {
+        self.reset_with_context(Context::default())
+    }
+
+    ///
+    /// Return the instance to new, with `context`, so that it may be started again with
+    /// `execute`. The configuration, history, queued, raised, deferred, and sent events, timers
+    /// and delayed sends, and statistics are discarded, and any do-activities and invoked
+    /// services are cancelled; the instance keeps its ID, observers, handlers, clock, and
+    /// policies. An instance may not be reset during a step.
+    ///
+    pub fn reset_with_context(&self, context: Context) -> Result<()> {
+        if self.is_in_step() {
+            return Err(ErrorKind::InstanceIsInStep.into());
+        }
+        self.active.borrow_mut().clear();
+        self.queue.borrow_mut().clear();
+        self.internal.events.borrow_mut().clear();
+        self.deferred.borrow_mut().clear();
+        self.outbox.borrow_mut().clear();
+        self.timers.clear();
+        for (_, activity) in self.activities.borrow_mut().drain() {
+            activity.cancel();
+        }
+        for active in self.invocations.borrow_mut().drain(..) {
+            active.handle.cancel();
+        }
+        self.history.borrow_mut().clear();
+        self.changes.borrow_mut().clear();
+        self.microsteps.borrow_mut().clear();
+        let _ = self.panicked.replace(None);
+        let _ = self.context.replace(context);
+        let _ = self.state.replace(ExecutionState::New);
+        let _ = self.paused.replace(false);
+        let _ = self.stats.replace(Default::default());
+        self.throughput.borrow_mut().reset();
+        Ok(())
+    }
+
+    ///
+    /// End the instance, as reaching a terminate pseudostate would; the active states are left
+    /// without performing their exit behaviors, and the instance then finishes as one that
+    /// completes does, see the module documentation. An instance may not be terminated during a
+    /// step, or before it has been started.
+    ///
+    pub fn terminate(&self) -> Result<()> {
+        if self.is_in_step() {
+            Err(ErrorKind::InstanceIsInStep.into())
+        } else if self.is_done() {
+            Err(ErrorKind::InstanceIsDone.into())
+        } else if self.is_new() {
+            Err(ErrorKind::InstanceIsNotActive.into())
+        } else {
+            self.active.borrow_mut().clear();
+            let _ = self.state.replace(ExecutionState::Done);
+            self.finish()
+        }
+    }
+
+    ///
+    /// Move the instance to `chart`, a revision of its machine, without exiting or entering any
+    /// state. Each active state, and each region and state remembered as history, moves to the
+    /// ID that `map` gives it, or keeps its ID where `map` gives none. This fails, leaving the
+    /// instance as it was, where `chart` is not valid, or where the states moved to are not a
+    /// configuration of `chart`, with one active state in each of its regions and in each region
+    /// of an active state.
+    ///
+    /// History that does not fit `chart` is forgotten. The timers, do-activities, and invoked
+    /// services of the states still active are kept, and the others cancelled, and change events
+    /// are evaluated afresh; hooks on states move with them, and hooks on transitions are
+    /// dropped. Queued and deferred events, and the context, are unchanged. An instance may not
+    /// be migrated during a step, before it has been started, or once it is done.
+    ///
+    pub fn migrate_to(&mut self, chart: Arc<StateMachine>, map: &HashMap<ID, ID>) -> Result<()> {
+        if self.is_in_step() {
+            return Err(ErrorKind::InstanceIsInStep.into());
+        } else if self.is_done() {
+            return Err(ErrorKind::InstanceIsDone.into());
+        } else if self.is_new() {
+            return Err(ErrorKind::InstanceIsNotActive.into());
+        }
+        chart.validate()?;
+        let index = ChartIndex::from(chart.as_ref());
+        index.validate_choices()?;
+        let moved = |id: &ID| map.get(id).cloned().unwrap_or_else(|| id.clone());
+
+        let mut active: HashMap<ID, ID> = Default::default();
+        for state in self.active_states() {
+            let target = moved(&state);
+            let region = index
+                .vertex_region
+                .get(&target)
+                .filter(|_| index.vertices[&target].as_state().is_some())
+                .ok_or_else(|| invalid_migration(&format!("'{}' is not a state", target)))?;
+            if active.insert(region.clone(), target).is_some() {
+                return Err(invalid_migration(&format!(
+                    "more than one state would be active in the region '{}'",
+                    region
+                )));
+            }
+        }
+        let owners: Vec<&ID> = std::iter::once(chart.id()).chain(active.values()).collect();
+        for owner in owners {
+            if let Some(region) = index
+                .regions_of(owner)
+                .find(|region| !active.contains_key(*region))
+            {
+                return Err(invalid_migration(&format!(
+                    "no state would be active in the region '{}'",
+                    region
+                )));
+            }
+        }
+        for state in active.values() {
+            if let Some(parent) = index.parent(state) {
+                if active.get(&index.vertex_region[parent]) != Some(parent) {
+                    return Err(invalid_migration(&format!(
+                        "'{}' would be active, but not the state '{}' containing it",
+                        state, parent
+                    )));
+                }
+            }
+        }
+        let is_active = |state: &ID| {
+            index
+                .vertex_region
+                .get(state)
+                .is_some_and(|region| active.get(region) == Some(state))
+        };
+
+        let history: HashMap<ID, Vec<ID>> = self
+            .history
+            .borrow()
+            .iter()
+            .map(|(region, states)| (moved(region), states.iter().map(moved).collect()))
+            .filter(|(region, states): &(ID, Vec<ID>)| {
+                index.region_owner.contains_key(region)
+                    && states
+                        .iter()
+                        .all(|state| index.vertices.contains_key(state))
+            })
+            .collect();
+        let timers = self
+            .timers
+            .scheduled()
+            .into_iter()
+            .map(|mut timer| {
+                timer.state = moved(&timer.state);
+                timer
+            })
+            .filter(|timer| {
+                is_active(&timer.state)
+                    && index.transitions_from(&timer.state).any(|transition| {
+                        transition
+                            .triggers()
+                            .any(|trigger| trigger.event() == &timer.event)
+                    })
+            })
+            .collect();
+        let activities = self
+            .activities
+            .borrow_mut()
+            .drain()
+            .filter_map(|(state, activity)| {
+                let state = moved(&state);
+                if is_active(&state) {
+                    Some((state, activity))
+                } else {
+                    activity.cancel();
+                    None
+                }
+            })
+            .collect();
+        self.invocations.borrow_mut().retain_mut(|invocation| {
+            invocation.state = moved(&invocation.state);
+            let kept = is_active(&invocation.state);
+            if !kept {
+                invocation.handle.cancel();
+            }
+            kept
+        });
+        let mut hooks = self.hooks.borrow_mut();
+        hooks.states = hooks
+            .states
+            .drain()
+            .map(|(state, observers)| (moved(&state), observers))
+            .filter(|(state, _)| index.vertices.contains_key(state))
+            .collect();
+        hooks.transitions.clear();
+        drop(hooks);
+
+        self.chart = chart;
+        self.index = Arc::new(index);
+        let _ = self.active.replace(active);
+        let _ = self.history.replace(history);
+        let _ = self.activities.replace(activities);
+        self.timers.restore(timers);
+        self.changes.borrow_mut().clear();
+        for state in self.active_states() {
+            self.watch_changes(&state);
+        }
+        self.take_panic()
+    }
+
+    ///
+    /// The statistics collected by this instance, up to now, see `stats`.
+    ///
+    pub fn stats(&self) -> ExecutionStats {
+        self.stats.borrow().as_of(self.timers.now())
+    }
+
+    ///
+    /// Discard the statistics collected so far, and collect them afresh from now.
+    ///
+    pub fn reset_stats(&self) {
+        self.stats.borrow_mut().reset(self.timers.now());
+        self.throughput.borrow_mut().reset()
+    }
+
+    ///
+    /// The throughput of this instance over the most recent window, see
+    /// `with_throughput_window`.
+    ///
+    pub fn metrics(&self) -> ThroughputMetrics {
+        self.throughput.borrow_mut().as_of(self.timers.now())
+    }
+
+    ///
+    /// The state of this instance, from which an equivalent instance may be created with
+    /// `restore`. A snapshot should be taken between steps, not by a behavior or an observer
+    /// during one.
+    ///
+    pub fn snapshot(&self) -> InstanceSnapshot {
+        let mut activities: Vec<ID> = self.activities.borrow().keys().cloned().collect();
+        activities.sort_by_key(|state| self.index.order[state]);
+        InstanceSnapshot {
+            id: self.id.clone(),
+            chart: self.chart.id().clone(),
+            state: match &*self.state.borrow() {
+                ExecutionState::InAction => ExecutionState::Active,
+                state => state.clone(),
+            },
+            paused: self.is_paused(),
+            active: self.active_states(),
+            history: self.history.borrow().clone(),
+            queue: self.queue.borrow().iter().cloned().collect(),
+            internal: self.internal.events.borrow().iter().cloned().collect(),
+            deferred: self.deferred.borrow().clone(),
+            timers: self.timers.scheduled(),
+            sends: self.timers.scheduled_sends(),
+            next_send: self.timers.next_send(),
+            activities,
+            context: self.context.borrow().deep_clone(),
+        }
+    }
+
+    ///
+    /// Add `observer`, to be notified of changes to this instance from now on.
+    ///
+    pub fn add_observer(&self, observer: Arc<dyn ExecutionObserver>) {
+        self.observers.borrow_mut().push(observer)
+    }
+
+    ///
+    /// Add `observer`, to be notified only as the states addressed by `hook` are entered and
+    /// exited, or the transitions it addresses fire. It is an error where `hook` addresses no
+    /// state, or transition, of the machine.
+    ///
+    pub fn add_hook(&self, hook: Hook, observer: Arc<dyn ExecutionObserver>) -> Result<()> {
+        let mut hooks = self.hooks.borrow_mut();
+        match &hook {
+            Hook::State(_) | Hook::StateLabeled(_) => {
+                let states = self.hooked_states(&hook);
+                if states.is_empty() {
+                    return Err(ErrorKind::ModelUnknownElement(format!("{:?}", hook)).into());
+                }
+                for state in states {
+                    hooks
+                        .states
+                        .entry(state)
+                        .or_default()
+                        .push(observer.clone());
+                }
+            }
+            Hook::Transition(_, _) | Hook::TransitionLabeled(_) => {
+                let transitions = self.hooked_transitions(&hook);
+                if transitions.is_empty() {
+                    return Err(ErrorKind::ModelUnknownElement(format!("{:?}", hook)).into());
+                }
+                for transition in transitions {
+                    hooks
+                        .transitions
+                        .entry(transition.source())
+                        .or_default()
+                        .push((transition, observer.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Post `event`, as for `post`, and return what happened as a result; the transitions
+    /// fired, states exited and entered, and behaviors performed, in order, until the instance
+    /// is again waiting for an event.
+    ///
+    pub fn step(&mut self, event: &Event) -> Result<StepResult> {
+        let _ = self.record.replace(Some(Default::default()));
+        let result = self.post(event);
+        let mut record = self.record.take().unwrap_or_default();
+        result.map(|_| {
+            record.completed = self.is_done();
+            record
+        })
+    }
+
+    ///
+    /// Step the instance with each of `events` in turn, as the returned iterator is advanced,
+    /// yielding the outcome of each step, see `step`.
+    ///
+    pub fn drive<I>(&mut self, events: I) -> Drive<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        Drive {
+            instance: self,
+            events: events.into_iter(),
         }
     }
 
-    pub fn active_states(&self) -> Iter<'_, StateID> {
-        self.active.iter()
+    ///
+    /// Dispatch the time events of any timers that have elapsed, each to the state that
+    /// scheduled it, and the events of any delayed sends that are due, in the order they were
+    /// due, returning the number dispatched, and complete any states whose do-activity has
+    /// finished. As for `post`, if a step is in progress the events are queued for dispatch
+    /// once it completes.
+    ///
+    pub fn tick(&self) -> Result<usize> {
+        if self.is_done() {
+            return Err(ErrorKind::InstanceIsDone.into());
+        } else if self.is_new() || self.is_in_error() {
+            return Err(ErrorKind::InstanceIsNotActive.into());
+        } else if self.is_paused() {
+            return Ok(0);
+        }
+        let mut elapsed: Vec<(SystemTime, Occurrence)> = self
+            .timers
+            .elapsed()
+            .into_iter()
+            .map(|timer| {
+                (
+                    timer.due,
+                    Occurrence {
+                        event: timer.event,
+                        target: Some(timer.state),
+                        payload: None,
+                    },
+                )
+            })
+            .chain(self.timers.elapsed_sends().into_iter().map(|send| {
+                (
+                    send.due,
+                    Occurrence {
+                        event: send.event,
+                        target: None,
+                        payload: send.payload,
+                    },
+                )
+            }))
+            .collect();
+        elapsed.sort_by_key(|(due, _)| *due);
+        let count = elapsed.len();
+        let queued = self.enqueue_all(elapsed.into_iter().map(|(_, occurrence)| occurrence));
+        if self.is_in_step() {
+            queued.map(|_| count)
+        } else {
+            let ran = self.run(|_| Ok(()));
+            queued.and(ran).map(|_| count)
+        }
+    }
+
+    ///
+    /// Send `event` to this instance after `delay`, as for the SCXML `<send>` element with a
+    /// `delay`; once due, and the instance is ticked, the event is dispatched as an external
+    /// event. The returned ID may be given to `cancel` until then.
+    ///
+    pub fn send_after(&self, delay: Duration, event: &Event) -> Result<SendId> {
+        self.schedule_send(delay, event, None)
+    }
+
+    ///
+    /// Send `event`, with `payload`, to this instance after `delay`, see `send_after`.
+    ///
+    pub fn send_after_with(
+        &self,
+        delay: Duration,
+        event: &Event,
+        payload: Context,
+    ) -> Result<SendId> {
+        self.schedule_send(delay, event, Some(payload))
+    }
+
+    ///
+    /// Cancel the delayed send `id`, as for the SCXML `<cancel>` element, returning `false`
+    /// where its event had already been sent, or it had already been cancelled.
+    ///
+    pub fn cancel(&self, id: SendId) -> bool {
+        self.timers.cancel_send(id)
+    }
+
+    ///
+    /// The handle of the do-activity of the active state `state`, if it has not yet finished.
+    ///
+    pub fn activity(&self, state: &ID) -> Option<DoActivityHandle> {
+        self.activities.borrow().get(state).cloned()
+    }
+
+    ///
+    /// The current time, by this instance's clock, see `with_clock`.
+    ///
+    pub fn now(&self) -> SystemTime {
+        self.timers.now()
+    }
+
+    ///
+    /// The time at which the next of this instance's timers is due, if any are scheduled.
+    ///
+    pub fn next_timer(&self) -> Option<SystemTime> {
+        self.timers.next_due()
+    }
+
+    ///
+    /// The events posted, but not yet dispatched, in the order they will be dispatched.
+    ///
+    pub fn queued_events(&self) -> Vec<Event> {
+        self.queue
+            .borrow()
+            .iter()
+            .map(|occurrence| occurrence.event.clone())
+            .collect()
+    }
+
+    ///
+    /// Take the events sent by behaviors to other instances, in the order they were sent, so
+    /// that they may be delivered.
+    ///
+    pub fn take_sent_events(&self) -> Vec<SentEvent> {
+        std::mem::take(&mut *self.outbox.borrow_mut())
+    }
+
+    ///
+    /// The events deferred by active states, in the order they were deferred. Once a step changes
+    /// the configuration any that are no longer deferred are dispatched, in this order, before
+    /// any other queued events.
+    ///
+    pub fn deferred_events(&self) -> Vec<Event> {
+        self.deferred
+            .borrow()
+            .iter()
+            .map(|occurrence| occurrence.event.clone())
+            .collect()
+    }
+
+    ///
+    /// The active states, at all levels of nesting, in document order.
+    ///
+    pub fn active_states(&self) -> Vec<ID> {
+        let mut active: Vec<ID> = self.active.borrow().values().cloned().collect();
+        active.sort_by_key(|id| self.index.order[id]);
+        active
+    }
+
+    pub fn is_in_state(&self, state: &ID) -> bool {
+        self.index
+            .vertex_region
+            .get(state)
+            .map(|region| self.active.borrow().get(region) == Some(state))
+            .unwrap_or_default()
+    }
+
+    pub fn is_new(&self) -> bool {
+        self.state.borrow().is_new()
     }
 
+    ///
+    /// Returns `true` once the instance has been started, until it is done or in error.
+    ///
     pub fn is_active(&self) -> bool {
-        self.state.borrow().is_active()
+        self.state.borrow().is_active() || self.is_in_step()
     }
 
     pub fn is_in_error(&self) -> bool {
@@ -123,201 +1027,3799 @@ impl<E: Clone + Eq + Hash, D> StateMachineInstance<E, D> {
         self.state.borrow().is_done()
     }
 
-    // --------------------------------------------------------------------------------------------
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
 
-    fn enter_state(&mut self, state: Rc<State<E, D>>, internal: bool) -> Vec<StateID> {
-        let state_id = state.id();
-        debug!("StateMachine::enter_state({}, .., {}", state_id, internal);
-        if !internal {
-            self.post_internal_event(&state_id, None, &InternalEvent::Entry);
-            self.post_internal_event(&state_id, None, &InternalEvent::Run);
+    ///
+    /// Returns `true` where the queue is full and events posted are handed back until there is
+    /// room, see `OverflowPolicy::Block`.
+    ///
+    pub fn is_blocked(&self) -> bool {
+        match self.queue_capacity {
+            Some((capacity, OverflowPolicy::Block)) => self.waiting() >= capacity,
+            _ => false,
         }
-        // The `None` value is used to determine those transitions that require no event
-        self.handle_transition(&state, &None)
     }
 
-    fn check_done(&mut self) {
-        debug!("StateMachine::check_done");
-        let done = !self.active.is_empty()
-            && self
-                .active
-                .iter()
-                .map(|id| self.chart.get_state(id).unwrap())
-                .all(|st| st.kind() == StateKind::Final);
-        if done {
-            self.post_internal_event(&StateID::invalid(), None, &InternalEvent::Done);
-            let _ = self.state.replace(ExecutionState::Done);
-        } else {
-            let _ = self.state.replace(ExecutionState::Active);
-        }
+    // --------------------------------------------------------------------------------------------
+
+    fn is_in_step(&self) -> bool {
+        self.state.borrow().is_in_action()
     }
 
-    fn post_internal_event(
-        &self,
-        in_state_id: &StateID,
-        transition: Option<&Transition<E, D>>,
-        on_event: &InternalEvent,
-    ) {
-        debug!(
-            "StateMachine::post_internal_event({}, , {:?})",
-            in_state_id, on_event
-        );
-        let previous_state = self.state.replace(ExecutionState::InAction);
-        match on_event {
-            InternalEvent::Init => {
-                self.run_actions(in_state_id, on_event, self.chart.init_actions());
-            }
-            InternalEvent::Done => {
-                self.run_actions(in_state_id, on_event, self.chart.done_actions());
-            }
-            InternalEvent::Entry => {
-                self.run_actions(
-                    in_state_id,
-                    on_event,
-                    self.chart.get_state(in_state_id).unwrap().entry_actions(),
-                );
-            }
-            InternalEvent::Run => {
-                self.run_actions(
-                    in_state_id,
-                    on_event,
-                    self.chart.get_state(in_state_id).unwrap().body_actions(),
-                );
-            }
-            InternalEvent::Exit => {
-                self.run_actions(
-                    in_state_id,
-                    on_event,
-                    self.chart.get_state(in_state_id).unwrap().exit_actions(),
-                );
+    ///
+    /// Queue `occurrence`, applying the overflow policy where the queue is full, and returning
+    /// `false` where the event was dropped rather than queued. Only an event that was `posted`
+    /// may be handed back.
+    ///
+    fn enqueue(&self, occurrence: Occurrence, posted: bool) -> Result<bool> {
+        if let Some((capacity, overflow_policy)) = self.queue_capacity {
+            if self.waiting() >= capacity {
+                match overflow_policy {
+                    OverflowPolicy::Block if posted => {
+                        return Err(ErrorKind::QueueBlocked(occurrence.event).into())
+                    }
+                    OverflowPolicy::Block | OverflowPolicy::Error => {
+                        return Err(ErrorKind::QueueFull(capacity).into())
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.event_dropped(&occurrence.event);
+                        return Ok(false);
+                    }
+                    OverflowPolicy::DropOldest => {
+                        let oldest = self.queue.borrow_mut().pop_front().or_else(|| {
+                            let mut deferred = self.deferred.borrow_mut();
+                            (!deferred.is_empty()).then(|| deferred.remove(0))
+                        });
+                        if let Some(oldest) = oldest {
+                            self.event_dropped(&oldest.event);
+                        }
+                    }
+                }
             }
-            InternalEvent::Transition => {
-                self.run_actions(in_state_id, on_event, transition.unwrap().actions());
+        }
+        self.queue.borrow_mut().push_back(occurrence);
+        Ok(true)
+    }
+
+    ///
+    /// Queue each of `occurred`, events the instance queues itself, in turn, returning the
+    /// first error, see `OverflowPolicy`.
+    ///
+    fn enqueue_all(&self, occurred: impl IntoIterator<Item = Occurrence>) -> Result<()> {
+        let mut result = Ok(());
+        for occurrence in occurred {
+            if let Err(error) = self.enqueue(occurrence, false) {
+                result = result.and(Err(error));
             }
         }
-        let _ = self.state.replace(previous_state);
+        result
     }
 
-    fn run_actions(
-        &self,
-        in_state_id: &StateID,
-        on_event: &InternalEvent,
-        actions: Actions<'_, D>,
-    ) {
-        for action in actions {
-            action(in_state_id, on_event, &mut self.context.borrow_mut());
+    ///
+    /// The number of events waiting to be dispatched, queued or deferred, which the capacity of
+    /// the queue limits.
+    ///
+    fn waiting(&self) -> usize {
+        self.queue.borrow().len() + self.deferred.borrow().len()
+    }
+
+    ///
+    /// Perform `step`, then dispatch queued events until the queue is empty or the instance is
+    /// complete, and set the resulting execution state.
+    ///
+    fn run(&self, step: impl FnOnce(&Self) -> Result<()>) -> Result<()> {
+        let _ = self.state.replace(ExecutionState::InAction);
+        self.microsteps.borrow_mut().clear();
+        let result = step(self)
+            .and_then(|_| self.run_to_completion())
+            .or_else(|error| self.recover(error));
+        let resolved = self.resolve_sent();
+        let result = result.and(resolved);
+        let _ = self.state.replace(match &result {
+            Err(_) => ExecutionState::Error,
+            Ok(_) if self.has_completed() => ExecutionState::Done,
+            Ok(_) => ExecutionState::Active,
+        });
+        if self.is_done() {
+            let finished = self.finish();
+            return result.and(finished);
         }
+        result
     }
 
-    fn handle_transition(
-        &self,
-        from_state: &Rc<State<E, D>>,
-        on_event: &Option<&E>,
-    ) -> Vec<StateID> {
-        debug!(
-            "StateMachine::handle_transition is_some={}",
-            on_event.is_some()
-        );
-        // Find all transitions that handle this event
-        let transitions = from_state
-            .transitions()
-            .filter(|t| t.event() == on_event.map(|e| e.clone()))
-            .collect::<Vec<_>>();
-        trace!(
-            "StateMachine::handle_transition > enabled transitions={}",
-            transitions.len()
-        );
-        if !transitions.is_empty() {
-            if transitions.iter().any(|t| !t.is_internal()) {
-                self.post_internal_event(&from_state.id(), None, &InternalEvent::Exit);
-            }
-            trace!("StateMachine::handle_transition > testing all outbound transitions");
-            transitions
-                .iter()
-                .filter_map(|t| self.fire_state_transitions(&from_state, t, on_event))
-                .collect()
-        } else {
-            Vec::default()
+    ///
+    /// Finish an instance that has just completed, in the order described in the module
+    /// documentation; the instance remains done whether or not this fails.
+    ///
+    fn finish(&self) -> Result<()> {
+        for (_, activity) in self.activities.borrow_mut().drain() {
+            activity.cancel();
+        }
+        for active in self.invocations.borrow_mut().drain(..) {
+            active.handle.cancel();
+        }
+        for region in self.index.regions_of(self.chart.id()) {
+            if self.is_region_finished(region) {
+                self.notify(|observer| observer.on_region_completed(self, region));
+            }
+        }
+        let trigger = Trigger::any();
+        for behavior in &self.done_behaviors {
+            self.record_performed(self.chart.id(), behavior.as_ref());
+            self.catching(
+                || match behavior.label() {
+                    None => "a behavior performed as the instance completed".to_string(),
+                    Some(label) => format!(
+                        "the behavior '{}' performed as the instance completed",
+                        label
+                    ),
+                },
+                || {
+                    behavior.perform_in(
+                        self.chart.id(),
+                        &trigger,
+                        &self.internal,
+                        &self.context.borrow(),
+                    )
+                },
+            );
+        }
+        let performed = self.take_panic().and(self.resolve_sent());
+        let queued = self.queued_events();
+        let deferred = self.deferred_events();
+        self.deferred.borrow_mut().clear();
+        self.queue.borrow_mut().clear();
+        self.internal.events.borrow_mut().clear();
+        for event in queued.iter().chain(deferred.iter()) {
+            self.event_dropped(event);
         }
+        self.timers.clear();
+        self.changes.borrow_mut().clear();
+        self.notify(|observer| observer.on_done(self));
+        let saved = match &self.snapshot_store {
+            None => Ok(()),
+            Some(store) => store.save(&self.snapshot()),
+        };
+        performed.and(saved)
     }
 
-    fn fire_state_transitions(
-        &self,
-        from_state: &Rc<State<E, D>>,
-        transition: &Transition<E, D>,
-        on_event: &Option<&E>,
-    ) -> Option<StateID> {
-        debug!("StateMachine::fire_state_transitions");
-        if on_event.map(|e| e.clone()) == transition.event() {
-            trace!("StateMachine::fire_state_transitions > event matches");
-            if transition
-                .conditions()
-                .all(|c| c(&from_state.id(), &on_event, &self.context.borrow()))
-            {
-                self.post_internal_event(
-                    &from_state.id(),
-                    Some(transition),
-                    &InternalEvent::Transition,
-                );
-                transition.target_state_id()
-            } else {
-                trace!("StateMachine::fire_state_transitions > not all conditions met");
-                None
+    ///
+    /// Apply the instance's error policy to `error`, and where the policy allows continue
+    /// processing queued events. Other than when errors are ignored a further error is returned,
+    /// and so enters the error state.
+    ///
+    fn recover(&self, error: Error) -> Result<()> {
+        self.notify(|observer| observer.on_error(self, &error));
+        let recovered = match &self.error_policy {
+            ErrorPolicy::FailFast => return Err(error),
+            ErrorPolicy::Ignore => Ok(()),
+            ErrorPolicy::EnterState(state) => self.enter_error_state(state),
+            ErrorPolicy::RaiseEvent => {
+                self.internal
+                    .events
+                    .borrow_mut()
+                    .push_front(Event::Signal(SignalEvent::new(ERROR_SIGNAL)));
+                Ok(())
             }
-        } else {
-            trace!("StateMachine::fire_state_transitions > event does not match");
-            None
+        };
+        match recovered.and_then(|_| self.run_to_completion()) {
+            Err(error) if self.error_policy == ErrorPolicy::Ignore => self.recover(error),
+            Err(error) => {
+                self.notify(|observer| observer.on_error(self, &error));
+                Err(error)
+            }
+            Ok(_) => Ok(()),
         }
     }
-}
 
-impl ExecutionState {
-    #[allow(dead_code)]
-    fn is_new(&self) -> bool {
-        match self {
-            ExecutionState::New => true,
-            _ => false,
+    fn enter_error_state(&self, state: &ID) -> Result<()> {
+        let region = self
+            .index
+            .outermost_region(state)
+            .ok_or(ErrorKind::TransitionTargetState)?;
+        let trigger = Trigger::any();
+        self.internal.events.borrow_mut().clear();
+        let machine_regions: Vec<ID> = self.index.regions_of(self.chart.id()).cloned().collect();
+        for machine_region in machine_regions {
+            let active = self.active.borrow().get(&machine_region).cloned();
+            if let Some(active) = active {
+                self.exit_state(&active, &trigger);
+            }
         }
+        let mut completions: VecDeque<ID> = Default::default();
+        self.enter_target(state, &region, &trigger, &mut completions)?;
+        self.complete(completions)
     }
 
-    fn is_active(&self) -> bool {
-        match self {
-            ExecutionState::Active => true,
-            _ => false,
+    fn notify(&self, f: impl Fn(&dyn ExecutionObserver)) {
+        let observers = self.observers.borrow().clone();
+        for observer in observers {
+            f(observer.as_ref())
         }
     }
 
-    #[allow(dead_code)]
-    fn is_in_action(&self) -> bool {
-        match self {
-            ExecutionState::InAction => true,
-            _ => false,
+    ///
+    /// Notify the observers hooked to the state `state`.
+    ///
+    fn notify_hooked(&self, state: &ID, f: impl Fn(&dyn ExecutionObserver)) {
+        let observers = self.hooks.borrow().states.get(state).cloned();
+        for observer in observers.into_iter().flatten() {
+            f(observer.as_ref())
         }
     }
 
-    fn is_in_error(&self) -> bool {
-        match self {
-            ExecutionState::Error => true,
-            _ => false,
+    ///
+    /// The states of the machine addressed by `hook`.
+    ///
+    fn hooked_states(&self, hook: &Hook) -> Vec<ID> {
+        self.index
+            .vertices
+            .values()
+            .filter_map(|vertex| vertex.as_state())
+            .filter(|state| match hook {
+                Hook::State(id) => state.id() == id,
+                Hook::StateLabeled(label) => state.label().as_ref() == Some(label),
+                _ => false,
+            })
+            .map(|state| state.id().clone())
+            .collect()
+    }
+
+    ///
+    /// The transitions of the machine addressed by `hook`.
+    ///
+    fn hooked_transitions(&self, hook: &Hook) -> Vec<Arc<Transition>> {
+        let mut transitions: Vec<Arc<Transition>> = Default::default();
+        self.chart.for_each_region(&mut |region| match hook {
+            Hook::Transition(id, index) if region.id() == id => {
+                transitions.extend(region.transitions().into_iter().nth(*index))
+            }
+            Hook::TransitionLabeled(label) => transitions.extend(
+                region
+                    .transitions()
+                    .into_iter()
+                    .filter(|transition| transition.label().as_ref() == Some(label)),
+            ),
+            _ => (),
+        });
+        transitions
+    }
+
+    fn run_to_completion(&self) -> Result<()> {
+        // whether a step has been taken since completed states were last checked
+        let mut stepped = false;
+        while !self.has_completed() && !self.is_paused() {
+            let finished = self.finished_activities();
+            if !finished.is_empty() {
+                self.dispatching(None, || self.complete(finished))?;
+                stepped = true;
+                continue;
+            }
+            self.check_changes()?;
+            self.check_invocations()?;
+            self.take_panic()?;
+            self.resolve_sent()?;
+            let raised = self.internal.events.borrow_mut().pop_front();
+            let occurrence = match raised {
+                Some(event) => Some(Occurrence {
+                    event,
+                    target: None,
+                    payload: None,
+                }),
+                None => {
+                    if std::mem::take(&mut stepped) && self.check_completions()? {
+                        stepped = true;
+                        continue;
+                    }
+                    self.microsteps.borrow_mut().clear();
+                    self.queue.borrow_mut().pop_front()
+                }
+            };
+            match occurrence {
+                None => break,
+                Some(occurrence) => self.dispatch(&occurrence)?,
+            }
+            stepped = true;
         }
+        Ok(())
     }
 
-    fn is_done(&self) -> bool {
-        match self {
-            ExecutionState::Done => true,
-            _ => false,
+    ///
+    /// Queue the events sent by behaviors to this instance for immediate delivery, and move those
+    /// sent to other instances to the outbox, resolving `SendTarget::Parent`.
+    ///
+    fn resolve_sent(&self) -> Result<()> {
+        let sent = std::mem::take(&mut *self.internal.sent.borrow_mut());
+        let mut to_self: Vec<Occurrence> = Default::default();
+        for (target, event, delivery) in sent {
+            let target = match target {
+                SendTarget::This if delivery == Delivery::Immediate => {
+                    to_self.push(Occurrence {
+                        event,
+                        target: None,
+                        payload: None,
+                    });
+                    continue;
+                }
+                SendTarget::This => self.id.clone(),
+                SendTarget::Instance(id) => id,
+                SendTarget::Parent => match &self.parent {
+                    Some(parent) => parent.clone(),
+                    None => {
+                        self.event_dropped(&event);
+                        continue;
+                    }
+                },
+            };
+            self.outbox.borrow_mut().push(SentEvent {
+                target,
+                event,
+                delivery,
+            });
         }
+        self.enqueue_all(to_self)
     }
-}
 
-// ------------------------------------------------------------------------------------------------
-// Private Types
-// ------------------------------------------------------------------------------------------------
+    ///
+    /// Queue the done, or error, event of each invoked service that has reported its outcome.
+    ///
+    fn check_invocations(&self) -> Result<()> {
+        let mut ended: Vec<Occurrence> = Default::default();
+        self.invocations.borrow_mut().retain(|active| {
+            let (event, payload) = match active.handle.outcome() {
+                None => return true,
+                Some(InvokeOutcome::Done) => (active.invoke.done_event(), None),
+                Some(InvokeOutcome::Failed(_)) => (active.invoke.error_event(), None),
+                Some(InvokeOutcome::Raised(event, payload)) => (event, payload),
+            };
+            ended.push(Occurrence {
+                event,
+                target: None,
+                payload,
+            });
+            false
+        });
+        self.enqueue_all(ended)
+    }
+
+    ///
+    /// Re-evaluate the change expression of each change event, queueing those that have become
+    /// `true` for the state that registered them.
+    ///
+    fn check_changes(&self) -> Result<()> {
+        let mut occurred: Vec<Occurrence> = Default::default();
+        {
+            let context = self.context.borrow();
+            for watch in self.changes.borrow_mut().iter_mut() {
+                let value = self.catching(
+                    || {
+                        format!(
+                            "the change expression of a transition from '{}'",
+                            watch.state
+                        )
+                    },
+                    || evaluate_change(watch, &context),
+                );
+                if value && !watch.value {
+                    occurred.push(Occurrence {
+                        event: watch.event.clone(),
+                        target: Some(watch.state.clone()),
+                        payload: None,
+                    });
+                }
+                watch.value = value;
+            }
+        }
+        self.enqueue_all(occurred)
+    }
+
+    ///
+    /// Remove the do-activities that have finished, returning those of their states that are
+    /// now complete.
+    ///
+    fn finished_activities(&self) -> VecDeque<ID> {
+        let mut finished: Vec<ID> = Default::default();
+        self.activities.borrow_mut().retain(|state, activity| {
+            if activity.is_finished() {
+                finished.push(state.clone());
+                false
+            } else {
+                true
+            }
+        });
+        finished.sort_by_key(|state| self.index.order[state]);
+        finished
+            .into_iter()
+            .filter(|state| {
+                self.is_in_state(state)
+                    && self
+                        .index
+                        .regions_of(state)
+                        .all(|region| self.is_region_finished(region))
+            })
+            .collect()
+    }
+
+    fn start(&self) -> Result<()> {
+        self.dispatching(None, || {
+            let trigger = Trigger::any();
+            let mut completions: VecDeque<ID> = Default::default();
+            for region in self.index.regions_of(self.chart.id()) {
+                self.enter_region(region, &trigger, &mut completions)?;
+            }
+            self.complete(completions)
+        })
+    }
+
+    ///
+    /// Notify observers either side of `f`, which processes `event`, or the start of the
+    /// instance or the completion of finished do-activities where there is no event.
+    ///
+    fn dispatching(&self, event: Option<&Event>, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        let (started, clock) = (Instant::now(), self.timers.now());
+        self.notify(|observer| observer.on_dispatch_started(self, event));
+        let result = f().and_then(|_| self.take_panic());
+        self.throughput
+            .borrow_mut()
+            .stepped(clock, started.elapsed());
+        result?;
+        self.notify(|observer| observer.on_dispatch_finished(self, event));
+        Ok(())
+    }
+
+    ///
+    /// Perform `f`, which evaluates or performs user code, catching any panic; the first panic
+    /// is remembered, with a description of where it occurred from `within`, and the default
+    /// value returned in place of the result of `f`.
+    ///
+    fn catching<T: Default>(&self, within: impl FnOnce() -> String, f: impl FnOnce() -> T) -> T {
+        match catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result,
+            Err(_) => {
+                let mut panicked = self.panicked.borrow_mut();
+                if panicked.is_none() {
+                    let _ = panicked.replace(within());
+                }
+                T::default()
+            }
+        }
+    }
+
+    fn take_panic(&self) -> Result<()> {
+        match self.panicked.take() {
+            None => Ok(()),
+            Some(within) => Err(ErrorKind::ActionPanicked(within).into()),
+        }
+    }
+
+    fn dispatch(&self, occurrence: &Occurrence) -> Result<()> {
+        self.stats.borrow_mut().events_processed += 1;
+        self.dispatching(Some(&occurrence.event), || self.dispatch_event(occurrence))
+    }
+
+    fn dispatch_event(&self, occurrence: &Occurrence) -> Result<()> {
+        let event = &occurrence.event;
+        let trigger = match &occurrence.payload {
+            None => Trigger::with_event(event.clone()),
+            Some(payload) => Trigger::with_event(event.clone()).with_payload(payload.clone()),
+        };
+        let mut completions: VecDeque<ID> = Default::default();
+        let selected = match &occurrence.target {
+            None => self.select_transitions(event, &trigger)?,
+            Some(target) => self.select_transitions_from(target, event, &trigger)?,
+        };
+        if selected.is_empty() {
+            if occurrence.target.is_none() && self.is_deferred(occurrence) {
+                self.deferred.borrow_mut().push(occurrence.clone());
+                self.stats.borrow_mut().events_deferred += 1;
+            } else {
+                self.event_dropped(event);
+            }
+            return Ok(());
+        }
+        for enabled in selected {
+            if self.is_in_state(&enabled.transition.source()) {
+                self.fire(&enabled, &trigger, &mut completions)?;
+            }
+        }
+        self.complete(completions)?;
+        self.recall_deferred();
+        Ok(())
+    }
+
+    fn is_deferred(&self, occurrence: &Occurrence) -> bool {
+        let payload = occurrence.payload.clone().unwrap_or_default();
+        self.active.borrow().values().any(|id| {
+            self.index.vertices[id]
+                .as_state()
+                .map(|state| {
+                    state.deferrable_triggers().any(|trigger| {
+                        trigger.matches(&occurrence.event) && trigger.matches_payload(&payload)
+                    })
+                })
+                .unwrap_or_default()
+        })
+    }
+
+    ///
+    /// Return the deferred events that no active state now defers to the front of the queue.
+    ///
+    fn recall_deferred(&self) {
+        let deferred = std::mem::take(&mut *self.deferred.borrow_mut());
+        let (still_deferred, recalled): (Vec<Occurrence>, Vec<Occurrence>) = deferred
+            .into_iter()
+            .partition(|occurrence| self.is_deferred(occurrence));
+        *self.deferred.borrow_mut() = still_deferred;
+        let mut queue = self.queue.borrow_mut();
+        for occurrence in recalled.into_iter().rev() {
+            queue.push_front(occurrence);
+        }
+    }
+
+    ///
+    /// Process completion events, in the order the states completed, firing the first enabled
+    /// completion transition from each state that is still active.
+    ///
+    fn complete(&self, mut completions: VecDeque<ID>) -> Result<()> {
+        let trigger = Trigger::any();
+        while let Some(state) = completions.pop_front() {
+            if self.has_completed() {
+                break;
+            }
+            if !self.is_in_state(&state) || self.activities.borrow().contains_key(&state) {
+                continue;
+            }
+            let enabled = self.completion_transitions(&state, &trigger);
+            if let Some(enabled) = self.choose(&state, enabled)? {
+                self.fire(&enabled, &trigger, &mut completions)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// The trigger-less transitions from `state` that are enabled.
+    ///
+    fn completion_transitions(&self, state: &ID, trigger: &Trigger) -> Vec<Enabled> {
+        self.index
+            .transitions_from(state)
+            .filter(|transition| !transition.has_triggers())
+            .filter_map(|transition| self.enabled(transition, state, trigger))
+            .collect()
+    }
+
+    ///
+    /// Re-evaluate the guarded, trigger-less, transitions of active states that have completed,
+    /// as their guards may have come to hold since the states completed, firing the first
+    /// enabled in document order. This is done at the end of each step, and so also once the
+    /// context is changed with `update_context`. Returns `true` if a transition fired.
+    ///
+    fn check_completions(&self) -> Result<bool> {
+        let trigger = Trigger::any();
+        for state in self.active_states() {
+            if !self.is_in_state(&state)
+                || !self.is_complete(&state)
+                || !self
+                    .index
+                    .transitions_from(&state)
+                    .any(|transition| !transition.has_triggers() && transition.guard().is_some())
+            {
+                continue;
+            }
+            let enabled = self.completion_transitions(&state, &trigger);
+            if !enabled.is_empty() {
+                self.dispatching(None, || {
+                    let mut completions: VecDeque<ID> = Default::default();
+                    if let Some(enabled) = self.choose(&state, enabled)? {
+                        self.fire(&enabled, &trigger, &mut completions)?;
+                    }
+                    self.complete(completions)
+                })?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    ///
+    /// Whether the active state `state` has completed; its do-activity, if any, has finished,
+    /// and each of its regions, if any, is in a final state.
+    ///
+    fn is_complete(&self, state: &ID) -> bool {
+        let active = self.active.borrow();
+        !self.activities.borrow().contains_key(state)
+            && self.index.regions_of(state).all(|region| {
+                active
+                    .get(region)
+                    .and_then(|active| self.index.vertices[active].as_state())
+                    .is_some_and(|state| state.is_final())
+            })
+    }
+
+    ///
+    /// Select the transitions that `event` enables. The event is offered to each of the
+    /// machine's regions in document order.
+    ///
+    fn select_transitions(&self, event: &Event, trigger: &Trigger) -> Result<Vec<Enabled>> {
+        let mut selected: Vec<Enabled> = Default::default();
+        for region in self.index.regions_of(self.chart.id()) {
+            let _ = self.select_in_region(region, event, trigger, &mut selected)?;
+        }
+        Ok(selected)
+    }
+
+    ///
+    /// Offer `event` to the active state of `region`, by first offering it to each of the
+    /// state's own regions in document order; the state itself is only offered the event where
+    /// no transition within it was selected. Returns `true` if any transition was selected.
+    ///
+    fn select_in_region(
+        &self,
+        region: &ID,
+        event: &Event,
+        trigger: &Trigger,
+        selected: &mut Vec<Enabled>,
+    ) -> Result<bool> {
+        let state = match self.active.borrow().get(region) {
+            None => return Ok(false),
+            Some(state) => state.clone(),
+        };
+        let mut found = false;
+        for region in self.index.regions_of(&state) {
+            found |= self.select_in_region(region, event, trigger, selected)?;
+        }
+        if !found {
+            if let Some(enabled) = self.select_from(&state, event, trigger)? {
+                selected.push(enabled);
+                found = true;
+            }
+        }
+        Ok(found)
+    }
+
+    fn select_transitions_from(
+        &self,
+        state: &ID,
+        event: &Event,
+        trigger: &Trigger,
+    ) -> Result<Vec<Enabled>> {
+        if self.is_in_state(state) {
+            Ok(self
+                .select_from(state, event, trigger)?
+                .into_iter()
+                .collect())
+        } else {
+            Ok(Default::default())
+        }
+    }
+
+    fn select_from(&self, state: &ID, event: &Event, trigger: &Trigger) -> Result<Option<Enabled>> {
+        let payload = trigger.payload().cloned().unwrap_or_default();
+        let enabled: Vec<Enabled> =
+            self.index
+                .transitions_triggered_by(state, event, |transition| {
+                    if transition.is_triggered_with(event, &payload) {
+                        self.enabled(transition, state, trigger)
+                    } else {
+                        None
+                    }
+                });
+        self.choose(state, enabled)
+    }
+
+    ///
+    /// Choose between the transitions from `state` that are `enabled` by the same event; where
+    /// there is more than one the tie-breaker, if any, chooses, or if the instance is
+    /// deterministic the first, otherwise this is an error.
+    ///
+    fn choose(&self, state: &ID, mut enabled: Vec<Enabled>) -> Result<Option<Enabled>> {
+        match (enabled.len(), &self.tie_breaker) {
+            (0, _) => Ok(None),
+            (1, _) => Ok(enabled.pop()),
+            (length, Some(tie_breaker)) => {
+                let transitions: Vec<Arc<Transition>> = enabled
+                    .iter()
+                    .map(|enabled| enabled.transition.clone())
+                    .collect();
+                match tie_breaker(state, &transitions) {
+                    index if index < length => Ok(Some(enabled.swap_remove(index))),
+                    _ => Err(ErrorKind::MoreThanOneTransition.into()),
+                }
+            }
+            (_, None) if self.deterministic => Ok(enabled.into_iter().next()),
+            (_, None) => Err(ErrorKind::MoreThanOneTransition.into()),
+        }
+    }
+
+    fn fire(
+        &self,
+        enabled: &Enabled,
+        trigger: &Trigger,
+        completions: &mut VecDeque<ID>,
+    ) -> Result<()> {
+        let (transition, branches) = (enabled.transition.as_ref(), &enabled.branches);
+        self.transition_fired(transition);
+        self.count_microstep(transition)?;
+        let source = transition.source();
+        // an internal transition must have the same source and target
+        if transition.is_internal() && source == transition.target() {
+            self.perform(transition.effect(), &source, trigger);
+            return Ok(());
+        }
+        let target = branches
+            .last()
+            .map(|branch| branch.target())
+            .unwrap_or_else(|| transition.target());
+        let local = transition.is_local() && source != target;
+        let (region, exiting, reenter) = if local && self.index.is_within(&target, &source) {
+            // a local transition to a vertex within the source does not exit the source
+            let region = self
+                .index
+                .region_within(&target, &source)
+                .ok_or(ErrorKind::TransitionTargetState)?;
+            let exiting = self.active.borrow().get(&region).cloned();
+            (region, exiting, false)
+        } else if local && self.index.is_within(&source, &target) {
+            // a local transition from within the target does not exit the target
+            let region = self
+                .index
+                .region_within(&source, &target)
+                .ok_or(ErrorKind::TransitionTargetState)?;
+            let exiting = self.active.borrow().get(&region).cloned();
+            (region, exiting, true)
+        } else {
+            // otherwise exit the source, or the state containing it, within the common region
+            let common = self.index.common_region(&source, &target);
+            let exiting = common
+                .clone()
+                .or_else(|| self.index.outermost_region(&source))
+                .map(|region| self.index.ancestor_in(&source, &region));
+            match common.or_else(|| self.index.outermost_region(&target)) {
+                None => return Err(ErrorKind::TransitionTargetState.into()),
+                Some(region) => (region, exiting, false),
+            }
+        };
+        if let Some(exiting) = exiting {
+            if self.is_in_state(&exiting) {
+                self.exit_state(&exiting, trigger);
+            }
+        }
+        self.perform(transition.effect(), &source, trigger);
+        for branch in branches {
+            self.transition_fired(branch);
+            self.perform(branch.effect(), &branch.source(), trigger);
+        }
+        if reenter {
+            self.enter_region(&region, trigger, completions)
+        } else {
+            self.enter_target(&target, &region, trigger, completions)
+        }
+    }
+
+    ///
+    /// The branches taken through the chain of junctions starting at `target`, evaluated before
+    /// the transition to `target` fires; this is empty where `target` is not a junction, and
+    /// `None` where some junction in the chain has no enabled branch.
+    ///
+    fn junction_branches(&self, target: &ID, trigger: &Trigger) -> Option<Vec<Arc<Transition>>> {
+        let mut branches: Vec<Arc<Transition>> = Default::default();
+        let mut target = target.clone();
+        while self
+            .index
+            .vertices
+            .get(&target)
+            .and_then(|vertex| vertex.as_pseudo_state())
+            .is_some_and(PseudoState::is_junction)
+        {
+            let branch = self.select_branch(&target, trigger)?;
+            target = branch.target();
+            branches.push(branch);
+        }
+        Some(branches)
+    }
+
+    ///
+    /// The branch from the choice or junction `id` to take; the first, in document order, whose
+    /// guard holds, otherwise the `else` branch if there is one.
+    ///
+    fn select_branch(&self, id: &ID, trigger: &Trigger) -> Option<Arc<Transition>> {
+        let mut otherwise: Option<&Arc<Transition>> = None;
+        for transition in self.index.transitions_from(id) {
+            match transition.guard() {
+                Some(guard) if is_else(guard.as_ref()) => {
+                    let _ = otherwise.get_or_insert(transition);
+                }
+                _ => {
+                    if self.guard_holds(transition, id, trigger) {
+                        return Some(transition.clone());
+                    }
+                }
+            }
+        }
+        otherwise.cloned()
+    }
+
+    ///
+    /// Enter `target`, and any of the states containing it, below the region `container`, that
+    /// are not already active. The other regions of such states are entered by default.
+    ///
+    fn enter_target(
+        &self,
+        target: &ID,
+        container: &ID,
+        trigger: &Trigger,
+        completions: &mut VecDeque<ID>,
+    ) -> Result<()> {
+        let mut path: Vec<(ID, ID)> = Default::default();
+        let mut vertex = target.clone();
+        while let Some(region) = self.index.vertex_region.get(&vertex) {
+            if region == container {
+                break;
+            }
+            match &self.index.region_owner[region] {
+                None => break,
+                Some(state) => {
+                    path.push((state.clone(), region.clone()));
+                    vertex = state.clone();
+                }
+            }
+        }
+        for (ancestor, on_path) in path.into_iter().rev() {
+            if !self.is_in_state(&ancestor) {
+                self.activate(&ancestor, trigger);
+                for region in self.index.regions_of(&ancestor) {
+                    if region != &on_path {
+                        self.enter_region(region, trigger, completions)?;
+                    }
+                }
+            }
+        }
+        self.enter_vertex(target, trigger, completions)
+    }
+
+    fn enter_region(
+        &self,
+        region: &ID,
+        trigger: &Trigger,
+        completions: &mut VecDeque<ID>,
+    ) -> Result<()> {
+        match self.index.initials.get(region) {
+            None => Err(ErrorKind::StateInitialState.into()),
+            Some(initial) => self.enter_vertex(initial, trigger, completions),
+        }
+    }
+
+    fn enter_vertex(
+        &self,
+        id: &ID,
+        trigger: &Trigger,
+        completions: &mut VecDeque<ID>,
+    ) -> Result<()> {
+        let vertex = match self.index.vertices.get(id) {
+            None => return Err(ErrorKind::TransitionTargetState.into()),
+            Some(vertex) => vertex.clone(),
+        };
+        match vertex.as_ref() {
+            Vertex::State(state) => {
+                self.activate(id, trigger);
+                if state.is_final() {
+                    self.region_finished(&self.index.vertex_region[id], completions);
+                } else if state.has_regions() {
+                    for region in self.index.regions_of(id) {
+                        self.enter_region(region, trigger, completions)?;
+                    }
+                } else {
+                    completions.push_back(id.clone());
+                }
+                Ok(())
+            }
+            Vertex::PseudoState(pseudo_state) => match pseudo_state.kind() {
+                PseudoStateKind::Initial => {
+                    let transition = match self.index.transitions_from(id).next() {
+                        None => return Err(ErrorKind::StateInitialState.into()),
+                        Some(transition) => transition.clone(),
+                    };
+                    self.follow(&transition, id, trigger, completions)
+                }
+                kind @ PseudoStateKind::ShallowHistory | kind @ PseudoStateKind::DeepHistory => {
+                    let region = &self.index.vertex_region[id];
+                    let remembered = self.history.borrow().get(region).cloned();
+                    match remembered {
+                        Some(remembered) if !remembered.is_empty() => self.restore_region(
+                            region,
+                            &remembered,
+                            kind == PseudoStateKind::DeepHistory,
+                            trigger,
+                            completions,
+                        ),
+                        _ => match self.index.transitions_from(id).next().cloned() {
+                            None => self.enter_region(region, trigger, completions),
+                            Some(transition) => self.follow(&transition, id, trigger, completions),
+                        },
+                    }
+                }
+                // junctions are normally resolved before the transition reaching them fires
+                PseudoStateKind::Choice | PseudoStateKind::Junction => {
+                    let branch = match self.select_branch(id, trigger) {
+                        None => return Err(ErrorKind::ChoiceNoBranch.into()),
+                        Some(branch) => branch,
+                    };
+                    self.follow(&branch, id, trigger, completions)
+                }
+                // the owning state has been entered, skipping its default entry
+                PseudoStateKind::EntryPoint => {
+                    let transitions: Vec<Arc<Transition>> =
+                        self.index.transitions_from(id).cloned().collect();
+                    if transitions.is_empty() {
+                        return Err(ErrorKind::TransitionTargetState.into());
+                    }
+                    for transition in transitions {
+                        self.follow(&transition, id, trigger, completions)?;
+                    }
+                    Ok(())
+                }
+                PseudoStateKind::ExitPoint => {
+                    if let Some(owner) = self.index.parent(id).cloned() {
+                        if self.is_in_state(&owner) {
+                            self.exit_state(&owner, trigger);
+                        }
+                    }
+                    let transition =
+                        self.index.transitions_from(id).next().cloned().or_else(|| {
+                            self.index
+                                .exit_references
+                                .get(id)
+                                .and_then(|reference| self.index.transitions_from(reference).next())
+                                .cloned()
+                        });
+                    match transition {
+                        None => Err(ErrorKind::TransitionTargetState.into()),
+                        Some(transition) => self.follow(&transition, id, trigger, completions),
+                    }
+                }
+                PseudoStateKind::Terminate => {
+                    self.active.borrow_mut().clear();
+                    Ok(())
+                }
+                kind => {
+                    Err(ErrorKind::ExecutionUnsupported(format!("{:?} pseudostates", kind)).into())
+                }
+            },
+            Vertex::ConnectionPointReference(reference) => {
+                let region = &self.index.vertex_region[id];
+                if reference.entry().next().is_none() {
+                    return match reference.state() {
+                        None => Err(ErrorKind::TransitionTargetState.into()),
+                        Some(state) => self.enter_target(state, region, trigger, completions),
+                    };
+                }
+                for entry in reference.entry() {
+                    if !self.index.vertices.contains_key(entry) {
+                        return Err(ErrorKind::ExecutionUnsupported(
+                            "entry points of sub-machines".to_string(),
+                        )
+                        .into());
+                    }
+                    self.enter_target(entry, region, trigger, completions)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Follow `transition` from the pseudostate `from`, performing its effect and entering its
+    /// target.
+    ///
+    fn follow(
+        &self,
+        transition: &Transition,
+        from: &ID,
+        trigger: &Trigger,
+        completions: &mut VecDeque<ID>,
+    ) -> Result<()> {
+        self.transition_fired(transition);
+        self.perform(transition.effect(), from, trigger);
+        self.enter_target(
+            &transition.target(),
+            transition.container(),
+            trigger,
+            completions,
+        )
+    }
+
+    ///
+    /// Re-enter `region` from its history, `remembered`; the remembered state directly within
+    /// the region is entered and, for `deep` history, so are the states remembered within it,
+    /// otherwise it is entered by default.
+    ///
+    fn restore_region(
+        &self,
+        region: &ID,
+        remembered: &[ID],
+        deep: bool,
+        trigger: &Trigger,
+        completions: &mut VecDeque<ID>,
+    ) -> Result<()> {
+        let state = remembered
+            .iter()
+            .find(|id| self.index.vertex_region.get(*id) == Some(region));
+        match state {
+            None => self.enter_region(region, trigger, completions),
+            Some(state) if deep && self.index.regions_of(state).next().is_some() => {
+                self.activate(state, trigger);
+                for region in self.index.regions_of(state) {
+                    self.restore_region(region, remembered, deep, trigger, completions)?;
+                }
+                Ok(())
+            }
+            Some(state) => self.enter_vertex(state, trigger, completions),
+        }
+    }
+
+    fn perform(&self, behavior: &Option<Box<dyn Behavior>>, in_state: &ID, trigger: &Trigger) {
+        if let Some(behavior) = behavior {
+            self.record_performed(in_state, behavior.as_ref());
+            self.catching(
+                || match behavior.label() {
+                    None => format!("a behavior performed in '{}'", in_state),
+                    Some(label) => format!("the behavior '{}' performed in '{}'", label, in_state),
+                },
+                || behavior.perform_in(in_state, trigger, &self.internal, &self.context.borrow()),
+            );
+        }
+    }
+
+    fn record(&self, f: impl FnOnce(&mut StepResult)) {
+        if let Some(record) = self.record.borrow_mut().as_mut() {
+            f(record)
+        }
+    }
+
+    fn event_dropped(&self, event: &Event) {
+        self.stats.borrow_mut().events_dropped += 1;
+        self.notify(|observer| observer.on_event_dropped(self, event));
+    }
+
+    fn transition_fired(&self, transition: &Transition) {
+        self.stats.borrow_mut().transitions_fired += 1;
+        self.notify(|observer| observer.on_transition_fired(self, transition));
+        let hooked: Vec<Arc<dyn ExecutionObserver>> =
+            match self.hooks.borrow().transitions.get(&transition.source()) {
+                None => Default::default(),
+                Some(hooks) => hooks
+                    .iter()
+                    .filter(|(hooked, _)| std::ptr::eq(hooked.as_ref(), transition))
+                    .map(|(_, observer)| observer.clone())
+                    .collect(),
+            };
+        for observer in hooked {
+            observer.on_transition_fired(self, transition);
+        }
+        self.record(|record| record.fired.push(transition.into()))
+    }
+
+    ///
+    /// Count `transition` against the microstep limit, if any, failing where it is exceeded
+    /// with the transitions fired since `transition` last fired.
+    ///
+    fn count_microstep(&self, transition: &Transition) -> Result<()> {
+        if let Some(limit) = self.microstep_limit {
+            let mut microsteps = self.microsteps.borrow_mut();
+            let fired = (transition.source(), transition.target());
+            let previous = microsteps.iter().rposition(|microstep| microstep == &fired);
+            microsteps.push(fired);
+            if microsteps.len() > limit {
+                let cycle = microsteps.split_off(previous.map(|i| i + 1).unwrap_or_default());
+                microsteps.clear();
+                return Err(ErrorKind::MicrostepLimit(limit, cycle).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn record_performed(&self, in_state: &ID, behavior: &dyn Behavior) {
+        self.record(|record| {
+            record.performed.push(PerformedBehavior {
+                in_state: in_state.clone(),
+                label: behavior.label().clone(),
+            })
+        })
+    }
+
+    ///
+    /// A transition is enabled where its guard holds and, for a transition to a junction, the
+    /// junction has an enabled branch; the branches are kept to be taken as the transition
+    /// fires, so that each guard is evaluated once in a step.
+    ///
+    fn enabled(
+        &self,
+        transition: &Arc<Transition>,
+        in_state: &ID,
+        trigger: &Trigger,
+    ) -> Option<Enabled> {
+        if !self.guard_holds(transition, in_state, trigger) {
+            return None;
+        }
+        self.junction_branches(&transition.target(), trigger)
+            .map(|branches| Enabled {
+                transition: transition.clone(),
+                branches,
+            })
+    }
+
+    fn guard_holds(&self, transition: &Transition, in_state: &ID, trigger: &Trigger) -> bool {
+        match transition.guard() {
+            None => true,
+            Some(guard) => {
+                let outcome = self.catching(
+                    || {
+                        format!(
+                            "the guard of the transition from '{}' to '{}'",
+                            transition.source(),
+                            transition.target()
+                        )
+                    },
+                    || guard.explain_in(in_state, trigger, &self.context.borrow()),
+                );
+                let result = outcome.result;
+                self.record(|record| {
+                    record.guards.push(EvaluatedGuard {
+                        source: transition.source(),
+                        target: transition.target(),
+                        label: guard.label().clone(),
+                        outcome,
+                    })
+                });
+                result
+            }
+        }
+    }
+
+    ///
+    /// Make the state `id` active, schedule timers for the time events, and watch the change
+    /// events, that trigger transitions from it, then perform its entry behavior and
+    /// do-activity.
+    ///
+    fn activate(&self, id: &ID, trigger: &Trigger) {
+        let region = self.index.vertex_region[id].clone();
+        let previous = self.active.borrow().get(&region).cloned();
+        if let Some(previous) = previous {
+            if &previous == id {
+                return;
+            }
+            // a region may only have one active state
+            self.exit_state(&previous, trigger);
+        }
+        let _ = self.active.borrow_mut().insert(region, id.clone());
+        self.record(|record| record.entered.push(id.clone()));
+        self.stats.borrow_mut().entered(id, self.timers.now());
+        self.notify(|observer| observer.on_state_entered(self, id));
+        self.notify_hooked(id, |observer| observer.on_state_entered(self, id));
+        for transition in self.index.transitions_from(id) {
+            for event in transition.triggers().map(Trigger::event) {
+                if event.is_time() {
+                    self.timers.schedule(id, event);
+                }
+            }
+        }
+        self.watch_changes(id);
+        if let Some(state) = self.index.vertices[id].as_state() {
+            self.perform(state.entry(), id, trigger);
+        }
+        self.start_activity(id, trigger);
+        self.start_invocations(id);
+    }
+
+    ///
+    /// Evaluate the change expression of each change event that triggers a transition from the
+    /// active state `id`, to be re-evaluated until the state is exited.
+    ///
+    fn watch_changes(&self, id: &ID) {
+        for transition in self.index.transitions_from(id) {
+            for event in transition.triggers().map(Trigger::event) {
+                if event.is_change() {
+                    let mut watch = ChangeWatch {
+                        state: id.clone(),
+                        event: event.clone(),
+                        value: false,
+                    };
+                    watch.value = self.catching(
+                        || format!("the change expression of a transition from '{}'", id),
+                        || evaluate_change(&watch, &self.context.borrow()),
+                    );
+                    self.changes.borrow_mut().push(watch);
+                }
+            }
+        }
+    }
+
+    fn start_activity(&self, id: &ID, trigger: &Trigger) {
+        if let Some(activity) = self.index.vertices[id]
+            .as_state()
+            .and_then(|state| state.do_activity().as_ref())
+        {
+            self.record_performed(id, activity.as_ref());
+            let handle = DoActivityHandle::default();
+            let _ = self
+                .activities
+                .borrow_mut()
+                .insert(id.clone(), handle.clone());
+            self.catching(
+                || format!("the do-activity of '{}'", id),
+                || activity.start(id, trigger, &self.internal, handle.clone()),
+            );
+            if handle.is_finished() {
+                let _ = self.activities.borrow_mut().remove(id);
+            }
+        }
+    }
+
+    ///
+    /// Start each of the services invoked by the state `id`, with the handler for its kind, or
+    /// where there is none queue its error event.
+    ///
+    fn start_invocations(&self, id: &ID) {
+        let state = match self.index.vertices[id].as_state() {
+            None => return,
+            Some(state) => state,
+        };
+        for invoke in state.invokes() {
+            let handle = InvokeHandle::default();
+            match self.invoke_handlers.get(invoke.kind()) {
+                None => handle.fail("no handler for the kind of service"),
+                Some(handler) => {
+                    let invocation = Invocation {
+                        instance: self.id.clone(),
+                        state: id.clone(),
+                        invoke: invoke.id().clone(),
+                        kind: invoke.kind().clone(),
+                        source: invoke.source().clone(),
+                        params: {
+                            let context = self.context.borrow();
+                            invoke
+                                .params()
+                                .filter_map(|(name, path)| {
+                                    context.get(path.clone()).map(|value| (name.clone(), value))
+                                })
+                                .collect()
+                        },
+                        done_event: invoke.done_event(),
+                        error_event: invoke.error_event(),
+                    };
+                    self.catching(
+                        || format!("the handler of invoke '{}' in '{}'", invoke.id(), id),
+                        || handler.start(&invocation, handle.clone()),
+                    );
+                }
+            }
+            self.invocations.borrow_mut().push(ActiveInvoke {
+                state: id.clone(),
+                invoke: invoke.clone(),
+                handle,
+            });
+        }
+    }
+
+    ///
+    /// Exit the state `id`, first exiting any active states it contains, innermost first, and
+    /// then performing its exit behavior. The state, and those active within it, are remembered
+    /// as the history of its region.
+    ///
+    fn exit_state(&self, id: &ID, trigger: &Trigger) {
+        if let Some(region) = self.index.vertex_region.get(id) {
+            let mut remembered: Vec<ID> = self
+                .active
+                .borrow()
+                .values()
+                .filter(|active| self.index.is_within(active, id))
+                .cloned()
+                .collect();
+            remembered.sort_by_key(|state| self.index.order[state]);
+            let _ = self.history.borrow_mut().insert(region.clone(), remembered);
+        }
+        let children: Vec<ID> = {
+            let active = self.active.borrow();
+            self.index
+                .regions_of(id)
+                .filter_map(|region| active.get(region).cloned())
+                .collect()
+        };
+        for child in children {
+            self.exit_state(&child, trigger);
+        }
+        if let Some(state) = self.index.vertices[id].as_state() {
+            self.perform(state.exit(), id, trigger);
+        }
+        if let Some(region) = self.index.vertex_region.get(id) {
+            let _ = self.active.borrow_mut().remove(region);
+        }
+        self.record(|record| record.exited.push(id.clone()));
+        self.stats.borrow_mut().exited(id, self.timers.now());
+        self.notify(|observer| observer.on_state_exited(self, id));
+        self.notify_hooked(id, |observer| observer.on_state_exited(self, id));
+        self.timers.cancel(id);
+        if let Some(activity) = self.activities.borrow_mut().remove(id) {
+            activity.cancel();
+        }
+        self.invocations.borrow_mut().retain(|active| {
+            if &active.state == id {
+                active.handle.cancel();
+            }
+            &active.state != id
+        });
+        self.changes.borrow_mut().retain(|watch| &watch.state != id);
+        self.queue
+            .borrow_mut()
+            .retain(|occurrence| occurrence.target.as_ref() != Some(id));
+    }
+
+    ///
+    /// A final state has been entered in `region`; where every region of the owning state is now
+    /// finished the owning state has completed.
+    ///
+    fn region_finished(&self, region: &ID, completions: &mut VecDeque<ID>) {
+        if let Some(state) = &self.index.region_owner[region] {
+            if self
+                .index
+                .regions_of(state)
+                .all(|region| self.is_region_finished(region))
+            {
+                completions.push_back(state.clone());
+            }
+        }
+    }
+
+    fn is_region_finished(&self, region: &ID) -> bool {
+        self.active
+            .borrow()
+            .get(region)
+            .and_then(|id| self.index.vertices[id].as_state())
+            .map(|state| state.is_final())
+            .unwrap_or_default()
+    }
+
+    ///
+    /// The instance has completed once every region of the machine is finished, or once a
+    /// terminate pseudostate has been reached.
+    ///
+    fn has_completed(&self) -> bool {
+        self.active.borrow().is_empty()
+            || self
+                .index
+                .regions_of(self.chart.id())
+                .all(|region| self.is_region_finished(region))
+    }
+}
 
 // ------------------------------------------------------------------------------------------------
-// Private Functions
+// Implementations - ExecutionState
+// ------------------------------------------------------------------------------------------------
+
+impl ExecutionState {
+    fn is_new(&self) -> bool {
+        matches!(self, ExecutionState::New)
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(self, ExecutionState::Active)
+    }
+
+    fn is_in_action(&self) -> bool {
+        matches!(self, ExecutionState::InAction)
+    }
+
+    fn is_in_error(&self) -> bool {
+        matches!(self, ExecutionState::Error)
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self, ExecutionState::Done)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - SeededIds
+// ------------------------------------------------------------------------------------------------
+
+impl SeededIds {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            next: Default::default(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    ///
+    /// The next ID in the sequence.
+    ///
+    pub fn next_id(&self) -> ID {
+        let n = self.next.fetch_add(1, Ordering::SeqCst);
+        ID::from_str(&format!("execution::{}-{}", self.seed, n)).unwrap()
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
+// Implementations - Drive
+// ------------------------------------------------------------------------------------------------
+
+impl<I> Iterator for Drive<'_, I>
+where
+    I: Iterator<Item = Event>,
+{
+    type Item = Result<StepResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.events.next()?;
+        Some(self.instance.step(&event))
+    }
+}
 
 // ------------------------------------------------------------------------------------------------
-// Modules
+// Implementations - StepResult
 // ------------------------------------------------------------------------------------------------
+
+impl StepResult {
+    pub fn fired(&self) -> Iter<'_, FiredTransition> {
+        self.fired.iter()
+    }
+
+    pub fn exited(&self) -> Iter<'_, ID> {
+        self.exited.iter()
+    }
+
+    pub fn entered(&self) -> Iter<'_, ID> {
+        self.entered.iter()
+    }
+
+    pub fn performed(&self) -> Iter<'_, PerformedBehavior> {
+        self.performed.iter()
+    }
+
+    ///
+    /// The guards evaluated during the step, in the order they were evaluated.
+    ///
+    pub fn guards(&self) -> Iter<'_, EvaluatedGuard> {
+        self.guards.iter()
+    }
+
+    ///
+    /// Returns `true` if no transition fired, the event was discarded or deferred.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.fired.is_empty()
+    }
+
+    ///
+    /// Returns `true` if the instance completed during the step.
+    ///
+    pub fn completed(&self) -> bool {
+        self.completed
+    }
+}
+
+impl From<&Transition> for FiredTransition {
+    fn from(transition: &Transition) -> Self {
+        Self {
+            source: transition.source(),
+            target: transition.target(),
+            label: transition.label().clone(),
+        }
+    }
+}
+
+impl FiredTransition {
+    pub fn source(&self) -> &ID {
+        &self.source
+    }
+
+    pub fn target(&self) -> &ID {
+        &self.target
+    }
+
+    pub fn label(&self) -> &Option<String> {
+        &self.label
+    }
+}
+
+impl EvaluatedGuard {
+    pub fn source(&self) -> &ID {
+        &self.source
+    }
+
+    pub fn target(&self) -> &ID {
+        &self.target
+    }
+
+    pub fn label(&self) -> &Option<String> {
+        &self.label
+    }
+
+    pub fn outcome(&self) -> &GuardOutcome {
+        &self.outcome
+    }
+}
+
+impl PerformedBehavior {
+    pub fn in_state(&self) -> &ID {
+        &self.in_state
+    }
+
+    pub fn label(&self) -> &Option<String> {
+        &self.label
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - InstanceSnapshot
+// ------------------------------------------------------------------------------------------------
+
+impl InstanceSnapshot {
+    pub fn id(&self) -> &ID {
+        &self.id
+    }
+
+    ///
+    /// The ID of the machine the instance was created for.
+    ///
+    pub fn chart(&self) -> &ID {
+        &self.chart
+    }
+
+    ///
+    /// The active states, in document order.
+    ///
+    pub fn active_states(&self) -> &[ID] {
+        &self.active
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    ///
+    /// Write this snapshot as a JSON document, see `format::native` for the schema.
+    ///
+    #[cfg(feature = "format-native")]
+    pub fn to_json(&self) -> Result<String> {
+        crate::format::native::write_snapshot(self)
+    }
+
+    ///
+    /// Read a snapshot from a JSON document written by `to_json`.
+    ///
+    #[cfg(feature = "format-native")]
+    pub fn from_json(json: &str) -> Result<Self> {
+        crate::format::native::read_snapshot(json)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - SnapshotStore
+// ------------------------------------------------------------------------------------------------
+
+impl<F> SnapshotStore for F
+where
+    F: Fn(&InstanceSnapshot) -> Result<()> + Send + Sync,
+{
+    fn save(&self, snapshot: &InstanceSnapshot) -> Result<()> {
+        self(snapshot)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - InternalQueue
+// ------------------------------------------------------------------------------------------------
+
+impl EventSink for InternalQueue {
+    fn raise(&self, event: Event) {
+        self.events.borrow_mut().push_back(event)
+    }
+
+    fn send(&self, target: SendTarget, event: Event, delivery: Delivery) {
+        self.sent.borrow_mut().push((target, event, delivery))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - SentEvent
+// ------------------------------------------------------------------------------------------------
+
+impl SentEvent {
+    pub fn target(&self) -> &ID {
+        &self.target
+    }
+
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    pub fn delivery(&self) -> Delivery {
+        self.delivery
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - HookTable
+// ------------------------------------------------------------------------------------------------
+
+impl HookTable {
+    ///
+    /// The number of observers hooked, counted once for each state or transition.
+    ///
+    pub(crate) fn len(&self) -> usize {
+        self.states.values().map(Vec::len).sum::<usize>()
+            + self.transitions.values().map(Vec::len).sum::<usize>()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations - ChartIndex
+// ------------------------------------------------------------------------------------------------
+
+impl From<&StateMachine> for ChartIndex {
+    fn from(machine: &StateMachine) -> Self {
+        let mut index = ChartIndex::default();
+        index.add_regions(machine.id(), None, machine.regions());
+        index
+    }
+}
+
+impl ChartIndex {
+    fn add_regions(&mut self, owner_id: &ID, owner: Option<&ID>, regions: Iter<'_, Region>) {
+        let mut region_ids: Vec<ID> = Default::default();
+        for region in regions {
+            region_ids.push(region.id().clone());
+            let _ = self
+                .region_owner
+                .insert(region.id().clone(), owner.cloned());
+            for vertex in region.vertices() {
+                let id = vertex.id().clone();
+                let _ = self.order.insert(id.clone(), self.order.len());
+                let _ = self.vertex_region.insert(id.clone(), region.id().clone());
+                if let Some(pseudo_state) = vertex.as_pseudo_state() {
+                    if pseudo_state.kind() == PseudoStateKind::Initial {
+                        let _ = self.initials.insert(region.id().clone(), id.clone());
+                    }
+                }
+                if let Some(state) = vertex.as_state() {
+                    self.add_regions(&id, Some(&id), state.regions());
+                }
+                if let Some(reference) = vertex.as_connection_point_reference() {
+                    for exit in reference.exit() {
+                        let _ = self.exit_references.insert(exit.clone(), id.clone());
+                    }
+                }
+                let _ = self.vertices.insert(id, vertex);
+            }
+            for transition in region.transitions() {
+                let mut keys: Vec<EventKey> = vec![];
+                for trigger in transition.triggers() {
+                    let key = trigger.event().key();
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+                for key in keys {
+                    self.triggered
+                        .entry((transition.source(), key))
+                        .or_default()
+                        .push(transition.clone());
+                }
+                self.transitions
+                    .entry(transition.source())
+                    .or_default()
+                    .push(transition);
+            }
+        }
+        let _ = self.regions.insert(owner_id.clone(), region_ids);
+    }
+
+    fn validate_choices(&self) -> Result<()> {
+        let choices = self
+            .vertices
+            .iter()
+            .filter(|(_, vertex)| vertex.as_pseudo_state().is_some_and(PseudoState::is_choice));
+        for (id, _) in choices {
+            if self.transitions_from(id).next().is_none() {
+                return Err(ErrorKind::ChoiceStateTransitions.into());
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn regions_of(&self, owner: &ID) -> Iter<'_, ID> {
+        self.regions
+            .get(owner)
+            .map(|regions| regions.iter())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn transitions_from(&self, source: &ID) -> Iter<'_, Arc<Transition>> {
+        self.transitions
+            .get(source)
+            .map(|transitions| transitions.iter())
+            .unwrap_or_default()
+    }
+
+    ///
+    /// The transitions from `source` that `event` triggers and that are `enabled`; as for
+    /// `Region::transitions_triggered_by` those that name the event take priority over those
+    /// triggered by an `AnyReceive` wildcard, which are only considered where none of those that
+    /// name it is enabled. Only the transitions with a trigger for the kind of event are
+    /// considered, rather than every transition from `source`.
+    ///
+    pub(crate) fn transitions_triggered_by<T>(
+        &self,
+        source: &ID,
+        event: &Event,
+        enabled: impl FnMut(&Arc<Transition>) -> Option<T>,
+    ) -> Vec<T> {
+        let triggered = |key: EventKey| {
+            self.triggered
+                .get(&(source.clone(), key))
+                .map(|transitions| transitions.iter())
+                .unwrap_or_default()
+                .cloned()
+        };
+        triggered_by(
+            triggered(event.key()),
+            triggered(EventKey::AnyReceive),
+            event,
+            enabled,
+        )
+    }
+
+    ///
+    /// The state containing `vertex`, if it is not directly within one of the machine's regions.
+    ///
+    pub(crate) fn parent(&self, vertex: &ID) -> Option<&ID> {
+        self.vertex_region
+            .get(vertex)
+            .and_then(|region| self.region_owner[region].as_ref())
+    }
+
+    ///
+    /// The regions containing `vertex`, at any depth, innermost first.
+    ///
+    pub(crate) fn regions_containing(&self, vertex: &ID) -> Vec<&ID> {
+        let mut regions: Vec<&ID> = Default::default();
+        let mut current = vertex;
+        while let Some(region) = self.vertex_region.get(current) {
+            regions.push(region);
+            match &self.region_owner[region] {
+                None => break,
+                Some(owner) => current = owner,
+            }
+        }
+        regions
+    }
+
+    ///
+    /// The innermost region containing both `lhs` and `rhs`, the region containing the least
+    /// common ancestor of the two; `None` where they are in different regions of the machine.
+    ///
+    pub(crate) fn common_region(&self, lhs: &ID, rhs: &ID) -> Option<ID> {
+        let rhs_regions = self.regions_containing(rhs);
+        self.regions_containing(lhs)
+            .into_iter()
+            .find(|region| rhs_regions.contains(region))
+            .cloned()
+    }
+
+    ///
+    /// The region of the state `ancestor` that contains `vertex`, if `vertex` is within it.
+    ///
+    pub(crate) fn region_within(&self, vertex: &ID, ancestor: &ID) -> Option<ID> {
+        self.regions_containing(vertex)
+            .into_iter()
+            .find(|region| self.region_owner[*region].as_ref() == Some(ancestor))
+            .cloned()
+    }
+
+    ///
+    /// The machine region that contains `vertex`.
+    ///
+    pub(crate) fn outermost_region(&self, vertex: &ID) -> Option<ID> {
+        self.regions_containing(vertex)
+            .last()
+            .map(|region| (*region).clone())
+    }
+
+    ///
+    /// Returns `true` if `vertex` is `ancestor`, or is nested, at any depth, within it.
+    ///
+    pub(crate) fn is_within(&self, vertex: &ID, ancestor: &ID) -> bool {
+        let mut current = Some(vertex);
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            current = self.parent(id);
+        }
+        false
+    }
+
+    ///
+    /// The vertex directly within `region` that is, or contains, `vertex`; or `vertex` itself
+    /// where it is not within `region`.
+    ///
+    pub(crate) fn ancestor_in(&self, vertex: &ID, region: &ID) -> ID {
+        let mut current = vertex;
+        loop {
+            if self.vertex_region.get(current) == Some(region) {
+                return current.clone();
+            }
+            match self.parent(current) {
+                None => return vertex.clone(),
+                Some(parent) => current = parent,
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn invalid_snapshot(reason: &str) -> Error {
+    ErrorKind::InvalidSnapshot(reason.to_string()).into()
+}
+
+fn invalid_migration(reason: &str) -> Error {
+    ErrorKind::InvalidMigration(reason.to_string()).into()
+}
+
+fn evaluate_change(watch: &ChangeWatch, context: &Context) -> bool {
+    match &watch.event {
+        Event::Change(change) => change.change_expression().evaluate_in(
+            &watch.state,
+            &Trigger::with_event(watch.event.clone()),
+            context,
+        ),
+        _ => false,
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::{Compound, FieldPath};
+    use crate::core::FieldValue;
+    use crate::definition::activities::threaded;
+    use crate::definition::behaviors;
+    use crate::definition::builder::MachineBuilder;
+    use crate::definition::guards::{and, else_guard, from_fn};
+    use crate::definition::opaque::{Bindings, OpaqueBehavior};
+    use crate::definition::types::{
+        ComparisonOperator, ConnectionPointReference, Constraint, Labeled, PayloadPredicate, State,
+        TransitionKind,
+    };
+    use crate::execution::fixtures::{add_state, add_transition, signal};
+    use crate::execution::timers::ManualClock;
+    use crate::execution::trace::{ExecutionTrace, TraceRecorder};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    ///
+    /// A guard over the context, `true` where the field `total` has the given value.
+    ///
+    struct TotalIs(Option<String>, i64);
+
+    impl Labeled for TotalIs {
+        fn label(&self) -> &Option<String> {
+            &self.0
+        }
+        fn set_label(&mut self, label: &str) {
+            self.0 = Some(label.to_string())
+        }
+        fn unset_label(&mut self) {
+            self.0 = None
+        }
+    }
+
+    impl Constraint for TotalIs {
+        fn evaluate(&self, _: &ID, _: &Trigger) -> bool {
+            false
+        }
+        fn evaluate_in(&self, _: &ID, _: &Trigger, context: &Context) -> bool {
+            context.get("total".parse::<FieldPath>().unwrap()) == Some(FieldValue::Integer(self.1))
+        }
+    }
+
+    #[test]
+    fn test_completion_microsteps() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut outer = State::within(region.id().clone());
+        let outer_id = outer.id().clone();
+        let inner = Region::within_state(outer_id.clone());
+        let inner_initial = inner.new_initial_state();
+        let working = add_state(&inner, "working", None);
+        let finished = inner.new_final_state();
+        add_transition(&inner, &inner_initial, &working, None);
+        add_transition(&inner, &working, &finished, None);
+        outer.add_region(inner);
+        region.add_state(outer);
+        let waiting = add_state(region, "waiting", None);
+        let done = region.new_final_state();
+        add_transition(region, &initial, &outer_id, None);
+        add_transition(region, &outer_id, &waiting, None);
+        add_transition(region, &waiting, &done, Some("go"));
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        assert!(instance.post(&signal("go")).is_err());
+        instance.execute().unwrap();
+        assert_eq!(instance.active_states(), vec![waiting]);
+        assert!(instance.is_active());
+
+        instance.post(&signal("ignored")).unwrap();
+        assert!(instance.is_active());
+        instance.post(&signal("go")).unwrap();
+        assert!(instance.is_done());
+        assert!(instance.post(&signal("go")).is_err());
+    }
+
+    fn history_machine(deep: bool) -> (StateMachine, ID, ID, ID, ID, ID) {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let paused = add_state(region, "paused", None);
+
+        let mut outer = State::within(region.id().clone());
+        let outer_id = outer.id().clone();
+        let inner = Region::within_state(outer_id.clone());
+        let inner_initial = inner.new_initial_state();
+        let history = if deep {
+            inner.new_deep_history_state()
+        } else {
+            inner.new_shallow_history_state()
+        };
+        let first = add_state(&inner, "first", None);
+        let mut second = State::within(inner.id().clone());
+        let second_id = second.id().clone();
+        let nested = Region::within_state(second_id.clone());
+        let nested_initial = nested.new_initial_state();
+        let stepping = add_state(&nested, "stepping", None);
+        let stepped = add_state(&nested, "stepped", None);
+        add_transition(&nested, &nested_initial, &stepping, None);
+        add_transition(&nested, &stepping, &stepped, Some("step"));
+        second.add_region(nested);
+        inner.add_state(second);
+        add_transition(&inner, &inner_initial, &first, None);
+        add_transition(&inner, &history, &second_id, None);
+        outer.add_region(inner);
+        region.add_state(outer);
+
+        add_transition(region, &initial, &paused, None);
+        add_transition(region, &paused, &history, Some("resume"));
+        add_transition(region, &outer_id, &paused, Some("pause"));
+        (machine, paused, outer_id, second_id, stepping, stepped)
+    }
+
+    #[test]
+    fn test_history() {
+        for deep in &[false, true] {
+            let (machine, paused, outer, second, stepping, stepped) = history_machine(*deep);
+            let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+            instance.execute().unwrap();
+            assert!(instance.is_in_state(&paused));
+
+            // no history, follow the default transition
+            instance.post(&signal("resume")).unwrap();
+            assert!(instance.is_in_state(&outer));
+            assert!(instance.is_in_state(&second));
+            assert!(instance.is_in_state(&stepping));
+
+            instance.post(&signal("step")).unwrap();
+            instance.post(&signal("pause")).unwrap();
+            assert_eq!(instance.active_states(), vec![paused.clone()]);
+
+            instance.post(&signal("resume")).unwrap();
+            assert!(instance.is_in_state(&second));
+            assert_eq!(instance.is_in_state(&stepped), *deep);
+            assert_eq!(instance.is_in_state(&stepping), !*deep);
+        }
+    }
+
+    fn add_branch(region: &Region, source: &ID, target: &ID, guard: Box<dyn Constraint>) {
+        let mut transition =
+            Transition::within(source.clone(), target.clone(), region.id().clone());
+        transition.set_guard(guard);
+        region.add_transition(transition);
+    }
+
+    #[test]
+    fn test_choice_and_junction() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let large = add_state(region, "large", None);
+        let small = add_state(region, "small", None);
+        add_transition(region, &initial, &idle, None);
+        let choice = region.new_choice_state();
+        add_transition(region, &idle, &choice, Some("choose"));
+        add_branch(region, &choice, &small, Box::new(else_guard()));
+        add_branch(region, &choice, &large, Box::new(TotalIs(None, 100)));
+        let junction = region.new_junction();
+        add_transition(region, &idle, &junction, Some("route"));
+        add_branch(region, &junction, &large, Box::new(TotalIs(None, 100)));
+        let no_else = region.new_choice_state();
+        add_transition(region, &idle, &no_else, Some("insist"));
+        add_branch(region, &no_else, &large, Box::new(TotalIs(None, 100)));
+        let machine = Arc::new(machine);
+
+        let total: FieldPath = "total".parse().unwrap();
+        let run = |value: i64, event: &str| {
+            let instance = StateMachineInstance::new(machine.clone()).unwrap();
+            instance.execute().unwrap();
+            instance
+                .update_context(|context| context.insert(total.clone(), FieldValue::Integer(value)))
+                .unwrap();
+            let result = instance.post(&signal(event));
+            (instance, result)
+        };
+
+        let (instance, _) = run(100, "choose");
+        assert_eq!(instance.active_states(), vec![large.clone()]);
+        let (instance, _) = run(10, "choose");
+        assert_eq!(instance.active_states(), vec![small]);
+
+        // a junction with no enabled branch disables the transition to it
+        let (instance, result) = run(10, "route");
+        assert!(result.is_ok());
+        assert_eq!(instance.active_states(), vec![idle]);
+        let (instance, _) = run(100, "route");
+        assert_eq!(instance.active_states(), vec![large]);
+
+        // a choice with no enabled branch is an error
+        let (instance, result) = run(10, "insist");
+        assert!(result.is_err());
+        assert!(instance.is_in_error());
+
+        let _ = machine.default_region().unwrap().new_choice_state();
+        assert!(StateMachineInstance::new(machine).is_err());
+    }
+
+    #[test]
+    fn test_guards_evaluated_once() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let done = add_state(region, "done", None);
+        add_transition(region, &initial, &idle, None);
+        let junction = region.new_junction();
+        add_transition(region, &idle, &junction, Some("route"));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        add_branch(
+            region,
+            &junction,
+            &done,
+            Box::new(from_fn("counted", move |_, _, _| {
+                let _ = counter.fetch_add(1, Ordering::SeqCst);
+                true
+            })),
+        );
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        instance.post(&signal("route")).unwrap();
+        assert_eq!(instance.active_states(), vec![done]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_entry_and_exit_points() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let done = add_state(region, "done", None);
+        let aborted = add_state(region, "aborted", None);
+
+        let mut outer = State::within(region.id().clone());
+        let outer_id = outer.id().clone();
+        let inner = Region::within_state(outer_id.clone());
+        let inner_initial = inner.new_initial_state();
+        let entry_point = inner.new_entry_point();
+        let exit_point = inner.new_exit_point();
+        let abort_point = inner.new_exit_point();
+        let first = add_state(&inner, "first", None);
+        let second = add_state(&inner, "second", None);
+        add_transition(&inner, &inner_initial, &first, None);
+        add_transition(&inner, &entry_point, &second, None);
+        add_transition(&inner, &second, &exit_point, Some("leave"));
+        add_transition(&inner, &second, &abort_point, Some("abort"));
+        outer.add_region(inner);
+        region.add_state(outer);
+
+        let mut reference = ConnectionPointReference::within(region.id().clone());
+        reference.add_entry(entry_point.clone());
+        reference.add_exit(abort_point);
+        reference.set_state(outer_id.clone());
+        let reference_id = reference.id().clone();
+        region.add_connection_point_ref(reference);
+
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &outer_id, Some("start"));
+        add_transition(region, &idle, &entry_point, Some("resume"));
+        add_transition(region, &idle, &reference_id, Some("connect"));
+        add_transition(region, &exit_point, &done, None);
+        add_transition(region, &reference_id, &aborted, None);
+        let machine = Arc::new(machine);
+
+        let run = |events: &[&str]| {
+            let instance = StateMachineInstance::new(machine.clone()).unwrap();
+            instance.execute().unwrap();
+            for event in events {
+                instance.post(&signal(event)).unwrap();
+            }
+            instance.active_states()
+        };
+
+        assert_eq!(run(&["start"]), vec![outer_id.clone(), first]);
+        assert_eq!(run(&["resume"]), vec![outer_id.clone(), second.clone()]);
+        assert_eq!(run(&["resume", "leave"]), vec![done]);
+        assert_eq!(run(&["connect"]), vec![outer_id, second]);
+        assert_eq!(run(&["connect", "abort"]), vec![aborted]);
+    }
+
+    #[test]
+    fn test_do_activities() {
+        let release = Arc::new(AtomicBool::new(false));
+        let released = release.clone();
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut working = State::within(region.id().clone());
+        working.set_do_activity(Box::new(threaded("work", move |handle| {
+            handle.set_progress(0.5);
+            while !released.load(Ordering::SeqCst) && !handle.is_cancelled() {
+                std::thread::yield_now();
+            }
+        })));
+        let working_id = working.id().clone();
+        region.add_state(working);
+        let done = add_state(region, "done", None);
+        let stopped = add_state(region, "stopped", None);
+        add_transition(region, &initial, &working_id, None);
+        add_transition(region, &working_id, &done, None);
+        add_transition(region, &working_id, &stopped, Some("stop"));
+        let machine = Arc::new(machine);
+
+        // the completion transition waits for the do-activity to finish
+        let instance = StateMachineInstance::new(machine.clone()).unwrap();
+        instance.execute().unwrap();
+        let activity = instance.activity(&working_id).unwrap();
+        while activity.progress().is_none() {
+            std::thread::yield_now();
+        }
+        assert_eq!(instance.tick().unwrap(), 0);
+        assert_eq!(instance.active_states(), vec![working_id.clone()]);
+        release.store(true, Ordering::SeqCst);
+        while !activity.is_finished() {
+            std::thread::yield_now();
+        }
+        assert_eq!(instance.tick().unwrap(), 0);
+        assert_eq!(instance.active_states(), vec![done]);
+        assert!(!activity.is_cancelled());
+
+        // exiting the state cancels the do-activity
+        release.store(false, Ordering::SeqCst);
+        let instance = StateMachineInstance::new(machine).unwrap();
+        instance.execute().unwrap();
+        let activity = instance.activity(&working_id).unwrap();
+        instance.post(&signal("stop")).unwrap();
+        assert_eq!(instance.active_states(), vec![stopped]);
+        assert!(activity.is_cancelled());
+        assert!(instance.activity(&working_id).is_none());
+    }
+
+    #[test]
+    fn test_orthogonal_regions() {
+        let log: Arc<Mutex<Vec<String>>> = Default::default();
+        let mut bindings = Bindings::default();
+        for name in &["left", "right", "outer"] {
+            let log = log.clone();
+            bindings.bind(name, move |_, _, _| {
+                log.lock().unwrap().push(name.to_string())
+            });
+        }
+        let logged = |name: &str| bindings.resolve(OpaqueBehavior::new(name, None), &[name]);
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut both = State::within(region.id().clone());
+        let both_id = both.id().clone();
+        let mut ends: Vec<(ID, ID)> = Default::default();
+        for name in &["left", "right"] {
+            let inner = Region::within_state(both_id.clone());
+            let inner_initial = inner.new_initial_state();
+            let start = add_state(&inner, name, None);
+            let end = add_state(&inner, name, None);
+            add_transition(&inner, &inner_initial, &start, None);
+            let mut transition = Transition::within(start.clone(), end.clone(), inner.id().clone());
+            transition.add_trigger(Trigger::signal("tick"));
+            transition.set_effect(logged(name));
+            inner.add_transition(transition);
+            both.add_region(inner);
+            ends.push((start, end));
+        }
+        region.add_state(both);
+        let after = add_state(region, "after", None);
+        add_transition(region, &initial, &both_id, None);
+        let mut transition = Transition::within(both_id.clone(), after, region.id().clone());
+        transition.add_trigger(Trigger::signal("tick"));
+        transition.set_effect(logged("outer"));
+        region.add_transition(transition);
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        assert_eq!(
+            instance.active_states(),
+            vec![both_id.clone(), ends[0].0.clone(), ends[1].0.clone()]
+        );
+
+        // each region takes the event, in document order, before the state containing them
+        instance.post(&signal("tick")).unwrap();
+        assert_eq!(
+            instance.active_states(),
+            vec![both_id, ends[0].1.clone(), ends[1].1.clone()]
+        );
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["left".to_string(), "right".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_transition_kinds() {
+        let log: Arc<Mutex<Vec<String>>> = Default::default();
+        let mut bindings = Bindings::default();
+        for name in &["+outer", "-outer", "+first", "-first", "+second", "-second"] {
+            let log = log.clone();
+            bindings.bind(name, move |_, _, _| {
+                log.lock().unwrap().push(name.to_string())
+            });
+        }
+        let logged = |name: &str| bindings.resolve(OpaqueBehavior::new(name, None), &[name]);
+        let logging_state = |region: &Region, name: &str| {
+            let mut state = State::within(region.id().clone());
+            state.set_entry(logged(&format!("+{}", name)));
+            state.set_exit(logged(&format!("-{}", name)));
+            state
+        };
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut outer = logging_state(region, "outer");
+        let outer_id = outer.id().clone();
+        let inner = Region::within_state(outer_id.clone());
+        let inner_initial = inner.new_initial_state();
+        let first = logging_state(&inner, "first");
+        let first_id = first.id().clone();
+        inner.add_state(first);
+        let second = logging_state(&inner, "second");
+        let second_id = second.id().clone();
+        inner.add_state(second);
+        add_transition(&inner, &inner_initial, &first_id, None);
+        outer.add_region(inner);
+        let inner_id = outer.regions().next().unwrap().id().clone();
+        region.add_state(outer);
+        add_transition(region, &initial, &outer_id, None);
+
+        let add = |source: &ID, target: &ID, kind: TransitionKind, signal: &str| {
+            let mut transition =
+                Transition::within(source.clone(), target.clone(), inner_id.clone());
+            transition.set_kind(kind);
+            transition.add_trigger(Trigger::signal(signal));
+            machine.default_region().unwrap().add_transition(transition);
+        };
+        add(
+            &outer_id,
+            &second_id,
+            TransitionKind::External,
+            "external-down",
+        );
+        add(&outer_id, &second_id, TransitionKind::Local, "local-down");
+        add(
+            &first_id,
+            &outer_id,
+            TransitionKind::External,
+            "external-up",
+        );
+        add(&first_id, &outer_id, TransitionKind::Local, "local-up");
+        add(&outer_id, &outer_id, TransitionKind::Internal, "internal");
+        let machine = Arc::new(machine);
+
+        let run = |event: &str| {
+            let instance = StateMachineInstance::new(machine.clone()).unwrap();
+            instance.execute().unwrap();
+            log.lock().unwrap().clear();
+            instance.post(&signal(event)).unwrap();
+            log.lock().unwrap().join(" ")
+        };
+
+        assert_eq!(run("external-down"), "-first -outer +outer +second");
+        assert_eq!(run("local-down"), "-first +second");
+        assert_eq!(run("external-up"), "-first -outer +outer +first");
+        assert_eq!(run("local-up"), "-first +first");
+        assert_eq!(run("internal"), "");
+    }
+
+    #[test]
+    fn test_conflicting_transitions() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut outer = State::within(region.id().clone());
+        let outer_id = outer.id().clone();
+        let inner = Region::within_state(outer_id.clone());
+        let inner_initial = inner.new_initial_state();
+        let idle = add_state(&inner, "idle", None);
+        let left = add_state(&inner, "left", None);
+        let right = add_state(&inner, "right", None);
+        add_transition(&inner, &inner_initial, &idle, None);
+        add_transition(&inner, &idle, &left, Some("go"));
+        add_transition(&inner, &idle, &right, Some("go"));
+        add_transition(&inner, &left, &right, Some("next"));
+        outer.add_region(inner);
+        region.add_state(outer);
+        let after = add_state(region, "after", None);
+        add_transition(region, &initial, &outer_id, None);
+        add_transition(region, &outer_id, &after, Some("go"));
+        add_transition(region, &outer_id, &after, Some("next"));
+        let machine = Arc::new(machine);
+
+        // transitions from the same state conflict
+        let instance = StateMachineInstance::new(machine.clone()).unwrap();
+        instance.execute().unwrap();
+        assert!(instance.post(&signal("go")).is_err());
+        assert!(instance.is_in_error());
+
+        let instance = StateMachineInstance::new(machine)
+            .unwrap()
+            .with_tie_breaker(|_, enabled| enabled.len() - 1);
+        instance.execute().unwrap();
+        instance.post(&signal("go")).unwrap();
+        assert_eq!(
+            instance.active_states(),
+            vec![outer_id.clone(), right.clone()]
+        );
+
+        // the innermost transition takes priority over one from a containing state
+        let instance = StateMachineInstance::new(instance.chart().clone())
+            .unwrap()
+            .with_tie_breaker(|_, _| 0);
+        instance.execute().unwrap();
+        instance.post(&signal("go")).unwrap();
+        instance.post(&signal("next")).unwrap();
+        assert_eq!(instance.active_states(), vec![outer_id, right]);
+    }
+
+    #[test]
+    fn test_deterministic_instances() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let left = add_state(region, "left", None);
+        let right = add_state(region, "right", None);
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &left, Some("go"));
+        add_transition(region, &idle, &right, Some("go"));
+        let machine = Arc::new(machine);
+
+        let run = |ids: &SeededIds| -> (ID, ExecutionTrace) {
+            let recorder = Arc::new(TraceRecorder::default());
+            let instance = StateMachineInstance::new(machine.clone())
+                .unwrap()
+                .with_id(ids.next_id())
+                .with_clock(Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH)))
+                .deterministic();
+            assert!(instance.is_deterministic());
+            instance.add_observer(recorder.clone());
+            instance.execute().unwrap();
+            instance.post(&signal("go")).unwrap();
+            assert_eq!(instance.active_states(), vec![left.clone()]);
+            (instance.id().clone(), recorder.trace())
+        };
+
+        let ids = SeededIds::new(7);
+        let (first, first_trace) = run(&ids);
+        let (second, _) = run(&ids);
+        assert_eq!(first.to_string(), "execution::7-0");
+        assert_eq!(second.to_string(), "execution::7-1");
+        let (again, again_trace) = run(&SeededIds::new(7));
+        assert_eq!(again, first);
+        assert_eq!(again_trace, first_trace);
+    }
+
+    #[test]
+    fn test_microstep_limit() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let ping = add_state(region, "ping", None);
+        let pong = add_state(region, "pong", None);
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &ping, Some("spin"));
+        add_transition(region, &ping, &pong, None);
+        add_transition(region, &pong, &ping, None);
+        let machine = Arc::new(machine);
+
+        let instance = StateMachineInstance::new(machine)
+            .unwrap()
+            .with_microstep_limit(5);
+        instance.execute().unwrap();
+        match instance.post(&signal("spin")) {
+            Err(Error(ErrorKind::MicrostepLimit(5, cycle), _)) => assert_eq!(
+                cycle,
+                vec![(pong.clone(), ping.clone()), (ping.clone(), pong.clone())]
+            ),
+            other => panic!("expected the microstep limit, not {:?}", other),
+        }
+        assert!(instance.is_in_error());
+    }
+
+    #[test]
+    fn test_error_policies() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let left = add_state(region, "left", None);
+        let right = add_state(region, "right", None);
+        let working = add_state(region, "working", None);
+        let failed = add_state(region, "failed", None);
+        let handled = add_state(region, "handled", None);
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &left, Some("go"));
+        add_transition(region, &idle, &right, Some("go"));
+        add_transition(region, &idle, &working, Some("work"));
+        add_transition(region, &idle, &handled, Some(ERROR_SIGNAL));
+        let machine = Arc::new(machine);
+        let errors = Arc::new(AtomicUsize::new(0));
+        struct Errors(Arc<AtomicUsize>);
+        impl ExecutionObserver for Errors {
+            fn on_error(&self, _: &StateMachineInstance, _: &Error) {
+                let _ = self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let instance = StateMachineInstance::new(machine.clone()).unwrap();
+        instance.add_observer(Arc::new(Errors(errors.clone())));
+        instance.execute().unwrap();
+        assert!(instance.post(&signal("go")).is_err());
+        assert!(instance.is_in_error());
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+        assert!(instance.post(&signal("work")).is_err());
+        instance.clear_error().unwrap();
+        assert!(instance.is_active());
+        instance.post(&signal("work")).unwrap();
+        assert_eq!(instance.active_states(), vec![working.clone()]);
+
+        let run = |policy: ErrorPolicy| -> Vec<ID> {
+            let instance = StateMachineInstance::new(machine.clone())
+                .unwrap()
+                .with_error_policy(policy);
+            instance.execute().unwrap();
+            instance.post(&signal("go")).unwrap();
+            assert!(instance.is_active());
+            instance.active_states()
+        };
+        assert_eq!(run(ErrorPolicy::Ignore), vec![idle]);
+        assert_eq!(run(ErrorPolicy::EnterState(failed.clone())), vec![failed]);
+        assert_eq!(run(ErrorPolicy::RaiseEvent), vec![handled]);
+    }
+
+    #[test]
+    fn test_panics_are_caught() {
+        struct Panics(Option<String>);
+        impl Labeled for Panics {
+            fn label(&self) -> &Option<String> {
+                &self.0
+            }
+            fn set_label(&mut self, label: &str) {
+                self.0 = Some(label.to_string())
+            }
+            fn unset_label(&mut self) {
+                self.0 = None
+            }
+        }
+        impl Constraint for Panics {
+            fn evaluate(&self, _: &ID, _: &Trigger) -> bool {
+                panic!("guard panicked")
+            }
+        }
+        let mut bindings = Bindings::default();
+        bindings.bind("boom", |_, _, _| panic!("behavior panicked"));
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let broken = add_state(
+            region,
+            "broken",
+            Some(bindings.resolve(OpaqueBehavior::new("boom", None), &["boom"])),
+        );
+        let guarded = add_state(region, "guarded", None);
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &broken, Some("go"));
+        add_branch(region, &idle, &guarded, Box::new(Panics(None)));
+        let machine = Arc::new(machine);
+
+        let instance = StateMachineInstance::new(machine.clone())
+            .unwrap()
+            .with_error_policy(ErrorPolicy::Ignore);
+        instance.execute().unwrap();
+        assert!(instance.is_active());
+        assert_eq!(instance.active_states(), vec![idle]);
+
+        let instance = StateMachineInstance::new(machine).unwrap();
+        assert!(matches!(
+            instance.execute().unwrap_err().kind(),
+            ErrorKind::ActionPanicked(_)
+        ));
+        assert!(instance.is_in_error());
+        instance.clear_error().unwrap();
+        match instance.post(&signal("go")).unwrap_err().kind() {
+            ErrorKind::ActionPanicked(within) => assert!(within.contains(&broken.to_string())),
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(instance.is_in_error());
+        assert_eq!(instance.active_states(), vec![broken]);
+    }
+
+    #[test]
+    fn test_guarded_completions_are_reevaluated() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let waiting = add_state(region, "waiting", None);
+        let ready = add_state(region, "ready", None);
+        add_transition(region, &initial, &waiting, None);
+        add_branch(region, &waiting, &ready, Box::new(TotalIs(None, 3)));
+        let machine = Arc::new(machine);
+        let total = || "total".parse::<FieldPath>().unwrap();
+
+        // when the context is updated
+        let instance = StateMachineInstance::new(machine.clone()).unwrap();
+        instance.execute().unwrap();
+        assert_eq!(instance.active_states(), vec![waiting.clone()]);
+        instance
+            .update_context(|context| context.insert(total(), FieldValue::Integer(2)))
+            .unwrap();
+        assert_eq!(instance.active_states(), vec![waiting.clone()]);
+        instance
+            .update_context(|context| context.insert(total(), FieldValue::Integer(3)))
+            .unwrap();
+        assert_eq!(instance.active_states(), vec![ready.clone()]);
+
+        // not while paused, but once resumed
+        let instance = StateMachineInstance::new(machine.clone()).unwrap();
+        instance.execute().unwrap();
+        instance.pause().unwrap();
+        instance
+            .update_context(|context| context.insert(total(), FieldValue::Integer(3)))
+            .unwrap();
+        assert_eq!(instance.active_states(), vec![waiting.clone()]);
+        instance.resume().unwrap();
+        assert_eq!(instance.active_states(), vec![ready.clone()]);
+
+        // at the end of a step, even where the event enabled no transition
+        let instance = StateMachineInstance::new(machine).unwrap();
+        instance.execute().unwrap();
+        instance
+            .context_mut()
+            .insert(total(), FieldValue::Integer(3));
+        assert_eq!(instance.active_states(), vec![waiting]);
+        instance.post(&signal("ignored")).unwrap();
+        assert_eq!(instance.active_states(), vec![ready]);
+    }
+
+    #[test]
+    fn test_step_results() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let busy = add_state(
+            region,
+            "busy",
+            Some(Box::new(OpaqueBehavior::new("work", None))),
+        );
+        let done = region.new_final_state();
+        add_transition(region, &initial, &idle, None);
+        let mut transition = Transition::within(idle.clone(), busy.clone(), region.id().clone());
+        transition.set_label("start");
+        transition.add_trigger(Trigger::signal("go"));
+        transition.set_effect(Box::new(OpaqueBehavior::new("prepare", None)));
+        region.add_transition(transition);
+        add_transition(region, &busy, &done, Some("finish"));
+
+        let mut instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        assert!(instance.step(&signal("ignored")).unwrap().is_empty());
+
+        let result = instance.step(&signal("go")).unwrap();
+        let fired: Vec<&FiredTransition> = result.fired().collect();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].source(), &idle);
+        assert_eq!(fired[0].target(), &busy);
+        assert_eq!(fired[0].label(), &Some("start".to_string()));
+        assert_eq!(result.exited().collect::<Vec<&ID>>(), vec![&idle]);
+        assert_eq!(result.entered().collect::<Vec<&ID>>(), vec![&busy]);
+        assert_eq!(
+            result
+                .performed()
+                .map(|performed| performed.label().clone().unwrap())
+                .collect::<Vec<String>>(),
+            vec!["prepare".to_string(), "work".to_string()]
+        );
+        assert!(!result.completed());
+
+        let result = instance.step(&signal("finish")).unwrap();
+        assert_eq!(result.entered().collect::<Vec<&ID>>(), vec![&done]);
+        assert!(result.completed());
+        assert!(instance.step(&signal("go")).is_err());
+    }
+
+    #[test]
+    fn test_step_results_record_guards() {
+        fn has(name: &'static str) -> impl Fn(&ID, &Trigger, &Context) -> bool {
+            move |_, _, context| context.contains_key(name.parse::<FieldPath>().unwrap())
+        }
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let busy = add_state(region, "busy", None);
+        add_transition(region, &initial, &idle, None);
+        let mut transition = Transition::within(idle.clone(), busy.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("go"));
+        transition.set_guard(Box::new(and(
+            from_fn("ready", has("ready")),
+            from_fn("paid", has("paid")),
+        )));
+        region.add_transition(transition);
+        let machine = Arc::new(machine);
+
+        // instances sharing the guard each see their own evaluation
+        let mut unready = StateMachineInstance::new(machine.clone()).unwrap();
+        unready.execute().unwrap();
+        let mut unpaid = StateMachineInstance::new(machine).unwrap();
+        unpaid.execute().unwrap();
+        unpaid
+            .context_mut()
+            .insert("ready".parse::<FieldPath>().unwrap(), true.into());
+
+        let unpaid_result = unpaid.step(&signal("go")).unwrap();
+        let unready_result = unready.step(&signal("go")).unwrap();
+        for &(result, failed) in [(&unready_result, 0), (&unpaid_result, 1)].iter() {
+            assert!(result.is_empty());
+            let guards: Vec<&EvaluatedGuard> = result.guards().collect();
+            assert_eq!(guards.len(), 1);
+            assert_eq!(guards[0].source(), &idle);
+            assert_eq!(guards[0].target(), &busy);
+            assert_eq!(guards[0].label(), &Some("ready && paid".to_string()));
+            assert!(!guards[0].outcome().result);
+            assert_eq!(guards[0].outcome().failed_guard(), Some(failed));
+            assert_eq!(guards[0].outcome().evaluated.len(), failed + 1);
+        }
+
+        unpaid
+            .context_mut()
+            .insert("paid".parse::<FieldPath>().unwrap(), true.into());
+        let result = unpaid.step(&signal("go")).unwrap();
+        assert_eq!(
+            result.guards().next().unwrap().outcome().failed_guard(),
+            None
+        );
+        assert_eq!(unpaid.active_states(), vec![busy]);
+    }
+
+    #[test]
+    fn test_drive() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let busy = add_state(region, "busy", None);
+        let done = region.new_final_state();
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &busy, Some("go"));
+        add_transition(region, &busy, &done, Some("finish"));
+
+        let mut instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        let results: Vec<Result<StepResult>> = instance
+            .drive(vec![
+                signal("ignored"),
+                signal("go"),
+                signal("finish"),
+                signal("go"),
+            ])
+            .collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].as_ref().unwrap().is_empty());
+        assert_eq!(
+            results[1].as_ref().unwrap().entered().collect::<Vec<&ID>>(),
+            vec![&busy]
+        );
+        assert!(results[2].as_ref().unwrap().completed());
+        assert!(results[3].is_err());
+        assert!(instance.is_done());
+    }
+
+    #[test]
+    fn test_observers() {
+        #[derive(Default)]
+        struct Audit(Mutex<Vec<String>>);
+
+        impl ExecutionObserver for Audit {
+            fn on_state_entered(&self, instance: &StateMachineInstance, state: &ID) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("+{}", label_of(instance, state)))
+            }
+            fn on_state_exited(&self, instance: &StateMachineInstance, state: &ID) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("-{}", label_of(instance, state)))
+            }
+            fn on_transition_fired(&self, _: &StateMachineInstance, transition: &Transition) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}", transition.triggers().count()))
+            }
+            fn on_event_dropped(&self, _: &StateMachineInstance, event: &Event) {
+                self.0.lock().unwrap().push(format!("?{}", event))
+            }
+            fn on_done(&self, _: &StateMachineInstance) {
+                self.0.lock().unwrap().push("done".to_string())
+            }
+        }
+
+        fn label_of(instance: &StateMachineInstance, state: &ID) -> String {
+            instance.index.vertices[state]
+                .as_state()
+                .and_then(|state| state.label().clone())
+                .unwrap_or_else(|| "final".to_string())
+        }
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let done = region.new_final_state();
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &done, Some("stop"));
+
+        let audit = Arc::new(Audit::default());
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.add_observer(audit.clone());
+        instance.execute().unwrap();
+        instance.post(&signal("ignored")).unwrap();
+        instance.post(&signal("stop")).unwrap();
+        assert_eq!(
+            audit.0.lock().unwrap().join(" "),
+            "0 +idle ?ignored 1 -idle +final done"
+        );
+    }
+
+    #[test]
+    fn test_completion_order() {
+        #[derive(Default)]
+        struct Audit(Mutex<Vec<String>>);
+
+        impl Audit {
+            fn log(&self, entry: String) {
+                self.0.lock().unwrap().push(entry)
+            }
+        }
+
+        impl ExecutionObserver for Audit {
+            fn on_event_dropped(&self, _: &StateMachineInstance, event: &Event) {
+                self.log(format!("?{}", event))
+            }
+            fn on_region_completed(&self, instance: &StateMachineInstance, region: &ID) {
+                let position = instance
+                    .chart
+                    .regions()
+                    .position(|machine_region| machine_region.id() == region)
+                    .unwrap();
+                self.log(format!("region {}", position))
+            }
+            fn on_done(&self, instance: &StateMachineInstance) {
+                assert!(instance.activities.borrow().is_empty());
+                assert!(instance.queued_events().is_empty());
+                self.log("done".to_string())
+            }
+        }
+
+        let (machine, _) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("left")
+                    .state("left", |state| state.on("stop", "left done"))
+                    .final_state("left done")
+            })
+            .region(|region| {
+                region
+                    .initial("right")
+                    .state("right", |state| state.on("stop", "right done"))
+                    .final_state("right done")
+            })
+            .build_with_names()
+            .unwrap();
+
+        let audit = Arc::new(Audit::default());
+        let performed = audit.clone();
+        let saved = audit.clone();
+        let mut instance = StateMachineInstance::new(machine)
+            .unwrap()
+            .with_done_behavior(behaviors::from_fn("first", move |_, _, events, _| {
+                performed.log("first".to_string());
+                events.send(SendTarget::This, signal("again"), Delivery::Immediate);
+            }))
+            .with_done_behavior(behaviors::from_fn("second", |_, _, _, _| {}))
+            .with_snapshot_store(move |snapshot: &InstanceSnapshot| {
+                assert_eq!(snapshot.state, ExecutionState::Done);
+                saved.log("saved".to_string());
+                Ok(())
+            });
+        instance.add_observer(audit.clone());
+        instance.execute().unwrap();
+        let result = instance.step(&signal("stop")).unwrap();
+        assert!(result.completed());
+        assert_eq!(
+            audit.0.lock().unwrap().join(", "),
+            "region 0, region 1, first, ?again, done, saved"
+        );
+        let performed: Vec<&Option<String>> =
+            result.performed().map(PerformedBehavior::label).collect();
+        assert_eq!(
+            performed[performed.len() - 2..],
+            [&Some("first".to_string()), &Some("second".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_hooks() {
+        #[derive(Default)]
+        struct Audit(Mutex<Vec<String>>);
+
+        impl ExecutionObserver for Audit {
+            fn on_dispatch_started(&self, _: &StateMachineInstance, _: Option<&Event>) {
+                self.0.lock().unwrap().push("dispatch".to_string())
+            }
+            fn on_state_entered(&self, _: &StateMachineInstance, _: &ID) {
+                self.0.lock().unwrap().push("+".to_string())
+            }
+            fn on_state_exited(&self, _: &StateMachineInstance, _: &ID) {
+                self.0.lock().unwrap().push("-".to_string())
+            }
+            fn on_transition_fired(&self, _: &StateMachineInstance, transition: &Transition) {
+                self.0.lock().unwrap().push(
+                    transition
+                        .triggers()
+                        .map(|trigger| trigger.event().to_string())
+                        .collect(),
+                )
+            }
+        }
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let busy = add_state(region, "busy", None);
+        add_transition(region, &initial, &idle, None);
+        let mut begin = Transition::within(idle.clone(), busy.clone(), region.id().clone());
+        begin.add_trigger(Trigger::signal("start"));
+        begin.set_label("begin");
+        region.add_transition(begin);
+        add_transition(region, &busy, &idle, Some("stop"));
+        let region = region.id().clone();
+
+        let states = Arc::new(Audit::default());
+        let transitions = Arc::new(Audit::default());
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance
+            .add_hook(Hook::State(busy.clone()), states.clone())
+            .unwrap();
+        instance
+            .add_hook(Hook::StateLabeled("idle".to_string()), states.clone())
+            .unwrap();
+        instance
+            .add_hook(
+                Hook::TransitionLabeled("begin".to_string()),
+                transitions.clone(),
+            )
+            .unwrap();
+        instance
+            .add_hook(Hook::Transition(region.clone(), 2), transitions.clone())
+            .unwrap();
+        assert!(instance
+            .add_hook(Hook::StateLabeled("missing".to_string()), states.clone())
+            .is_err());
+        assert!(instance
+            .add_hook(Hook::Transition(region, 3), transitions.clone())
+            .is_err());
+
+        instance.execute().unwrap();
+        assert_eq!(states.0.lock().unwrap().join(" "), "+");
+        assert!(transitions.0.lock().unwrap().is_empty());
+        for name in &["start", "ignored", "stop"] {
+            instance.post(&signal(name)).unwrap();
+        }
+        assert_eq!(states.0.lock().unwrap().join(" "), "+ - + - +");
+        assert_eq!(transitions.0.lock().unwrap().join(" "), "start stop");
+    }
+
+    #[test]
+    fn test_deferred_events() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut busy = State::within(region.id().clone());
+        busy.add_deferrable_trigger(Trigger::signal("job"));
+        let busy = {
+            let id = busy.id().clone();
+            region.add_state(busy);
+            id
+        };
+        let idle = add_state(region, "idle", None);
+        let working = add_state(region, "working", None);
+        add_transition(region, &initial, &busy, None);
+        add_transition(region, &busy, &idle, Some("ready"));
+        add_transition(region, &idle, &working, Some("job"));
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        instance.post(&signal("job")).unwrap();
+        instance.post(&signal("other")).unwrap();
+        assert_eq!(instance.deferred_events(), vec![signal("job")]);
+        assert!(instance.is_in_state(&busy));
+
+        instance.post(&signal("ready")).unwrap();
+        assert!(instance.deferred_events().is_empty());
+        assert_eq!(instance.active_states(), vec![working]);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut busy = State::within(region.id().clone());
+        busy.add_deferrable_trigger(Trigger::signal("job"));
+        let busy = {
+            let id = busy.id().clone();
+            region.add_state(busy);
+            id
+        };
+        let idle = add_state(region, "idle", None);
+        let working = add_state(region, "working", None);
+        let failed = add_state(region, "failed", None);
+        add_transition(region, &initial, &busy, None);
+        add_transition(region, &busy, &idle, Some("ready"));
+        add_transition(region, &idle, &working, Some("job"));
+        let mut timeout = Transition::within(busy.clone(), failed.clone(), region.id().clone());
+        timeout.add_trigger(Trigger::after(Duration::from_secs(10)));
+        region.add_transition(timeout);
+        let machine = Arc::new(machine);
+
+        let start = SystemTime::UNIX_EPOCH;
+        let instance = StateMachineInstance::new(machine.clone())
+            .unwrap()
+            .with_clock(Arc::new(ManualClock::new(start)));
+        instance.execute().unwrap();
+        instance.post(&signal("job")).unwrap();
+        let total: FieldPath = "total".parse().unwrap();
+        instance
+            .context()
+            .insert(total.clone(), FieldValue::Integer(3));
+        let snapshot = instance.snapshot();
+        instance
+            .context()
+            .insert(total.clone(), FieldValue::Integer(4));
+        assert_eq!(snapshot.active_states(), vec![busy.clone()]);
+        assert_eq!(
+            snapshot.context().get(total.clone()),
+            Some(FieldValue::Integer(3))
+        );
+        #[cfg(feature = "format-native")]
+        assert_eq!(
+            InstanceSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap(),
+            snapshot
+        );
+
+        let other = Arc::new(StateMachine::default());
+        assert!(StateMachineInstance::restore(other, &snapshot).is_err());
+
+        let clock = Arc::new(ManualClock::new(start + Duration::from_secs(5)));
+        let restored = StateMachineInstance::restore(machine, &snapshot)
+            .unwrap()
+            .with_clock(clock.clone());
+        assert_eq!(restored.id(), instance.id());
+        assert!(restored.is_active());
+        assert!(restored.is_in_state(&busy));
+        assert_eq!(restored.deferred_events(), vec![signal("job")]);
+        assert_eq!(restored.next_timer(), Some(start + Duration::from_secs(10)));
+        assert_eq!(restored.context().get(total), Some(FieldValue::Integer(3)));
+
+        restored.post(&signal("ready")).unwrap();
+        assert_eq!(restored.active_states(), vec![working]);
+        assert_eq!(restored.next_timer(), None);
+    }
+
+    #[test]
+    fn test_strict_bindings() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let greeting = add_state(
+            region,
+            "greeting",
+            Some(Box::new(OpaqueBehavior::new("greet", None))),
+        );
+        add_transition(region, &initial, &greeting, None);
+        let _ = add_state(
+            region,
+            "unreachable",
+            Some(Box::new(OpaqueBehavior::new("audit", None))),
+        );
+        let machine = Arc::new(machine);
+
+        let greeted = Arc::new(AtomicUsize::new(0));
+        let counter = greeted.clone();
+        let mut bindings = Bindings::default();
+        bindings.bind("greet", move |_, _, _| {
+            let _ = counter.fetch_add(1, Ordering::SeqCst);
+        });
+        bindings.bind("audit", |_, _, _| {});
+        let result = StateMachineInstance::with_bindings(
+            machine.clone(),
+            Context::default(),
+            &bindings,
+            BindingMode::Strict,
+        );
+        assert!(matches!(
+            result.map(|_| ()).map_err(|e| e.0),
+            Err(ErrorKind::BindingMismatch(unbound, pruned))
+                if unbound.is_empty() && pruned == vec!["audit".to_string()]
+        ));
+
+        let counter = greeted.clone();
+        let mut bindings = Bindings::default();
+        bindings.bind("greet", move |_, _, _| {
+            let _ = counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let instance = StateMachineInstance::with_bindings(
+            machine,
+            Context::default(),
+            &bindings,
+            BindingMode::Strict,
+        )
+        .unwrap();
+        instance.execute().unwrap();
+        assert!(instance.is_in_state(&greeting));
+        assert_eq!(greeted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_event_payloads() {
+        let totals: Arc<Mutex<Vec<Option<FieldValue>>>> = Default::default();
+        let recorded = totals.clone();
+        let mut bindings = Bindings::default();
+        bindings.bind("record", move |_, trigger, _| {
+            let total = trigger
+                .payload()
+                .and_then(|payload| payload.get("total".parse::<FieldPath>().unwrap()));
+            recorded.lock().unwrap().push(total)
+        });
+        let total_path: FieldPath = "total".parse().unwrap();
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut busy = State::within(region.id().clone());
+        busy.add_deferrable_trigger(Trigger::signal("order"));
+        let busy = {
+            let id = busy.id().clone();
+            region.add_state(busy);
+            id
+        };
+        let open = add_state(region, "open", None);
+        let review = add_state(region, "review", None);
+        add_transition(region, &initial, &busy, None);
+        add_transition(region, &busy, &open, Some("ready"));
+        let mut large = Trigger::signal("order");
+        large.add_payload_predicate(PayloadPredicate::new(
+            total_path.clone(),
+            ComparisonOperator::Greater,
+            FieldValue::Integer(100),
+        ));
+        let mut transition = Transition::within(open.clone(), review.clone(), region.id().clone());
+        transition.add_trigger(large);
+        transition.set_effect(bindings.resolve(OpaqueBehavior::new("record", None), &["record"]));
+        region.add_transition(transition);
+        let machine = Arc::new(machine);
+
+        let payload = |total: i64| {
+            let payload = Context::default();
+            payload.insert(total_path.clone(), FieldValue::Integer(total));
+            payload
+        };
+        let instance = StateMachineInstance::new(machine.clone()).unwrap();
+        instance.execute().unwrap();
+        instance.post_with(&signal("order"), payload(250)).unwrap();
+        assert_eq!(instance.deferred_events(), vec![signal("order")]);
+        #[cfg(feature = "format-native")]
+        {
+            let snapshot = instance.snapshot();
+            assert_eq!(
+                InstanceSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap(),
+                snapshot
+            );
+        }
+
+        // the deferred order keeps its payload when recalled.
+        instance.post(&signal("ready")).unwrap();
+        assert!(instance.is_in_state(&review));
+        assert_eq!(
+            *totals.lock().unwrap(),
+            vec![Some(FieldValue::Integer(250))]
+        );
+
+        let instance = StateMachineInstance::new(machine).unwrap();
+        instance.execute().unwrap();
+        instance.post(&signal("ready")).unwrap();
+        instance.post_with(&signal("order"), payload(50)).unwrap();
+        instance.post(&signal("order")).unwrap();
+        assert!(instance.is_in_state(&open));
+        assert_eq!(totals.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wildcard_takes_disabled_events() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let fraud = add_state(region, "fraud", None);
+        let locked = add_state(region, "locked", None);
+        let caught = add_state(region, "caught", None);
+        add_transition(region, &initial, &idle, None);
+        let reason: FieldPath = "reason".parse().unwrap();
+        let mut cancelled = Trigger::signal("cancelled");
+        cancelled.add_payload_predicate(PayloadPredicate::new(
+            reason.clone(),
+            ComparisonOperator::Equal,
+            "fraud".into(),
+        ));
+        let mut transition = Transition::within(idle.clone(), fraud.clone(), region.id().clone());
+        transition.add_trigger(cancelled);
+        region.add_transition(transition);
+        let mut transition = Transition::within(idle.clone(), locked, region.id().clone());
+        transition.add_trigger(Trigger::signal("locked"));
+        transition.set_guard(Box::new(TotalIs(None, 100)));
+        region.add_transition(transition);
+        let mut transition = Transition::within(idle.clone(), caught.clone(), region.id().clone());
+        transition.add_trigger(Trigger::any());
+        region.add_transition(transition);
+        add_transition(region, &caught, &idle, Some("reset"));
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        let payload = |value: &str| {
+            let payload = Context::default();
+            payload.insert(reason.clone(), value.into());
+            payload
+        };
+        instance
+            .post_with(&signal("cancelled"), payload("customer"))
+            .unwrap();
+        assert_eq!(instance.active_states(), vec![caught.clone()]);
+        instance.post(&signal("reset")).unwrap();
+        instance.post(&signal("locked")).unwrap();
+        assert_eq!(instance.active_states(), vec![caught]);
+        instance.post(&signal("reset")).unwrap();
+        instance
+            .post_with(&signal("cancelled"), payload("fraud"))
+            .unwrap();
+        assert_eq!(instance.active_states(), vec![fraud]);
+    }
+
+    #[test]
+    fn test_bounded_queue() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        add_transition(region, &initial, &idle, None);
+        let machine = Arc::new(machine);
+
+        let dropped: Arc<Mutex<Vec<Event>>> = Default::default();
+        struct Dropped(Arc<Mutex<Vec<Event>>>);
+        impl ExecutionObserver for Dropped {
+            fn on_event_dropped(&self, _: &StateMachineInstance, event: &Event) {
+                self.0.lock().unwrap().push(event.clone())
+            }
+        }
+        let paused = |overflow_policy: OverflowPolicy| {
+            let instance = StateMachineInstance::new(machine.clone())
+                .unwrap()
+                .with_queue_capacity(2, overflow_policy);
+            instance.add_observer(Arc::new(Dropped(dropped.clone())));
+            instance.execute().unwrap();
+            instance.pause().unwrap();
+            instance.post(&signal("a")).unwrap();
+            instance.post(&signal("b")).unwrap();
+            instance
+        };
+
+        let instance = paused(OverflowPolicy::Error);
+        assert!(!instance.is_blocked());
+        assert!(matches!(
+            instance.post(&signal("c")).unwrap_err().kind(),
+            ErrorKind::QueueFull(2)
+        ));
+        assert_eq!(instance.queued_events(), vec![signal("a"), signal("b")]);
+        assert!(instance.is_active());
+
+        let instance = paused(OverflowPolicy::DropNewest);
+        instance.post(&signal("c")).unwrap();
+        assert_eq!(instance.queued_events(), vec![signal("a"), signal("b")]);
+        assert_eq!(
+            dropped.lock().unwrap().drain(..).collect::<Vec<Event>>(),
+            vec![signal("c")]
+        );
+
+        let instance = paused(OverflowPolicy::DropOldest);
+        instance.post(&signal("c")).unwrap();
+        assert_eq!(instance.queued_events(), vec![signal("b"), signal("c")]);
+        assert_eq!(
+            dropped.lock().unwrap().drain(..).collect::<Vec<Event>>(),
+            vec![signal("a")]
+        );
+        assert_eq!(instance.stats().events_dropped(), 1);
+
+        let instance = paused(OverflowPolicy::Block);
+        assert!(instance.is_blocked());
+        for _ in 0..1000 {
+            assert!(matches!(
+                instance.post(&signal("c")).unwrap_err().kind(),
+                ErrorKind::QueueBlocked(event) if event == &signal("c")
+            ));
+        }
+        assert_eq!(instance.queued_events(), vec![signal("a"), signal("b")]);
+        assert!(dropped.lock().unwrap().is_empty());
+        instance.resume().unwrap();
+        assert!(!instance.is_blocked());
+        instance.post(&signal("c")).unwrap();
+        assert_eq!(instance.stats().events_processed(), 3);
+    }
+
+    #[test]
+    fn test_bounded_queue_internal_events() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        add_transition(region, &initial, &idle, None);
+        let machine = Arc::new(machine);
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        struct Dropped(Arc<AtomicUsize>);
+        impl ExecutionObserver for Dropped {
+            fn on_event_dropped(&self, _: &StateMachineInstance, _: &Event) {
+                let _ = self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let ticked = |overflow_policy: OverflowPolicy| {
+            let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+            let instance = StateMachineInstance::new(machine.clone())
+                .unwrap()
+                .with_clock(clock.clone())
+                .with_queue_capacity(1, overflow_policy);
+            instance.add_observer(Arc::new(Dropped(dropped.clone())));
+            instance.execute().unwrap();
+            for name in &["a", "b", "c"] {
+                let _ = instance
+                    .send_after(Duration::from_secs(1), &signal(name))
+                    .unwrap();
+            }
+            clock.advance(Duration::from_secs(2));
+            (instance.tick(), instance)
+        };
+
+        // the delayed sends due at a tick are limited as posted events are
+        let (result, instance) = ticked(OverflowPolicy::Error);
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            ErrorKind::QueueFull(1)
+        ));
+        assert!(instance.is_active());
+        assert_eq!(instance.stats().events_processed(), 1);
+        let (result, _) = ticked(OverflowPolicy::Block);
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            ErrorKind::QueueFull(1)
+        ));
+        let before = dropped.load(Ordering::SeqCst);
+        let (result, instance) = ticked(OverflowPolicy::DropNewest);
+        assert!(result.is_ok());
+        // two of the sends were dropped, and the one dispatched enabled no transition
+        assert_eq!(dropped.load(Ordering::SeqCst) - before, 3);
+        assert_eq!(instance.stats().events_processed(), 1);
+    }
+
+    #[test]
+    fn test_bounded_queue_deferred_events() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut busy = State::within(region.id().clone());
+        busy.add_deferrable_trigger(Trigger::signal("job"));
+        let busy = {
+            let id = busy.id().clone();
+            region.add_state(busy);
+            id
+        };
+        let idle = add_state(region, "idle", None);
+        let working = add_state(region, "working", None);
+        add_transition(region, &initial, &busy, None);
+        add_transition(region, &busy, &idle, Some("ready"));
+        add_transition(region, &idle, &working, Some("job"));
+
+        let instance = StateMachineInstance::new(Arc::new(machine))
+            .unwrap()
+            .with_queue_capacity(3, OverflowPolicy::Error);
+        instance.execute().unwrap();
+        instance.post(&signal("job")).unwrap();
+        instance.post(&signal("job")).unwrap();
+        instance.pause().unwrap();
+        instance.post(&signal("ready")).unwrap();
+        // deferred events count towards the capacity
+        assert!(matches!(
+            instance.post(&signal("other")).unwrap_err().kind(),
+            ErrorKind::QueueFull(3)
+        ));
+
+        // deferred events recalled to the queue are exempt, having been counted
+        instance.resume().unwrap();
+        assert!(instance.deferred_events().is_empty());
+        assert!(instance.is_in_state(&working));
+        assert!(!instance.is_in_state(&busy));
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let working = add_state(region, "working", None);
+        let stalled = add_state(region, "stalled", None);
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &working, Some("job"));
+        add_transition(region, &stalled, &idle, Some("finished"));
+        let mut timeout = Transition::within(working.clone(), stalled.clone(), region.id().clone());
+        timeout.add_trigger(Trigger::after(Duration::from_secs(10)));
+        region.add_transition(timeout);
+        let machine = Arc::new(machine);
+
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let instance = StateMachineInstance::new(machine.clone())
+            .unwrap()
+            .with_clock(clock.clone());
+        assert!(instance.pause().is_err());
+        instance.execute().unwrap();
+        instance.post(&signal("job")).unwrap();
+        instance.pause().unwrap();
+        assert!(instance.is_paused());
+        assert!(instance.is_active());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(instance.tick().unwrap(), 0);
+        assert!(instance.is_in_state(&working));
+        instance.resume().unwrap();
+        assert!(!instance.is_paused());
+        assert_eq!(instance.tick().unwrap(), 1);
+        assert!(instance.is_in_state(&stalled));
+
+        instance.pause().unwrap();
+        instance.post(&signal("finished")).unwrap();
+        instance.post(&signal("job")).unwrap();
+        assert!(instance.is_in_state(&stalled));
+        assert_eq!(
+            instance.queued_events(),
+            vec![signal("finished"), signal("job")]
+        );
+
+        let snapshot = instance.snapshot();
+        #[cfg(feature = "format-native")]
+        assert_eq!(
+            InstanceSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap(),
+            snapshot
+        );
+        let restored = StateMachineInstance::restore(machine, &snapshot).unwrap();
+        assert!(restored.is_paused());
+
+        instance.resume().unwrap();
+        assert!(instance.queued_events().is_empty());
+        assert!(instance.is_in_state(&working));
+    }
+
+    #[test]
+    fn test_timers_are_cancelled_on_exit() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let waiting = add_state(region, "waiting", None);
+        let retrying = add_state(region, "retrying", None);
+        let failed = add_state(region, "failed", None);
+        add_transition(region, &initial, &waiting, None);
+        add_transition(region, &waiting, &retrying, Some("retry"));
+        add_transition(region, &retrying, &waiting, Some("back"));
+        let mut timeout = Transition::within(waiting.clone(), failed.clone(), region.id().clone());
+        timeout.add_trigger(Trigger::after(Duration::from_secs(10)));
+        region.add_transition(timeout);
+
+        let start = SystemTime::UNIX_EPOCH;
+        let clock = Arc::new(ManualClock::new(start));
+        let instance = StateMachineInstance::new(Arc::new(machine))
+            .unwrap()
+            .with_clock(clock.clone());
+        instance.execute().unwrap();
+        assert_eq!(instance.next_timer(), Some(start + Duration::from_secs(10)));
+
+        clock.advance(Duration::from_secs(6));
+        instance.post(&signal("retry")).unwrap();
+        assert_eq!(instance.next_timer(), None);
+        instance.post(&signal("back")).unwrap();
+        assert_eq!(instance.next_timer(), Some(start + Duration::from_secs(16)));
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(instance.tick().unwrap(), 0);
+        assert!(instance.is_in_state(&waiting));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(instance.tick().unwrap(), 1);
+        assert_eq!(instance.active_states(), vec![failed]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let busy = add_state(region, "busy", None);
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &busy, Some("go"));
+        region.new_timeout(busy.clone(), Duration::from_secs(10), idle.clone());
+
+        let recorder = Arc::new(TraceRecorder::default());
+        let instance = StateMachineInstance::new(Arc::new(machine))
+            .unwrap()
+            .with_clock(Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH)));
+        instance.add_observer(recorder.clone());
+        let id = instance.id().clone();
+        instance.execute().unwrap();
+        instance.post(&signal("go")).unwrap();
+        let _ = instance
+            .send_after(Duration::from_secs(5), &signal("go"))
+            .unwrap();
+        let total: FieldPath = "total".parse().unwrap();
+        instance
+            .context()
+            .insert(total.clone(), FieldValue::Integer(3));
+        assert!(instance.next_timer().is_some());
+
+        instance.reset().unwrap();
+        assert!(instance.is_new());
+        assert_eq!(instance.id(), &id);
+        assert!(instance.active_states().is_empty());
+        assert_eq!(instance.next_timer(), None);
+        assert_eq!(instance.context().get(total.clone()), None);
+        assert_eq!(instance.stats().transitions_fired(), 0);
+        assert!(instance.post(&signal("go")).is_err());
+
+        let context = Context::default();
+        context.insert(total.clone(), FieldValue::Integer(7));
+        instance.reset_with_context(context).unwrap();
+        instance.execute().unwrap();
+        assert_eq!(instance.active_states(), vec![idle]);
+        assert_eq!(instance.context().get(total), Some(FieldValue::Integer(7)));
+        assert_eq!(recorder.trace().len(), 3);
+    }
+
+    #[test]
+    fn test_delayed_sends() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let waiting = add_state(region, "waiting", None);
+        let retrying = add_state(region, "retrying", None);
+        let done = region.new_final_state();
+        add_transition(region, &initial, &waiting, None);
+        add_transition(region, &waiting, &retrying, Some("retry"));
+        add_transition(region, &retrying, &done, Some("give-up"));
+        let machine = Arc::new(machine);
+
+        let start = SystemTime::UNIX_EPOCH;
+        let clock = Arc::new(ManualClock::new(start));
+        let instance = StateMachineInstance::new(machine.clone())
+            .unwrap()
+            .with_clock(clock.clone());
+        instance.execute().unwrap();
+        let retry = instance
+            .send_after(Duration::from_secs(5), &signal("retry"))
+            .unwrap();
+        let early = instance
+            .send_after(Duration::from_secs(3), &signal("retry"))
+            .unwrap();
+        assert_ne!(retry, early);
+        assert!(instance.cancel(early));
+        assert!(!instance.cancel(early));
+        assert_eq!(instance.next_timer(), Some(start + Duration::from_secs(5)));
+
+        // delayed sends are not cancelled by leaving the state they were sent from.
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(instance.tick().unwrap(), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(instance.tick().unwrap(), 1);
+        assert!(instance.is_in_state(&retrying));
+        assert!(!instance.cancel(retry));
+
+        let payload = Context::default();
+        payload.insert(
+            "attempt".parse::<FieldPath>().unwrap(),
+            FieldValue::Integer(2),
+        );
+        let give_up = instance
+            .send_after_with(Duration::from_secs(10), &signal("give-up"), payload)
+            .unwrap();
+        let snapshot = instance.snapshot();
+        #[cfg(feature = "format-native")]
+        assert_eq!(
+            InstanceSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap(),
+            snapshot
+        );
+        let restored = StateMachineInstance::restore(machine, &snapshot)
+            .unwrap()
+            .with_clock(clock.clone());
+        assert_eq!(restored.next_timer(), Some(start + Duration::from_secs(15)));
+        let later = restored
+            .send_after(Duration::from_secs(20), &signal("retry"))
+            .unwrap();
+        assert!(![retry, early, give_up].contains(&later));
+
+        // pending sends are cancelled once the instance completes.
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(restored.tick().unwrap(), 1);
+        assert!(restored.is_done());
+        assert_eq!(restored.next_timer(), None);
+        assert!(!restored.cancel(later));
+        assert!(restored
+            .send_after(Duration::from_secs(1), &signal("retry"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_change_events() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let open = add_state(region, "open", None);
+        let full = add_state(region, "full", None);
+        add_transition(region, &initial, &open, None);
+        let mut transition = Transition::within(open.clone(), full.clone(), region.id().clone());
+        transition.add_trigger(Trigger::when(Arc::new(TotalIs(None, 100))));
+        region.add_transition(transition);
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        let total: FieldPath = "total".parse().unwrap();
+        instance
+            .update_context(|context| context.insert(total.clone(), FieldValue::Integer(10)))
+            .unwrap();
+        assert!(instance.is_in_state(&open));
+        instance
+            .update_context(|context| context.insert(total.clone(), FieldValue::Integer(100)))
+            .unwrap();
+        assert_eq!(instance.active_states(), vec![full]);
+    }
+
+    #[test]
+    fn test_raised_events_are_dispatched_before_queued_events() {
+        struct Poster;
+        impl ExecutionObserver for Poster {
+            fn on_transition_fired(
+                &self,
+                instance: &StateMachineInstance,
+                transition: &Transition,
+            ) {
+                if transition
+                    .triggers()
+                    .any(|trigger| trigger == &Trigger::signal("go"))
+                {
+                    instance.post(&signal("posted")).unwrap();
+                }
+            }
+        }
+        let mut bindings = Bindings::default();
+        bindings.bind("start", move |_, _, events| events.raise(signal("raised")));
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let started = add_state(region, "started", None);
+        let raised = add_state(region, "raised", None);
+        let posted = add_state(region, "posted", None);
+        let skipped = add_state(region, "skipped", None);
+        add_transition(region, &initial, &idle, None);
+        let mut transition = Transition::within(idle.clone(), started.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("go"));
+        transition.set_effect(bindings.resolve(OpaqueBehavior::new("start", None), &["start"]));
+        region.add_transition(transition);
+        add_transition(region, &started, &raised, Some("raised"));
+        add_transition(region, &raised, &posted, Some("posted"));
+        add_transition(region, &started, &skipped, Some("posted"));
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.add_observer(Arc::new(Poster));
+        instance.execute().unwrap();
+        instance.post(&signal("go")).unwrap();
+
+        assert_eq!(instance.active_states(), vec![posted]);
+        assert!(instance.queued_events().is_empty());
+    }
+
+    #[test]
+    fn test_events_posted_during_a_step_are_queued() {
+        #[derive(Default)]
+        struct Poster {
+            posts_from: Vec<ID>,
+            entered: Mutex<Vec<String>>,
+        }
+        impl ExecutionObserver for Poster {
+            fn on_state_entered(&self, instance: &StateMachineInstance, state: &ID) {
+                if self.posts_from.contains(state) {
+                    self.entered
+                        .lock()
+                        .unwrap()
+                        .push(format!("{}", instance.queued_events().len()));
+                    instance.post(&signal("next")).unwrap();
+                    instance.post(&signal("next")).unwrap();
+                }
+            }
+        }
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let first = add_state(region, "first", None);
+        let second = add_state(region, "second", None);
+        let third = add_state(region, "third", None);
+        add_transition(region, &initial, &first, None);
+        add_transition(region, &first, &second, Some("next"));
+        add_transition(region, &second, &third, Some("next"));
+
+        let poster = Arc::new(Poster {
+            posts_from: vec![first, second],
+            ..Default::default()
+        });
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.add_observer(poster.clone());
+        instance.execute().unwrap();
+
+        assert_eq!(instance.active_states(), vec![third]);
+        assert!(instance.queued_events().is_empty());
+        assert_eq!(
+            *poster.entered.lock().unwrap(),
+            vec!["0".to_string(), "1".to_string()]
+        );
+        assert!(instance.is_active());
+    }
+
+    #[test]
+    fn test_events_sent_to_this_instance() {
+        let mut bindings = Bindings::default();
+        bindings.bind("send", |_, _, events| {
+            events.send(SendTarget::This, signal("later"), Delivery::Queued);
+            events.send(SendTarget::This, signal("next"), Delivery::Immediate);
+            events.send(SendTarget::Parent, signal("dropped"), Delivery::Immediate);
+        });
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let first = add_state(
+            region,
+            "first",
+            Some(bindings.resolve(OpaqueBehavior::new("send", None), &["send"])),
+        );
+        let second = add_state(region, "second", None);
+        add_transition(region, &initial, &first, None);
+        add_transition(region, &first, &second, Some("next"));
+
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.execute().unwrap();
+        assert_eq!(instance.active_states(), vec![second]);
+        let sent = instance.take_sent_events();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].target(), instance.id());
+        assert_eq!(sent[0].event(), &signal("later"));
+        assert_eq!(sent[0].delivery(), Delivery::Queued);
+        assert!(instance.take_sent_events().is_empty());
+    }
+
+    #[test]
+    fn test_instances_of_a_shared_machine_on_threads() {
+        fn is_send_and_sync<T: Send + Sync>() {}
+        fn is_send<T: Send>() {}
+        is_send_and_sync::<StateMachine>();
+        is_send::<StateMachineInstance>();
+
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = add_state(region, "idle", None);
+        let done = region.new_final_state();
+        add_transition(region, &initial, &idle, None);
+        add_transition(region, &idle, &done, Some("stop"));
+        let machine = Arc::new(machine);
+
+        let workers: Vec<std::thread::JoinHandle<bool>> = (0..4)
+            .map(|_| {
+                let machine = machine.clone();
+                std::thread::spawn(move || {
+                    let instance = StateMachineInstance::new(machine).unwrap();
+                    instance.execute().unwrap();
+                    instance.post(&signal("stop")).unwrap();
+                    instance.is_done()
+                })
+            })
+            .collect();
+        assert!(workers.into_iter().all(|worker| worker.join().unwrap()));
+
+        // an instance may be moved to, and driven by, another thread.
+        let instance = StateMachineInstance::new(machine).unwrap();
+        instance.execute().unwrap();
+        let instance = std::thread::spawn(move || {
+            instance.post(&signal("stop")).unwrap();
+            instance
+        })
+        .join()
+        .unwrap();
+        assert!(instance.is_done());
+    }
+}
@@ -0,0 +1,419 @@
+/*!
+Invoke handlers start the external services declared by the states of a machine, see
+`definition::types::Invoke`, as for the SCXML `<invoke>` element and xstate services.
+
+An instance is given a handler for each kind of service with
+`StateMachineInstance::with_invoke_handler`. When a state is entered each of its invokes is
+started, in order, by the handler for its kind, which is given an `Invocation`, with the values
+of the invoke's parameters taken from the context, and an `InvokeHandle`. The handler, or
+whatever it starts, reports the outcome through the handle; once it has, the instance posts the
+invoke's `done.invoke.<id>` event, or its `error.invoke.<id>` event where the service failed, as
+an external event. Where there is no handler for an invoke's kind its error event is posted
+immediately. As for do-activities the instance is synchronous, it notices that a service has
+completed when an event is next posted, or when `StateMachineInstance::tick` is called. If the
+state is exited first the handle is cancelled, and its outcome is ignored.
+
+A service may instead respond with a `Message`, such as the output of a process or the response
+to an HTTP request, which a `MessageMapper` maps to the outcome reported; `StatusMapper` posts the
+done or error event, as the message reports success or failure, with the message as its
+payload. The handlers in `execution::services` start external processes and HTTP requests this
+way.
+
+Invocations are not recorded by `StateMachineInstance::snapshot`, an instance restored from a
+snapshot does not receive the outcome of any service that was running when it was taken.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::invoke::{Invocation, InvokeHandle};
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("checkout");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let mut paying = State::within(region.id().clone());
+let invoke = Invoke::new("payment");
+paying.add_invoke(invoke.clone());
+let paying_id = paying.id().clone();
+region.add_state(paying);
+let paid = region.new_final_state();
+region.new_transition(initial, paying_id.clone());
+let mut transition = Transition::within(paying_id, paid, region.id().clone());
+transition.add_trigger(Trigger::with_event(invoke.done_event()));
+region.add_transition(transition);
+
+let instance = StateMachineInstance::new(Arc::new(machine))
+    .unwrap()
+    .with_invoke_handler("payment", |_: &Invocation, handle: InvokeHandle| handle.complete());
+instance.execute().unwrap();
+assert!(instance.is_done());
+```
+
+*/
+
+use crate::core::context::{Compound, FieldPath};
+use crate::core::{Context, FieldValue, ID};
+use crate::definition::types::Event;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// ------------------------------------------------------------------------------------------------
+// Public Traits
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Starts the services of one kind; a handler may be shared by instances on different threads.
+///
+pub trait InvokeHandler: Send + Sync {
+    ///
+    /// Start the service described by `invocation`, its outcome must be reported to `handle`,
+    /// either before returning or later, from elsewhere.
+    ///
+    fn start(&self, invocation: &Invocation, handle: InvokeHandle);
+}
+
+///
+/// Maps the message a service responded with to the outcome of its invocation, for example to
+/// raise an event of the machine's own with fields of the message as its payload.
+///
+pub trait MessageMapper: Send + Sync {
+    fn map(&self, invocation: &Invocation, message: &Message) -> InvokeOutcome;
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A service being started by an `InvokeHandler`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Invocation {
+    pub(crate) instance: ID,
+    pub(crate) state: ID,
+    pub(crate) invoke: ID,
+    pub(crate) kind: String,
+    pub(crate) source: Option<String>,
+    pub(crate) params: HashMap<String, FieldValue>,
+    pub(crate) done_event: Event,
+    pub(crate) error_event: Event,
+}
+
+///
+/// How a service ended, as reported to its `InvokeHandle`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum InvokeOutcome {
+    Done,
+    Failed(String),
+    /// The service ended by raising an event, with an optional payload, in place of its done or
+    /// error event.
+    Raised(Event, Option<Context>),
+}
+
+///
+/// A message received from an external service, such as its response.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Message {
+    /// Whether the service reported success, such as a zero exit status or a 2xx HTTP status.
+    pub success: bool,
+    /// The status the service reported, such as an exit status or an HTTP status code.
+    pub status: Option<i64>,
+    /// Any named values sent with the message, such as HTTP headers.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+///
+/// Maps a message to the invoke's done event where it reports success, and otherwise to its
+/// error event, with the message's `status`, where there is one, and `body` as the payload.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StatusMapper;
+
+///
+/// Shared between a running service and the instance that invoked it; the service reports its
+/// outcome, the instance signals cancellation.
+///
+#[derive(Clone, Debug, Default)]
+pub struct InvokeHandle {
+    inner: Arc<InvokeState>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl<F> InvokeHandler for F
+where
+    F: Fn(&Invocation, InvokeHandle) + Send + Sync,
+{
+    fn start(&self, invocation: &Invocation, handle: InvokeHandle) {
+        self(invocation, handle)
+    }
+}
+
+impl<F> MessageMapper for F
+where
+    F: Fn(&Invocation, &Message) -> InvokeOutcome + Send + Sync,
+{
+    fn map(&self, invocation: &Invocation, message: &Message) -> InvokeOutcome {
+        self(invocation, message)
+    }
+}
+
+impl MessageMapper for StatusMapper {
+    fn map(&self, invocation: &Invocation, message: &Message) -> InvokeOutcome {
+        let payload = Context::default();
+        if let Some(status) = message.status {
+            payload.insert(
+                FieldPath::from_str("status").unwrap(),
+                FieldValue::Integer(status),
+            );
+        }
+        payload.insert(
+            FieldPath::from_str("body").unwrap(),
+            FieldValue::String(message.body.clone()),
+        );
+        let event = if message.success {
+            invocation.done_event()
+        } else {
+            invocation.error_event()
+        };
+        InvokeOutcome::Raised(event.clone(), Some(payload))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Invocation {
+    ///
+    /// The ID of the instance that invoked the service.
+    ///
+    pub fn instance(&self) -> &ID {
+        &self.instance
+    }
+
+    ///
+    /// The ID of the state that declares the invoke.
+    ///
+    pub fn state(&self) -> &ID {
+        &self.state
+    }
+
+    ///
+    /// The ID of the invoke, see `Invoke::id`.
+    ///
+    pub fn invoke(&self) -> &ID {
+        &self.invoke
+    }
+
+    pub fn kind(&self) -> &String {
+        &self.kind
+    }
+
+    pub fn source(&self) -> &Option<String> {
+        &self.source
+    }
+
+    ///
+    /// The value of each parameter, taken from the context as the service started; parameters
+    /// whose field was not present in the context are omitted.
+    ///
+    pub fn params(&self) -> &HashMap<String, FieldValue> {
+        &self.params
+    }
+
+    pub fn param(&self, name: &str) -> Option<&FieldValue> {
+        self.params.get(name)
+    }
+
+    ///
+    /// The event posted when the service completes, see `Invoke::done_event`.
+    ///
+    pub fn done_event(&self) -> &Event {
+        &self.done_event
+    }
+
+    ///
+    /// The event posted when the service fails, see `Invoke::error_event`.
+    ///
+    pub fn error_event(&self) -> &Event {
+        &self.error_event
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl InvokeHandle {
+    ///
+    /// Report that the service completed; only the first outcome reported is kept.
+    ///
+    pub fn complete(&self) {
+        self.report(InvokeOutcome::Done)
+    }
+
+    ///
+    /// Report that the service failed, for `reason`; only the first outcome reported is kept.
+    ///
+    pub fn fail(&self, reason: &str) {
+        self.report(InvokeOutcome::Failed(reason.to_string()))
+    }
+
+    ///
+    /// Report that the service ended by raising `event`, with `payload`; only the first outcome
+    /// reported is kept.
+    ///
+    pub fn raise(&self, event: Event, payload: Option<Context>) {
+        self.report(InvokeOutcome::Raised(event, payload))
+    }
+
+    ///
+    /// Report the outcome `mapper` maps `message` to; only the first outcome reported is kept.
+    ///
+    pub fn respond(&self, invocation: &Invocation, message: &Message, mapper: &dyn MessageMapper) {
+        self.report(mapper.map(invocation, message))
+    }
+
+    pub fn outcome(&self) -> Option<InvokeOutcome> {
+        self.inner.outcome.lock().unwrap().clone()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn report(&self, outcome: InvokeOutcome) {
+        let mut current = self.inner.outcome.lock().unwrap();
+        if current.is_none() {
+            *current = Some(outcome)
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+struct InvokeState {
+    cancelled: AtomicBool,
+    outcome: Mutex<Option<InvokeOutcome>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::{Compound, FieldPath};
+    use crate::core::Context;
+    use crate::definition::types::{
+        Event, HasRegions, Identified, Invoke, SignalEvent, State, StateMachine, Trigger,
+    };
+    use crate::execution::fixtures::add_triggered;
+    use crate::execution::types::StateMachineInstance;
+
+    #[test]
+    fn test_invoked_services() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut fetching = State::within(region.id().clone());
+        let mut fetch = Invoke::new("http");
+        fetch.set_source("https://example.com/orders");
+        fetch.add_param("order", "order".parse::<FieldPath>().unwrap());
+        fetch.add_param("missing", "missing".parse::<FieldPath>().unwrap());
+        fetching.add_invoke(fetch.clone());
+        let unhandled = Invoke::new("unknown");
+        fetching.add_invoke(unhandled.clone());
+        let fetching_id = fetching.id().clone();
+        region.add_state(fetching);
+        let fetched = region.new_simple_state();
+        let failed = region.new_simple_state();
+        region.new_transition(initial, fetching_id.clone());
+        add_triggered(
+            region,
+            &fetching_id,
+            &fetched,
+            Trigger::with_event(fetch.done_event()),
+        );
+        add_triggered(
+            region,
+            &fetching_id,
+            &failed,
+            Trigger::with_event(fetch.error_event()),
+        );
+        add_triggered(region, &fetched, &fetching_id, Trigger::signal("retry"));
+        add_triggered(region, &failed, &fetching_id, Trigger::signal("retry"));
+        add_triggered(region, &fetching_id, &fetched, Trigger::signal("skip"));
+
+        let started: Arc<Mutex<Vec<(Invocation, InvokeHandle)>>> = Default::default();
+        let handles = started.clone();
+        let context = Context::default();
+        context.insert(
+            "order".parse::<FieldPath>().unwrap(),
+            FieldValue::Integer(42),
+        );
+        let instance = StateMachineInstance::with_context(Arc::new(machine), context)
+            .unwrap()
+            .with_invoke_handler("http", move |invocation: &Invocation, handle| {
+                handles.lock().unwrap().push((invocation.clone(), handle))
+            });
+        instance.execute().unwrap();
+
+        // the service without a handler fails, but its error event triggers no transition.
+        assert!(instance.is_in_state(&fetching_id));
+        let (invocation, handle) = started.lock().unwrap()[0].clone();
+        assert_eq!(invocation.instance(), instance.id());
+        assert_eq!(invocation.state(), &fetching_id);
+        assert_eq!(invocation.invoke(), fetch.id());
+        assert_eq!(
+            invocation.source(),
+            &Some("https://example.com/orders".to_string())
+        );
+        assert_eq!(invocation.param("order"), Some(&FieldValue::Integer(42)));
+        assert_eq!(invocation.params().len(), 1);
+
+        assert_eq!(instance.tick().unwrap(), 0);
+        assert!(instance.is_in_state(&fetching_id));
+        handle.complete();
+        handle.fail("too late");
+        assert_eq!(handle.outcome(), Some(InvokeOutcome::Done));
+        let _ = instance.tick().unwrap();
+        assert!(instance.is_in_state(&fetched));
+
+        instance
+            .post(&Event::Signal(SignalEvent::new("retry")))
+            .unwrap();
+        let (_, handle) = started.lock().unwrap()[1].clone();
+        handle.fail("unavailable");
+        let _ = instance.tick().unwrap();
+        assert!(instance.is_in_state(&failed));
+
+        // leaving the state cancels the service, and its outcome is ignored.
+        instance
+            .post(&Event::Signal(SignalEvent::new("retry")))
+            .unwrap();
+        let (_, handle) = started.lock().unwrap()[2].clone();
+        instance
+            .post(&Event::Signal(SignalEvent::new("skip")))
+            .unwrap();
+        assert!(handle.is_cancelled());
+        handle.fail("cancelled");
+        let _ = instance.tick().unwrap();
+        assert!(instance.is_in_state(&fetched));
+        assert_eq!(started.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,1269 @@
+/*!
+An `InstanceManager` owns many running instances, of the same or of different machines, so that
+the bookkeeping common to most hosts of this crate is not written again for each.
+
+Each instance is addressed by its ID, and optionally also by a name unique within the manager.
+Events are routed to an instance with `InstanceManager::post`, and `InstanceManager::tick` ticks
+the timers of every active instance at once; `InstanceManager::next_timer` returns the time at
+which the next timer of any instance is due, so that a host knows how long it may wait. The
+manager reports how many of its instances are new, active, in error, or done, with
+`InstanceManager::status`, and their combined throughput with `InstanceManager::metrics`.
+
+Instances are kept in the order they were added. Each may be given a priority, with
+`InstanceManager::set_priority`, and instances are started and ticked in order of priority,
+highest first, and otherwise in the order they were added. Each may also be given a deadline,
+with `InstanceManager::set_deadline`, by which it is expected to be done; once an active instance
+is within the manager's SLA warning of its deadline, see `InstanceManager::with_sla_warning`,
+the signal event `SLA_BREACHED`, or the event given with `InstanceManager::with_sla_event`, is
+posted to it, once, as the manager is ticked, so that the machine itself may escalate.
+
+Instances may be tagged with key/value pairs as they are added, with
+`InstanceManager::add_tagged`, or later with `InstanceManager::set_tag`, and the manager
+records when each was added. `InstanceManager::find_matching` then returns the instances that
+match an `InstanceFilter`, by their tags, the labels of their active states, their status, and
+their age; `find`, by contrast, looks up the one instance added with a name. The same filters
+select the instances for the bulk operations `post_to_matching`, `cancel_matching`, and
+`migrate_matching`, each of which is applied to every matching instance, even where it fails
+for an earlier one, and returns the result for each instance with its ID.
+
+The manager delivers the events that the behaviors of its instances send to one another, see
+`EventSink::send`. Each time an instance has run, as the result of `post`, `tick`, or
+`execute_all`, the events it sent for immediate delivery are posted to their targets, and so on
+for any events those targets then send; events sent for queued delivery are held by the manager
+until `InstanceManager::deliver_queued` is called, as it is by each `tick`. Where the target of
+an event is not managed, or is not active, the signal event `ERROR_COMMUNICATION` is posted back
+to the instance that sent it. Where the queue of the target is full, and its `OverflowPolicy` is
+`Block`, the event is held by the manager, as for queued delivery, until there is room.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::manager::InstanceManager;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+let open = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+let mut transition = Transition::within(closed.clone(), open.clone(), region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+let machine = Arc::new(machine);
+
+let mut manager = InstanceManager::default();
+let front = manager
+    .add_named("front", StateMachineInstance::new(machine.clone()).unwrap())
+    .unwrap();
+let _ = manager
+    .add_named("back", StateMachineInstance::new(machine).unwrap())
+    .unwrap();
+manager.execute_all().unwrap();
+
+manager.post(&front, &Event::Signal(SignalEvent::new("push"))).unwrap();
+assert!(manager.get(&front).unwrap().is_in_state(&open));
+assert!(manager.find("back").unwrap().is_in_state(&closed));
+assert_eq!(manager.status().active(), 2);
+```
+
+*/
+
+use crate::core::{Context, ID};
+use crate::definition::types::{Delivery, Event, Labeled, SignalEvent, StateMachine};
+use crate::error::{ErrorKind, Result};
+use crate::execution::stats::ThroughputMetrics;
+use crate::execution::types::{SentEvent, StateMachineInstance, ERROR_COMMUNICATION};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Owns a set of running instances, addressed by ID or by name.
+///
+#[derive(Debug, Default)]
+pub struct InstanceManager {
+    instances: HashMap<ID, StateMachineInstance>,
+    order: Vec<ID>,
+    names: HashMap<String, ID>,
+    /// The priority of each instance given one, the others have the priority 0.
+    priorities: HashMap<ID, i32>,
+    deadlines: HashMap<ID, SystemTime>,
+    tags: HashMap<ID, HashMap<String, String>>,
+    /// When each instance was added, by its own clock.
+    added: HashMap<ID, SystemTime>,
+    /// The instances the SLA event has been posted to, since their deadline was last set.
+    breached: RefCell<HashSet<ID>>,
+    /// How long before its deadline the SLA event is posted to an instance.
+    sla_warning: Duration,
+    /// The event posted as an instance approaches its deadline, where not `SLA_BREACHED`.
+    sla_event: Option<Event>,
+    /// Events sent for queued delivery, with the ID of the instance that sent each.
+    queued: RefCell<VecDeque<(ID, SentEvent)>>,
+}
+
+///
+/// The name of the signal event posted to an instance as it approaches its deadline, unless the
+/// manager is given another with `InstanceManager::with_sla_event`.
+///
+pub const SLA_BREACHED: &str = "sla.breached";
+
+///
+/// The execution state of a managed instance, as counted by `InstanceManager::status`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstanceStatus {
+    NotStarted,
+    Active,
+    InError,
+    Done,
+}
+
+///
+/// Selects managed instances, see `InstanceManager::find_matching`; an instance matches where it
+/// matches every criterion given, and so every instance matches the default filter.
+///
+#[derive(Clone, Debug, Default)]
+pub struct InstanceFilter {
+    tags: Vec<(String, String)>,
+    state_label: Option<String>,
+    status: Option<InstanceStatus>,
+    older_than: Option<Duration>,
+    younger_than: Option<Duration>,
+}
+
+///
+/// The number of managed instances in each execution state, as returned by
+/// `InstanceManager::status`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ManagerStatus {
+    not_started: usize,
+    active: usize,
+    in_error: usize,
+    done: usize,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl InstanceManager {
+    ///
+    /// Post the SLA event to each active instance once it is within `warning` of its deadline,
+    /// rather than once the deadline has passed.
+    ///
+    pub fn with_sla_warning(self, warning: Duration) -> Self {
+        Self {
+            sla_warning: warning,
+            ..self
+        }
+    }
+
+    ///
+    /// Post `event`, rather than the signal event `SLA_BREACHED`, to each active instance as it
+    /// approaches its deadline.
+    ///
+    pub fn with_sla_event(self, event: Event) -> Self {
+        Self {
+            sla_event: Some(event),
+            ..self
+        }
+    }
+
+    ///
+    /// Add `instance`, returning its ID; it is an error where an instance with the same ID is
+    /// already managed.
+    ///
+    pub fn add(&mut self, instance: StateMachineInstance) -> Result<ID> {
+        let id = instance.id().clone();
+        if self.instances.contains_key(&id) {
+            return Err(ErrorKind::DuplicateInstance(id.to_string()).into());
+        }
+        self.order.push(id.clone());
+        let _ = self.added.insert(id.clone(), instance.now());
+        let _ = self.instances.insert(id.clone(), instance);
+        Ok(id)
+    }
+
+    ///
+    /// Add `instance`, tagged with each of `tags`, as `add`.
+    ///
+    pub fn add_tagged<'a>(
+        &mut self,
+        instance: StateMachineInstance,
+        tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<ID> {
+        let id = self.add(instance)?;
+        for (key, value) in tags {
+            self.set_tag(&id, key, value)?;
+        }
+        Ok(id)
+    }
+
+    ///
+    /// Add `instance`, addressable also by `name`; it is an error where an instance with the same
+    /// ID, or the same name, is already managed.
+    ///
+    pub fn add_named(&mut self, name: &str, instance: StateMachineInstance) -> Result<ID> {
+        if self.names.contains_key(name) {
+            return Err(ErrorKind::DuplicateInstance(name.to_string()).into());
+        }
+        let id = self.add(instance)?;
+        let _ = self.names.insert(name.to_string(), id.clone());
+        Ok(id)
+    }
+
+    ///
+    /// Remove, and return, the instance with the ID `id`.
+    ///
+    pub fn remove(&mut self, id: &ID) -> Option<StateMachineInstance> {
+        let instance = self.instances.remove(id)?;
+        self.order.retain(|managed| managed != id);
+        self.names.retain(|_, managed| managed != id);
+        let _ = self.priorities.remove(id);
+        let _ = self.deadlines.remove(id);
+        let _ = self.breached.get_mut().remove(id);
+        let _ = self.tags.remove(id);
+        let _ = self.added.remove(id);
+        Some(instance)
+    }
+
+    ///
+    /// Remove, and return, every instance that is done.
+    ///
+    pub fn remove_done(&mut self) -> Vec<StateMachineInstance> {
+        let done: Vec<ID> = self
+            .instances()
+            .filter(|instance| instance.is_done())
+            .map(|instance| instance.id().clone())
+            .collect();
+        done.iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    pub fn get(&self, id: &ID) -> Option<&StateMachineInstance> {
+        self.instances.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &ID) -> Option<&mut StateMachineInstance> {
+        self.instances.get_mut(id)
+    }
+
+    ///
+    /// The instance added with the name `name`.
+    ///
+    pub fn find(&self, name: &str) -> Option<&StateMachineInstance> {
+        self.names.get(name).and_then(|id| self.get(id))
+    }
+
+    ///
+    /// The managed instances that match `filter`, in the order they were added.
+    ///
+    pub fn find_matching(&self, filter: &InstanceFilter) -> Vec<&StateMachineInstance> {
+        self.instances()
+            .filter(|instance| self.matches(filter, instance))
+            .collect()
+    }
+
+    ///
+    /// Post `event` to each instance that matches `filter`, see `post`.
+    ///
+    pub fn post_to_matching(
+        &self,
+        filter: &InstanceFilter,
+        event: &Event,
+    ) -> Vec<(ID, Result<()>)> {
+        self.matching_ids(filter)
+            .into_iter()
+            .map(|id| {
+                let result = self.post(&id, event);
+                (id, result)
+            })
+            .collect()
+    }
+
+    ///
+    /// Terminate each instance that matches `filter`, see `StateMachineInstance::terminate`; the
+    /// instances remain managed, as done, until removed.
+    ///
+    pub fn cancel_matching(&self, filter: &InstanceFilter) -> Vec<(ID, Result<()>)> {
+        self.matching_ids(filter)
+            .into_iter()
+            .map(|id| {
+                let result = self.instance(&id).and_then(StateMachineInstance::terminate);
+                let result = first_error(result, self.route(&id));
+                (id, result)
+            })
+            .collect()
+    }
+
+    ///
+    /// Migrate each instance that matches `filter` to `chart`, moving its states as given by
+    /// `map`, see `StateMachineInstance::migrate_to`. An instance that cannot be migrated is left
+    /// as it was.
+    ///
+    pub fn migrate_matching(
+        &mut self,
+        filter: &InstanceFilter,
+        chart: Arc<StateMachine>,
+        map: &HashMap<ID, ID>,
+    ) -> Vec<(ID, Result<()>)> {
+        self.matching_ids(filter)
+            .into_iter()
+            .map(|id| {
+                let result = match self.instances.get_mut(&id) {
+                    None => Err(ErrorKind::UnknownInstance(id.to_string()).into()),
+                    Some(instance) => instance.migrate_to(chart.clone(), map),
+                };
+                (id, result)
+            })
+            .collect()
+    }
+
+    ///
+    /// Tag the instance with the ID `id` with `key`, replacing any value it was tagged with.
+    ///
+    pub fn set_tag(&mut self, id: &ID, key: &str, value: &str) -> Result<()> {
+        let _ = self.instance(id)?;
+        let _ = self
+            .tags
+            .entry(id.clone())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    ///
+    /// The value the instance with the ID `id` is tagged with for `key`, if any.
+    ///
+    pub fn tag_of(&self, id: &ID, key: &str) -> Option<&str> {
+        self.tags
+            .get(id)
+            .and_then(|tags| tags.get(key))
+            .map(String::as_str)
+    }
+
+    ///
+    /// How long ago, by its own clock, the instance with the ID `id` was added.
+    ///
+    pub fn age_of(&self, id: &ID) -> Option<Duration> {
+        let instance = self.get(id)?;
+        Some(
+            instance
+                .now()
+                .duration_since(self.added[id])
+                .unwrap_or_default(),
+        )
+    }
+
+    ///
+    /// The name the instance with the ID `id` was added with, if any.
+    ///
+    pub fn name_of(&self, id: &ID) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, managed)| *managed == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    ///
+    /// The managed instances, in the order they were added.
+    ///
+    pub fn instances(&self) -> impl Iterator<Item = &StateMachineInstance> {
+        self.order.iter().map(move |id| &self.instances[id])
+    }
+
+    ///
+    /// The managed instances in order of priority, highest first, and otherwise in the order
+    /// they were added; the order in which they are started and ticked.
+    ///
+    pub fn prioritized(&self) -> impl Iterator<Item = &StateMachineInstance> {
+        let mut order: Vec<&ID> = self.order.iter().collect();
+        order.sort_by_key(|id| Reverse(self.priorities.get(*id).copied().unwrap_or_default()));
+        order.into_iter().map(move |id| &self.instances[id])
+    }
+
+    ///
+    /// Give the instance with the ID `id` the priority `priority`; instances have the priority 0
+    /// until given another.
+    ///
+    pub fn set_priority(&mut self, id: &ID, priority: i32) -> Result<()> {
+        let _ = self.instance(id)?;
+        let _ = self.priorities.insert(id.clone(), priority);
+        Ok(())
+    }
+
+    ///
+    /// The priority of the instance with the ID `id`, if it is managed.
+    ///
+    pub fn priority_of(&self, id: &ID) -> Option<i32> {
+        self.get(id)
+            .map(|_| self.priorities.get(id).copied().unwrap_or_default())
+    }
+
+    ///
+    /// Expect the instance with the ID `id` to be done by `deadline`, replacing any deadline it
+    /// had; the SLA event is posted to it again as it approaches the new deadline.
+    ///
+    pub fn set_deadline(&mut self, id: &ID, deadline: SystemTime) -> Result<()> {
+        let _ = self.instance(id)?;
+        let _ = self.deadlines.insert(id.clone(), deadline);
+        let _ = self.breached.get_mut().remove(id);
+        Ok(())
+    }
+
+    pub fn deadline_of(&self, id: &ID) -> Option<SystemTime> {
+        self.deadlines.get(id).copied()
+    }
+
+    ///
+    /// Whether the SLA event has been posted to the instance with the ID `id` since its deadline
+    /// was set.
+    ///
+    pub fn is_breached(&self, id: &ID) -> bool {
+        self.breached.borrow().contains(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    ///
+    /// Start every instance that has not yet been started, see `StateMachineInstance::execute`.
+    /// Each is started even where an earlier one fails, the first error is returned.
+    ///
+    pub fn execute_all(&self) -> Result<()> {
+        self.for_each(
+            |instance| instance.is_new(),
+            |instance| instance.execute().map(|_| 0),
+        )
+        .map(|_| ())
+    }
+
+    ///
+    /// Post `event` to the instance with the ID `id`, see `StateMachineInstance::post`.
+    ///
+    pub fn post(&self, id: &ID, event: &Event) -> Result<()> {
+        let result = self.instance(id)?.post(event);
+        first_error(result, self.route(id))
+    }
+
+    ///
+    /// Post `event`, carrying `payload`, to the instance with the ID `id`, see
+    /// `StateMachineInstance::post_with`.
+    ///
+    pub fn post_with(&self, id: &ID, event: &Event, payload: Context) -> Result<()> {
+        let result = self.instance(id)?.post_with(event, payload);
+        first_error(result, self.route(id))
+    }
+
+    ///
+    /// Post `event` to the instance added with the name `name`.
+    ///
+    pub fn post_named(&self, name: &str, event: &Event) -> Result<()> {
+        match self.names.get(name) {
+            None => Err(ErrorKind::UnknownInstance(name.to_string()).into()),
+            Some(id) => self.post(id, event),
+        }
+    }
+
+    ///
+    /// Deliver the queued events sent before the tick, then tick the timers of every active
+    /// instance, see `StateMachineInstance::tick`, and then post the SLA event to each active
+    /// instance approaching its deadline, returning the total number of timers that elapsed.
+    /// Each instance is ticked even where an earlier one fails, the first error is returned.
+    ///
+    pub fn tick(&self) -> Result<usize> {
+        let delivered = self.deliver_queued();
+        let ticked = self.for_each(|instance| instance.is_active(), StateMachineInstance::tick);
+        let breached = self.check_deadlines();
+        first_error(delivered.and(ticked), breached)
+    }
+
+    ///
+    /// Deliver the events sent for queued delivery, in the order they were sent, returning the
+    /// number delivered. Events sent for queued delivery in the meantime, and those whose
+    /// target is blocked, see `StateMachineInstance::is_blocked`, are held until the next call.
+    ///
+    pub fn deliver_queued(&self) -> Result<usize> {
+        let queued = std::mem::take(&mut *self.queued.borrow_mut());
+        let mut blocked: VecDeque<(ID, SentEvent)> = Default::default();
+        let mut count = 0;
+        let mut result = Ok(());
+        for (source, sent) in queued {
+            if self.is_blocked(sent.target()) {
+                blocked.push_back((source, sent));
+                continue;
+            }
+            let (ran, delivered) = self.deliver(&source, &sent);
+            count += 1;
+            result = first_error(result, delivered);
+            result = first_error(result, self.route(&ran));
+        }
+        let mut queued = self.queued.borrow_mut();
+        blocked.append(&mut queued);
+        *queued = blocked;
+        result.map(|_| count)
+    }
+
+    ///
+    /// The events sent for queued delivery that have not yet been delivered, in the order they
+    /// were sent.
+    ///
+    pub fn queued_events(&self) -> Vec<SentEvent> {
+        self.queued
+            .borrow()
+            .iter()
+            .map(|(_, sent)| sent.clone())
+            .collect()
+    }
+
+    ///
+    /// The time at which the next timer of any active instance is due.
+    ///
+    pub fn next_timer(&self) -> Option<SystemTime> {
+        self.instances()
+            .filter(|instance| instance.is_active())
+            .filter_map(|instance| instance.next_timer())
+            .min()
+    }
+
+    pub fn status(&self) -> ManagerStatus {
+        let mut status = ManagerStatus::default();
+        for instance in self.instances() {
+            match InstanceStatus::of(instance) {
+                InstanceStatus::NotStarted => status.not_started += 1,
+                InstanceStatus::Active => status.active += 1,
+                InstanceStatus::InError => status.in_error += 1,
+                InstanceStatus::Done => status.done += 1,
+            }
+        }
+        status
+    }
+
+    ///
+    /// The throughput of every managed instance combined, see `StateMachineInstance::metrics`;
+    /// what instances did before they were removed is not included.
+    ///
+    pub fn metrics(&self) -> ThroughputMetrics {
+        let mut metrics = ThroughputMetrics::default();
+        for instance in self.instances() {
+            metrics.combine(&instance.metrics());
+        }
+        metrics
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    fn instance(&self, id: &ID) -> Result<&StateMachineInstance> {
+        self.get(id)
+            .ok_or_else(|| ErrorKind::UnknownInstance(id.to_string()).into())
+    }
+
+    fn for_each(
+        &self,
+        filter: impl Fn(&StateMachineInstance) -> bool,
+        f: impl Fn(&StateMachineInstance) -> Result<usize>,
+    ) -> Result<usize> {
+        let mut total = 0;
+        let mut result = Ok(());
+        for instance in self.prioritized().filter(|instance| filter(instance)) {
+            result = first_error(result, f(instance).map(|count| total += count));
+            result = first_error(result, self.route(instance.id()));
+        }
+        result.map(|_| total)
+    }
+
+    ///
+    /// Deliver the events sent for immediate delivery by the instance `id`, and then those sent
+    /// by each instance that ran as a result, in turn; queue those sent for queued delivery.
+    ///
+    fn route(&self, id: &ID) -> Result<()> {
+        let mut result = Ok(());
+        let mut ran: VecDeque<ID> = VecDeque::from(vec![id.clone()]);
+        while let Some(source) = ran.pop_front() {
+            let sent = match self.get(&source) {
+                None => continue,
+                Some(instance) => instance.take_sent_events(),
+            };
+            for sent in sent {
+                if sent.delivery() == Delivery::Queued || self.is_blocked(sent.target()) {
+                    self.queued.borrow_mut().push_back((source.clone(), sent));
+                } else {
+                    let (target, delivered) = self.deliver(&source, &sent);
+                    result = first_error(result, delivered);
+                    ran.push_back(target);
+                }
+            }
+        }
+        result
+    }
+
+    ///
+    /// Post the SLA event to each active instance, in order of priority, that is within the SLA
+    /// warning of its deadline and has not already been sent it.
+    ///
+    fn check_deadlines(&self) -> Result<usize> {
+        let event = match &self.sla_event {
+            None => Event::Signal(SignalEvent::new(SLA_BREACHED)),
+            Some(event) => event.clone(),
+        };
+        self.for_each(
+            |instance| {
+                instance.is_active()
+                    && !self.is_breached(instance.id())
+                    && self
+                        .deadline_of(instance.id())
+                        .is_some_and(|deadline| instance.now() + self.sla_warning >= deadline)
+            },
+            |instance| {
+                let _ = self.breached.borrow_mut().insert(instance.id().clone());
+                instance.post(&event).map(|_| 1)
+            },
+        )
+    }
+
+    fn matching_ids(&self, filter: &InstanceFilter) -> Vec<ID> {
+        self.find_matching(filter)
+            .into_iter()
+            .map(|instance| instance.id().clone())
+            .collect()
+    }
+
+    fn matches(&self, filter: &InstanceFilter, instance: &StateMachineInstance) -> bool {
+        let id = instance.id();
+        filter
+            .tags
+            .iter()
+            .all(|(key, value)| self.tag_of(id, key) == Some(value.as_str()))
+            && filter.state_label.as_ref().is_none_or(|label| {
+                instance.active_states().iter().any(|state| {
+                    instance.index.vertices[state]
+                        .as_state()
+                        .is_some_and(|state| state.label().as_ref() == Some(label))
+                })
+            })
+            && filter
+                .status
+                .is_none_or(|status| InstanceStatus::of(instance) == status)
+            && self.age_of(id).is_some_and(|age| {
+                filter.older_than.is_none_or(|older| age > older)
+                    && filter.younger_than.is_none_or(|younger| age < younger)
+            })
+    }
+
+    fn is_blocked(&self, id: &ID) -> bool {
+        self.get(id)
+            .map(StateMachineInstance::is_blocked)
+            .unwrap_or_default()
+    }
+
+    ///
+    /// Post `sent` to its target, or where it cannot be received `ERROR_COMMUNICATION` to
+    /// `source`, returning the ID of the instance posted to.
+    ///
+    fn deliver(&self, source: &ID, sent: &SentEvent) -> (ID, Result<()>) {
+        match self.get(sent.target()) {
+            Some(target) if target.is_active() => {
+                (sent.target().clone(), target.post(sent.event()))
+            }
+            _ => match self.get(source) {
+                Some(instance) if instance.is_active() => (
+                    source.clone(),
+                    instance.post(&Event::Signal(SignalEvent::new(ERROR_COMMUNICATION))),
+                ),
+                _ => (source.clone(), Ok(())),
+            },
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl InstanceStatus {
+    fn of(instance: &StateMachineInstance) -> Self {
+        if instance.is_new() {
+            Self::NotStarted
+        } else if instance.is_in_error() {
+            Self::InError
+        } else if instance.is_done() {
+            Self::Done
+        } else {
+            Self::Active
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl InstanceFilter {
+    ///
+    /// Match only instances tagged with `key` with the value `value`.
+    ///
+    pub fn tagged(mut self, key: &str, value: &str) -> Self {
+        self.tags.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    ///
+    /// Match only instances with an active state, at any depth, labeled `label`.
+    ///
+    pub fn in_state_labeled(self, label: &str) -> Self {
+        Self {
+            state_label: Some(label.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_status(self, status: InstanceStatus) -> Self {
+        Self {
+            status: Some(status),
+            ..self
+        }
+    }
+
+    ///
+    /// Match only instances added more than `age` ago.
+    ///
+    pub fn older_than(self, age: Duration) -> Self {
+        Self {
+            older_than: Some(age),
+            ..self
+        }
+    }
+
+    ///
+    /// Match only instances added less than `age` ago.
+    ///
+    pub fn younger_than(self, age: Duration) -> Self {
+        Self {
+            younger_than: Some(age),
+            ..self
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl ManagerStatus {
+    pub fn not_started(&self) -> usize {
+        self.not_started
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn in_error(&self) -> usize {
+        self.in_error
+    }
+
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    pub fn total(&self) -> usize {
+        self.not_started + self.active + self.in_error + self.done
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn first_error<T, U>(first: Result<T>, second: Result<U>) -> Result<T> {
+    match (first, second) {
+        (Err(e), _) => Err(e),
+        (Ok(_), Err(e)) => Err(e),
+        (Ok(value), Ok(_)) => Ok(value),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::builder::MachineBuilder;
+    use crate::definition::opaque::{Bindings, OpaqueBehavior};
+    use crate::definition::types::{Behavior, SendTarget};
+    use crate::definition::types::{
+        HasRegions, Identified, State, StateMachine, Transition, Trigger,
+    };
+    use crate::execution::fixtures::{add_triggered, signal};
+    use crate::execution::timers::{Clock, ManualClock};
+    use crate::execution::types::OverflowPolicy;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn timeout() -> Arc<StateMachine> {
+        let machine = StateMachine::labeled("timeout");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let waiting = region.new_simple_state();
+        let done = region.new_final_state();
+        region.new_transition(initial, waiting.clone());
+        add_triggered(
+            region,
+            &waiting,
+            &done,
+            Trigger::after(Duration::from_secs(5)),
+        );
+        add_triggered(region, &waiting, &done, Trigger::signal("stop"));
+        Arc::new(machine)
+    }
+
+    #[test]
+    fn test_routing_by_id_and_name() {
+        let machine = timeout();
+        let mut manager = InstanceManager::default();
+        let first = manager
+            .add_named("first", StateMachineInstance::new(machine.clone()).unwrap())
+            .unwrap();
+        let second = manager
+            .add(StateMachineInstance::new(machine.clone()).unwrap())
+            .unwrap();
+        assert_eq!(manager.len(), 2);
+        assert_eq!(manager.name_of(&first), Some("first"));
+        assert_eq!(manager.name_of(&second), None);
+        assert!(manager
+            .add_named("first", StateMachineInstance::new(machine).unwrap())
+            .is_err());
+        assert_eq!(manager.status().not_started(), 2);
+
+        manager.execute_all().unwrap();
+        manager.post_named("first", &signal("stop")).unwrap();
+        assert!(manager.get(&first).unwrap().is_done());
+        assert!(manager.get(&second).unwrap().is_active());
+        assert!(manager.post(&ID::random(), &signal("stop")).is_err());
+        assert!(manager.post_named("third", &signal("stop")).is_err());
+
+        let status = manager.status();
+        assert_eq!((status.active(), status.done(), status.total()), (1, 1, 2));
+        let removed = manager.remove_done();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id(), &first);
+        assert!(manager.find("first").is_none());
+        assert_eq!(
+            manager
+                .instances()
+                .map(|instance| instance.id().clone())
+                .collect::<Vec<ID>>(),
+            vec![second]
+        );
+    }
+
+    #[test]
+    fn test_tick_all() {
+        let machine = timeout();
+        let start = SystemTime::UNIX_EPOCH;
+        let clock = Arc::new(ManualClock::new(start));
+        let mut manager = InstanceManager::default();
+        for _ in 0..3 {
+            let _ = manager
+                .add(
+                    StateMachineInstance::new(machine.clone())
+                        .unwrap()
+                        .with_clock(clock.clone()),
+                )
+                .unwrap();
+        }
+        assert_eq!(manager.next_timer(), None);
+        manager.execute_all().unwrap();
+        assert_eq!(manager.next_timer(), Some(start + Duration::from_secs(5)));
+
+        let first = manager.instances().next().unwrap().id().clone();
+        manager.post(&first, &signal("stop")).unwrap();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(manager.tick().unwrap(), 2);
+        assert_eq!(manager.status().done(), 3);
+        assert_eq!(manager.next_timer(), None);
+        assert_eq!(manager.tick().unwrap(), 0);
+
+        let metrics = manager.metrics();
+        assert_eq!(metrics.window(), Duration::from_secs(60));
+        assert_eq!(metrics.events_accepted(), 1);
+        // one step to start each instance, one for the stop, and one for each timeout
+        assert_eq!(metrics.steps_executed(), 6);
+        assert_eq!(metrics.steps_per_second(), 0.1);
+    }
+
+    #[test]
+    fn test_priorities_and_deadlines() {
+        let machine = StateMachine::labeled("escalating");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let waiting = region.new_simple_state();
+        let escalated = region.new_simple_state();
+        let late = region.new_simple_state();
+        region.new_transition(initial, waiting.clone());
+        add_triggered(region, &waiting, &escalated, Trigger::signal(SLA_BREACHED));
+        add_triggered(region, &escalated, &late, Trigger::signal(SLA_BREACHED));
+        let machine = Arc::new(machine);
+
+        let start = SystemTime::UNIX_EPOCH;
+        let clock = Arc::new(ManualClock::new(start));
+        let mut manager = InstanceManager::default().with_sla_warning(Duration::from_secs(2));
+        let ids: Vec<ID> = (0..3)
+            .map(|_| {
+                manager
+                    .add(
+                        StateMachineInstance::new(machine.clone())
+                            .unwrap()
+                            .with_clock(clock.clone()),
+                    )
+                    .unwrap()
+            })
+            .collect();
+        manager.set_priority(&ids[2], 5).unwrap();
+        manager.set_priority(&ids[0], -1).unwrap();
+        assert!(manager.set_priority(&ID::random(), 1).is_err());
+        assert_eq!(manager.priority_of(&ids[1]), Some(0));
+        assert_eq!(
+            manager
+                .prioritized()
+                .map(|instance| instance.id().clone())
+                .collect::<Vec<ID>>(),
+            vec![ids[2].clone(), ids[1].clone(), ids[0].clone()]
+        );
+
+        manager.execute_all().unwrap();
+        manager
+            .set_deadline(&ids[0], start + Duration::from_secs(10))
+            .unwrap();
+        clock.advance(Duration::from_secs(7));
+        let _ = manager.tick().unwrap();
+        assert!(!manager.is_breached(&ids[0]));
+        clock.advance(Duration::from_secs(1));
+        let _ = manager.tick().unwrap();
+        assert!(manager.is_breached(&ids[0]));
+        assert!(manager.get(&ids[0]).unwrap().is_in_state(&escalated));
+        assert!(manager.get(&ids[1]).unwrap().is_in_state(&waiting));
+
+        // the event is posted once for each deadline set
+        let _ = manager.tick().unwrap();
+        assert!(manager.get(&ids[0]).unwrap().is_in_state(&escalated));
+        manager
+            .set_deadline(&ids[0], start + Duration::from_secs(20))
+            .unwrap();
+        assert!(!manager.is_breached(&ids[0]));
+        clock.advance(Duration::from_secs(10));
+        let _ = manager.tick().unwrap();
+        assert!(manager.get(&ids[0]).unwrap().is_in_state(&late));
+
+        let mut manager = InstanceManager::default().with_sla_event(signal("late"));
+        let id = manager
+            .add(
+                StateMachineInstance::new(machine)
+                    .unwrap()
+                    .with_clock(clock.clone()),
+            )
+            .unwrap();
+        manager.execute_all().unwrap();
+        manager.set_deadline(&id, clock.now()).unwrap();
+        let _ = manager.tick().unwrap();
+        assert!(manager.is_breached(&id));
+        assert!(manager.get(&id).unwrap().is_in_state(&waiting));
+        let _ = manager.remove(&id);
+        assert_eq!(manager.deadline_of(&id), None);
+    }
+
+    #[test]
+    fn test_find_matching() {
+        let machine = timeout();
+        let start = SystemTime::UNIX_EPOCH;
+        let clock = Arc::new(ManualClock::new(start));
+        let idle = StateMachine::labeled("idle");
+        let region = idle.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut waiting = State::within(region.id().clone());
+        waiting.set_label("waiting");
+        let waiting_id = waiting.id().clone();
+        region.add_state(waiting);
+        region.new_transition(initial, waiting_id);
+        let idle = Arc::new(idle);
+
+        let mut manager = InstanceManager::default();
+        let instance = |machine: &Arc<StateMachine>| {
+            StateMachineInstance::new(machine.clone())
+                .unwrap()
+                .with_clock(clock.clone())
+        };
+        let eu = manager
+            .add_tagged(instance(&machine), vec![("region", "eu"), ("tier", "gold")])
+            .unwrap();
+        clock.advance(Duration::from_secs(60));
+        let us = manager
+            .add_tagged(instance(&machine), vec![("region", "us")])
+            .unwrap();
+        let waiting = manager.add(instance(&idle)).unwrap();
+        manager.set_tag(&waiting, "region", "eu").unwrap();
+        assert!(manager.set_tag(&ID::random(), "region", "eu").is_err());
+        assert_eq!(manager.tag_of(&eu, "tier"), Some("gold"));
+        assert_eq!(manager.age_of(&eu), Some(Duration::from_secs(60)));
+        manager.execute_all().unwrap();
+        manager.post(&us, &signal("stop")).unwrap();
+
+        let ids = |filter: InstanceFilter| -> Vec<ID> {
+            manager
+                .find_matching(&filter)
+                .into_iter()
+                .map(|instance| instance.id().clone())
+                .collect()
+        };
+        assert_eq!(ids(InstanceFilter::default()).len(), 3);
+        assert_eq!(
+            ids(InstanceFilter::default().tagged("region", "eu")),
+            vec![eu.clone(), waiting.clone()]
+        );
+        assert_eq!(
+            ids(InstanceFilter::default()
+                .tagged("region", "eu")
+                .tagged("tier", "gold")),
+            vec![eu.clone()]
+        );
+        assert_eq!(
+            ids(InstanceFilter::default().in_state_labeled("waiting")),
+            vec![waiting.clone()]
+        );
+        assert_eq!(
+            ids(InstanceFilter::default().with_status(InstanceStatus::Done)),
+            vec![us.clone()]
+        );
+        assert_eq!(
+            ids(InstanceFilter::default().older_than(Duration::from_secs(30))),
+            vec![eu.clone()]
+        );
+        assert_eq!(
+            ids(InstanceFilter::default()
+                .younger_than(Duration::from_secs(30))
+                .with_status(InstanceStatus::Active)),
+            vec![waiting]
+        );
+    }
+
+    #[test]
+    fn test_bulk_operations() {
+        let machine = timeout();
+        let region = machine.default_region().unwrap();
+        let waiting = region.transitions()[0].target();
+
+        // the revision replaces the waiting state, and adds a composite state
+        let (revised, names) = MachineBuilder::labeled("timeout")
+            .region(|region| {
+                region
+                    .initial("pending")
+                    .state("pending", |state| state.on("stop", "done").on("go", "busy"))
+                    .state("busy", |state| {
+                        state.region(|inner| inner.initial("queued").simple_state("queued"))
+                    })
+                    .final_state("done")
+            })
+            .build_with_names()
+            .unwrap();
+        let (pending_id, queued) = (names["pending"].clone(), names["queued"].clone());
+
+        let mut manager = InstanceManager::default();
+        let tagged = |manager: &mut InstanceManager, batch: &str| {
+            manager
+                .add_tagged(
+                    StateMachineInstance::new(machine.clone()).unwrap(),
+                    vec![("batch", batch)],
+                )
+                .unwrap()
+        };
+        let first = tagged(&mut manager, "a");
+        let second = tagged(&mut manager, "a");
+        let third = tagged(&mut manager, "b");
+        let batch_a = InstanceFilter::default().tagged("batch", "a");
+
+        // the instances are not started, so each post fails
+        let results = manager.post_to_matching(&batch_a, &signal("stop"));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, first);
+        assert!(results.iter().all(|(_, result)| result.is_err()));
+
+        manager.execute_all().unwrap();
+        manager.post(&first, &signal("stop")).unwrap();
+        let results = manager.post_to_matching(&batch_a, &signal("stop"));
+        assert!(results[0].1.is_err());
+        assert!(results[1].1.is_ok());
+        assert!(manager.get(&second).unwrap().is_done());
+
+        let third_filter = InstanceFilter::default().tagged("batch", "b");
+        let mut map: HashMap<ID, ID> = Default::default();
+        let _ = map.insert(waiting.clone(), queued.clone());
+        let results = manager.migrate_matching(&third_filter, revised.clone(), &map);
+        assert!(results[0].1.is_err());
+        assert!(manager.get(&third).unwrap().is_in_state(&waiting));
+        let _ = map.insert(waiting.clone(), pending_id.clone());
+        let results = manager.migrate_matching(&third_filter, revised.clone(), &map);
+        assert!(results[0].1.is_ok());
+        let migrated = manager.get(&third).unwrap();
+        assert_eq!(migrated.chart().id(), revised.id());
+        assert!(migrated.is_in_state(&pending_id));
+        assert_eq!(migrated.next_timer(), None);
+        manager.post(&third, &signal("go")).unwrap();
+        assert!(manager.get(&third).unwrap().is_in_state(&queued));
+
+        let results = manager.cancel_matching(&InstanceFilter::default());
+        assert_eq!(results.len(), 3);
+        assert!(results[0].1.is_err());
+        assert!(results[2].1.is_ok());
+        assert_eq!(manager.status().done(), 3);
+    }
+
+    #[test]
+    fn test_sending_between_instances() {
+        let unknown = ID::random();
+        let mut bindings = Bindings::default();
+        bindings.bind("started", |_, _, events| {
+            events.send(SendTarget::Parent, signal("started"), Delivery::Immediate)
+        });
+        bindings.bind("finished", |_, _, events| {
+            events.send(SendTarget::Parent, signal("finished"), Delivery::Queued)
+        });
+        bindings.bind("lost", move |_, _, events| {
+            events.send(
+                SendTarget::Instance(unknown.clone()),
+                signal("lost"),
+                Delivery::Immediate,
+            )
+        });
+        let sending = |name: &str| -> Box<dyn Behavior> {
+            bindings.resolve(OpaqueBehavior::new(name, None), &[name])
+        };
+
+        let supervisor = StateMachine::labeled("supervisor");
+        let region = supervisor.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let waiting = region.new_simple_state();
+        let supervising = region.new_simple_state();
+        let done = region.new_final_state();
+        region.new_transition(initial, waiting.clone());
+        add_triggered(region, &waiting, &supervising, Trigger::signal("started"));
+        add_triggered(region, &supervising, &done, Trigger::signal("finished"));
+
+        let worker = StateMachine::labeled("worker");
+        let region = worker.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        let busy = region.new_simple_state();
+        let failed = region.new_simple_state();
+        let stopped = region.new_final_state();
+        region.new_transition(initial, idle.clone());
+        for (source, target, trigger, effect) in &[
+            (&idle, &busy, "go", "started"),
+            (&busy, &stopped, "stop", "finished"),
+            (&idle, &idle, "lose", "lost"),
+        ] {
+            let mut transition =
+                Transition::within((*source).clone(), (*target).clone(), region.id().clone());
+            transition.add_trigger(Trigger::signal(trigger));
+            transition.set_effect(sending(effect));
+            region.add_transition(transition);
+        }
+        add_triggered(region, &idle, &failed, Trigger::signal(ERROR_COMMUNICATION));
+        let worker = Arc::new(worker);
+
+        let mut manager = InstanceManager::default();
+        let parent = manager
+            .add(StateMachineInstance::new(Arc::new(supervisor)).unwrap())
+            .unwrap();
+        let child = manager
+            .add(
+                StateMachineInstance::new(worker.clone())
+                    .unwrap()
+                    .with_parent(parent.clone()),
+            )
+            .unwrap();
+        let orphan = manager
+            .add(StateMachineInstance::new(worker).unwrap())
+            .unwrap();
+        manager.execute_all().unwrap();
+
+        manager.post(&child, &signal("go")).unwrap();
+        assert!(manager.get(&parent).unwrap().is_in_state(&supervising));
+        manager.post(&child, &signal("stop")).unwrap();
+        assert!(manager.get(&child).unwrap().is_done());
+        assert!(manager.get(&parent).unwrap().is_in_state(&supervising));
+        assert_eq!(manager.queued_events().len(), 1);
+        assert_eq!(manager.queued_events()[0].target(), &parent);
+        assert_eq!(manager.deliver_queued().unwrap(), 1);
+        assert!(manager.get(&parent).unwrap().is_done());
+
+        // without a parent the event is dropped, to an unknown instance it is returned as an error.
+        manager.post(&orphan, &signal("go")).unwrap();
+        assert!(manager.get(&orphan).unwrap().is_in_state(&busy));
+        let orphan = manager
+            .add(StateMachineInstance::new(manager.get(&orphan).unwrap().chart()).unwrap())
+            .unwrap();
+        manager.execute_all().unwrap();
+        manager.post(&orphan, &signal("lose")).unwrap();
+        assert!(manager.get(&orphan).unwrap().is_in_state(&failed));
+    }
+
+    #[test]
+    fn test_events_to_blocked_instances_are_held() {
+        let mut bindings = Bindings::default();
+        let target = ID::random();
+        let to = target.clone();
+        bindings.bind("notify", move |_, _, events| {
+            events.send(
+                SendTarget::Instance(to.clone()),
+                signal("note"),
+                Delivery::Immediate,
+            )
+        });
+
+        let machine = StateMachine::labeled("notifier");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        let noted = region.new_simple_state();
+        region.new_transition(initial, idle.clone());
+        let mut transition = Transition::within(idle.clone(), idle.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("go"));
+        transition.set_effect(bindings.resolve(OpaqueBehavior::new("notify", None), &["notify"]));
+        region.add_transition(transition);
+        add_triggered(region, &idle, &noted, Trigger::signal("note"));
+        let machine = Arc::new(machine);
+
+        let mut manager = InstanceManager::default();
+        let source = manager
+            .add(StateMachineInstance::new(machine.clone()).unwrap())
+            .unwrap();
+        let mut instance = StateMachineInstance::new(machine)
+            .unwrap()
+            .with_queue_capacity(1, OverflowPolicy::Block);
+        instance.id = target.clone();
+        let _ = manager.add(instance).unwrap();
+        manager.execute_all().unwrap();
+
+        manager.get(&target).unwrap().pause().unwrap();
+        manager.post(&target, &signal("ignored")).unwrap();
+        assert!(manager.get(&target).unwrap().is_blocked());
+        manager.post(&source, &signal("go")).unwrap();
+        assert_eq!(manager.queued_events().len(), 1);
+        assert_eq!(manager.deliver_queued().unwrap(), 0);
+        assert_eq!(manager.queued_events().len(), 1);
+
+        manager.get(&target).unwrap().resume().unwrap();
+        assert_eq!(manager.deliver_queued().unwrap(), 1);
+        assert!(manager.get(&target).unwrap().is_in_state(&noted));
+    }
+}
@@ -1,4 +1,5 @@
 /*!
+An in-memory execution environment for instances of a state machine.
 */
 
 // ------------------------------------------------------------------------------------------------
@@ -7,5 +8,32 @@
 
 pub mod types;
 
-// #[doc(hidden)]
-// pub mod impls;
+pub mod timers;
+
+pub mod invoke;
+
+pub mod services;
+
+pub mod trace;
+
+pub mod stats;
+
+pub mod replay;
+
+pub mod differential;
+
+pub mod manager;
+
+#[cfg(feature = "tokio")]
+pub mod async_rt;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+
+#[cfg(test)]
+pub(crate) mod fixtures;
+
+pub use replay::replay;
+
+#[doc(hidden)]
+pub mod impls;
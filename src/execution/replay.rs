@@ -0,0 +1,243 @@
+/*!
+Replays a recorded `ExecutionTrace` against a machine, so that traces recorded from one version
+of a machine may be used to check that a later version still behaves the same way.
+
+`replay` creates a new instance of the machine, with a `ManualClock` set to the time of each
+recorded entry in turn, and drives it with the recorded events; time events are replayed with
+`StateMachineInstance::tick`, other events with `StateMachineInstance::post`. Events that were
+raised by behaviors, or recalled once no longer deferred, are not posted again, they are expected
+to be dispatched by the replayed instance as they were by the original. Each entry recorded by
+the replayed instance is compared with the original, and the first whose event or fired
+transitions differ is returned as a `Divergence`.
+
+The instance is created with an empty context; changes made to the context of the original
+instance with `update_context` are not replayed, so change events that they caused are not
+expected to occur again. Traces do not record the payloads of events, so events are replayed
+without them.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::replay;
+use uml_state_machine::execution::trace::TraceRecorder;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+let open = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+let mut transition = Transition::within(closed.clone(), open.clone(), region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+let machine = Arc::new(machine);
+
+let recorder = Arc::new(TraceRecorder::default());
+let instance = StateMachineInstance::new(machine.clone()).unwrap();
+instance.add_observer(recorder.clone());
+instance.execute().unwrap();
+instance.post(&Event::Signal(SignalEvent::new("push"))).unwrap();
+
+assert_eq!(replay(machine, &recorder.trace()).unwrap(), None);
+```
+
+*/
+
+use crate::definition::types::{Event, StateMachine};
+use crate::error::Result;
+use crate::execution::timers::ManualClock;
+use crate::execution::trace::{ExecutionTrace, TraceEntry, TraceRecorder};
+use crate::execution::types::StateMachineInstance;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The first entry of a replayed trace that differs from the recorded trace.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    index: usize,
+    expected: Option<TraceEntry>,
+    actual: Option<TraceEntry>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Replay `trace` against a new instance of `machine`, returning the first divergence from the
+/// trace, or `None` where the same transitions fired in response to the same events. An error
+/// is returned where the instance cannot be created, or fails while an event is replayed.
+///
+pub fn replay(machine: Arc<StateMachine>, trace: &ExecutionTrace) -> Result<Option<Divergence>> {
+    let expected: Vec<&TraceEntry> = trace.entries().collect();
+    let clock = Arc::new(ManualClock::new(
+        expected
+            .first()
+            .map(|entry| entry.time())
+            .unwrap_or(SystemTime::UNIX_EPOCH),
+    ));
+    let recorder = Arc::new(TraceRecorder::default());
+    let instance = StateMachineInstance::new(machine)?.with_clock(clock.clone());
+    instance.add_observer(recorder.clone());
+    instance.execute()?;
+
+    let mut index = 0;
+    let mut actual = entries(&recorder);
+    if expected
+        .first()
+        .is_some_and(|entry| entry.event().is_some())
+    {
+        // the trace was recorded from an instance that had already started.
+        actual.clear();
+    }
+    loop {
+        for entry in actual {
+            match expected.get(index) {
+                Some(expected) if is_same(expected, &entry) => index += 1,
+                expected => {
+                    return Ok(Some(Divergence {
+                        index,
+                        expected: expected.map(|expected| (*expected).clone()),
+                        actual: Some(entry),
+                    }))
+                }
+            }
+        }
+        let next = match expected.get(index) {
+            None => return Ok(None),
+            Some(next) => *next,
+        };
+        if instance.is_active() {
+            clock.set(next.time());
+            match next.event() {
+                Some(Event::Time(_)) | None => {
+                    let _ = instance.tick()?;
+                }
+                Some(Event::Change(_)) => instance.update_context(|_| {})?,
+                Some(event) => instance.post(event)?,
+            }
+        }
+        actual = entries(&recorder);
+        if actual.is_empty() {
+            return Ok(Some(Divergence {
+                index,
+                expected: Some(next.clone()),
+                actual: None,
+            }));
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Divergence {
+    ///
+    /// The index, within the recorded trace, of the entry that was expected.
+    ///
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    ///
+    /// The entry recorded, `None` where the replayed instance did more than the original.
+    ///
+    pub fn expected(&self) -> Option<&TraceEntry> {
+        self.expected.as_ref()
+    }
+
+    ///
+    /// The entry replayed, `None` where the replayed instance did nothing in response to the
+    /// expected entry's event.
+    ///
+    pub fn actual(&self) -> Option<&TraceEntry> {
+        self.actual.as_ref()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn entries(recorder: &TraceRecorder) -> Vec<TraceEntry> {
+    recorder.take().entries().cloned().collect()
+}
+
+fn is_same(expected: &TraceEntry, actual: &TraceEntry) -> bool {
+    expected.event() == actual.event() && expected.fired().eq(actual.fired())
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ID;
+    use crate::definition::types::{HasRegions, Trigger};
+    use crate::execution::fixtures::{add_triggered, signal};
+    use std::time::Duration;
+
+    #[test]
+    fn test_replay() {
+        let machine = StateMachine::labeled("worker");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        let busy = region.new_simple_state();
+        region.new_transition(initial, idle.clone());
+        add_triggered(region, &idle, &busy, Trigger::signal("go"));
+        add_triggered(region, &busy, &idle, Trigger::signal("stop"));
+        add_triggered(region, &busy, &idle, Trigger::after(Duration::from_secs(5)));
+        let machine = Arc::new(machine);
+
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let recorder = Arc::new(TraceRecorder::default());
+        let instance = StateMachineInstance::new(machine.clone())
+            .unwrap()
+            .with_clock(clock.clone());
+        instance.add_observer(recorder.clone());
+        instance.execute().unwrap();
+        instance.post(&signal("go")).unwrap();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(instance.tick().unwrap(), 1);
+        instance.post(&signal("go")).unwrap();
+        instance.post(&signal("ignored")).unwrap();
+        instance.post(&signal("stop")).unwrap();
+        let trace = recorder.take();
+        assert_eq!(trace.len(), 6);
+        assert_eq!(replay(machine.clone(), &trace).unwrap(), None);
+
+        // a shorter timeout now fires first, so the tick leaves `busy` for `stuck`.
+        let region = machine.default_region().unwrap();
+        let stuck = region.new_simple_state();
+        add_triggered(
+            region,
+            &busy,
+            &stuck,
+            Trigger::after(Duration::from_secs(2)),
+        );
+        let divergence = replay(machine, &trace).unwrap().unwrap();
+        assert_eq!(divergence.index(), 2);
+        let targets = |entry: Option<&TraceEntry>| -> Vec<ID> {
+            entry
+                .unwrap()
+                .fired()
+                .map(|fired| fired.target().clone())
+                .collect()
+        };
+        assert_eq!(targets(divergence.expected()), vec![idle]);
+        assert_eq!(targets(divergence.actual()), vec![stuck]);
+    }
+}
@@ -0,0 +1,569 @@
+/*!
+Invoke handlers for services outside the process, external programs and HTTP endpoints, so that a
+machine read from SCXML may `<invoke>` them as it would there.
+
+Each handler is configured declaratively by the invoke itself: its `source` names the program, or
+the URL, and its parameters are passed to the service, as environment variables of the program
+or as the form-encoded body of the request. The service runs on a thread of its own and, once it
+responds, the handler's `MessageMapper`, by default `StatusMapper`, maps the response to the
+outcome of the invocation. Where the invoking state is exited first a program is killed, and the
+response to a request is ignored.
+
+# Example
+
+```rust,no_run
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::services::{HttpHandler, ProcessHandler};
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("publish");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let mut building = State::within(region.id().clone());
+let mut build = Invoke::new("process");
+build.set_label("build");
+build.set_source("make site");
+building.add_invoke(build);
+let building_id = building.id().clone();
+region.add_state(building);
+let published = region.new_final_state();
+region.new_transition(initial, building_id.clone());
+let mut transition = Transition::within(building_id, published, region.id().clone());
+transition.add_trigger(Trigger::signal("done.invoke.build"));
+region.add_transition(transition);
+
+let instance = StateMachineInstance::new(Arc::new(machine))
+    .unwrap()
+    .with_invoke_handler("process", ProcessHandler::default())
+    .with_invoke_handler("http", HttpHandler::default());
+instance.execute().unwrap();
+while !instance.is_done() {
+    let _ = instance.tick().unwrap();
+}
+```
+
+*/
+
+use crate::core::FieldValue;
+use crate::execution::invoke::{
+    Invocation, InvokeHandle, InvokeHandler, Message, MessageMapper, StatusMapper,
+};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Runs the program named by an invoke's `source`, split on whitespace into the program and its
+/// arguments, with each parameter as an environment variable. The response is the program's
+/// exit status and standard output, and it succeeded where its status is zero.
+///
+#[derive(Clone)]
+pub struct ProcessHandler {
+    mapper: Arc<dyn MessageMapper>,
+}
+
+///
+/// Sends an HTTP/1.0 `POST` to the `http` URL of an invoke's `source`, with its parameters as a
+/// form-encoded body. The response is the status, headers, and body returned, and it succeeded
+/// where its status is 2xx. Secure, `https`, endpoints are not supported.
+///
+#[derive(Clone)]
+pub struct HttpHandler {
+    mapper: Arc<dyn MessageMapper>,
+    timeout: Duration,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Default for ProcessHandler {
+    fn default() -> Self {
+        Self::with_mapper(StatusMapper)
+    }
+}
+
+impl ProcessHandler {
+    pub fn with_mapper(mapper: impl MessageMapper + 'static) -> Self {
+        Self {
+            mapper: Arc::new(mapper),
+        }
+    }
+}
+
+impl InvokeHandler for ProcessHandler {
+    fn start(&self, invocation: &Invocation, handle: InvokeHandle) {
+        let source = invocation.source().clone().unwrap_or_default();
+        let mut words = source.split_whitespace();
+        let program = match words.next() {
+            None => return handle.fail("a process service must name a program as its source"),
+            Some(program) => program,
+        };
+        let child = Command::new(program)
+            .args(words)
+            .envs(
+                invocation
+                    .params()
+                    .iter()
+                    .map(|(name, value)| (name, param_text(value))),
+            )
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Err(e) => return handle.fail(&e.to_string()),
+            Ok(child) => child,
+        };
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            stdout.read_to_string(&mut output).map(|_| output)
+        });
+        let invocation = invocation.clone();
+        let mapper = self.mapper.clone();
+        let _ = thread::spawn(move || loop {
+            if handle.is_cancelled() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            match child.try_wait() {
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(e) => return handle.fail(&e.to_string()),
+                Ok(Some(status)) => {
+                    return match reader.join() {
+                        Ok(Ok(body)) => {
+                            let message = Message {
+                                success: status.success(),
+                                status: status.code().map(i64::from),
+                                headers: Default::default(),
+                                body,
+                            };
+                            handle.respond(&invocation, &message, mapper.as_ref())
+                        }
+                        Ok(Err(e)) => handle.fail(&e.to_string()),
+                        Err(_) => handle.fail("reading the program's output panicked"),
+                    };
+                }
+            }
+        });
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Default for HttpHandler {
+    fn default() -> Self {
+        Self::with_mapper(StatusMapper)
+    }
+}
+
+impl HttpHandler {
+    pub fn with_mapper(mapper: impl MessageMapper + 'static) -> Self {
+        Self {
+            mapper: Arc::new(mapper),
+            timeout: DEFAULT_HTTP_TIMEOUT,
+        }
+    }
+
+    ///
+    /// Fail requests whose connection, or any read or write, takes longer than `timeout`; the
+    /// default is thirty seconds.
+    ///
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+}
+
+impl InvokeHandler for HttpHandler {
+    fn start(&self, invocation: &Invocation, handle: InvokeHandle) {
+        let url = match invocation.source().as_deref().map(parse_url) {
+            None => return handle.fail("an HTTP service must have a URL as its source"),
+            Some(Err(reason)) => return handle.fail(&reason),
+            Some(Ok(url)) => url,
+        };
+        let mut params: Vec<(&String, &FieldValue)> = invocation.params().iter().collect();
+        params.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+        let body = params
+            .into_iter()
+            .map(|(name, value)| {
+                format!("{}={}", form_encode(name), form_encode(&param_text(value)))
+            })
+            .collect::<Vec<String>>()
+            .join("&");
+        let invocation = invocation.clone();
+        let mapper = self.mapper.clone();
+        let timeout = self.timeout;
+        let _ = thread::spawn(move || match post(&url, &body, timeout) {
+            Err(e) => handle.fail(&e),
+            Ok(message) => {
+                if !handle.is_cancelled() {
+                    handle.respond(&invocation, &message, mapper.as_ref())
+                }
+            }
+        });
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct Url {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The text of a parameter, strings without the quotes of their display form.
+///
+fn param_text(value: &FieldValue) -> String {
+    match value {
+        FieldValue::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn parse_url(url: &str) -> Result<Url, String> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some((scheme, _)) => return Err(format!("`{}` URLs are not supported", scheme)),
+        None => return Err(format!("`{}` is not a URL", url)),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(port) => (host, port),
+            Err(_) => return Err(format!("`{}` is not a valid port", port)),
+        },
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("`{}` has no host", url));
+    }
+    Ok(Url {
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+fn form_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b' ' => "+".to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn post(url: &Url, body: &str, timeout: Duration) -> Result<Message, String> {
+    let address = (url.host.as_str(), url.port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("`{}` has no address", url.host))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+    write!(
+        stream,
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        url.path,
+        url.host,
+        body.len(),
+        body
+    )
+    .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    let _ = stream
+        .read_to_end(&mut response)
+        .map_err(|e| e.to_string())?;
+    parse_response(&String::from_utf8_lossy(&response))
+}
+
+fn parse_response(response: &str) -> Result<Message, String> {
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| "the response has no header".to_string())?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<i64>().ok())
+        .ok_or_else(|| "the response has no status".to_string())?;
+    Ok(Message {
+        success: (200..300).contains(&status),
+        status: Some(status),
+        headers: lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect(),
+        body: body.to_string(),
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::{Compound, FieldPath};
+    use crate::core::{Context, ID};
+    use crate::definition::types::{
+        ComparisonOperator, Event, HasRegions, Identified, Invoke, Labeled, PayloadPredicate,
+        SignalEvent, State, StateMachine, Trigger,
+    };
+    use crate::execution::fixtures::add_triggered;
+    use crate::execution::types::StateMachineInstance;
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    struct Invoking {
+        instance: StateMachineInstance,
+        calling: ID,
+        done: ID,
+        failed: ID,
+    }
+
+    ///
+    /// An instance whose `calling` state invokes `invoke` with `handler`, moving to `done` on
+    /// its done event where the payload has `status` (or if `None`, any payload), or on `skip`,
+    /// and to `failed` on its error event.
+    ///
+    fn invoking(
+        invoke: Invoke,
+        handler: impl InvokeHandler + 'static,
+        status: Option<i64>,
+    ) -> Invoking {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut calling = State::within(region.id().clone());
+        calling.add_invoke(invoke.clone());
+        let calling_id = calling.id().clone();
+        region.add_state(calling);
+        let done = region.new_simple_state();
+        let failed = region.new_simple_state();
+        region.new_transition(initial, calling_id.clone());
+        let mut trigger = Trigger::with_event(invoke.done_event());
+        if let Some(status) = status {
+            trigger.add_payload_predicate(PayloadPredicate::new(
+                FieldPath::from_str("status").unwrap(),
+                ComparisonOperator::Equal,
+                FieldValue::Integer(status),
+            ));
+        }
+        add_triggered(region, &calling_id, &done, trigger);
+        add_triggered(region, &calling_id, &done, Trigger::signal("skip"));
+        add_triggered(
+            region,
+            &calling_id,
+            &failed,
+            Trigger::with_event(invoke.error_event()),
+        );
+        let context = Context::default();
+        context.insert(
+            FieldPath::from_str("order").unwrap(),
+            FieldValue::Integer(42),
+        );
+        context.insert(
+            FieldPath::from_str("note").unwrap(),
+            FieldValue::String("gift wrap".to_string()),
+        );
+        let instance = StateMachineInstance::with_context(Arc::new(machine), context)
+            .unwrap()
+            .with_invoke_handler(invoke.kind(), handler);
+        instance.execute().unwrap();
+        Invoking {
+            instance,
+            calling: calling_id,
+            done,
+            failed,
+        }
+    }
+
+    ///
+    /// Tick `invoking` until it leaves its calling state, or ten seconds have passed.
+    ///
+    fn finish(invoking: &Invoking) {
+        let started = Instant::now();
+        while invoking.instance.is_in_state(&invoking.calling)
+            && started.elapsed() < Duration::from_secs(10)
+        {
+            thread::sleep(POLL_INTERVAL);
+            let _ = invoking.instance.tick().unwrap();
+        }
+    }
+
+    fn service(kind: &str, label: &str, source: &str) -> Invoke {
+        let mut invoke = Invoke::new(kind);
+        invoke.set_label(label);
+        invoke.set_source(source);
+        invoke.add_param("ORDER", FieldPath::from_str("order").unwrap());
+        invoke.add_param("note", FieldPath::from_str("note").unwrap());
+        invoke
+    }
+
+    ///
+    /// A mapper that keeps each message before mapping it with `StatusMapper`.
+    ///
+    fn recording(messages: &Arc<Mutex<Vec<Message>>>) -> impl MessageMapper {
+        let messages = messages.clone();
+        move |invocation: &Invocation, message: &Message| {
+            messages.lock().unwrap().push(message.clone());
+            StatusMapper.map(invocation, message)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_process_services() {
+        let messages: Arc<Mutex<Vec<Message>>> = Default::default();
+        let handler = ProcessHandler::with_mapper(recording(&messages));
+
+        let printing = invoking(
+            service("process", "print", "printenv ORDER note"),
+            handler.clone(),
+            Some(0),
+        );
+        finish(&printing);
+        assert!(printing.instance.is_in_state(&printing.done));
+        assert_eq!(
+            messages.lock().unwrap()[0],
+            Message {
+                success: true,
+                status: Some(0),
+                headers: vec![],
+                body: "42\ngift wrap\n".to_string(),
+            }
+        );
+
+        let failing = invoking(service("process", "fail", "false"), handler.clone(), None);
+        finish(&failing);
+        assert!(failing.instance.is_in_state(&failing.failed));
+        assert!(!messages.lock().unwrap()[1].success);
+
+        let missing = invoking(
+            service("process", "missing", "/no/such/program"),
+            handler.clone(),
+            None,
+        );
+        let _ = missing.instance.tick().unwrap();
+        assert!(missing.instance.is_in_state(&missing.failed));
+
+        // leaving the state kills the program, which never responds.
+        let sleeping = invoking(service("process", "sleep", "sleep 30"), handler, None);
+        sleeping
+            .instance
+            .post(&Event::Signal(SignalEvent::new("skip")))
+            .unwrap();
+        assert!(sleeping.instance.is_in_state(&sleeping.done));
+        thread::sleep(POLL_INTERVAL * 5);
+        assert_eq!(messages.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_http_services() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/orders", listener.local_addr().unwrap());
+        let requests: Arc<Mutex<Vec<String>>> = Default::default();
+        let received = requests.clone();
+        let _ = thread::spawn(move || {
+            for (stream, status) in listener
+                .incoming()
+                .zip(["200 OK", "503 Unavailable"].iter())
+            {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 1024];
+                // the request is complete once as many bytes as its length follow the header.
+                loop {
+                    let read = stream.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("Content-Length: "))
+                            .and_then(|length| length.parse::<usize>().ok())
+                            .unwrap_or_default();
+                        if body.len() >= length {
+                            received.lock().unwrap().push(text);
+                            break;
+                        }
+                    }
+                }
+                write!(
+                    stream,
+                    "HTTP/1.0 {}\r\nContent-Type: text/plain\r\n\r\naccepted",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        let messages: Arc<Mutex<Vec<Message>>> = Default::default();
+        let handler = HttpHandler::with_mapper(recording(&messages));
+
+        let submitting = invoking(service("http", "submit", &url), handler.clone(), Some(200));
+        finish(&submitting);
+        assert!(submitting.instance.is_in_state(&submitting.done));
+        assert_eq!(
+            messages.lock().unwrap()[0],
+            Message {
+                success: true,
+                status: Some(200),
+                headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                body: "accepted".to_string(),
+            }
+        );
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with("POST /orders HTTP/1.0\r\n"));
+        assert!(request.ends_with("\r\n\r\nORDER=42&note=gift+wrap"));
+
+        let retrying = invoking(service("http", "retry", &url), handler.clone(), None);
+        finish(&retrying);
+        assert!(retrying.instance.is_in_state(&retrying.failed));
+        assert_eq!(messages.lock().unwrap()[1].status, Some(503));
+
+        let secure = invoking(
+            service("http", "secure", "https://example.com/"),
+            handler,
+            None,
+        );
+        let _ = secure.instance.tick().unwrap();
+        assert!(secure.instance.is_in_state(&secure.failed));
+        assert_eq!(messages.lock().unwrap().len(), 2);
+    }
+}
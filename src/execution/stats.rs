@@ -0,0 +1,727 @@
+/*!
+Statistics collected by an instance as it executes, see `StateMachineInstance::stats`.
+
+Each instance counts the events it has processed, deferred, and dropped, and the transitions it
+has fired, and for each state the number of times it has been entered and the total time it has
+been active, measured by the instance's clock. The statistics are collected from the moment
+the instance is created, or from the last call to `StateMachineInstance::reset_stats`; they are
+not recorded by `StateMachineInstance::snapshot`, and so begin again for a restored instance.
+
+Each instance also measures its throughput over a rolling window, one minute unless set with
+`StateMachineInstance::with_throughput_window`, returned by `StateMachineInstance::metrics`: the
+events accepted, that is posted and queued, the steps executed, one for each dispatch that
+observers are notified of, see `ExecutionObserver::on_dispatch_started`, and the average time
+each step took. The window is
+measured by the instance's clock, and the time each step took by the system's monotonic clock,
+so that it remains meaningful where the instance's clock is controlled. The metrics of every
+instance of a manager are combined by `InstanceManager::metrics`.
+
+With the `metrics` feature a `MetricsObserver`, added to an instance with
+`StateMachineInstance::add_observer`, publishes these statistics to the
+[metrics](https://docs.rs/metrics) facade as they change. The counts are published as counters,
+and the time in each state as a histogram, in seconds, with a sample each time a state is
+exited; each metric is labeled with the `machine`, and the histogram with the `state`, by label
+where they have one, otherwise by ID.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::timers::ManualClock;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+let open = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+let mut transition = Transition::within(closed.clone(), open.clone(), region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+
+let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+let instance = StateMachineInstance::new(Arc::new(machine))
+    .unwrap()
+    .with_clock(clock.clone());
+instance.execute().unwrap();
+clock.advance(Duration::from_secs(3));
+instance.post(&Event::Signal(SignalEvent::new("push"))).unwrap();
+
+let stats = instance.stats();
+assert_eq!(stats.transitions_fired(), 2);
+assert_eq!(stats.state(&closed).unwrap().entries(), 1);
+assert_eq!(stats.state(&closed).unwrap().time_in_state(), Duration::from_secs(3));
+```
+
+*/
+
+use crate::core::ID;
+#[cfg(feature = "metrics")]
+use crate::definition::types::{Event, Identified, Labeled};
+#[cfg(feature = "metrics")]
+use crate::execution::types::{ExecutionObserver, StateMachineInstance};
+use std::collections::hash_map::Iter;
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+// ------------------------------------------------------------------------------------------------
+// Public Values
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The counter of events processed, published by `MetricsObserver`.
+///
+pub const METRIC_EVENTS_PROCESSED: &str = "uml_state_machine.events.processed";
+
+///
+/// The counter of events deferred, published by `MetricsObserver`.
+///
+pub const METRIC_EVENTS_DEFERRED: &str = "uml_state_machine.events.deferred";
+
+///
+/// The counter of events dropped, published by `MetricsObserver`.
+///
+pub const METRIC_EVENTS_DROPPED: &str = "uml_state_machine.events.dropped";
+
+///
+/// The counter of transitions fired, published by `MetricsObserver`.
+///
+pub const METRIC_TRANSITIONS_FIRED: &str = "uml_state_machine.transitions.fired";
+
+///
+/// The histogram of the time, in seconds, of each visit to a state, published by
+/// `MetricsObserver`.
+///
+pub const METRIC_TIME_IN_STATE: &str = "uml_state_machine.state.time";
+
+///
+/// The window over which an instance measures its throughput, unless set with
+/// `StateMachineInstance::with_throughput_window`.
+///
+pub const DEFAULT_THROUGHPUT_WINDOW: Duration = Duration::from_secs(60);
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// What an instance has done since it was created, or its statistics were last reset.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionStats {
+    pub(crate) states: HashMap<ID, StateStats>,
+    pub(crate) events_processed: u64,
+    pub(crate) events_deferred: u64,
+    pub(crate) events_dropped: u64,
+    pub(crate) transitions_fired: u64,
+}
+
+///
+/// How often, and for how long, a state has been active.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateStats {
+    pub(crate) entries: u64,
+    pub(crate) time_in_state: Duration,
+    /// When the state was last entered, while it is active.
+    pub(crate) entered_at: Option<SystemTime>,
+}
+
+///
+/// The throughput of an instance, or of the instances of a manager, over the most recent
+/// window, see `StateMachineInstance::metrics`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ThroughputMetrics {
+    pub(crate) window: Duration,
+    pub(crate) events_accepted: u64,
+    pub(crate) steps_executed: u64,
+    /// The total time taken by the steps executed.
+    pub(crate) step_time: Duration,
+}
+
+///
+/// Publishes the statistics of the instances it observes to the `metrics` facade; one observer
+/// may be shared by many instances.
+///
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct MetricsObserver {
+    /// What has been published so far, by the ID of the instance.
+    published: Mutex<HashMap<ID, Published>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl ExecutionStats {
+    ///
+    /// The statistics of the state `id`, if it has been active.
+    ///
+    pub fn state(&self, id: &ID) -> Option<&StateStats> {
+        self.states.get(id)
+    }
+
+    pub fn states(&self) -> Iter<'_, ID, StateStats> {
+        self.states.iter()
+    }
+
+    ///
+    /// The number of events dispatched, including those that were deferred or dropped.
+    ///
+    pub fn events_processed(&self) -> u64 {
+        self.events_processed
+    }
+
+    pub fn events_deferred(&self) -> u64 {
+        self.events_deferred
+    }
+
+    ///
+    /// The number of events that enabled no transition and were not deferred, or that were
+    /// discarded, unprocessed, when the instance completed.
+    ///
+    pub fn events_dropped(&self) -> u64 {
+        self.events_dropped
+    }
+
+    ///
+    /// The number of transitions fired, including those from pseudostates that are part of a
+    /// compound transition.
+    ///
+    pub fn transitions_fired(&self) -> u64 {
+        self.transitions_fired
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    pub(crate) fn entered(&mut self, state: &ID, now: SystemTime) {
+        let stats = self.states.entry(state.clone()).or_default();
+        stats.entries += 1;
+        stats.entered_at = Some(now);
+    }
+
+    pub(crate) fn exited(&mut self, state: &ID, now: SystemTime) {
+        if let Some(stats) = self.states.get_mut(state) {
+            if let Some(entered_at) = stats.entered_at.take() {
+                stats.time_in_state += elapsed(entered_at, now);
+            }
+        }
+    }
+
+    ///
+    /// Count the time the state `state` is active from `now`, without counting an entry, as for
+    /// the active states of a restored instance.
+    ///
+    pub(crate) fn active_since(&mut self, state: &ID, now: SystemTime) {
+        self.states.entry(state.clone()).or_default().entered_at = Some(now);
+    }
+
+    ///
+    /// These statistics with the time, up to `now`, that each active state has been active.
+    ///
+    pub(crate) fn as_of(&self, now: SystemTime) -> Self {
+        let mut stats = self.clone();
+        for state in stats.states.values_mut() {
+            if let Some(entered_at) = state.entered_at {
+                state.time_in_state += elapsed(entered_at, now);
+            }
+        }
+        stats
+    }
+
+    ///
+    /// Discard all statistics, counting the time each active state is active from `now`.
+    ///
+    pub(crate) fn reset(&mut self, now: SystemTime) {
+        let active: Vec<ID> = self
+            .states
+            .iter()
+            .filter(|(_, stats)| stats.entered_at.is_some())
+            .map(|(state, _)| state.clone())
+            .collect();
+        *self = Default::default();
+        for state in active {
+            self.active_since(&state, now);
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateStats {
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    ///
+    /// The total time the state has been active, including the time so far where it is active.
+    ///
+    pub fn time_in_state(&self) -> Duration {
+        self.time_in_state
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.entered_at.is_some()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl ThroughputMetrics {
+    ///
+    /// The length of the window measured; for a manager, the longest window of its instances.
+    ///
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    ///
+    /// The number of events posted, and queued, within the window.
+    ///
+    pub fn events_accepted(&self) -> u64 {
+        self.events_accepted
+    }
+
+    ///
+    /// The number of steps executed within the window, one for the start of the instance, for
+    /// each event dispatched, and for each completion without an event.
+    ///
+    pub fn steps_executed(&self) -> u64 {
+        self.steps_executed
+    }
+
+    ///
+    /// The average time taken by the steps executed within the window, `None` where there were
+    /// none.
+    ///
+    pub fn average_step_latency(&self) -> Option<Duration> {
+        if self.steps_executed == 0 {
+            None
+        } else {
+            Some(self.step_time.div_f64(self.steps_executed as f64))
+        }
+    }
+
+    pub fn events_per_second(&self) -> f64 {
+        per_second(self.events_accepted, self.window)
+    }
+
+    pub fn steps_per_second(&self) -> f64 {
+        per_second(self.steps_executed, self.window)
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    ///
+    /// Add the metrics of another instance, `other`, to these.
+    ///
+    pub(crate) fn combine(&mut self, other: &Self) {
+        self.window = self.window.max(other.window);
+        self.events_accepted += other.events_accepted;
+        self.steps_executed += other.steps_executed;
+        self.step_time += other.step_time;
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self::new(DEFAULT_THROUGHPUT_WINDOW)
+    }
+}
+
+impl Throughput {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            accepted: Default::default(),
+            steps: Default::default(),
+        }
+    }
+
+    pub(crate) fn accepted(&mut self, now: SystemTime) {
+        self.accepted.push_back(now);
+        self.expire(now);
+    }
+
+    pub(crate) fn stepped(&mut self, now: SystemTime, latency: Duration) {
+        self.steps.push_back((now, latency));
+        self.expire(now);
+    }
+
+    ///
+    /// The metrics of the window ending `now`.
+    ///
+    pub(crate) fn as_of(&mut self, now: SystemTime) -> ThroughputMetrics {
+        self.expire(now);
+        ThroughputMetrics {
+            window: self.window,
+            events_accepted: self.accepted.len() as u64,
+            steps_executed: self.steps.len() as u64,
+            step_time: self.steps.iter().map(|(_, latency)| *latency).sum(),
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.window);
+    }
+
+    ///
+    /// Discard what happened before the window ending `now`.
+    ///
+    fn expire(&mut self, now: SystemTime) {
+        let start = now
+            .checked_sub(self.window)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        while self.accepted.front().is_some_and(|time| *time < start) {
+            let _ = self.accepted.pop_front();
+        }
+        while self.steps.front().is_some_and(|(time, _)| *time < start) {
+            let _ = self.steps.pop_front();
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "metrics")]
+impl ExecutionObserver for MetricsObserver {
+    fn on_dispatch_finished(&self, instance: &StateMachineInstance, _event: Option<&Event>) {
+        self.publish_counts(instance)
+    }
+
+    fn on_state_exited(&self, instance: &StateMachineInstance, state: &ID) {
+        let time_in_state = instance
+            .stats()
+            .state(state)
+            .map(StateStats::time_in_state)
+            .unwrap_or_default();
+        let visit = {
+            let mut published = self.published.lock().unwrap();
+            let published = published
+                .entry(instance.id().clone())
+                .or_default()
+                .time_in_state
+                .entry(state.clone())
+                .or_default();
+            // the statistics may have been reset since the last visit
+            let visit = time_in_state
+                .checked_sub(*published)
+                .unwrap_or(time_in_state);
+            *published = time_in_state;
+            visit
+        };
+        let state = instance
+            .index
+            .vertices
+            .get(state)
+            .and_then(|vertex| vertex.label().clone())
+            .unwrap_or_else(|| state.to_string());
+        metrics::histogram!(
+            METRIC_TIME_IN_STATE,
+            "machine" => machine_label(instance),
+            "state" => state
+        )
+        .record(visit.as_secs_f64());
+    }
+
+    fn on_done(&self, instance: &StateMachineInstance) {
+        self.publish_counts(instance)
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsObserver {
+    ///
+    /// Publish the increase in each count since it was last published for `instance`.
+    ///
+    fn publish_counts(&self, instance: &StateMachineInstance) {
+        let stats = instance.stats();
+        let machine = machine_label(instance);
+        let mut published = self.published.lock().unwrap();
+        let published = published.entry(instance.id().clone()).or_default();
+        for (name, count, published) in [
+            (
+                METRIC_EVENTS_PROCESSED,
+                stats.events_processed,
+                &mut published.events_processed,
+            ),
+            (
+                METRIC_EVENTS_DEFERRED,
+                stats.events_deferred,
+                &mut published.events_deferred,
+            ),
+            (
+                METRIC_EVENTS_DROPPED,
+                stats.events_dropped,
+                &mut published.events_dropped,
+            ),
+            (
+                METRIC_TRANSITIONS_FIRED,
+                stats.transitions_fired,
+                &mut published.transitions_fired,
+            ),
+        ] {
+            // the statistics may have been reset since they were last published
+            let increase = count.checked_sub(*published).unwrap_or(count);
+            *published = count;
+            if increase > 0 {
+                metrics::counter!(name, "machine" => machine.clone()).increment(increase);
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// When each event was accepted, and each step executed, within the window ending with the
+/// last of them.
+///
+#[derive(Clone, Debug)]
+pub(crate) struct Throughput {
+    pub(crate) window: Duration,
+    accepted: VecDeque<SystemTime>,
+    /// When each step started, and how long it took.
+    steps: VecDeque<(SystemTime, Duration)>,
+}
+
+///
+/// The statistics of one instance as last published by a `MetricsObserver`.
+///
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct Published {
+    events_processed: u64,
+    events_deferred: u64,
+    events_dropped: u64,
+    transitions_fired: u64,
+    time_in_state: HashMap<ID, Duration>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "metrics")]
+fn machine_label(instance: &StateMachineInstance) -> String {
+    let chart = instance.chart();
+    chart
+        .label()
+        .clone()
+        .unwrap_or_else(|| chart.id().to_string())
+}
+
+fn elapsed(from: SystemTime, to: SystemTime) -> Duration {
+    to.duration_since(from).unwrap_or_default()
+}
+
+fn per_second(count: u64, window: Duration) -> f64 {
+    if window.is_zero() {
+        0.0
+    } else {
+        count as f64 / window.as_secs_f64()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::{HasRegions, Identified, State, StateMachine, Trigger};
+    use crate::execution::fixtures::{add_transition, signal};
+    use crate::execution::timers::ManualClock;
+    use crate::execution::types::StateMachineInstance;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_stats() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        let mut busy = State::within(region.id().clone());
+        busy.add_deferrable_trigger(Trigger::signal("job"));
+        let busy_id = busy.id().clone();
+        region.add_state(busy);
+        region.new_transition(initial, idle.clone());
+        add_transition(region, &idle, &busy_id, Some("start"));
+        add_transition(region, &busy_id, &idle, Some("stop"));
+        add_transition(region, &idle, &idle, Some("job"));
+
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let instance = StateMachineInstance::new(Arc::new(machine))
+            .unwrap()
+            .with_clock(clock.clone());
+        instance.execute().unwrap();
+        clock.advance(Duration::from_secs(2));
+        instance.post(&signal("start")).unwrap();
+        instance.post(&signal("job")).unwrap();
+        instance.post(&signal("ignored")).unwrap();
+        clock.advance(Duration::from_secs(5));
+        instance.post(&signal("stop")).unwrap();
+        clock.advance(Duration::from_secs(1));
+
+        let stats = instance.stats();
+        assert_eq!(stats.events_processed(), 5);
+        assert_eq!(stats.events_deferred(), 1);
+        assert_eq!(stats.events_dropped(), 1);
+        assert_eq!(stats.transitions_fired(), 4);
+        // the recalled job fires an internal transition, which does not re-enter idle.
+        let idle_stats = stats.state(&idle).unwrap();
+        assert_eq!(idle_stats.entries(), 2);
+        assert!(idle_stats.is_active());
+        assert_eq!(idle_stats.time_in_state(), Duration::from_secs(3));
+        let busy_stats = stats.state(&busy_id).unwrap();
+        assert_eq!(busy_stats.entries(), 1);
+        assert!(!busy_stats.is_active());
+        assert_eq!(busy_stats.time_in_state(), Duration::from_secs(5));
+
+        instance.reset_stats();
+        clock.advance(Duration::from_secs(4));
+        let stats = instance.stats();
+        assert_eq!(stats.events_processed(), 0);
+        assert_eq!(stats.states().count(), 1);
+        assert_eq!(stats.state(&idle).unwrap().entries(), 0);
+        assert_eq!(
+            stats.state(&idle).unwrap().time_in_state(),
+            Duration::from_secs(4)
+        );
+    }
+
+    #[test]
+    fn test_throughput() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        let busy = region.new_simple_state();
+        region.new_transition(initial, idle.clone());
+        add_transition(region, &idle, &busy, Some("start"));
+        add_transition(region, &busy, &idle, Some("stop"));
+
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let instance = StateMachineInstance::new(Arc::new(machine))
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_throughput_window(Duration::from_secs(10));
+        assert_eq!(instance.metrics().steps_executed(), 0);
+        assert_eq!(instance.metrics().average_step_latency(), None);
+        instance.execute().unwrap();
+        clock.advance(Duration::from_secs(5));
+        for name in &["start", "ignored", "stop", "start"] {
+            instance.post(&signal(name)).unwrap();
+        }
+
+        let metrics = instance.metrics();
+        assert_eq!(metrics.window(), Duration::from_secs(10));
+        assert_eq!(metrics.events_accepted(), 4);
+        assert_eq!(metrics.steps_executed(), 5);
+        assert!(metrics.average_step_latency().is_some());
+        assert_eq!(metrics.events_per_second(), 0.4);
+        assert_eq!(metrics.steps_per_second(), 0.5);
+
+        // the start of the instance leaves the window first
+        clock.advance(Duration::from_secs(6));
+        instance.post(&signal("stop")).unwrap();
+        let metrics = instance.metrics();
+        assert_eq!(metrics.events_accepted(), 5);
+        assert_eq!(metrics.steps_executed(), 5);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(instance.metrics().events_accepted(), 1);
+
+        instance.reset_stats();
+        assert_eq!(
+            instance.metrics(),
+            ThroughputMetrics {
+                window: Duration::from_secs(10),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let machine = StateMachine::labeled("door");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut closed = State::within(region.id().clone());
+        closed.set_label("closed");
+        let closed_id = closed.id().clone();
+        region.add_state(closed);
+        let open = region.new_simple_state();
+        region.new_transition(initial, closed_id.clone());
+        add_transition(region, &closed_id, &open, Some("push"));
+        add_transition(region, &open, &closed_id, Some("pull"));
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let stats = metrics::with_local_recorder(&recorder, || {
+            let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+            let instance = StateMachineInstance::new(Arc::new(machine))
+                .unwrap()
+                .with_clock(clock.clone());
+            instance.add_observer(Arc::new(MetricsObserver::default()));
+            instance.execute().unwrap();
+            clock.advance(Duration::from_secs(2));
+            instance.post(&signal("push")).unwrap();
+            instance.post(&signal("ignored")).unwrap();
+            instance.post(&signal("pull")).unwrap();
+            clock.advance(Duration::from_secs(3));
+            instance.post(&signal("push")).unwrap();
+            instance.stats()
+        });
+
+        let metrics: HashMap<String, DebugValue> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let labels: Vec<String> = key
+                    .key()
+                    .labels()
+                    .map(|label| format!("{}={}", label.key(), label.value()))
+                    .collect();
+                (
+                    format!("{}{{{}}}", key.key().name(), labels.join(",")),
+                    value,
+                )
+            })
+            .collect();
+        let counter = |name: &str| match metrics.get(&format!("{}{{machine=door}}", name)) {
+            Some(DebugValue::Counter(count)) => *count,
+            _ => 0,
+        };
+        assert_eq!(counter(METRIC_EVENTS_PROCESSED), stats.events_processed());
+        assert_eq!(counter(METRIC_EVENTS_PROCESSED), 4);
+        assert_eq!(counter(METRIC_EVENTS_DEFERRED), 0);
+        assert_eq!(counter(METRIC_EVENTS_DROPPED), 1);
+        assert_eq!(counter(METRIC_TRANSITIONS_FIRED), stats.transitions_fired());
+        match metrics.get(&format!(
+            "{}{{machine=door,state=closed}}",
+            METRIC_TIME_IN_STATE
+        )) {
+            Some(DebugValue::Histogram(visits)) => {
+                let visits: Vec<f64> = visits.iter().map(|visit| visit.0).collect();
+                assert_eq!(visits, vec![2.0, 3.0]);
+            }
+            _ => panic!("no time in state for closed"),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+/*!
+The timer service that implements time events, §13.3.3.1 **TimeEvent**, for an instance.
+
+When a state is entered a timer is scheduled for each distinct time event that triggers a
+transition from it; relative time events are measured from the time of entry, absolute time
+events are due at their time. Timers are cancelled when the state is exited. As the instance is
+synchronous timers do not fire by themselves, the owner of the instance calls
+`StateMachineInstance::tick` and any timers that have elapsed, according to the instance's
+`Clock`, are dispatched in the order they were due. `StateMachineInstance::next_timer` returns the
+time at which the next timer is due, so that callers know how long they may wait.
+
+An event may also be sent to the instance after a delay, as for the SCXML `<send>` element with
+a `delay`, with `StateMachineInstance::send_after`, which returns a `SendId` that may be given
+to `StateMachineInstance::cancel`, as for SCXML `<cancel>`, until the event has been sent.
+Unlike time events these delayed sends are not owned by a state, and are not cancelled when it
+is exited; once due they are dispatched as external events, in the order they, and any timers,
+were due. The delayed sends that are pending when the instance completes are cancelled.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::timers::ManualClock;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::default();
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let waiting = region.new_simple_state();
+let timed_out = region.new_final_state();
+region.new_transition(initial, waiting.clone());
+let mut transition = Transition::within(waiting, timed_out, region.id().clone());
+transition.add_trigger(Trigger::after(Duration::from_secs(5)));
+region.add_transition(transition);
+
+let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+let instance = StateMachineInstance::new(Arc::new(machine))
+    .unwrap()
+    .with_clock(clock.clone());
+instance.execute().unwrap();
+assert_eq!(instance.next_timer(), Some(SystemTime::UNIX_EPOCH + Duration::from_secs(5)));
+
+clock.advance(Duration::from_secs(4));
+assert_eq!(instance.tick().unwrap(), 0);
+clock.advance(Duration::from_secs(1));
+assert_eq!(instance.tick().unwrap(), 1);
+assert!(instance.is_done());
+```
+
+*/
+
+use crate::core::{Context, ID};
+use crate::definition::types::{Event, TimeEvent};
+use std::cell::RefCell;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The source of the current time for an instance's timers; a clock may be shared by instances
+/// on different threads.
+///
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+///
+/// Identifies an event sent after a delay, see `StateMachineInstance::send_after`; IDs are
+/// unique within an instance.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SendId(pub(crate) u64);
+
+///
+/// A clock that reports the system time, this is the default for all instances.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock {}
+
+///
+/// A clock that only moves when told to, primarily for testing.
+///
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Display for SendId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Debug for TimerService {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimerService")
+            .field("timers", &self.timers)
+            .field("sends", &self.sends)
+            .field("next_send", &self.next_send)
+            .finish()
+    }
+}
+
+impl Default for TimerService {
+    fn default() -> Self {
+        Self::with_clock(Arc::new(SystemClock::default()))
+    }
+}
+
+impl TimerService {
+    pub(crate) fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            timers: Default::default(),
+            sends: Default::default(),
+            next_send: Default::default(),
+        }
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    ///
+    /// Schedule a timer for `event`, a time event, on behalf of the state `state`; a timer that
+    /// is already scheduled for the same state and event is left unchanged.
+    ///
+    pub(crate) fn schedule(&self, state: &ID, event: &Event) {
+        let due = match event {
+            Event::Time(TimeEvent::Relative(duration)) => self.now() + *duration,
+            Event::Time(TimeEvent::Absolute(time)) => *time,
+            _ => return,
+        };
+        let mut timers = self.timers.borrow_mut();
+        if !timers
+            .iter()
+            .any(|timer| &timer.state == state && &timer.event == event)
+        {
+            timers.push(Timer {
+                state: state.clone(),
+                event: event.clone(),
+                due,
+            });
+        }
+    }
+
+    pub(crate) fn cancel(&self, state: &ID) {
+        self.timers
+            .borrow_mut()
+            .retain(|timer| &timer.state != state)
+    }
+
+    ///
+    /// Cancel every timer, and every delayed send.
+    ///
+    pub(crate) fn clear(&self) {
+        self.timers.borrow_mut().clear();
+        self.sends.borrow_mut().clear()
+    }
+
+    ///
+    /// Remove and return the timers that have elapsed, in the order they were due.
+    ///
+    pub(crate) fn elapsed(&self) -> Vec<Timer> {
+        let now = self.now();
+        let mut timers = self.timers.borrow_mut();
+        let (mut elapsed, pending): (Vec<Timer>, Vec<Timer>) =
+            timers.drain(..).partition(|timer| timer.due <= now);
+        *timers = pending;
+        elapsed.sort_by_key(|timer| timer.due);
+        elapsed
+    }
+
+    ///
+    /// The timers scheduled, in the order they were scheduled.
+    ///
+    pub(crate) fn scheduled(&self) -> Vec<Timer> {
+        self.timers.borrow().clone()
+    }
+
+    ///
+    /// Replace the timers scheduled with `timers`, as returned by `scheduled`.
+    ///
+    pub(crate) fn restore(&self, timers: Vec<Timer>) {
+        let _ = self.timers.replace(timers);
+    }
+
+    pub(crate) fn next_due(&self) -> Option<SystemTime> {
+        let timers = self.timers.borrow();
+        let sends = self.sends.borrow();
+        timers
+            .iter()
+            .map(|timer| timer.due)
+            .chain(sends.iter().map(|send| send.due))
+            .min()
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    ///
+    /// Schedule `event`, with `payload`, to be sent after `delay`.
+    ///
+    pub(crate) fn schedule_send(
+        &self,
+        delay: Duration,
+        event: &Event,
+        payload: Option<Context>,
+    ) -> SendId {
+        let id = SendId(self.next_send.replace_with(|next| *next + 1));
+        self.sends.borrow_mut().push(DelayedSend {
+            id,
+            event: event.clone(),
+            payload,
+            due: self.now() + delay,
+        });
+        id
+    }
+
+    ///
+    /// Cancel the delayed send `id`, returning `false` where it had already been sent, or
+    /// cancelled.
+    ///
+    pub(crate) fn cancel_send(&self, id: SendId) -> bool {
+        let mut sends = self.sends.borrow_mut();
+        let before = sends.len();
+        sends.retain(|send| send.id != id);
+        sends.len() != before
+    }
+
+    ///
+    /// Remove and return the delayed sends that are due, in the order they were due.
+    ///
+    pub(crate) fn elapsed_sends(&self) -> Vec<DelayedSend> {
+        let now = self.now();
+        let mut sends = self.sends.borrow_mut();
+        let (mut elapsed, pending): (Vec<DelayedSend>, Vec<DelayedSend>) =
+            sends.drain(..).partition(|send| send.due <= now);
+        *sends = pending;
+        elapsed.sort_by_key(|send| send.due);
+        elapsed
+    }
+
+    ///
+    /// The delayed sends pending, in the order they were scheduled.
+    ///
+    pub(crate) fn scheduled_sends(&self) -> Vec<DelayedSend> {
+        self.sends.borrow().clone()
+    }
+
+    ///
+    /// The ID that will be given to the next delayed send.
+    ///
+    pub(crate) fn next_send(&self) -> u64 {
+        *self.next_send.borrow()
+    }
+
+    ///
+    /// Replace the delayed sends pending with `sends`, as returned by `scheduled_sends`, with
+    /// IDs then issued from `next_send`, or after the greatest of theirs.
+    ///
+    pub(crate) fn restore_sends(&self, sends: Vec<DelayedSend>, next_send: u64) {
+        let after = sends
+            .iter()
+            .map(|send| send.id.0 + 1)
+            .max()
+            .unwrap_or_default();
+        let _ = self.next_send.replace(next_send.max(after));
+        let _ = self.sends.replace(sends);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The timers scheduled for the active states of an instance.
+///
+#[derive(Clone)]
+pub(crate) struct TimerService {
+    clock: Arc<dyn Clock>,
+    timers: RefCell<Vec<Timer>>,
+    sends: RefCell<Vec<DelayedSend>>,
+    next_send: RefCell<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Timer {
+    pub(crate) state: ID,
+    pub(crate) event: Event,
+    pub(crate) due: SystemTime,
+}
+
+///
+/// An event sent to the instance itself after a delay.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DelayedSend {
+    pub(crate) id: SendId,
+    pub(crate) event: Event,
+    pub(crate) payload: Option<Context>,
+    pub(crate) due: SystemTime,
+}
@@ -0,0 +1,360 @@
+/*!
+Records what an instance did, event by event, as an `ExecutionTrace`.
+
+A `TraceRecorder` is an observer, see `StateMachineInstance::add_observer`, that adds a
+`TraceEntry` to its trace for each event dispatched, and for the start of the instance. Each
+entry records when the event was dispatched, according to the instance's clock, the event, the
+transitions fired as a result, the active states before and after, and a hash of the instance's
+context once the event had been processed. The hash allows two runs to be compared without
+recording the context itself; it is the same for equal contexts, and is computed with a fixed
+algorithm, see `Context::stable_hash`, so that traces recorded by different builds, or on
+different platforms, may be compared.
+
+With the `format-native` feature a trace may be written to, and read from, JSON with
+`ExecutionTrace::to_json` and `ExecutionTrace::from_json`.
+
+# Example
+
+```rust
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::trace::TraceRecorder;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+let open = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+let mut transition = Transition::within(closed.clone(), open.clone(), region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+
+let recorder = Arc::new(TraceRecorder::default());
+let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+instance.add_observer(recorder.clone());
+instance.execute().unwrap();
+instance.post(&Event::Signal(SignalEvent::new("push"))).unwrap();
+
+let trace = recorder.trace();
+assert_eq!(trace.len(), 2);
+let pushed = trace.entries().last().unwrap();
+assert_eq!(pushed.before(), &[closed]);
+assert_eq!(pushed.after(), &[open]);
+```
+
+*/
+
+use crate::core::{Context, ID};
+use crate::definition::types::{Event, Transition};
+use crate::execution::types::{ExecutionObserver, FiredTransition, StateMachineInstance};
+use std::slice::Iter;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[cfg(feature = "format-native")]
+use crate::error::Result;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The entries recorded by a `TraceRecorder`, in the order the events were dispatched.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionTrace {
+    pub(crate) entries: Vec<TraceEntry>,
+}
+
+///
+/// The dispatch of a single event, or the start of the instance where there is no event.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub(crate) time: SystemTime,
+    pub(crate) event: Option<Event>,
+    pub(crate) fired: Vec<FiredTransition>,
+    pub(crate) before: Vec<ID>,
+    pub(crate) after: Vec<ID>,
+    pub(crate) context_hash: u64,
+}
+
+///
+/// An observer that records an `ExecutionTrace` of each instance it is added to.
+///
+#[derive(Debug, Default)]
+pub struct TraceRecorder {
+    trace: Mutex<ExecutionTrace>,
+    current: Mutex<Vec<TraceEntry>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The hash of `context` recorded in each `TraceEntry`.
+///
+pub fn context_hash(context: &Context) -> u64 {
+    context.stable_hash()
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl ExecutionTrace {
+    pub fn entries(&self) -> Iter<'_, TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///
+    /// Write this trace as a JSON document, see `format::native` for the schema.
+    ///
+    #[cfg(feature = "format-native")]
+    pub fn to_json(&self) -> Result<String> {
+        crate::format::native::write_trace(self)
+    }
+
+    ///
+    /// Read a trace from a JSON document written by `to_json`.
+    ///
+    #[cfg(feature = "format-native")]
+    pub fn from_json(json: &str) -> Result<Self> {
+        crate::format::native::read_trace(json)
+    }
+}
+
+impl From<Vec<TraceEntry>> for ExecutionTrace {
+    fn from(entries: Vec<TraceEntry>) -> Self {
+        Self { entries }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl TraceEntry {
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    ///
+    /// The event dispatched, `None` for the start of the instance and for the completion of
+    /// states whose do-activity had finished.
+    ///
+    pub fn event(&self) -> Option<&Event> {
+        self.event.as_ref()
+    }
+
+    pub fn fired(&self) -> Iter<'_, FiredTransition> {
+        self.fired.iter()
+    }
+
+    ///
+    /// The active states, in document order, before the event was dispatched.
+    ///
+    pub fn before(&self) -> &[ID] {
+        &self.before
+    }
+
+    ///
+    /// The active states, in document order, once the event had been processed.
+    ///
+    pub fn after(&self) -> &[ID] {
+        &self.after
+    }
+
+    ///
+    /// The `context_hash` of the instance's context once the event had been processed.
+    ///
+    pub fn context_hash(&self) -> u64 {
+        self.context_hash
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl ExecutionObserver for TraceRecorder {
+    fn on_dispatch_started(&self, instance: &StateMachineInstance, event: Option<&Event>) {
+        self.current.lock().unwrap().push(TraceEntry {
+            time: instance.timers.now(),
+            event: event.cloned(),
+            fired: Default::default(),
+            before: instance.active_states(),
+            after: Default::default(),
+            context_hash: 0,
+        })
+    }
+
+    fn on_dispatch_finished(&self, instance: &StateMachineInstance, _event: Option<&Event>) {
+        if let Some(mut entry) = self.current.lock().unwrap().pop() {
+            entry.after = instance.active_states();
+            entry.context_hash = context_hash(&instance.context());
+            self.trace.lock().unwrap().entries.push(entry);
+        }
+    }
+
+    fn on_transition_fired(&self, _instance: &StateMachineInstance, transition: &Transition) {
+        if let Some(entry) = self.current.lock().unwrap().last_mut() {
+            entry.fired.push(transition.into());
+        }
+    }
+}
+
+impl TraceRecorder {
+    ///
+    /// A copy of the trace recorded so far.
+    ///
+    pub fn trace(&self) -> ExecutionTrace {
+        self.trace.lock().unwrap().clone()
+    }
+
+    ///
+    /// Return the trace recorded so far, and start a new one.
+    ///
+    pub fn take(&self) -> ExecutionTrace {
+        std::mem::take(&mut *self.trace.lock().unwrap())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::{Compound, FieldPath, FieldValue};
+    use crate::definition::types::{HasRegions, SignalEvent, StateMachine};
+    use crate::execution::fixtures::add_transition;
+    use crate::execution::timers::ManualClock;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn counter() -> (StateMachine, ID, ID) {
+        let machine = StateMachine::labeled("counter");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let idle = region.new_simple_state();
+        let busy = region.new_simple_state();
+        region.new_transition(initial, idle.clone());
+        add_transition(region, &idle, &busy, Some("go"));
+        add_transition(region, &busy, &idle, Some("stop"));
+        (machine, idle, busy)
+    }
+
+    fn run(context: i64) -> ExecutionTrace {
+        let (machine, _, _) = counter();
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let recorder = Arc::new(TraceRecorder::default());
+        let instance = StateMachineInstance::new(Arc::new(machine))
+            .unwrap()
+            .with_clock(clock.clone());
+        instance.add_observer(recorder.clone());
+        instance.execute().unwrap();
+        for signal in &["go", "ignored", "stop"] {
+            clock.advance(Duration::from_secs(1));
+            instance
+                .update_context(|c| {
+                    c.insert(
+                        FieldPath::from_str("total").unwrap(),
+                        FieldValue::Integer(context),
+                    )
+                })
+                .unwrap();
+            instance
+                .post(&Event::Signal(SignalEvent::new(signal)))
+                .unwrap();
+        }
+        recorder.take()
+    }
+
+    #[test]
+    fn test_context_hash_is_stable() {
+        let context = Context::default();
+        assert_eq!(context_hash(&context), 0xbf2f_d77e_fb5a_3d99);
+
+        context.insert(FieldPath::from_str("name").unwrap(), "door".into());
+        context.insert(FieldPath::from_str("count").unwrap(), 3i64.into());
+        assert_eq!(context_hash(&context), 0x5144_3c3d_e468_f78b);
+        assert_eq!(context_hash(&context.deep_clone()), context_hash(&context));
+        context.insert(FieldPath::from_str("count").unwrap(), 4i64.into());
+        assert_ne!(context_hash(&context), 0x5144_3c3d_e468_f78b);
+    }
+
+    #[test]
+    fn test_trace_recorder() {
+        let (machine, idle, busy) = counter();
+        let recorder = Arc::new(TraceRecorder::default());
+        let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+        instance.add_observer(recorder.clone());
+        instance.execute().unwrap();
+        instance
+            .post(&Event::Signal(SignalEvent::new("go")))
+            .unwrap();
+        instance
+            .post(&Event::Signal(SignalEvent::new("ignored")))
+            .unwrap();
+
+        let trace = recorder.trace();
+        let entries: Vec<&TraceEntry> = trace.entries().collect();
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].event(), None);
+        assert!(entries[0].before().is_empty());
+        assert_eq!(entries[0].after(), vec![idle.clone()]);
+        assert_eq!(entries[0].fired().count(), 1);
+
+        assert_eq!(
+            entries[1].event(),
+            Some(&Event::Signal(SignalEvent::new("go")))
+        );
+        assert_eq!(entries[1].before(), vec![idle.clone()]);
+        assert_eq!(entries[1].after(), vec![busy.clone()]);
+        let fired: Vec<&FiredTransition> = entries[1].fired().collect();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].source(), &idle);
+        assert_eq!(fired[0].target(), &busy);
+
+        assert_eq!(entries[2].fired().count(), 0);
+        assert_eq!(entries[2].before(), entries[2].after());
+        assert_eq!(entries[2].context_hash(), context_hash(&Context::default()));
+    }
+
+    #[test]
+    fn test_traces_of_the_same_run_differ_only_by_ids() {
+        let lhs = run(1);
+        let rhs = run(1);
+        let hashes = |trace: &ExecutionTrace| -> Vec<(SystemTime, u64)> {
+            trace
+                .entries()
+                .map(|entry| (entry.time(), entry.context_hash()))
+                .collect()
+        };
+        assert_eq!(hashes(&lhs), hashes(&rhs));
+        assert_ne!(hashes(&lhs), hashes(&run(2)));
+        assert_eq!(
+            lhs.entries().last().unwrap().time(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(3)
+        );
+    }
+
+    #[cfg(feature = "format-native")]
+    #[test]
+    fn test_trace_json_round_trip() {
+        let trace = run(1);
+        let json = trace.to_json().unwrap();
+        assert_eq!(ExecutionTrace::from_json(&json).unwrap(), trace);
+    }
+}
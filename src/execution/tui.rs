@@ -0,0 +1,591 @@
+/*!
+A terminal dashboard for debugging instances as they run, with
+[ratatui](https://ratatui.rs/); this module requires the `tui` feature.
+
+A `Dashboard` shows, for the selected instance of an `InstanceManager`, the active
+configuration, as a tree of the active states, the most recent entries of its trace, see
+`execution::trace`, its context, and an input box. The name of a signal typed into the input
+box is posted to the instance on `Enter`; `Tab` and `BackTab` select the next and previous
+instance, and `Esc` quits. A dashboard may also be created for a single instance, with
+`Dashboard::new`.
+
+`Dashboard::run` takes over the terminal, starting any instance that is new, and ticks the
+instances, between key presses, until the dashboard is quit. Hosts with a terminal of their own
+may instead pass key events to `Dashboard::handle_key`, and draw the dashboard into a frame with
+`Dashboard::draw`.
+
+# Example
+
+```rust
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::Terminal;
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::tui::Dashboard;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+let open = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+let mut transition = Transition::within(closed.clone(), open.clone(), region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+
+let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+instance.execute().unwrap();
+let mut dashboard = Dashboard::new(instance).unwrap();
+for c in "push".chars() {
+    dashboard.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+}
+dashboard.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+assert!(dashboard.selected().unwrap().is_in_state(&open));
+
+let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+```
+
+*/
+
+use crate::core::context::Compound;
+use crate::core::{FieldValue, ID};
+use crate::definition::types::{Event, Labeled, SignalEvent};
+use crate::error::Result;
+use crate::execution::manager::InstanceManager;
+use crate::execution::trace::{TraceEntry, TraceRecorder};
+use crate::execution::types::StateMachineInstance;
+use ratatui::backend::Backend;
+use ratatui::crossterm::event::{self, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// A terminal dashboard showing the instances of a manager, one at a time.
+///
+#[derive(Debug)]
+pub struct Dashboard {
+    manager: InstanceManager,
+    recorders: HashMap<ID, Arc<TraceRecorder>>,
+    selected: usize,
+    input: String,
+    /// The outcome of the last event posted, or error, shown beneath the input box.
+    message: Option<String>,
+    trace_length: usize,
+    tick_interval: Duration,
+    quit: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Values
+// ------------------------------------------------------------------------------------------------
+
+const HELP: &str = "Enter: post signal  Tab: next instance  Esc: quit";
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Dashboard {
+    ///
+    /// Create a dashboard for the single instance `instance`.
+    ///
+    pub fn new(instance: StateMachineInstance) -> Result<Self> {
+        let mut manager = InstanceManager::default();
+        let _ = manager.add(instance)?;
+        Ok(Self::with_manager(manager))
+    }
+
+    ///
+    /// Create a dashboard for the instances of `manager`; a `TraceRecorder` is added to each, so
+    /// only what happens from now on is shown in its trace.
+    ///
+    pub fn with_manager(manager: InstanceManager) -> Self {
+        let recorders = manager
+            .instances()
+            .map(|instance| {
+                let recorder = Arc::new(TraceRecorder::default());
+                instance.add_observer(recorder.clone());
+                (instance.id().clone(), recorder)
+            })
+            .collect();
+        Self {
+            manager,
+            recorders,
+            selected: 0,
+            input: Default::default(),
+            message: None,
+            trace_length: 100,
+            tick_interval: Duration::from_millis(100),
+            quit: false,
+        }
+    }
+
+    ///
+    /// Show at most `trace_length` of the most recent trace entries, the default is 100.
+    ///
+    pub fn with_trace_length(mut self, trace_length: usize) -> Self {
+        self.trace_length = trace_length;
+        self
+    }
+
+    ///
+    /// Tick the instances every `tick_interval`, while waiting for key presses, in `run`; the
+    /// default is 100ms.
+    ///
+    pub fn with_tick_interval(mut self, tick_interval: Duration) -> Self {
+        self.tick_interval = tick_interval;
+        self
+    }
+
+    pub fn manager(&self) -> &InstanceManager {
+        &self.manager
+    }
+
+    ///
+    /// The instance currently shown, `None` where the manager has no instances.
+    ///
+    pub fn selected(&self) -> Option<&StateMachineInstance> {
+        self.manager.instances().nth(self.selected)
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    ///
+    /// Returns `true` once `Esc`, or `Ctrl-C`, has been pressed.
+    ///
+    pub fn is_quit(&self) -> bool {
+        self.quit
+    }
+
+    ///
+    /// Take over the terminal, start any instance that is new, and show the dashboard until it
+    /// is quit; the terminal is restored before returning.
+    ///
+    pub fn run(&mut self) -> Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.run_in(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    ///
+    /// As `run`, but showing the dashboard in `terminal`, which is left as it is on return.
+    ///
+    pub fn run_in<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        if let Err(e) = self.manager.execute_all() {
+            self.message = Some(e.to_string());
+        }
+        while !self.quit {
+            let _ = terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(self.tick_interval)? {
+                if let event::Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key);
+                    }
+                }
+            }
+            self.tick();
+        }
+        Ok(())
+    }
+
+    ///
+    /// Update the input box, or act on it, for the key pressed; errors posting an event are
+    /// shown beneath the input box rather than returned.
+    ///
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                let _ = self.input.pop();
+            }
+            KeyCode::Enter => self.post(),
+            KeyCode::Tab => self.select(1),
+            KeyCode::BackTab => self.select(self.manager.len().saturating_sub(1)),
+            _ => (),
+        }
+    }
+
+    ///
+    /// Tick the timers of every instance, see `InstanceManager::tick`.
+    ///
+    pub fn tick(&mut self) {
+        if let Err(e) = self.manager.tick() {
+            self.message = Some(e.to_string());
+        }
+    }
+
+    ///
+    /// Draw the dashboard, for the selected instance, into `frame`.
+    ///
+    pub fn draw(&self, frame: &mut Frame<'_>) {
+        let [main, input, message] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(3),
+                Constraint::Length(3),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+        let [left, trace] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .areas(main);
+        let [configuration, context] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(left);
+
+        let instance = self.selected();
+        frame.render_widget(
+            List::new(
+                instance
+                    .map(|instance| self.configuration(instance))
+                    .unwrap_or_default(),
+            )
+            .block(Block::default().borders(Borders::ALL).title(self.title())),
+            configuration,
+        );
+        frame.render_widget(
+            List::new(
+                instance
+                    .map(|instance| self.context(instance))
+                    .unwrap_or_default(),
+            )
+            .block(Block::default().borders(Borders::ALL).title("Context")),
+            context,
+        );
+        frame.render_widget(
+            List::new(
+                instance
+                    .map(|instance| self.trace(instance))
+                    .unwrap_or_default(),
+            )
+            .block(Block::default().borders(Borders::ALL).title("Trace")),
+            trace,
+        );
+        frame.render_widget(
+            Paragraph::new(self.input.as_str())
+                .block(Block::default().borders(Borders::ALL).title("Signal")),
+            input,
+        );
+        frame.render_widget(
+            Paragraph::new(self.message.as_deref().unwrap_or(HELP)),
+            message,
+        );
+    }
+
+    // --------------------------------------------------------------------------------------------
+
+    fn post(&mut self) {
+        let name = self.input.trim().to_string();
+        self.input.clear();
+        if name.is_empty() {
+            return;
+        }
+        let id = match self.selected() {
+            None => return,
+            Some(instance) => instance.id().clone(),
+        };
+        let event = Event::Signal(SignalEvent::new(&name));
+        self.message = Some(match self.manager.post(&id, &event) {
+            Ok(()) => format!("posted {}", event),
+            Err(e) => e.to_string(),
+        });
+    }
+
+    fn select(&mut self, offset: usize) {
+        if !self.manager.is_empty() {
+            self.selected = (self.selected + offset) % self.manager.len();
+            self.message = None;
+        }
+    }
+
+    ///
+    /// The name of the selected instance, or its ID, its status, and its position.
+    ///
+    fn title(&self) -> String {
+        match self.selected() {
+            None => "No instances".to_string(),
+            Some(instance) => format!(
+                "{} ({}) {}/{}",
+                self.manager
+                    .name_of(instance.id())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| instance.id().to_string()),
+                status(instance),
+                self.selected + 1,
+                self.manager.len()
+            ),
+        }
+    }
+
+    ///
+    /// The active states, each indented beneath the state containing it.
+    ///
+    fn configuration(&self, instance: &StateMachineInstance) -> Vec<ListItem<'static>> {
+        instance
+            .active_states()
+            .iter()
+            .map(|state| {
+                ListItem::new(format!(
+                    "{}{}",
+                    "  ".repeat(depth(instance, state)),
+                    label(instance, state)
+                ))
+            })
+            .collect()
+    }
+
+    ///
+    /// The fields of the context, in name order.
+    ///
+    fn context(&self, instance: &StateMachineInstance) -> Vec<ListItem<'static>> {
+        match instance.context().root() {
+            FieldValue::Object(object) => {
+                let object = object.read().unwrap();
+                let mut names = object.names();
+                names.sort_by_key(|name| name.to_string());
+                names
+                    .into_iter()
+                    .filter_map(|name| {
+                        object
+                            .get(name.clone())
+                            .map(|value| ListItem::new(format!("{}: {}", name, value)))
+                    })
+                    .collect()
+            }
+            _ => Default::default(),
+        }
+    }
+
+    ///
+    /// The most recent trace entries, the latest first.
+    ///
+    fn trace(&self, instance: &StateMachineInstance) -> Vec<ListItem<'static>> {
+        match self.recorders.get(instance.id()) {
+            None => Default::default(),
+            Some(recorder) => {
+                let trace = recorder.trace();
+                trace
+                    .entries()
+                    .rev()
+                    .take(self.trace_length)
+                    .map(|entry| ListItem::new(describe(instance, entry)))
+                    .collect()
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn status(instance: &StateMachineInstance) -> &'static str {
+    if instance.is_new() {
+        "new"
+    } else if instance.is_in_error() {
+        "error"
+    } else if instance.is_done() {
+        "done"
+    } else if instance.is_paused() {
+        "paused"
+    } else {
+        "active"
+    }
+}
+
+///
+/// The number of states containing `state`.
+///
+fn depth(instance: &StateMachineInstance, state: &ID) -> usize {
+    let mut depth = 0;
+    let mut current = state.clone();
+    while let Some(Some(owner)) = instance
+        .index
+        .vertex_region
+        .get(&current)
+        .and_then(|region| instance.index.region_owner.get(region))
+    {
+        depth += 1;
+        current = owner.clone();
+    }
+    depth
+}
+
+fn label(instance: &StateMachineInstance, state: &ID) -> String {
+    instance
+        .index
+        .vertices
+        .get(state)
+        .and_then(|vertex| vertex.as_state())
+        .and_then(|state| state.label().clone())
+        .unwrap_or_else(|| state.to_string())
+}
+
+///
+/// The event, or `(start)`, the labels of the transitions fired, and the states active after.
+///
+fn describe(instance: &StateMachineInstance, entry: &TraceEntry) -> String {
+    let fired: Vec<String> = entry
+        .fired()
+        .filter_map(|transition| transition.label().clone())
+        .collect();
+    let after: Vec<String> = entry
+        .after()
+        .iter()
+        .map(|state| label(instance, state))
+        .collect();
+    format!(
+        "{}{} -> {}",
+        entry
+            .event()
+            .map(|event| event.to_string())
+            .unwrap_or_else(|| "(start)".to_string()),
+        if fired.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", fired.join(", "))
+        },
+        after.join(", ")
+    )
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::FieldPath;
+    use crate::definition::types::{HasRegions, Identified, StateMachine, Transition, Trigger};
+    use crate::execution::fixtures::add_state;
+    use ratatui::backend::TestBackend;
+
+    fn door() -> (Arc<StateMachine>, ID, ID) {
+        let machine = StateMachine::labeled("door");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let closed = add_state(region, "closed", None);
+        let open = add_state(region, "open", None);
+        region.new_transition(initial, closed.clone());
+        for (source, target, signal) in [(&closed, &open, "push"), (&open, &closed, "pull")] {
+            let mut transition =
+                Transition::within(source.clone(), target.clone(), region.id().clone());
+            transition.add_trigger(Trigger::signal(signal));
+            region.add_transition(transition);
+        }
+        (Arc::new(machine), closed, open)
+    }
+
+    fn type_keys(dashboard: &mut Dashboard, keys: &str) {
+        for c in keys.chars() {
+            dashboard.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    fn press(dashboard: &mut Dashboard, code: KeyCode) {
+        dashboard.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn screen(dashboard: &Dashboard) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        let _ = terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let width = buffer.area.width as usize;
+        buffer
+            .content()
+            .chunks(width)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_dashboard_posts_signals() {
+        let (machine, closed, open) = door();
+        let instance = StateMachineInstance::new(machine).unwrap();
+        let mut dashboard = Dashboard::new(instance).unwrap();
+        dashboard.manager().execute_all().unwrap();
+        dashboard
+            .selected()
+            .unwrap()
+            .update_context(|context| {
+                context.insert("count".parse::<FieldPath>().unwrap(), 2i64.into())
+            })
+            .unwrap();
+        assert!(dashboard.selected().unwrap().is_in_state(&closed));
+        let shown = screen(&dashboard);
+        assert!(shown.contains("(active) 1/1"));
+        assert!(shown.contains("(start) -> closed"));
+        assert!(shown.contains("count: 2"));
+        assert!(shown.contains(HELP));
+
+        type_keys(&mut dashboard, "pusx");
+        press(&mut dashboard, KeyCode::Backspace);
+        type_keys(&mut dashboard, "h");
+        assert_eq!(dashboard.input(), "push");
+        assert!(screen(&dashboard).contains("push"));
+        press(&mut dashboard, KeyCode::Enter);
+        assert_eq!(dashboard.input(), "");
+        assert!(dashboard.selected().unwrap().is_in_state(&open));
+        let shown = screen(&dashboard);
+        assert!(shown.contains(" -> open"));
+        assert!(shown.contains("posted"));
+
+        assert!(!dashboard.is_quit());
+        press(&mut dashboard, KeyCode::Esc);
+        assert!(dashboard.is_quit());
+    }
+
+    #[test]
+    fn test_dashboard_selects_instances() {
+        let (machine, closed, open) = door();
+        let mut manager = InstanceManager::default();
+        let _ = manager
+            .add_named("front", StateMachineInstance::new(machine.clone()).unwrap())
+            .unwrap();
+        let _ = manager
+            .add_named("back", StateMachineInstance::new(machine).unwrap())
+            .unwrap();
+        let mut dashboard = Dashboard::with_manager(manager).with_trace_length(1);
+        assert!(screen(&dashboard).contains("front (new) 1/2"));
+        dashboard.manager().execute_all().unwrap();
+
+        press(&mut dashboard, KeyCode::Tab);
+        assert!(screen(&dashboard).contains("back (active) 2/2"));
+        type_keys(&mut dashboard, "push");
+        press(&mut dashboard, KeyCode::Enter);
+        type_keys(&mut dashboard, "pull");
+        press(&mut dashboard, KeyCode::Enter);
+        let shown = screen(&dashboard);
+        assert!(shown.contains(" -> closed"));
+        assert!(!shown.contains("(start)"));
+
+        press(&mut dashboard, KeyCode::BackTab);
+        assert!(screen(&dashboard).contains("front (active) 1/2"));
+        assert!(dashboard
+            .manager()
+            .find("front")
+            .unwrap()
+            .is_in_state(&closed));
+        assert!(!dashboard.manager().find("back").unwrap().is_in_state(&open));
+    }
+}
@@ -1,31 +1,509 @@
 /*!
-One-line description.
+The types used to execute an instance of a state machine.
 
-More detailed description, with
+An instance is created for a validated machine, started with `execute`, which enters the
+machine's regions, and then driven by posting events. Each posted event is processed to
+completion, as described in §14.2.3.9 **Event processing for StateMachines**; see
+`StateMachineInstance::post` for the details. An event that enables no transition, but matches a
+deferrable trigger of an active state, is deferred until the configuration changes such that no
+active state defers it, §14.2.3.9.3 **Deferred Events**.
+
+An event may be posted with a payload, a `Context` of its parameters, using
+`StateMachineInstance::post_with`. A trigger with payload predicates only fires where they all
+hold for the payload, and the guards and behaviors performed in response to the event are given
+the payload with the trigger that fired, see `Trigger::payload`.
+
+Each active region has exactly one active state, so an active orthogonal state has one active
+state in each of its regions. An event is offered to every active region, in document order,
+and within a region to the innermost active state first; a state is only offered the event
+where no state it contains has a transition enabled by it. Transitions from one state that are
+enabled by the same event are in conflict, this is an error unless the instance has a
+tie-breaker, see `StateMachineInstance::with_tie_breaker`; as are completion transitions from
+one state enabled at the same time.
+
+A completed state whose guarded completion transitions were not enabled when it completed
+remains active, and their guards are evaluated again at the end of each step, and when the
+context is changed with `StateMachineInstance::update_context`, so that the state is left once
+one of them holds.
+
+A machine whose completion transitions, or raised events, lead back to where they started never
+finishes a step. An instance given a limit, with `StateMachineInstance::with_microstep_limit`,
+instead fails the step with `MicrostepLimit`, carrying the transitions that repeat, once more
+transitions than the limit have fired without another external event being dispatched.
+
+An instance made deterministic, with `StateMachineInstance::deterministic`, instead resolves
+such conflicts by choosing the first of the transitions in document order. Otherwise the
+interpreter already makes its choices in a stable order; regions are entered, exited, and
+offered events in document order, and events, timers, and deferred events are taken in the
+order they occurred. What remains is the ID of each instance, which is generated afresh, and
+may be given with `StateMachineInstance::with_id`; `SeededIds` generates them from a seed, so
+that the instances of two runs with the same seed have the same IDs, and traces recorded with a
+`timers::ManualClock` are then identical.
+
+When a state is exited the states active within it are remembered as the history of its
+region. A transition to a shallow history pseudostate re-enters the
+remembered substate of the region, entering it by default, and a transition to a deep history
+pseudostate re-enters the whole remembered configuration within the region. Where the region has
+no history the history pseudostate's own transition is followed, or failing that the region is
+entered by default.
+
+A transition to a choice pseudostate continues along the first of the choice's outbound
+transitions whose guard holds, evaluated against the context once the choice is reached, or the
+branch guarded by `guards::else_guard` where none do; it is an error for no branch to be
+enabled. The branches of junctions are instead evaluated before the transition to the junction
+fires, and where no branch is enabled the transition itself is not enabled.
+
+A transition to an entry point enters the state owning the entry point's region, and then
+follows the entry point's own transitions rather than entering that region by default. A
+transition to an exit point exits the state owning its region, and then follows the exit
+point's transition or, where it has none, the transition from the connection point reference
+that refers to it. A transition to a connection point reference enters by each of the entry
+points it refers to, or enters its state by default where it refers to none.
+
+The do-activity of a state is started, with `Behavior::start`, once the state has been
+entered and is cancelled if the state is exited before it finishes. A state is not complete
+until its do-activity has finished; as the instance is synchronous it notices that a
+do-activity running elsewhere has finished when an event is next posted, or when
+`StateMachineInstance::tick` is called. The services a state invokes are started, in the same way,
+once its do-activity has been started, see `invoke`.
+
+A behavior, guard, or change expression that panics does not unwind through the instance. A
+panicking guard or change expression is taken to be `false`, a panicking behavior to have
+completed, and once the event has been processed the step fails with `ActionPanicked`, which is
+then handled according to the instance's `ErrorPolicy`.
+
+Behaviors performed by the instance may raise internal events, as for the SCXML `<raise>`
+element; these are dispatched, in the order raised, before any queued external event, so that
+they are processed within the same run-to-completion step as the event that raised them.
+Behaviors may also send events to other instances, with `EventSink::send`. An instance cannot
+deliver these itself; an event sent to `SendTarget::This` for immediate delivery is queued as
+an external event, any other is resolved to the ID of its target instance, `SendTarget::Parent`
+to the instance given to `StateMachineInstance::with_parent`, and kept until its owner takes
+them with `StateMachineInstance::take_sent_events`, as `manager::InstanceManager` does.
+
+When a transition fires the states are exited, innermost first, up to the least common
+ancestor of its source and target, then its effect is performed, and then the states down to its
+target are entered, outermost first. An external transition exits its source even where the
+target is within it, a local transition does not exit the source where the target is within it,
+nor the target where the source is within it, and an internal transition, whose source and
+target are the same, performs only its effect.
+
+A change event, §13.3.3.1 **ChangeEvent**, occurs when its change expression becomes `true`.
+When a state is entered the change expression of each change event that triggers a transition
+from it is evaluated, using `Constraint::evaluate_in` with the instance's context, and it is then
+re-evaluated before each event is dispatched and after the context is changed with
+`StateMachineInstance::update_context`. The change event is dispatched to the state when the
+expression changes from `false` to `true`.
+
+An instance fails where the machine cannot be executed as it stands; where transitions are in
+conflict, where a choice has no enabled branch, or where the target of a transition cannot be
+entered. What then happens depends upon the instance's `ErrorPolicy`, see
+`StateMachineInstance::with_error_policy`. By default the instance enters the error state, the
+error is returned, and no further events are accepted until `StateMachineInstance::clear_error` is
+called; the configuration is left as it was when the error occurred. The other policies abandon
+the step that failed and continue: ignoring the error, entering a designated error state, or
+raising the signal event `ERROR_SIGNAL` so that the machine may handle the error with a
+transition of its own.
+
+An instance may be paused, with `StateMachineInstance::pause`, for example during a maintenance
+window. A paused instance does not advance; events posted to it are queued, but not dispatched,
+and its timers do not fire, until it is resumed with `StateMachineInstance::resume`.
+
+An instance may be returned to new with `StateMachineInstance::reset`, with an empty context
+or one provided, and then started again; it keeps its ID, and the observers, handlers, and
+policies it was configured with, so that none need to be added again.
+
+The queue of an instance is unbounded unless given a capacity with
+`StateMachineInstance::with_queue_capacity`, in which case an `OverflowPolicy` determines what
+happens to an event queued once it is full; an event that is dropped is reported to the
+instance's observers with `ExecutionObserver::on_event_dropped`. The capacity limits every
+event waiting to be dispatched: those posted, the time events and delayed sends due at a tick,
+change events, the outcomes of invoked services, events sent by behaviors to the instance
+itself, and deferred events. Two kinds of event are exempt: events raised by behaviors, which
+are dispatched within the step that raised them, and deferred events recalled to the queue,
+which were counted when they were deferred.
+
+The state of an instance may be saved with `StateMachineInstance::snapshot`, and an equivalent
+instance, of the same machine, created from it later with `StateMachineInstance::restore`. The
+snapshot records the active configuration, the history of each region, the queued, raised, and
+deferred events, the scheduled timers and delayed sends, and a copy of the context. Do-activities cannot be saved,
+so any do-activity that had not finished is started again when the instance is restored, and the
+change expression of each change event is evaluated afresh. Events sent to other instances that had not
+been taken are not recorded.
+
+An instance completes once each of the machine's regions is finished, or a terminate
+pseudostate is reached, and then always finishes in the same order. First the do-activities,
+and then the invoked services, of the states still active are cancelled; then the observers
+are notified, with `ExecutionObserver::on_region_completed`, of each of the machine's regions
+that finished, in document order; then the behaviors given with
+`StateMachineInstance::with_done_behavior` are performed, in the order given, and the events
+they send resolved; then the events still queued or deferred are dropped, and the timers
+cleared; then the observers are notified with `ExecutionObserver::on_done`; and last, the
+instance's snapshot is saved to the store given with
+`StateMachineInstance::with_snapshot_store`, so that a stored instance is never seen to be done
+before its observers have been told.
+
+An observer may also be attached to specific states or transitions, with
+`StateMachineInstance::add_hook`, rather than to the whole instance; it is then only notified as
+those states are entered and exited, or those transitions fire. A `Hook` addresses states by ID
+or label, and transitions by their region and index within it, or by label; the states and
+transitions it addresses are found when it is added, so that each is looked up directly as the
+instance runs, and instrumentation of a few elements costs nothing elsewhere.
+
+A machine is `Send` and `Sync`, so that once validated it may be shared, as an `Arc`, by
+instances running on different threads. An instance is `Send`, so that it may be moved to, and
+owned by, a worker thread or actor, but it is not `Sync`; it is driven by one thread at a time.
+Behaviors, constraints, observers, clocks, and tie-breakers are shared by the instances of a
+machine, and so must themselves be `Send` and `Sync`.
 
 # Example
 
+```rust
+use std::sync::Arc;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::execution::types::StateMachineInstance;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+let open = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+let mut transition = Transition::within(closed.clone(), open.clone(), region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+
+let instance = StateMachineInstance::new(Arc::new(machine)).unwrap();
+instance.execute().unwrap();
+assert!(instance.is_in_state(&closed));
+
+instance.post(&Event::Signal(SignalEvent::new("push"))).unwrap();
+assert!(instance.is_in_state(&open));
+```
+
 */
 
 use crate::core::{Context, ID};
-use crate::definition::types::StateMachine;
+use crate::definition::activities::DoActivityHandle;
+use crate::definition::guards::GuardOutcome;
+use crate::definition::types::{
+    Behavior, Delivery, Event, EventKey, Invoke, SendTarget, StateMachine, Transition, Vertex,
+};
+use crate::error::{Error, Result};
+use crate::execution::invoke::{InvokeHandle, InvokeHandler};
+use crate::execution::stats::{ExecutionStats, Throughput};
+use crate::execution::timers::{DelayedSend, Timer, TimerService};
 use std::cell::RefCell;
-use std::collections::HashSet;
-use std::rc::Rc;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Traits
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Notified of the changes to an instance as they happen, see
+/// `StateMachineInstance::add_observer`. Each method does nothing by default. Observers are
+/// notified during a step, so events they post are queued until it completes.
+///
+pub trait ExecutionObserver: Send + Sync {
+    ///
+    /// `event` is about to be dispatched; `event` is `None` when the instance is started, and
+    /// when it completes states whose do-activity has finished.
+    ///
+    fn on_dispatch_started(&self, _instance: &StateMachineInstance, _event: Option<&Event>) {}
+
+    ///
+    /// `event`, as given to `on_dispatch_started`, and any completion events that resulted, has
+    /// been processed.
+    ///
+    fn on_dispatch_finished(&self, _instance: &StateMachineInstance, _event: Option<&Event>) {}
+
+    fn on_state_entered(&self, _instance: &StateMachineInstance, _state: &ID) {}
+
+    fn on_state_exited(&self, _instance: &StateMachineInstance, _state: &ID) {}
+
+    fn on_transition_fired(&self, _instance: &StateMachineInstance, _transition: &Transition) {}
+
+    ///
+    /// `event` enabled no transition, and was not deferred, was still queued or deferred when
+    /// the instance completed, or was dropped as the instance's queue was full.
+    ///
+    fn on_event_dropped(&self, _instance: &StateMachineInstance, _event: &Event) {}
+
+    ///
+    /// `region`, one of the machine's own regions, finished as the instance completed; this is
+    /// called for each such region, in document order, before `on_done`.
+    ///
+    fn on_region_completed(&self, _instance: &StateMachineInstance, _region: &ID) {}
+
+    fn on_done(&self, _instance: &StateMachineInstance) {}
+
+    ///
+    /// A step failed with `error`, this is called before the instance's `ErrorPolicy` is
+    /// applied.
+    ///
+    fn on_error(&self, _instance: &StateMachineInstance, _error: &Error) {}
+}
+
+///
+/// Saves the snapshot of an instance as it completes, see
+/// `StateMachineInstance::with_snapshot_store`.
+///
+pub trait SnapshotStore: Send + Sync {
+    fn save(&self, snapshot: &InstanceSnapshot) -> Result<()>;
+}
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// The name of the signal event raised by the `ErrorPolicy::RaiseEvent` policy, as for the SCXML
+/// `error.execution` event.
+///
+pub const ERROR_SIGNAL: &str = "error.execution";
+
+///
+/// The name of the signal event posted back to an instance that sent an event to an instance
+/// that could not receive it, as for the SCXML `error.communication` event.
+///
+pub const ERROR_COMMUNICATION: &str = "error.communication";
+
+///
+/// What an instance does when a step fails.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ErrorPolicy {
+    /// Enter the error state and return the error, this is the default.
+    #[default]
+    FailFast,
+    /// Abandon the step, and continue with the next queued event.
+    Ignore,
+    /// Abandon the step, exit every active state, and enter the state with this ID.
+    EnterState(ID),
+    /// Abandon the step, and raise the signal event `ERROR_SIGNAL`; where it enables no
+    /// transition it is discarded.
+    RaiseEvent,
+}
+
+///
+/// The states, or transitions, whose changes an observer added with
+/// `StateMachineInstance::add_hook` is notified of.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hook {
+    /// The state with this ID.
+    State(ID),
+    /// Each state with this label.
+    StateLabeled(String),
+    /// The transition with this index in the region with this ID.
+    Transition(ID, usize),
+    /// Each transition with this label.
+    TransitionLabeled(String),
+}
+
+///
+/// What an instance does when an event is posted to its queue once it is full, see
+/// `StateMachineInstance::with_queue_capacity`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Hand the event back, `post` fails with `QueueBlocked`, carrying the event, so that the
+    /// sender may post it again once the instance is no longer blocked, see
+    /// `StateMachineInstance::is_blocked`, as `manager::InstanceManager` does with the events
+    /// it delivers. An event the instance queues itself cannot be handed back, and fails as for
+    /// `Error`.
+    Block,
+    /// Drop the event being queued.
+    DropNewest,
+    /// Drop the oldest waiting event, queued or else deferred, to make room for the event being
+    /// queued.
+    DropOldest,
+    /// Reject the event being queued, `post` fails with `QueueFull`; this is the default. For
+    /// an event the instance queues itself the call that queued it, `tick` for a time event,
+    /// fails, or otherwise the step, under the instance's `ErrorPolicy`.
+    #[default]
+    Error,
+}
+
+///
+/// A running instance of a state machine, with its own active configuration, event queue, and
+/// context.
+///
 #[derive(Clone)]
 pub struct StateMachineInstance {
-    id: ID,
-    chart: Rc<StateMachine>,
-    active: HashSet<ID>,
-    context: RefCell<Context>,
-    state: RefCell<ExecutionState>,
+    pub(crate) id: ID,
+    pub(crate) chart: Arc<StateMachine>,
+    pub(crate) index: Arc<ChartIndex>,
+    /// The active state of each active region, at all levels of nesting; this is the
+    /// configuration of the instance, with exactly one active state in each region of an
+    /// active state.
+    pub(crate) active: RefCell<HashMap<ID, ID>>,
+    /// Events posted but not yet dispatched, in the order they were posted.
+    pub(crate) queue: RefCell<VecDeque<Occurrence>>,
+    /// The number of events that may be posted to the queue, and what happens to those posted
+    /// once it is full; where `None` the queue is unbounded.
+    pub(crate) queue_capacity: Option<(usize, OverflowPolicy)>,
+    /// Events raised by behaviors, dispatched before any queued event.
+    pub(crate) internal: InternalQueue,
+    /// Events deferred by an active state, in the order they were deferred, with their payloads.
+    pub(crate) deferred: RefCell<Vec<Occurrence>>,
+    pub(crate) timers: TimerService,
+    pub(crate) tie_breaker: Option<Arc<TieBreaker>>,
+    /// Whether conflicting transitions are resolved in document order, where there is no
+    /// tie-breaker.
+    pub(crate) deterministic: bool,
+    /// The number of transitions that may fire without an external event, see
+    /// `StateMachineInstance::with_microstep_limit`.
+    pub(crate) microstep_limit: Option<usize>,
+    /// The transitions fired, as source and target, since the last external event; only kept
+    /// where there is a limit.
+    pub(crate) microsteps: RefCell<Vec<(ID, ID)>>,
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) observers: RefCell<Vec<Arc<dyn ExecutionObserver>>>,
+    pub(crate) hooks: RefCell<HookTable>,
+    /// The instance that started this one, the target of events sent to `SendTarget::Parent`.
+    pub(crate) parent: Option<ID>,
+    /// Events sent to other instances, in the order sent, that have not yet been taken.
+    pub(crate) outbox: RefCell<Vec<SentEvent>>,
+    /// What has happened so far in the current call to `step`.
+    pub(crate) record: RefCell<Option<StepResult>>,
+    /// Where the first behavior or constraint to panic during the current step was performed.
+    pub(crate) panicked: RefCell<Option<String>>,
+    /// The do-activities of active states that have not yet finished.
+    pub(crate) activities: RefCell<HashMap<ID, DoActivityHandle>>,
+    /// The handler for each kind of invoked service.
+    pub(crate) invoke_handlers: HashMap<String, Arc<dyn InvokeHandler>>,
+    /// The services invoked by active states, in the order started, whose outcome has not yet
+    /// been posted.
+    pub(crate) invocations: RefCell<Vec<ActiveInvoke>>,
+    /// The states most recently active within each region, at any depth, in document order.
+    pub(crate) history: RefCell<HashMap<ID, Vec<ID>>>,
+    /// The change events that trigger transitions from active states.
+    pub(crate) changes: RefCell<Vec<ChangeWatch>>,
+    /// The behaviors performed as the instance completes, in the order given.
+    pub(crate) done_behaviors: Vec<Arc<dyn Behavior>>,
+    /// Where the instance's snapshot is saved as it completes.
+    pub(crate) snapshot_store: Option<Arc<dyn SnapshotStore>>,
+    pub(crate) context: RefCell<Context>,
+    pub(crate) state: RefCell<ExecutionState>,
+    /// Whether the instance is paused, see `StateMachineInstance::pause`.
+    pub(crate) paused: RefCell<bool>,
+    pub(crate) stats: RefCell<ExecutionStats>,
+    pub(crate) throughput: RefCell<Throughput>,
 }
 
+///
+/// Generates instance IDs from a seed, `execution::<seed>-<n>` for the `n`th, so that the
+/// instances of two runs given the same seed have the same IDs. It may be shared between
+/// threads, but the IDs are then only reproducible where the instances are created in the same
+/// order.
+///
+#[derive(Debug, Default)]
+pub struct SeededIds {
+    pub(crate) seed: u64,
+    pub(crate) next: AtomicU64,
+}
+
+///
+/// What happened during a call to `StateMachineInstance::step`, in the order it happened.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StepResult {
+    pub(crate) fired: Vec<FiredTransition>,
+    pub(crate) exited: Vec<ID>,
+    pub(crate) entered: Vec<ID>,
+    pub(crate) performed: Vec<PerformedBehavior>,
+    pub(crate) guards: Vec<EvaluatedGuard>,
+    pub(crate) completed: bool,
+}
+
+///
+/// An iterator stepping an instance with each of a sequence of events in turn, yielding the
+/// outcome of each step, see `StateMachineInstance::drive`.
+///
+#[derive(Debug)]
+pub struct Drive<'a, I> {
+    pub(crate) instance: &'a mut StateMachineInstance,
+    pub(crate) events: I,
+}
+
+///
+/// A transition that fired during a step, including those from pseudostates that are part of a
+/// compound transition.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FiredTransition {
+    pub(crate) source: ID,
+    pub(crate) target: ID,
+    pub(crate) label: Option<String>,
+}
+
+///
+/// The guard of a transition evaluated during a step, with the sub-guards evaluated by that
+/// evaluation, see `Constraint::explain_in`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvaluatedGuard {
+    pub(crate) source: ID,
+    pub(crate) target: ID,
+    pub(crate) label: Option<String>,
+    pub(crate) outcome: GuardOutcome,
+}
+
+///
+/// A behavior performed during a step; an entry, exit, or effect behavior, or the start of a
+/// do-activity.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerformedBehavior {
+    pub(crate) in_state: ID,
+    pub(crate) label: Option<String>,
+}
+
+///
+/// The state of an instance, taken between steps with `StateMachineInstance::snapshot`, from
+/// which an equivalent instance may be created with `StateMachineInstance::restore`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceSnapshot {
+    pub(crate) id: ID,
+    /// The ID of the machine the instance was created for.
+    pub(crate) chart: ID,
+    pub(crate) state: ExecutionState,
+    pub(crate) paused: bool,
+    /// The active states, in document order.
+    pub(crate) active: Vec<ID>,
+    pub(crate) history: HashMap<ID, Vec<ID>>,
+    pub(crate) queue: Vec<Occurrence>,
+    pub(crate) internal: Vec<Event>,
+    pub(crate) deferred: Vec<Occurrence>,
+    pub(crate) timers: Vec<Timer>,
+    /// The events to be sent after a delay, and the ID to be given to the next.
+    pub(crate) sends: Vec<DelayedSend>,
+    pub(crate) next_send: u64,
+    /// The active states whose do-activity had not finished.
+    pub(crate) activities: Vec<ID>,
+    pub(crate) context: Context,
+}
+
+///
+/// An event sent by a behavior to another instance, see `StateMachineInstance::take_sent_events`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SentEvent {
+    pub(crate) target: ID,
+    pub(crate) event: Event,
+    pub(crate) delivery: Delivery,
+}
+
+///
+/// Chooses, by index, between the transitions from a state that are enabled by the same event.
+///
+pub type TieBreaker = dyn Fn(&ID, &[Arc<Transition>]) -> usize + Send + Sync;
+
 // ------------------------------------------------------------------------------------------------
 // Public Functions
 // ------------------------------------------------------------------------------------------------
@@ -38,17 +516,110 @@ pub struct StateMachineInstance {
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
-#[allow(dead_code)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[repr(u8)]
-enum ExecutionState {
+pub(crate) enum ExecutionState {
     New = 0,
     Active,
+    /// A run-to-completion step is in progress.
     InAction,
     Done,
+    /// A step failed under the `ErrorPolicy::FailFast` policy, or the instance failed to recover
+    /// from an error.
     Error,
 }
 
+///
+/// An event waiting in an instance's queue; an event with a target is only offered to that
+/// state, as for the time event of a timer scheduled by the state.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Occurrence {
+    pub(crate) event: Event,
+    pub(crate) target: Option<ID>,
+    /// The payload the event was posted with, see `StateMachineInstance::post_with`.
+    pub(crate) payload: Option<Context>,
+}
+
+///
+/// A transition selected to fire, with the branches taken through the chain of junctions at its
+/// target, whose guards were evaluated, once, as the transition was selected.
+///
+#[derive(Clone)]
+pub(crate) struct Enabled {
+    pub(crate) transition: Arc<Transition>,
+    pub(crate) branches: Vec<Arc<Transition>>,
+}
+
+///
+/// The sink given to the behaviors an instance performs.
+///
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InternalQueue {
+    pub(crate) events: RefCell<VecDeque<Event>>,
+    /// Events sent by behaviors during the current step, not yet resolved.
+    pub(crate) sent: RefCell<Vec<(SendTarget, Event, Delivery)>>,
+}
+
+///
+/// A service invoked by the active state `state`.
+///
+#[derive(Clone, Debug)]
+pub(crate) struct ActiveInvoke {
+    pub(crate) state: ID,
+    pub(crate) invoke: Invoke,
+    pub(crate) handle: InvokeHandle,
+}
+
+///
+/// The most recent value of the change expression of a change event, on behalf of the state
+/// whose transitions it triggers.
+///
+#[derive(Clone, Debug)]
+pub(crate) struct ChangeWatch {
+    pub(crate) state: ID,
+    pub(crate) event: Event,
+    pub(crate) value: bool,
+}
+
+///
+/// The observers added with `StateMachineInstance::add_hook`, by the states and transitions
+/// they are notified of.
+///
+#[derive(Clone, Default)]
+pub(crate) struct HookTable {
+    pub(crate) states: HashMap<ID, Vec<Arc<dyn ExecutionObserver>>>,
+    /// The hooked transitions from each vertex, with their observers.
+    pub(crate) transitions: HashMap<ID, Vec<HookedTransition>>,
+}
+
+pub(crate) type HookedTransition = (Arc<Transition>, Arc<dyn ExecutionObserver>);
+
+///
+/// The structure of the machine, computed once when an instance is created, so that the
+/// executor can navigate between vertices, regions, and their owners by ID.
+///
+#[derive(Default)]
+pub(crate) struct ChartIndex {
+    pub(crate) vertices: HashMap<ID, Arc<Vertex>>,
+    /// The position of each vertex in document order, parents before their children.
+    pub(crate) order: HashMap<ID, usize>,
+    /// The region owning each vertex.
+    pub(crate) vertex_region: HashMap<ID, ID>,
+    /// The state owning each region, `None` for the machine's own regions.
+    pub(crate) region_owner: HashMap<ID, Option<ID>>,
+    /// The regions of each state, and of the machine itself under its own ID.
+    pub(crate) regions: HashMap<ID, Vec<ID>>,
+    /// The initial pseudostate of each region that has one.
+    pub(crate) initials: HashMap<ID, ID>,
+    /// The transitions from each vertex, in document order.
+    pub(crate) transitions: HashMap<ID, Vec<Arc<Transition>>>,
+    /// The transitions from each vertex with a trigger for each kind of event, in document order.
+    pub(crate) triggered: HashMap<(ID, EventKey), Vec<Arc<Transition>>>,
+    /// The connection point reference for each exit point it refers to.
+    pub(crate) exit_references: HashMap<ID, ID>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
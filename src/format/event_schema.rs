@@ -0,0 +1,353 @@
+/*!
+Writes the external event contract of a state machine as a [JSON Schema](https://json-schema.org/)
+document. Does not support parsing.
+
+The schema describes each signal and call event referenced by a trigger, either on a transition
+or as a deferrable trigger of a state. An event is an object with a `name`, a `kind` of either
+`"signal"` or `"call"`, and a `payload`. The fields of the payload are those of the payload schema
+of a signal, see `SignalEvent::with_field`, and those inferred from the payload predicates of all
+triggers on the event; any field inspected by a predicate is required, and its type is taken from
+the value it is compared against.
+
+# Example
+
+```rust
+use std::str::FromStr;
+use uml_state_machine::core::FieldPath;
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::event_schema::WriteEventSchema;
+use uml_state_machine::format::Stringify;
+
+let machine = StateMachine::labeled("orders");
+let region: &Region = machine.default_region().unwrap();
+let initial_id = region.new_initial_state();
+let open_id = region.new_simple_state();
+let final_id = region.new_final_state();
+region.new_transition(initial_id, open_id.clone());
+
+let mut trigger = Trigger::signal("cancelled");
+trigger.add_payload_predicate(PayloadPredicate::new(
+    FieldPath::from_str("reason").unwrap(),
+    ComparisonOperator::Equal,
+    "fraud".into(),
+));
+let mut transition = Transition::within(open_id, final_id, region.id().clone());
+transition.add_trigger(trigger);
+region.add_transition(transition);
+
+let schema = WriteEventSchema::default().stringify(&machine).unwrap();
+assert!(schema.contains("\"cancelled\""));
+```
+
+*/
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::slice::Iter;
+
+use serde_json::{json, Map, Value};
+
+use crate::core::{FieldKind, FieldPath, ID};
+use crate::definition::types::{
+    Behavior, Constraint, Event, Identified, Labeled, StateMachine, TransitionKind, Trigger,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::Error;
+use crate::format::Write;
+use std::io;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Implements Write, and so Stringify, for the event JSON Schema.
+///
+#[derive(Debug, Default)]
+pub struct WriteEventSchema {}
+
+///
+/// The JSON Schema dialect emitted by `WriteEventSchema`.
+///
+pub const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Write for WriteEventSchema {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<(), Self::Error> {
+        let visitor = Visitor::default();
+        visit_state_machine(machine, &visitor)?;
+
+        let events: Vec<Value> = visitor
+            .events
+            .into_inner()
+            .into_iter()
+            .map(|((kind, name), fields)| event_schema(kind, &name, &fields))
+            .collect();
+        let title = match machine.label() {
+            None => format!("{} events", machine.id()),
+            Some(label) => format!("{} events", label),
+        };
+        let schema = json!({
+            "$schema": JSON_SCHEMA_DIALECT,
+            "title": title,
+            "oneOf": events,
+        });
+        serde_json::to_writer_pretty(&mut *w, &schema).map_err(io::Error::from)?;
+        Ok(w.flush()?)
+    }
+}
+
+impl StateMachineVisitor for Visitor {
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        _region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        for trigger in deferrable_triggers {
+            self.add_trigger(trigger);
+        }
+    }
+
+    fn transition(
+        &self,
+        _resolver: &Resolver<'_>,
+        _label: &Option<String>,
+        _kind: TransitionKind,
+        _source: ID,
+        _target: ID,
+        triggers: Iter<'_, Trigger>,
+        _guard: &Option<Box<dyn Constraint>>,
+        _effect: &Option<Box<dyn Behavior>>,
+    ) {
+        for trigger in triggers {
+            self.add_trigger(trigger);
+        }
+    }
+}
+
+impl Visitor {
+    fn add_trigger(&self, trigger: &Trigger) {
+        let (key, schema) = match trigger.event() {
+            Event::Signal(event) => (
+                (EventKind::Signal, event.signal().clone()),
+                event.payload_schema().as_slice(),
+            ),
+            Event::Call(event) => ((EventKind::Call, event.operation().clone()), &[][..]),
+            _ => return,
+        };
+        let mut events = self.events.borrow_mut();
+        let fields = events.entry(key).or_default();
+        for (path, kind) in schema {
+            let _ = fields
+                .entry(path.to_string())
+                .or_default()
+                .insert(json_type(*kind));
+        }
+        for predicate in trigger.payload_predicates() {
+            let _ = fields
+                .entry(predicate.path().to_string())
+                .or_default()
+                .insert(json_type(predicate.value().kind()));
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum EventKind {
+    Signal,
+    Call,
+}
+
+/// Field paths to the set of JSON types they have been compared against.
+type Fields = BTreeMap<String, BTreeSet<&'static str>>;
+
+#[derive(Default)]
+struct Visitor {
+    events: RefCell<BTreeMap<(EventKind, String), Fields>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn json_type(kind: FieldKind) -> &'static str {
+    match kind {
+        FieldKind::Bool => "boolean",
+        FieldKind::Byte | FieldKind::Integer => "integer",
+        FieldKind::Float => "number",
+        FieldKind::String => "string",
+        FieldKind::Array => "array",
+        FieldKind::Object => "object",
+    }
+}
+
+fn event_schema(kind: EventKind, name: &str, fields: &Fields) -> Value {
+    let mut payload = ObjectSchema::default();
+    for (path, types) in fields {
+        let path: FieldPath = path.parse().unwrap();
+        payload.insert(&path, field_type(types));
+    }
+    json!({
+        "type": "object",
+        "title": name,
+        "properties": {
+            "name": { "const": name },
+            "kind": { "const": match kind {
+                EventKind::Signal => "signal",
+                EventKind::Call => "call",
+            } },
+            "payload": payload.into_value(),
+        },
+        "required": ["name", "payload"],
+    })
+}
+
+fn field_type(types: &BTreeSet<&'static str>) -> Value {
+    let mut types = types.clone();
+    if types.contains("number") {
+        let _ = types.remove("integer");
+    }
+    if types.len() == 1 {
+        json!({ "type": types.into_iter().next().unwrap() })
+    } else {
+        json!({ "type": types.into_iter().collect::<Vec<&str>>() })
+    }
+}
+
+#[derive(Default)]
+struct ObjectSchema {
+    properties: BTreeMap<String, Property>,
+}
+
+enum Property {
+    Leaf(Value),
+    Object(ObjectSchema),
+}
+
+impl ObjectSchema {
+    fn insert(&mut self, path: &FieldPath, schema: Value) {
+        let name = path.first().unwrap().to_string();
+        if path.len() == 1 {
+            let _ = self.properties.insert(name, Property::Leaf(schema));
+        } else {
+            let property = self
+                .properties
+                .entry(name)
+                .or_insert_with(|| Property::Object(Default::default()));
+            if let Property::Leaf(_) = property {
+                *property = Property::Object(Default::default());
+            }
+            if let Property::Object(object) = property {
+                object.insert(&path.rest(), schema);
+            }
+        }
+    }
+
+    fn into_value(self) -> Value {
+        let required: Vec<String> = self.properties.keys().cloned().collect();
+        let properties: Map<String, Value> = self
+            .properties
+            .into_iter()
+            .map(|(name, property)| match property {
+                Property::Leaf(value) => (name, value),
+                Property::Object(object) => (name, object.into_value()),
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::FieldValue;
+    use crate::definition::types::{
+        ComparisonOperator, HasRegions, PayloadPredicate, Region, SignalEvent, Transition,
+    };
+    use crate::format::Stringify;
+    use std::str::FromStr;
+
+    fn predicate(path: &str, value: FieldValue) -> PayloadPredicate {
+        PayloadPredicate::new(
+            FieldPath::from_str(path).unwrap(),
+            ComparisonOperator::Equal,
+            value,
+        )
+    }
+
+    #[test]
+    fn test_nested_payload() {
+        let machine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let initial_id = region.new_initial_state();
+        let idle_id = region.new_simple_state();
+        let final_id = region.new_final_state();
+        region.new_transition(initial_id, idle_id.clone());
+
+        let mut first = Trigger::signal("placed");
+        first.add_payload_predicate(predicate("order/total", 10i64.into()));
+        let mut second = Trigger::signal("placed");
+        second.add_payload_predicate(predicate("order/total", 10.5.into()));
+        second.add_payload_predicate(predicate("channel", "web".into()));
+        let mut transition = Transition::within(idle_id, final_id, region.id().clone());
+        transition.add_trigger(first);
+        transition.add_trigger(second);
+        transition.add_trigger(Trigger::with_event(Event::Signal(
+            SignalEvent::new("placed")
+                .with_field(FieldPath::from_str("customer").unwrap(), FieldKind::String),
+        )));
+        transition.add_trigger(Trigger::call("refresh"));
+        transition.add_trigger(Trigger::any());
+        region.add_transition(transition);
+
+        let schema: Value =
+            serde_json::from_str(&WriteEventSchema::default().stringify(&machine).unwrap())
+                .unwrap();
+        let events = schema["oneOf"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+
+        let placed = &events[0];
+        assert_eq!(placed["title"], "placed");
+        let payload = &placed["properties"]["payload"];
+        assert_eq!(payload["required"], json!(["channel", "customer", "order"]));
+        assert_eq!(
+            payload["properties"]["order"]["properties"]["total"]["type"],
+            "number"
+        );
+        assert_eq!(payload["properties"]["channel"]["type"], "string");
+        assert_eq!(payload["properties"]["customer"]["type"], "string");
+
+        let refresh = &events[1];
+        assert_eq!(refresh["properties"]["kind"]["const"], "call");
+        assert_eq!(refresh["properties"]["payload"]["required"], json!([]));
+    }
+}
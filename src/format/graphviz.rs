@@ -2,18 +2,155 @@
 Writes a state machine out as a [GraphViz](https://graphviz.org/) DOT file. Does not support
 parsing DOT.
 
+The model is mapped onto the graph as follows.
+
+* Simple states are rounded boxes, labeled with the state's label and any entry, do, and exit
+  behaviors; final states use the double-circle convention.
+* Composite states become a `subgraph cluster_*` block containing their vertices; where a state,
+  or the machine itself, has more than one region each region is a further, dashed, cluster.
+* Pseudostates use distinct shapes: a point for initial, a diamond for choice, a small point
+  for junction, a bar for fork and join, `H` and `H*` circles for history, small circles for
+  entry and exit points, and an `X` for terminate.
+* Transitions are edges labeled in the UML form `trigger, ... [guard] / effect`. As DOT edges
+  cannot end on a cluster, each composite state has an invisible anchor node in its cluster and
+  edges to or from it are clipped at the cluster boundary.
+
+All edges are written after all vertices, so that no vertex is accidentally placed in the
+cluster of a transition that references it. The layout direction, fonts, and colors are set with
+`GraphvizOptions`, and individual vertices and transitions may be styled by callbacks registered
+with `WriteGraphviz::with_node_style` and `WriteGraphviz::with_edge_style`.
+
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::graphviz::{NodeStyle, WriteGraphviz};
+use uml_state_machine::format::Stringify;
+
+let machine = StateMachine::default();
+let region = machine.default_region().unwrap();
+let mut failed = State::within(region.id().clone());
+failed.set_label("failed");
+region.add_state(failed);
+
+let writer = WriteGraphviz::default().with_node_style(|vertex: &Vertex| match vertex {
+    Vertex::State(state) if state.label() == &Some("failed".to_string()) => NodeStyle {
+        color: Some("red".to_string()),
+        ..Default::default()
+    },
+    _ => NodeStyle::default(),
+});
+assert!(writer.stringify(&machine).unwrap().contains("color=\"red\""));
+```
+
 # Example
 
-TBD
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::graphviz::WriteGraphviz;
+use uml_state_machine::format::Stringify;
+
+let machine = StateMachine::labeled("light");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let mut on = State::within(region.id().clone());
+on.set_label("on");
+let on_id = on.id().clone();
+region.add_state(on);
+region.new_transition(initial, on_id);
+
+let dot = WriteGraphviz::default().stringify(&machine).unwrap();
+assert!(dot.starts_with("digraph \"light\" {"));
+assert!(dot.contains("shape=point"));
+```
 
 */
 
-// use ...
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::slice::Iter;
+use std::sync::Arc;
+
+use crate::core::ID;
+use crate::definition::types::{
+    Behavior, Constraint, HasRegions, Identified, PseudoState, PseudoStateKind, StateMachine,
+    Transition, TransitionKind, Trigger, Vertex,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::{Error, Result};
+use crate::format::{Direction, Sink, Write};
+use std::io;
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Implements Write, and so Stringify, for GraphViz DOT.
+///
+#[derive(Default)]
+pub struct WriteGraphviz {
+    options: GraphvizOptions,
+    node_style: Option<Box<NodeStyler>>,
+    edge_style: Option<Box<EdgeStyler>>,
+}
+
+///
+/// A callback returning the style of a vertex, composite states are styled as their cluster.
+///
+pub type NodeStyler = dyn Fn(&Vertex) -> NodeStyle;
+
+///
+/// A callback returning the style of a transition's edge.
+///
+pub type EdgeStyler = dyn Fn(&Transition) -> EdgeStyle;
+
+///
+/// The style of a single node, or cluster; any value that is `None` keeps the writer's default.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeStyle {
+    pub color: Option<String>,
+    pub fill_color: Option<String>,
+    pub font_color: Option<String>,
+    pub pen_width: Option<f32>,
+    /// GraphViz styles, such as `bold` or `dashed`, added to those the writer uses.
+    pub style: Option<String>,
+}
+
+///
+/// The style of a single edge; any value that is `None` keeps the writer's default.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeStyle {
+    pub color: Option<String>,
+    pub font_color: Option<String>,
+    pub pen_width: Option<f32>,
+    /// GraphViz styles, such as `bold` or `dotted`.
+    pub style: Option<String>,
+}
+
+///
+/// Presentation options for the graph written by `WriteGraphviz`; colors are any GraphViz color
+/// name or `#rrggbb` value.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphvizOptions {
+    /// The `rankdir` of the graph, by default `LeftToRight`.
+    pub direction: Direction,
+    /// The font of all labels, by default `Helvetica`.
+    pub font_name: Option<String>,
+    /// The font size, in points, of state and cluster labels.
+    pub font_size: Option<u32>,
+    /// The font size, in points, of transition labels, by default 10.
+    pub edge_font_size: Option<u32>,
+    /// The color of all labels.
+    pub font_color: Option<String>,
+    /// The color of state and cluster outlines and of transitions.
+    pub line_color: Option<String>,
+    /// The fill color of states; pseudostates keep their conventional fill.
+    pub fill_color: Option<String>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Public Functions
 // ------------------------------------------------------------------------------------------------
@@ -22,14 +159,765 @@ TBD
 // Implementations
 // ------------------------------------------------------------------------------------------------
 
+impl Default for GraphvizOptions {
+    fn default() -> Self {
+        Self {
+            direction: Direction::LeftToRight,
+            font_name: Some("Helvetica".to_string()),
+            font_size: None,
+            edge_font_size: Some(10),
+            font_color: None,
+            line_color: None,
+            fill_color: None,
+        }
+    }
+}
+
+impl Debug for WriteGraphviz {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteGraphviz")
+            .field("options", &self.options)
+            .field("node_style", &self.node_style.is_some())
+            .field("edge_style", &self.edge_style.is_some())
+            .finish()
+    }
+}
+
+impl WriteGraphviz {
+    pub fn with_options(options: GraphvizOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    pub fn options(&self) -> &GraphvizOptions {
+        &self.options
+    }
+
+    ///
+    /// Style each vertex with the result of `styler`, on top of the writer's options.
+    ///
+    pub fn with_node_style<F>(mut self, styler: F) -> Self
+    where
+        F: Fn(&Vertex) -> NodeStyle + 'static,
+    {
+        self.node_style = Some(Box::new(styler));
+        self
+    }
+
+    ///
+    /// Style each transition with the result of `styler`, on top of the writer's options.
+    ///
+    pub fn with_edge_style<F>(mut self, styler: F) -> Self
+    where
+        F: Fn(&Transition) -> EdgeStyle + 'static,
+    {
+        self.edge_style = Some(Box::new(styler));
+        self
+    }
+}
+
+impl Write for WriteGraphviz {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let mut writer = Writer {
+            options: &self.options,
+            node_style: self.node_style.as_deref(),
+            edge_style: self.edge_style.as_deref(),
+            out: Sink::new(w),
+            depth: RefCell::new(1),
+            region_counts: RefCell::new(vec![machine.regions().len()]),
+            composites: Default::default(),
+            edges: Default::default(),
+            vertices: Default::default(),
+            transitions: Default::default(),
+            regions: Default::default(),
+        };
+        if self.node_style.is_some() || self.edge_style.is_some() {
+            machine.for_each_region(&mut |region| {
+                for vertex in region.vertices() {
+                    let _ = writer.vertices.insert(vertex.id().clone(), vertex);
+                }
+                let _ = writer
+                    .transitions
+                    .insert(region.id().clone(), region.transitions());
+            });
+        }
+        visit_state_machine(machine, &writer)?;
+        writer.finish()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachineVisitor for Writer<'_> {
+    fn enter_state_machine(
+        &self,
+        _: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _: Iter<'_, ID>,
+        _: Iter<'_, PseudoState>,
+    ) {
+        self.push_str(&format!(
+            "digraph {} {{\n",
+            quote(label.as_deref().unwrap_or(&id.to_string()))
+        ));
+        let options = self.options;
+        self.push_line(&format!(
+            "rankdir={};",
+            match options.direction {
+                Direction::TopToBottom => "TB",
+                Direction::LeftToRight => "LR",
+                Direction::BottomToTop => "BT",
+                Direction::RightToLeft => "RL",
+            }
+        ));
+        self.push_line("compound=true;");
+        let fonts = attributes(&[
+            (
+                "fontname",
+                options.font_name.as_ref().map(|name| quote(name)),
+            ),
+            ("fontsize", options.font_size.map(|size| size.to_string())),
+            (
+                "fontcolor",
+                options.font_color.as_ref().map(|color| quote(color)),
+            ),
+            (
+                "color",
+                options.line_color.as_ref().map(|color| quote(color)),
+            ),
+        ]);
+        if !fonts.is_empty() {
+            self.push_line(&format!("graph [{}];", fonts));
+        }
+        let nodes = attributes(&[
+            (
+                "fontname",
+                options.font_name.as_ref().map(|name| quote(name)),
+            ),
+            ("fontsize", options.font_size.map(|size| size.to_string())),
+            (
+                "fontcolor",
+                options.font_color.as_ref().map(|color| quote(color)),
+            ),
+            (
+                "color",
+                options.line_color.as_ref().map(|color| quote(color)),
+            ),
+            (
+                "fillcolor",
+                options.fill_color.as_ref().map(|color| quote(color)),
+            ),
+        ]);
+        if !nodes.is_empty() {
+            self.push_line(&format!("node [{}];", nodes));
+        }
+        let edges = attributes(&[
+            (
+                "fontname",
+                options.font_name.as_ref().map(|name| quote(name)),
+            ),
+            (
+                "fontsize",
+                options.edge_font_size.map(|size| size.to_string()),
+            ),
+            (
+                "fontcolor",
+                options.font_color.as_ref().map(|color| quote(color)),
+            ),
+            (
+                "color",
+                options.line_color.as_ref().map(|color| quote(color)),
+            ),
+        ]);
+        if !edges.is_empty() {
+            self.push_line(&format!("edge [{}];", edges));
+        }
+    }
+
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _comments: Iter<'_, String>,
+        region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        entry: &Option<Box<dyn Behavior>>,
+        do_activity: &Option<Box<dyn Behavior>>,
+        exit: &Option<Box<dyn Behavior>>,
+        is_final: bool,
+    ) {
+        let label = state_label(id, label, entry, do_activity, exit);
+        if is_final {
+            self.push_line(&format!(
+                "{} [shape=doublecircle, label={}{}];",
+                quote(&id.to_string()),
+                quote(&label),
+                joined(self.node_style(id, &[]))
+            ));
+        } else if region_count == 0 {
+            self.push_line(&format!(
+                "{} [shape=box, style={}, label={}{}];",
+                quote(&id.to_string()),
+                self.state_style(),
+                quote(&label),
+                joined(self.node_style(id, &self.state_styles()))
+            ));
+        } else {
+            let _ = self.composites.borrow_mut().insert(id.clone());
+            self.open_cluster(id);
+            self.push_line(&format!("label={};", quote(&label)));
+            self.push_line(&format!("style={};", self.state_style()));
+            if let Some(color) = &self.options.fill_color {
+                self.push_line(&format!("fillcolor={};", quote(color)));
+            }
+            for attribute in self.node_style(id, &self.state_styles()) {
+                self.push_line(&format!("{};", attribute));
+            }
+            self.push_line(&format!(
+                "{} [shape=point, style=invis, width=0, height=0, label=\"\"];",
+                quote(&id.to_string())
+            ));
+        }
+        self.region_counts.borrow_mut().push(region_count);
+    }
+
+    fn exit_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        let _ = self.region_counts.borrow_mut().pop();
+        if region_count > 0 {
+            self.close_cluster();
+        }
+    }
+
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, label: &Option<String>, _last: bool) {
+        self.regions.borrow_mut().push((id.clone(), 0));
+        if self.is_orthogonal() {
+            self.open_cluster(id);
+            self.push_line(&format!(
+                "label={};",
+                quote(label.as_deref().unwrap_or_default())
+            ));
+            self.push_line("style=dashed;");
+        }
+    }
+
+    fn exit_region(&self, _resolver: &Resolver<'_>, _: &ID, _label: &Option<String>, _last: bool) {
+        let _ = self.regions.borrow_mut().pop();
+        if self.is_orthogonal() {
+            self.close_cluster();
+        }
+    }
+
+    fn connection_point_reference(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _entry: Iter<'_, ID>,
+        _exit: Iter<'_, ID>,
+        _state: &Option<ID>,
+    ) {
+        self.push_line(&format!(
+            "{} [shape=circle, width=0.2, fixedsize=true, label=\"\", xlabel={}{}];",
+            quote(&id.to_string()),
+            quote(label.as_deref().unwrap_or_default()),
+            joined(self.node_style(id, &[]))
+        ));
+    }
+
+    fn pseudo_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        kind: &PseudoStateKind,
+    ) {
+        let xlabel = quote(label.as_deref().unwrap_or_default());
+        let attributes = match kind {
+            PseudoStateKind::Initial => {
+                "shape=point, width=0.2, style=filled, fillcolor=black, label=\"\"".to_string()
+            }
+            PseudoStateKind::Choice => {
+                format!("shape=diamond, width=0.3, height=0.3, label=\"\", xlabel={}", xlabel)
+            }
+            PseudoStateKind::Junction => format!(
+                "shape=point, width=0.1, style=filled, fillcolor=black, label=\"\", xlabel={}",
+                xlabel
+            ),
+            PseudoStateKind::Fork | PseudoStateKind::Join => format!(
+                "shape=box, width=0.05, height=0.5, style=filled, fillcolor=black, label=\"\", xlabel={}",
+                xlabel
+            ),
+            PseudoStateKind::ShallowHistory => {
+                "shape=circle, width=0.3, fixedsize=true, label=\"H\"".to_string()
+            }
+            PseudoStateKind::DeepHistory => {
+                "shape=circle, width=0.3, fixedsize=true, label=\"H*\"".to_string()
+            }
+            PseudoStateKind::EntryPoint => format!(
+                "shape=circle, width=0.2, fixedsize=true, label=\"\", xlabel={}",
+                xlabel
+            ),
+            PseudoStateKind::ExitPoint => format!(
+                "shape=circle, width=0.2, fixedsize=true, label=\"X\", xlabel={}",
+                xlabel
+            ),
+            PseudoStateKind::Terminate => {
+                format!("shape=none, width=0.2, label=\"X\", xlabel={}", xlabel)
+            }
+        };
+        let styles: &[&str] = match kind {
+            PseudoStateKind::Initial
+            | PseudoStateKind::Junction
+            | PseudoStateKind::Fork
+            | PseudoStateKind::Join => &["filled"],
+            _ => &[],
+        };
+        self.push_line(&format!(
+            "{} [{}{}];",
+            quote(&id.to_string()),
+            attributes,
+            joined(self.node_style(id, styles))
+        ));
+    }
+
+    fn transition(
+        &self,
+        _resolver: &Resolver<'_>,
+        label: &Option<String>,
+        _kind: TransitionKind,
+        source: ID,
+        target: ID,
+        triggers: Iter<'_, Trigger>,
+        guard: &Option<Box<dyn Constraint>>,
+        effect: &Option<Box<dyn Behavior>>,
+    ) {
+        let mut all_label = triggers
+            .map(|trigger| trigger.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        if let Some(guard) = guard {
+            if let Some(label) = guard.label() {
+                push_word(&mut all_label, &format!("[{}]", label));
+            }
+        }
+        if let Some(label) = label {
+            push_word(&mut all_label, label);
+        }
+        if let Some(effect) = effect {
+            if let Some(label) = effect.label() {
+                push_word(&mut all_label, &format!("/ {}", label));
+            }
+        }
+        let style = self.edge_style();
+        self.edges.borrow_mut().push(Edge {
+            source,
+            target,
+            label: all_label,
+            style,
+        });
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl Writer<'_> {
+    fn push_str(&self, string: &str) {
+        self.out.push_str(string);
+    }
+
+    fn push_line(&self, string: &str) {
+        self.out.push_str(&"    ".repeat(*self.depth.borrow()));
+        self.out.push_line(string);
+    }
+
+    fn open_cluster(&self, id: &ID) {
+        self.push_line(&format!("subgraph {} {{", quote(&cluster_name(id))));
+        *self.depth.borrow_mut() += 1;
+    }
+
+    fn close_cluster(&self) {
+        *self.depth.borrow_mut() -= 1;
+        self.push_line("}");
+    }
+
+    fn state_styles(&self) -> Vec<&'static str> {
+        if self.options.fill_color.is_some() {
+            vec!["rounded", "filled"]
+        } else {
+            vec!["rounded"]
+        }
+    }
+
+    fn state_style(&self) -> &'static str {
+        if self.options.fill_color.is_some() {
+            "\"rounded,filled\""
+        } else {
+            "rounded"
+        }
+    }
+
+    /// The attributes from the node style callback, if any, for the vertex `id`; `styles` are
+    /// those already applied, which any new style must include.
+    fn node_style(&self, id: &ID, styles: &[&str]) -> Vec<String> {
+        let style = match (self.node_style, self.vertices.get(id)) {
+            (Some(styler), Some(vertex)) => styler(vertex),
+            _ => return Vec::new(),
+        };
+        let mut attributes: Vec<String> = Default::default();
+        if style.style.is_some() || (style.fill_color.is_some() && !styles.contains(&"filled")) {
+            let mut all_styles: Vec<&str> = styles.to_vec();
+            if style.fill_color.is_some() && !styles.contains(&"filled") {
+                all_styles.push("filled");
+            }
+            if let Some(style) = &style.style {
+                all_styles.extend(style.split(',').map(str::trim));
+            }
+            attributes.push(format!("style={}", quote(&all_styles.join(","))));
+        }
+        attributes.extend(style_attributes(
+            &style.color,
+            &style.fill_color,
+            &style.font_color,
+            style.pen_width,
+        ));
+        attributes
+    }
+
+    /// The attributes from the edge style callback, if any, for the next transition in the
+    /// current region.
+    fn edge_style(&self) -> Vec<String> {
+        let mut regions = self.regions.borrow_mut();
+        let (region, index) = match regions.last_mut() {
+            Some(current) => current,
+            None => return Vec::new(),
+        };
+        *index += 1;
+        let transition = self
+            .transitions
+            .get(region)
+            .and_then(|transitions| transitions.get(*index - 1));
+        let style = match (self.edge_style, transition) {
+            (Some(styler), Some(transition)) => styler(transition),
+            _ => return Vec::new(),
+        };
+        let mut attributes: Vec<String> = Default::default();
+        if let Some(value) = &style.style {
+            attributes.push(format!("style={}", quote(value)));
+        }
+        attributes.extend(style_attributes(
+            &style.color,
+            &None,
+            &style.font_color,
+            style.pen_width,
+        ));
+        attributes
+    }
+
+    fn is_orthogonal(&self) -> bool {
+        self.region_counts
+            .borrow()
+            .last()
+            .map(|count| *count > 1)
+            .unwrap_or_default()
+    }
+
+    fn finish(self) -> Result<()> {
+        let composites = self.composites.take();
+        for edge in self.edges.take() {
+            let mut attributes: Vec<String> = Default::default();
+            if !edge.label.is_empty() {
+                attributes.push(format!("label={}", quote(&edge.label)));
+            }
+            if composites.contains(&edge.source) {
+                attributes.push(format!("ltail={}", quote(&cluster_name(&edge.source))));
+            }
+            if composites.contains(&edge.target) {
+                attributes.push(format!("lhead={}", quote(&cluster_name(&edge.target))));
+            }
+            attributes.extend(edge.style);
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attributes.join(", "))
+            };
+            self.push_line(&format!(
+                "{} -> {}{};",
+                quote(&edge.source.to_string()),
+                quote(&edge.target.to_string()),
+                attributes
+            ));
+        }
+        self.push_str("}\n");
+        self.out.finish()
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+struct Writer<'w> {
+    options: &'w GraphvizOptions,
+    node_style: Option<&'w NodeStyler>,
+    edge_style: Option<&'w EdgeStyler>,
+    out: Sink<'w>,
+    depth: RefCell<usize>,
+    /// The number of regions of each enclosing state, and the machine, innermost last.
+    region_counts: RefCell<Vec<usize>>,
+    /// States rendered as clusters, edges to these are clipped at the cluster boundary.
+    composites: RefCell<HashSet<ID>>,
+    edges: RefCell<Vec<Edge>>,
+    /// Every vertex, and the transitions of every region, only collected for style callbacks.
+    vertices: HashMap<ID, Arc<Vertex>>,
+    transitions: HashMap<ID, Vec<Arc<Transition>>>,
+    /// The region being visited, innermost last, with the number of its transitions visited.
+    regions: RefCell<Vec<(ID, usize)>>,
+}
+
+struct Edge {
+    source: ID,
+    target: ID,
+    label: String,
+    style: Vec<String>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+fn state_label(
+    id: &ID,
+    label: &Option<String>,
+    entry: &Option<Box<dyn Behavior>>,
+    do_activity: &Option<Box<dyn Behavior>>,
+    exit: &Option<Box<dyn Behavior>>,
+) -> String {
+    let mut lines = vec![label.clone().unwrap_or_else(|| id.to_string())];
+    for (name, behavior) in [("entry", entry), ("do", do_activity), ("exit", exit)] {
+        if let Some(behavior) = behavior {
+            lines.push(format!(
+                "{} / {}",
+                name,
+                behavior.label().as_deref().unwrap_or("()")
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+fn attributes(values: &[(&str, Option<String>)]) -> String {
+    values
+        .iter()
+        .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn style_attributes(
+    color: &Option<String>,
+    fill_color: &Option<String>,
+    font_color: &Option<String>,
+    pen_width: Option<f32>,
+) -> Vec<String> {
+    [
+        ("color", color.as_ref().map(|color| quote(color))),
+        ("fillcolor", fill_color.as_ref().map(|color| quote(color))),
+        ("fontcolor", font_color.as_ref().map(|color| quote(color))),
+        ("penwidth", pen_width.map(|width| width.to_string())),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+    .collect()
+}
+
+/// Attributes to be appended to those of a node.
+fn joined(attributes: Vec<String>) -> String {
+    attributes
+        .iter()
+        .map(|attribute| format!(", {}", attribute))
+        .collect()
+}
+
+fn push_word(label: &mut String, word: &str) {
+    if !label.is_empty() {
+        label.push(' ');
+    }
+    label.push_str(word);
+}
+
+fn cluster_name(id: &ID) -> String {
+    format!("cluster_{}", id)
+}
+
+fn quote(string: &str) -> String {
+    format!(
+        "\"{}\"",
+        string
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
 // ------------------------------------------------------------------------------------------------
 // Modules
 // ------------------------------------------------------------------------------------------------
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::{Identified, Labeled, Region, State, Transition};
+    use crate::format::Stringify;
+
+    #[test]
+    fn test_clusters_and_shapes() {
+        let machine = StateMachine::labeled("player");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+
+        let mut playing = State::within(region.id().clone());
+        playing.set_label("playing");
+        let mut audio = Region::within_state(playing.id().clone());
+        audio.set_label("audio");
+        let track = audio.new_simple_state();
+        playing.add_region(audio);
+        let _ = playing.new_region();
+        let video = playing.regions().last().unwrap().new_simple_state();
+        let playing_id = playing.id().clone();
+        region.add_state(playing);
+
+        let choice = region.new_choice_state();
+        let fork = region.new_fork();
+        let done = region.new_final_state();
+        let idle = region.new_simple_state();
+        region.new_transition(initial, idle.clone());
+        let mut transition = Transition::within(idle, playing_id.clone(), region.id().clone());
+        transition.set_kind(TransitionKind::External);
+        transition.add_trigger(Trigger::signal("play"));
+        region.add_transition(transition);
+        region.new_transition(playing_id.clone(), choice.clone());
+        region.new_transition(choice, fork.clone());
+        region.new_transition(fork.clone(), track);
+        region.new_transition(fork, video);
+
+        let dot = WriteGraphviz::default().stringify(&machine).unwrap();
+        assert!(dot.starts_with("digraph \"player\" {\n"));
+        assert!(dot.contains(&format!("subgraph \"cluster_{}\" {{", playing_id)));
+        assert_eq!(dot.matches("style=dashed;").count(), 2);
+        assert!(dot.contains("label=\"audio\";"));
+        assert!(dot.contains("shape=diamond"));
+        assert!(dot.contains("shape=box, width=0.05"));
+        assert!(dot.contains(&format!(
+            "\"{}\" [shape=doublecircle, label=\"{}\"];",
+            done, done
+        )));
+        assert!(dot.contains(&format!(
+            "-> \"{}\" [label=\"play\", lhead=\"cluster_{}\"];",
+            playing_id, playing_id
+        )));
+        assert!(dot.contains(&format!("ltail=\"cluster_{}\"", playing_id)));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_options() {
+        let machine = StateMachine::labeled("light");
+        let region = machine.default_region().unwrap();
+        let on = region.new_simple_state();
+
+        let dot = WriteGraphviz::default().stringify(&machine).unwrap();
+        assert!(dot.contains("rankdir=LR;\n"));
+        assert!(dot.contains("edge [fontname=\"Helvetica\", fontsize=10];\n"));
+        assert!(dot.contains("shape=box, style=rounded,"));
+
+        let writer = WriteGraphviz::with_options(GraphvizOptions {
+            direction: Direction::TopToBottom,
+            font_name: None,
+            font_size: Some(12),
+            line_color: Some("#336699".to_string()),
+            fill_color: Some("lightyellow".to_string()),
+            ..Default::default()
+        });
+        let dot = writer.stringify(&machine).unwrap();
+        assert!(dot.contains("rankdir=TB;\n"));
+        assert!(dot.contains("node [fontsize=12, color=\"#336699\", fillcolor=\"lightyellow\"];\n"));
+        assert!(dot.contains("edge [fontsize=10, color=\"#336699\"];\n"));
+        assert!(dot.contains(&format!("\"{}\" [shape=box, style=\"rounded,filled\",", on)));
+    }
+
+    #[test]
+    fn test_style_callbacks() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut failed = State::within(region.id().clone());
+        failed.set_label("failed");
+        let failed_id = failed.id().clone();
+        region.add_state(failed);
+        region.new_transition(initial, failed_id.clone());
+        let mut transition =
+            Transition::within(failed_id.clone(), failed_id.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("error"));
+        region.add_transition(transition);
+        region.new_transition(failed_id.clone(), failed_id.clone());
+
+        let writer = WriteGraphviz::default()
+            .with_node_style(|vertex: &Vertex| match vertex {
+                Vertex::State(_) => NodeStyle {
+                    color: Some("red".to_string()),
+                    fill_color: Some("mistyrose".to_string()),
+                    style: Some("bold".to_string()),
+                    ..Default::default()
+                },
+                _ => NodeStyle::default(),
+            })
+            .with_edge_style(|transition: &Transition| {
+                if transition.has_triggers() {
+                    EdgeStyle {
+                        pen_width: Some(2.5),
+                        style: Some("bold".to_string()),
+                        ..Default::default()
+                    }
+                } else {
+                    EdgeStyle::default()
+                }
+            });
+        let dot = writer.stringify(&machine).unwrap();
+        assert!(dot.contains(&format!(
+            "\"{}\" [shape=box, style=rounded, label=\"failed\", style=\"rounded,filled,bold\", color=\"red\", fillcolor=\"mistyrose\"];",
+            failed_id
+        )));
+        assert!(dot.contains("[label=\"error\", style=\"bold\", penwidth=2.5];"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", failed_id, failed_id)));
+    }
+}
@@ -0,0 +1,143 @@
+/*!
+The position and size of an element in a diagram, kept as presentation metadata so that a machine
+read from a format that records its layout may be written back without losing it.
+
+A layout is the metadata value `LAYOUT_METADATA` of a state, an object whose members `x` and `y`
+are the position of the element's top-left corner, and `width` and `height`, where known, its
+size. The SCXML, XMI, and xstate readers set it from the layout extensions of each format, and
+their writers emit it again, so that a layout read from one may also be written to another.
+
+# Example
+
+```rust
+use uml_state_machine::definition::types::{HasMetadata, State};
+use uml_state_machine::format::layout::{layout, set_layout, Layout};
+use uml_state_machine::core::ID;
+
+let mut state = State::within(ID::random());
+assert_eq!(layout(state.metadata()), None);
+
+set_layout(&mut state, Layout::at(10.0, 20.0).with_size(120.0, 60.0));
+assert_eq!(
+    layout(state.metadata()),
+    Some(Layout { x: 10.0, y: 20.0, width: Some(120.0), height: Some(60.0) })
+);
+```
+
+*/
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::core::context::{Compound, FieldName, Object};
+use crate::core::FieldValue;
+use crate::definition::types::HasMetadata;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The position, and optionally the size, of an element in a diagram.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Layout {
+    /// The horizontal position of the element's top-left corner.
+    pub x: f64,
+    /// The vertical position of the element's top-left corner.
+    pub y: f64,
+    /// The element's width, if known.
+    pub width: Option<f64>,
+    /// The element's height, if known.
+    pub height: Option<f64>,
+}
+
+///
+/// The metadata key under which an element's `Layout` is kept.
+///
+pub const LAYOUT_METADATA: &str = "layout";
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The layout kept in `metadata`, if there is one; members that are not numbers are ignored, and
+/// there is no layout without both `x` and `y`.
+///
+pub fn layout(metadata: &HashMap<String, FieldValue>) -> Option<Layout> {
+    match metadata.get(LAYOUT_METADATA) {
+        Some(FieldValue::Object(object)) => {
+            let object = object.read().unwrap();
+            let member = |name: &str| number(object.get(FieldName::from_str(name).unwrap()));
+            Some(Layout {
+                x: member("x")?,
+                y: member("y")?,
+                width: member("width"),
+                height: member("height"),
+            })
+        }
+        _ => None,
+    }
+}
+
+///
+/// Keep `layout` in the metadata of `element`, replacing any layout it had.
+///
+pub fn set_layout(element: &mut impl HasMetadata, layout: Layout) {
+    element.set_metadata(LAYOUT_METADATA, layout.into())
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Layout {
+    pub fn at(x: f64, y: f64) -> Self {
+        Self {
+            x,
+            y,
+            width: None,
+            height: None,
+        }
+    }
+
+    pub fn with_size(self, width: f64, height: f64) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+            ..self
+        }
+    }
+}
+
+impl From<Layout> for FieldValue {
+    fn from(layout: Layout) -> Self {
+        let object = Object::default();
+        let members = [
+            ("x", Some(layout.x)),
+            ("y", Some(layout.y)),
+            ("width", layout.width),
+            ("height", layout.height),
+        ];
+        for (name, value) in members.iter() {
+            if let Some(value) = value {
+                object.insert(FieldName::from_str(name).unwrap(), (*value).into());
+            }
+        }
+        object.into()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn number(value: Option<FieldValue>) -> Option<f64> {
+    match value? {
+        FieldValue::Float(value) => Some(value),
+        FieldValue::Integer(value) => Some(value as f64),
+        FieldValue::Byte(value) => Some(f64::from(value)),
+        _ => None,
+    }
+}
@@ -2,7 +2,11 @@
 Provides support for parsing, and emitting, external representations of a `StateMachine`.
 
 Each sub-module provides for a different representation type but may not implement both the
-`Parse` and `Stringify` traits.
+`Parse` and `Write` traits. Every writer implements `Write`, which emits directly to an
+`std::io::Write`, and so also `Stringify` for when the representation is wanted as a `String`.
+
+The formats that record the layout of a diagram, SCXML, XMI, and xstate, keep it as the
+presentation metadata described in `layout`, so that it survives a round trip.
 
 # Example
 
@@ -24,9 +28,45 @@ let writer = WritePlantUml::default();
 let string = writer.stringify(&simple);
 ```
 
+Large machines can be written directly to a file, or any other `std::io::Write`.
+
+```rust,no_run
+use std::fs::File;
+use std::io::BufWriter;
+use uml_state_machine::definition::types::StateMachine;
+use uml_state_machine::format::plant_uml::WritePlantUml;
+use uml_state_machine::format::Write;
+
+let machine = StateMachine::default();
+let mut file = BufWriter::new(File::create("machine.puml").unwrap());
+WritePlantUml::default().write(&machine, &mut file).unwrap();
+```
+
+Where the format of the input is not known in advance `parse_any` will detect it, and use the
+parser for that format if it is enabled.
+
+```rust
+use uml_state_machine::definition::types::Labeled;
+use uml_state_machine::format::{detect_format, parse_any, Format};
+
+let input = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" name="door">
+  <state id="closed"/>
+</scxml>"#;
+assert_eq!(detect_format(input), Some(Format::Scxml));
+
+let machine = parse_any(input).unwrap();
+assert_eq!(machine.label(), &Some("door".to_string()));
+```
+
 */
 
+use std::cell::RefCell;
+use std::io;
+#[cfg(any(feature = "format-plantuml", feature = "format-uml"))]
+use std::time::Duration;
+
 use crate::definition::types::StateMachine;
+use crate::error::{Error, ErrorKind};
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
@@ -50,37 +90,317 @@ pub trait Stringify {
     fn stringify(&self, machine: &StateMachine) -> Result<String, Self::Error>;
 }
 
+///
+/// Write a textual representation of the state machine to `w`, as it is generated rather than
+/// first building it as a `String`. Writers stream as much of their output as the format allows,
+/// formats that must be complete before writing, such as JSON, are written from an in-memory
+/// value. If writing fails part of the representation may already have been written.
+///
+pub trait Write {
+    type Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<(), Self::Error>;
+}
+
+///
+/// The direction in which a diagram writer lays out the flow of a machine; not every diagram
+/// format supports every direction.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    TopToBottom,
+    LeftToRight,
+    BottomToTop,
+    RightToLeft,
+}
+
+///
+/// The external representations that `detect_format` can recognize.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A Graphviz DOT graph, which can be written but not read.
+    Dot,
+    /// The lossless JSON format of the `native` module.
+    Native,
+    /// A PlantUML diagram, starting with `@startuml`.
+    PlantUml,
+    /// An XML document with an `<scxml>` root element.
+    Scxml,
+    /// Any other XML document, assumed to be UML XMI.
+    Xmi,
+    /// Any other JSON document, assumed to be an XState machine configuration.
+    XState,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Public Functions
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Detect the format of `input` from its first significant content: the root element of an XML
+/// document, whether a JSON document is in the native format, the `@startuml` of PlantUML, or
+/// the graph keyword of DOT. Returns `None` where the format is not recognized.
+///
+pub fn detect_format(input: &str) -> Option<Format> {
+    let input = input.trim_start_matches('\u{feff}').trim_start();
+    if input.starts_with('<') {
+        match xml_root_name(input) {
+            Some("scxml") => Some(Format::Scxml),
+            Some(_) => Some(Format::Xmi),
+            None => None,
+        }
+    } else if input.starts_with('{') {
+        if is_native_json(input) {
+            Some(Format::Native)
+        } else {
+            Some(Format::XState)
+        }
+    } else {
+        let first_line = input
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('\'') && !line.starts_with("//"))?;
+        let keyword = first_line
+            .split(|c: char| c.is_whitespace() || c == '{')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if keyword == "@startuml" {
+            Some(Format::PlantUml)
+        } else if ["digraph", "graph", "strict"].contains(&keyword.as_str()) {
+            Some(Format::Dot)
+        } else {
+            None
+        }
+    }
+}
+
+///
+/// Parse `input`, in whichever format `detect_format` recognizes, with the parser for that
+/// format. This fails where the format is not recognized, or where it cannot be read because
+/// it has no parser or the feature for its parser is not enabled.
+///
+pub fn parse_any(input: &str) -> Result<StateMachine, Error> {
+    match detect_format(input) {
+        None => {
+            Err(ErrorKind::FormatParse("the input format was not recognized".to_string()).into())
+        }
+        #[cfg(feature = "format-native")]
+        Some(Format::Native) => native::ReadNative::default().parse(input),
+        #[cfg(feature = "format-plantuml")]
+        Some(Format::PlantUml) => plant_uml::ReadPlantUml::default().parse(input),
+        #[cfg(feature = "format-scxml")]
+        Some(Format::Scxml) => scxml::ReadScxml::default().parse(input),
+        #[cfg(feature = "format-uml")]
+        Some(Format::Xmi) => uml::ReadXmi::default().parse(input),
+        #[cfg(feature = "format-xstate")]
+        Some(Format::XState) => xstate::ReadXState::default().parse(input),
+        #[allow(unreachable_patterns)]
+        Some(format) => Err(ErrorKind::FormatUnsupported(format!("reading {:?}", format)).into()),
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Implementations
 // ------------------------------------------------------------------------------------------------
 
+impl<T> Stringify for T
+where
+    T: Write<Error = Error>,
+{
+    type Error = Error;
+
+    fn stringify(&self, machine: &StateMachine) -> Result<String, Self::Error> {
+        let mut buffer: Vec<u8> = Default::default();
+        self.write(machine, &mut buffer)?;
+        Ok(String::from_utf8(buffer).expect("writers only emit UTF-8"))
+    }
+}
+
+#[allow(dead_code)]
+impl<'w> Sink<'w> {
+    pub(crate) fn new(w: &'w mut dyn io::Write) -> Self {
+        Self {
+            out: RefCell::new(w),
+            error: Default::default(),
+        }
+    }
+
+    pub(crate) fn push_str(&self, string: &str) {
+        let mut error = self.error.borrow_mut();
+        if error.is_none() {
+            if let Err(e) = self.out.borrow_mut().write_all(string.as_bytes()) {
+                *error = Some(e);
+            }
+        }
+    }
+
+    pub(crate) fn push_line(&self, string: &str) {
+        self.push_str(string);
+        self.push_str("\n");
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        match self.error.into_inner() {
+            Some(e) => Err(e.into()),
+            None => Ok(self.out.into_inner().flush()?),
+        }
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Output for writers that implement `StateMachineVisitor`, where visitor methods cannot return
+/// errors; the first error writing is kept and returned by `finish`.
+///
+pub(crate) struct Sink<'w> {
+    out: RefCell<&'w mut dyn io::Write>,
+    error: RefCell<Option<io::Error>>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Parse a duration in the form written by its `Debug` implementation, such as `1.5s` or `250ms`.
+///
+#[cfg(any(feature = "format-plantuml", feature = "format-uml"))]
+pub(crate) fn parse_duration(duration: &str) -> Option<Duration> {
+    let units: [(&str, f64); 5] = [
+        ("ns", 1.0),
+        ("µs", 1e3),
+        ("us", 1e3),
+        ("ms", 1e6),
+        ("s", 1e9),
+    ];
+    units.iter().find_map(|(suffix, nanos)| {
+        duration
+            .strip_suffix(suffix)
+            .and_then(|value| value.parse::<f64>().ok())
+            .map(|value| Duration::from_nanos((value * nanos).round() as u64))
+    })
+}
+
+///
+/// The local name of the root element of an XML document, skipping any declaration, processing
+/// instructions, comments, and document type.
+///
+fn xml_root_name(input: &str) -> Option<&str> {
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = &comment[comment.find("-->")? + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = &rest[rest.find('>')? + 1..];
+        } else {
+            let name = rest.strip_prefix('<')?;
+            let end = name.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+            let name = &name[..end];
+            return Some(name.rsplit(':').next().unwrap_or(name));
+        }
+    }
+}
+
+#[cfg(any(feature = "format-native", feature = "format-xstate"))]
+fn is_native_json(input: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(input)
+        .ok()
+        .and_then(|value| {
+            value
+                .get("format")
+                .and_then(serde_json::Value::as_str)
+                .map(|format| format == "uml_state_machine")
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(not(any(feature = "format-native", feature = "format-xstate")))]
+fn is_native_json(input: &str) -> bool {
+    input.contains("\"uml_state_machine\"")
+}
+
 // ------------------------------------------------------------------------------------------------
 // Modules
 // ------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "format-event-schema")]
+pub mod event_schema;
+
+#[cfg(any(feature = "format-scxml", feature = "format-xstate"))]
+mod names;
+
 #[cfg(feature = "format-graphviz")]
 pub mod graphviz;
 
+pub mod layout;
+
+#[cfg(feature = "format-native")]
+pub mod native;
+
 #[cfg(feature = "format-plantuml")]
 pub mod plant_uml;
 
 #[cfg(feature = "format-scxml")]
 pub mod scxml;
 
+#[cfg(feature = "format-smcat")]
+pub mod smcat;
+
+#[cfg(feature = "format-table")]
+pub mod table;
+
 #[cfg(feature = "format-uml")]
 pub mod uml;
 
 #[cfg(feature = "format-xstate")]
 pub mod xstate;
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            detect_format("<?xml version=\"1.0\"?>\n<!-- a -> b -->\n<scxml version=\"1.0\"/>"),
+            Some(Format::Scxml)
+        );
+        assert_eq!(
+            detect_format("<xmi:XMI xmlns:xmi=\"http://www.omg.org/spec/XMI/20131001\"/>"),
+            Some(Format::Xmi)
+        );
+        assert_eq!(
+            detect_format(r#"{"format": "uml_state_machine", "version": 1}"#),
+            Some(Format::Native)
+        );
+        assert_eq!(detect_format(r#"{"id": "fetch"}"#), Some(Format::XState));
+        assert_eq!(
+            detect_format("' a comment\n@startuml\n[*] --> idle\n@enduml"),
+            Some(Format::PlantUml)
+        );
+        assert_eq!(detect_format("digraph machine {}"), Some(Format::Dot));
+        assert_eq!(detect_format("idle -> busy;"), None);
+    }
+
+    #[test]
+    fn test_parse_any_errors() {
+        assert!(matches!(
+            parse_any("idle -> busy;").err().unwrap().kind(),
+            ErrorKind::FormatParse(_)
+        ));
+        assert!(matches!(
+            parse_any("digraph machine {}").err().unwrap().kind(),
+            ErrorKind::FormatUnsupported(_)
+        ));
+    }
+}
@@ -0,0 +1,125 @@
+/*!
+Assigns each named element of a machine a name unique within it, for formats that identify
+elements by name rather than by model ID.
+*/
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::slice::Iter;
+
+use crate::core::ID;
+use crate::definition::types::{
+    Behavior, Constraint, PseudoState, PseudoStateKind, StateMachine, Trigger,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::Result;
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Return a name for the machine and each of its regions and vertices. An element's label is
+/// used where it is unique within the machine, otherwise its ID; either is passed through
+/// `sanitize`, and a numeric suffix added should the result still clash.
+///
+pub(crate) fn unique_names(
+    machine: &StateMachine,
+    sanitize: fn(&str) -> String,
+) -> Result<HashMap<ID, String>> {
+    let names = Names::default();
+    visit_state_machine(machine, &names)?;
+    Ok(names.into_names(sanitize))
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachineVisitor for Names {
+    fn enter_state_machine(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _machine_states: Iter<'_, ID>,
+        _connection_points: Iter<'_, PseudoState>,
+    ) {
+        self.add(id, label)
+    }
+
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _comments: Iter<'_, String>,
+        _region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        self.add(id, label)
+    }
+
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, label: &Option<String>, _: bool) {
+        self.add(id, label)
+    }
+
+    fn pseudo_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _kind: &PseudoStateKind,
+    ) {
+        self.add(id, label)
+    }
+}
+
+impl Names {
+    fn add(&self, id: &ID, label: &Option<String>) {
+        self.elements.borrow_mut().push((id.clone(), label.clone()))
+    }
+
+    fn into_names(self, sanitize: fn(&str) -> String) -> HashMap<ID, String> {
+        let elements = self.elements.into_inner();
+        let mut label_counts: HashMap<String, usize> = Default::default();
+        for label in elements.iter().filter_map(|(_, label)| label.as_ref()) {
+            *label_counts.entry(label.clone()).or_default() += 1;
+        }
+        let mut used: HashSet<String> = Default::default();
+        let mut ids: HashMap<ID, String> = Default::default();
+        for (id, label) in elements {
+            let candidate = match label {
+                Some(label) if label_counts[&label] == 1 => label,
+                _ => id.to_string(),
+            };
+            let base = sanitize(&candidate);
+            let mut name = base.clone();
+            let mut suffix = 1;
+            while used.contains(&name) {
+                suffix += 1;
+                name = format!("{}_{}", base, suffix);
+            }
+            let _ = used.insert(name.clone());
+            let _ = ids.insert(id, name);
+        }
+        ids
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Names {
+    elements: RefCell<Vec<(ID, Option<String>)>>,
+}
@@ -0,0 +1,1539 @@
+/*!
+Reads and writes a state machine in this crate's own, versioned, JSON format. Unlike the other
+formats it records every field of the model, including those no interchange format represents,
+such as container IDs and connection points, so that it may be used to persist a machine and read
+it back unchanged.
+
+# Schema
+
+A document is an object with a `format` member, always `"uml_state_machine"`, a `version`,
+currently `1`, and the `machine`. Readers reject documents written with a later version. Members
+with no value, and empty arrays, are omitted when writing and take their default when reading.
+
+* The `machine` has an `id`, `label`, `regions`, `sub_machine_states`, a list of state IDs,
+  `connection_points`, a list of pseudostates, and the ID of the machine it `extends`, if any.
+* A region has an `id`, `label`, `container`, `container_type`, one of `state_machine` or `state`,
+  the ID of the region it `extends`, if any, `vertices`, and `transitions`.
+* A vertex has a `type` and the members of that type.
+  * `state`: `id`, `label`, `container`, `comments`, `regions`, `sub_machine`, `connections`,
+    `connection_points`, `deferrable_triggers`, `invariant`, `entry`, `do_activity`, `exit`,
+    `invokes`, and `final`. Each invoke has an `id`, `label`, `kind`, `source`, and `params`, each
+    with a `name` and the context `path` it is taken from.
+  * `pseudo_state`: `id`, `label`, `container`, and `kind`, one of `initial`, `deep_history`,
+    `shallow_history`, `join`, `fork`, `junction`, `choice`, `entry_point`, `exit_point`, or
+    `terminate`.
+  * `connection_point_reference`: `id`, `label`, `container`, `entry`, `exit`, and `state`.
+  * Each vertex also has the ID of the vertex it `redefines`, if any.
+* The machine, regions, states, and transitions may each have `metadata`, an object whose members
+  are field values, written as for predicates below, ordered by name.
+* A transition has a `label`, `container`, `kind`, one of `internal`, `local`, or `external`,
+  `source`, `target`, `triggers`, `guard`, `effect`, and the transition it `redefines`, if any,
+  with the `region` and `index` of that transition.
+* A trigger has an `event` and `payload` predicates. Each predicate has a `path`, an `operator`
+  as displayed, such as `"<="`, and a `value` as an object with a single member naming its type,
+  one of `bool`, `byte`, `integer`, `float`, `string`, `array`, or `object`.
+* An event has a `type` of `signal`, with a `signal` and, where it has a schema, the `payload`
+  fields of the signal, each with a `path` and the `kind` of its value, named as for predicate
+  values, `call`, with an `operation`, `after` or
+  `at`, with `seconds` and `nanos`, which for `at` are since the Unix epoch, `change`, with an
+  `expression`, or `any_receive`.
+
+A trace, see `execution::trace`, is written as a document with a `trace` member, in place of the
+`machine`, that is a list of entries. Each entry has a `time`, with `seconds` and `nanos` since
+the Unix epoch, the `event`, omitted where there was none, the transitions `fired`, each with a
+`source`, `target`, and `label`, the state IDs active `before` and `after`, and a `context_hash`
+as a string of hexadecimal digits.
+
+A snapshot of an instance, see `StateMachineInstance::snapshot`, is written as a document with a
+`snapshot` member, in place of the `machine`. It has the instance's `id`, the ID of its
+`machine`, its `state`, one of `new`, `active`, `done`, or `error`, `paused` where it is paused,
+the `active` state IDs, the `history` of each region, with the `region` and its `states`, the
+`queue` of events, each with an `event`, the `target` state, if any, and the `payload`, if any,
+as a field value, the `internal` events raised, the `deferred` events, written as for the queue,
+the `timers`, each with a `state`, `event`, and the time it is `due`, the delayed `sends`, each
+with its `id`, `event`, `payload`, if any, and the time it is `due`, and, where any have been
+sent, the `next_send` ID, the state IDs whose
+`activities` had not finished, and the `context` as a field value.
+
+Constraints and behaviors are code, so are written as an object with only their `label`, and are
+read as `OpaqueConstraint` and `OpaqueBehavior` values with that label as their body.
+
+As JSON is a subset of YAML 1.2 the written document may also be read by any YAML tooling. With
+the `format-native-yaml` feature `WriteNativeYaml` writes the same document as block-style YAML,
+and `ReadNativeYaml` reads it.
+
+# Example
+
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::native::{ReadNative, WriteNative};
+use uml_state_machine::format::{Parse, Stringify};
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let closed = region.new_simple_state();
+region.new_transition(initial, closed.clone());
+
+let json = WriteNative::default().stringify(&machine).unwrap();
+let read = ReadNative::default().parse(&json).unwrap();
+assert_eq!(read.id(), machine.id());
+assert_eq!(read.default_region().unwrap().id(), region.id());
+assert_eq!(WriteNative::default().stringify(&read).unwrap(), json);
+```
+
+*/
+
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use crate::core::context::{Array, Compound, FieldKind, FieldName, FieldPath, FieldValue, Object};
+#[cfg(feature = "execution")]
+use crate::core::Context;
+use crate::core::ID;
+use crate::definition::guards::recognize_else;
+use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+use crate::definition::types::{
+    Behavior, CallEvent, ChangeEvent, ComparisonOperator, ConnectionPointReference, Constraint,
+    Event, Invoke, Labeled, PayloadPredicate, PseudoState, PseudoStateKind, Region,
+    RegionContainerType, SignalEvent, State, StateMachine, TimeEvent, Transition, TransitionKind,
+    Trigger, Vertex,
+};
+use crate::error::{Error, ErrorKind, Result};
+#[cfg(feature = "execution")]
+use crate::execution::timers::{DelayedSend, SendId, Timer};
+#[cfg(feature = "execution")]
+use crate::execution::trace::{ExecutionTrace, TraceEntry};
+#[cfg(feature = "execution")]
+use crate::execution::types::{ExecutionState, FiredTransition, InstanceSnapshot, Occurrence};
+use crate::format::{Parse, Write};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The value of the `format` member of every native document.
+///
+pub const NATIVE_FORMAT: &str = "uml_state_machine";
+
+///
+/// The version of the schema written by `WriteNative`, and the latest read by `ReadNative`.
+///
+pub const NATIVE_VERSION: u64 = 1;
+
+///
+/// Implements Write, and so Stringify, for the native JSON format.
+///
+#[derive(Debug, Default)]
+pub struct WriteNative {}
+
+///
+/// Implements Parse for the native JSON format.
+///
+#[derive(Debug, Default)]
+pub struct ReadNative {}
+
+///
+/// Implements Write, and so Stringify, for the native format as block-style YAML.
+///
+#[cfg(feature = "format-native-yaml")]
+#[derive(Debug, Default)]
+pub struct WriteNativeYaml {}
+
+///
+/// Implements Parse for the native format written as YAML.
+///
+#[cfg(feature = "format-native-yaml")]
+#[derive(Debug, Default)]
+pub struct ReadNativeYaml {}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Write for WriteNative {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let document = machine_document(machine)?;
+        serde_json::to_writer_pretty(&mut *w, &document).map_err(io::Error::from)?;
+        Ok(w.flush()?)
+    }
+}
+
+impl Parse for ReadNative {
+    type Error = Error;
+
+    fn parse(&self, string: &str) -> Result<StateMachine> {
+        read_machine(&read_document(string)?)
+    }
+}
+
+#[cfg(feature = "format-native-yaml")]
+impl Write for WriteNativeYaml {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let document = machine_document(machine)?;
+        serde_yaml::to_writer(&mut *w, &document).map_err(io::Error::other)?;
+        Ok(w.flush()?)
+    }
+}
+
+#[cfg(feature = "format-native-yaml")]
+impl Parse for ReadNativeYaml {
+    type Error = Error;
+
+    fn parse(&self, string: &str) -> Result<StateMachine> {
+        let document: Value =
+            serde_yaml::from_str(string).map_err(|e| parse_error(&e.to_string()))?;
+        read_machine(&check_document(document)?)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn machine_document(machine: &StateMachine) -> Result<Value> {
+    let mut object = Map::new();
+    let _ = object.insert("id".to_string(), json!(machine.id.to_string()));
+    insert_option(&mut object, "label", &machine.label);
+    let _ = object.insert(
+        "regions".to_string(),
+        Value::Array(
+            machine
+                .regions
+                .iter()
+                .map(region_value)
+                .collect::<Result<Vec<Value>>>()?,
+        ),
+    );
+    insert_ids(
+        &mut object,
+        "sub_machine_states",
+        &machine.sub_machine_states,
+    );
+    insert_values(
+        &mut object,
+        "connection_points",
+        machine
+            .connection_points
+            .iter()
+            .map(pseudo_state_value)
+            .collect(),
+    );
+    insert_id(&mut object, "extends", &machine.extended);
+    insert_metadata(&mut object, &machine.metadata);
+    Ok(json!({
+        "format": NATIVE_FORMAT,
+        "version": NATIVE_VERSION,
+        "machine": object,
+    }))
+}
+
+fn read_machine(document: &Value) -> Result<StateMachine> {
+    let document = as_object(document, "document")?;
+    let object = as_object(required(document, "machine")?, "machine")?;
+    let machine = StateMachine {
+        id: id(required(object, "id")?)?,
+        label: optional_string(object, "label")?,
+        regions: array(object, "regions")?
+            .iter()
+            .map(read_region)
+            .collect::<Result<Vec<Region>>>()?,
+        sub_machine_states: ids(object, "sub_machine_states")?,
+        connection_points: array(object, "connection_points")?
+            .iter()
+            .map(read_pseudo_state)
+            .collect::<Result<Vec<PseudoState>>>()?,
+        extended: optional_id(object, "extends")?,
+        metadata: read_metadata(object)?,
+        ref_machines: Default::default(),
+        ref_vertices: Default::default(),
+        ref_transitions: Default::default(),
+    };
+    if machine.regions.is_empty() {
+        return Err(parse_error("machine has no regions"));
+    }
+    Ok(machine)
+}
+
+///
+/// Write `trace` as a native document with a `trace` member in place of the `machine`.
+///
+#[cfg(feature = "execution")]
+pub(crate) fn write_trace(trace: &ExecutionTrace) -> Result<String> {
+    let entries = trace
+        .entries()
+        .map(trace_entry_value)
+        .collect::<Result<Vec<Value>>>()?;
+    let document = json!({
+        "format": NATIVE_FORMAT,
+        "version": NATIVE_VERSION,
+        "trace": entries,
+    });
+    serde_json::to_string_pretty(&document).map_err(|e| io::Error::from(e).into())
+}
+
+#[cfg(feature = "execution")]
+pub(crate) fn read_trace(json: &str) -> Result<ExecutionTrace> {
+    let document = read_document(json)?;
+    let document = as_object(&document, "document")?;
+    let entries = required(document, "trace")?
+        .as_array()
+        .ok_or_else(|| parse_error("'trace' is not an array"))?
+        .iter()
+        .map(read_trace_entry)
+        .collect::<Result<Vec<TraceEntry>>>()?;
+    Ok(entries.into())
+}
+
+///
+/// Write `snapshot` as a native document with a `snapshot` member in place of the `machine`.
+///
+#[cfg(feature = "execution")]
+pub(crate) fn write_snapshot(snapshot: &InstanceSnapshot) -> Result<String> {
+    let mut object = Map::new();
+    let _ = object.insert("id".to_string(), json!(snapshot.id.to_string()));
+    let _ = object.insert("machine".to_string(), json!(snapshot.chart.to_string()));
+    let _ = object.insert(
+        "state".to_string(),
+        json!(match snapshot.state {
+            ExecutionState::New => "new",
+            ExecutionState::Active | ExecutionState::InAction => "active",
+            ExecutionState::Done => "done",
+            ExecutionState::Error => "error",
+        }),
+    );
+    if snapshot.paused {
+        let _ = object.insert("paused".to_string(), json!(true));
+    }
+    insert_ids(&mut object, "active", &snapshot.active);
+    let mut history: Vec<(&ID, &Vec<ID>)> = snapshot.history.iter().collect();
+    history.sort_by_key(|(region, _)| region.to_string());
+    insert_values(
+        &mut object,
+        "history",
+        history
+            .into_iter()
+            .map(|(region, states)| {
+                let mut object = Map::new();
+                let _ = object.insert("region".to_string(), json!(region.to_string()));
+                insert_ids(&mut object, "states", states);
+                Value::Object(object)
+            })
+            .collect(),
+    );
+    let queue = snapshot
+        .queue
+        .iter()
+        .map(occurrence_value)
+        .collect::<Result<Vec<Value>>>()?;
+    insert_values(&mut object, "queue", queue);
+    let internal = snapshot
+        .internal
+        .iter()
+        .map(event_value)
+        .collect::<Result<Vec<Value>>>()?;
+    insert_values(&mut object, "internal", internal);
+    let deferred = snapshot
+        .deferred
+        .iter()
+        .map(occurrence_value)
+        .collect::<Result<Vec<Value>>>()?;
+    insert_values(&mut object, "deferred", deferred);
+    let timers = snapshot
+        .timers
+        .iter()
+        .map(|timer| {
+            Ok(json!({
+                "state": timer.state.to_string(),
+                "event": event_value(&timer.event)?,
+                "due": system_time_value(&timer.due)?,
+            }))
+        })
+        .collect::<Result<Vec<Value>>>()?;
+    insert_values(&mut object, "timers", timers);
+    let sends = snapshot
+        .sends
+        .iter()
+        .map(|send| {
+            let mut value = json!({
+                "id": send.id.0,
+                "event": event_value(&send.event)?,
+                "due": system_time_value(&send.due)?,
+            });
+            if let Some(payload) = &send.payload {
+                let _ = value
+                    .as_object_mut()
+                    .unwrap()
+                    .insert("payload".to_string(), field_value(payload.root()));
+            }
+            Ok(value)
+        })
+        .collect::<Result<Vec<Value>>>()?;
+    insert_values(&mut object, "sends", sends);
+    if snapshot.next_send > 0 {
+        let _ = object.insert("next_send".to_string(), json!(snapshot.next_send));
+    }
+    insert_ids(&mut object, "activities", &snapshot.activities);
+    let _ = object.insert("context".to_string(), field_value(snapshot.context.root()));
+    let document = json!({
+        "format": NATIVE_FORMAT,
+        "version": NATIVE_VERSION,
+        "snapshot": object,
+    });
+    serde_json::to_string_pretty(&document).map_err(|e| io::Error::from(e).into())
+}
+
+#[cfg(feature = "execution")]
+pub(crate) fn read_snapshot(json: &str) -> Result<InstanceSnapshot> {
+    let document = read_document(json)?;
+    let document = as_object(&document, "document")?;
+    let object = as_object(required(document, "snapshot")?, "snapshot")?;
+    let events = |name: &str| -> Result<Vec<Event>> {
+        array(object, name)?.iter().map(read_event).collect()
+    };
+    Ok(InstanceSnapshot {
+        id: id(required(object, "id")?)?,
+        chart: id(required(object, "machine")?)?,
+        state: match string(object, "state")? {
+            "new" => ExecutionState::New,
+            "active" => ExecutionState::Active,
+            "done" => ExecutionState::Done,
+            "error" => ExecutionState::Error,
+            other => return Err(parse_error(&format!("unknown state '{}'", other))),
+        },
+        paused: match object.get("paused") {
+            None => false,
+            Some(value) => value
+                .as_bool()
+                .ok_or_else(|| parse_error("'paused' is not a boolean"))?,
+        },
+        active: ids(object, "active")?,
+        history: array(object, "history")?
+            .iter()
+            .map(|value| {
+                let object = as_object(value, "history")?;
+                Ok((id(required(object, "region")?)?, ids(object, "states")?))
+            })
+            .collect::<Result<HashMap<ID, Vec<ID>>>>()?,
+        queue: array(object, "queue")?
+            .iter()
+            .map(read_occurrence)
+            .collect::<Result<Vec<Occurrence>>>()?,
+        internal: events("internal")?,
+        deferred: array(object, "deferred")?
+            .iter()
+            .map(read_occurrence)
+            .collect::<Result<Vec<Occurrence>>>()?,
+        timers: array(object, "timers")?
+            .iter()
+            .map(|value| {
+                let object = as_object(value, "timer")?;
+                Ok(Timer {
+                    state: id(required(object, "state")?)?,
+                    event: read_event(required(object, "event")?)?,
+                    due: read_system_time(required(object, "due")?)?,
+                })
+            })
+            .collect::<Result<Vec<Timer>>>()?,
+        sends: array(object, "sends")?
+            .iter()
+            .map(|value| {
+                let object = as_object(value, "send")?;
+                Ok(DelayedSend {
+                    id: SendId(unsigned(required(object, "id")?, "id")?),
+                    event: read_event(required(object, "event")?)?,
+                    payload: object
+                        .get("payload")
+                        .map(|value| read_context(value, "payload"))
+                        .transpose()?,
+                    due: read_system_time(required(object, "due")?)?,
+                })
+            })
+            .collect::<Result<Vec<DelayedSend>>>()?,
+        next_send: match object.get("next_send") {
+            None => 0,
+            Some(value) => unsigned(value, "next_send")?,
+        },
+        activities: ids(object, "activities")?,
+        context: read_context(required(object, "context")?, "context")?,
+    })
+}
+
+fn read_document(string: &str) -> Result<Value> {
+    check_document(serde_json::from_str(string).map_err(|e| parse_error(&e.to_string()))?)
+}
+
+///
+/// Check that `document` is a native document of a version that can be read.
+///
+fn check_document(document: Value) -> Result<Value> {
+    let object = as_object(&document, "document")?;
+    if object.get("format").and_then(Value::as_str) != Some(NATIVE_FORMAT) {
+        return Err(parse_error("not a uml_state_machine document"));
+    }
+    match object.get("version").and_then(Value::as_u64) {
+        Some(version) if version > NATIVE_VERSION => {
+            return Err(unsupported_error(&format!("document version {}", version)))
+        }
+        Some(_) => {}
+        None => return Err(parse_error("document has no version")),
+    }
+    Ok(document)
+}
+
+#[cfg(feature = "execution")]
+fn trace_entry_value(entry: &TraceEntry) -> Result<Value> {
+    let mut object = Map::new();
+    let _ = object.insert("time".to_string(), system_time_value(&entry.time)?);
+    if let Some(event) = &entry.event {
+        let _ = object.insert("event".to_string(), event_value(event)?);
+    }
+    insert_values(
+        &mut object,
+        "fired",
+        entry
+            .fired
+            .iter()
+            .map(|fired| {
+                let mut object = Map::new();
+                let _ = object.insert("source".to_string(), json!(fired.source.to_string()));
+                let _ = object.insert("target".to_string(), json!(fired.target.to_string()));
+                insert_option(&mut object, "label", &fired.label);
+                Value::Object(object)
+            })
+            .collect(),
+    );
+    insert_ids(&mut object, "before", &entry.before);
+    insert_ids(&mut object, "after", &entry.after);
+    let _ = object.insert(
+        "context_hash".to_string(),
+        json!(format!("{:016x}", entry.context_hash)),
+    );
+    Ok(Value::Object(object))
+}
+
+fn region_value(region: &Region) -> Result<Value> {
+    let mut object = Map::new();
+    let _ = object.insert("id".to_string(), json!(region.id.to_string()));
+    insert_option(&mut object, "label", &region.label);
+    let _ = object.insert("container".to_string(), json!(region.container.to_string()));
+    let _ = object.insert(
+        "container_type".to_string(),
+        json!(match region.container_type {
+            RegionContainerType::State => "state",
+            RegionContainerType::StateMachine => "state_machine",
+        }),
+    );
+    insert_id(&mut object, "extends", &region.extended);
+    insert_metadata(&mut object, &region.metadata);
+    insert_values(
+        &mut object,
+        "vertices",
+        region
+            .vertices()
+            .iter()
+            .map(|vertex| vertex_value(vertex))
+            .collect::<Result<Vec<Value>>>()?,
+    );
+    insert_values(
+        &mut object,
+        "transitions",
+        region
+            .transitions()
+            .iter()
+            .map(|transition| transition_value(transition))
+            .collect::<Result<Vec<Value>>>()?,
+    );
+    Ok(Value::Object(object))
+}
+
+fn vertex_value(vertex: &Vertex) -> Result<Value> {
+    match vertex {
+        Vertex::State(state) => state_value(state),
+        Vertex::PseudoState(pseudo_state) => Ok(pseudo_state_value(pseudo_state)),
+        Vertex::ConnectionPointReference(cpr) => {
+            let mut object = element_map("connection_point_reference", &cpr.id, &cpr.label);
+            let _ = object.insert("container".to_string(), json!(cpr.container.to_string()));
+            insert_ids(&mut object, "entry", &cpr.entry);
+            insert_ids(&mut object, "exit", &cpr.exit);
+            if let Some(state) = &cpr.state {
+                let _ = object.insert("state".to_string(), json!(state.to_string()));
+            }
+            insert_id(&mut object, "redefines", &cpr.redefined);
+            Ok(Value::Object(object))
+        }
+    }
+}
+
+fn state_value(state: &State) -> Result<Value> {
+    let mut object = element_map("state", &state.id, &state.label);
+    let _ = object.insert("container".to_string(), json!(state.container.to_string()));
+    insert_values(
+        &mut object,
+        "comments",
+        state
+            .comments
+            .iter()
+            .map(|comment| json!(comment))
+            .collect(),
+    );
+    insert_values(
+        &mut object,
+        "regions",
+        state
+            .regions
+            .iter()
+            .map(region_value)
+            .collect::<Result<Vec<Value>>>()?,
+    );
+    if let Some(sub_machine) = &state.sub_machine {
+        let _ = object.insert("sub_machine".to_string(), json!(sub_machine.to_string()));
+    }
+    insert_ids(&mut object, "connections", &state.connections);
+    insert_ids(&mut object, "connection_points", &state.connection_points);
+    insert_values(
+        &mut object,
+        "deferrable_triggers",
+        state
+            .deferrable_triggers
+            .iter()
+            .map(trigger_value)
+            .collect::<Result<Vec<Value>>>()?,
+    );
+    if let Some(invariant) = &state.invariant {
+        let _ = object.insert("invariant".to_string(), opaque_value(invariant.label()));
+    }
+    if let Some(entry) = &state.entry {
+        let _ = object.insert("entry".to_string(), opaque_value(entry.label()));
+    }
+    if let Some(do_activity) = &state.do_activity {
+        let _ = object.insert("do_activity".to_string(), opaque_value(do_activity.label()));
+    }
+    if let Some(exit) = &state.exit {
+        let _ = object.insert("exit".to_string(), opaque_value(exit.label()));
+    }
+    insert_values(
+        &mut object,
+        "invokes",
+        state.invokes.iter().map(invoke_value).collect(),
+    );
+    if state.final_state {
+        let _ = object.insert("final".to_string(), json!(true));
+    }
+    insert_id(&mut object, "redefines", &state.redefined);
+    insert_metadata(&mut object, &state.metadata);
+    Ok(Value::Object(object))
+}
+
+fn invoke_value(invoke: &Invoke) -> Value {
+    let mut object = Map::new();
+    let _ = object.insert("id".to_string(), json!(invoke.id.to_string()));
+    insert_option(&mut object, "label", &invoke.label);
+    let _ = object.insert("kind".to_string(), json!(invoke.kind));
+    insert_option(&mut object, "source", &invoke.source);
+    insert_values(
+        &mut object,
+        "params",
+        invoke
+            .params
+            .iter()
+            .map(|(name, path)| json!({"name": name, "path": path.to_string()}))
+            .collect(),
+    );
+    Value::Object(object)
+}
+
+fn pseudo_state_value(pseudo_state: &PseudoState) -> Value {
+    let mut object = element_map("pseudo_state", &pseudo_state.id, &pseudo_state.label);
+    let _ = object.insert(
+        "container".to_string(),
+        json!(pseudo_state.container.to_string()),
+    );
+    let _ = object.insert(
+        "kind".to_string(),
+        json!(match pseudo_state.kind {
+            PseudoStateKind::Initial => "initial",
+            PseudoStateKind::DeepHistory => "deep_history",
+            PseudoStateKind::ShallowHistory => "shallow_history",
+            PseudoStateKind::Join => "join",
+            PseudoStateKind::Fork => "fork",
+            PseudoStateKind::Junction => "junction",
+            PseudoStateKind::Choice => "choice",
+            PseudoStateKind::EntryPoint => "entry_point",
+            PseudoStateKind::ExitPoint => "exit_point",
+            PseudoStateKind::Terminate => "terminate",
+        }),
+    );
+    insert_id(&mut object, "redefines", &pseudo_state.redefined);
+    Value::Object(object)
+}
+
+fn transition_value(transition: &Transition) -> Result<Value> {
+    let mut object = Map::new();
+    insert_option(&mut object, "label", &transition.label);
+    let _ = object.insert(
+        "container".to_string(),
+        json!(transition.container.to_string()),
+    );
+    let _ = object.insert(
+        "kind".to_string(),
+        json!(match transition.kind {
+            TransitionKind::Internal => "internal",
+            TransitionKind::Local => "local",
+            TransitionKind::External => "external",
+        }),
+    );
+    let _ = object.insert("source".to_string(), json!(transition.source.to_string()));
+    let _ = object.insert("target".to_string(), json!(transition.target.to_string()));
+    insert_values(
+        &mut object,
+        "triggers",
+        transition
+            .triggers
+            .iter()
+            .map(trigger_value)
+            .collect::<Result<Vec<Value>>>()?,
+    );
+    if let Some(guard) = &transition.guard {
+        let _ = object.insert("guard".to_string(), opaque_value(guard.label()));
+    }
+    if let Some(effect) = &transition.effect {
+        let _ = object.insert("effect".to_string(), opaque_value(effect.label()));
+    }
+    if let Some((region, index)) = &transition.redefined {
+        let _ = object.insert(
+            "redefines".to_string(),
+            json!({"region": region.to_string(), "index": index}),
+        );
+    }
+    insert_metadata(&mut object, &transition.metadata);
+    Ok(Value::Object(object))
+}
+
+fn trigger_value(trigger: &Trigger) -> Result<Value> {
+    let mut object = Map::new();
+    let _ = object.insert("event".to_string(), event_value(&trigger.event)?);
+    insert_values(
+        &mut object,
+        "payload",
+        trigger
+            .predicates
+            .iter()
+            .map(|predicate| {
+                json!({
+                    "path": predicate.path.to_string(),
+                    "operator": predicate.operator.to_string(),
+                    "value": field_value(&predicate.value),
+                })
+            })
+            .collect(),
+    );
+    Ok(Value::Object(object))
+}
+
+fn event_value(event: &Event) -> Result<Value> {
+    Ok(match event {
+        Event::Signal(event) if event.has_payload_schema() => json!({
+            "type": "signal",
+            "signal": event.signal,
+            "payload": event
+                .payload_schema()
+                .map(|(path, kind)| json!({"path": path.to_string(), "kind": kind.to_string()}))
+                .collect::<Vec<Value>>(),
+        }),
+        Event::Signal(event) => json!({"type": "signal", "signal": event.signal}),
+        Event::Call(event) => json!({"type": "call", "operation": event.operation}),
+        Event::Time(TimeEvent::Relative(duration)) => time_value("after", duration),
+        Event::Time(TimeEvent::Absolute(time)) => {
+            let since = time
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| unsupported_error("times before the Unix epoch"))?;
+            time_value("at", &since)
+        }
+        Event::Change(event) => json!({
+            "type": "change",
+            "expression": opaque_value(event.change_expression.label()),
+        }),
+        Event::AnyReceive => json!({"type": "any_receive"}),
+    })
+}
+
+#[cfg(feature = "execution")]
+fn occurrence_value(occurrence: &Occurrence) -> Result<Value> {
+    let mut object = Map::new();
+    let _ = object.insert("event".to_string(), event_value(&occurrence.event)?);
+    if let Some(target) = &occurrence.target {
+        let _ = object.insert("target".to_string(), json!(target.to_string()));
+    }
+    if let Some(payload) = &occurrence.payload {
+        let _ = object.insert("payload".to_string(), field_value(payload.root()));
+    }
+    Ok(Value::Object(object))
+}
+
+fn time_value(kind: &str, duration: &Duration) -> Value {
+    json!({
+        "type": kind,
+        "seconds": duration.as_secs(),
+        "nanos": duration.subsec_nanos(),
+    })
+}
+
+#[cfg(feature = "execution")]
+fn system_time_value(time: &SystemTime) -> Result<Value> {
+    let since = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| unsupported_error("times before the Unix epoch"))?;
+    Ok(json!({ "seconds": since.as_secs(), "nanos": since.subsec_nanos() }))
+}
+
+fn field_value(value: &FieldValue) -> Value {
+    match value {
+        FieldValue::Bool(v) => json!({ "bool": v }),
+        FieldValue::Byte(v) => json!({ "byte": v }),
+        FieldValue::Integer(v) => json!({ "integer": v }),
+        FieldValue::Float(v) => json!({ "float": v }),
+        FieldValue::String(v) => json!({ "string": v }),
+        FieldValue::Array(v) => {
+            let v = v.read().unwrap();
+            let values: Vec<Value> = (0..v.len())
+                .filter_map(|index| v.get(index))
+                .map(|value| field_value(&value))
+                .collect();
+            json!({ "array": values })
+        }
+        FieldValue::Object(v) => {
+            let v = v.read().unwrap();
+            let mut names = v.names();
+            names.sort_by_key(|name| name.to_string());
+            let mut members = Map::new();
+            for name in names {
+                if let Some(value) = v.get(name.clone()) {
+                    let _ = members.insert(name.to_string(), field_value(&value));
+                }
+            }
+            json!({ "object": members })
+        }
+    }
+}
+
+fn opaque_value(label: &Option<String>) -> Value {
+    let mut object = Map::new();
+    insert_option(&mut object, "label", label);
+    Value::Object(object)
+}
+
+fn element_map(kind: &str, id: &ID, label: &Option<String>) -> Map<String, Value> {
+    let mut object = Map::new();
+    let _ = object.insert("type".to_string(), json!(kind));
+    let _ = object.insert("id".to_string(), json!(id.to_string()));
+    insert_option(&mut object, "label", label);
+    object
+}
+
+fn insert_option(object: &mut Map<String, Value>, name: &str, value: &Option<String>) {
+    if let Some(value) = value {
+        let _ = object.insert(name.to_string(), json!(value));
+    }
+}
+
+fn insert_id(object: &mut Map<String, Value>, name: &str, value: &Option<ID>) {
+    if let Some(value) = value {
+        let _ = object.insert(name.to_string(), json!(value.to_string()));
+    }
+}
+
+fn insert_metadata(object: &mut Map<String, Value>, metadata: &HashMap<String, FieldValue>) {
+    if !metadata.is_empty() {
+        // sorted, so that the same machine is always written the same way
+        let mut keys: Vec<&String> = metadata.keys().collect();
+        keys.sort();
+        let _ = object.insert(
+            "metadata".to_string(),
+            Value::Object(
+                keys.into_iter()
+                    .map(|key| (key.clone(), field_value(&metadata[key])))
+                    .collect(),
+            ),
+        );
+    }
+}
+
+fn insert_ids(object: &mut Map<String, Value>, name: &str, ids: &[ID]) {
+    insert_values(
+        object,
+        name,
+        ids.iter().map(|id| json!(id.to_string())).collect(),
+    );
+}
+
+fn insert_values(object: &mut Map<String, Value>, name: &str, values: Vec<Value>) {
+    if !values.is_empty() {
+        let _ = object.insert(name.to_string(), Value::Array(values));
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "execution")]
+fn read_trace_entry(value: &Value) -> Result<TraceEntry> {
+    let object = as_object(value, "trace entry")?;
+    let context_hash = string(object, "context_hash")?;
+    Ok(TraceEntry {
+        time: read_system_time(required(object, "time")?)?,
+        event: object.get("event").map(read_event).transpose()?,
+        fired: array(object, "fired")?
+            .iter()
+            .map(|value| {
+                let object = as_object(value, "fired transition")?;
+                Ok(FiredTransition {
+                    source: id(required(object, "source")?)?,
+                    target: id(required(object, "target")?)?,
+                    label: optional_string(object, "label")?,
+                })
+            })
+            .collect::<Result<Vec<FiredTransition>>>()?,
+        before: ids(object, "before")?,
+        after: ids(object, "after")?,
+        context_hash: u64::from_str_radix(context_hash, 16)
+            .map_err(|_| parse_error(&format!("invalid context hash '{}'", context_hash)))?,
+    })
+}
+
+fn read_region(value: &Value) -> Result<Region> {
+    let object = as_object(value, "region")?;
+    let container_type = match string(object, "container_type")? {
+        "state" => RegionContainerType::State,
+        "state_machine" => RegionContainerType::StateMachine,
+        other => {
+            return Err(parse_error(&format!(
+                "unknown region container '{}'",
+                other
+            )))
+        }
+    };
+    Ok(Region {
+        id: id(required(object, "id")?)?,
+        label: optional_string(object, "label")?,
+        container: id(required(object, "container")?)?,
+        container_type,
+        extended: optional_id(object, "extends")?,
+        metadata: read_metadata(object)?,
+        vertices: Arc::new(RwLock::new(
+            array(object, "vertices")?
+                .iter()
+                .map(|vertex| read_vertex(vertex).map(Arc::new))
+                .collect::<Result<Vec<Arc<Vertex>>>>()?,
+        )),
+        transitions: Arc::new(RwLock::new(
+            array(object, "transitions")?
+                .iter()
+                .map(|transition| read_transition(transition).map(Arc::new))
+                .collect::<Result<Vec<Arc<Transition>>>>()?,
+        )),
+    })
+}
+
+fn read_vertex(value: &Value) -> Result<Vertex> {
+    let object = as_object(value, "vertex")?;
+    match string(object, "type")? {
+        "state" => Ok(Vertex::State(State {
+            id: id(required(object, "id")?)?,
+            label: optional_string(object, "label")?,
+            comments: array(object, "comments")?
+                .iter()
+                .map(|comment| {
+                    comment
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| parse_error("a comment is not a string"))
+                })
+                .collect::<Result<Vec<String>>>()?,
+            container: id(required(object, "container")?)?,
+            regions: array(object, "regions")?
+                .iter()
+                .map(read_region)
+                .collect::<Result<Vec<Region>>>()?,
+            sub_machine: optional_id(object, "sub_machine")?,
+            connections: ids(object, "connections")?,
+            connection_points: ids(object, "connection_points")?,
+            deferrable_triggers: array(object, "deferrable_triggers")?
+                .iter()
+                .map(read_trigger)
+                .collect::<Result<Vec<Trigger>>>()?,
+            invariant: optional_constraint(object, "invariant")?,
+            entry: optional_behavior(object, "entry")?,
+            do_activity: optional_behavior(object, "do_activity")?,
+            exit: optional_behavior(object, "exit")?,
+            invokes: array(object, "invokes")?
+                .iter()
+                .map(read_invoke)
+                .collect::<Result<Vec<Invoke>>>()?,
+            final_state: match object.get("final") {
+                None => false,
+                Some(value) => value
+                    .as_bool()
+                    .ok_or_else(|| parse_error("'final' is not a boolean"))?,
+            },
+            redefined: optional_id(object, "redefines")?,
+            metadata: read_metadata(object)?,
+        })),
+        "pseudo_state" => Ok(Vertex::PseudoState(read_pseudo_state(value)?)),
+        "connection_point_reference" => {
+            Ok(Vertex::ConnectionPointReference(ConnectionPointReference {
+                id: id(required(object, "id")?)?,
+                label: optional_string(object, "label")?,
+                container: id(required(object, "container")?)?,
+                entry: ids(object, "entry")?,
+                exit: ids(object, "exit")?,
+                state: optional_id(object, "state")?,
+                redefined: optional_id(object, "redefines")?,
+            }))
+        }
+        other => Err(parse_error(&format!("unknown vertex type '{}'", other))),
+    }
+}
+
+fn read_invoke(value: &Value) -> Result<Invoke> {
+    let object = as_object(value, "invoke")?;
+    Ok(Invoke {
+        id: id(required(object, "id")?)?,
+        label: optional_string(object, "label")?,
+        kind: string(object, "kind")?.to_string(),
+        source: optional_string(object, "source")?,
+        params: array(object, "params")?
+            .iter()
+            .map(|param| {
+                let param = as_object(param, "invoke parameter")?;
+                let path = string(param, "path")?;
+                Ok((
+                    string(param, "name")?.to_string(),
+                    FieldPath::from_str(path)
+                        .map_err(|_| parse_error(&format!("invalid parameter path '{}'", path)))?,
+                ))
+            })
+            .collect::<Result<Vec<(String, FieldPath)>>>()?,
+    })
+}
+
+fn read_pseudo_state(value: &Value) -> Result<PseudoState> {
+    let object = as_object(value, "pseudo state")?;
+    let kind = match string(object, "kind")? {
+        "initial" => PseudoStateKind::Initial,
+        "deep_history" => PseudoStateKind::DeepHistory,
+        "shallow_history" => PseudoStateKind::ShallowHistory,
+        "join" => PseudoStateKind::Join,
+        "fork" => PseudoStateKind::Fork,
+        "junction" => PseudoStateKind::Junction,
+        "choice" => PseudoStateKind::Choice,
+        "entry_point" => PseudoStateKind::EntryPoint,
+        "exit_point" => PseudoStateKind::ExitPoint,
+        "terminate" => PseudoStateKind::Terminate,
+        other => {
+            return Err(parse_error(&format!(
+                "unknown pseudo state kind '{}'",
+                other
+            )))
+        }
+    };
+    Ok(PseudoState {
+        id: id(required(object, "id")?)?,
+        label: optional_string(object, "label")?,
+        container: id(required(object, "container")?)?,
+        kind,
+        redefined: optional_id(object, "redefines")?,
+    })
+}
+
+fn read_transition(value: &Value) -> Result<Transition> {
+    let object = as_object(value, "transition")?;
+    let kind = match string(object, "kind")? {
+        "internal" => TransitionKind::Internal,
+        "local" => TransitionKind::Local,
+        "external" => TransitionKind::External,
+        other => return Err(parse_error(&format!("unknown transition kind '{}'", other))),
+    };
+    Ok(Transition {
+        label: optional_string(object, "label")?,
+        container: id(required(object, "container")?)?,
+        kind,
+        source: id(required(object, "source")?)?,
+        target: id(required(object, "target")?)?,
+        triggers: array(object, "triggers")?
+            .iter()
+            .map(read_trigger)
+            .collect::<Result<Vec<Trigger>>>()?,
+        guard: optional_constraint(object, "guard")?.map(recognize_else),
+        effect: optional_behavior(object, "effect")?,
+        redefined: object
+            .get("redefines")
+            .map(read_redefined_transition)
+            .transpose()?,
+        metadata: read_metadata(object)?,
+    })
+}
+
+fn read_metadata(object: &Map<String, Value>) -> Result<HashMap<String, FieldValue>> {
+    match object.get("metadata") {
+        None => Ok(Default::default()),
+        Some(metadata) => as_object(metadata, "metadata")?
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), read_field_value(value)?)))
+            .collect(),
+    }
+}
+
+fn read_redefined_transition(value: &Value) -> Result<(ID, usize)> {
+    let object = as_object(value, "redefined transition")?;
+    Ok((
+        id(required(object, "region")?)?,
+        unsigned(required(object, "index")?, "index")? as usize,
+    ))
+}
+
+fn read_trigger(value: &Value) -> Result<Trigger> {
+    let object = as_object(value, "trigger")?;
+    Ok(Trigger {
+        event: read_event(required(object, "event")?)?,
+        predicates: array(object, "payload")?
+            .iter()
+            .map(read_payload_predicate)
+            .collect::<Result<Vec<PayloadPredicate>>>()?,
+        payload: None,
+    })
+}
+
+///
+/// Read a queued or deferred event; deferred events were once written as bare event values, and
+/// are still read as such.
+///
+#[cfg(feature = "execution")]
+fn read_occurrence(value: &Value) -> Result<Occurrence> {
+    let object = as_object(value, "queued event")?;
+    match object.get("event") {
+        None => Ok(Occurrence {
+            event: read_event(value)?,
+            target: None,
+            payload: None,
+        }),
+        Some(event) => Ok(Occurrence {
+            event: read_event(event)?,
+            target: optional_id(object, "target")?,
+            payload: match object.get("payload") {
+                None => None,
+                Some(payload) => Some(read_context(payload, "payload")?),
+            },
+        }),
+    }
+}
+
+#[cfg(feature = "execution")]
+fn read_context(value: &Value, name: &str) -> Result<Context> {
+    match read_field_value(value)? {
+        FieldValue::Object(object) => Ok(Context::from(object.read().unwrap().clone())),
+        _ => Err(parse_error(&format!("'{}' is not an object", name))),
+    }
+}
+
+fn read_event(value: &Value) -> Result<Event> {
+    let event = as_object(value, "event")?;
+    Ok(match string(event, "type")? {
+        "signal" => Event::Signal(read_signal_event(event)?),
+        "call" => Event::Call(CallEvent::new(string(event, "operation")?)),
+        "after" => Event::Time(TimeEvent::Relative(read_duration(event)?)),
+        "at" => Event::Time(TimeEvent::Absolute(
+            SystemTime::UNIX_EPOCH + read_duration(event)?,
+        )),
+        "change" => Event::Change(ChangeEvent::new(Arc::new(read_opaque(
+            required(event, "expression")?,
+            OpaqueConstraint::new,
+        )?))),
+        "any_receive" => Event::AnyReceive,
+        other => return Err(parse_error(&format!("unknown event type '{}'", other))),
+    })
+}
+
+#[cfg(feature = "execution")]
+fn read_system_time(value: &Value) -> Result<SystemTime> {
+    Ok(UNIX_EPOCH + read_duration(as_object(value, "time")?)?)
+}
+
+fn read_duration(event: &Map<String, Value>) -> Result<Duration> {
+    let seconds = required(event, "seconds")?
+        .as_u64()
+        .ok_or_else(|| parse_error("'seconds' is not an unsigned integer"))?;
+    let nanos = match event.get("nanos") {
+        None => 0,
+        Some(nanos) => nanos
+            .as_u64()
+            .filter(|nanos| *nanos < 1_000_000_000)
+            .ok_or_else(|| parse_error("'nanos' is not a valid number of nanoseconds"))?,
+    };
+    Ok(Duration::new(seconds, nanos as u32))
+}
+
+fn read_signal_event(event: &Map<String, Value>) -> Result<SignalEvent> {
+    let mut signal = SignalEvent::new(string(event, "signal")?);
+    for field in array(event, "payload")? {
+        let field = as_object(field, "payload field")?;
+        let path = string(field, "path")?;
+        let kind = string(field, "kind")?;
+        signal = signal.with_field(
+            FieldPath::from_str(path)
+                .map_err(|_| parse_error(&format!("invalid payload path '{}'", path)))?,
+            FieldKind::from_str(kind)
+                .map_err(|_| parse_error(&format!("unknown field kind '{}'", kind)))?,
+        );
+    }
+    Ok(signal)
+}
+
+fn read_payload_predicate(value: &Value) -> Result<PayloadPredicate> {
+    let object = as_object(value, "payload predicate")?;
+    let path = string(object, "path")?;
+    let path = FieldPath::from_str(path)
+        .map_err(|_| parse_error(&format!("invalid payload path '{}'", path)))?;
+    let operator = match string(object, "operator")? {
+        "==" => ComparisonOperator::Equal,
+        "!=" => ComparisonOperator::NotEqual,
+        "<" => ComparisonOperator::Less,
+        "<=" => ComparisonOperator::LessOrEqual,
+        ">" => ComparisonOperator::Greater,
+        ">=" => ComparisonOperator::GreaterOrEqual,
+        other => return Err(parse_error(&format!("unknown operator '{}'", other))),
+    };
+    Ok(PayloadPredicate::new(
+        path,
+        operator,
+        read_field_value(required(object, "value")?)?,
+    ))
+}
+
+fn read_field_value(value: &Value) -> Result<FieldValue> {
+    let object = as_object(value, "field value")?;
+    let (kind, value) = match object.iter().next() {
+        Some(member) if object.len() == 1 => member,
+        _ => return Err(parse_error("a field value must have exactly one member")),
+    };
+    let invalid = || parse_error(&format!("invalid {} field value", kind));
+    match kind.as_str() {
+        "bool" => value.as_bool().map(FieldValue::Bool).ok_or_else(invalid),
+        "byte" => value
+            .as_u64()
+            .filter(|v| *v <= u8::MAX as u64)
+            .map(|v| FieldValue::Byte(v as u8))
+            .ok_or_else(invalid),
+        "integer" => value.as_i64().map(FieldValue::Integer).ok_or_else(invalid),
+        "float" => value.as_f64().map(FieldValue::Float).ok_or_else(invalid),
+        "string" => value
+            .as_str()
+            .map(|v| FieldValue::String(v.to_string()))
+            .ok_or_else(invalid),
+        "array" => {
+            let values = value
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(read_field_value)
+                .collect::<Result<Vec<FieldValue>>>()?;
+            Ok(Array::from(values).into())
+        }
+        "object" => {
+            let mut members: HashMap<FieldName, FieldValue> = Default::default();
+            for (name, value) in value.as_object().ok_or_else(invalid)? {
+                let name = FieldName::from_str(name)
+                    .map_err(|_| parse_error(&format!("invalid field name '{}'", name)))?;
+                let _ = members.insert(name, read_field_value(value)?);
+            }
+            Ok(Object::from(members).into())
+        }
+        other => Err(parse_error(&format!(
+            "unknown field value type '{}'",
+            other
+        ))),
+    }
+}
+
+fn optional_constraint(
+    object: &Map<String, Value>,
+    name: &str,
+) -> Result<Option<Box<dyn Constraint>>> {
+    match object.get(name) {
+        None => Ok(None),
+        Some(value) => Ok(Some(Box::new(read_opaque(value, OpaqueConstraint::new)?))),
+    }
+}
+
+fn optional_behavior(object: &Map<String, Value>, name: &str) -> Result<Option<Box<dyn Behavior>>> {
+    match object.get(name) {
+        None => Ok(None),
+        Some(value) => Ok(Some(Box::new(read_opaque(value, OpaqueBehavior::new)?))),
+    }
+}
+
+fn read_opaque<T>(value: &Value, new: fn(&str, Option<&str>) -> T) -> Result<T>
+where
+    T: Labeled,
+{
+    let object = as_object(value, "constraint or behavior")?;
+    match optional_string(object, "label")? {
+        Some(label) => Ok(new(&label, None)),
+        None => {
+            let mut opaque = new("", None);
+            opaque.unset_label();
+            Ok(opaque)
+        }
+    }
+}
+
+fn id(value: &Value) -> Result<ID> {
+    let string = value
+        .as_str()
+        .ok_or_else(|| parse_error("an ID is not a string"))?;
+    ID::from_str(string).map_err(|_| parse_error(&format!("invalid ID '{}'", string)))
+}
+
+fn optional_id(object: &Map<String, Value>, name: &str) -> Result<Option<ID>> {
+    object.get(name).map(id).transpose()
+}
+
+fn ids(object: &Map<String, Value>, name: &str) -> Result<Vec<ID>> {
+    array(object, name)?.iter().map(id).collect()
+}
+
+fn string<'a>(object: &'a Map<String, Value>, name: &str) -> Result<&'a str> {
+    required(object, name)?
+        .as_str()
+        .ok_or_else(|| parse_error(&format!("'{}' is not a string", name)))
+}
+
+fn optional_string(object: &Map<String, Value>, name: &str) -> Result<Option<String>> {
+    match object.get(name) {
+        None => Ok(None),
+        Some(_) => Ok(Some(string(object, name)?.to_string())),
+    }
+}
+
+fn unsigned(value: &Value, name: &str) -> Result<u64> {
+    value
+        .as_u64()
+        .ok_or_else(|| parse_error(&format!("'{}' is not an unsigned integer", name)))
+}
+
+fn array<'a>(object: &'a Map<String, Value>, name: &str) -> Result<&'a [Value]> {
+    match object.get(name) {
+        None => Ok(&[]),
+        Some(value) => value
+            .as_array()
+            .map(Vec::as_slice)
+            .ok_or_else(|| parse_error(&format!("'{}' is not an array", name))),
+    }
+}
+
+fn required<'a>(object: &'a Map<String, Value>, name: &str) -> Result<&'a Value> {
+    object
+        .get(name)
+        .ok_or_else(|| parse_error(&format!("missing '{}'", name)))
+}
+
+fn as_object<'a>(value: &'a Value, what: &str) -> Result<&'a Map<String, Value>> {
+    value
+        .as_object()
+        .ok_or_else(|| parse_error(&format!("{} is not an object", what)))
+}
+
+fn parse_error(message: &str) -> Error {
+    ErrorKind::FormatParse(message.to_string()).into()
+}
+
+fn unsupported_error(feature: &str) -> Error {
+    ErrorKind::FormatUnsupported(feature.to_string()).into()
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::{Contained, HasMetadata, HasRegions, Identified};
+    use crate::format::Stringify;
+
+    fn order_machine() -> StateMachine {
+        let machine = StateMachine::labeled("order");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut state = State::within(region.id().clone());
+        state.set_label("paying");
+        state.add_comment("Waits for payment");
+        state.set_entry(Box::new(OpaqueBehavior::new("charge()", None)));
+        state.add_deferrable_trigger(Trigger::call("cancel"));
+        state.set_metadata("sla_hours", FieldValue::Integer(24));
+        state.set_metadata("owner", FieldValue::String("payments".to_string()));
+        let mut invoke = Invoke::new("http");
+        invoke.set_source("https://payments.example.com/charge");
+        invoke.add_param("amount", FieldPath::from_str("order/total").unwrap());
+        state.add_invoke(invoke.clone());
+        let _ = state.new_region();
+        let inner = state.default_region().unwrap();
+        let entry = inner.new_entry_point();
+        let state_id = state.id().clone();
+        region.add_state(state);
+        let mut cpr = ConnectionPointReference::within(region.id().clone());
+        cpr.entry.push(entry);
+        cpr.state = Some(state_id.clone());
+        let cpr_id = cpr.id.clone();
+        region.add_connection_point_ref(cpr);
+        let done = region.new_final_state();
+
+        let mut transition = Transition::within(initial, cpr_id, region.id().clone());
+        transition.set_kind(TransitionKind::External);
+        region.add_transition(transition);
+        let mut transition = Transition::within(state_id, done, region.id().clone());
+        transition.set_kind(TransitionKind::Local);
+        let mut trigger = Trigger::with_event(Event::Signal(SignalEvent::new("paid").with_field(
+            FieldPath::from_str("order/total").unwrap(),
+            FieldKind::Float,
+        )));
+        trigger.add_payload_predicate(PayloadPredicate::new(
+            FieldPath::from_str("order/total").unwrap(),
+            ComparisonOperator::GreaterOrEqual,
+            FieldValue::Float(100.5),
+        ));
+        transition.add_trigger(trigger);
+        transition.add_trigger(Trigger::after(Duration::from_millis(1500)));
+        transition.add_trigger(Trigger::at(UNIX_EPOCH + Duration::from_secs(86_400)));
+        transition.set_guard(Box::new(OpaqueConstraint::new("ready", None)));
+        transition.set_metadata("audited", FieldValue::Bool(true));
+        region.add_transition(transition);
+        machine
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let machine = order_machine();
+        let invoke = machine.default_region().unwrap().vertices()[1]
+            .as_state()
+            .unwrap()
+            .invokes()
+            .next()
+            .unwrap()
+            .clone();
+        let written = WriteNative::default().stringify(&machine).unwrap();
+        let read = ReadNative::default().parse(&written).unwrap();
+        assert_eq!(WriteNative::default().stringify(&read).unwrap(), written);
+
+        let region = read.default_region().unwrap();
+        assert_eq!(region.container(), machine.id());
+        let transitions = region.transitions();
+        assert_eq!(transitions[1].kind(), TransitionKind::Local);
+        assert_eq!(transitions[1].triggers().len(), 3);
+        assert_eq!(
+            transitions[1].metadata_value("audited"),
+            Some(&FieldValue::Bool(true))
+        );
+        let paid = transitions[1].triggers().next().unwrap().event();
+        assert_eq!(
+            paid.as_signal().unwrap().payload_schema().next(),
+            Some(&(
+                FieldPath::from_str("order/total").unwrap(),
+                FieldKind::Float
+            ))
+        );
+        assert_eq!(
+            transitions[1].triggers().nth(1).unwrap().event(),
+            &Event::Time(TimeEvent::Relative(Duration::from_millis(1500)))
+        );
+        let vertices = region.vertices();
+        assert_eq!(
+            vertices[1].as_state().unwrap().metadata_value("sla_hours"),
+            Some(&FieldValue::Integer(24))
+        );
+        let cpr = vertices[2].as_connection_point_reference().unwrap();
+        assert_eq!(cpr.entry().count(), 1);
+        assert_eq!(cpr.state(), &Some(vertices[1].id().clone()));
+        assert_eq!(
+            vertices[1]
+                .as_state()
+                .unwrap()
+                .invokes()
+                .collect::<Vec<&Invoke>>(),
+            vec![&invoke]
+        );
+
+        let extension = machine.extend().unwrap();
+        let written = WriteNative::default().stringify(&extension).unwrap();
+        let read = ReadNative::default().parse(&written).unwrap();
+        assert_eq!(read.extended_machine(), &Some(machine.id().clone()));
+        let base_region = machine.default_region().unwrap();
+        let region = read.default_region().unwrap();
+        assert_eq!(region.extended_region(), &Some(base_region.id().clone()));
+        assert_eq!(
+            region.vertices()[1].redefined_vertex(),
+            &Some(base_region.vertices()[1].id().clone())
+        );
+        assert_eq!(
+            region.transitions()[1].redefined_transition(),
+            &Some((base_region.id().clone(), 1))
+        );
+        assert!(read.validate_extension(&machine).is_ok());
+    }
+
+    #[cfg(feature = "format-native-yaml")]
+    #[test]
+    fn test_yaml_round_trip() {
+        let machine = order_machine();
+        let yaml = WriteNativeYaml::default().stringify(&machine).unwrap();
+        assert!(yaml.starts_with("format: uml_state_machine\nversion: 1\nmachine:\n"));
+        let read = ReadNativeYaml::default().parse(&yaml).unwrap();
+        assert_eq!(WriteNativeYaml::default().stringify(&read).unwrap(), yaml);
+        assert_eq!(
+            WriteNative::default().stringify(&read).unwrap(),
+            WriteNative::default().stringify(&machine).unwrap()
+        );
+
+        // the JSON document is also YAML
+        let json = WriteNative::default().stringify(&machine).unwrap();
+        let read = ReadNativeYaml::default().parse(&json).unwrap();
+        assert_eq!(WriteNative::default().stringify(&read).unwrap(), json);
+        assert!(ReadNativeYaml::default()
+            .parse("format: other\nversion: 1\nmachine: {}\n")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let reader = ReadNative::default();
+        assert!(reader.parse("[]").is_err());
+        assert!(reader
+            .parse(r#"{"format": "other", "version": 1, "machine": {}}"#)
+            .is_err());
+        match reader.parse(r#"{"format": "uml_state_machine", "version": 2, "machine": {}}"#) {
+            Err(Error(ErrorKind::FormatUnsupported(_), _)) => {}
+            result => panic!("expected an unsupported version, not {:?}", result.is_ok()),
+        }
+        assert!(reader
+            .parse(
+                r#"{"format": "uml_state_machine", "version": 1, "machine": {
+                    "id": "1",
+                    "regions": [{"id": "2", "container": "1", "container_type": "machine"}]
+                }}"#
+            )
+            .is_err());
+    }
+}
@@ -1,6 +1,29 @@
 /*!
-Writes a state machine out in the [PlantUML](https://plantuml.com/) format. Does not support
-parsing PlantUML.
+Reads and writes a state machine in the [PlantUML](https://plantuml.com/) state diagram format.
+
+The reader accepts the subset of the state diagram syntax that describes a machine.
+
+* `state Name`, `state "Label" as Name`, and `state Name <<stereotype>>` declarations, where the
+  stereotypes `choice`, `fork`, `join`, `entryPoint`, `exitPoint`, `history`, `history*`, `start`,
+  and `end` (read as terminate) create pseudostates. Any state referenced in a transition that has
+  not been declared is created in the enclosing block.
+* Nested `state Name { ... }` blocks, with `--` or `||` separating orthogonal regions.
+* `[*]` as the source of a transition is the region's initial pseudostate, and as the target its
+  final state; `[H]` and `[H*]`, optionally qualified as `Name[H]`, are history pseudostates.
+* Transition labels of the form `trigger, ... [guard] label / effect`, where triggers are written
+  as the model displays them, such as `signal`, `operation()`, `after(5s)`, or `*`. Guards and
+  effects become opaque constraints and behaviors.
+* `Name : entry / behavior`, as well as `do`, `exit`, and `defer trigger` descriptions; any other
+  description, and notes attached to a state, become comments.
+
+Presentation directives such as `skinparam` and `hide` are ignored, any other line is an error.
+The writer emits only the presentation directives selected in its `PlantUmlOptions`.
+
+With the `execution` feature, `trace_to_plantuml` also writes a recorded `ExecutionTrace` as a
+sequence diagram of the instance, rather than of its machine: each event arrives at the instance
+in the order it was dispatched, separated by the time that passed on the instance's clock, and
+is followed by the transitions it fired and, where they changed, the states then active. As it
+writes a trace rather than a machine it is a function, and not an implementation of `Write`.
 
 # Example
 
@@ -15,6 +38,25 @@ pub fn print_machine(machine: &StateMachine) -> Option<String> {
 }
 ```
 
+```rust
+use uml_state_machine::definition::types::{HasRegions, Labeled};
+use uml_state_machine::format::plant_uml::ReadPlantUml;
+use uml_state_machine::format::Parse;
+
+let machine = ReadPlantUml::default()
+    .parse(
+        r#"@startuml
+title Door
+[*] --> Closed
+Closed --> Open : open [unlocked]
+Open --> Closed : close / latch
+@enduml"#,
+    )
+    .unwrap();
+assert_eq!(machine.label(), &Some("Door".to_string()));
+assert_eq!(machine.default_region().unwrap().transitions().len(), 3);
+```
+
 */
 
 // use ...
@@ -24,61 +66,177 @@ pub fn print_machine(machine: &StateMachine) -> Option<String> {
 // ------------------------------------------------------------------------------------------------
 
 use std::cell::RefCell;
-use std::marker::PhantomData;
+use std::collections::HashMap;
 use std::slice::Iter;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
 use crate::core::ID;
-use crate::definition::types::Identified;
+use crate::definition::guards::recognize_else;
+use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
 use crate::definition::types::{
     Behavior, Constraint, PseudoState, PseudoStateKind, StateMachine, TransitionKind, Trigger,
     Vertex,
 };
+use crate::definition::types::{HasRegions, Identified, Labeled, Region, State, Transition};
 use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
-use crate::format::Stringify;
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::{parse_duration, Direction, Parse, Sink, Write};
+
+#[cfg(feature = "execution")]
+use crate::execution::trace::ExecutionTrace;
 use std::borrow::Borrow;
+use std::io;
 
 ///
-/// Implements Stringify for PlantUML.
+/// Implements Write, and so Stringify, for PlantUML.
 ///
+#[derive(Debug, Default)]
 pub struct WritePlantUml {
-    ph: PhantomData<u8>,
+    options: PlantUmlOptions,
 }
 
-struct Visitor {
+///
+/// Presentation options for the diagram written by `WritePlantUml`; the default writes none of
+/// these and leaves presentation to PlantUML.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlantUmlOptions {
+    /// The layout direction, PlantUML supports only `TopToBottom` and `LeftToRight`.
+    pub direction: Option<Direction>,
+    /// Hide the empty description compartment of states without behaviors.
+    pub hide_empty_description: bool,
+    /// Show the ID beneath the label of labeled states, unlabeled states always show their ID.
+    pub show_ids: bool,
+    /// The name of a PlantUML theme, such as `plain`, to apply with `!theme`.
+    pub theme: Option<String>,
+}
+
+///
+/// Implements Parse for PlantUML.
+///
+#[derive(Debug, Default)]
+pub struct ReadPlantUml {}
+
+struct Visitor<'w> {
+    options: &'w PlantUmlOptions,
     container: RefCell<Vec<ID>>,
-    buffer: RefCell<String>,
+    /// History pseudostates are not declared, transitions reference them as `[H]` or `[H*]`
+    /// qualified by the enclosing state.
+    history: RefCell<HashMap<ID, String>>,
+    out: Sink<'w>,
 }
 
 // ------------------------------------------------------------------------------------------------
 // Public Functions
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Write `trace` as a PlantUML sequence diagram, see the module documentation, naming each state
+/// by its ID.
+///
+#[cfg(feature = "execution")]
+pub fn trace_to_plantuml(trace: &ExecutionTrace) -> String {
+    write_trace(trace, |id| id.to_string())
+}
+
+///
+/// Write `trace` as `trace_to_plantuml` does, naming each state of `machine`, the machine of the
+/// instance that recorded it, by its label where it has one, and initial pseudostates `[*]`.
+///
+#[cfg(feature = "execution")]
+pub fn trace_to_plantuml_labeled(trace: &ExecutionTrace, machine: &StateMachine) -> String {
+    let labels: HashMap<ID, String> = machine
+        .find_vertices(|_| true)
+        .into_iter()
+        .filter_map(|vertex| {
+            let label = match (vertex.label(), vertex.as_pseudo_state()) {
+                (_, Some(pseudo_state)) if pseudo_state.kind() == PseudoStateKind::Initial => {
+                    "[*]".to_string()
+                }
+                (Some(label), _) => label.clone(),
+                (None, _) => return None,
+            };
+            Some((vertex.id().clone(), label))
+        })
+        .collect();
+    write_trace(trace, |id| {
+        labels.get(id).cloned().unwrap_or_else(|| id.to_string())
+    })
+}
+
 // ------------------------------------------------------------------------------------------------
 // Implementations
 // ------------------------------------------------------------------------------------------------
 
-impl Default for WritePlantUml {
-    fn default() -> Self {
-        Self { ph: PhantomData }
+impl WritePlantUml {
+    pub fn with_options(options: PlantUmlOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn options(&self) -> &PlantUmlOptions {
+        &self.options
     }
 }
 
-impl Stringify for WritePlantUml {
-    type Error = ();
+impl Write for WritePlantUml {
+    type Error = Error;
 
-    fn stringify(&self, machine: &StateMachine) -> Result<String, Self::Error> {
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let direction = match self.options.direction {
+            None => None,
+            Some(Direction::TopToBottom) => Some("top to bottom direction"),
+            Some(Direction::LeftToRight) => Some("left to right direction"),
+            Some(direction) => {
+                return Err(ErrorKind::FormatUnsupported(format!(
+                    "PlantUML direction {:?}",
+                    direction
+                ))
+                .into())
+            }
+        };
         let visitor = Visitor {
+            options: &self.options,
             container: Default::default(),
-            buffer: RefCell::new(String::new()),
+            history: Default::default(),
+            out: Sink::new(w),
         };
         visitor.push_line("@startuml");
-        let _ = visit_state_machine(&machine, &visitor);
+        if let Some(theme) = &self.options.theme {
+            visitor.push_line(&format!("!theme {}", theme));
+        }
+        if let Some(direction) = direction {
+            visitor.push_line(direction);
+        }
+        if self.options.hide_empty_description {
+            visitor.push_line("hide empty description");
+        }
+        visit_state_machine(machine, &visitor)?;
         visitor.push_line("@enduml");
-        Ok(visitor.buffer.into_inner())
+        visitor.out.finish()
     }
 }
 
-impl StateMachineVisitor for Visitor {
+impl Parse for ReadPlantUml {
+    type Error = Error;
+
+    fn parse(&self, string: &str) -> Result<StateMachine> {
+        let mut parser = Parser::default();
+        for (index, line) in string.lines().enumerate() {
+            parser.line = index + 1;
+            parser.read_line(line.trim())?;
+        }
+        if parser.scopes.len() > 1 {
+            return Err(parser.error("a state block is not closed"));
+        }
+        if !matches!(parser.block, Block::None) {
+            return Err(parser.error("a note or comment block is not closed"));
+        }
+        Ok(parser.into_machine())
+    }
+}
+
+impl StateMachineVisitor for Visitor<'_> {
     fn enter_state_machine(
         &self,
         _: &Resolver<'_>,
@@ -110,6 +268,7 @@ impl StateMachineVisitor for Visitor {
         _resolver: &Resolver<'_>,
         id: &ID,
         label: &Option<String>,
+        _comments: Iter<'_, String>,
         region_count: usize,
         _sub_machine: &Option<ID>,
         _connections: Iter<'_, ID>,
@@ -124,7 +283,11 @@ impl StateMachineVisitor for Visitor {
         self.container.borrow_mut().push(id.clone());
         if !is_final {
             if let Some(label) = label {
-                self.push_str(&format!("state \"{}\" as {}", label, id));
+                self.push_str(&format!(
+                    "state \"{}\" as {}",
+                    self.display_name(label, id),
+                    id
+                ));
             } else {
                 self.push_str(&format!("state {}", id));
             }
@@ -140,11 +303,12 @@ impl StateMachineVisitor for Visitor {
         _resolver: &Resolver<'_>,
         id: &ID,
         _label: &Option<String>,
+        comments: Iter<'_, String>,
         region_count: usize,
         _sub_machine: &Option<ID>,
         _connections: Iter<'_, ID>,
         _connection_points: Iter<'_, ID>,
-        _deferrable_triggers: Iter<'_, Trigger>,
+        deferrable_triggers: Iter<'_, Trigger>,
         _invariant: &Option<Box<dyn Constraint>>,
         entry: &Option<Box<dyn Behavior>>,
         do_activity: &Option<Box<dyn Behavior>>,
@@ -176,18 +340,27 @@ impl StateMachineVisitor for Visitor {
                     self.push_line(&format!("{}: exit / ()", id));
                 }
             }
+            for trigger in deferrable_triggers {
+                self.push_line(&format!("{}: defer {}", id, trigger));
+            }
+            for comment in comments {
+                if comment.contains('\n') {
+                    self.push_line(&format!("note right of {}", id));
+                    self.push_line(comment);
+                    self.push_line("end note");
+                } else {
+                    self.push_line(&format!("note right of {} : {}", id, comment));
+                }
+            }
         }
         let _ = self.container.borrow_mut().pop();
     }
 
-    fn enter_region(
-        &self,
-        _resolver: &Resolver<'_>,
-        id: &ID,
-        _label: &Option<String>,
-        _last: bool,
-    ) {
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, label: &Option<String>, _last: bool) {
         self.container.borrow_mut().push(id.clone());
+        if let Some(label) = label {
+            self.push_line(&format!("' region {}", label));
+        }
     }
 
     fn exit_region(&self, _resolver: &Resolver<'_>, _: &ID, _label: &Option<String>, last: bool) {
@@ -204,19 +377,45 @@ impl StateMachineVisitor for Visitor {
         label: &Option<String>,
         kind: &PseudoStateKind,
     ) {
-        fn pseudo_as_state(visitor: &Visitor, id: &ID, label: &Option<String>, stereotype: &str) {
+        fn pseudo_as_state(
+            visitor: &Visitor<'_>,
+            id: &ID,
+            label: &Option<String>,
+            stereotype: &str,
+        ) {
             if let Some(label) = label {
-                visitor.push_str(&format!("state \"{}\" as {} <<{}>>", label, id, stereotype));
+                visitor.push_line(&format!(
+                    "state \"{}\" as {} <<{}>>",
+                    visitor.display_name(label, id),
+                    id,
+                    stereotype
+                ));
             } else {
-                visitor.push_str(&format!("state {} <<{}>>", id, stereotype));
+                visitor.push_line(&format!("state {} <<{}>>", id, stereotype));
             }
         }
+        fn history(visitor: &Visitor<'_>, id: &ID, marker: &str) {
+            // the container stack ends with this region, preceded by its state if nested.
+            let container = visitor.container.borrow();
+            let reference = if container.len() > 2 {
+                format!("{}{}", container[container.len() - 2], marker)
+            } else {
+                marker.to_string()
+            };
+            let _ = visitor.history.borrow_mut().insert(id.clone(), reference);
+        }
         match kind {
             PseudoStateKind::Initial => {}
+            PseudoStateKind::DeepHistory => history(self, id, "[H*]"),
+            PseudoStateKind::ShallowHistory => history(self, id, "[H]"),
             PseudoStateKind::Choice => pseudo_as_state(self, id, label, "choice"),
+            PseudoStateKind::Junction => pseudo_as_state(self, id, label, "choice"),
             PseudoStateKind::Fork => pseudo_as_state(self, id, label, "fork"),
             PseudoStateKind::Join => pseudo_as_state(self, id, label, "join"),
-            _ => unimplemented!(),
+            PseudoStateKind::EntryPoint => pseudo_as_state(self, id, label, "entryPoint"),
+            PseudoStateKind::ExitPoint => pseudo_as_state(self, id, label, "exitPoint"),
+            // PlantUML has no terminate notation, it is drawn as an end point.
+            PseudoStateKind::Terminate => pseudo_as_state(self, id, label, "end"),
         }
     }
 
@@ -227,11 +426,19 @@ impl StateMachineVisitor for Visitor {
         _kind: TransitionKind,
         source: ID,
         target: ID,
-        _triggers: Iter<'_, Trigger>,
+        triggers: Iter<'_, Trigger>,
         guard: &Option<Box<dyn Constraint>>,
         effect: &Option<Box<dyn Behavior>>,
     ) {
-        fn state_str(resolver: &Resolver<'_>, container: ID, id: ID) -> String {
+        fn state_str(
+            visitor: &Visitor<'_>,
+            resolver: &Resolver<'_>,
+            container: ID,
+            id: ID,
+        ) -> String {
+            if let Some(reference) = visitor.history.borrow().get(&id) {
+                return reference.clone();
+            }
             match resolver.find_vertex(container.clone(), id) {
                 None => "ERROR".to_string(),
                 Some(rc_vertex) => match rc_vertex.borrow() {
@@ -256,10 +463,16 @@ impl StateMachineVisitor for Visitor {
         let container = self.container.borrow().last().unwrap().clone();
         self.push_str(&format!(
             "{} --> {}",
-            state_str(resolver, container.clone(), source),
-            state_str(resolver, container.clone(), target)
+            state_str(self, resolver, container.clone(), source),
+            state_str(self, resolver, container.clone(), target)
         ));
-        let mut all_label = String::new();
+        let mut all_label = triggers
+            .map(|trigger| trigger.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        if !all_label.is_empty() {
+            all_label.push(' ');
+        }
         if let Some(guard) = guard {
             if let Some(label) = guard.label() {
                 all_label.push_str(&format!("[{}] ", label));
@@ -281,13 +494,425 @@ impl StateMachineVisitor for Visitor {
     }
 }
 
-impl Visitor {
+impl Visitor<'_> {
     pub(crate) fn push_str(&self, string: &str) {
-        self.buffer.borrow_mut().push_str(string);
+        self.out.push_str(string);
     }
 
     pub(crate) fn push_line(&self, string: &str) {
-        self.buffer.borrow_mut().push_str(&format!("{}\n", string));
+        self.out.push_line(string);
+    }
+
+    fn display_name(&self, label: &str, id: &ID) -> String {
+        if self.options.show_ids {
+            format!("{}\\n{}", label, id)
+        } else {
+            label.to_string()
+        }
+    }
+}
+
+impl Parser {
+    fn read_line(&mut self, line: &str) -> Result<()> {
+        match &mut self.block {
+            Block::None => {}
+            Block::Note(node, lines) => {
+                if line == "end note" {
+                    let comment = lines.join("\n");
+                    let node = *node;
+                    self.nodes[node].comments.push(comment);
+                    self.block = Block::None;
+                } else {
+                    lines.push(line.to_string());
+                }
+                return Ok(());
+            }
+            Block::Skip(end) => {
+                if line.ends_with(*end) {
+                    self.block = Block::None;
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some(label) = line.strip_prefix("' region ") {
+            let scope = self.scope();
+            self.nodes[scope].regions.last_mut().unwrap().label = Some(label.trim().to_string());
+            return Ok(());
+        }
+        if line.is_empty()
+            || line.starts_with('\'')
+            || line.starts_with("@startuml")
+            || line.starts_with("@enduml")
+        {
+            return Ok(());
+        }
+
+        if line.starts_with("/'") {
+            if !line.ends_with("'/") {
+                self.block = Block::Skip("'/");
+            }
+        } else if let Some(title) = line.strip_prefix("title ") {
+            self.title = Some(title.trim().to_string());
+        } else if line == "}" {
+            if self.scopes.len() == 1 {
+                return Err(self.error("unbalanced `}`"));
+            }
+            let _ = self.scopes.pop();
+        } else if line == "--" || line == "||" {
+            let scope = self.scope();
+            self.nodes[scope].regions.push(Default::default());
+        } else if let Some(rest) = line.strip_prefix("state ") {
+            self.read_state(rest.trim())?;
+        } else if let Some(rest) = line.strip_prefix("note ") {
+            self.read_note(rest.trim());
+        } else if line == "legend" || line.starts_with("legend ") {
+            self.block = Block::Skip("endlegend");
+        } else if IGNORED_DIRECTIVES
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+        {
+            if line.ends_with('{') {
+                self.block = Block::Skip("}");
+            }
+        } else if !self.read_transition(line)? {
+            let (name, rest) = split_name(line);
+            match rest.trim_start().strip_prefix(':') {
+                Some(text) if !name.is_empty() => {
+                    let node = self.node(name);
+                    self.read_description(node, text.trim())?;
+                }
+                _ => return Err(self.error(&format!("unrecognized line `{}`", line))),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_state(&mut self, rest: &str) -> Result<()> {
+        let (label, rest) = match rest.strip_prefix('"') {
+            None => (None, rest),
+            Some(rest) => {
+                let (label, rest) = rest
+                    .split_once('"')
+                    .ok_or_else(|| self.error("unterminated state label"))?;
+                let rest = rest
+                    .trim_start()
+                    .strip_prefix("as ")
+                    .ok_or_else(|| self.error("expected `as` after a state label"))?;
+                (Some(label.to_string()), rest.trim_start())
+            }
+        };
+        let (name, rest) = split_name(rest);
+        if name.is_empty() {
+            return Err(self.error("expected a state name"));
+        }
+        let mut rest = rest.trim_start();
+        let mut kind = None;
+        if let Some(stereotype) = rest.strip_prefix("<<") {
+            let (stereotype, after) = stereotype
+                .split_once(">>")
+                .ok_or_else(|| self.error("unterminated stereotype"))?;
+            kind = Some(pseudo_state_kind(stereotype.trim())?);
+            rest = after.trim_start();
+        }
+
+        let node = self.node(name);
+        if label.is_some() {
+            self.nodes[node].label = label;
+        }
+        if kind.is_some() {
+            self.nodes[node].kind = kind;
+        }
+        if let Some(after) = rest.strip_prefix('{') {
+            if self.nodes[node].regions.is_empty() {
+                self.nodes[node].regions.push(Default::default());
+            }
+            self.scopes.push(node);
+            rest = after.trim_start();
+        }
+        if let Some(text) = rest.strip_prefix(':') {
+            self.read_description(node, text.trim())
+        } else if rest.is_empty() {
+            Ok(())
+        } else {
+            Err(self.error(&format!("unexpected `{}` in a state declaration", rest)))
+        }
+    }
+
+    fn read_note(&mut self, rest: &str) {
+        let attached = ["left of ", "right of ", "top of ", "bottom of "]
+            .iter()
+            .find_map(|position| rest.strip_prefix(position));
+        match attached {
+            // floating notes are not attached to any state, and are ignored.
+            None => {
+                if !rest.starts_with('"') {
+                    self.block = Block::Skip("end note");
+                }
+            }
+            Some(rest) => {
+                let (name, text) = split_name(rest.trim_start());
+                let node = self.node(name);
+                match text.trim_start().strip_prefix(':') {
+                    Some(text) => self.nodes[node].comments.push(text.trim().to_string()),
+                    None => self.block = Block::Note(node, Default::default()),
+                }
+            }
+        }
+    }
+
+    fn read_description(&mut self, node: usize, text: &str) -> Result<()> {
+        if let Some(behavior) = text.strip_prefix("entry /") {
+            self.nodes[node].entry = Some(behavior.trim().to_string());
+        } else if let Some(behavior) = text.strip_prefix("do /") {
+            self.nodes[node].do_activity = Some(behavior.trim().to_string());
+        } else if let Some(behavior) = text.strip_prefix("exit /") {
+            self.nodes[node].exit = Some(behavior.trim().to_string());
+        } else if let Some(trigger) = text.strip_prefix("defer ") {
+            let trigger = self.trigger(trigger.trim())?;
+            self.nodes[node].deferred.push(trigger);
+        } else {
+            self.nodes[node].comments.push(text.to_string());
+        }
+        Ok(())
+    }
+
+    fn read_transition(&mut self, line: &str) -> Result<bool> {
+        let (source, rest) = match endpoint(line) {
+            None => return Ok(false),
+            Some(source) => source,
+        };
+        let rest = rest.trim_start();
+        let arrow_end = match rest.find('>') {
+            Some(end) if rest.starts_with('-') && rest[..=end].ends_with("->") => end,
+            _ => return Ok(false),
+        };
+        let (target, rest) = endpoint(rest[arrow_end + 1..].trim_start())
+            .ok_or_else(|| self.error("expected a transition target"))?;
+        let label = match rest.trim() {
+            "" => "",
+            rest => rest
+                .strip_prefix(':')
+                .ok_or_else(|| self.error(&format!("unexpected `{}` after a transition", rest)))?
+                .trim(),
+        };
+
+        let (triggers, guard, label, effect) = split_transition_label(label);
+        let triggers = triggers
+            .into_iter()
+            .map(|trigger| self.trigger(trigger))
+            .collect::<Result<Vec<Trigger>>>()?;
+        let source = self.endpoint(source, true);
+        let target = self.endpoint(target, false);
+        let scope = self.scope();
+        self.transitions.push(ParsedTransition {
+            node: scope,
+            region: self.nodes[scope].regions.len() - 1,
+            source,
+            target,
+            triggers,
+            guard: guard.map(str::to_string),
+            label: label.map(str::to_string),
+            effect: effect.map(str::to_string),
+        });
+        Ok(true)
+    }
+
+    fn trigger(&self, trigger: &str) -> Result<Trigger> {
+        parse_trigger(trigger)
+            .ok_or_else(|| self.error(&format!("could not parse the trigger `{}`", trigger)))
+    }
+
+    fn scope(&self) -> usize {
+        *self.scopes.last().unwrap()
+    }
+
+    fn node(&mut self, name: &str) -> usize {
+        if let Some(node) = self.names.get(name) {
+            return *node;
+        }
+        let scope = self.scope();
+        self.nodes.push(ParsedNode {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        let node = self.nodes.len() - 1;
+        self.nodes[scope]
+            .regions
+            .last_mut()
+            .unwrap()
+            .vertices
+            .push(node);
+        let _ = self.names.insert(name.to_string(), node);
+        node
+    }
+
+    fn endpoint(&mut self, endpoint: RawEndpoint<'_>, is_source: bool) -> Endpoint {
+        let scope = self.scope();
+        let region = self.nodes[scope].regions.len() - 1;
+        let implied = match endpoint {
+            RawEndpoint::Name(name) => return Endpoint::Node(self.node(name)),
+            RawEndpoint::Star if is_source => (scope, region, Implied::Initial),
+            RawEndpoint::Star => (scope, region, Implied::Final),
+            RawEndpoint::History(None, deep) => (scope, region, Implied::history(deep)),
+            RawEndpoint::History(Some(name), deep) => {
+                let node = self.node(name);
+                if self.nodes[node].regions.is_empty() {
+                    self.nodes[node].regions.push(Default::default());
+                }
+                (node, 0, Implied::history(deep))
+            }
+        };
+        if !self.implied.contains(&implied) {
+            self.implied.push(implied.clone());
+        }
+        Endpoint::Implied(implied)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        ErrorKind::FormatParse(format!("line {}: {}", self.line, message)).into()
+    }
+
+    fn into_machine(self) -> StateMachine {
+        let mut machine = match &self.title {
+            None => StateMachine::default(),
+            Some(title) => StateMachine::labeled(title),
+        };
+        let root = &self.nodes[0];
+        for _ in 1..root.regions.len() {
+            let _ = machine.new_region();
+        }
+        let mut built = Built::default();
+        for (index, parsed) in root.regions.iter().enumerate() {
+            let region = &mut machine.regions[index];
+            if let Some(label) = &parsed.label {
+                region.set_label(label);
+            }
+            self.build_region(0, index, region, &mut built);
+        }
+
+        let mut transitions: HashMap<ID, Vec<Transition>> = Default::default();
+        for parsed in &self.transitions {
+            let container = built.regions[&(parsed.node, parsed.region)].clone();
+            let mut transition = Transition::within(
+                built.vertex(&parsed.source),
+                built.vertex(&parsed.target),
+                container.clone(),
+            );
+            transition.set_kind(TransitionKind::External);
+            for trigger in &parsed.triggers {
+                transition.add_trigger(trigger.clone());
+            }
+            if let Some(guard) = &parsed.guard {
+                transition.set_guard(recognize_else(Box::new(OpaqueConstraint::new(guard, None))));
+            }
+            if let Some(label) = &parsed.label {
+                transition.set_label(label);
+            }
+            if let Some(effect) = &parsed.effect {
+                transition.set_effect(Box::new(OpaqueBehavior::new(effect, None)));
+            }
+            transitions.entry(container).or_default().push(transition);
+        }
+        machine.for_each_region(&mut |region| {
+            for transition in transitions.remove(region.id()).unwrap_or_default() {
+                region.add_transition(transition);
+            }
+        });
+        machine
+    }
+
+    fn build_region(&self, node: usize, index: usize, region: &Region, built: &mut Built) {
+        let _ = built.regions.insert((node, index), region.id().clone());
+        for child in &self.nodes[node].regions[index].vertices {
+            let parsed = &self.nodes[*child];
+            let label = parsed.label.as_ref().unwrap_or(&parsed.name);
+            match &parsed.kind {
+                Some(kind) => {
+                    let mut pseudo_state = PseudoState::within(region.id().clone(), kind.clone());
+                    pseudo_state.set_label(label);
+                    let _ = built.vertices.insert(*child, pseudo_state.id().clone());
+                    region.add_pseudo_state(pseudo_state);
+                }
+                None => {
+                    let mut state = State::within(region.id().clone());
+                    state.set_label(label);
+                    let _ = built.vertices.insert(*child, state.id().clone());
+                    for (index, parsed_region) in parsed.regions.iter().enumerate() {
+                        let mut inner = Region::within_state(state.id().clone());
+                        if let Some(label) = &parsed_region.label {
+                            inner.set_label(label);
+                        }
+                        self.build_region(*child, index, &inner, built);
+                        state.add_region(inner);
+                    }
+                    if let Some(entry) = &parsed.entry {
+                        state.set_entry(Box::new(OpaqueBehavior::new(entry, None)));
+                    }
+                    if let Some(do_activity) = &parsed.do_activity {
+                        state.set_do_activity(Box::new(OpaqueBehavior::new(do_activity, None)));
+                    }
+                    if let Some(exit) = &parsed.exit {
+                        state.set_exit(Box::new(OpaqueBehavior::new(exit, None)));
+                    }
+                    for trigger in &parsed.deferred {
+                        state.add_deferrable_trigger(trigger.clone());
+                    }
+                    for comment in &parsed.comments {
+                        state.add_comment(comment);
+                    }
+                    region.add_state(state);
+                }
+            }
+        }
+        for implied in &self.implied {
+            if implied.0 == node && implied.1 == index {
+                let id = match implied.2 {
+                    Implied::Initial => region.new_initial_state(),
+                    Implied::Final => region.new_final_state(),
+                    Implied::ShallowHistory => region.new_shallow_history_state(),
+                    Implied::DeepHistory => region.new_deep_history_state(),
+                };
+                let _ = built.implied.insert(implied.clone(), id);
+            }
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            line: 0,
+            title: None,
+            nodes: vec![ParsedNode {
+                regions: vec![Default::default()],
+                ..Default::default()
+            }],
+            names: Default::default(),
+            scopes: vec![0],
+            transitions: Default::default(),
+            implied: Default::default(),
+            block: Block::None,
+        }
+    }
+}
+
+impl Implied {
+    fn history(deep: bool) -> Self {
+        if deep {
+            Implied::DeepHistory
+        } else {
+            Implied::ShallowHistory
+        }
+    }
+}
+
+impl Built {
+    fn vertex(&self, endpoint: &Endpoint) -> ID {
+        match endpoint {
+            Endpoint::Node(node) => self.vertices[node].clone(),
+            Endpoint::Implied(implied) => self.implied[implied].clone(),
+        }
     }
 }
 
@@ -295,10 +920,612 @@ impl Visitor {
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+const IGNORED_DIRECTIVES: &[&str] = &[
+    "!",
+    "skinparam",
+    "hide ",
+    "show ",
+    "scale ",
+    "caption ",
+    "header ",
+    "footer ",
+    "left to right direction",
+    "top to bottom direction",
+];
+
+struct Parser {
+    line: usize,
+    title: Option<String>,
+    /// The machine is node `0`, all other nodes are vertices.
+    nodes: Vec<ParsedNode>,
+    names: HashMap<String, usize>,
+    /// The nodes whose blocks enclose the current line, innermost last.
+    scopes: Vec<usize>,
+    transitions: Vec<ParsedTransition>,
+    /// Pseudostates that are only referenced by transitions, `[*]` and `[H]`.
+    implied: Vec<(usize, usize, Implied)>,
+    block: Block,
+}
+
+enum Block {
+    None,
+    Note(usize, Vec<String>),
+    Skip(&'static str),
+}
+
+#[derive(Default)]
+struct ParsedNode {
+    name: String,
+    label: Option<String>,
+    kind: Option<PseudoStateKind>,
+    regions: Vec<ParsedRegion>,
+    entry: Option<String>,
+    do_activity: Option<String>,
+    exit: Option<String>,
+    deferred: Vec<Trigger>,
+    comments: Vec<String>,
+}
+
+#[derive(Default)]
+struct ParsedRegion {
+    label: Option<String>,
+    vertices: Vec<usize>,
+}
+
+struct ParsedTransition {
+    node: usize,
+    region: usize,
+    source: Endpoint,
+    target: Endpoint,
+    triggers: Vec<Trigger>,
+    guard: Option<String>,
+    label: Option<String>,
+    effect: Option<String>,
+}
+
+enum RawEndpoint<'a> {
+    Star,
+    History(Option<&'a str>, bool),
+    Name(&'a str),
+}
+
+enum Endpoint {
+    Node(usize),
+    Implied((usize, usize, Implied)),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Implied {
+    Initial,
+    Final,
+    ShallowHistory,
+    DeepHistory,
+}
+
+#[derive(Default)]
+struct Built {
+    vertices: HashMap<usize, ID>,
+    regions: HashMap<(usize, usize), ID>,
+    implied: HashMap<(usize, usize, Implied), ID>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "execution")]
+fn write_trace(trace: &ExecutionTrace, name: impl Fn(&ID) -> String) -> String {
+    let names = |states: &[ID]| match states {
+        [] => "(none)".to_string(),
+        states => states.iter().map(&name).collect::<Vec<String>>().join(", "),
+    };
+    let mut lines: Vec<String> = vec![
+        "@startuml".to_string(),
+        "hide footbox".to_string(),
+        "participant Instance as instance".to_string(),
+    ];
+    let mut previous = None;
+    for entry in trace.entries() {
+        if let Some(previous) = previous {
+            let elapsed = entry.time().duration_since(previous).unwrap_or_default();
+            if !elapsed.is_zero() {
+                lines.push(format!("...{:?} later...", elapsed));
+            }
+        }
+        previous = Some(entry.time());
+        match entry.event() {
+            None => lines.push("[-> instance : start".to_string()),
+            Some(event) => lines.push(format!("[-> instance : {}", event)),
+        }
+        for fired in entry.fired() {
+            lines.push(format!(
+                "instance -> instance : {} -> {}",
+                name(fired.source()),
+                name(fired.target())
+            ));
+        }
+        if entry.event().is_none() || entry.before() != entry.after() {
+            lines.push(format!("hnote over instance : {}", names(entry.after())));
+        }
+    }
+    lines.push("@enduml".to_string());
+    lines.join("\n")
+}
+
+fn split_name(string: &str) -> (&str, &str) {
+    let bytes = string.as_bytes();
+    let mut end = 0;
+    while end < bytes.len() {
+        let c = bytes[end];
+        if c.is_ascii_alphanumeric() || c == b'_' || c == b'.' {
+            end += 1;
+        } else if c == b':' && bytes.get(end + 1) == Some(&b':') {
+            end += 2;
+        } else {
+            break;
+        }
+    }
+    string.split_at(end)
+}
+
+fn endpoint(string: &str) -> Option<(RawEndpoint<'_>, &str)> {
+    if let Some(rest) = string.strip_prefix("[*]") {
+        return Some((RawEndpoint::Star, rest));
+    }
+    if let Some((deep, rest)) = history_marker(string) {
+        return Some((RawEndpoint::History(None, deep), rest));
+    }
+    let (name, rest) = split_name(string);
+    if name.is_empty() {
+        None
+    } else if let Some((deep, rest)) = history_marker(rest) {
+        Some((RawEndpoint::History(Some(name), deep), rest))
+    } else {
+        Some((RawEndpoint::Name(name), rest))
+    }
+}
+
+fn history_marker(string: &str) -> Option<(bool, &str)> {
+    if let Some(rest) = string.strip_prefix("[H*]") {
+        Some((true, rest))
+    } else {
+        string.strip_prefix("[H]").map(|rest| (false, rest))
+    }
+}
+
+fn pseudo_state_kind(stereotype: &str) -> Result<PseudoStateKind> {
+    match stereotype {
+        "start" => Ok(PseudoStateKind::Initial),
+        "choice" => Ok(PseudoStateKind::Choice),
+        "fork" => Ok(PseudoStateKind::Fork),
+        "join" => Ok(PseudoStateKind::Join),
+        "entryPoint" => Ok(PseudoStateKind::EntryPoint),
+        "exitPoint" => Ok(PseudoStateKind::ExitPoint),
+        "history" => Ok(PseudoStateKind::ShallowHistory),
+        "history*" => Ok(PseudoStateKind::DeepHistory),
+        "end" => Ok(PseudoStateKind::Terminate),
+        stereotype => {
+            Err(ErrorKind::FormatUnsupported(format!("the stereotype `<<{}>>`", stereotype)).into())
+        }
+    }
+}
+
+///
+/// Split a transition label into its triggers, guard, free text, and effect; brackets and
+/// parentheses are respected so that guards and trigger arguments may contain separators.
+///
+fn split_transition_label(label: &str) -> (Vec<&str>, Option<&str>, Option<&str>, Option<&str>) {
+    let mut depth = 0;
+    let mut guard: Option<(usize, usize)> = None;
+    let mut effect: Option<usize> = None;
+    let mut separators: Vec<usize> = Default::default();
+    for (index, c) in label.char_indices() {
+        match c {
+            '[' => {
+                if depth == 0 && guard.is_none() {
+                    guard = Some((index, index));
+                }
+                depth += 1;
+            }
+            ']' => {
+                depth -= 1;
+                if let Some((start, end)) = &mut guard {
+                    if depth == 0 && start == end {
+                        *end = index;
+                    }
+                }
+            }
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 && guard.is_none() => separators.push(index),
+            '/' if depth == 0 => {
+                effect = Some(index);
+                break;
+            }
+            _ => {}
+        }
+    }
+    fn non_empty(s: &str) -> Option<&str> {
+        let s = s.trim();
+        if s.is_empty() {
+            None
+        } else {
+            Some(s)
+        }
+    }
+    let body_end = effect.unwrap_or(label.len());
+    let effect = effect.and_then(|index| non_empty(&label[index + 1..]));
+    let (triggers_end, guard_text, text) = match guard {
+        Some((start, end)) if end > start => (
+            start,
+            non_empty(&label[start + 1..end]),
+            non_empty(&label[end + 1..body_end]),
+        ),
+        _ => (body_end, None, None),
+    };
+    let mut triggers: Vec<&str> = Default::default();
+    let mut start = 0;
+    for separator in separators.into_iter().chain(std::iter::once(triggers_end)) {
+        if let Some(trigger) = non_empty(&label[start..separator]) {
+            triggers.push(trigger);
+        }
+        start = separator + 1;
+    }
+    (triggers, guard_text, text, effect)
+}
+
+///
+/// The inverse of the `Display` implementation for `Trigger`, without payload predicates.
+///
+fn parse_trigger(trigger: &str) -> Option<Trigger> {
+    if trigger == "*" {
+        return Some(Trigger::any());
+    }
+    match trigger.strip_suffix(')').and_then(|t| t.split_once('(')) {
+        None => Some(Trigger::signal(trigger)),
+        Some(("after", duration)) => parse_duration(duration).map(Trigger::after),
+        Some(("at", seconds)) => seconds
+            .parse()
+            .ok()
+            .map(|seconds| Trigger::at(UNIX_EPOCH + Duration::from_secs(seconds))),
+        Some(("when", expression)) => Some(Trigger::when(Arc::new(OpaqueConstraint::new(
+            expression, None,
+        )))),
+        Some((operation, "")) => Some(Trigger::call(operation)),
+        Some(_) => None,
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Modules
 // ------------------------------------------------------------------------------------------------
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Stringify;
+
+    const ORDER: &str = r#"@startuml
+title Order
+skinparam state {
+  BackgroundColor White
+}
+[*] --> Idle
+state Idle : entry / reset
+Idle -right-> Active : submit [valid] / notify
+state "Processing" as Active {
+  [*] --> Checking
+  Checking --> Paying : checked, after(1.5s)
+  Paying : defer cancel
+  --
+  [*] --> Logging
+}
+state c <<choice>>
+Active --> c : after(5s)
+c --> [*]
+Active --> Active[H*] : resume, *
+note right of Idle : waits
+note left of Active
+  Takes
+  payment
+end note
+@enduml"#;
+
+    #[cfg(feature = "execution")]
+    #[test]
+    fn test_trace_to_plantuml() {
+        use crate::definition::builder::MachineBuilder;
+        use crate::definition::types::{Event, SignalEvent};
+        use crate::execution::timers::ManualClock;
+        use crate::execution::trace::TraceRecorder;
+        use crate::execution::types::StateMachineInstance;
+        use std::time::SystemTime;
+
+        let machine = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("Closed")
+                    .state("Closed", |state| state.on("open", "Open"))
+                    .state("Open", |state| state.on("close", "Closed"))
+            })
+            .build()
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let recorder = Arc::new(TraceRecorder::default());
+        let instance = StateMachineInstance::new(machine.clone())
+            .unwrap()
+            .with_clock(clock.clone());
+        instance.add_observer(recorder.clone());
+        instance.execute().unwrap();
+        clock.advance(Duration::from_millis(1500));
+        for signal in &["open", "ignored"] {
+            instance
+                .post(&Event::Signal(SignalEvent::new(signal)))
+                .unwrap();
+        }
+        let trace = recorder.trace();
+
+        assert_eq!(
+            trace_to_plantuml_labeled(&trace, &machine),
+            r#"@startuml
+hide footbox
+participant Instance as instance
+[-> instance : start
+instance -> instance : [*] -> Closed
+hnote over instance : Closed
+...1.5s later...
+[-> instance : open
+instance -> instance : Closed -> Open
+hnote over instance : Open
+[-> instance : ignored
+@enduml"#
+        );
+        let closed = machine.find_state_by_label("Closed").unwrap();
+        assert!(
+            trace_to_plantuml(&trace).contains(&format!("hnote over instance : {}", closed.id()))
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let machine = ReadPlantUml::default().parse(ORDER).unwrap();
+        assert_eq!(machine.label(), &Some("Order".to_string()));
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.vertices().len(), 5);
+        assert_eq!(region.transitions().len(), 5);
+
+        let find = |vertices: Vec<Arc<Vertex>>, label: &str| {
+            vertices
+                .into_iter()
+                .find(|vertex| {
+                    vertex.as_state().and_then(|state| state.label().clone())
+                        == Some(label.to_string())
+                })
+                .unwrap()
+        };
+        let idle = find(region.vertices(), "Idle");
+        let idle = idle.as_state().unwrap();
+        assert_eq!(
+            idle.entry().as_ref().unwrap().label(),
+            &Some("reset".to_string())
+        );
+        assert_eq!(idle.comments().collect::<Vec<_>>(), vec!["waits"]);
+
+        let active = find(region.vertices(), "Processing");
+        let active = active.as_state().unwrap();
+        assert_eq!(active.regions().len(), 2);
+        assert_eq!(
+            active.comments().collect::<Vec<_>>(),
+            vec!["Takes\npayment"]
+        );
+        let inner = active.default_region().unwrap();
+        assert_eq!(inner.vertices().len(), 4);
+        assert!(inner
+            .vertices()
+            .iter()
+            .any(|v| v.as_pseudo_state().map(|p| p.is_deep_history()) == Some(true)));
+        let paying = find(inner.vertices(), "Paying");
+        assert_eq!(
+            paying.as_state().unwrap().deferrable_triggers().next(),
+            Some(&Trigger::signal("cancel"))
+        );
+        let checked = &inner.transitions()[1];
+        assert_eq!(
+            checked.triggers().collect::<Vec<_>>(),
+            vec![
+                &Trigger::signal("checked"),
+                &Trigger::after(Duration::from_millis(1500))
+            ]
+        );
+
+        let submit = region
+            .transitions()
+            .into_iter()
+            .find(|t| t.triggers().next() == Some(&Trigger::signal("submit")))
+            .unwrap();
+        assert_eq!(
+            submit.guard().as_ref().unwrap().label(),
+            &Some("valid".to_string())
+        );
+        assert_eq!(
+            submit.effect().as_ref().unwrap().label(),
+            &Some("notify".to_string())
+        );
+        let resume = region.transitions().pop().unwrap();
+        assert_eq!(
+            resume.triggers().collect::<Vec<_>>(),
+            vec![&Trigger::signal("resume"), &Trigger::any()]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let machine = ReadPlantUml::default().parse(ORDER).unwrap();
+        let written = WritePlantUml::default().stringify(&machine).unwrap();
+        let reread = ReadPlantUml::default().parse(&written).unwrap();
+        let rewritten = WritePlantUml::default().stringify(&reread).unwrap();
+        assert_eq!(written.lines().count(), rewritten.lines().count());
+        assert_eq!(written.matches("-->").count(), 8);
+        assert_eq!(rewritten.matches("-->").count(), 8);
+        assert!(rewritten.contains("[H*] : resume, * \n"));
+        assert!(rewritten.contains(": entry / reset\n"));
+        assert!(rewritten.contains(": defer cancel\n"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let reader = ReadPlantUml::default();
+        assert!(reader.parse("@startuml\n}\n@enduml").is_err());
+        assert!(reader.parse("@startuml\nstate A {\n@enduml").is_err());
+        assert!(reader
+            .parse("@startuml\nA --> B : after(soon)\n@enduml")
+            .is_err());
+        assert!(reader
+            .parse("@startuml\nstate A <<sdlreceive>>\n@enduml")
+            .is_err());
+        match reader.parse("@startuml\nA --> B\nwhat is this\n@enduml") {
+            Err(Error(ErrorKind::FormatParse(message), _)) => {
+                assert!(message.starts_with("line 3:"))
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_notes_regions_and_deferral() {
+        let machine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let mut state = State::within(region.id().clone());
+        state.add_comment("Waits for payment");
+        state.add_comment("Retries\nthree times");
+        state.add_deferrable_trigger(Trigger::signal("cancel"));
+        let _ = state.new_region();
+        let _ = state.new_region();
+        let mut payment = Region::within_state(state.id().clone());
+        payment.set_label("payment");
+        state.add_region(payment);
+        let state_id = state.id().clone();
+        region.add_state(state);
+
+        let uml = WritePlantUml::default().stringify(&machine).unwrap();
+        assert!(uml.contains(&format!("{}: defer cancel\n", state_id)));
+        assert!(uml.contains(&format!("note right of {} : Waits for payment\n", state_id)));
+        assert!(uml.contains(&format!(
+            "note right of {}\nRetries\nthree times\nend note\n",
+            state_id
+        )));
+        assert!(uml.contains("' region payment\n"));
+        assert_eq!(uml.matches("--\n").count(), 2);
+    }
+
+    #[test]
+    fn test_pseudo_states() {
+        let machine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let mut state = State::within(region.id().clone());
+        let _ = state.new_region();
+        let inner = state.default_region().unwrap();
+        let deep = inner.new_deep_history_state();
+        let entry = inner.new_entry_point();
+        let exit = inner.new_exit_point();
+        let shallow = inner.new_shallow_history_state();
+        let state_id = state.id().clone();
+        region.add_state(state);
+        let choice = region.new_choice_state();
+        let junction = region.new_junction();
+        let fork = region.new_fork();
+        let join = region.new_join();
+        let terminate = region.new_terminate_state();
+        region.new_transition(choice.clone(), deep);
+        region.new_transition(junction.clone(), shallow);
+        let mut split = State::within(region.id().clone());
+        let _ = split.new_region();
+        let _ = split.new_region();
+        let ends: Vec<ID> = split
+            .regions()
+            .map(|region| region.new_simple_state())
+            .collect();
+        region.add_state(split);
+        let idle = region.new_simple_state();
+        region.new_transition(idle.clone(), choice.clone());
+        region.new_transition(idle.clone(), junction.clone());
+        region.new_transition(idle, fork.clone());
+        for end in &ends {
+            region.new_transition(fork.clone(), end.clone());
+            region.new_transition(end.clone(), join.clone());
+        }
+        region.new_transition(join.clone(), terminate.clone());
+
+        let uml = WritePlantUml::default().stringify(&machine).unwrap();
+        assert!(uml.contains(&format!("state {} <<choice>>\n", choice)));
+        assert!(uml.contains(&format!("state {} <<fork>>\n", fork)));
+        assert!(uml.contains(&format!("state {} <<join>>\n", join)));
+        assert!(uml.contains(&format!("state {} <<entryPoint>>\n", entry)));
+        assert!(uml.contains(&format!("state {} <<exitPoint>>\n", exit)));
+        assert!(uml.contains(&format!("state {} <<end>>\n", terminate)));
+        assert!(uml.contains(&format!("{} --> {}[H*]\n", choice, state_id)));
+        assert!(uml.contains(&format!("{} --> {}[H]\n", junction, state_id)));
+    }
+
+    #[test]
+    fn test_write_streams_output() {
+        struct Closed {}
+
+        impl io::Write for Closed {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let machine = ReadPlantUml::default().parse(ORDER).unwrap();
+        let writer = WritePlantUml::default();
+        let mut buffer: Vec<u8> = Default::default();
+        writer.write(&machine, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            writer.stringify(&machine).unwrap()
+        );
+
+        match writer.write(&machine, &mut Closed {}) {
+            Err(Error(ErrorKind::Io(e), _)) => assert_eq!(e.kind(), io::ErrorKind::BrokenPipe),
+            result => panic!("expected an I/O error, not {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        let machine = StateMachine::default();
+        let region: &Region = machine.default_region().unwrap();
+        let mut state = State::within(region.id().clone());
+        state.set_label("Open");
+        let state_id = state.id().clone();
+        region.add_state(state);
+
+        let writer = WritePlantUml::with_options(PlantUmlOptions {
+            direction: Some(Direction::LeftToRight),
+            hide_empty_description: true,
+            show_ids: true,
+            theme: Some("plain".to_string()),
+        });
+        let uml = writer.stringify(&machine).unwrap();
+        assert!(uml.starts_with(
+            "@startuml\n!theme plain\nleft to right direction\nhide empty description\n"
+        ));
+        assert!(uml.contains(&format!("state \"Open\\n{}\" as {}\n", state_id, state_id)));
+        assert!(ReadPlantUml::default().parse(&uml).is_ok());
+
+        let writer = WritePlantUml::with_options(PlantUmlOptions {
+            direction: Some(Direction::RightToLeft),
+            ..Default::default()
+        });
+        assert!(writer.stringify(&machine).is_err());
+    }
+}
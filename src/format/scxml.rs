@@ -1,35 +1,1308 @@
 /*!
-Writes a state machine out in the [State Chart XML (SCXML): State Machine Notation for Control
-Abstraction](https://www.w3.org/TR/scxml/) format. Does not support parsing SCXML.
+Reads and writes a state machine in the [State Chart XML (SCXML): State Machine Notation for
+Control Abstraction](https://www.w3.org/TR/scxml/) format.
+
+The SCXML elements are mapped onto the model as follows, by both the reader and writer.
+
+* `<scxml>` is the machine itself, with its `name` as the machine's label.
+* `<state>` is a state, composite if it has child states, labeled with its `id`.
+* `<parallel>` is an orthogonal state, each child state is placed in a region of its own.
+* `<final>` is a final state.
+* `<initial>`, or the `initial` attribute, is an initial pseudostate with a transition to the
+  default child state. Where neither are present the first child state in document order is
+  the default.
+* `<history>` is a deep or shallow history pseudostate.
+* `<transition>` is a transition within the region of its source, each event descriptor in
+  `event` becomes a signal trigger (or any-receive for `*`), and `cond` becomes an
+  [`OpaqueConstraint`](../../definition/opaque/struct.OpaqueConstraint.html) guard in the
+  language named by the document's `datamodel`. A transition with no `target` is internal, one
+  with `type="internal"` is local.
+
+* `<onentry>` and `<onexit>` are a state's entry and exit behaviors, and the executable content
+  of a `<transition>` is its effect. In each case the content is kept, unchanged, as the body of an
+  [`OpaqueBehavior`](../../definition/opaque/struct.OpaqueBehavior.html) in the language
+  `SCXML_CONTENT_LANGUAGE`. Where the reader is given
+  [`Bindings`](../../definition/opaque/struct.Bindings.html) any names the content refers to are
+  bound: the `event` of `<raise>` and `<send>`, the `label` of `<log>`, the `location` of
+  `<assign>`, and the `src`, or otherwise the text, of `<script>`.
+* `<invoke>` is an [`Invoke`](../../definition/types/struct.Invoke.html) of its state, with the
+  `type`, by default `SCXML_INVOKE_TYPE`, as its kind, `src` as its source, and `id` as its
+  label, so that its done and error events are `done.invoke.<id>` and `error.invoke.<id>`. Each
+  `<param>` with a `location`, or an `expr` that is a location, and each location in `namelist`,
+  is a parameter taken from the context; a location's `.` separated names are the names of a
+  field path. The service is started by the instance's handler for its kind, such as those of
+  [`execution::services`](../../execution/services/index.html).
+
+Transitions with more than one target, history within `<parallel>`, and the `typeexpr` and
+`srcexpr` of `<invoke>` are not supported. Data models, and the `<content>` and `<finalize>` of
+`<invoke>`, are ignored.
+
+The layout of a state, as recorded by the Qt SCXML editor in the `geometry` of a `<qt:editorinfo>`
+child element, is kept as its [`Layout`](../layout/struct.Layout.html), and a state with a layout
+is written with such an element.
+
+When writing, a vertex's label is used as its `id` where it is unique within the machine, and
+otherwise its model ID is used; in either case characters not allowed in an XML name are
+replaced. A region of an orthogonal state holding anything other than a single state is written
+as a `<state>` wrapping its contents, as is a machine with more than one region. Pseudostates
+other than initial and history, connection point references, time and change triggers, payload
+predicates, and guards or behaviors without a label cannot be written and result in an error.
+Behaviors other than SCXML content are written as a `<script>` with their label as its text, and
+do-activities are not written.
 
 # Example
 
-TBD
+```rust
+use uml_state_machine::definition::types::{HasRegions, Labeled};
+use uml_state_machine::format::scxml::{ReadScxml, WriteScxml};
+use uml_state_machine::format::{Parse, Stringify};
+
+let machine = ReadScxml::default()
+    .parse(
+        r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" name="door" initial="closed">
+             <state id="closed"><transition event="open" target="opened"/></state>
+             <state id="opened"><transition event="close" target="closed"/></state>
+           </scxml>"#,
+    )
+    .unwrap();
+
+assert_eq!(machine.label(), &Some("door".to_string()));
+assert_eq!(machine.default_region().unwrap().transitions().len(), 3);
+
+let scxml = WriteScxml::default().stringify(&machine).unwrap();
+assert!(scxml.contains(r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" name="door" initial="closed">"#));
+assert!(scxml.contains(r#"<transition event="open" target="opened"/>"#));
+```
 
 */
 
-// use ...
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::slice::Iter;
+
+use roxmltree::{Document, Node};
+
+use crate::core::context::FieldPath;
+use crate::core::{FieldValue, ID};
+use crate::definition::guards::recognize_else;
+use crate::definition::opaque::{Bindings, OpaqueBehavior, OpaqueConstraint};
+use crate::definition::types::{
+    Behavior, Constraint, Event, HasRegions, Identified, Invoke, Labeled, PseudoState,
+    PseudoStateKind, Region, State, StateMachine, Transition, TransitionKind, Trigger,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::layout::{layout, set_layout, Layout};
+use crate::format::names::unique_names;
+use crate::format::{Parse, Write};
+use std::io;
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// Implements Parse for SCXML.
+///
+#[derive(Debug, Default)]
+pub struct ReadScxml {
+    bindings: Bindings,
+}
+
+///
+/// Implements Write, and so Stringify, for SCXML.
+///
+#[derive(Debug, Default)]
+pub struct WriteScxml {}
+
+///
+/// The XML namespace of SCXML elements.
+///
+pub const SCXML_NAMESPACE: &str = "http://www.w3.org/2005/07/scxml";
+
+///
+/// The language of opaque behaviors whose body is SCXML executable content.
+///
+pub const SCXML_CONTENT_LANGUAGE: &str = "scxml";
+
+///
+/// The XML namespace of the Qt SCXML editor's extensions, including the layout of states.
+///
+pub const QT_SCXML_NAMESPACE: &str = "http://www.qt.io/2015/02/scxml-ext";
+
+///
+/// The `type` of an `<invoke>` without one, another SCXML machine.
+///
+pub const SCXML_INVOKE_TYPE: &str = "http://www.w3.org/TR/scxml/";
+
 // ------------------------------------------------------------------------------------------------
-// Public Functions
+// Implementations
 // ------------------------------------------------------------------------------------------------
 
+impl ReadScxml {
+    pub fn with_bindings(bindings: Bindings) -> Self {
+        Self { bindings }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+}
+
+impl Parse for ReadScxml {
+    type Error = Error;
+
+    fn parse(&self, string: &str) -> Result<StateMachine> {
+        let document = Document::parse(string)
+            .map_err(|e| Error::from(ErrorKind::FormatParse(e.to_string())))?;
+        let root = document.root_element();
+        if !is_scxml_element(&root) || root.tag_name().name() != "scxml" {
+            return Err(parse_error("expected an <scxml> root element"));
+        }
+
+        let machine = match root.attribute("name") {
+            None => StateMachine::default(),
+            Some(name) => StateMachine::labeled(name),
+        };
+        let mut reader = Reader {
+            language: root.attribute("datamodel"),
+            bindings: &self.bindings,
+            vertices: Default::default(),
+            transitions: Default::default(),
+        };
+        reader.read_region(&root, machine.default_region().unwrap())?;
+        reader.add_transitions(&machine)?;
+        Ok(machine)
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
-// Implementations
+
+impl<'a> Reader<'a, '_> {
+    fn read_region(&mut self, parent: &Node<'a, '_>, region: &Region) -> Result<()> {
+        let mut default_state: Option<ID> = None;
+        let mut has_initial = false;
+        for child in scxml_children(parent) {
+            match child.tag_name().name() {
+                "state" | "parallel" | "final" => {
+                    let state = self.read_state(&child, region.id())?;
+                    if default_state.is_none() {
+                        default_state = Some(state.id().clone());
+                    }
+                    region.add_state(state);
+                }
+                "history" => {
+                    let kind = match child.attribute("type") {
+                        Some("deep") => PseudoStateKind::DeepHistory,
+                        _ => PseudoStateKind::ShallowHistory,
+                    };
+                    let pseudo_state = self.read_pseudo_state(&child, region.id(), kind)?;
+                    region.add_pseudo_state(pseudo_state);
+                }
+                "initial" => {
+                    has_initial = true;
+                    let pseudo_state =
+                        self.read_pseudo_state(&child, region.id(), PseudoStateKind::Initial)?;
+                    region.add_pseudo_state(pseudo_state);
+                }
+                _ => {}
+            }
+        }
+
+        if !has_initial {
+            if let Some(targets) = parent.attribute("initial") {
+                let initial = region.new_initial_state();
+                self.add_pending(
+                    region.id(),
+                    PendingTransition {
+                        source: initial,
+                        targets: Some(targets),
+                        events: None,
+                        cond: None,
+                        kind: None,
+                        effect: None,
+                    },
+                );
+            } else if let Some(default_state) = default_state {
+                let initial = region.new_initial_state();
+                let mut transition =
+                    Transition::within(initial, default_state, region.id().clone());
+                transition.set_kind(TransitionKind::External);
+                region.add_transition(transition);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_state(&mut self, node: &Node<'a, '_>, container: &ID) -> Result<State> {
+        let mut state = State::within(container.clone());
+        if let Some(id) = node.attribute("id") {
+            state.set_label(id);
+            self.add_vertex(id, state.id())?;
+        }
+        match node.tag_name().name() {
+            "final" => state.set_final(true),
+            "parallel" => {
+                for child in scxml_children(node) {
+                    match child.tag_name().name() {
+                        "state" | "parallel" => {
+                            let region = Region::within_state(state.id().clone());
+                            let child_state = self.read_state(&child, region.id())?;
+                            let child_id = child_state.id().clone();
+                            region.add_state(child_state);
+                            let initial = region.new_initial_state();
+                            let mut transition =
+                                Transition::within(initial, child_id, region.id().clone());
+                            transition.set_kind(TransitionKind::External);
+                            region.add_transition(transition);
+                            state.add_region(region);
+                        }
+                        "history" => {
+                            return Err(unsupported_error("<history> within <parallel>"));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {
+                if scxml_children(node).any(|child| is_vertex_element(&child)) {
+                    let region = Region::within_state(state.id().clone());
+                    self.read_region(node, &region)?;
+                    state.add_region(region);
+                }
+            }
+        }
+        if let Some(entry) = self.read_content(scxml_children(node), "onentry") {
+            state.set_entry(entry);
+        }
+        if let Some(exit) = self.read_content(scxml_children(node), "onexit") {
+            state.set_exit(exit);
+        }
+        for invoke in scxml_children(node).filter(|child| child.tag_name().name() == "invoke") {
+            state.add_invoke(read_invoke(&invoke)?);
+        }
+        if let Some(layout) = editor_layout(node) {
+            set_layout(&mut state, layout);
+        }
+        self.read_transitions(node, container, state.id());
+        Ok(state)
+    }
+
+    fn read_pseudo_state(
+        &mut self,
+        node: &Node<'a, '_>,
+        container: &ID,
+        kind: PseudoStateKind,
+    ) -> Result<PseudoState> {
+        let mut pseudo_state = PseudoState::within(container.clone(), kind);
+        if let Some(id) = node.attribute("id") {
+            pseudo_state.set_label(id);
+            self.add_vertex(id, pseudo_state.id())?;
+        }
+        self.read_transitions(node, container, pseudo_state.id());
+        Ok(pseudo_state)
+    }
+
+    fn read_transitions(&mut self, node: &Node<'a, '_>, container: &ID, source: &ID) {
+        for child in scxml_children(node).filter(|child| child.tag_name().name() == "transition") {
+            let effect = self.read_content(std::iter::once(child), "transition");
+            self.add_pending(
+                container,
+                PendingTransition {
+                    source: source.clone(),
+                    targets: child.attribute("target"),
+                    events: child.attribute("event"),
+                    cond: child.attribute("cond"),
+                    kind: child.attribute("type"),
+                    effect,
+                },
+            );
+        }
+    }
+
+    ///
+    /// The executable content of all the `element_name` elements in `elements`, as a single
+    /// behavior.
+    ///
+    fn read_content<'n, 'input: 'n>(
+        &self,
+        elements: impl Iterator<Item = Node<'n, 'input>>,
+        element_name: &str,
+    ) -> Option<Box<dyn Behavior>> {
+        let mut bodies: Vec<&str> = Default::default();
+        let mut names: Vec<String> = Default::default();
+        for element in elements.filter(|element| element.tag_name().name() == element_name) {
+            if let (Some(first), Some(last)) = (element.first_child(), element.last_child()) {
+                let body =
+                    element.document().input_text()[first.range().start..last.range().end].trim();
+                if !body.is_empty() {
+                    bodies.push(body);
+                }
+            }
+            names.extend(
+                element
+                    .descendants()
+                    .filter(is_scxml_element)
+                    .filter_map(|node| content_name(&node)),
+            );
+        }
+        if bodies.is_empty() {
+            None
+        } else {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            Some(self.bindings.resolve(
+                OpaqueBehavior::new(&bodies.join("\n"), Some(SCXML_CONTENT_LANGUAGE)),
+                &names,
+            ))
+        }
+    }
+
+    fn add_vertex(&mut self, scxml_id: &str, id: &ID) -> Result<()> {
+        if self
+            .vertices
+            .insert(scxml_id.to_string(), id.clone())
+            .is_some()
+        {
+            Err(parse_error(&format!("duplicate id `{}`", scxml_id)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn add_pending(&mut self, container: &ID, transition: PendingTransition<'a>) {
+        self.transitions
+            .entry(container.clone())
+            .or_default()
+            .push(transition);
+    }
+
+    fn add_transitions(&mut self, machine: &StateMachine) -> Result<()> {
+        let mut transitions: HashMap<ID, Vec<Transition>> = Default::default();
+        for (container, pending) in std::mem::take(&mut self.transitions) {
+            let resolved = pending
+                .into_iter()
+                .map(|transition| self.transition(transition, &container))
+                .collect::<Result<Vec<Transition>>>()?;
+            let _ = transitions.insert(container, resolved);
+        }
+        machine.for_each_region(&mut |region| {
+            for transition in transitions.remove(region.id()).unwrap_or_default() {
+                region.add_transition(transition);
+            }
+        });
+        Ok(())
+    }
+
+    fn transition(&self, pending: PendingTransition<'a>, container: &ID) -> Result<Transition> {
+        let targets: Vec<&str> = pending
+            .targets
+            .map(|targets| targets.split_whitespace().collect())
+            .unwrap_or_default();
+        let (target, kind) = match targets.as_slice() {
+            [] => (pending.source.clone(), TransitionKind::Internal),
+            [target] => match self.vertices.get(*target) {
+                None => {
+                    return Err(parse_error(&format!(
+                        "unknown transition target `{}`",
+                        target
+                    )))
+                }
+                Some(target) if pending.kind == Some("internal") => {
+                    (target.clone(), TransitionKind::Local)
+                }
+                Some(target) => (target.clone(), TransitionKind::External),
+            },
+            _ => return Err(unsupported_error("transitions with multiple targets")),
+        };
+
+        let mut transition = Transition::within(pending.source, target, container.clone());
+        transition.set_kind(kind);
+        for event in pending.events.unwrap_or_default().split_whitespace() {
+            transition.add_trigger(if event == "*" {
+                Trigger::any()
+            } else {
+                Trigger::signal(event)
+            });
+        }
+        if let Some(cond) = pending.cond {
+            transition.set_guard(recognize_else(Box::new(OpaqueConstraint::new(
+                cond,
+                self.language,
+            ))));
+        }
+        if let Some(effect) = pending.effect {
+            transition.set_effect(effect);
+        }
+        Ok(transition)
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 
+impl Write for WriteScxml {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let mut root = XmlElement::new("scxml");
+        root.set("xmlns", SCXML_NAMESPACE);
+        root.set("version", "1.0");
+        if let Some(label) = machine.label() {
+            root.set("name", label);
+        }
+        let writer = Writer {
+            ids: unique_names(machine, xml_name)?,
+            is_orthogonal: machine.regions().len() > 1,
+            elements: RefCell::new(vec![root]),
+            parents: Default::default(),
+            vertices: Default::default(),
+            initials: Default::default(),
+            invokes: machine
+                .find_vertices(|vertex| {
+                    vertex
+                        .as_state()
+                        .is_some_and(|state| state.invokes().len() > 0)
+                })
+                .iter()
+                .filter_map(|vertex| vertex.as_state())
+                .map(|state| (state.id().clone(), state.invokes().cloned().collect()))
+                .collect(),
+            current_state: Default::default(),
+            error: Default::default(),
+        };
+        visit_state_machine(machine, &writer)?;
+        if let Some(error) = writer.error.into_inner() {
+            return Err(error);
+        }
+
+        writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        write_element(&writer.elements.into_inner(), 0, 0, w)?;
+        Ok(w.flush()?)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachineVisitor for Writer {
+    fn enter_state_machine(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        _label: &Option<String>,
+        _machine_states: Iter<'_, ID>,
+        _connection_points: Iter<'_, PseudoState>,
+    ) {
+        self.parents.borrow_mut().push(0);
+        if self.is_orthogonal {
+            let mut parallel = XmlElement::new("parallel");
+            parallel.set("id", &self.ids[id]);
+            let parallel = self.add_child(parallel);
+            self.parents.borrow_mut().push(parallel);
+        }
+    }
+
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        exit: &Option<Box<dyn Behavior>>,
+        is_final: bool,
+    ) {
+        let mut element = XmlElement::new(if is_final {
+            "final"
+        } else if region_count > 1 {
+            "parallel"
+        } else {
+            "state"
+        });
+        element.set("id", &self.ids[id]);
+        let element = self.add_child(element);
+        let _ = self.vertices.borrow_mut().insert(id.clone(), element);
+        self.parents.borrow_mut().push(element);
+        let _ = self.current_state.borrow_mut().replace(element);
+        for (name, behavior) in [("onentry", entry), ("onexit", exit)] {
+            if let Some(behavior) = behavior {
+                let mut content = XmlElement::new(name);
+                match executable_content(behavior.as_ref()) {
+                    Ok(body) => content.content = Some(body),
+                    Err(e) => return self.fail(e),
+                }
+                let _ = self.add_child(content);
+            }
+        }
+        for invoke in self.invokes.get(id).into_iter().flatten() {
+            self.add_invoke(invoke);
+        }
+    }
+
+    fn exit_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        _region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        let _ = self.current_state.borrow_mut().take();
+        let _ = self.parents.borrow_mut().pop();
+    }
+
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, _label: &Option<String>, _: bool) {
+        let _ = self.current_state.borrow_mut().take();
+        let parent = self.parent();
+        if self.elements.borrow()[parent].name == "parallel" {
+            let mut wrapper = XmlElement::new("state");
+            wrapper.set("id", &self.ids[id]);
+            wrapper.is_region = true;
+            let wrapper = self.add_child(wrapper);
+            self.parents.borrow_mut().push(wrapper);
+        } else {
+            self.parents.borrow_mut().push(parent);
+        }
+    }
+
+    fn exit_region(&self, _resolver: &Resolver<'_>, _id: &ID, _label: &Option<String>, _: bool) {
+        let _ = self.parents.borrow_mut().pop();
+    }
+
+    fn connection_point_reference(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _entry: Iter<'_, ID>,
+        _exit: Iter<'_, ID>,
+        _state: &Option<ID>,
+    ) {
+        self.fail(unsupported_error("connection point references"))
+    }
+
+    fn pseudo_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        _label: &Option<String>,
+        kind: &PseudoStateKind,
+    ) {
+        let history_type = match kind {
+            PseudoStateKind::Initial => {
+                let _ = self.initials.borrow_mut().insert(id.clone(), self.parent());
+                return;
+            }
+            PseudoStateKind::DeepHistory => "deep",
+            PseudoStateKind::ShallowHistory => "shallow",
+            _ => {
+                return self.fail(unsupported_error(&format!("{:?} pseudostates", kind)));
+            }
+        };
+        let mut element = XmlElement::new("history");
+        element.set("id", &self.ids[id]);
+        element.set("type", history_type);
+        let element = self.add_child(element);
+        let _ = self.vertices.borrow_mut().insert(id.clone(), element);
+    }
+
+    fn transition(
+        &self,
+        _resolver: &Resolver<'_>,
+        _label: &Option<String>,
+        kind: TransitionKind,
+        source: ID,
+        target: ID,
+        triggers: Iter<'_, Trigger>,
+        guard: &Option<Box<dyn Constraint>>,
+        effect: &Option<Box<dyn Behavior>>,
+    ) {
+        let _ = self.current_state.borrow_mut().take();
+        let target_id = match self.ids.get(&target) {
+            None => return self.fail(ErrorKind::TransitionTargetState.into()),
+            Some(target_id) => target_id,
+        };
+        if let Some(parent) = self.initials.borrow().get(&source) {
+            self.elements.borrow_mut()[*parent].set("initial", target_id);
+            return;
+        }
+
+        let mut element = XmlElement::new("transition");
+        let mut events: Vec<String> = Default::default();
+        for trigger in triggers {
+            if trigger.payload_predicates().next().is_some() {
+                return self.fail(unsupported_error("trigger payload predicates"));
+            }
+            events.push(match trigger.event() {
+                Event::Signal(event) => event.signal().clone(),
+                Event::Call(event) => event.operation().clone(),
+                Event::AnyReceive => "*".to_string(),
+                event => return self.fail(unsupported_error(&format!("`{}` triggers", event))),
+            });
+        }
+        if !events.is_empty() {
+            element.set("event", &events.join(" "));
+        }
+        if let Some(guard) = guard {
+            match guard.label() {
+                None => return self.fail(unsupported_error("guards without a label")),
+                Some(label) => element.set("cond", label),
+            }
+        }
+        if let Some(effect) = effect {
+            match executable_content(effect.as_ref()) {
+                Ok(body) => element.content = Some(body),
+                Err(e) => return self.fail(e),
+            }
+        }
+        match kind {
+            TransitionKind::Internal if source == target => {}
+            TransitionKind::Local => {
+                element.set("target", target_id);
+                element.set("type", "internal");
+            }
+            _ => element.set("target", target_id),
+        }
+
+        let source_element = self.vertices.borrow().get(&source).cloned();
+        match source_element {
+            None => self.fail(unsupported_error("transitions from outside their region")),
+            Some(source_element) => {
+                let element = self.add_element(element);
+                self.elements.borrow_mut()[source_element]
+                    .children
+                    .push(element);
+            }
+        }
+    }
+
+    fn metadata(&self, _resolver: &Resolver<'_>, metadata: &HashMap<String, FieldValue>) {
+        let state = self.current_state.borrow_mut().take();
+        if let (Some(state), Some(layout)) = (state, layout(metadata)) {
+            let mut editor_info = XmlElement::new("qt:editorinfo");
+            editor_info.set("geometry", &geometry(&layout));
+            let editor_info = self.add_element(editor_info);
+            let mut elements = self.elements.borrow_mut();
+            elements[state].children.push(editor_info);
+            if !elements[0]
+                .attributes
+                .iter()
+                .any(|(name, _)| *name == "xmlns:qt")
+            {
+                elements[0].set("xmlns:qt", QT_SCXML_NAMESPACE);
+            }
+        }
+    }
+}
+
+impl Writer {
+    fn parent(&self) -> usize {
+        *self.parents.borrow().last().unwrap()
+    }
+
+    fn add_element(&self, element: XmlElement) -> usize {
+        let mut elements = self.elements.borrow_mut();
+        elements.push(element);
+        elements.len() - 1
+    }
+
+    fn add_invoke(&self, invoke: &Invoke) {
+        let mut element = XmlElement::new("invoke");
+        element.set("type", invoke.kind());
+        if let Some(source) = invoke.source() {
+            element.set("src", source);
+        }
+        if let Some(label) = invoke.label() {
+            element.set("id", label);
+        }
+        let element = self.add_child(element);
+        for (name, path) in invoke.params() {
+            let mut param = XmlElement::new("param");
+            param.set("name", name);
+            param.set("location", &path.to_string().replace('/', "."));
+            let param = self.add_element(param);
+            self.elements.borrow_mut()[element].children.push(param);
+        }
+    }
+
+    fn add_child(&self, element: XmlElement) -> usize {
+        let parent = self.parent();
+        let element = self.add_element(element);
+        self.elements.borrow_mut()[parent].children.push(element);
+        element
+    }
+
+    fn fail(&self, error: Error) {
+        let mut current = self.error.borrow_mut();
+        if current.is_none() {
+            *current = Some(error);
+        }
+    }
+}
+
+impl XmlElement {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            attributes: Default::default(),
+            children: Default::default(),
+            content: None,
+            is_region: false,
+        }
+    }
+
+    fn set(&mut self, name: &'static str, value: &str) {
+        self.attributes.push((name, value.to_string()))
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+struct Reader<'a, 'b> {
+    language: Option<&'a str>,
+    bindings: &'b Bindings,
+    /// SCXML IDs to the model ID of the corresponding vertex.
+    vertices: HashMap<String, ID>,
+    /// Transitions to add once all vertices are known, by the ID of their containing region.
+    transitions: HashMap<ID, Vec<PendingTransition<'a>>>,
+}
+
+struct PendingTransition<'a> {
+    source: ID,
+    targets: Option<&'a str>,
+    events: Option<&'a str>,
+    cond: Option<&'a str>,
+    kind: Option<&'a str>,
+    effect: Option<Box<dyn Behavior>>,
+}
+
+struct Writer {
+    ids: HashMap<ID, String>,
+    is_orthogonal: bool,
+    /// All elements written, the document element is at index 0.
+    elements: RefCell<Vec<XmlElement>>,
+    parents: RefCell<Vec<usize>>,
+    vertices: RefCell<HashMap<ID, usize>>,
+    /// Initial pseudostates to the element that will take the `initial` attribute.
+    initials: RefCell<HashMap<ID, usize>>,
+    /// The services invoked by each state that invokes any.
+    invokes: HashMap<ID, Vec<Invoke>>,
+    /// The element of the state just entered, until its metadata, or a region, is visited.
+    current_state: RefCell<Option<usize>>,
+    error: RefCell<Option<Error>>,
+}
+
+struct XmlElement {
+    name: &'static str,
+    attributes: Vec<(&'static str, String)>,
+    children: Vec<usize>,
+    /// Executable content, written unescaped before any children.
+    content: Option<String>,
+    /// A `<state>` wrapping the contents of a region in an orthogonal state.
+    is_region: bool,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+fn is_scxml_element(node: &Node<'_, '_>) -> bool {
+    node.is_element()
+        && match node.tag_name().namespace() {
+            None => true,
+            Some(namespace) => namespace == SCXML_NAMESPACE,
+        }
+}
+
+fn is_vertex_element(node: &Node<'_, '_>) -> bool {
+    matches!(
+        node.tag_name().name(),
+        "state" | "parallel" | "final" | "history" | "initial"
+    )
+}
+
+fn scxml_children<'a, 'input>(node: &Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(is_scxml_element)
+}
+
+///
+/// The layout in the `geometry` of the Qt editor's `<qt:editorinfo>` child of `node`, either the
+/// position of an item and its bounding rectangle relative to that position, `x;y;left;top;w;h`,
+/// or the position alone, `x;y`.
+///
+fn editor_layout(node: &Node<'_, '_>) -> Option<Layout> {
+    let geometry = node
+        .children()
+        .find(|child| {
+            child.tag_name().namespace() == Some(QT_SCXML_NAMESPACE)
+                && child.tag_name().name() == "editorinfo"
+        })?
+        .attribute("geometry")?;
+    let values: Option<Vec<f64>> = geometry
+        .split(';')
+        .map(|value| value.trim().parse::<f64>().ok())
+        .collect();
+    match values?.as_slice() {
+        [x, y] => Some(Layout::at(*x, *y)),
+        [x, y, left, top, width, height] => {
+            Some(Layout::at(x + left, y + top).with_size(*width, *height))
+        }
+        _ => None,
+    }
+}
+
+fn geometry(layout: &Layout) -> String {
+    match (layout.width, layout.height) {
+        (Some(width), Some(height)) => {
+            format!("{};{};0;0;{};{}", layout.x, layout.y, width, height)
+        }
+        _ => format!("{};{}", layout.x, layout.y),
+    }
+}
+
+fn read_invoke(node: &Node<'_, '_>) -> Result<Invoke> {
+    for name in ["typeexpr", "srcexpr"].iter() {
+        if node.has_attribute(*name) {
+            return Err(unsupported_error(&format!("the `{}` of <invoke>", name)));
+        }
+    }
+    let mut invoke = Invoke::new(node.attribute("type").unwrap_or(SCXML_INVOKE_TYPE));
+    if let Some(id) = node.attribute("id") {
+        invoke.set_label(id);
+    }
+    if let Some(source) = node.attribute("src") {
+        invoke.set_source(source);
+    }
+    for location in node
+        .attribute("namelist")
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        invoke.add_param(location, location_path(location)?);
+    }
+    for param in scxml_children(node).filter(|child| child.tag_name().name() == "param") {
+        let name = param
+            .attribute("name")
+            .ok_or_else(|| parse_error("<param> must have a name"))?;
+        let location = param
+            .attribute("location")
+            .or_else(|| param.attribute("expr"))
+            .ok_or_else(|| parse_error("<param> must have a location or expr"))?;
+        let path = location_path(location)
+            .map_err(|_| unsupported_error(&format!("the <param> expression `{}`", location)))?;
+        invoke.add_param(name, path);
+    }
+    Ok(invoke)
+}
+
+///
+/// The field path of an SCXML location, its `.` separated names.
+///
+fn location_path(location: &str) -> Result<FieldPath> {
+    location
+        .replace('.', "/")
+        .parse::<FieldPath>()
+        .map_err(|_| parse_error(&format!("`{}` is not a location", location)))
+}
+
+fn write_element(
+    elements: &[XmlElement],
+    index: usize,
+    depth: usize,
+    w: &mut dyn io::Write,
+) -> io::Result<()> {
+    let element = &elements[index];
+    if element.is_region && element.children.len() == 1 {
+        return write_element(elements, element.children[0], depth, w);
+    }
+    let indent = "  ".repeat(depth);
+    write!(w, "{}<{}", indent, element.name)?;
+    for (name, value) in &element.attributes {
+        write!(w, " {}=\"{}\"", name, xml_escape(value))?;
+    }
+    if element.children.is_empty() && element.content.is_none() {
+        writeln!(w, "/>")
+    } else {
+        writeln!(w, ">")?;
+        if let Some(content) = &element.content {
+            writeln!(w, "{}  {}", indent, content)?;
+        }
+        for child in &element.children {
+            write_element(elements, *child, depth + 1, w)?;
+        }
+        writeln!(w, "{}</{}>", indent, element.name)
+    }
+}
+
+///
+/// The name a binding may use for an executable content element, if it has one.
+///
+fn content_name(node: &Node<'_, '_>) -> Option<String> {
+    match node.tag_name().name() {
+        "raise" | "send" => node.attribute("event").map(str::to_string),
+        "log" => node.attribute("label").map(str::to_string),
+        "assign" => node.attribute("location").map(str::to_string),
+        "script" => node
+            .attribute("src")
+            .map(str::to_string)
+            .or_else(|| node.text().map(|text| text.trim().to_string())),
+        _ => None,
+    }
+}
+
+fn executable_content(behavior: &dyn Behavior) -> Result<String> {
+    match behavior.as_opaque() {
+        Some(opaque) if opaque.language().as_deref() == Some(SCXML_CONTENT_LANGUAGE) => {
+            Ok(opaque.body().clone())
+        }
+        _ => match behavior.label() {
+            None => Err(unsupported_error("behaviors without a label")),
+            Some(label) => Ok(format!("<script>{}</script>", xml_escape(label))),
+        },
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_name(value: &str) -> String {
+    let mut name: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn parse_error(message: &str) -> Error {
+    ErrorKind::FormatParse(message.to_string()).into()
+}
+
+fn unsupported_error(feature: &str) -> Error {
+    ErrorKind::FormatUnsupported(feature.to_string()).into()
+}
+
 // ------------------------------------------------------------------------------------------------
-// Modules
+// Unit Tests
 // ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::{Event, HasMetadata, SignalEvent, Validate, Vertex};
+    use crate::format::Stringify;
+    use std::cell::RefCell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const ORDERS: &str = r#"<?xml version="1.0"?>
+<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript">
+  <state id="open">
+    <initial><transition target="cart"/></initial>
+    <state id="cart">
+      <transition event="add remove"/>
+      <transition event="checkout" cond="items.length > 0" target="pay"/>
+    </state>
+    <state id="pay">
+      <transition event="back" type="internal" target="cart"/>
+    </state>
+    <history id="resume" type="deep"><transition target="cart"/></history>
+    <transition event="*" target="done"/>
+  </state>
+  <parallel id="shipping">
+    <state id="packing"/>
+    <state id="billing"/>
+  </parallel>
+  <final id="done"/>
+</scxml>"#;
+
+    fn find_state(region: &Region, label: &str) -> Option<Arc<Vertex>> {
+        region
+            .vertices()
+            .into_iter()
+            .find(|vertex| vertex.as_state().and_then(|s| s.label().clone()) == Some(label.into()))
+    }
+
+    #[test]
+    fn test_nested_parallel_and_history() {
+        let machine = ReadScxml::default().parse(ORDERS).unwrap();
+        assert!(machine.validate().is_ok());
+
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.vertices().len(), 4);
+        assert!(find_state(region, "done")
+            .unwrap()
+            .as_state()
+            .unwrap()
+            .is_final());
+        let shipping = find_state(region, "shipping").unwrap();
+        assert!(shipping.as_state().unwrap().is_orthogonal());
+
+        let open = find_state(region, "open").unwrap();
+        let open = open.as_state().unwrap();
+        assert!(open.is_composite());
+        let inner = open.default_region().unwrap();
+        assert!(inner
+            .vertices()
+            .iter()
+            .any(|v| v.as_pseudo_state().map(|p| p.is_deep_history()) == Some(true)));
+
+        let transitions = inner.transitions();
+        assert_eq!(transitions.len(), 5);
+        let cart = find_state(inner, "cart").unwrap();
+        let from_cart: Vec<&Arc<Transition>> = transitions
+            .iter()
+            .filter(|t| &t.source() == cart.id())
+            .collect();
+        assert_eq!(from_cart.len(), 2);
+        assert!(from_cart[0].is_internal());
+        assert_eq!(from_cart[0].triggers().count(), 2);
+        assert_eq!(
+            from_cart[1].guard().as_ref().unwrap().label(),
+            &Some("items.length > 0".to_string())
+        );
+        assert!(!from_cart[1]
+            .guard()
+            .as_ref()
+            .unwrap()
+            .evaluate(cart.id(), &Trigger::signal("checkout")));
+        assert!(transitions.iter().any(|t| t.is_local()));
+
+        let event = Event::Signal(SignalEvent::new("unexpected"));
+        let from_open = region.transitions_triggered_by(open.id(), &event);
+        assert_eq!(from_open.len(), 1);
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let machine = ReadScxml::default().parse(ORDERS).unwrap();
+        let written = WriteScxml::default().stringify(&machine).unwrap();
+        assert!(written.contains(r#"<state id="open" initial="cart">"#));
+        assert!(written.contains(r#"<transition event="add remove"/>"#));
+        assert!(written.contains(r#"cond="items.length &gt; 0" target="pay"/>"#));
+        assert!(written.contains(r#"<transition event="back" target="cart" type="internal"/>"#));
+        assert!(written.contains(r#"<history id="resume" type="deep">"#));
+        assert!(written.contains(r#"<parallel id="shipping">"#));
+        assert!(written.contains(r#"<final id="done"/>"#));
+
+        let reread = ReadScxml::default().parse(&written).unwrap();
+        let rewritten = WriteScxml::default().stringify(&reread).unwrap();
+        assert_eq!(written, rewritten);
+    }
+
+    #[test]
+    fn test_invoke_round_trip() {
+        let machine = ReadScxml::default()
+            .parse(
+                r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+                     <state id="charging">
+                       <invoke type="http" src="http://payments.example.com/charge" id="charge" namelist="order.id">
+                         <param name="amount" location="order.total"/>
+                         <param name="currency" expr="currency"/>
+                         <finalize><log label="charged"/></finalize>
+                       </invoke>
+                       <transition event="done.invoke.charge" target="charged"/>
+                     </state>
+                     <state id="charged"><invoke src="receipt.scxml"/></state>
+                   </scxml>"#,
+            )
+            .unwrap();
+        let region = machine.default_region().unwrap();
+        let charging = find_state(region, "charging").unwrap();
+        let invokes: Vec<Invoke> = charging.as_state().unwrap().invokes().cloned().collect();
+        assert_eq!(invokes.len(), 1);
+        let charge = &invokes[0];
+        assert_eq!(charge.kind(), "http");
+        assert_eq!(
+            charge.source(),
+            &Some("http://payments.example.com/charge".to_string())
+        );
+        assert_eq!(charge.done_event().to_string(), "done.invoke.charge");
+        assert_eq!(
+            charge
+                .params()
+                .map(|(name, path)| format!("{}={}", name, path))
+                .collect::<Vec<String>>(),
+            vec![
+                "order.id=order/id",
+                "amount=order/total",
+                "currency=currency"
+            ]
+        );
+        let charged = find_state(region, "charged").unwrap();
+        let receipt = charged
+            .as_state()
+            .unwrap()
+            .invokes()
+            .next()
+            .unwrap()
+            .clone();
+        assert_eq!(receipt.kind(), SCXML_INVOKE_TYPE);
+        assert_eq!(receipt.label(), &None);
+
+        let written = WriteScxml::default().stringify(&machine).unwrap();
+        assert!(written.contains(
+            r#"<invoke type="http" src="http://payments.example.com/charge" id="charge">"#
+        ));
+        assert!(written.contains(r#"<param name="amount" location="order.total"/>"#));
+        let rewritten = WriteScxml::default()
+            .stringify(&ReadScxml::default().parse(&written).unwrap())
+            .unwrap();
+        assert_eq!(written, rewritten);
+
+        for unsupported in [
+            r#"<invoke typeexpr="kind"/>"#,
+            r#"<invoke><param name="total" expr="order.total * 2"/></invoke>"#,
+        ]
+        .iter()
+        {
+            let result = ReadScxml::default().parse(&format!(
+                r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0"><state id="s">{}</state></scxml>"#,
+                unsupported
+            ));
+            assert!(matches!(
+                result.map_err(|e| e.0),
+                Err(ErrorKind::FormatUnsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let machine = ReadScxml::default()
+            .parse(
+                r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" xmlns:qt="http://www.qt.io/2015/02/scxml-ext" version="1.0">
+                     <state id="closed">
+                       <qt:editorinfo geometry="100;50;-60;-25;120;50" scenegeometry="100;50;40;25;120;50"/>
+                       <transition event="open" target="opened"/>
+                     </state>
+                     <state id="opened"/>
+                   </scxml>"#,
+            )
+            .unwrap();
+        let region = machine.default_region().unwrap();
+        let closed = find_state(region, "closed").unwrap();
+        assert_eq!(
+            layout(closed.as_state().unwrap().metadata()),
+            Some(Layout::at(40.0, 25.0).with_size(120.0, 50.0))
+        );
+        let opened = find_state(region, "opened").unwrap();
+        assert_eq!(layout(opened.as_state().unwrap().metadata()), None);
+
+        let written = WriteScxml::default().stringify(&machine).unwrap();
+        assert!(written.contains(r#"xmlns:qt="http://www.qt.io/2015/02/scxml-ext""#));
+        assert!(written.contains(r#"<qt:editorinfo geometry="40;25;0;0;120;50"/>"#));
+        assert_eq!(written.matches("qt:editorinfo").count(), 1);
+
+        let reread = ReadScxml::default().parse(&written).unwrap();
+        let closed = find_state(reread.default_region().unwrap(), "closed").unwrap();
+        assert_eq!(
+            layout(closed.as_state().unwrap().metadata()),
+            Some(Layout::at(40.0, 25.0).with_size(120.0, 50.0))
+        );
+        assert_eq!(WriteScxml::default().stringify(&reread).unwrap(), written);
+    }
+
+    #[test]
+    fn test_executable_content() {
+        let raised = Arc::new(AtomicUsize::new(0));
+        let counter = raised.clone();
+        let mut bindings = Bindings::default();
+        bindings.bind("started", move |_, _, _| {
+            let _ = counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let machine = ReadScxml::with_bindings(bindings)
+            .parse(
+                r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+  <state id="idle">
+    <onentry><raise event="started"/><log label="idle" expr="'entered'"/></onentry>
+    <onexit><assign location="count" expr="count + 1"/></onexit>
+    <transition event="go" target="idle"><script>tick()</script></transition>
+  </state>
+</scxml>"#,
+            )
+            .unwrap();
+        let region = machine.default_region().unwrap();
+        let idle = find_state(region, "idle").unwrap();
+        let idle = idle.as_state().unwrap();
+
+        let entry = idle.entry().as_ref().unwrap();
+        assert_eq!(
+            entry.as_opaque().unwrap().body(),
+            r#"<raise event="started"/><log label="idle" expr="'entered'"/>"#
+        );
+        let events: RefCell<Vec<Event>> = Default::default();
+        entry.perform(idle.id(), &Trigger::any(), &events);
+        assert_eq!(raised.load(Ordering::SeqCst), 1);
+        let exit = idle.exit().as_ref().unwrap();
+        exit.perform(idle.id(), &Trigger::any(), &events);
+        assert_eq!(
+            exit.as_opaque().unwrap().language(),
+            &Some(SCXML_CONTENT_LANGUAGE.to_string())
+        );
+
+        let written = WriteScxml::default().stringify(&machine).unwrap();
+        assert!(written.contains(
+            r#"<onentry>
+      <raise event="started"/><log label="idle" expr="'entered'"/>
+    </onentry>"#
+        ));
+        assert!(written.contains(r#"<assign location="count" expr="count + 1"/>"#));
+        assert!(written.contains(
+            r#"<transition event="go" target="idle">
+      <script>tick()</script>
+    </transition>"#
+        ));
+
+        let reread = ReadScxml::default().parse(&written).unwrap();
+        let rewritten = WriteScxml::default().stringify(&reread).unwrap();
+        assert_eq!(written, rewritten);
+    }
+
+    #[test]
+    fn test_write_unsupported() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let _ = region.new_choice_state();
+        assert!(WriteScxml::default().stringify(&machine).is_err());
+    }
+
+    #[test]
+    fn test_unknown_target() {
+        let result = ReadScxml::default().parse(
+            r#"<scxml xmlns="http://www.w3.org/2005/07/scxml"><state id="a"><transition target="b"/></state></scxml>"#,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,509 @@
+/*!
+Writes a state machine in the textual format of
+[state-machine-cat](https://github.com/sverweij/state-machine-cat) (smcat). Does not support
+parsing smcat.
+
+The model is mapped onto the smcat grammar as follows.
+
+* Every vertex is named by its ID, with its label, if any, as the `label` attribute; names other
+  than simple identifiers are quoted.
+* Pseudostates and final states are given an explicit `type` attribute, rather than relying on
+  smcat's naming conventions: `initial`, `history`, `deephistory`, `choice`, `junction`, `fork`,
+  `join`, `terminate`, and `final`. Entry and exit points, and connection point references, have
+  no smcat equivalent and are an error.
+* Entry, do, and exit behaviors are written as the state's activities, and comments as `#`
+  comments before the state, which smcat renders as notes.
+* Composite states contain a nested machine in `{ ... }`. Where a state, or the machine itself,
+  has more than one region it is marked `parallel` and each region is a nested state; the machine
+  is then written as a single, top-level, parallel state.
+* Transitions are written in the block of the region that owns them, labeled in the UML form
+  `trigger, ... [guard] / effect`.
+
+# Example
+
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::smcat::WriteSmcat;
+use uml_state_machine::format::Stringify;
+
+let machine = StateMachine::labeled("light");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let mut on = State::within(region.id().clone());
+on.set_label("on");
+let on_id = on.id().clone();
+region.add_state(on);
+region.new_transition(initial.clone(), on_id.clone());
+
+let smcat = WriteSmcat::default().stringify(&machine).unwrap();
+assert!(smcat.contains(&format!("{} [type=initial]", initial)));
+assert!(smcat.contains(&format!("{} [label=\"on\"];", on_id)));
+assert!(smcat.contains(&format!("{} => {};", initial, on_id)));
+```
+
+*/
+
+use std::cell::RefCell;
+use std::io;
+use std::slice::Iter;
+
+use crate::core::ID;
+use crate::definition::types::{
+    Behavior, Constraint, HasRegions, PseudoState, PseudoStateKind, StateMachine, TransitionKind,
+    Trigger,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::{Sink, Write};
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// Implements Write, and so Stringify, for state-machine-cat.
+///
+#[derive(Debug, Default)]
+pub struct WriteSmcat {}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl Write for WriteSmcat {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let writer = Writer {
+            out: Sink::new(w),
+            blocks: RefCell::new(vec![Block::default()]),
+            region_counts: RefCell::new(vec![machine.regions().len()]),
+            error: Default::default(),
+        };
+        visit_state_machine(machine, &writer)?;
+        if let Some(error) = writer.error.take() {
+            return Err(error);
+        }
+        writer.end_vertices();
+        writer.out.finish()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachineVisitor for Writer<'_> {
+    fn enter_state_machine(
+        &self,
+        _: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _: Iter<'_, ID>,
+        _: Iter<'_, PseudoState>,
+    ) {
+        if self.is_orthogonal() {
+            self.open_vertex(&format!(
+                "{} {}",
+                name(id),
+                attributes(Some("parallel"), label)
+            ));
+        }
+    }
+
+    fn exit_state_machine(
+        &self,
+        _: &Resolver<'_>,
+        _: &ID,
+        _: &Option<String>,
+        _: Iter<'_, ID>,
+        _: Iter<'_, PseudoState>,
+    ) {
+        if self.is_orthogonal() {
+            self.close_vertex();
+        }
+    }
+
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        comments: Iter<'_, String>,
+        region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        entry: &Option<Box<dyn Behavior>>,
+        do_activity: &Option<Box<dyn Behavior>>,
+        exit: &Option<Box<dyn Behavior>>,
+        is_final: bool,
+    ) {
+        for comment in comments {
+            self.comment(comment);
+        }
+        let kind = if is_final {
+            Some("final")
+        } else if region_count > 1 {
+            Some("parallel")
+        } else {
+            None
+        };
+        let mut declaration = name(id);
+        let attributes = attributes(kind, label);
+        if !attributes.is_empty() {
+            declaration.push(' ');
+            declaration.push_str(&attributes);
+        }
+        let activities = [("entry", entry), ("do", do_activity), ("exit", exit)]
+            .iter()
+            .filter_map(|(kind, behavior)| {
+                behavior.as_ref().map(|behavior| {
+                    format!(
+                        "{}/ {}",
+                        kind,
+                        behavior.label().as_deref().unwrap_or_default()
+                    )
+                })
+            })
+            .collect::<Vec<String>>();
+        if !activities.is_empty() {
+            declaration.push_str(": ");
+            declaration.push_str(&quote_if_needed(&activities.join("\n")));
+        }
+        if region_count > 0 {
+            self.open_vertex(&declaration);
+        } else {
+            self.vertex(&declaration);
+        }
+        self.region_counts.borrow_mut().push(region_count);
+    }
+
+    fn exit_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        let _ = self.region_counts.borrow_mut().pop();
+        if region_count > 0 {
+            self.close_vertex();
+        }
+    }
+
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, label: &Option<String>, _last: bool) {
+        if self.is_orthogonal() {
+            let attributes = attributes(None, label);
+            if attributes.is_empty() {
+                self.open_vertex(&name(id));
+            } else {
+                self.open_vertex(&format!("{} {}", name(id), attributes));
+            }
+        }
+    }
+
+    fn exit_region(&self, _resolver: &Resolver<'_>, _: &ID, _label: &Option<String>, _last: bool) {
+        if self.is_orthogonal() {
+            self.close_vertex();
+        }
+    }
+
+    fn connection_point_reference(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _entry: Iter<'_, ID>,
+        _exit: Iter<'_, ID>,
+        _state: &Option<ID>,
+    ) {
+        self.fail(ErrorKind::FormatUnsupported("connection point references".to_string()).into());
+    }
+
+    fn pseudo_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        kind: &PseudoStateKind,
+    ) {
+        let kind = match kind {
+            PseudoStateKind::Initial => "initial",
+            PseudoStateKind::DeepHistory => "deephistory",
+            PseudoStateKind::ShallowHistory => "history",
+            PseudoStateKind::Join => "join",
+            PseudoStateKind::Fork => "fork",
+            PseudoStateKind::Junction => "junction",
+            PseudoStateKind::Choice => "choice",
+            PseudoStateKind::Terminate => "terminate",
+            PseudoStateKind::EntryPoint | PseudoStateKind::ExitPoint => {
+                return self.fail(
+                    ErrorKind::FormatUnsupported("entry and exit points".to_string()).into(),
+                );
+            }
+        };
+        self.vertex(&format!("{} {}", name(id), attributes(Some(kind), label)));
+    }
+
+    fn transition(
+        &self,
+        _resolver: &Resolver<'_>,
+        label: &Option<String>,
+        _kind: TransitionKind,
+        source: ID,
+        target: ID,
+        triggers: Iter<'_, Trigger>,
+        guard: &Option<Box<dyn Constraint>>,
+        effect: &Option<Box<dyn Behavior>>,
+    ) {
+        self.end_vertices();
+        let mut all_label = triggers
+            .map(|trigger| trigger.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        if let Some(guard) = guard {
+            if let Some(label) = guard.label() {
+                push_word(&mut all_label, &format!("[{}]", label));
+            }
+        }
+        if let Some(label) = label {
+            push_word(&mut all_label, label);
+        }
+        if let Some(effect) = effect {
+            if let Some(label) = effect.label() {
+                push_word(&mut all_label, &format!("/ {}", label));
+            }
+        }
+        if all_label.is_empty() {
+            self.line(&format!("{} => {};", name(&source), name(&target)));
+        } else {
+            self.line(&format!(
+                "{} => {}: {};",
+                name(&source),
+                name(&target),
+                quote_if_needed(&all_label)
+            ));
+        }
+    }
+}
+
+impl Writer<'_> {
+    fn line(&self, string: &str) {
+        self.out
+            .push_str(&"  ".repeat(self.blocks.borrow().len() - 1));
+        self.out.push_line(string);
+    }
+
+    fn comment(&self, comment: &str) {
+        self.separate();
+        for line in comment.lines() {
+            self.line(&format!("# {}", line));
+        }
+    }
+
+    /// Terminate the previous vertex in this block with `,`, the list is terminated with `;`.
+    fn separate(&self) {
+        let mut blocks = self.blocks.borrow_mut();
+        let block = blocks.last_mut().unwrap();
+        if block.pending {
+            self.out.push_line(",");
+            block.pending = false;
+        }
+    }
+
+    fn vertex(&self, declaration: &str) {
+        self.separate();
+        self.out
+            .push_str(&"  ".repeat(self.blocks.borrow().len() - 1));
+        self.out.push_str(declaration);
+        let mut blocks = self.blocks.borrow_mut();
+        let block = blocks.last_mut().unwrap();
+        block.pending = true;
+    }
+
+    fn open_vertex(&self, declaration: &str) {
+        self.vertex(&format!("{} {{", declaration));
+        self.out.push_line("");
+        self.blocks.borrow_mut().last_mut().unwrap().pending = false;
+        self.blocks.borrow_mut().push(Block::default());
+    }
+
+    fn close_vertex(&self) {
+        self.end_vertices();
+        let _ = self.blocks.borrow_mut().pop();
+        self.out
+            .push_str(&"  ".repeat(self.blocks.borrow().len() - 1));
+        self.out.push_str("}");
+        self.blocks.borrow_mut().last_mut().unwrap().pending = true;
+    }
+
+    /// Terminate the list of vertices in this block, if one is open.
+    fn end_vertices(&self) {
+        let mut blocks = self.blocks.borrow_mut();
+        let block = blocks.last_mut().unwrap();
+        if block.pending {
+            self.out.push_line(";");
+            block.pending = false;
+        }
+    }
+
+    fn is_orthogonal(&self) -> bool {
+        self.region_counts
+            .borrow()
+            .last()
+            .map(|count| *count > 1)
+            .unwrap_or_default()
+    }
+
+    fn fail(&self, error: Error) {
+        let mut current = self.error.borrow_mut();
+        if current.is_none() {
+            *current = Some(error);
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct Writer<'w> {
+    out: Sink<'w>,
+    /// The machine, and each nested `{ ... }` block, innermost last.
+    blocks: RefCell<Vec<Block>>,
+    /// The number of regions of each enclosing state, and the machine, innermost last.
+    region_counts: RefCell<Vec<usize>>,
+    error: RefCell<Option<Error>>,
+}
+
+#[derive(Default)]
+struct Block {
+    /// A vertex has been written but not yet terminated.
+    pending: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+fn name(id: &ID) -> String {
+    quote_if_needed(&id.to_string())
+}
+
+fn attributes(kind: Option<&str>, label: &Option<String>) -> String {
+    let mut attributes: Vec<String> = Default::default();
+    if let Some(kind) = kind {
+        attributes.push(format!("type={}", kind));
+    }
+    if let Some(label) = label {
+        attributes.push(format!("label={}", quote(label)));
+    }
+    if attributes.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", attributes.join(" "))
+    }
+}
+
+fn quote_if_needed(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn push_word(label: &mut String, word: &str) {
+    if !label.is_empty() {
+        label.push(' ');
+    }
+    label.push_str(word);
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::opaque::OpaqueBehavior;
+    use crate::definition::types::{Identified, Labeled, Region, State, Transition};
+    use crate::format::Stringify;
+
+    #[test]
+    fn test_nesting_and_pseudo_states() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut player = State::within(region.id().clone());
+        player.set_label("player");
+        player.add_comment("Plays audio");
+        player.set_entry(Box::new(OpaqueBehavior::new("load", None)));
+        let mut audio = Region::within_state(player.id().clone());
+        audio.set_label("audio");
+        let inner_initial = audio.new_initial_state();
+        let playing = audio.new_simple_state();
+        audio.new_transition(inner_initial.clone(), playing.clone());
+        player.add_region(audio);
+        let _ = player.new_region();
+        let player_id = player.id().clone();
+        region.add_state(player);
+        let choice = region.new_choice_state();
+        let done = region.new_final_state();
+
+        region.new_transition(initial.clone(), player_id.clone());
+        let mut transition =
+            Transition::within(player_id.clone(), choice.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("play"));
+        region.add_transition(transition);
+        region.new_transition(choice.clone(), done.clone());
+
+        let smcat = WriteSmcat::default().stringify(&machine).unwrap();
+        let expected_head = format!(
+            "{} [type=initial],\n# Plays audio\n{} [type=parallel label=\"player\"]: \"entry/ load\" {{\n  ",
+            initial, player_id
+        );
+        assert!(smcat.starts_with(&expected_head), "{}", smcat);
+        assert!(smcat.contains("[label=\"audio\"] {\n"));
+        assert!(smcat.contains(&format!(
+            "    {} [type=initial],\n    {};\n    {} => {};\n  }},\n",
+            inner_initial, playing, inner_initial, playing
+        )));
+        assert!(smcat.contains(&format!(
+            "}},\n{} [type=choice],\n{} [type=final];\n",
+            choice, done
+        )));
+        assert!(smcat.ends_with(&format!(
+            "{} => {};\n{} => {}: play;\n{} => {};\n",
+            initial, player_id, player_id, choice, choice, done
+        )));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let _ = region.new_entry_point();
+        assert!(WriteSmcat::default().stringify(&machine).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+/*!
+Writes a state machine as a state-transition table, in either CSV or Markdown.
+
+The table has a row for each state, in any region, and for each pseudostate that is the source
+of a transition, and a column for each distinct trigger, in the order first used. Transitions
+without a trigger, such as those from initial pseudostates or completion transitions, are listed
+in a final `(completion)` column. Each cell lists the targets of the transitions from the row's
+vertex on the column's trigger, with any guard label in brackets, separated by `; `. Vertices are
+named by their label, or their ID where they have none.
+
+# Example
+
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::table::{TableStyle, WriteTable};
+use uml_state_machine::format::Stringify;
+
+let machine = StateMachine::labeled("door");
+let region = machine.default_region().unwrap();
+let mut closed = State::within(region.id().clone());
+closed.set_label("closed");
+let closed_id = closed.id().clone();
+region.add_state(closed);
+let mut open = State::within(region.id().clone());
+open.set_label("open");
+let open_id = open.id().clone();
+region.add_state(open);
+let mut transition = Transition::within(closed_id, open_id, region.id().clone());
+transition.add_trigger(Trigger::signal("push"));
+region.add_transition(transition);
+
+let table = WriteTable::for_style(TableStyle::Markdown)
+    .stringify(&machine)
+    .unwrap();
+assert_eq!(
+    table,
+    "| State | push |\n| --- | --- |\n| closed | open |\n| open |  |\n"
+);
+```
+
+*/
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::core::ID;
+use crate::definition::types::{Identified, Labeled, StateMachine, Validate, Vertex};
+use crate::error::{Error, Result};
+use crate::format::Write;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The textual form of the table written by `WriteTable`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Comma-separated values, as described in [RFC 4180](https://tools.ietf.org/html/rfc4180).
+    #[default]
+    Csv,
+    /// A GitHub-flavored Markdown table.
+    Markdown,
+}
+
+///
+/// Implements Write, and so Stringify, for state-transition tables.
+///
+#[derive(Debug, Default)]
+pub struct WriteTable {
+    style: TableStyle,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+impl WriteTable {
+    pub fn for_style(style: TableStyle) -> Self {
+        Self { style }
+    }
+
+    pub fn style(&self) -> TableStyle {
+        self.style
+    }
+}
+
+impl Write for WriteTable {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        machine.validate()?;
+        let table = Table::from(machine);
+        let mut header = vec!["State".to_string()];
+        header.extend(table.columns.iter().map(|column| match column {
+            Some(trigger) => trigger.clone(),
+            None => COMPLETION_COLUMN.to_string(),
+        }));
+        self.write_row(w, &header)?;
+        if self.style == TableStyle::Markdown {
+            self.write_row(w, &vec!["---".to_string(); header.len()])?;
+        }
+        for row in &table.rows {
+            let mut cells = vec![table.names[&row.vertex].clone()];
+            cells.extend(table.columns.iter().map(|column| {
+                row.cells
+                    .get(column)
+                    .map(|targets| {
+                        targets
+                            .iter()
+                            .map(|(target, guard)| match guard {
+                                Some(guard) => format!("{} [{}]", table.names[target], guard),
+                                None => table.names[target].clone(),
+                            })
+                            .collect::<Vec<String>>()
+                            .join("; ")
+                    })
+                    .unwrap_or_default()
+            }));
+            self.write_row(w, &cells)?;
+        }
+        Ok(w.flush()?)
+    }
+}
+
+impl WriteTable {
+    fn write_row(&self, w: &mut dyn io::Write, cells: &[String]) -> io::Result<()> {
+        match self.style {
+            TableStyle::Csv => writeln!(
+                w,
+                "{}",
+                cells
+                    .iter()
+                    .map(|cell| csv_cell(cell))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            TableStyle::Markdown => writeln!(
+                w,
+                "| {} |",
+                cells
+                    .iter()
+                    .map(|cell| markdown_cell(cell))
+                    .collect::<Vec<String>>()
+                    .join(" | ")
+            ),
+        }
+    }
+}
+
+impl From<&StateMachine> for Table {
+    fn from(machine: &StateMachine) -> Self {
+        let mut table = Table {
+            names: Default::default(),
+            columns: Default::default(),
+            rows: Default::default(),
+        };
+        let mut sources: Vec<Row> = Default::default();
+        let mut transitions: Vec<(ID, Option<String>, ID, Option<String>)> = Default::default();
+        machine.for_each_region(&mut |region| {
+            for vertex in region.vertices() {
+                let (id, label) = match vertex.as_ref() {
+                    Vertex::State(state) => (state.id(), state.label()),
+                    Vertex::PseudoState(pseudo_state) => (pseudo_state.id(), pseudo_state.label()),
+                    Vertex::ConnectionPointReference(cpr) => (cpr.id(), cpr.label()),
+                };
+                let name = label.clone().unwrap_or_else(|| id.to_string());
+                let _ = table.names.insert(id.clone(), name);
+                let row = Row {
+                    vertex: id.clone(),
+                    cells: Default::default(),
+                };
+                if vertex.is_state() {
+                    table.rows.push(row);
+                } else {
+                    sources.push(row);
+                }
+            }
+            for transition in region.transitions() {
+                let guard = transition
+                    .guard()
+                    .as_ref()
+                    .and_then(|guard| guard.label().clone());
+                let mut columns: Vec<Option<String>> = transition
+                    .triggers()
+                    .map(|trigger| Some(trigger.to_string()))
+                    .collect();
+                if columns.is_empty() {
+                    columns.push(None);
+                }
+                for column in columns {
+                    if !table.columns.contains(&column) {
+                        table.columns.push(column.clone());
+                    }
+                    transitions.push((
+                        transition.source(),
+                        column,
+                        transition.target(),
+                        guard.clone(),
+                    ));
+                }
+            }
+        });
+        // pseudostates only have a row where they have outgoing transitions.
+        sources.retain(|row| {
+            transitions
+                .iter()
+                .any(|(source, _, _, _)| source == &row.vertex)
+        });
+        table.rows.extend(sources);
+        // the completion column always comes last.
+        if let Some(index) = table.columns.iter().position(Option::is_none) {
+            let column = table.columns.remove(index);
+            table.columns.push(column);
+        }
+        for (source, column, target, guard) in transitions {
+            if let Some(row) = table.rows.iter_mut().find(|row| row.vertex == source) {
+                row.cells.entry(column).or_default().push((target, guard));
+            }
+        }
+        table
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Types
+// ------------------------------------------------------------------------------------------------
+
+struct Table {
+    names: HashMap<ID, String>,
+    /// The trigger of each column, `None` for transitions without a trigger.
+    columns: Vec<Option<String>>,
+    rows: Vec<Row>,
+}
+
+struct Row {
+    vertex: ID,
+    /// The target, and guard label, of each transition by column.
+    cells: HashMap<Option<String>, Vec<(ID, Option<String>)>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Private Functions
+// ------------------------------------------------------------------------------------------------
+
+const COMPLETION_COLUMN: &str = "(completion)";
+
+fn csv_cell(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::opaque::OpaqueConstraint;
+    use crate::definition::types::{HasRegions, State, Transition, Trigger};
+    use crate::format::Stringify;
+
+    #[test]
+    fn test_csv() {
+        let machine = StateMachine::default();
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut idle = State::within(region.id().clone());
+        idle.set_label("idle");
+        let idle_id = idle.id().clone();
+        region.add_state(idle);
+        let mut busy = State::within(region.id().clone());
+        busy.set_label("busy, working");
+        let busy_id = busy.id().clone();
+        region.add_state(busy);
+        let choice = region.new_choice_state();
+
+        region.new_transition(initial.clone(), idle_id.clone());
+        let mut transition =
+            Transition::within(idle_id.clone(), busy_id.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("start"));
+        transition.set_guard(Box::new(OpaqueConstraint::new("ready", None)));
+        region.add_transition(transition);
+        let mut transition =
+            Transition::within(idle_id.clone(), idle_id.clone(), region.id().clone());
+        transition.add_trigger(Trigger::signal("start"));
+        region.add_transition(transition);
+        let mut transition = Transition::within(busy_id, choice.clone(), region.id().clone());
+        transition.add_trigger(Trigger::call("stop"));
+        region.add_transition(transition);
+        region.new_transition(choice.clone(), idle_id);
+
+        let csv = WriteTable::default().stringify(&machine).unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "State,start,stop(),(completion)\n\
+                 idle,\"busy, working [ready]; idle\",,\n\
+                 \"busy, working\",,{},\n\
+                 {},,,idle\n\
+                 {},,,idle\n",
+                choice, initial, choice
+            )
+        );
+    }
+}
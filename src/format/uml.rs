@@ -1,18 +1,127 @@
 /*!
-Writes a state machine out in the UML XMI format. Does not yet support parsing XMI.
+Reads and writes a state machine in the UML [XML Metadata Interchange](https://www.omg.org/spec/XMI)
+(XMI) 2.x format, as exchanged with modeling tools such as Enterprise Architect and Papyrus.
+
+The document contains a single `uml:Model` that owns the `uml:StateMachine`, and the events and
+signals referenced by its triggers. The machine's elements are mapped as follows.
+
+* Regions, states, pseudostates, final states, connection point references, and transitions are
+  written as `uml:Region`, `uml:State`, `uml:Pseudostate`, `uml:FinalState`,
+  `uml:ConnectionPointReference`, and `uml:Transition` elements. Each element's `xmi:id` is
+  derived from its model `ID`, and all references between them, such as a transition's `source`
+  and `target`, use these identifiers.
+* Entry, do, and exit behaviors and transition effects are `uml:OpaqueBehavior` elements named
+  with the behavior's label. Guards and invariants are `uml:Constraint` elements with an
+  `uml:OpaqueExpression` specification whose body is the constraint's label.
+* Each distinct trigger event is a `uml:SignalEvent` (with its `uml:Signal`), `uml:CallEvent`,
+  `uml:TimeEvent`, `uml:ChangeEvent`, or `uml:AnyReceiveEvent` element in the model, and
+  triggers reference these by `event`. Relative time events are written as a duration string,
+  such as `1.5s`, and absolute times as seconds since the UNIX epoch.
+
+UML has no notion of trigger payload predicates, machines that use them result in an error. Signals
+are written without attributes, so the payload schema of a signal event is not kept.
+
+When reading, the first `uml:StateMachine` in the document is used, wherever it is owned, and the
+same mapping is applied in reverse. Elements are recognized by their `xmi:type`, for any version of
+the XMI and UML namespaces, and references may be written either as attributes or as child
+elements with an `xmi:idref`; they are resolved across the whole document so that, for example,
+guards owned as rules of the machine, or events and signals owned by other packages, are found.
+Constraints and behaviors become [`OpaqueConstraint`](../../definition/opaque/struct.OpaqueConstraint.html)
+and [`OpaqueBehavior`](../../definition/opaque/struct.OpaqueBehavior.html) values. A state's own
+connection points are added to its first region. References to other machines, such as a
+sub-machine state's `submachine`, and connection point references to the entry and exit points of
+other machines are not resolved.
+
+The layout of a state, as the `bounds` of a `umldi:UMLShape` whose `modelElement` is the state, in
+any diagram of the document, is kept as its [`Layout`](../layout/struct.Layout.html). When the
+machine has any states with a layout, a `umldi:UMLStateMachineDiagram` with a shape for each is
+written after the model.
 
 # Example
 
-TBD
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::uml::{ReadXmi, WriteXmi};
+use uml_state_machine::format::{Parse, Stringify};
+
+let machine = StateMachine::labeled("light");
+let region = machine.default_region().unwrap();
+let initial = region.new_initial_state();
+let mut off = State::within(region.id().clone());
+off.set_label("off");
+let off_id = off.id().clone();
+region.add_state(off);
+region.new_transition(initial, off_id);
+
+let xmi = WriteXmi::default().stringify(&machine).unwrap();
+assert!(xmi.contains(r#"xmi:type="uml:StateMachine""#));
+assert!(xmi.contains(r#"kind="initial""#));
+
+let read = ReadXmi::default().parse(&xmi).unwrap();
+assert_eq!(read.label(), &Some("light".to_string()));
+assert_eq!(read.default_region().unwrap().vertices().len(), 2);
+```
 
 */
 
-// use ...
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::slice::Iter;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use roxmltree::{Document, Node};
+
+use crate::core::{FieldValue, ID};
+use crate::definition::guards::recognize_else;
+use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+use crate::definition::types::{
+    Behavior, ConnectionPointReference, Constraint, Event, HasRegions, Identified, Labeled,
+    PseudoState, PseudoStateKind, Region, State, StateMachine, TimeEvent, Transition,
+    TransitionKind, Trigger,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::layout::{layout, set_layout, Layout};
+use crate::format::{parse_duration, Parse, Sink, Write};
+use std::io;
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// The XMI namespace for version 2.5.1 of the specification.
+///
+pub const XMI_NAMESPACE: &str = "http://www.omg.org/spec/XMI/20131001";
+
+///
+/// The UML namespace for version 2.5.1 of the specification.
+///
+pub const UML_NAMESPACE: &str = "http://www.omg.org/spec/UML/20161101";
+
+///
+/// The UML Diagram Interchange namespace for version 2.5.1 of the specification.
+///
+pub const UMLDI_NAMESPACE: &str = "http://www.omg.org/spec/UML/20131001/UMLDI";
+
+///
+/// The Diagram Common namespace, of the bounds of diagram shapes.
+///
+pub const DC_NAMESPACE: &str = "http://www.omg.org/spec/DD/20131001/DC";
+
+///
+/// Implements Write, and so Stringify, for UML XMI.
+///
+#[derive(Debug, Default)]
+pub struct WriteXmi {}
+
+///
+/// Implements Parse for UML XMI.
+///
+#[derive(Debug, Default)]
+pub struct ReadXmi {}
+
 // ------------------------------------------------------------------------------------------------
 // Public Functions
 // ------------------------------------------------------------------------------------------------
@@ -21,14 +130,1217 @@ TBD
 // Implementations
 // ------------------------------------------------------------------------------------------------
 
+impl Write for WriteXmi {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let writer = Writer {
+            out: Sink::new(w),
+            depth: RefCell::new(2),
+            open_states: Default::default(),
+            transitions: Default::default(),
+            events: Default::default(),
+            current_state: Default::default(),
+            layouts: Default::default(),
+            error: Default::default(),
+        };
+        writer
+            .out
+            .push_line("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        writer.out.push_line(&format!(
+            "<xmi:XMI xmi:version=\"2.5\" xmlns:xmi=\"{}\" xmlns:uml=\"{}\">",
+            XMI_NAMESPACE, UML_NAMESPACE
+        ));
+        writer.out.push_line(&format!(
+            "  <uml:Model xmi:type=\"uml:Model\" xmi:id=\"{}.model\"{}>",
+            xmi_id(machine.id()),
+            name_attribute(machine.label())
+        ));
+        visit_state_machine(machine, &writer)?;
+        if let Some(error) = writer.error.take() {
+            return Err(error);
+        }
+        for (index, event) in writer.events.take().iter().enumerate() {
+            writer.out.push_str(&event_element(index, event));
+        }
+        writer.out.push_line("  </uml:Model>");
+        writer.diagram(machine.id());
+        writer.out.push_line("</xmi:XMI>");
+        writer.out.finish()
+    }
+}
+
+impl Parse for ReadXmi {
+    type Error = Error;
+
+    fn parse(&self, string: &str) -> Result<StateMachine> {
+        let document = Document::parse(string)
+            .map_err(|e| Error::from(ErrorKind::FormatParse(e.to_string())))?;
+        let node = document
+            .descendants()
+            .find(|node| uml_type(node) == Some("StateMachine"))
+            .ok_or_else(|| parse_error("no uml:StateMachine element"))?;
+        let mut reader = Reader {
+            elements: document
+                .descendants()
+                .filter_map(|node| xmi_attribute(&node, "id").map(|id| (id, node)))
+                .collect(),
+            layouts: document
+                .descendants()
+                .filter(|node| uml_type(node) == Some("UMLShape"))
+                .filter_map(|node| Some((reference(&node, "modelElement")?, shape_layout(&node)?)))
+                .collect(),
+            vertices: Default::default(),
+            transitions: Default::default(),
+        };
+
+        let mut machine = match node.attribute("name") {
+            None => StateMachine::default(),
+            Some(name) => StateMachine::labeled(name),
+        };
+        for child in children(&node, "connectionPoint") {
+            let pseudo_state = reader.read_pseudo_state(&child, machine.id())?;
+            machine.connection_points.push(pseudo_state);
+        }
+        let regions: Vec<Node<'_, '_>> = children(&node, "region").collect();
+        if regions.is_empty() {
+            return Err(parse_error("a state machine must have at least one region"));
+        }
+        for _ in 1..regions.len() {
+            let _ = machine.new_region();
+        }
+        for (index, child) in regions.iter().enumerate() {
+            let region = &mut machine.regions[index];
+            if let Some(name) = child.attribute("name") {
+                region.set_label(name);
+            }
+            reader.read_region(child, region)?;
+        }
+        reader.add_transitions(&machine)?;
+        Ok(machine)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl<'a, 'input> Reader<'a, 'input> {
+    fn read_region(&mut self, node: &Node<'a, 'input>, region: &Region) -> Result<()> {
+        for child in children(node, "subvertex") {
+            match uml_type(&child) {
+                Some("State") | Some("FinalState") => {
+                    let state = self.read_state(&child, region.id())?;
+                    region.add_state(state);
+                }
+                Some("Pseudostate") => {
+                    let pseudo_state = self.read_pseudo_state(&child, region.id())?;
+                    region.add_pseudo_state(pseudo_state);
+                }
+                Some("ConnectionPointReference") => {
+                    let reference = self.read_connection_point_reference(&child, region.id())?;
+                    region.add_connection_point_ref(reference);
+                }
+                other => {
+                    return Err(unsupported_error(&format!(
+                        "`{}` vertices",
+                        other.unwrap_or("untyped")
+                    )))
+                }
+            }
+        }
+        for child in children(node, "transition") {
+            self.transitions.push((region.id().clone(), child));
+        }
+        Ok(())
+    }
+
+    fn read_state(&mut self, node: &Node<'a, 'input>, container: &ID) -> Result<State> {
+        let mut state = State::within(container.clone());
+        if let Some(name) = node.attribute("name") {
+            state.set_label(name);
+        }
+        self.add_vertex(node, state.id())?;
+        if uml_type(node) == Some("FinalState") {
+            state.set_final(true);
+        }
+        for comment in children(node, "ownedComment") {
+            if let Some(body) = body(&comment) {
+                state.add_comment(&body);
+            }
+        }
+        for child in children(node, "region") {
+            let mut region = Region::within_state(state.id().clone());
+            if let Some(name) = child.attribute("name") {
+                region.set_label(name);
+            }
+            self.read_region(&child, &region)?;
+            state.add_region(region);
+        }
+        for child in children(node, "connectionPoint") {
+            if !state.has_regions() {
+                state.add_region(Region::within_state(state.id().clone()));
+            }
+            let region = state.default_region().unwrap();
+            let pseudo_state = self.read_pseudo_state(&child, region.id())?;
+            let pseudo_state_id = pseudo_state.id().clone();
+            region.add_pseudo_state(pseudo_state);
+            state.connection_points.push(pseudo_state_id);
+        }
+        if let Some(invariant) = self.element(node, "stateInvariant") {
+            state.set_invariant(self.constraint(&invariant)?);
+        }
+        if let Some(entry) = self.element(node, "entry") {
+            state.set_entry(self.behavior(&entry)?);
+        }
+        if let Some(do_activity) = self.element(node, "doActivity") {
+            state.set_do_activity(self.behavior(&do_activity)?);
+        }
+        if let Some(exit) = self.element(node, "exit") {
+            state.set_exit(self.behavior(&exit)?);
+        }
+        for trigger in children(node, "deferrableTrigger") {
+            let trigger = self.trigger(&self.dereference(&trigger))?;
+            state.add_deferrable_trigger(trigger);
+        }
+        if let Some(layout) = xmi_attribute(node, "id").and_then(|id| self.layouts.get(id)) {
+            set_layout(&mut state, *layout);
+        }
+        Ok(state)
+    }
+
+    fn read_pseudo_state(
+        &mut self,
+        node: &Node<'a, 'input>,
+        container: &ID,
+    ) -> Result<PseudoState> {
+        let kind = match node.attribute("kind").unwrap_or("initial") {
+            "initial" => PseudoStateKind::Initial,
+            "deepHistory" => PseudoStateKind::DeepHistory,
+            "shallowHistory" => PseudoStateKind::ShallowHistory,
+            "join" => PseudoStateKind::Join,
+            "fork" => PseudoStateKind::Fork,
+            "junction" => PseudoStateKind::Junction,
+            "choice" => PseudoStateKind::Choice,
+            "entryPoint" => PseudoStateKind::EntryPoint,
+            "exitPoint" => PseudoStateKind::ExitPoint,
+            "terminate" => PseudoStateKind::Terminate,
+            kind => return Err(parse_error(&format!("unknown pseudostate kind `{}`", kind))),
+        };
+        let mut pseudo_state = PseudoState::within(container.clone(), kind);
+        if let Some(name) = node.attribute("name") {
+            pseudo_state.set_label(name);
+        }
+        self.add_vertex(node, pseudo_state.id())?;
+        Ok(pseudo_state)
+    }
+
+    fn read_connection_point_reference(
+        &mut self,
+        node: &Node<'a, 'input>,
+        container: &ID,
+    ) -> Result<ConnectionPointReference> {
+        let mut reference = ConnectionPointReference::within(container.clone());
+        if let Some(name) = node.attribute("name") {
+            reference.set_label(name);
+        }
+        self.add_vertex(node, reference.id())?;
+        // only points already read, such as the machine's own connection points, are known.
+        reference.entry = self.references(node, "entry");
+        reference.exit = self.references(node, "exit");
+        Ok(reference)
+    }
+
+    fn add_vertex(&mut self, node: &Node<'a, 'input>, id: &ID) -> Result<()> {
+        if let Some(xmi_id) = xmi_attribute(node, "id") {
+            if self.vertices.insert(xmi_id, id.clone()).is_some() {
+                return Err(parse_error(&format!("duplicate xmi:id `{}`", xmi_id)));
+            }
+        }
+        Ok(())
+    }
+
+    fn add_transitions(&self, machine: &StateMachine) -> Result<()> {
+        let mut transitions: HashMap<ID, Vec<Transition>> = Default::default();
+        for (container, node) in &self.transitions {
+            let transition = self.transition(node, container)?;
+            transitions
+                .entry(container.clone())
+                .or_default()
+                .push(transition);
+        }
+        machine.for_each_region(&mut |region| {
+            for transition in transitions.remove(region.id()).unwrap_or_default() {
+                region.add_transition(transition);
+            }
+        });
+        Ok(())
+    }
+
+    fn transition(&self, node: &Node<'a, 'input>, container: &ID) -> Result<Transition> {
+        let vertex = |name: &str| -> Result<ID> {
+            let reference = reference(node, name)
+                .ok_or_else(|| parse_error(&format!("a transition without a {}", name)))?;
+            self.vertices
+                .get(reference)
+                .cloned()
+                .ok_or_else(|| parse_error(&format!("unknown transition {} `{}`", name, reference)))
+        };
+        let mut transition =
+            Transition::within(vertex("source")?, vertex("target")?, container.clone());
+        transition.set_kind(match node.attribute("kind").unwrap_or("external") {
+            "internal" => TransitionKind::Internal,
+            "local" => TransitionKind::Local,
+            "external" => TransitionKind::External,
+            kind => return Err(parse_error(&format!("unknown transition kind `{}`", kind))),
+        });
+        if let Some(name) = node.attribute("name") {
+            transition.set_label(name);
+        }
+        for trigger in children(node, "trigger") {
+            transition.add_trigger(self.trigger(&self.dereference(&trigger))?);
+        }
+        if let Some(guard) = self.element(node, "guard") {
+            transition.set_guard(recognize_else(self.constraint(&guard)?));
+        }
+        if let Some(effect) = self.element(node, "effect") {
+            transition.set_effect(self.behavior(&effect)?);
+        }
+        Ok(transition)
+    }
+
+    fn trigger(&self, node: &Node<'a, 'input>) -> Result<Trigger> {
+        let event = match self.element(node, "event") {
+            Some(event) => event,
+            // some tools name the trigger after its signal, with no event.
+            None => {
+                return node
+                    .attribute("name")
+                    .map(Trigger::signal)
+                    .ok_or_else(|| parse_error("a trigger without an event"))
+            }
+        };
+        let named = |reference: &'static str| -> Option<&str> {
+            self.element(&event, reference)
+                .and_then(|element| element.attribute("name"))
+                .or_else(|| event.attribute("name"))
+        };
+        match uml_type(&event) {
+            Some("SignalEvent") => named("signal")
+                .map(Trigger::signal)
+                .ok_or_else(|| parse_error("a signal event without a signal")),
+            Some("CallEvent") => named("operation")
+                .map(Trigger::call)
+                .ok_or_else(|| parse_error("a call event without an operation")),
+            Some("AnyReceiveEvent") => Ok(Trigger::any()),
+            Some("ChangeEvent") => {
+                let expression = self
+                    .element(&event, "changeExpression")
+                    .ok_or_else(|| parse_error("a change event without an expression"))?;
+                let (body, language) = specification_text(&expression)
+                    .ok_or_else(|| parse_error("a change event without an expression"))?;
+                Ok(Trigger::when(Arc::new(OpaqueConstraint::new(
+                    &body,
+                    language.as_deref(),
+                ))))
+            }
+            Some("TimeEvent") => {
+                let value = self
+                    .element(&event, "when")
+                    .and_then(|when| self.element(&when, "expr").or(Some(when)))
+                    .and_then(|expression| specification_text(&expression))
+                    .map(|(value, _)| value)
+                    .ok_or_else(|| parse_error("a time event without a `when` expression"))?;
+                if event.attribute("isRelative") == Some("true") {
+                    parse_duration(&value)
+                        .or_else(|| value.parse::<f64>().ok().map(Duration::from_secs_f64))
+                        .map(Trigger::after)
+                        .ok_or_else(|| {
+                            parse_error(&format!("could not parse the duration `{}`", value))
+                        })
+                } else {
+                    value
+                        .parse::<u64>()
+                        .map(|seconds| Trigger::at(UNIX_EPOCH + Duration::from_secs(seconds)))
+                        .map_err(|_| parse_error(&format!("could not parse the time `{}`", value)))
+                }
+            }
+            other => Err(unsupported_error(&format!(
+                "`{}` events",
+                other.unwrap_or("untyped")
+            ))),
+        }
+    }
+
+    fn constraint(&self, node: &Node<'a, 'input>) -> Result<Box<dyn Constraint>> {
+        let specification = self
+            .element(node, "specification")
+            .and_then(|specification| specification_text(&specification))
+            .or_else(|| node.attribute("name").map(|name| (name.to_string(), None)))
+            .ok_or_else(|| parse_error("a constraint without a specification"))?;
+        Ok(Box::new(OpaqueConstraint::new(
+            &specification.0,
+            specification.1.as_deref(),
+        )))
+    }
+
+    fn behavior(&self, node: &Node<'a, 'input>) -> Result<Box<dyn Behavior>> {
+        let language = child_text(node, "language");
+        let body = node
+            .attribute("name")
+            .map(str::to_string)
+            .or_else(|| body(node))
+            .ok_or_else(|| parse_error("a behavior without a name or body"))?;
+        Ok(Box::new(OpaqueBehavior::new(&body, language.as_deref())))
+    }
+
+    ///
+    /// The element referenced by `name` from `node`, either as a child element, which may itself
+    /// be a reference, or as an attribute.
+    ///
+    fn element(&self, node: &Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+        match children(node, name).next() {
+            Some(child) => Some(self.dereference(&child)),
+            None => node
+                .attribute(name)
+                .and_then(|reference| self.elements.get(reference).cloned()),
+        }
+    }
+
+    fn dereference(&self, node: &Node<'a, 'input>) -> Node<'a, 'input> {
+        xmi_attribute(node, "idref")
+            .and_then(|reference| self.elements.get(reference).cloned())
+            .unwrap_or(*node)
+    }
+
+    fn references(&self, node: &Node<'a, 'input>, name: &'static str) -> Vec<ID> {
+        let mut references: Vec<&str> = node
+            .attribute(name)
+            .map(|references| references.split_whitespace().collect())
+            .unwrap_or_default();
+        references.extend(children(node, name).filter_map(|child| xmi_attribute(&child, "idref")));
+        references
+            .into_iter()
+            .filter_map(|reference| self.vertices.get(reference).cloned())
+            .collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+impl StateMachineVisitor for Writer<'_> {
+    fn enter_state_machine(
+        &self,
+        _: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        _: Iter<'_, ID>,
+        connection_points: Iter<'_, PseudoState>,
+    ) {
+        self.open(&format!(
+            "<packagedElement xmi:type=\"uml:StateMachine\" xmi:id=\"{}\"{}>",
+            xmi_id(id),
+            name_attribute(label)
+        ));
+        for pseudo_state in connection_points {
+            self.line(&format!(
+                "<connectionPoint xmi:type=\"uml:Pseudostate\" xmi:id=\"{}\"{} kind=\"{}\"/>",
+                xmi_id(pseudo_state.id()),
+                name_attribute(pseudo_state.label()),
+                pseudo_state_kind(&pseudo_state.kind())
+            ));
+        }
+    }
+
+    fn exit_state_machine(
+        &self,
+        _: &Resolver<'_>,
+        _: &ID,
+        _: &Option<String>,
+        _: Iter<'_, ID>,
+        _: Iter<'_, PseudoState>,
+    ) {
+        self.close("</packagedElement>");
+    }
+
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        comments: Iter<'_, String>,
+        region_count: usize,
+        sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        deferrable_triggers: Iter<'_, Trigger>,
+        invariant: &Option<Box<dyn Constraint>>,
+        entry: &Option<Box<dyn Behavior>>,
+        do_activity: &Option<Box<dyn Behavior>>,
+        exit: &Option<Box<dyn Behavior>>,
+        is_final: bool,
+    ) {
+        let mut element = format!(
+            "<subvertex xmi:type=\"uml:{}\" xmi:id=\"{}\"{}",
+            if is_final { "FinalState" } else { "State" },
+            xmi_id(id),
+            name_attribute(label)
+        );
+        if let Some(sub_machine) = sub_machine {
+            element.push_str(&format!(" submachine=\"{}\"", xmi_id(sub_machine)));
+        }
+        let _ = self.current_state.borrow_mut().replace(xmi_id(id));
+        let has_content = region_count > 0
+            || comments.len() > 0
+            || deferrable_triggers.len() > 0
+            || invariant.is_some()
+            || entry.is_some()
+            || do_activity.is_some()
+            || exit.is_some();
+        if !has_content {
+            self.line(&format!("{}/>", element));
+            self.open_states.borrow_mut().push(false);
+            return;
+        }
+        self.open(&format!("{}>", element));
+        self.open_states.borrow_mut().push(true);
+
+        for (index, comment) in comments.enumerate() {
+            self.line(&format!(
+                "<ownedComment xmi:type=\"uml:Comment\" xmi:id=\"{}.comment.{}\"><body>{}</body></ownedComment>",
+                xmi_id(id),
+                index,
+                escape(comment)
+            ));
+        }
+        if let Some(invariant) = invariant {
+            self.constraint(
+                "stateInvariant",
+                &format!("{}.invariant", xmi_id(id)),
+                invariant.as_ref(),
+            );
+        }
+        for (name, behavior) in [
+            ("entry", entry),
+            ("doActivity", do_activity),
+            ("exit", exit),
+        ] {
+            if let Some(behavior) = behavior {
+                self.behavior(name, &format!("{}.{}", xmi_id(id), name), behavior.as_ref());
+            }
+        }
+        for (index, trigger) in deferrable_triggers.enumerate() {
+            self.trigger(
+                "deferrableTrigger",
+                &format!("{}.deferrableTrigger.{}", xmi_id(id), index),
+                trigger,
+            );
+        }
+    }
+
+    fn exit_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        _region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        let _ = self.current_state.borrow_mut().take();
+        if self.open_states.borrow_mut().pop() == Some(true) {
+            self.close("</subvertex>");
+        }
+    }
+
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, label: &Option<String>, _last: bool) {
+        let _ = self.current_state.borrow_mut().take();
+        self.open(&format!(
+            "<region xmi:type=\"uml:Region\" xmi:id=\"{}\"{}>",
+            xmi_id(id),
+            name_attribute(label)
+        ));
+    }
+
+    fn exit_region(&self, _resolver: &Resolver<'_>, _: &ID, _label: &Option<String>, _last: bool) {
+        self.close("</region>");
+    }
+
+    fn connection_point_reference(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        entry: Iter<'_, ID>,
+        exit: Iter<'_, ID>,
+        state: &Option<ID>,
+    ) {
+        let mut element = format!(
+            "<subvertex xmi:type=\"uml:ConnectionPointReference\" xmi:id=\"{}\"{}",
+            xmi_id(id),
+            name_attribute(label)
+        );
+        for (name, references) in [("entry", entry), ("exit", exit)] {
+            let references = references.map(xmi_id).collect::<Vec<String>>();
+            if !references.is_empty() {
+                element.push_str(&format!(" {}=\"{}\"", name, references.join(" ")));
+            }
+        }
+        if let Some(state) = state {
+            element.push_str(&format!(" state=\"{}\"", xmi_id(state)));
+        }
+        self.line(&format!("{}/>", element));
+    }
+
+    fn pseudo_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        label: &Option<String>,
+        kind: &PseudoStateKind,
+    ) {
+        self.line(&format!(
+            "<subvertex xmi:type=\"uml:Pseudostate\" xmi:id=\"{}\"{} kind=\"{}\"/>",
+            xmi_id(id),
+            name_attribute(label),
+            pseudo_state_kind(kind)
+        ));
+    }
+
+    fn transition(
+        &self,
+        _resolver: &Resolver<'_>,
+        label: &Option<String>,
+        kind: TransitionKind,
+        source: ID,
+        target: ID,
+        triggers: Iter<'_, Trigger>,
+        guard: &Option<Box<dyn Constraint>>,
+        effect: &Option<Box<dyn Behavior>>,
+    ) {
+        let _ = self.current_state.borrow_mut().take();
+        // transitions have no model ID, but the source, target, and kind identify them well
+        // enough; the position disambiguates any that share all three.
+        let id = format!(
+            "{}.{}.{}",
+            xmi_id(&source),
+            xmi_id(&target),
+            self.transitions.replace_with(|count| *count + 1)
+        );
+        let element = format!(
+            "<transition xmi:type=\"uml:Transition\" xmi:id=\"{}\"{} kind=\"{}\" source=\"{}\" target=\"{}\"",
+            id,
+            name_attribute(label),
+            match kind {
+                TransitionKind::Internal => "internal",
+                TransitionKind::Local => "local",
+                TransitionKind::External => "external",
+            },
+            xmi_id(&source),
+            xmi_id(&target)
+        );
+        if triggers.len() == 0 && guard.is_none() && effect.is_none() {
+            self.line(&format!("{}/>", element));
+            return;
+        }
+        self.open(&format!("{}>", element));
+        for (index, trigger) in triggers.enumerate() {
+            self.trigger("trigger", &format!("{}.trigger.{}", id, index), trigger);
+        }
+        if let Some(guard) = guard {
+            self.constraint("guard", &format!("{}.guard", id), guard.as_ref());
+        }
+        if let Some(effect) = effect {
+            self.behavior("effect", &format!("{}.effect", id), effect.as_ref());
+        }
+        self.close("</transition>");
+    }
+
+    fn metadata(&self, _resolver: &Resolver<'_>, metadata: &HashMap<String, FieldValue>) {
+        let state = self.current_state.borrow_mut().take();
+        if let (Some(state), Some(layout)) = (state, layout(metadata)) {
+            self.layouts.borrow_mut().push((state, layout));
+        }
+    }
+}
+
+impl Writer<'_> {
+    ///
+    /// Write a diagram with a shape for each state with a layout, if there are any.
+    ///
+    fn diagram(&self, machine: &ID) {
+        let layouts = self.layouts.take();
+        if layouts.is_empty() {
+            return;
+        }
+        self.out.push_line(&format!(
+            "  <umldi:UMLDiagram xmlns:umldi=\"{}\" xmlns:dc=\"{}\" xmi:type=\"umldi:UMLStateMachineDiagram\" xmi:id=\"{}.diagram\" modelElement=\"{}\">",
+            UMLDI_NAMESPACE,
+            DC_NAMESPACE,
+            xmi_id(machine),
+            xmi_id(machine)
+        ));
+        for (id, layout) in layouts {
+            let mut bounds = format!("x=\"{}\" y=\"{}\"", layout.x, layout.y);
+            if let (Some(width), Some(height)) = (layout.width, layout.height) {
+                bounds.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+            }
+            self.out.push_line(&format!(
+                "    <ownedElement xmi:type=\"umldi:UMLShape\" xmi:id=\"{}.shape\" modelElement=\"{}\"><bounds xmi:type=\"dc:Bounds\" {}/></ownedElement>",
+                id, id, bounds
+            ));
+        }
+        self.out.push_line("  </umldi:UMLDiagram>");
+    }
+
+    fn line(&self, string: &str) {
+        self.out.push_str(&"  ".repeat(*self.depth.borrow()));
+        self.out.push_line(string);
+    }
+
+    fn open(&self, string: &str) {
+        self.line(string);
+        *self.depth.borrow_mut() += 1;
+    }
+
+    fn close(&self, string: &str) {
+        *self.depth.borrow_mut() -= 1;
+        self.line(string);
+    }
+
+    fn behavior(&self, element: &str, id: &str, behavior: &dyn Behavior) {
+        self.line(&format!(
+            "<{} xmi:type=\"uml:OpaqueBehavior\" xmi:id=\"{}\"{}/>",
+            element,
+            id,
+            name_attribute(behavior.label())
+        ));
+    }
+
+    fn constraint(&self, element: &str, id: &str, constraint: &dyn Constraint) {
+        self.open(&format!(
+            "<{} xmi:type=\"uml:Constraint\" xmi:id=\"{}\">",
+            element, id
+        ));
+        self.line(&format!(
+            "<specification xmi:type=\"uml:OpaqueExpression\" xmi:id=\"{}.specification\"><body>{}</body></specification>",
+            id,
+            escape(constraint.label().as_deref().unwrap_or_default())
+        ));
+        self.close(&format!("</{}>", element));
+    }
+
+    fn trigger(&self, element: &str, id: &str, trigger: &Trigger) {
+        if trigger.payload_predicates().len() > 0 {
+            return self.fail(
+                ErrorKind::FormatUnsupported("trigger payload predicates".to_string()).into(),
+            );
+        }
+        let event = trigger.event();
+        let index = {
+            let mut events = self.events.borrow_mut();
+            match events.iter().position(|existing| existing == event) {
+                Some(index) => index,
+                None => {
+                    events.push(event.clone());
+                    events.len() - 1
+                }
+            }
+        };
+        self.line(&format!(
+            "<{} xmi:type=\"uml:Trigger\" xmi:id=\"{}\" event=\"{}\"/>",
+            element,
+            id,
+            event_id(index)
+        ));
+    }
+
+    fn fail(&self, error: Error) {
+        let mut current = self.error.borrow_mut();
+        if current.is_none() {
+            *current = Some(error);
+        }
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+struct Reader<'a, 'input> {
+    /// Every element in the document with an `xmi:id`.
+    elements: HashMap<&'a str, Node<'a, 'input>>,
+    /// The layout of each element with a diagram shape, by the element's `xmi:id`.
+    layouts: HashMap<&'a str, Layout>,
+    vertices: HashMap<&'a str, ID>,
+    /// Transitions are resolved once all vertices are known, with their region.
+    transitions: Vec<(ID, Node<'a, 'input>)>,
+}
+
+struct Writer<'w> {
+    out: Sink<'w>,
+    depth: RefCell<usize>,
+    /// For each state being visited, whether it was written as an open element.
+    open_states: RefCell<Vec<bool>>,
+    /// The number of transitions written, used to make transition IDs unique.
+    transitions: RefCell<usize>,
+    /// The distinct events referenced by triggers, written into the model once visited.
+    events: RefCell<Vec<Event>>,
+    /// The `xmi:id` of the state just entered, until its metadata, or a region, is visited.
+    current_state: RefCell<Option<String>>,
+    /// The layouts of states, written as the shapes of a diagram once the model is written.
+    layouts: RefCell<Vec<(String, Layout)>>,
+    error: RefCell<Option<Error>>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+fn uml_type<'a>(node: &Node<'a, '_>) -> Option<&'a str> {
+    if !node.is_element() {
+        return None;
+    }
+    match xmi_attribute(node, "type") {
+        Some(xmi_type) => Some(xmi_type.rsplit(':').next().unwrap_or(xmi_type)),
+        None if node
+            .tag_name()
+            .namespace()
+            .map(is_uml_namespace)
+            .unwrap_or_default() =>
+        {
+            Some(node.tag_name().name())
+        }
+        None => None,
+    }
+}
+
+fn xmi_attribute<'a>(node: &Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|attribute| {
+            attribute.name() == name && attribute.namespace().map(is_xmi_namespace) == Some(true)
+        })
+        .map(|attribute| attribute.value())
+}
+
+fn is_xmi_namespace(namespace: &str) -> bool {
+    namespace.contains("/XMI")
+}
+
+fn is_uml_namespace(namespace: &str) -> bool {
+    namespace.contains("/UML") || namespace.contains("/uml2/")
+}
+
+fn children<'a, 'input: 'a>(
+    node: &Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn reference<'a>(node: &Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute(name).or_else(|| {
+        node.children()
+            .find(|child| child.is_element() && child.tag_name().name() == name)
+            .and_then(|child| xmi_attribute(&child, "idref"))
+    })
+}
+
+///
+/// The layout in the `bounds` of a diagram shape, whose members each default to zero as in the
+/// Diagram Common model; a shape without bounds has no layout.
+///
+fn shape_layout(node: &Node<'_, '_>) -> Option<Layout> {
+    let bounds = children(node, "bounds").next()?;
+    let member = |name: &str| {
+        bounds
+            .attribute(name)
+            .and_then(|value| value.parse::<f64>().ok())
+    };
+    let layout = Layout::at(
+        member("x").unwrap_or_default(),
+        member("y").unwrap_or_default(),
+    );
+    Some(match (member("width"), member("height")) {
+        (Some(width), Some(height)) => layout.with_size(width, height),
+        _ => layout,
+    })
+}
+
+fn child_text(node: &Node<'_, '_>, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+        .map(|child| child.text().unwrap_or_default().to_string())
+        .or_else(|| node.attribute(name).map(str::to_string))
+}
+
+fn body(node: &Node<'_, '_>) -> Option<String> {
+    child_text(node, "body")
+}
+
+///
+/// The text of a value specification, the body of an opaque expression or the value of a
+/// literal, with its language if known.
+///
+fn specification_text(node: &Node<'_, '_>) -> Option<(String, Option<String>)> {
+    body(node)
+        .or_else(|| node.attribute("value").map(str::to_string))
+        .map(|text| (text, child_text(node, "language")))
+}
+
+fn parse_error(message: &str) -> Error {
+    ErrorKind::FormatParse(message.to_string()).into()
+}
+
+fn unsupported_error(feature: &str) -> Error {
+    ErrorKind::FormatUnsupported(feature.to_string()).into()
+}
+
+///
+/// XMI identifiers must be XML names, model IDs may start with a digit or contain `:` so are
+/// prefixed with `_` and have `:` replaced by `.`, which cannot occur in an `ID`.
+///
+fn xmi_id(id: &ID) -> String {
+    format!("_{}", id.to_string().replace(&[':', '/'][..], "."))
+}
+
+fn event_id(index: usize) -> String {
+    format!("_event.{}", index)
+}
+
+fn name_attribute(label: &Option<String>) -> String {
+    match label {
+        None => String::new(),
+        Some(label) => format!(" name=\"{}\"", escape(label)),
+    }
+}
+
+fn pseudo_state_kind(kind: &PseudoStateKind) -> &'static str {
+    match kind {
+        PseudoStateKind::Initial => "initial",
+        PseudoStateKind::DeepHistory => "deepHistory",
+        PseudoStateKind::ShallowHistory => "shallowHistory",
+        PseudoStateKind::Join => "join",
+        PseudoStateKind::Fork => "fork",
+        PseudoStateKind::Junction => "junction",
+        PseudoStateKind::Choice => "choice",
+        PseudoStateKind::EntryPoint => "entryPoint",
+        PseudoStateKind::ExitPoint => "exitPoint",
+        PseudoStateKind::Terminate => "terminate",
+    }
+}
+
+fn event_element(index: usize, event: &Event) -> String {
+    let id = event_id(index);
+    match event {
+        Event::Signal(signal) => format!(
+            "    <packagedElement xmi:type=\"uml:SignalEvent\" xmi:id=\"{id}\" name=\"{name}\" signal=\"{id}.signal\"/>\n    <packagedElement xmi:type=\"uml:Signal\" xmi:id=\"{id}.signal\" name=\"{name}\"/>\n",
+            id = id,
+            name = escape(signal.signal())
+        ),
+        Event::Call(call) => format!(
+            "    <packagedElement xmi:type=\"uml:CallEvent\" xmi:id=\"{}\" name=\"{}\"/>\n",
+            id,
+            escape(call.operation())
+        ),
+        Event::Time(time) => {
+            let (relative, expression) = match time {
+                TimeEvent::Relative(duration) => (
+                    true,
+                    format!(
+                        "<expr xmi:type=\"uml:LiteralString\" xmi:id=\"{}.when.expr\" value=\"{:?}\"/>",
+                        id, duration
+                    ),
+                ),
+                TimeEvent::Absolute(time) => (
+                    false,
+                    format!(
+                        "<expr xmi:type=\"uml:LiteralInteger\" xmi:id=\"{}.when.expr\" value=\"{}\"/>",
+                        id,
+                        time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+                    ),
+                ),
+            };
+            format!(
+                "    <packagedElement xmi:type=\"uml:TimeEvent\" xmi:id=\"{id}\" isRelative=\"{relative}\">\n      <when xmi:type=\"uml:TimeExpression\" xmi:id=\"{id}.when\">{expression}</when>\n    </packagedElement>\n",
+                id = id,
+                relative = relative,
+                expression = expression
+            )
+        }
+        Event::Change(change) => format!(
+            "    <packagedElement xmi:type=\"uml:ChangeEvent\" xmi:id=\"{id}\">\n      <changeExpression xmi:type=\"uml:OpaqueExpression\" xmi:id=\"{id}.changeExpression\"><body>{body}</body></changeExpression>\n    </packagedElement>\n",
+            id = id,
+            body = escape(change.change_expression().label().as_deref().unwrap_or_default())
+        ),
+        Event::AnyReceive => format!(
+            "    <packagedElement xmi:type=\"uml:AnyReceiveEvent\" xmi:id=\"{}\"/>\n",
+            id
+        ),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
 // ------------------------------------------------------------------------------------------------
 // Modules
 // ------------------------------------------------------------------------------------------------
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+    use crate::definition::types::{HasMetadata, HasRegions, Region, State, Transition};
+    use crate::format::Stringify;
+    use std::time::Duration;
+
+    #[test]
+    fn test_write_machine() {
+        let machine = StateMachine::labeled("player");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut playing = State::within(region.id().clone());
+        playing.set_label("playing");
+        playing.add_comment("Plays <audio> & video");
+        playing.set_entry(Box::new(OpaqueBehavior::new("start", None)));
+        playing.add_deferrable_trigger(Trigger::signal("pause"));
+        let inner = Region::within_state(playing.id().clone());
+        let _ = inner.new_simple_state();
+        let history = inner.new_deep_history_state();
+        playing.add_region(inner);
+        let playing_id = playing.id().clone();
+        region.add_state(playing);
+        let done = region.new_final_state();
+
+        region.new_transition(initial, playing_id.clone());
+        let mut stop = Transition::within(playing_id.clone(), done.clone(), region.id().clone());
+        stop.set_kind(TransitionKind::External);
+        stop.add_trigger(Trigger::signal("pause"));
+        stop.add_trigger(Trigger::after(Duration::from_millis(1500)));
+        stop.set_guard(Box::new(OpaqueConstraint::new("idle", None)));
+        stop.set_effect(Box::new(OpaqueBehavior::new("log", None)));
+        region.add_transition(stop);
+
+        let xmi = WriteXmi::default().stringify(&machine).unwrap();
+        assert!(xmi.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<xmi:XMI"));
+        assert!(xmi.contains(&format!("xmlns:uml=\"{}\"", UML_NAMESPACE)));
+        assert!(xmi.contains(r#"uml:Model" xmi:id=""#));
+        assert!(xmi.contains(&format!(
+            "<subvertex xmi:type=\"uml:State\" xmi:id=\"{}\" name=\"playing\">",
+            xmi_id(&playing_id)
+        )));
+        assert!(xmi.contains("<body>Plays &lt;audio&gt; &amp; video</body>"));
+        assert!(xmi.contains(r#"<entry xmi:type="uml:OpaqueBehavior""#));
+        assert!(xmi.contains(&format!(
+            "xmi:id=\"{}\" kind=\"deepHistory\"/>",
+            xmi_id(&history)
+        )));
+        assert!(xmi.contains(&format!(
+            "<subvertex xmi:type=\"uml:FinalState\" xmi:id=\"{}\"/>",
+            xmi_id(&done)
+        )));
+        assert!(xmi.contains(&format!(
+            "kind=\"external\" source=\"{}\" target=\"{}\">",
+            xmi_id(&playing_id),
+            xmi_id(&done)
+        )));
+        assert!(xmi.contains("<body>idle</body>"));
+        // the deferred and transition triggers share an event.
+        assert_eq!(xmi.matches("event=\"_event.0\"").count(), 2);
+        assert_eq!(xmi.matches("uml:SignalEvent").count(), 1);
+        assert!(xmi.contains(r#"value="1.5s""#));
+        assert!(xmi.ends_with("  </uml:Model>\n</xmi:XMI>\n"));
+    }
+
+    #[test]
+    fn test_read_round_trip() {
+        let machine = StateMachine::labeled("player");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+        let mut playing = State::within(region.id().clone());
+        playing.set_label("playing");
+        playing.add_comment("Plays audio");
+        playing.set_exit(Box::new(OpaqueBehavior::new("stop", None)));
+        playing.add_deferrable_trigger(Trigger::call("seek"));
+        let _ = playing.new_region();
+        let _ = playing.new_region();
+        let playing_id = playing.id().clone();
+        region.add_state(playing);
+        let choice = region.new_choice_state();
+        region.new_transition(initial, playing_id.clone());
+        let mut transition =
+            Transition::within(playing_id.clone(), choice.clone(), region.id().clone());
+        transition.set_kind(TransitionKind::Local);
+        transition.add_trigger(Trigger::after(Duration::from_millis(250)));
+        transition.add_trigger(Trigger::any());
+        transition.set_guard(Box::new(OpaqueConstraint::new("done", None)));
+        region.add_transition(transition);
+        region.new_transition(choice, playing_id);
+
+        let written = WriteXmi::default().stringify(&machine).unwrap();
+        let read = ReadXmi::default().parse(&written).unwrap();
+        assert_eq!(read.label(), &Some("player".to_string()));
+        let region = read.default_region().unwrap();
+        assert_eq!(region.vertices().len(), 3);
+        let playing = region.vertices()[1].clone();
+        let playing = playing.as_state().unwrap();
+        assert_eq!(playing.regions().len(), 2);
+        assert_eq!(playing.comments().collect::<Vec<_>>(), vec!["Plays audio"]);
+        assert_eq!(
+            playing.deferrable_triggers().next(),
+            Some(&Trigger::call("seek"))
+        );
+        let transition = region.transitions()[1].clone();
+        assert_eq!(transition.kind(), TransitionKind::Local);
+        assert_eq!(
+            transition.triggers().collect::<Vec<_>>(),
+            vec![&Trigger::after(Duration::from_millis(250)), &Trigger::any()]
+        );
+        assert_eq!(
+            transition.guard().as_ref().unwrap().label(),
+            &Some("done".to_string())
+        );
+
+        // identifiers differ, but the documents are otherwise the same.
+        let rewritten = WriteXmi::default().stringify(&read).unwrap();
+        let shape = |xmi: &str| {
+            xmi.lines()
+                .map(|line| line.split(" xmi:id=").next().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(shape(&written), shape(&rewritten));
+    }
+
+    #[test]
+    fn test_read_references() {
+        let xmi = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xmi:XMI xmi:version="2.1" xmlns:xmi="http://schema.omg.org/spec/XMI/2.1" xmlns:uml="http://schema.omg.org/spec/UML/2.1">
+  <uml:Model xmi:type="uml:Model" xmi:id="model" name="Orders">
+    <packagedElement xmi:type="uml:Class" xmi:id="order" name="Order">
+      <ownedBehavior xmi:type="uml:StateMachine" xmi:id="sm" name="Lifecycle">
+        <ownedRule xmi:type="uml:Constraint" xmi:id="paid">
+          <specification xmi:type="uml:OpaqueExpression" xmi:id="paid.spec">
+            <body>self.balance = 0</body>
+            <language>OCL</language>
+          </specification>
+        </ownedRule>
+        <region xmi:type="uml:Region" xmi:id="r">
+          <subvertex xmi:type="uml:Pseudostate" xmi:id="start"/>
+          <subvertex xmi:type="uml:State" xmi:id="open" name="Open">
+            <entry xmi:type="uml:OpaqueBehavior" xmi:id="open.entry">
+              <body>notify()</body>
+            </entry>
+          </subvertex>
+          <subvertex xmi:type="uml:FinalState" xmi:id="closed" name="Closed"/>
+          <transition xmi:type="uml:Transition" xmi:id="t1" source="start" target="open"/>
+          <transition xmi:type="uml:Transition" xmi:id="t2" guard="paid">
+            <source xmi:idref="open"/>
+            <target xmi:idref="closed"/>
+            <trigger xmi:type="uml:Trigger" xmi:id="t2.trigger" event="close.event"/>
+          </transition>
+        </region>
+      </ownedBehavior>
+    </packagedElement>
+    <packagedElement xmi:type="uml:Signal" xmi:id="close" name="Close"/>
+    <packagedElement xmi:type="uml:SignalEvent" xmi:id="close.event" signal="close"/>
+  </uml:Model>
+</xmi:XMI>"#;
+        let machine = ReadXmi::default().parse(xmi).unwrap();
+        assert_eq!(machine.label(), &Some("Lifecycle".to_string()));
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.vertices().len(), 3);
+        let open = region.vertices()[1].clone();
+        assert_eq!(
+            open.as_state().unwrap().entry().as_ref().unwrap().label(),
+            &Some("notify()".to_string())
+        );
+        assert!(region.vertices()[2].as_state().unwrap().is_final());
+        let close = region.transitions()[1].clone();
+        assert_eq!(close.source(), open.id().clone());
+        assert_eq!(
+            close.triggers().collect::<Vec<_>>(),
+            vec![&Trigger::signal("Close")]
+        );
+        assert_eq!(
+            close.guard().as_ref().unwrap().label(),
+            &Some("self.balance = 0".to_string())
+        );
+
+        let missing = xmi.replace(r#"target="open""#, r#"target="nowhere""#);
+        assert!(ReadXmi::default().parse(&missing).is_err());
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let machine = StateMachine::labeled("door");
+        let region = machine.default_region().unwrap();
+        let mut closed = State::within(region.id().clone());
+        closed.set_label("closed");
+        set_layout(&mut closed, Layout::at(40.0, 25.5).with_size(120.0, 50.0));
+        let closed_id = closed.id().clone();
+        region.add_state(closed);
+        let mut opened = State::within(region.id().clone());
+        opened.set_label("opened");
+        set_layout(&mut opened, Layout::at(200.0, 25.0));
+        region.add_state(opened);
+        let _ = region.new_simple_state();
+
+        let xmi = WriteXmi::default().stringify(&machine).unwrap();
+        assert!(xmi.contains(&format!("xmlns:umldi=\"{}\"", UMLDI_NAMESPACE)));
+        assert!(xmi.contains(&format!(
+            "modelElement=\"{}\"><bounds xmi:type=\"dc:Bounds\" x=\"40\" y=\"25.5\" width=\"120\" height=\"50\"/>",
+            xmi_id(&closed_id)
+        )));
+        assert_eq!(xmi.matches("umldi:UMLShape").count(), 2);
+        assert!(xmi.ends_with("  </umldi:UMLDiagram>\n</xmi:XMI>\n"));
+
+        let read = ReadXmi::default().parse(&xmi).unwrap();
+        let layouts = read
+            .default_region()
+            .unwrap()
+            .vertices()
+            .iter()
+            .map(|vertex| layout(vertex.as_state().unwrap().metadata()))
+            .collect::<Vec<Option<Layout>>>();
+        assert_eq!(
+            layouts,
+            vec![
+                Some(Layout::at(40.0, 25.5).with_size(120.0, 50.0)),
+                Some(Layout::at(200.0, 25.0)),
+                None
+            ]
+        );
+
+        let plain = StateMachine::labeled("plain");
+        let _ = plain.default_region().unwrap().new_simple_state();
+        let xmi = WriteXmi::default().stringify(&plain).unwrap();
+        assert!(!xmi.contains("UMLDiagram"));
+    }
+
+    #[test]
+    fn test_xmi_ids() {
+        let namespace: ID = "billing".parse().unwrap();
+        let id = ID::random_in_namespace(&namespace, "state").unwrap();
+        let xmi = xmi_id(&id);
+        assert!(xmi.starts_with("_billing.state.."));
+        assert!(!xmi.contains(':') && !xmi.contains('/'));
+    }
+}
@@ -1,35 +1,1260 @@
 /*!
-Writes a state machine out in the [JavaScript state machines and StateMachines (XState)](https://xstate.js.org/)
-format. Does not yet support parsing XState.
+Reads and writes a state machine as a [JavaScript state machines and StateCharts (XState)](https://xstate.js.org/)
+machine configuration, in JSON.
+
+The model is mapped onto the configuration as follows, by both the reader and writer.
+
+* The machine is the root state node, with its name as the `id`.
+* Each state is a state node keyed by its name, the node `type` is `final` for final states and
+  `parallel` for orthogonal states. A region of an orthogonal state is a child state node unless
+  it holds a single state, in which case that state is the child.
+* Entry and exit behaviors are the `entry` and `exit` actions, and a do-activity is an `invoke`
+  of the service named by its label.
+* An initial pseudostate is the `initial` of its parent, history pseudostates are `history`
+  state nodes, and choice and junction pseudostates are transient state nodes.
+* Transitions are listed under `on` for signal, call and any-receive (`*`) triggers, under `after`
+  for relative time triggers, and under `always` for triggerless transitions. Guards and effects
+  are emitted by label, as `guard` and `actions` (`cond` rather than `guard` for version 4).
+  A transition to a sibling is targeted by key, otherwise by `#id` with the target given an
+  `id`.
+
+A name is the element's label where it is unique in the machine, otherwise its model ID, with
+`.` and `#` replaced. Fork, join, entry and exit point, and terminate pseudostates, connection
+point references, absolute time and change triggers, payload predicates, and unlabeled guards
+or behaviors cannot be written and result in an error.
+
+When reading, guards and actions become [`OpaqueConstraint`](../../definition/opaque/struct.OpaqueConstraint.html)
+and [`OpaqueBehavior`](../../definition/opaque/struct.OpaqueBehavior.html) values labeled with
+their name; where a state or transition lists more than one action they are combined into a
+single behavior labeled with the names separated by `, `. Targets may be sibling keys, `.child`
+paths relative to the source, or `#id` references optionally followed by a path. A targeted
+transition is local if it is marked `internal` (version 4), or its target is the source or a
+descendant and it is not marked `reenter` (version 5); otherwise it is external. Transitions
+with more than one target, the array form of `on`, and named delays are not supported.
+
+The layout of a state, as an object with the members `x`, `y`, and optionally `width` and `height`
+under the `layout` of the state node's `meta`, is kept as its [`Layout`](../layout/struct.Layout.html)
+and written back in the same form.
 
 # Example
 
-TBD
+```rust
+use uml_state_machine::definition::types::*;
+use uml_state_machine::format::xstate::{ReadXState, WriteXState};
+use uml_state_machine::format::{Parse, Stringify};
+
+let machine = StateMachine::labeled("light");
+let region = machine.default_region().unwrap();
+let initial_id = region.new_initial_state();
+let mut off = State::within(region.id().clone());
+off.set_label("off");
+let off_id = off.id().clone();
+region.add_state(off);
+let mut on = State::within(region.id().clone());
+on.set_label("on");
+let on_id = on.id().clone();
+region.add_state(on);
+region.new_transition(initial_id, off_id.clone());
+let mut transition = Transition::within(off_id, on_id, region.id().clone());
+transition.add_trigger(Trigger::signal("TOGGLE"));
+region.add_transition(transition);
+
+let config = WriteXState::default().stringify(&machine).unwrap();
+assert!(config.contains(r#""initial": "off""#));
+assert!(config.contains(r#""TOGGLE""#));
+
+let read = ReadXState::default().parse(&config).unwrap();
+assert_eq!(read.label(), &Some("light".to_string()));
+assert_eq!(WriteXState::default().stringify(&read).unwrap(), config);
+```
 
 */
 
-// use ...
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::slice::Iter;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::core::{FieldValue, ID};
+use crate::definition::guards::recognize_else;
+use crate::definition::opaque::{OpaqueBehavior, OpaqueConstraint};
+use crate::definition::types::{
+    Behavior, Constraint, Event, HasRegions, Identified, Labeled, PseudoState, PseudoStateKind,
+    Region, State, StateMachine, TimeEvent, Transition, TransitionKind, Trigger,
+};
+use crate::definition::visitor::{visit_state_machine, Resolver, StateMachineVisitor};
+use crate::error::{Error, ErrorKind, Result};
+use crate::format::layout::{layout, set_layout, Layout};
+use crate::format::names::unique_names;
+use crate::format::{Parse, Write};
+use std::io;
 
 // ------------------------------------------------------------------------------------------------
 // Public Types
 // ------------------------------------------------------------------------------------------------
 
+///
+/// The major version of XState whose configuration format is written.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum XStateVersion {
+    V4,
+    #[default]
+    V5,
+}
+
+///
+/// Implements Write, and so Stringify, for XState.
+///
+#[derive(Debug, Default)]
+pub struct WriteXState {
+    version: XStateVersion,
+}
+
+///
+/// Implements Parse for XState, accepting both version 4 and 5 configurations.
+///
+#[derive(Debug, Default)]
+pub struct ReadXState {}
+
 // ------------------------------------------------------------------------------------------------
-// Public Functions
+// Implementations
 // ------------------------------------------------------------------------------------------------
 
+impl WriteXState {
+    pub fn for_version(version: XStateVersion) -> Self {
+        Self { version }
+    }
+
+    pub fn version(&self) -> XStateVersion {
+        self.version
+    }
+}
+
+impl Write for WriteXState {
+    type Error = Error;
+
+    fn write(&self, machine: &StateMachine, w: &mut dyn io::Write) -> Result<()> {
+        let names = unique_names(machine, xstate_name)?;
+        let writer = Writer {
+            version: self.version,
+            is_orthogonal: machine.regions().len() > 1,
+            names,
+            nodes: Default::default(),
+            parents: Default::default(),
+            vertices: Default::default(),
+            initials: Default::default(),
+            transitions: Default::default(),
+            current_state: Default::default(),
+            error: Default::default(),
+        };
+        visit_state_machine(machine, &writer)?;
+        if let Some(error) = writer.error.take() {
+            return Err(error);
+        }
+        let config = writer.into_config()?;
+        serde_json::to_writer_pretty(&mut *w, &config).map_err(io::Error::from)?;
+        Ok(w.flush()?)
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
-// Implementations
+
+impl Parse for ReadXState {
+    type Error = Error;
+
+    fn parse(&self, string: &str) -> Result<StateMachine> {
+        let config: Value =
+            serde_json::from_str(string).map_err(|e| parse_error(&e.to_string()))?;
+        let config = as_object(&config, "the machine")?;
+        let mut machine = match config.get("id").and_then(Value::as_str) {
+            None => StateMachine::default(),
+            Some(id) => StateMachine::labeled(id),
+        };
+
+        let mut reader = Reader::default();
+        let root = reader.add_node("", None, config, None, None);
+        if config.get("type").and_then(Value::as_str) == Some("parallel") {
+            let states = child_states(config)?;
+            for _ in 1..states.len() {
+                let _ = machine.new_region();
+            }
+            for (index, (key, child)) in states.into_iter().enumerate() {
+                reader.read_parallel_child(root, key, child, machine.region(index).unwrap())?;
+            }
+        } else {
+            reader.read_region(root, config, machine.default_region().unwrap())?;
+        }
+        reader.add_transitions(&machine)?;
+        Ok(machine)
+    }
+}
+
+impl<'a> Reader<'a> {
+    fn add_node(
+        &mut self,
+        key: &str,
+        parent: Option<usize>,
+        config: &'a Map<String, Value>,
+        vertex: Option<ID>,
+        container: Option<ID>,
+    ) -> usize {
+        self.nodes.push(ParsedNode {
+            key: key.to_string(),
+            parent,
+            children: Default::default(),
+            config,
+            vertex,
+            container,
+        });
+        let index = self.nodes.len() - 1;
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        if let Some(id) = config.get("id").and_then(Value::as_str) {
+            let _ = self.ids.insert(id.to_string(), index);
+        }
+        index
+    }
+
+    fn read_region(
+        &mut self,
+        parent: usize,
+        config: &'a Map<String, Value>,
+        region: &Region,
+    ) -> Result<()> {
+        let mut default_state: Option<ID> = None;
+        for (key, child) in child_states(config)? {
+            if child.get("type").and_then(Value::as_str) == Some("history") {
+                let kind = match child.get("history").and_then(Value::as_str) {
+                    Some("deep") => PseudoStateKind::DeepHistory,
+                    _ => PseudoStateKind::ShallowHistory,
+                };
+                let mut pseudo_state = PseudoState::within(region.id().clone(), kind);
+                pseudo_state.set_label(key);
+                let _ = self.add_node(
+                    key,
+                    Some(parent),
+                    child,
+                    Some(pseudo_state.id().clone()),
+                    Some(region.id().clone()),
+                );
+                region.add_pseudo_state(pseudo_state);
+            } else {
+                let state = self.read_state(parent, key, child, region.id())?;
+                if default_state.is_none() {
+                    default_state = Some(state.id().clone());
+                }
+                region.add_state(state);
+            }
+        }
+
+        if let Some(initial) = config.get("initial") {
+            let initial = initial
+                .as_str()
+                .ok_or_else(|| parse_error("`initial` must be a string"))?;
+            let pseudo_state = region.new_initial_state();
+            self.initials
+                .push((pseudo_state, region.id().clone(), parent, initial));
+        } else if let Some(default_state) = default_state {
+            let initial = region.new_initial_state();
+            let mut transition = Transition::within(initial, default_state, region.id().clone());
+            transition.set_kind(TransitionKind::External);
+            region.add_transition(transition);
+        }
+        Ok(())
+    }
+
+    fn read_parallel_child(
+        &mut self,
+        parent: usize,
+        key: &'a str,
+        config: &'a Map<String, Value>,
+        region: &Region,
+    ) -> Result<()> {
+        let state = self.read_state(parent, key, config, region.id())?;
+        let state_id = state.id().clone();
+        region.add_state(state);
+        let initial = region.new_initial_state();
+        let mut transition = Transition::within(initial, state_id, region.id().clone());
+        transition.set_kind(TransitionKind::External);
+        region.add_transition(transition);
+        Ok(())
+    }
+
+    fn read_state(
+        &mut self,
+        parent: usize,
+        key: &'a str,
+        config: &'a Map<String, Value>,
+        container: &ID,
+    ) -> Result<State> {
+        let mut state = State::within(container.clone());
+        state.set_label(key);
+        let node = self.add_node(
+            key,
+            Some(parent),
+            config,
+            Some(state.id().clone()),
+            Some(container.clone()),
+        );
+        match config.get("type").and_then(Value::as_str) {
+            Some("final") => state.set_final(true),
+            Some("parallel") => {
+                for (key, child) in child_states(config)? {
+                    let region = Region::within_state(state.id().clone());
+                    self.read_parallel_child(node, key, child, &region)?;
+                    state.add_region(region);
+                }
+            }
+            _ => {
+                if !child_states(config)?.is_empty() {
+                    let region = Region::within_state(state.id().clone());
+                    self.read_region(node, config, &region)?;
+                    state.add_region(region);
+                }
+            }
+        }
+        if let Some(entry) = config.get("entry") {
+            state.set_entry(behavior(entry)?);
+        }
+        if let Some(exit) = config.get("exit") {
+            state.set_exit(behavior(exit)?);
+        }
+        if let Some(invoke) = config.get("invoke") {
+            state.set_do_activity(invocation(invoke)?);
+        }
+        if let Some(layout) = meta_layout(config) {
+            set_layout(&mut state, layout);
+        }
+        Ok(state)
+    }
+
+    fn add_transitions(&self, machine: &StateMachine) -> Result<()> {
+        let mut transitions: HashMap<ID, Vec<Transition>> = Default::default();
+        for (initial, container, parent, target) in &self.initials {
+            let target = self.resolve(*parent, target)?;
+            let mut transition = Transition::within(
+                initial.clone(),
+                self.vertex(target)?.clone(),
+                container.clone(),
+            );
+            transition.set_kind(TransitionKind::External);
+            transitions
+                .entry(container.clone())
+                .or_default()
+                .push(transition);
+        }
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let Some(container) = &node.container {
+                let node_transitions = self.node_transitions(index, container)?;
+                transitions
+                    .entry(container.clone())
+                    .or_default()
+                    .extend(node_transitions);
+            }
+        }
+        machine.for_each_region(&mut |region| {
+            for transition in transitions.remove(region.id()).unwrap_or_default() {
+                region.add_transition(transition);
+            }
+        });
+        Ok(())
+    }
+
+    fn node_transitions(&self, index: usize, container: &ID) -> Result<Vec<Transition>> {
+        let config = self.nodes[index].config;
+        let mut transitions: Vec<Transition> = Default::default();
+        if config.get("type").and_then(Value::as_str) == Some("history") {
+            if let Some(target) = config.get("target") {
+                let target = json!({ "target": target });
+                transitions.push(self.transition(index, container, None, &target)?);
+            }
+            return Ok(transitions);
+        }
+        if let Some(on) = config.get("on") {
+            let on = on
+                .as_object()
+                .ok_or_else(|| unsupported_error("the array form of `on`"))?;
+            for (event, value) in on {
+                let trigger = match event.as_str() {
+                    "" => None,
+                    "*" => Some(Trigger::any()),
+                    event => Some(Trigger::signal(event)),
+                };
+                for value in one_or_many(value) {
+                    transitions.push(self.transition(index, container, trigger.clone(), value)?);
+                }
+            }
+        }
+        if let Some(after) = config.get("after") {
+            for (delay, value) in as_object(after, "`after`")? {
+                let delay: u64 = delay
+                    .parse()
+                    .map_err(|_| unsupported_error(&format!("the named delay `{}`", delay)))?;
+                let trigger = Trigger::after(Duration::from_millis(delay));
+                for value in one_or_many(value) {
+                    transitions.push(self.transition(
+                        index,
+                        container,
+                        Some(trigger.clone()),
+                        value,
+                    )?);
+                }
+            }
+        }
+        if let Some(always) = config.get("always") {
+            for value in one_or_many(always) {
+                transitions.push(self.transition(index, container, None, value)?);
+            }
+        }
+        Ok(transitions)
+    }
+
+    fn transition(
+        &self,
+        source: usize,
+        container: &ID,
+        trigger: Option<Trigger>,
+        value: &Value,
+    ) -> Result<Transition> {
+        let config = match value {
+            Value::String(_) => None,
+            value => Some(as_object(value, "a transition")?),
+        };
+        let target = match value {
+            Value::String(target) => Some(target.as_str()),
+            _ => match config.and_then(|config| config.get("target")) {
+                None | Some(Value::Null) => None,
+                Some(Value::String(target)) => Some(target.as_str()),
+                Some(Value::Array(targets)) if targets.len() == 1 => targets[0].as_str(),
+                Some(Value::Array(targets)) if targets.is_empty() => None,
+                Some(_) => return Err(unsupported_error("transitions with multiple targets")),
+            },
+        };
+        let flag = |name: &str| {
+            config
+                .and_then(|config| config.get(name))
+                .and_then(Value::as_bool)
+        };
+
+        let source_id = self.vertex(source)?.clone();
+        let mut transition = match target {
+            None => {
+                let mut transition =
+                    Transition::within(source_id.clone(), source_id, container.clone());
+                transition.set_kind(TransitionKind::Internal);
+                transition
+            }
+            Some(target) => {
+                let target = self.resolve_target(source, target)?;
+                let kind = match (flag("internal"), flag("reenter")) {
+                    (Some(true), _) => TransitionKind::Local,
+                    (_, Some(true)) | (Some(false), _) => TransitionKind::External,
+                    _ if self.is_descendant_or_self(target, source) => TransitionKind::Local,
+                    _ => TransitionKind::External,
+                };
+                let mut transition =
+                    Transition::within(source_id, self.vertex(target)?.clone(), container.clone());
+                transition.set_kind(kind);
+                transition
+            }
+        };
+        if let Some(trigger) = trigger {
+            transition.add_trigger(trigger);
+        }
+        if let Some(config) = config {
+            if let Some(guard) = config.get("guard").or_else(|| config.get("cond")) {
+                let name = named(guard, "a guard")?;
+                transition.set_guard(recognize_else(Box::new(OpaqueConstraint::new(&name, None))));
+            }
+            if let Some(actions) = config.get("actions") {
+                transition.set_effect(behavior(actions)?);
+            }
+        }
+        Ok(transition)
+    }
+
+    fn resolve_target(&self, source: usize, target: &str) -> Result<usize> {
+        if let Some(reference) = target.strip_prefix('#') {
+            let mut segments = reference.split('.');
+            let id = segments.next().unwrap_or_default();
+            let node = *self
+                .ids
+                .get(id)
+                .ok_or_else(|| parse_error(&format!("unknown state id `#{}`", id)))?;
+            self.descend(node, segments, target)
+        } else if let Some(path) = target.strip_prefix('.') {
+            self.descend(source, path.split('.'), target)
+        } else {
+            match self.nodes[source].parent {
+                None => Err(parse_error(&format!("unknown target `{}`", target))),
+                Some(parent) => self.resolve(parent, target),
+            }
+        }
+    }
+
+    fn resolve(&self, parent: usize, path: &str) -> Result<usize> {
+        self.descend(parent, path.split('.'), path)
+    }
+
+    fn descend<'b>(
+        &self,
+        from: usize,
+        segments: impl Iterator<Item = &'b str>,
+        target: &str,
+    ) -> Result<usize> {
+        let mut node = from;
+        for segment in segments {
+            node = *self.nodes[node]
+                .children
+                .iter()
+                .find(|child| self.nodes[**child].key == segment)
+                .ok_or_else(|| parse_error(&format!("unknown target `{}`", target)))?;
+        }
+        Ok(node)
+    }
+
+    fn is_descendant_or_self(&self, node: usize, ancestor: usize) -> bool {
+        let mut current = Some(node);
+        while let Some(index) = current {
+            if index == ancestor {
+                return true;
+            }
+            current = self.nodes[index].parent;
+        }
+        false
+    }
+
+    fn vertex(&self, node: usize) -> Result<&ID> {
+        self.nodes[node]
+            .vertex
+            .as_ref()
+            .ok_or_else(|| Error::from(ErrorKind::TransitionTargetState))
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 
+impl StateMachineVisitor for Writer {
+    fn enter_state_machine(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        _label: &Option<String>,
+        _machine_states: Iter<'_, ID>,
+        _connection_points: Iter<'_, PseudoState>,
+    ) {
+        let mut root = StateNode::new(&self.names[id], None);
+        root.set("id", json!(self.names[id]));
+        if self.is_orthogonal {
+            root.set("type", json!("parallel"));
+        }
+        let root = self.add_node(root);
+        self.parents.borrow_mut().push(root);
+    }
+
+    fn enter_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        entry: &Option<Box<dyn Behavior>>,
+        do_activity: &Option<Box<dyn Behavior>>,
+        exit: &Option<Box<dyn Behavior>>,
+        is_final: bool,
+    ) {
+        let mut node = StateNode::new(&self.names[id], Some(self.parent()));
+        if is_final {
+            node.set("type", json!("final"));
+        } else if region_count > 1 {
+            node.set("type", json!("parallel"));
+        }
+        for (name, behavior) in &[("entry", entry), ("exit", exit)] {
+            if let Some(behavior) = behavior {
+                match behavior.label() {
+                    None => self.fail(unsupported_error("behaviors without a label")),
+                    Some(label) => node.set(name, json!([label])),
+                }
+            }
+        }
+        if let Some(do_activity) = do_activity {
+            match do_activity.label() {
+                None => self.fail(unsupported_error("behaviors without a label")),
+                Some(label) => node.set("invoke", json!({ "src": label })),
+            }
+        }
+        let node = self.add_node(node);
+        let _ = self.vertices.borrow_mut().insert(id.clone(), node);
+        self.parents.borrow_mut().push(node);
+        let _ = self.current_state.borrow_mut().replace(node);
+    }
+
+    fn exit_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _comments: Iter<'_, String>,
+        _region_count: usize,
+        _sub_machine: &Option<ID>,
+        _connections: Iter<'_, ID>,
+        _connection_points: Iter<'_, ID>,
+        _deferrable_triggers: Iter<'_, Trigger>,
+        _invariant: &Option<Box<dyn Constraint>>,
+        _entry: &Option<Box<dyn Behavior>>,
+        _do_activity: &Option<Box<dyn Behavior>>,
+        _exit: &Option<Box<dyn Behavior>>,
+        _is_final: bool,
+    ) {
+        let _ = self.current_state.borrow_mut().take();
+        let _ = self.parents.borrow_mut().pop();
+    }
+
+    fn enter_region(&self, _resolver: &Resolver<'_>, id: &ID, _label: &Option<String>, _: bool) {
+        let _ = self.current_state.borrow_mut().take();
+        let parent = self.parent();
+        if self.nodes.borrow()[parent].fields.get("type") == Some(&json!("parallel")) {
+            let mut node = StateNode::new(&self.names[id], Some(parent));
+            node.is_region = true;
+            let node = self.add_node(node);
+            self.parents.borrow_mut().push(node);
+        } else {
+            self.parents.borrow_mut().push(parent);
+        }
+    }
+
+    fn exit_region(&self, _resolver: &Resolver<'_>, _id: &ID, _label: &Option<String>, _: bool) {
+        let _ = self.parents.borrow_mut().pop();
+    }
+
+    fn connection_point_reference(
+        &self,
+        _resolver: &Resolver<'_>,
+        _id: &ID,
+        _label: &Option<String>,
+        _entry: Iter<'_, ID>,
+        _exit: Iter<'_, ID>,
+        _state: &Option<ID>,
+    ) {
+        self.fail(unsupported_error("connection point references"))
+    }
+
+    fn pseudo_state(
+        &self,
+        _resolver: &Resolver<'_>,
+        id: &ID,
+        _label: &Option<String>,
+        kind: &PseudoStateKind,
+    ) {
+        let mut node = StateNode::new(&self.names[id], Some(self.parent()));
+        match kind {
+            PseudoStateKind::Initial => {
+                let _ = self.initials.borrow_mut().insert(id.clone(), self.parent());
+                return;
+            }
+            PseudoStateKind::DeepHistory => {
+                node.set("type", json!("history"));
+                node.set("history", json!("deep"));
+            }
+            PseudoStateKind::ShallowHistory => {
+                node.set("type", json!("history"));
+                node.set("history", json!("shallow"));
+            }
+            PseudoStateKind::Choice | PseudoStateKind::Junction => {}
+            _ => return self.fail(unsupported_error(&format!("{:?} pseudostates", kind))),
+        }
+        let node = self.add_node(node);
+        let _ = self.vertices.borrow_mut().insert(id.clone(), node);
+    }
+
+    fn transition(
+        &self,
+        _resolver: &Resolver<'_>,
+        _label: &Option<String>,
+        kind: TransitionKind,
+        source: ID,
+        target: ID,
+        triggers: Iter<'_, Trigger>,
+        guard: &Option<Box<dyn Constraint>>,
+        effect: &Option<Box<dyn Behavior>>,
+    ) {
+        let _ = self.current_state.borrow_mut().take();
+        let mut events: Vec<(&'static str, String)> = Default::default();
+        for trigger in triggers {
+            if trigger.payload_predicates().next().is_some() {
+                return self.fail(unsupported_error("trigger payload predicates"));
+            }
+            events.push(match trigger.event() {
+                Event::Signal(event) => ("on", event.signal().clone()),
+                Event::Call(event) => ("on", event.operation().clone()),
+                Event::AnyReceive => ("on", "*".to_string()),
+                Event::Time(TimeEvent::Relative(duration)) => {
+                    ("after", duration.as_millis().to_string())
+                }
+                event => return self.fail(unsupported_error(&format!("`{}` triggers", event))),
+            });
+        }
+        if events.is_empty() {
+            events.push(("always", String::new()));
+        }
+
+        let mut fields: Map<String, Value> = Default::default();
+        if let Some(guard) = guard {
+            match guard.label() {
+                None => return self.fail(unsupported_error("guards without a label")),
+                Some(label) => {
+                    let name = match self.version {
+                        XStateVersion::V4 => "cond",
+                        XStateVersion::V5 => "guard",
+                    };
+                    let _ = fields.insert(name.to_string(), json!(label));
+                }
+            }
+        }
+        if let Some(effect) = effect {
+            match effect.label() {
+                None => return self.fail(unsupported_error("behaviors without a label")),
+                Some(label) => {
+                    let _ = fields.insert("actions".to_string(), json!([label]));
+                }
+            }
+        }
+        self.transitions.borrow_mut().push(PendingTransition {
+            source,
+            target,
+            kind,
+            events,
+            fields,
+        });
+    }
+
+    fn metadata(&self, _resolver: &Resolver<'_>, metadata: &HashMap<String, FieldValue>) {
+        let state = self.current_state.borrow_mut().take();
+        if let (Some(state), Some(layout)) = (state, layout(metadata)) {
+            let mut value = json!({ "x": layout.x, "y": layout.y });
+            if let (Some(width), Some(height)) = (layout.width, layout.height) {
+                value["width"] = json!(width);
+                value["height"] = json!(height);
+            }
+            self.nodes.borrow_mut()[state].set("meta", json!({ "layout": value }));
+        }
+    }
+}
+
+impl Writer {
+    fn parent(&self) -> usize {
+        *self.parents.borrow().last().unwrap()
+    }
+
+    fn add_node(&self, node: StateNode) -> usize {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(node);
+        let index = nodes.len() - 1;
+        if let Some(parent) = nodes[index].parent {
+            nodes[parent].children.push(index);
+        }
+        index
+    }
+
+    fn fail(&self, error: Error) {
+        let mut current = self.error.borrow_mut();
+        if current.is_none() {
+            *current = Some(error);
+        }
+    }
+
+    fn into_config(self) -> Result<Value> {
+        let mut nodes = self.nodes.into_inner();
+        let vertices = self.vertices.into_inner();
+        let initials = self.initials.into_inner();
+        let mut referenced: HashSet<usize> = Default::default();
+
+        for transition in self.transitions.into_inner() {
+            let target_node = *vertices
+                .get(&transition.target)
+                .ok_or_else(|| Error::from(ErrorKind::TransitionTargetState))?;
+            if let Some(parent) = initials.get(&transition.source) {
+                let key = nodes[target_node].key.clone();
+                nodes[*parent].set("initial", json!(key));
+                continue;
+            }
+            let source_node = *vertices
+                .get(&transition.source)
+                .ok_or_else(|| unsupported_error("transitions from outside their region"))?;
+
+            let mut fields = transition.fields;
+            let is_self = transition.source == transition.target;
+            if !(is_self && transition.kind == TransitionKind::Internal) {
+                let target = if effective_parent(&nodes, source_node)
+                    == effective_parent(&nodes, target_node)
+                {
+                    nodes[target_node].key.clone()
+                } else {
+                    let _ = referenced.insert(target_node);
+                    format!("#{}", nodes[target_node].key)
+                };
+                let _ = fields.insert("target".to_string(), json!(target));
+                match (self.version, transition.kind) {
+                    (XStateVersion::V4, TransitionKind::Local) => {
+                        let _ = fields.insert("internal".to_string(), json!(true));
+                    }
+                    (XStateVersion::V5, TransitionKind::External) if is_self => {
+                        let _ = fields.insert("reenter".to_string(), json!(true));
+                    }
+                    _ => {}
+                }
+            }
+
+            let node = &mut nodes[source_node];
+            if node.fields.get("type") == Some(&json!("history")) {
+                if let Some(target) = fields.remove("target") {
+                    node.set("target", target);
+                }
+                continue;
+            }
+            for (property, event) in transition.events {
+                let list = if property == "always" {
+                    node.fields
+                        .entry(property.to_string())
+                        .or_insert_with(|| json!([]))
+                } else {
+                    node.fields
+                        .entry(property.to_string())
+                        .or_insert_with(|| json!({}))
+                        .as_object_mut()
+                        .unwrap()
+                        .entry(event)
+                        .or_insert_with(|| json!([]))
+                };
+                list.as_array_mut()
+                    .unwrap()
+                    .push(Value::Object(fields.clone()));
+            }
+        }
+
+        for node in referenced {
+            let key = nodes[node].key.clone();
+            nodes[node].set("id", json!(key));
+        }
+        Ok(node_config(&nodes, 0))
+    }
+}
+
+impl StateNode {
+    fn new(key: &str, parent: Option<usize>) -> Self {
+        Self {
+            key: key.to_string(),
+            parent,
+            fields: Default::default(),
+            children: Default::default(),
+            is_region: false,
+        }
+    }
+
+    fn set(&mut self, name: &str, value: Value) {
+        let _ = self.fields.insert(name.to_string(), value);
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Types
 // ------------------------------------------------------------------------------------------------
 
+#[derive(Default)]
+struct Reader<'a> {
+    nodes: Vec<ParsedNode<'a>>,
+    /// Explicit `id`s to the node that declared them.
+    ids: HashMap<String, usize>,
+    /// The initial pseudostate, its region, the parent node and `initial` key of each region.
+    initials: Vec<(ID, ID, usize, &'a str)>,
+}
+
+struct ParsedNode<'a> {
+    key: String,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    config: &'a Map<String, Value>,
+    /// The model vertex for this state node, not present for the root.
+    vertex: Option<ID>,
+    /// The region containing the vertex.
+    container: Option<ID>,
+}
+
+struct Writer {
+    version: XStateVersion,
+    is_orthogonal: bool,
+    names: HashMap<ID, String>,
+    /// All state nodes written, the root node is at index 0.
+    nodes: RefCell<Vec<StateNode>>,
+    parents: RefCell<Vec<usize>>,
+    vertices: RefCell<HashMap<ID, usize>>,
+    /// Initial pseudostates to the node that will take the `initial` property.
+    initials: RefCell<HashMap<ID, usize>>,
+    /// Transitions are resolved once all nodes are known, as targets are relative.
+    transitions: RefCell<Vec<PendingTransition>>,
+    /// The node of the state just entered, until its metadata, or a region, is visited.
+    current_state: RefCell<Option<usize>>,
+    error: RefCell<Option<Error>>,
+}
+
+struct StateNode {
+    key: String,
+    parent: Option<usize>,
+    fields: Map<String, Value>,
+    children: Vec<usize>,
+    /// A node for a region in an orthogonal state.
+    is_region: bool,
+}
+
+struct PendingTransition {
+    source: ID,
+    target: ID,
+    kind: TransitionKind,
+    /// The property, `on`, `after` or `always`, and event for each trigger.
+    events: Vec<(&'static str, String)>,
+    fields: Map<String, Value>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // Private Functions
 // ------------------------------------------------------------------------------------------------
 
+fn is_collapsed(nodes: &[StateNode], index: usize) -> bool {
+    nodes[index].is_region && nodes[index].children.len() == 1
+}
+
+fn effective_parent(nodes: &[StateNode], index: usize) -> Option<usize> {
+    let mut parent = nodes[index].parent;
+    while let Some(index) = parent {
+        if is_collapsed(nodes, index) {
+            parent = nodes[index].parent;
+        } else {
+            break;
+        }
+    }
+    parent
+}
+
+fn node_config(nodes: &[StateNode], index: usize) -> Value {
+    let node = &nodes[index];
+    let mut config = node.fields.clone();
+    if !node.children.is_empty() {
+        let states: Map<String, Value> = node
+            .children
+            .iter()
+            .map(|child| {
+                let child = if is_collapsed(nodes, *child) {
+                    nodes[*child].children[0]
+                } else {
+                    *child
+                };
+                (nodes[child].key.clone(), node_config(nodes, child))
+            })
+            .collect();
+        let _ = config.insert("states".to_string(), Value::Object(states));
+    }
+    Value::Object(config)
+}
+
+fn xstate_name(value: &str) -> String {
+    value.replace(['.', '#'], "_")
+}
+
+fn child_states(config: &Map<String, Value>) -> Result<Vec<(&str, &Map<String, Value>)>> {
+    match config.get("states") {
+        None => Ok(Default::default()),
+        Some(states) => as_object(states, "`states`")?
+            .iter()
+            .map(|(key, child)| Ok((key.as_str(), as_object(child, key)?)))
+            .collect(),
+    }
+}
+
+fn one_or_many(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    }
+}
+
+fn named(value: &Value, what: &str) -> Result<String> {
+    match value {
+        Value::String(name) => Ok(name.clone()),
+        Value::Object(object) => match object.get("type").or_else(|| object.get("src")) {
+            Some(Value::String(name)) => Ok(name.clone()),
+            _ => Err(parse_error(&format!("{} must be named", what))),
+        },
+        _ => Err(parse_error(&format!("{} must be named", what))),
+    }
+}
+
+fn behavior(value: &Value) -> Result<Box<dyn Behavior>> {
+    let names = one_or_many(value)
+        .into_iter()
+        .map(|action| named(action, "an action"))
+        .collect::<Result<Vec<String>>>()?;
+    Ok(Box::new(OpaqueBehavior::new(&names.join(", "), None)))
+}
+
+fn invocation(value: &Value) -> Result<Box<dyn Behavior>> {
+    let names = one_or_many(value)
+        .into_iter()
+        .map(|invoke| match invoke {
+            Value::Object(invoke) => match invoke.get("src") {
+                Some(src) => named(src, "an invoked service"),
+                None => Err(parse_error("an invoke must have a `src`")),
+            },
+            invoke => named(invoke, "an invoked service"),
+        })
+        .collect::<Result<Vec<String>>>()?;
+    Ok(Box::new(OpaqueBehavior::new(&names.join(", "), None)))
+}
+
+///
+/// The layout under the `layout` of a state node's `meta`, which must have a numeric `x` and `y`.
+///
+fn meta_layout(config: &Map<String, Value>) -> Option<Layout> {
+    let value = config.get("meta")?.get("layout")?;
+    let layout = Layout::at(value.get("x")?.as_f64()?, value.get("y")?.as_f64()?);
+    let size = |name: &str| value.get(name).and_then(Value::as_f64);
+    Some(match (size("width"), size("height")) {
+        (Some(width), Some(height)) => layout.with_size(width, height),
+        _ => layout,
+    })
+}
+
+fn as_object<'a>(value: &'a Value, what: &str) -> Result<&'a Map<String, Value>> {
+    value
+        .as_object()
+        .ok_or_else(|| parse_error(&format!("{} must be an object", what)))
+}
+
+fn parse_error(message: &str) -> Error {
+    ErrorKind::FormatParse(message.to_string()).into()
+}
+
+fn unsupported_error(feature: &str) -> Error {
+    ErrorKind::FormatUnsupported(feature.to_string()).into()
+}
+
 // ------------------------------------------------------------------------------------------------
-// Modules
+// Unit Tests
 // ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::types::HasMetadata;
+    use crate::format::Stringify;
+
+    const V4_CONFIG: &str = r##"{
+      "id": "fetch",
+      "initial": "idle",
+      "states": {
+        "idle": { "on": { "FETCH": "loading" } },
+        "loading": {
+          "entry": ["startSpinner", { "type": "logStart" }],
+          "invoke": { "src": "fetchData" },
+          "on": {
+            "RESOLVE": [{ "target": "success", "cond": "hasData" }, { "target": "failure" }],
+            "RETRY": { "target": ".waiting", "internal": true }
+          },
+          "initial": "waiting",
+          "states": { "waiting": {}, "hist": { "type": "history", "history": "deep", "target": "waiting" } },
+          "after": { "5000": "#failed" }
+        },
+        "success": { "type": "final" },
+        "failure": { "id": "failed", "always": [{ "target": "idle", "actions": "reset" }] }
+      }
+    }"##;
+
+    fn state(region: &Region, label: &str) -> State {
+        let mut state = State::within(region.id().clone());
+        state.set_label(label);
+        state
+    }
+
+    fn transition(region: &Region, source: &ID, target: &ID, trigger: Option<Trigger>) {
+        let mut transition =
+            Transition::within(source.clone(), target.clone(), region.id().clone());
+        transition.set_kind(TransitionKind::External);
+        if let Some(trigger) = trigger {
+            transition.add_trigger(trigger);
+        }
+        region.add_transition(transition);
+    }
+
+    #[test]
+    fn test_nested_and_parallel() {
+        let machine = StateMachine::labeled("player");
+        let region = machine.default_region().unwrap();
+        let initial = region.new_initial_state();
+
+        let mut active = state(region, "active");
+        let _ = active.new_region();
+        let _ = active.new_region();
+        let audio = active.region(0).unwrap();
+        let muted = state(audio, "muted");
+        let muted_id = muted.id().clone();
+        audio.add_state(muted);
+        let video = active.region(1).unwrap();
+        let video_initial = video.new_initial_state();
+        let playing = state(video, "playing");
+        let playing_id = playing.id().clone();
+        video.add_state(playing);
+        let paused = state(video, "paused");
+        let paused_id = paused.id().clone();
+        video.add_state(paused);
+        transition(video, &video_initial, &playing_id, None);
+        transition(
+            video,
+            &playing_id,
+            &paused_id,
+            Some(Trigger::signal("PAUSE")),
+        );
+        let active_id = active.id().clone();
+        region.add_state(active);
+
+        let stopped = state(region, "stopped");
+        let stopped_id = stopped.id().clone();
+        region.add_state(stopped);
+        transition(region, &initial, &active_id, None);
+        transition(
+            region,
+            &active_id,
+            &stopped_id,
+            Some(Trigger::signal("STOP")),
+        );
+        transition(
+            region,
+            &stopped_id,
+            &muted_id,
+            Some(Trigger::after(Duration::from_secs(2))),
+        );
+
+        let config: Value =
+            serde_json::from_str(&WriteXState::default().stringify(&machine).unwrap()).unwrap();
+        assert_eq!(config["id"], "player");
+        assert_eq!(config["initial"], "active");
+        let active = &config["states"]["active"];
+        assert_eq!(active["type"], "parallel");
+        assert_eq!(active["on"]["STOP"][0]["target"], "stopped");
+        assert_eq!(active["states"]["muted"]["id"], "muted");
+        let video = active["states"]
+            .as_object()
+            .unwrap()
+            .values()
+            .nth(1)
+            .unwrap();
+        assert_eq!(video["initial"], "playing");
+        assert_eq!(
+            video["states"]["playing"]["on"]["PAUSE"][0]["target"],
+            "paused"
+        );
+        assert_eq!(
+            config["states"]["stopped"]["after"]["2000"][0]["target"],
+            "#muted"
+        );
+    }
+
+    #[test]
+    fn test_read_v4() {
+        let machine = ReadXState::default().parse(V4_CONFIG).unwrap();
+        assert_eq!(machine.label(), &Some("fetch".to_string()));
+        let region = machine.default_region().unwrap();
+        assert_eq!(region.vertices().len(), 5);
+        assert_eq!(region.transitions().len(), 7);
+
+        let loading = region
+            .vertices()
+            .into_iter()
+            .find(|v| v.as_state().and_then(|s| s.label().clone()) == Some("loading".into()))
+            .unwrap();
+        let loading = loading.as_state().unwrap();
+        assert_eq!(
+            loading.entry().as_ref().unwrap().label(),
+            &Some("startSpinner, logStart".to_string())
+        );
+        assert_eq!(
+            loading.do_activity().as_ref().unwrap().label(),
+            &Some("fetchData".to_string())
+        );
+        let inner = loading.default_region().unwrap();
+        assert!(inner
+            .vertices()
+            .iter()
+            .any(|v| v.as_pseudo_state().map(|p| p.is_deep_history()) == Some(true)));
+        assert!(region.transitions().iter().any(|t| t.is_local()));
+
+        let written = WriteXState::for_version(XStateVersion::V4)
+            .stringify(&machine)
+            .unwrap();
+        let config: Value = serde_json::from_str(&written).unwrap();
+        let loading = &config["states"]["loading"];
+        assert_eq!(loading["on"]["RESOLVE"][0]["cond"], "hasData");
+        assert_eq!(loading["on"]["RETRY"][0]["internal"], true);
+        assert_eq!(loading["after"]["5000"][0]["target"], "failure");
+        assert_eq!(loading["states"]["hist"]["target"], "waiting");
+        let reread = ReadXState::default().parse(&written).unwrap();
+        assert_eq!(
+            WriteXState::for_version(XStateVersion::V4)
+                .stringify(&reread)
+                .unwrap(),
+            written
+        );
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let config = r#"{
+          "id": "door",
+          "initial": "closed",
+          "states": {
+            "closed": {
+              "meta": { "layout": { "x": 40, "y": 25.5, "width": 120, "height": 50 } },
+              "on": { "OPEN": "opened" }
+            },
+            "opened": { "meta": { "layout": { "x": 200, "y": 25 } } },
+            "broken": { "meta": { "layout": { "x": "left" } } }
+          }
+        }"#;
+        let machine = ReadXState::default().parse(config).unwrap();
+        let layouts = machine
+            .default_region()
+            .unwrap()
+            .vertices()
+            .iter()
+            .filter_map(|vertex| vertex.as_state())
+            .map(|state| (state.label().clone().unwrap(), layout(state.metadata())))
+            .collect::<HashMap<String, Option<Layout>>>();
+        assert_eq!(
+            layouts["closed"],
+            Some(Layout::at(40.0, 25.5).with_size(120.0, 50.0))
+        );
+        assert_eq!(layouts["opened"], Some(Layout::at(200.0, 25.0)));
+        assert_eq!(layouts["broken"], None);
+
+        let written: Value =
+            serde_json::from_str(&WriteXState::default().stringify(&machine).unwrap()).unwrap();
+        let states = &written["states"];
+        assert_eq!(
+            states["closed"]["meta"],
+            json!({ "layout": { "x": 40.0, "y": 25.5, "width": 120.0, "height": 50.0 } })
+        );
+        assert_eq!(
+            states["opened"]["meta"],
+            json!({ "layout": { "x": 200.0, "y": 25.0 } })
+        );
+        assert_eq!(states["broken"].get("meta"), None);
+    }
+}
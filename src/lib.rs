@@ -11,7 +11,8 @@ specification, especially copies of the constraints and semantic rules.
 ![Abstract Syntax](https://raw.githubusercontent.com/johnstonskj/rust-uml_state_machine/master/doc/abstract-syntax.png)
 
 The crate contains the descriptive model elements at the root level, with modules for error handling,
-execution of instances and reading and writing formatted representations.
+execution of instances and reading and writing formatted representations. The `prelude` module
+re-exports the commonly used traits and types.
 
 # Example
 
@@ -20,12 +21,22 @@ TBD
 # Features
 
 * `execution` - an in-memory execution environment for machines, included by default.
-* `format-graphviz` - supports writing state diagrams with [GraphViz](https://graphviz.org/)
-   ,following the style in [this post](https://martin-thoma.com/how-to-draw-a-finite-state-machine/).
-* `format-plantuml` - supports writing [PlantUML](https://plantuml.com/state-diagram) state diagrams.
-* `format-scxml` - supports writing [State Chart XML](https://www.w3.org/TR/scxml).
-* `format-uml` - supports writing UML's [XML Metadata Interchange](https://www.omg.org/spec/XMI).
-* `format-xstate` - supports writing JavaScript [state machines](https://xstate.js.org/).
+* `format-event-schema` - supports writing a machine's event contract as [JSON Schema](https://json-schema.org/).
+* `format-graphviz` - supports writing state diagrams with [GraphViz](https://graphviz.org/),
+  following the style in [this post](https://martin-thoma.com/how-to-draw-a-finite-state-machine/).
+* `format-native` - supports reading and writing this crate's own, lossless, JSON format.
+* `format-native-yaml` - supports reading and writing the native format as YAML.
+* `format-plantuml` - supports reading and writing [PlantUML](https://plantuml.com/state-diagram) state diagrams.
+* `format-scxml` - supports reading and writing [State Chart XML](https://www.w3.org/TR/scxml).
+* `format-smcat` - supports writing [state-machine-cat](https://github.com/sverweij/state-machine-cat) diagrams.
+* `format-table` - supports writing state-transition tables as CSV or Markdown.
+* `format-uml` - supports reading and writing UML's [XML Metadata Interchange](https://www.omg.org/spec/XMI).
+* `format-xstate` - supports reading and writing JavaScript [state machines](https://xstate.js.org/).
+* `metrics` - publishes execution statistics to the [metrics](https://docs.rs/metrics) facade.
+* `tokio` - an asynchronous adapter for instances, on the [tokio](https://tokio.rs/) runtime.
+* `tui` - a terminal dashboard, with [ratatui](https://ratatui.rs/), for debugging instances.
+* `unstable` - exposes, in the `unstable` module, the compiled representations and analysis
+  internals beneath the stable façade of the `prelude`, which may change in any release.
 
 # See Also
 
@@ -57,6 +68,8 @@ unused_import_braces,
 unused_qualifications,
 unused_results,
 )]
+// error_chain! expands recursively, once for each error kind.
+#![recursion_limit = "256"]
 
 #[macro_use]
 extern crate error_chain;
@@ -86,3 +99,8 @@ pub mod definition;
 pub mod execution;
 
 pub mod format;
+
+pub mod prelude;
+
+#[cfg(feature = "unstable")]
+pub mod unstable;
@@ -0,0 +1,167 @@
+/*!
+Re-exports the commonly used traits and types of the crate, so that client code can use a single
+glob import rather than a number of deep paths. Items exported here are considered the stable
+surface of the crate, the module paths they come from may change.
+
+The crate has two tiers of stability. This module is the stable one, the façade that will only
+change incompatibly in a major release; its exports, and the signatures of the most used of them,
+are snapshotted by this module's tests so that any such change is deliberate. The other is the
+[`unstable`](../unstable/index.html) module, behind the `unstable` feature, which exposes the
+compiled representations and analysis internals beneath the façade and may change in any
+release.
+
+# Example
+
+```rust
+use uml_state_machine::prelude::*;
+
+let simple: StateMachine = StateMachine::default();
+let region: &Region = simple.default_region().unwrap();
+let initial_id = region.new_initial_state();
+let final_id = region.new_final_state();
+region.new_transition(initial_id, final_id);
+
+assert!(simple.validate().is_ok());
+assert!(simple.has_regions());
+```
+
+*/
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+pub use crate::core::{Context, FieldValue, ID};
+
+pub use crate::definition::types::{
+    Behavior, ConnectionPointReference, Constraint, Contained, Event, HasMetadata, HasRegions,
+    Identified, Labeled, PseudoState, PseudoStateKind, Region, State, StateMachine, Transition,
+    TransitionKind, Trigger, Validate, Vertex,
+};
+
+pub use crate::definition::builder::MachineBuilder;
+
+pub use crate::definition::opaque::{Bindings, OpaqueBehavior, OpaqueConstraint};
+
+pub use crate::definition::registry::{bind, bind_with, BindingMode, BindingReport};
+
+pub use crate::definition::visitor::{visit_state_machine, StateMachineVisitor};
+
+#[cfg(feature = "execution")]
+pub use crate::execution::invoke::{InvokeHandle, InvokeHandler};
+
+#[cfg(feature = "execution")]
+pub use crate::execution::manager::InstanceManager;
+
+#[cfg(feature = "execution")]
+pub use crate::execution::types::{
+    ErrorPolicy, ExecutionObserver, InstanceSnapshot, StateMachineInstance, StepResult,
+};
+
+pub use crate::format::{detect_format, parse_any, Format, Parse, Stringify};
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use std::sync::Arc;
+
+    ///
+    /// The names exported by this module; a change to this list is a change to the stable
+    /// surface of the crate.
+    ///
+    const SNAPSHOT: &[&str] = &[
+        "Behavior",
+        "BindingMode",
+        "BindingReport",
+        "Bindings",
+        "ConnectionPointReference",
+        "Constraint",
+        "Contained",
+        "Context",
+        "ErrorPolicy",
+        "Event",
+        "ExecutionObserver",
+        "FieldValue",
+        "Format",
+        "HasMetadata",
+        "HasRegions",
+        "ID",
+        "Identified",
+        "InstanceManager",
+        "InstanceSnapshot",
+        "InvokeHandle",
+        "InvokeHandler",
+        "Labeled",
+        "MachineBuilder",
+        "OpaqueBehavior",
+        "OpaqueConstraint",
+        "Parse",
+        "PseudoState",
+        "PseudoStateKind",
+        "Region",
+        "State",
+        "StateMachine",
+        "StateMachineInstance",
+        "StateMachineVisitor",
+        "StepResult",
+        "Stringify",
+        "Transition",
+        "TransitionKind",
+        "Trigger",
+        "Validate",
+        "Vertex",
+        "bind",
+        "bind_with",
+        "detect_format",
+        "parse_any",
+        "visit_state_machine",
+    ];
+
+    fn exported() -> Vec<String> {
+        let source = include_str!("prelude.rs");
+        let source = &source[..source.find("#[cfg(test)]").unwrap()];
+        let mut names: Vec<String> = source
+            .split("pub use ")
+            .skip(1)
+            .flat_map(|export| {
+                let export = &export[..export.find(';').unwrap()];
+                let names = &export[export.rfind("::").unwrap() + 2..];
+                names
+                    .trim_matches(|c: char| c == '{' || c == '}' || c.is_whitespace())
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_exports_snapshot() {
+        assert_eq!(exported(), SNAPSHOT);
+    }
+
+    #[test]
+    fn test_signatures_snapshot() {
+        let _: fn(&str) -> StateMachine = StateMachine::labeled;
+        let _: fn(&StateMachine, &Bindings) -> Result<BindingReport> = bind;
+        let _: fn(&StateMachine, &Bindings, BindingMode) -> Result<BindingReport> = bind_with;
+        let _: fn(&str) -> Option<Format> = detect_format;
+        let _: fn(&str) -> Result<StateMachine> = parse_any;
+        let _: fn(&StateMachine, &dyn StateMachineVisitor) -> Result<()> = visit_state_machine;
+        #[cfg(feature = "execution")]
+        {
+            let _: fn(Arc<StateMachine>) -> Result<StateMachineInstance> =
+                StateMachineInstance::new;
+            let _: fn(Arc<StateMachine>, Context) -> Result<StateMachineInstance> =
+                StateMachineInstance::with_context;
+        }
+    }
+}
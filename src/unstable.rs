@@ -0,0 +1,245 @@
+/*!
+The unstable tier of the crate, the compiled representations and analysis internals beneath the
+stable façade of the [`prelude`](../prelude/index.html), for tools that need more than it offers.
+This module is enabled by the `unstable` feature and, unlike the prelude, any of its items may
+change, or be removed, in any release as the executor and the analyses are reworked.
+
+[`CompiledChart`] is the structure the executor computes from a machine when an instance is
+created, with which it navigates between vertices, regions, and their owners by ID.
+[`reached_vertices`] is the single search from which `analysis::reachable` computes its fixed
+point, for a given set of states assumed to be able to complete.
+
+# Example
+
+```rust
+use uml_state_machine::definition::builder::MachineBuilder;
+use uml_state_machine::unstable::CompiledChart;
+
+let (machine, names) = MachineBuilder::new()
+    .region(|region| {
+        region.initial("phone").state("phone", |state| {
+            state.region(|inner| inner.initial("ringing").simple_state("ringing"))
+        })
+    })
+    .build_with_names()
+    .unwrap();
+
+let chart = CompiledChart::compile(&machine);
+assert_eq!(chart.parent(&names["ringing"]), Some(&names["phone"]));
+assert!(chart.is_within(&names["ringing"], &names["phone"]));
+assert_eq!(chart.regions_of(&names["phone"]).count(), 1);
+```
+
+*/
+
+use crate::core::ID;
+use crate::definition::types::StateMachine;
+use std::collections::HashSet;
+
+#[cfg(feature = "execution")]
+use crate::definition::types::{Transition, Vertex};
+#[cfg(feature = "execution")]
+use crate::execution::types::{ChartIndex, StateMachineInstance};
+#[cfg(feature = "execution")]
+use std::slice::Iter;
+#[cfg(feature = "execution")]
+use std::sync::Arc;
+
+// ------------------------------------------------------------------------------------------------
+// Public Types
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The compiled structure of a machine, as used by the executor.
+///
+#[cfg(feature = "execution")]
+#[derive(Clone)]
+pub struct CompiledChart {
+    index: Arc<ChartIndex>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// Public Functions
+// ------------------------------------------------------------------------------------------------
+
+///
+/// The vertices of `machine` that may be reached from its initial configuration where exactly
+/// the states in `completing` may complete, without repeating the search as the states that
+/// may complete change.
+///
+pub fn reached_vertices(machine: &StateMachine, completing: &HashSet<ID>) -> HashSet<ID> {
+    crate::definition::analysis::reached_vertices(machine, completing)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Implementations
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "execution")]
+impl CompiledChart {
+    ///
+    /// Compile `machine`, as the executor does when an instance is created.
+    ///
+    pub fn compile(machine: &StateMachine) -> Self {
+        Self {
+            index: Arc::new(ChartIndex::from(machine)),
+        }
+    }
+
+    ///
+    /// The vertex with `id`, at any depth.
+    ///
+    pub fn vertex(&self, id: &ID) -> Option<&Arc<Vertex>> {
+        self.index.vertices.get(id)
+    }
+
+    ///
+    /// The position of `vertex` in document order, parents before their children.
+    ///
+    pub fn document_order(&self, vertex: &ID) -> Option<usize> {
+        self.index.order.get(vertex).copied()
+    }
+
+    ///
+    /// The region directly containing `vertex`.
+    ///
+    pub fn region_of(&self, vertex: &ID) -> Option<&ID> {
+        self.index.vertex_region.get(vertex)
+    }
+
+    ///
+    /// The state owning `region`, `None` for the machine's own regions and unknown regions.
+    ///
+    pub fn owner_of(&self, region: &ID) -> Option<&ID> {
+        self.index
+            .region_owner
+            .get(region)
+            .and_then(|owner| owner.as_ref())
+    }
+
+    ///
+    /// The regions of the state `owner`, or of the machine where `owner` is the machine's ID.
+    ///
+    pub fn regions_of(&self, owner: &ID) -> Iter<'_, ID> {
+        self.index.regions_of(owner)
+    }
+
+    ///
+    /// The initial pseudostate of `region`, if it has one.
+    ///
+    pub fn initial_of(&self, region: &ID) -> Option<&ID> {
+        self.index.initials.get(region)
+    }
+
+    ///
+    /// The transitions from `source`, in document order.
+    ///
+    pub fn transitions_from(&self, source: &ID) -> Iter<'_, Arc<Transition>> {
+        self.index.transitions_from(source)
+    }
+
+    ///
+    /// The state containing `vertex`, if it is not directly within one of the machine's regions.
+    ///
+    pub fn parent(&self, vertex: &ID) -> Option<&ID> {
+        self.index.parent(vertex)
+    }
+
+    ///
+    /// The regions containing `vertex`, at any depth, innermost first.
+    ///
+    pub fn regions_containing(&self, vertex: &ID) -> Vec<&ID> {
+        self.index.regions_containing(vertex)
+    }
+
+    ///
+    /// The innermost region containing both `lhs` and `rhs`.
+    ///
+    pub fn common_region(&self, lhs: &ID, rhs: &ID) -> Option<ID> {
+        self.index.common_region(lhs, rhs)
+    }
+
+    ///
+    /// Whether `vertex` is `ancestor` or is contained by it, at any depth.
+    ///
+    pub fn is_within(&self, vertex: &ID, ancestor: &ID) -> bool {
+        self.index.is_within(vertex, ancestor)
+    }
+}
+
+#[cfg(feature = "execution")]
+impl StateMachineInstance {
+    ///
+    /// The compiled structure of this instance's machine, shared with the instance.
+    ///
+    pub fn compiled_chart(&self) -> CompiledChart {
+        CompiledChart {
+            index: self.index.clone(),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Unit Tests
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::builder::{MachineBuilder, TransitionBuilder};
+
+    #[test]
+    fn test_reached_vertices() {
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region
+                    .initial("busy")
+                    .state("busy", |state| {
+                        state
+                            .region(|inner| inner.initial("working").final_state("working"))
+                            .transition(TransitionBuilder::to("done"))
+                    })
+                    .final_state("done")
+            })
+            .build_with_names()
+            .unwrap();
+
+        let reached = reached_vertices(&machine, &Default::default());
+        assert!(reached.contains(&names["working"]));
+        assert!(!reached.contains(&names["done"]));
+
+        let completing: HashSet<ID> = vec![names["busy"].clone()].into_iter().collect();
+        assert!(reached_vertices(&machine, &completing).contains(&names["done"]));
+    }
+
+    #[cfg(feature = "execution")]
+    #[test]
+    fn test_compiled_chart() {
+        let (machine, names) = MachineBuilder::new()
+            .region(|region| {
+                region.initial("idle").state("idle", |state| {
+                    state
+                        .on("go", "idle")
+                        .region(|inner| inner.initial("left").simple_state("left"))
+                        .region(|inner| inner.initial("right").simple_state("right"))
+                })
+            })
+            .build_with_names()
+            .unwrap();
+        let instance = StateMachineInstance::new(machine).unwrap();
+        let chart = instance.compiled_chart();
+
+        assert_eq!(chart.regions_of(&names["idle"]).count(), 2);
+        assert_eq!(chart.parent(&names["left"]), Some(&names["idle"]));
+        assert_eq!(chart.parent(&names["idle"]), None);
+        let left_region = chart.region_of(&names["left"]).unwrap().clone();
+        assert_eq!(chart.owner_of(&left_region), Some(&names["idle"]));
+        assert!(chart.initial_of(&left_region).is_some());
+        assert_eq!(
+            chart.common_region(&names["left"], &names["right"]),
+            chart.region_of(&names["idle"]).cloned()
+        );
+        assert!(chart.document_order(&names["idle"]) < chart.document_order(&names["left"]));
+        assert_eq!(chart.transitions_from(&names["idle"]).count(), 1);
+    }
+}